@@ -0,0 +1,222 @@
+//! Live Package Accesses
+//!
+//! A scan sees each package's atime once, whenever the scan happens to run,
+//! and volumes mounted `noatime` or `relatime` barely keep it. `watch` holds
+//! filesystem watches on the `node_modules` trees of the projects below its
+//! roots and records an access in the feature store whenever a package is
+//! used, so the ML features follow packages as they are loaded rather than
+//! one snapshot.
+//!
+//! Only Linux (inotify) reports files being opened. FSEvents on macOS and
+//! `ReadDirectoryChangesW` on Windows report writes alone, so there an
+//! install, a build or anything else writing into a package is what counts.
+//! Opening a package's `package.json` or listing a directory does not count:
+//! scanners, this one included, do both for every package. Renames and
+//! deletions do not count either, so quarantining a package does not look
+//! like using it.
+//!
+//! Each package is recorded at most once per `min_interval`, under the
+//! innermost package containing the file. Packages installed while watching
+//! are named from their `package.json`; projects installed after the watch
+//! started are picked up on the next `watch`.
+
+use anyhow::Context;
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::feature_store::FeatureStore;
+use crate::progress::OperationContext;
+use crate::types::ScanOutput;
+
+/// `node_modules` trees of the scanned projects
+pub fn watched_trees(scan: &ScanOutput) -> Vec<PathBuf> {
+    let mut trees: Vec<PathBuf> = scan.projects.iter()
+        .map(|p| Path::new(&p.path).join("node_modules"))
+        .filter(|p| p.is_dir())
+        .collect();
+    trees.sort();
+    trees.dedup();
+    trees
+}
+
+/// Whether an event says a package was used
+fn is_use(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Access(_) | EventKind::Create(_) => true,
+        EventKind::Modify(ModifyKind::Name(_)) => false,
+        EventKind::Modify(_) => true,
+        _ => false,
+    }
+}
+
+/// Maps paths of events to the package they touch and decides which
+/// accesses are recorded
+pub struct AccessRecorder {
+    /// Package directory to `name@version`
+    keys: HashMap<PathBuf, String>,
+    last: HashMap<String, Instant>,
+    min_interval: Duration,
+}
+
+impl AccessRecorder {
+    pub fn new(scan: &ScanOutput, min_interval: Duration) -> Self {
+        let keys = scan.packages.iter()
+            .map(|p| (PathBuf::from(&p.path), format!("{}@{}", p.name, p.version)))
+            .collect();
+        Self { keys, last: HashMap::new(), min_interval }
+    }
+
+    /// `name@version` of the innermost package containing `path`
+    pub fn package_of(&mut self, path: &Path) -> Option<String> {
+        for dir in path.ancestors().skip(1) {
+            if let Some(key) = self.keys.get(dir) {
+                return Some(key.clone());
+            }
+            if !is_package_dir(dir) {
+                continue;
+            }
+            // Installed since the scan
+            let key = read_key(dir)?;
+            self.keys.insert(dir.to_path_buf(), key.clone());
+            return Some(key);
+        }
+        None
+    }
+
+    /// Key to record for a use of `path` at `now`; None when `path` is not
+    /// in a package, does not count as a use or its package was recorded
+    /// less than `min_interval` ago
+    pub fn observe(&mut self, path: &Path, now: Instant) -> Option<String> {
+        if path.file_name().is_some_and(|n| n == "package.json") || path.is_dir() {
+            return None;
+        }
+        let key = self.package_of(path)?;
+        if self.last.get(&key).is_some_and(|at| now.duration_since(*at) < self.min_interval) {
+            return None;
+        }
+        self.last.insert(key.clone(), now);
+        Some(key)
+    }
+}
+
+/// `node_modules/<name>` or `node_modules/@scope/<name>`
+fn is_package_dir(dir: &Path) -> bool {
+    let Some(parent) = dir.parent() else { return false };
+    parent.file_name().is_some_and(|n| n == "node_modules")
+        || (parent.file_name().is_some_and(|n| n.to_string_lossy().starts_with('@'))
+            && parent.parent().and_then(Path::file_name).is_some_and(|n| n == "node_modules"))
+}
+
+fn read_key(dir: &Path) -> Option<String> {
+    let text = std::fs::read_to_string(dir.join("package.json")).ok()?;
+    let manifest: serde_json::Value = serde_json::from_str(&text).ok()?;
+    Some(format!("{}@{}", manifest.get("name")?.as_str()?, manifest.get("version")?.as_str()?))
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WatchSummary {
+    pub trees: Vec<String>,
+    /// Filesystem events received
+    pub events: u64,
+    /// Accesses written to the feature store
+    pub recorded: u64,
+    /// Distinct packages recorded
+    pub packages: usize,
+    pub elapsed_secs: u64,
+}
+
+/// Watch the `node_modules` trees of `scan`'s projects and record package
+/// uses in `db` until cancelled or, when given, `duration` has passed
+pub fn watch(
+    scan: &ScanOutput,
+    db: &FeatureStore,
+    min_interval: Duration,
+    duration: Option<Duration>,
+    ctx: &OperationContext,
+) -> crate::Result<WatchSummary> {
+    let started = Instant::now();
+    let trees = watched_trees(scan);
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)
+        .context("Failed to start a filesystem watcher")
+        .map_err(Error::Scan)?;
+    for tree in &trees {
+        watcher.watch(tree, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {:?}", tree))
+            .map_err(Error::Scan)?;
+    }
+    eprintln!("Watching {} node_modules trees; Ctrl-C to stop", trees.len());
+
+    let mut recorder = AccessRecorder::new(scan, min_interval);
+    let mut summary = WatchSummary { trees: trees.iter().map(|t| t.to_string_lossy().to_string()).collect(), ..Default::default() };
+    let mut packages = std::collections::HashSet::new();
+    while !ctx.cancel.is_cancelled() && duration.is_none_or(|d| started.elapsed() < d) {
+        let event = match rx.recv_timeout(Duration::from_millis(250)) {
+            Ok(Ok(event)) => event,
+            Ok(Err(e)) => {
+                eprintln!("Warning: watch error: {}", e);
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        summary.events += 1;
+        if !is_use(&event.kind) {
+            continue;
+        }
+        for path in &event.paths {
+            let Some(key) = recorder.observe(path, Instant::now()) else { continue };
+            db.record_package_access(&key, 0)?;
+            summary.recorded += 1;
+            packages.insert(key);
+        }
+    }
+    summary.packages = packages.len();
+    summary.elapsed_secs = started.elapsed().as_secs();
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::scan_with_context;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_recorder_attributes_and_throttles_uses() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("app");
+        let pkg = project.join("node_modules/left-pad");
+        let nested = pkg.join("node_modules/@types/node");
+        for (dir, name) in [(&pkg, "left-pad"), (&nested, "@types/node")] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("package.json"), format!(r#"{{"name":"{}","version":"1.0.0"}}"#, name)).unwrap();
+            fs::write(dir.join("index.js"), "").unwrap();
+        }
+        fs::write(project.join("package.json"), r#"{"name":"app","dependencies":{"left-pad":"1.0.0"}}"#).unwrap();
+        let scan = scan_with_context(&[temp.path().to_path_buf()], false, &OperationContext::default()).unwrap();
+        assert_eq!(watched_trees(&scan), [project.join("node_modules")]);
+
+        let mut recorder = AccessRecorder::new(&scan, Duration::from_secs(60));
+        let now = Instant::now();
+        assert_eq!(recorder.observe(&pkg.join("index.js"), now).as_deref(), Some("left-pad@1.0.0"));
+        assert_eq!(recorder.observe(&pkg.join("index.js"), now + Duration::from_secs(1)), None);
+        assert_eq!(recorder.observe(&pkg.join("index.js"), now + Duration::from_secs(61)).as_deref(), Some("left-pad@1.0.0"));
+        assert_eq!(recorder.observe(&nested.join("index.js"), now).as_deref(), Some("@types/node@1.0.0"));
+        assert_eq!(recorder.observe(&pkg.join("package.json"), now + Duration::from_secs(200)), None);
+
+        // Installed after the scan
+        let late = project.join("node_modules/late");
+        fs::create_dir_all(&late).unwrap();
+        fs::write(late.join("package.json"), r#"{"name":"late","version":"2.0.0"}"#).unwrap();
+        assert_eq!(recorder.package_of(&late.join("lib/a.js")).as_deref(), Some("late@2.0.0"));
+        assert_eq!(recorder.package_of(&project.join("src/a.js")), None);
+    }
+}
@@ -0,0 +1,601 @@
+//! Two-Person Approval
+//!
+//! On shared infrastructure a large purge should not rest on one person's
+//! judgement. Once a size threshold is configured (`approval set`), a plan
+//! whose items add up to more than it (as stated, or as measured on disk
+//! when that is more) only applies with an approval token issued by someone
+//! other than the person applying it:
+//!
+//! 1. `dry-run > plan.json` produces the plan
+//! 2. a second person reviews it and runs `approve plan.json`, which prints a
+//!    token binding the plan's hash, their name and the time of approval
+//! 3. `apply plan.json --approval <token>` checks the token and quarantines
+//!
+//! A plan refused for lack of a token is listed as awaiting approval (see
+//! `pending_plans`) until a token is issued for it.
+//!
+//! Approvers are told apart by their OS account (uid on unix, SID on
+//! Windows), never by `USER` or anything else a caller can set. Each approver
+//! runs `approval enroll` once, which creates an ed25519 signing key in their
+//! own config directory and lists its public half in the approvers keyring.
+//! Tokens are signed with the approver's key and checked against the keyring,
+//! so a token cannot be edited, moved to a different plan, reused after the
+//! plan changed or issued by anyone not in the keyring, and the person
+//! applying a plan cannot approve it themselves.
+//!
+//! The keyring and the threshold live in the shared directory
+//! (`/etc/packagepurge`, `%ProgramData%\packagepurge` on Windows) when it
+//! exists; make it writable only by approvers. Otherwise they live in the
+//! config directory, which only suits a single account.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::native_walk::tree_totals;
+use crate::types::DryRunReport;
+
+const TOKEN_PREFIX: &str = "ppa2";
+
+/// When approvals are required and how long they stay valid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalConfig {
+    /// Plans estimated above this many bytes need an approval (0 = never)
+    pub threshold_bytes: u64,
+    /// Hours a token stays valid after it was issued
+    pub token_ttl_hours: i64,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self { threshold_bytes: 0, token_ttl_hours: 24 }
+    }
+}
+
+impl ApprovalConfig {
+    /// Whether applying a plan of `bytes` (see `approval_size`) needs an approval token
+    pub fn requires_approval(&self, bytes: u64) -> bool {
+        self.threshold_bytes > 0 && bytes > self.threshold_bytes
+    }
+}
+
+/// An OS account. `id` (`uid:<n>` or a SID) is what approvals are checked
+/// against; `name` is only shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub id: String,
+    pub name: String,
+}
+
+/// An approver as listed in the keyring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Approver {
+    #[serde(flatten)]
+    pub identity: Identity,
+    /// Hex-encoded ed25519 public key
+    pub public_key: String,
+    pub enrolled_at: DateTime<Utc>,
+}
+
+/// A verified approval token
+#[derive(Debug, Clone, Serialize)]
+pub struct Approval {
+    pub approver: String,
+    pub approver_id: String,
+    pub plan_hash: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A plan whose apply was refused for lack of an approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPlan {
+    pub plan_hash: String,
+    pub items: usize,
+    pub estimated_bytes: u64,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Directory every account on the machine shares
+fn shared_dir() -> PathBuf {
+    if cfg!(windows) {
+        std::env::var_os("ProgramData").map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+            .join("packagepurge")
+    } else {
+        PathBuf::from("/etc/packagepurge")
+    }
+}
+
+/// The shared directory when it exists, else the config directory
+fn approval_dir() -> PathBuf {
+    let shared = shared_dir();
+    if shared.is_dir() { shared } else { crate::paths::config_dir() }
+}
+
+fn config_path() -> PathBuf {
+    approval_dir().join("approval.json")
+}
+
+fn keyring_path() -> PathBuf {
+    approval_dir().join("approvers.json")
+}
+
+fn pending_dir() -> PathBuf {
+    crate::paths::state_dir().join("pending-approvals")
+}
+
+/// The current account's private signing key
+fn key_path() -> PathBuf {
+    crate::paths::config_dir().join("approval-signing.key")
+}
+
+pub fn load_config() -> ApprovalConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &ApprovalConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &ApprovalConfig) -> Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save approval config to {:?}", path))
+}
+
+/// Sum of the plan's item sizes (not the report's total, which is not signed over)
+pub fn plan_size(plan: &DryRunReport) -> u64 {
+    plan.items.iter().map(|i| i.estimated_size_bytes).sum()
+}
+
+/// Bytes checked against the approval threshold: each item's stated size,
+/// or what its target measures on disk when that is more. Whoever applies
+/// the plan supplies the file, so understated sizes must not skip approval.
+pub fn approval_size(plan: &DryRunReport) -> u64 {
+    plan.items.iter()
+        .map(|i| {
+            let on_disk = tree_totals(Path::new(&i.target_path), None);
+            i.estimated_size_bytes.max(on_disk.bytes + on_disk.linked_bytes)
+        })
+        .sum()
+}
+
+/// Hash of the targets and sizes of a plan, independent of item order
+pub fn plan_hash(plan: &DryRunReport) -> String {
+    let mut items: Vec<_> = plan.items.iter()
+        .map(|i| (i.target_path.as_str(), i.estimated_size_bytes))
+        .collect();
+    items.sort_unstable();
+    let mut hasher = Sha256::new();
+    for (path, size) in items {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update(size.to_le_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Login name of the user running this process, for display only
+pub fn current_user() -> String {
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// The OS account running this process
+pub fn current_identity() -> crate::Result<Identity> {
+    let id = os_account_id()
+        .ok_or_else(|| Error::Approval(anyhow::anyhow!("Cannot tell which OS account is running this process")))?;
+    Ok(Identity { id, name: current_user() })
+}
+
+/// Effective uid: the owner of a file this process creates
+#[cfg(unix)]
+fn os_account_id() -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let file = tempfile::tempfile().ok()?;
+    Some(format!("uid:{}", file.metadata().ok()?.uid()))
+}
+
+/// SID of the process token's user
+#[cfg(windows)]
+fn os_account_id() -> Option<String> {
+    use windows_sys::Win32::Foundation::{CloseHandle, LocalFree, HANDLE};
+    use windows_sys::Win32::Security::Authorization::ConvertSidToStringSidW;
+    use windows_sys::Win32::Security::{GetTokenInformation, TokenUser, TOKEN_QUERY, TOKEN_USER};
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    unsafe {
+        let mut token: HANDLE = std::ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return None;
+        }
+        let mut len = 0u32;
+        GetTokenInformation(token, TokenUser, std::ptr::null_mut(), 0, &mut len);
+        // u64 words keep TOKEN_USER aligned
+        let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+        let ok = GetTokenInformation(token, TokenUser, buf.as_mut_ptr().cast(), len, &mut len);
+        CloseHandle(token);
+        if ok == 0 {
+            return None;
+        }
+        let user = &*(buf.as_ptr() as *const TOKEN_USER);
+        let mut text: *mut u16 = std::ptr::null_mut();
+        if ConvertSidToStringSidW(user.User.Sid, &mut text) == 0 {
+            return None;
+        }
+        let chars = (0..).take_while(|&i| *text.add(i) != 0).count();
+        let sid = String::from_utf16_lossy(std::slice::from_raw_parts(text, chars));
+        LocalFree(text.cast());
+        Some(sid)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn os_account_id() -> Option<String> {
+    None
+}
+
+/// Approvers listed in the keyring
+pub fn approvers() -> Vec<Approver> {
+    read_keyring(&keyring_path()).unwrap_or_default()
+}
+
+fn read_keyring(path: &Path) -> Result<Vec<Approver>> {
+    match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).with_context(|| format!("Invalid approvers keyring {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(anyhow::Error::from(e).context(format!("Failed to read approvers keyring {:?}", path))),
+    }
+}
+
+/// Create the current account's signing key if it has none and list its
+/// public half in the keyring, replacing an earlier entry for the account.
+/// Fails for accounts that cannot write the keyring.
+pub fn enroll() -> crate::Result<Approver> {
+    let identity = current_identity()?;
+    enroll_impl(&identity, &key_path(), &keyring_path(), Utc::now()).map_err(Error::lift(Error::Approval))
+}
+
+fn enroll_impl(identity: &Identity, key_path: &Path, keyring_path: &Path, now: DateTime<Utc>) -> Result<Approver> {
+    let key = load_or_create_key(key_path)?;
+    let approver = Approver {
+        identity: identity.clone(),
+        public_key: hex::encode(key.verifying_key().as_bytes()),
+        enrolled_at: now,
+    };
+    let mut keyring = read_keyring(keyring_path)?;
+    keyring.retain(|a| a.identity.id != identity.id);
+    keyring.push(approver.clone());
+    if let Some(parent) = keyring_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(keyring_path, serde_json::to_string_pretty(&keyring)?)
+        .with_context(|| format!("Failed to write approvers keyring {:?} (only approvers may enroll)", keyring_path))?;
+    Ok(approver)
+}
+
+/// Issue a token approving `plan` on behalf of the current account, which
+/// must have enrolled
+pub fn approve(plan: &DryRunReport) -> crate::Result<(String, Approval)> {
+    let identity = current_identity()?;
+    let issue = || -> Result<(String, Approval)> {
+        let key = fs::read(key_path()).ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .map(|bytes| SigningKey::from_bytes(&bytes))
+            .context("No approval signing key for this account; run `approval enroll` first")?;
+        let keyring = read_keyring(&keyring_path())?;
+        let approver = keyring.iter()
+            .find(|a| a.identity.id == identity.id && a.public_key == hex::encode(key.verifying_key().as_bytes()))
+            .with_context(|| format!("{} is not in the approvers keyring; run `approval enroll`", identity.id))?;
+        approve_impl(plan, approver, &key, &load_config(), Utc::now())
+    };
+    let (token, approval) = issue().map_err(Error::lift(Error::Approval))?;
+    clear_pending(&pending_dir(), &approval.plan_hash);
+    Ok((token, approval))
+}
+
+fn approve_impl(
+    plan: &DryRunReport,
+    approver: &Approver,
+    key: &SigningKey,
+    config: &ApprovalConfig,
+    now: DateTime<Utc>,
+) -> Result<(String, Approval)> {
+    let approval = Approval {
+        approver: approver.identity.name.clone(),
+        approver_id: approver.identity.id.clone(),
+        plan_hash: plan_hash(plan),
+        issued_at: now,
+        expires_at: now + Duration::hours(config.token_ttl_hours),
+    };
+    let issued = now.timestamp();
+    let signature = key.sign(&signed_message(&approval.plan_hash, &approval.approver_id, issued));
+    let token = format!(
+        "{}.{}.{}.{}",
+        TOKEN_PREFIX,
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&approval.approver_id),
+        issued,
+        hex::encode(signature.to_bytes())
+    );
+    Ok((token, approval))
+}
+
+/// Check that the current account may apply `plan`: no token is needed
+/// below the threshold, otherwise `token` must approve exactly this plan, be
+/// unexpired and come from an approver other than the current account
+pub fn check_apply(plan: &DryRunReport, token: Option<&str>) -> crate::Result<Option<Approval>> {
+    let config = load_config();
+    let size = approval_size(plan);
+    if !config.requires_approval(size) {
+        return Ok(None);
+    }
+    let applier = current_identity()?;
+    let keyring = || read_keyring(&keyring_path());
+    let checked = check_apply_impl(plan, size, token, &applier, keyring, &config, Utc::now());
+    if checked.is_err() && token.is_none() {
+        if let Err(e) = note_pending(&pending_dir(), plan, size, &applier.name, Utc::now()) {
+            eprintln!("Warning: Failed to record plan awaiting approval: {}", e);
+        }
+    }
+    checked.map_err(Error::lift(Error::Approval))
+}
+
+/// Plans awaiting approval, oldest first
+pub fn pending_plans() -> Vec<PendingPlan> {
+    pending_in(&pending_dir())
+}
+
+fn pending_in(dir: &Path) -> Vec<PendingPlan> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut pending: Vec<PendingPlan> = entries.filter_map(|e| e.ok())
+        .filter_map(|e| serde_json::from_str(&fs::read_to_string(e.path()).ok()?).ok())
+        .collect();
+    pending.sort_by_key(|p| p.requested_at);
+    pending
+}
+
+/// List `plan` as awaiting approval; a plan refused again keeps its first request time
+fn note_pending(dir: &Path, plan: &DryRunReport, size: u64, applier: &str, now: DateTime<Utc>) -> Result<()> {
+    let hash = plan_hash(plan);
+    let path = dir.join(format!("{}.json", hash));
+    if path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    let pending = PendingPlan {
+        plan_hash: hash,
+        items: plan.items.len(),
+        estimated_bytes: size,
+        requested_by: applier.to_string(),
+        requested_at: now,
+    };
+    fs::write(&path, serde_json::to_string_pretty(&pending)?)
+        .with_context(|| format!("Failed to write {:?}", path))
+}
+
+fn clear_pending(dir: &Path, plan_hash: &str) {
+    let _ = fs::remove_file(dir.join(format!("{}.json", plan_hash)));
+}
+
+fn check_apply_impl(
+    plan: &DryRunReport,
+    size: u64,
+    token: Option<&str>,
+    applier: &Identity,
+    keyring: impl FnOnce() -> Result<Vec<Approver>>,
+    config: &ApprovalConfig,
+    now: DateTime<Utc>,
+) -> Result<Option<Approval>> {
+    if !config.requires_approval(size) {
+        return Ok(None);
+    }
+    let Some(token) = token else {
+        anyhow::bail!(
+            "Plan frees {} bytes, above the approval threshold of {}; ask someone else to run `approve` on it",
+            size,
+            config.threshold_bytes
+        );
+    };
+    let approval = verify(plan, token, &keyring()?, config, now)?;
+    if approval.approver_id == applier.id {
+        anyhow::bail!("{} ({}) approved this plan and cannot also apply it", approval.approver, approval.approver_id);
+    }
+    Ok(Some(approval))
+}
+
+fn verify(plan: &DryRunReport, token: &str, keyring: &[Approver], config: &ApprovalConfig, now: DateTime<Utc>) -> Result<Approval> {
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let [TOKEN_PREFIX, approver_id, issued, signature] = parts[..] else {
+        anyhow::bail!("Malformed approval token");
+    };
+    let approver_id = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(approver_id).ok()
+        .and_then(|b| String::from_utf8(b).ok())
+        .context("Malformed approver in approval token")?;
+    let issued: i64 = issued.parse().context("Malformed timestamp in approval token")?;
+    let signature = hex::decode(signature).ok()
+        .and_then(|b| Signature::from_slice(&b).ok())
+        .context("Malformed signature in approval token")?;
+
+    let approver = keyring.iter().find(|a| a.identity.id == approver_id)
+        .with_context(|| format!("Approval token is from {}, who is not in the approvers keyring", approver_id))?;
+    let public_key = hex::decode(&approver.public_key).ok()
+        .and_then(|b| <[u8; 32]>::try_from(b).ok())
+        .and_then(|b| VerifyingKey::from_bytes(&b).ok())
+        .with_context(|| format!("Invalid public key for {} in the approvers keyring", approver_id))?;
+    let hash = plan_hash(plan);
+    public_key.verify(&signed_message(&hash, &approver_id, issued), &signature)
+        .map_err(|_| anyhow::anyhow!("Approval token does not match this plan (or was altered)"))?;
+
+    let issued_at = Utc.timestamp_opt(issued, 0).single().context("Invalid timestamp in approval token")?;
+    let expires_at = issued_at + Duration::hours(config.token_ttl_hours);
+    if now > expires_at {
+        anyhow::bail!("Approval by {} expired at {}", approver.identity.name, expires_at);
+    }
+    Ok(Approval { approver: approver.identity.name.clone(), approver_id, plan_hash: hash, issued_at, expires_at })
+}
+
+fn signed_message(plan_hash: &str, approver_id: &str, issued: i64) -> Vec<u8> {
+    format!("{}\n{}\n{}\n{}", TOKEN_PREFIX, plan_hash, approver_id, issued).into_bytes()
+}
+
+fn load_or_create_key(path: &Path) -> Result<SigningKey> {
+    if let Ok(bytes) = fs::read(path) {
+        let bytes = <[u8; 32]>::try_from(bytes).map_err(|_| anyhow::anyhow!("Invalid approval signing key {:?}", path))?;
+        return Ok(SigningKey::from_bytes(&bytes));
+    }
+    let mut secret = [0u8; 32];
+    getrandom::getrandom(&mut secret).map_err(|e| anyhow::anyhow!("Failed to generate approval key: {}", e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, secret).with_context(|| format!("Failed to write approval key {:?}", path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PlanItem, PlanReason};
+
+    fn plan(sizes: &[(&str, u64)]) -> DryRunReport {
+        DryRunReport {
+            items: sizes.iter().map(|(path, size)| PlanItem {
+                target_path: path.to_string(),
+                estimated_size_bytes: *size,
+                reason: PlanReason::Orphaned,
+                blockers: Vec::new(),
+                risk: None,
+            }).collect(),
+            total_estimated_bytes: sizes.iter().map(|(_, s)| s).sum(),
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_hash_ignores_order() {
+        let a = plan(&[("/p/node_modules/a", 10), ("/p/node_modules/b", 20)]);
+        let b = plan(&[("/p/node_modules/b", 20), ("/p/node_modules/a", 10)]);
+        assert_eq!(plan_hash(&a), plan_hash(&b));
+        assert_ne!(plan_hash(&a), plan_hash(&plan(&[("/p/node_modules/a", 10)])));
+    }
+
+    fn approver(id: &str, name: &str, seed: u8) -> (Approver, SigningKey) {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        let approver = Approver {
+            identity: Identity { id: id.into(), name: name.into() },
+            public_key: hex::encode(key.verifying_key().as_bytes()),
+            enrolled_at: Utc::now(),
+        };
+        (approver, key)
+    }
+
+    #[test]
+    fn test_approval_required_above_threshold() {
+        let (alice, alice_key) = approver("uid:1001", "alice", 7);
+        let (bob, _) = approver("uid:1002", "bob", 8);
+        let keyring = || Ok(vec![alice.clone(), bob.clone()]);
+        let config = ApprovalConfig { threshold_bytes: 100, token_ttl_hours: 24 };
+        let now = Utc::now();
+        let small = plan(&[("/p/node_modules/a", 50)]);
+        let large = plan(&[("/p/node_modules/a", 50), ("/p/node_modules/b", 80)]);
+
+        assert!(check_apply_impl(&small, approval_size(&small), None, &bob.identity, keyring, &config, now).unwrap().is_none());
+        assert!(check_apply_impl(&large, approval_size(&large), None, &bob.identity, keyring, &config, now).is_err());
+
+        let (token, _) = approve_impl(&large, &alice, &alice_key, &config, now).unwrap();
+        let approval = check_apply_impl(&large, approval_size(&large), Some(&token), &bob.identity, keyring, &config, now).unwrap().unwrap();
+        assert_eq!((approval.approver.as_str(), approval.approver_id.as_str()), ("alice", "uid:1001"));
+        // Not for another plan, not after expiry, not once alice left the keyring
+        let other = plan(&[("/p/node_modules/a", 50), ("/p/node_modules/c", 80)]);
+        assert!(check_apply_impl(&other, approval_size(&other), Some(&token), &bob.identity, keyring, &config, now).is_err());
+        assert!(check_apply_impl(&large, approval_size(&large), Some(&token), &bob.identity, keyring, &config, now + Duration::hours(25)).is_err());
+        assert!(check_apply_impl(&large, approval_size(&large), Some(&token), &bob.identity, || Ok(vec![bob.clone()]), &config, now).is_err());
+    }
+
+    #[test]
+    fn test_understated_plan_still_needs_approval() {
+        let temp = tempfile::tempdir().unwrap();
+        let target = temp.path().join("node_modules/a");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("index.js"), vec![b'x'; 500]).unwrap();
+        let (bob, _) = approver("uid:1002", "bob", 8);
+        let config = ApprovalConfig { threshold_bytes: 100, token_ttl_hours: 24 };
+
+        // Sizes zeroed in the plan file: the target is measured instead
+        let zeroed = plan(&[(target.to_str().unwrap(), 0)]);
+        assert_eq!(approval_size(&zeroed), 500);
+        let err = check_apply_impl(&zeroed, approval_size(&zeroed), None, &bob.identity, || Ok(vec![bob.clone()]), &config, Utc::now()).unwrap_err();
+        assert!(err.to_string().contains("Plan frees 500 bytes"), "{}", err);
+    }
+
+    #[test]
+    fn test_self_approval_refused() {
+        let (alice, alice_key) = approver("uid:1001", "alice", 7);
+        let (bob, _) = approver("uid:1002", "bob", 8);
+        let keyring = || Ok(vec![alice.clone(), bob.clone()]);
+        let config = ApprovalConfig { threshold_bytes: 100, token_ttl_hours: 24 };
+        let now = Utc::now();
+        let large = plan(&[("/p/node_modules/a", 500)]);
+        let (token, _) = approve_impl(&large, &alice, &alice_key, &config, now).unwrap();
+
+        // Same account under another login name (USER=bob)
+        let disguised = Identity { id: "uid:1001".into(), name: "bob".into() };
+        assert!(check_apply_impl(&large, approval_size(&large), Some(&token), &disguised, keyring, &config, now).is_err());
+
+        // alice's key passing itself off as bob's approval
+        let posing = Approver { identity: bob.identity.clone(), ..alice.clone() };
+        let (forged, _) = approve_impl(&large, &posing, &alice_key, &config, now).unwrap();
+        assert!(check_apply_impl(&large, approval_size(&large), Some(&forged), &alice.identity, keyring, &config, now).is_err());
+    }
+
+    #[test]
+    fn test_enroll_replaces_entry() {
+        let temp = tempfile::tempdir().unwrap();
+        let key = temp.path().join("config/approval-signing.key");
+        let keyring = temp.path().join("shared/approvers.json");
+        let alice = Identity { id: "uid:1001".into(), name: "alice".into() };
+        let first = enroll_impl(&alice, &key, &keyring, Utc::now()).unwrap();
+        let again = enroll_impl(&alice, &key, &keyring, Utc::now()).unwrap();
+        assert_eq!(first.public_key, again.public_key);
+        assert_eq!(read_keyring(&keyring).unwrap().len(), 1);
+        assert!(current_identity().unwrap().id.starts_with(if cfg!(unix) { "uid:" } else { "S-" }));
+    }
+
+    #[test]
+    fn test_pending_until_approved() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("pending");
+        let large = plan(&[("/p/node_modules/a", 500)]);
+        let first = Utc::now() - Duration::hours(2);
+        note_pending(&dir, &large, approval_size(&large), "bob", first).unwrap();
+        note_pending(&dir, &large, approval_size(&large), "carol", Utc::now()).unwrap();
+        let pending = pending_in(&dir);
+        assert_eq!(pending.len(), 1);
+        assert_eq!((pending[0].requested_by.as_str(), pending[0].estimated_bytes), ("bob", 500));
+        assert_eq!(pending[0].requested_at, first);
+        clear_pending(&dir, &plan_hash(&large));
+        assert!(pending_in(&dir).is_empty());
+    }
+}
@@ -1,5 +1,9 @@
 #![allow(dead_code)]
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use crate::eviction::PolicyCounters;
 
 // SLRU: probationary and protected segments, each LRU-like (front=MRU, back=LRU)
 pub struct SlruPolicy {
@@ -9,12 +13,36 @@ pub struct SlruPolicy {
 	cap_protected: usize,
 	in_probationary: HashMap<String, bool>,
 	in_protected: HashMap<String, bool>,
+	pub(crate) counters: PolicyCounters,
+}
+
+/// Share of the capacity given to the protected segment unless configured otherwise
+pub const DEFAULT_PROTECTED_RATIO: f32 = 0.8;
+
+/// Occupancy of each segment against its capacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct SlruStats {
+	pub probationary: usize,
+	pub cap_probationary: usize,
+	pub protected: usize,
+	pub cap_protected: usize,
+}
+
+/// Split `capacity` into (probationary, protected) slots. New keys are only
+/// admitted through probation, so it keeps at least one slot whenever there
+/// is any capacity at all.
+fn split_capacity(capacity: usize, protected_ratio: f32) -> (usize, usize) {
+	let ratio = if protected_ratio.is_finite() { protected_ratio.clamp(0.0, 1.0) } else { DEFAULT_PROTECTED_RATIO };
+	let protected = ((capacity as f64 * ratio as f64).round() as usize).min(capacity.saturating_sub(1));
+	(capacity - protected, protected)
 }
 
 impl SlruPolicy {
-	pub fn new(capacity: usize) -> Self {
-		let cap_protected = (capacity as f32 * 0.8) as usize;
-		let cap_probationary = capacity.saturating_sub(cap_protected);
+	pub fn new(capacity: usize) -> Self { Self::with_protected_ratio(capacity, DEFAULT_PROTECTED_RATIO) }
+
+	/// `protected_ratio` of `capacity` (0.0-1.0) goes to the protected segment
+	pub fn with_protected_ratio(capacity: usize, protected_ratio: f32) -> Self {
+		let (cap_probationary, cap_protected) = split_capacity(capacity, protected_ratio);
 		Self {
 			probationary: VecDeque::new(),
 			protected: VecDeque::new(),
@@ -22,11 +50,28 @@ impl SlruPolicy {
 			cap_protected,
 			in_probationary: HashMap::new(),
 			in_protected: HashMap::new(),
+			counters: PolicyCounters::default(),
+		}
+	}
+
+	pub fn contains(&self, key: &str) -> bool { self.in_probationary.contains_key(key) || self.in_protected.contains_key(key) }
+
+	pub fn len(&self) -> usize { self.probationary.len() + self.protected.len() }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	pub fn segment_stats(&self) -> SlruStats {
+		SlruStats {
+			probationary: self.probationary.len(),
+			cap_probationary: self.cap_probationary,
+			protected: self.protected.len(),
+			cap_protected: self.cap_protected,
 		}
 	}
 
 	pub fn record_hit(&mut self, key: &str) {
 		let k = key.to_string();
+		self.counters.record(self.contains(key));
 		if self.in_protected.remove(&k).is_some() {
 			self.protected.retain(|x| x != &k);
 			self.protected.push_front(k.clone());
@@ -38,43 +83,125 @@ impl SlruPolicy {
 			self.probationary.retain(|x| x != &k);
 			self.protected.push_front(k.clone());
 			self.in_protected.insert(k.clone(), true);
-			// enforce protected capacity
+			// protected overflow gets a second chance in probation instead of being dropped
 			while self.protected.len() > self.cap_protected {
-				if let Some(v) = self.protected.pop_back() { self.in_protected.remove(&v); }
+				if let Some(v) = self.protected.pop_back() {
+					self.in_protected.remove(&v);
+					self.probationary.push_front(v.clone());
+					self.in_probationary.insert(v, true);
+				}
 			}
+			self.enforce_probationary();
 			return;
 		}
 		// new entry goes to probationary
 		self.probationary.push_front(k.clone());
 		self.in_probationary.insert(k.clone(), true);
+		self.enforce_probationary();
+	}
+
+	fn enforce_probationary(&mut self) {
 		while self.probationary.len() > self.cap_probationary {
-			if let Some(v) = self.probationary.pop_back() { self.in_probationary.remove(&v); }
+			if let Some(v) = self.probationary.pop_back() {
+				self.in_probationary.remove(&v);
+				self.counters.evictions += 1;
+			}
 		}
 	}
 
 	pub fn select_victim(&mut self) -> Option<String> {
-		if let Some(v) = self.probationary.pop_back() { self.in_probationary.remove(&v); return Some(v); }
-		if let Some(v) = self.protected.pop_back() { self.in_protected.remove(&v); return Some(v); }
-		None
+		let victim = if let Some(v) = self.probationary.pop_back() {
+			self.in_probationary.remove(&v);
+			v
+		} else {
+			let v = self.protected.pop_back()?;
+			self.in_protected.remove(&v);
+			v
+		};
+		self.counters.evictions += 1;
+		Some(victim)
 	}
 }
 
-// Simple LFU: key->freq, and buckets freq->VecDeque keys. Evicts from lowest freq, oldest within bucket
+// Simple LFU: key->freq, and buckets freq->VecDeque keys. Evicts from lowest freq, oldest within bucket.
+// Counts are halved every `decay_interval` increments so formerly hot keys age back into eviction range.
 pub struct SimpleLfu {
 	freq: HashMap<String, usize>,
 	buckets: HashMap<usize, VecDeque<String>>,
+	// increment number of each key's last access, to keep buckets in recency order across a decay
+	touched: HashMap<String, u64>,
+	ticks: u64,
+	decay_interval: Option<u64>,
+	// keys held at most; a new key first evicts the victim when full
+	capacity: Option<usize>,
+	pub(crate) counters: PolicyCounters,
+}
+
+/// Increments between halvings unless configured otherwise
+pub const DEFAULT_DECAY_INTERVAL: u64 = 1024;
+
+impl Default for SimpleLfu {
+	fn default() -> Self { Self::new() }
 }
 
 impl SimpleLfu {
-	pub fn new() -> Self { Self { freq: HashMap::new(), buckets: HashMap::new() } }
+	pub fn new() -> Self { Self::with_decay_interval(Some(DEFAULT_DECAY_INTERVAL)) }
+
+	/// Halve every count after each `interval` increments; `None` never decays
+	pub fn with_decay_interval(interval: Option<u64>) -> Self {
+		Self {
+			freq: HashMap::new(),
+			buckets: HashMap::new(),
+			touched: HashMap::new(),
+			ticks: 0,
+			decay_interval: interval.filter(|n| *n > 0),
+			capacity: None,
+			counters: PolicyCounters::default(),
+		}
+	}
+
+	pub fn set_decay_interval(&mut self, interval: Option<u64>) { self.decay_interval = interval.filter(|n| *n > 0); }
+
+	/// Hold at most `capacity` keys; `None` is unbounded
+	pub fn set_capacity(&mut self, capacity: Option<usize>) { self.capacity = capacity; }
+
+	pub fn capacity(&self) -> Option<usize> { self.capacity }
+
+	pub fn frequency(&self, key: &str) -> Option<usize> { self.freq.get(key).copied() }
+
+	pub fn contains(&self, key: &str) -> bool { self.freq.contains_key(key) }
+
+	pub fn len(&self) -> usize { self.freq.len() }
+
+	pub fn is_empty(&self) -> bool { self.freq.is_empty() }
 
 	pub fn increment(&mut self, key: &str) {
 		let k = key.to_string();
+		let hit = self.contains(key);
+		self.counters.record(hit);
+		if !hit && self.capacity.is_some_and(|c| self.freq.len() >= c) {
+			self.victim();
+			if self.capacity == Some(0) { return; }
+		}
 		let f = *self.freq.get(&k).unwrap_or(&0);
 		if let Some(q) = self.buckets.get_mut(&f) { q.retain(|x| x != &k); }
 		let nf = f + 1;
 		self.freq.insert(k.clone(), nf);
+		self.ticks += 1;
+		self.touched.insert(k.clone(), self.ticks);
 		self.buckets.entry(nf).or_default().push_front(k);
+		if self.decay_interval.is_some_and(|n| self.ticks.is_multiple_of(n)) { self.decay(); }
+	}
+
+	/// Halve every count (aging); keys keep their recency order within the merged buckets
+	pub fn decay(&mut self) {
+		let mut keys: Vec<(&String, &mut usize)> = self.freq.iter_mut().collect();
+		keys.sort_by_key(|(k, _)| std::cmp::Reverse(self.touched.get(*k).copied().unwrap_or(0)));
+		self.buckets.clear();
+		for (k, f) in keys {
+			*f /= 2;
+			self.buckets.entry(*f).or_default().push_back(k.clone());
+		}
 	}
 
 	pub fn victim(&mut self) -> Option<String> {
@@ -83,9 +210,321 @@ impl SimpleLfu {
 		if let Some(q) = self.buckets.get_mut(&minf) {
 			if let Some(k) = q.pop_back() {
 				self.freq.remove(&k);
+				self.touched.remove(&k);
+				self.counters.evictions += 1;
 				return Some(k);
 			}
 		}
 		None
 	}
-}
\ No newline at end of file
+}
+
+// ARC: recency (t1) and frequency (t2) lists of resident keys, with ghost lists (b1, b2) of keys
+// recently evicted from each. A ghost hit moves the target size `p` of t1 towards the list that
+// would have kept the key, so the split adapts to the workload.
+pub struct ArcPolicy {
+	capacity: usize,
+	p: usize,
+	t1: VecDeque<String>,
+	t2: VecDeque<String>,
+	b1: VecDeque<String>,
+	b2: VecDeque<String>,
+	pub(crate) counters: PolicyCounters,
+}
+
+fn remove_key(list: &mut VecDeque<String>, key: &str) -> bool {
+	match list.iter().position(|x| x == key) {
+		Some(i) => { list.remove(i); true }
+		None => false,
+	}
+}
+
+impl ArcPolicy {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			p: 0,
+			t1: VecDeque::new(),
+			t2: VecDeque::new(),
+			b1: VecDeque::new(),
+			b2: VecDeque::new(),
+			counters: PolicyCounters::default(),
+		}
+	}
+
+	pub fn contains(&self, key: &str) -> bool { self.t1.iter().chain(&self.t2).any(|x| x == key) }
+
+	pub fn len(&self) -> usize { self.t1.len() + self.t2.len() }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	pub fn capacity(&self) -> usize { self.capacity }
+
+	/// Target size of the recency list
+	pub fn target(&self) -> usize { self.p }
+
+	/// (t1, t2, b1, b2) lengths
+	pub fn list_lengths(&self) -> (usize, usize, usize, usize) { (self.t1.len(), self.t2.len(), self.b1.len(), self.b2.len()) }
+
+	pub fn record_access(&mut self, key: &str) {
+		let k = key.to_string();
+		if remove_key(&mut self.t1, key) || remove_key(&mut self.t2, key) {
+			self.counters.record(true);
+			self.t2.push_front(k);
+			return;
+		}
+		self.counters.record(false);
+		if self.capacity == 0 {
+			self.counters.evictions += 1;
+			return;
+		}
+		if remove_key(&mut self.b1, key) {
+			let delta = (self.b2.len() / (self.b1.len() + 1)).max(1);
+			self.p = (self.p + delta).min(self.capacity);
+			if self.len() >= self.capacity { self.replace(false); }
+			self.t2.push_front(k);
+			return;
+		}
+		if remove_key(&mut self.b2, key) {
+			let delta = (self.b1.len() / (self.b2.len() + 1)).max(1);
+			self.p = self.p.saturating_sub(delta);
+			if self.len() >= self.capacity { self.replace(true); }
+			self.t2.push_front(k);
+			return;
+		}
+		if self.t1.len() + self.b1.len() >= self.capacity {
+			if self.t1.len() < self.capacity {
+				self.b1.pop_back();
+				if self.len() >= self.capacity { self.replace(false); }
+			} else if self.t1.pop_back().is_some() {
+				self.counters.evictions += 1;
+			}
+		} else if self.len() + self.b1.len() + self.b2.len() >= self.capacity {
+			if self.len() + self.b1.len() + self.b2.len() >= 2 * self.capacity {
+				self.b2.pop_back();
+			}
+			if self.len() >= self.capacity {
+				self.replace(false);
+			}
+		}
+		self.t1.push_front(k);
+	}
+
+	// Evict the LRU key of t1 or t2 into its ghost list, per the target `p`
+	fn replace(&mut self, ghost_in_b2: bool) -> Option<String> {
+		let from_t1 = !self.t1.is_empty() && (self.t1.len() > self.p || (ghost_in_b2 && self.t1.len() == self.p) || self.t2.is_empty());
+		let victim = if from_t1 {
+			let v = self.t1.pop_back()?;
+			self.b1.push_front(v.clone());
+			v
+		} else {
+			let v = self.t2.pop_back()?;
+			self.b2.push_front(v.clone());
+			v
+		};
+		while self.b1.len() > self.capacity { self.b1.pop_back(); }
+		while self.b2.len() > self.capacity { self.b2.pop_back(); }
+		self.counters.evictions += 1;
+		Some(victim)
+	}
+
+	pub fn select_victim(&mut self) -> Option<String> { self.replace(false) }
+}
+
+// Count-min sketch of 4-bit counters, halved after every `sample` additions so old popularity fades
+struct FrequencySketch {
+	table: Vec<u8>,
+	mask: usize,
+	additions: usize,
+	sample: usize,
+}
+
+impl FrequencySketch {
+	const DEPTH: u64 = 4;
+	const MAX: u8 = 15;
+
+	fn new(capacity: usize) -> Self {
+		let width = capacity.max(16).next_power_of_two();
+		Self { table: vec![0; width * Self::DEPTH as usize], mask: width - 1, additions: 0, sample: capacity.max(1) * 10 }
+	}
+
+	fn slots(&self, key: &str) -> [usize; 4] {
+		let width = self.mask + 1;
+		let mut slots = [0; 4];
+		for (row, slot) in slots.iter_mut().enumerate() {
+			let mut h = DefaultHasher::new();
+			(row as u64).hash(&mut h);
+			key.hash(&mut h);
+			*slot = row * width + (h.finish() as usize & self.mask);
+		}
+		slots
+	}
+
+	fn frequency(&self, key: &str) -> u8 { self.slots(key).iter().map(|i| self.table[*i]).min().unwrap_or(0) }
+
+	fn increment(&mut self, key: &str) {
+		for i in self.slots(key) {
+			if self.table[i] < Self::MAX { self.table[i] += 1; }
+		}
+		self.additions += 1;
+		if self.additions >= self.sample {
+			for c in self.table.iter_mut() { *c /= 2; }
+			self.additions /= 2;
+		}
+	}
+}
+
+// W-TinyLFU: a small LRU window admits every new key; keys leaving it compete with the main
+// SLRU's next victim and only get in when the frequency sketch says they are used more often
+pub struct WTinyLfu {
+	window: VecDeque<String>,
+	probation: VecDeque<String>,
+	protected: VecDeque<String>,
+	cap_window: usize,
+	cap_protected: usize,
+	cap_main: usize,
+	sketch: FrequencySketch,
+	pub(crate) counters: PolicyCounters,
+}
+
+impl WTinyLfu {
+	/// 1% of `capacity` (at least one slot) is the window; 80% of the rest is protected
+	pub fn new(capacity: usize) -> Self {
+		let cap_window = if capacity >= 2 { (capacity / 100).max(1) } else { capacity };
+		let cap_main = capacity - cap_window;
+		let (_, cap_protected) = split_capacity(cap_main, DEFAULT_PROTECTED_RATIO);
+		Self {
+			window: VecDeque::new(),
+			probation: VecDeque::new(),
+			protected: VecDeque::new(),
+			cap_window,
+			cap_protected,
+			cap_main,
+			sketch: FrequencySketch::new(capacity),
+			counters: PolicyCounters::default(),
+		}
+	}
+
+	pub fn contains(&self, key: &str) -> bool { self.window.iter().chain(&self.probation).chain(&self.protected).any(|x| x == key) }
+
+	pub fn len(&self) -> usize { self.window.len() + self.probation.len() + self.protected.len() }
+
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	/// (window, probation, protected) lengths and capacities
+	pub fn segment_lengths(&self) -> [(usize, usize); 3] {
+		[
+			(self.window.len(), self.cap_window),
+			(self.probation.len(), self.cap_main - self.cap_protected),
+			(self.protected.len(), self.cap_protected),
+		]
+	}
+
+	pub fn record_access(&mut self, key: &str) {
+		self.sketch.increment(key);
+		let k = key.to_string();
+		if remove_key(&mut self.window, key) {
+			self.counters.record(true);
+			self.window.push_front(k);
+			return;
+		}
+		if remove_key(&mut self.protected, key) {
+			self.counters.record(true);
+			self.protected.push_front(k);
+			return;
+		}
+		if remove_key(&mut self.probation, key) {
+			self.counters.record(true);
+			self.protected.push_front(k);
+			while self.protected.len() > self.cap_protected {
+				if let Some(v) = self.protected.pop_back() { self.probation.push_front(v); }
+			}
+			return;
+		}
+		self.counters.record(false);
+		self.window.push_front(k);
+		while self.window.len() > self.cap_window {
+			let Some(candidate) = self.window.pop_back() else { break };
+			self.admit(candidate);
+		}
+	}
+
+	// Move a key leaving the window into the main space if it beats the main victim
+	fn admit(&mut self, candidate: String) {
+		if self.probation.len() + self.protected.len() < self.cap_main {
+			self.probation.push_front(candidate);
+			return;
+		}
+		let victim_in_probation = !self.probation.is_empty();
+		let victim = if victim_in_probation { self.probation.back() } else { self.protected.back() };
+		let admit = victim.is_some_and(|v| self.sketch.frequency(&candidate) > self.sketch.frequency(v));
+		self.counters.evictions += 1;
+		if admit {
+			if victim_in_probation { self.probation.pop_back(); } else { self.protected.pop_back(); }
+			self.probation.push_front(candidate);
+		}
+	}
+
+	/// Probation's LRU key first, then the window's, then protected's
+	pub fn select_victim(&mut self) -> Option<String> {
+		let victim = self.probation.pop_back().or_else(|| self.window.pop_back()).or_else(|| self.protected.pop_back())?;
+		self.counters.evictions += 1;
+		Some(victim)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_slru_split_keeps_a_probationary_slot() {
+		assert_eq!(split_capacity(0, 0.8), (0, 0));
+		assert_eq!(split_capacity(1, 0.8), (1, 0));
+		assert_eq!(split_capacity(4, 0.8), (1, 3));
+		assert_eq!(split_capacity(10, 0.8), (2, 8));
+		assert_eq!(split_capacity(10, 1.0), (1, 9));
+		assert_eq!(split_capacity(10, f32::NAN), (2, 8));
+
+		// capacity 4 used to leave no probationary slot, so nothing was ever admitted
+		let mut slru = SlruPolicy::new(4);
+		for key in ["a", "a", "b", "b", "c"] { slru.record_hit(key); }
+		assert_eq!(slru.segment_stats(), SlruStats { probationary: 1, cap_probationary: 1, protected: 2, cap_protected: 3 });
+		assert_eq!(slru.select_victim().as_deref(), Some("c"));
+		assert_eq!(slru.select_victim().as_deref(), Some("a"));
+
+		// a full protected segment demotes its LRU key to probation
+		let mut small = SlruPolicy::with_protected_ratio(2, 0.5);
+		for key in ["x", "x", "y", "y"] { small.record_hit(key); }
+		assert_eq!(small.segment_stats(), SlruStats { probationary: 1, cap_probationary: 1, protected: 1, cap_protected: 1 });
+		assert_eq!(small.select_victim().as_deref(), Some("x"));
+	}
+
+	#[test]
+	fn test_decay_halves_counts_and_keeps_recency() {
+		let mut lfu = SimpleLfu::with_decay_interval(None);
+		for _ in 0..5 { lfu.increment("a"); }
+		for _ in 0..4 { lfu.increment("b"); }
+		lfu.increment("c");
+		lfu.decay();
+		assert_eq!((lfu.frequency("a"), lfu.frequency("b"), lfu.frequency("c")), (Some(2), Some(2), Some(0)));
+		assert_eq!(lfu.victim().as_deref(), Some("c"));
+		// a and b share a bucket now; a was touched longest ago
+		assert_eq!(lfu.victim().as_deref(), Some("a"));
+	}
+
+	#[test]
+	fn test_formerly_hot_key_is_eventually_evicted() {
+		let hot_then_cold = |interval| {
+			let mut lfu = SimpleLfu::with_decay_interval(interval);
+			for _ in 0..1000 { lfu.increment("old-favourite"); }
+			// the workload moves on: the new keys are used steadily, the old one never again
+			for _ in 0..200 {
+				for key in ["react", "vite", "zod"] { lfu.increment(key); }
+			}
+			lfu.victim()
+		};
+		assert_eq!(hot_then_cold(None).as_deref(), Some("react"));
+		assert_eq!(hot_then_cold(Some(100)).as_deref(), Some("old-favourite"));
+	}
+}
@@ -0,0 +1,278 @@
+//! Project Archiving
+//!
+//! Packs a dormant project's regenerable directories (`node_modules`,
+//! framework caches, and gitignored build output) into a tar.gz in cold
+//! storage, either a local directory or an `s3://bucket/prefix`. The archive
+//! is verified before the originals are deleted and recorded in the feature
+//! store, so `unarchive` can later bring the directories back as they were.
+//! Archives are kept after a restore; the record is marked restored.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::feature_store::FeatureStore;
+use crate::native_walk::tree_totals;
+use crate::progress::{OperationContext, Phase};
+use crate::s3::{self, S3Url};
+use crate::safety::ensure_writable;
+use crate::types::ArchiveRecord;
+
+/// Caches that are always safe to regenerate
+const CACHE_DIRS: &[&str] = &[
+    "node_modules", ".next", ".nuxt", ".svelte-kit", ".turbo", ".parcel-cache", ".angular", ".vite",
+];
+
+/// Build output, archived only when the project's `.gitignore` lists it
+const OUTPUT_DIRS: &[&str] = &["dist", "build", "out", "coverage"];
+
+/// Default cold storage: `archives` in the state directory
+pub fn default_destination() -> PathBuf {
+    crate::paths::state_dir().join("archives")
+}
+
+/// Directories of `project` that `archive_project` would pack: present
+/// defaults plus any of `extra` that exist. Symlinks are never followed.
+pub fn artifact_dirs(project: &Path, extra: &[String]) -> Vec<String> {
+    let ignored = gitignored_names(project);
+    let candidates = CACHE_DIRS.iter().map(|d| d.to_string())
+        .chain(OUTPUT_DIRS.iter().filter(|d| ignored.iter().any(|i| i == *d)).map(|d| d.to_string()))
+        .chain(extra.iter().cloned());
+    let mut dirs: Vec<String> = Vec::new();
+    for dir in candidates {
+        let is_dir = fs::symlink_metadata(project.join(&dir)).map(|m| m.is_dir()).unwrap_or(false);
+        if is_dir && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// Plain directory names listed in the root `.gitignore` (`dist`, `/dist/`)
+fn gitignored_names(project: &Path) -> Vec<String> {
+    fs::read_to_string(project.join(".gitignore"))
+        .map(|text| text.lines().map(|l| l.trim().trim_matches('/').to_string()).filter(|l| !l.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Pack `project`'s artifact directories into `dest` (a directory or
+/// `s3://bucket/prefix`), verify the archive, delete the directories and
+/// record the archive in `db`
+pub fn archive_project(
+    project: &Path,
+    dest: &str,
+    extra: &[String],
+    db: &FeatureStore,
+    ctx: &OperationContext,
+) -> crate::Result<ArchiveRecord> {
+    ensure_writable("archive projects")?;
+    let record = archive_project_impl(project, dest, extra, ctx).map_err(Error::lift(Error::Archive))?;
+    let id = db.record_archive(&record)?;
+    Ok(ArchiveRecord { id, ..record })
+}
+
+fn archive_project_impl(project: &Path, dest: &str, extra: &[String], ctx: &OperationContext) -> Result<ArchiveRecord> {
+    let project = project.canonicalize().with_context(|| format!("Project {:?} not found", project))?;
+    for dir in extra {
+        anyhow::ensure!(
+            Path::new(dir).components().all(|c| matches!(c, std::path::Component::Normal(_))),
+            "--include takes directories relative to the project, got `{}`", dir
+        );
+    }
+    let dirs = artifact_dirs(&project, extra);
+    anyhow::ensure!(!dirs.is_empty(), "Nothing to archive in {:?}", project);
+
+    let name = format!(
+        "{}-{}.tar.gz",
+        project.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "project".into()),
+        Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+    let remote = S3Url::parse(dest).map(|url| url.join(&name));
+    let mut file = match &remote {
+        Some(_) => tempfile::NamedTempFile::new()?,
+        None => {
+            fs::create_dir_all(dest).with_context(|| format!("Failed to create {:?}", dest))?;
+            tempfile::NamedTempFile::new_in(dest)?
+        }
+    };
+
+    let size_bytes: u64 = dirs.iter().map(|d| tree_totals(&project.join(d), None).bytes).sum();
+    let mut builder = tar::Builder::new(GzEncoder::new(file.as_file_mut(), flate2::Compression::default()));
+    builder.follow_symlinks(false);
+    for (i, dir) in dirs.iter().enumerate() {
+        ctx.check()?;
+        ctx.report(Phase::Quarantine, i as u64, Some(dirs.len() as u64), Some(&project.join(dir)));
+        builder.append_dir_all(dir, project.join(dir)).with_context(|| format!("Failed to pack {}", dir))?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+
+    // Read the whole archive back before anything is deleted
+    ctx.report(Phase::Verify, 0, None, None);
+    let (sha256, archive_bytes) = verify_archive(file.as_file_mut(), &dirs)?;
+
+    let location = match &remote {
+        Some(url) => {
+            file.as_file_mut().rewind()?;
+            s3::put(url, file.as_file_mut(), archive_bytes)?;
+            url.to_string()
+        }
+        None => {
+            let path = Path::new(dest).canonicalize()?.join(&name);
+            file.persist(&path).map_err(|e| e.error).with_context(|| format!("Failed to write {:?}", path))?;
+            path.to_string_lossy().to_string()
+        }
+    };
+
+    ctx.check()?;
+    for (i, dir) in dirs.iter().enumerate() {
+        ctx.report(Phase::Remove, i as u64, Some(dirs.len() as u64), Some(&project.join(dir)));
+        fs::remove_dir_all(project.join(dir)).with_context(|| format!("Failed to remove {:?}", project.join(dir)))?;
+    }
+
+    Ok(ArchiveRecord {
+        id: 0,
+        project_path: project.to_string_lossy().to_string(),
+        location,
+        dirs,
+        size_bytes,
+        archive_bytes,
+        sha256,
+        created_at: Utc::now(),
+        restored_at: None,
+    })
+}
+
+/// Decompress every entry and check that each archived directory is present;
+/// returns the archive's sha256 and length
+fn verify_archive(file: &mut File, dirs: &[String]) -> Result<(String, u64)> {
+    file.rewind()?;
+    let mut hashing = HashingReader::new(&mut *file);
+    let mut seen = vec![false; dirs.len()];
+    {
+        let mut archive = tar::Archive::new(GzDecoder::new(&mut hashing));
+        for entry in archive.entries()? {
+            let mut entry = entry.context("Archive is corrupt")?;
+            let path = entry.path()?.into_owned();
+            if let Some(i) = dirs.iter().position(|d| path.starts_with(d)) {
+                seen[i] = true;
+            }
+            io::copy(&mut entry, &mut io::sink())?;
+        }
+    }
+    // Trailing gzip padding is not consumed by the tar reader
+    io::copy(&mut hashing, &mut io::sink())?;
+    anyhow::ensure!(seen.iter().all(|s| *s), "Archive is missing directories");
+    Ok((hex::encode(hashing.hasher.finalize()), hashing.len))
+}
+
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, hasher: Sha256::new(), len: 0 }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+}
+
+/// Restore the most recent unrestored archive of `project` and mark it restored
+pub fn unarchive_project(project: &Path, db: &FeatureStore, ctx: &OperationContext) -> crate::Result<ArchiveRecord> {
+    ensure_writable("restore archives")?;
+    let key = project.canonicalize().unwrap_or_else(|_| project.to_path_buf()).to_string_lossy().to_string();
+    let record = db.list_archives(false)?
+        .into_iter()
+        .find(|a| a.project_path == key)
+        .ok_or_else(|| Error::Archive(anyhow::anyhow!("No archive recorded for {}", key)))?;
+    unarchive_impl(&record, ctx).map_err(Error::lift(Error::Archive))?;
+    db.mark_archive_restored(record.id)?;
+    Ok(ArchiveRecord { restored_at: Some(Utc::now()), ..record })
+}
+
+fn unarchive_impl(record: &ArchiveRecord, ctx: &OperationContext) -> Result<()> {
+    let project = Path::new(&record.project_path);
+    for dir in &record.dirs {
+        anyhow::ensure!(!project.join(dir).exists(), "{:?} already exists; remove it before restoring", project.join(dir));
+    }
+
+    // Fetch into a local copy first so the checksum is known before extracting
+    let mut local = tempfile::tempfile()?;
+    let mut hashing = HashingReader::new(open_location(&record.location)?);
+    ctx.report(Phase::Verify, 0, Some(record.archive_bytes), None);
+    io::copy(&mut hashing, &mut local)?;
+    let sha256 = hex::encode(hashing.hasher.finalize());
+    anyhow::ensure!(sha256 == record.sha256, "Checksum of {} does not match the recorded one", record.location);
+
+    ctx.check()?;
+    ctx.report(Phase::Symlink, 0, None, Some(project));
+    local.rewind()?;
+    let mut archive = tar::Archive::new(GzDecoder::new(local));
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.unpack(project).with_context(|| format!("Failed to extract into {:?}", project))?;
+    Ok(())
+}
+
+fn open_location(location: &str) -> Result<Box<dyn Read + Send>> {
+    match S3Url::parse(location) {
+        Some(url) => Ok(Box::new(s3::get(&url)?)),
+        None => Ok(Box::new(File::open(location).with_context(|| format!("Archive {} is missing", location))?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_round_trip() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("web");
+        fs::create_dir_all(project.join("node_modules/left-pad")).unwrap();
+        fs::write(project.join("node_modules/left-pad/index.js"), "module.exports = 1").unwrap();
+        fs::create_dir_all(project.join("dist")).unwrap();
+        fs::write(project.join("dist/app.js"), "app").unwrap();
+        fs::create_dir_all(project.join("build")).unwrap();
+        fs::write(project.join(".gitignore"), "/dist/\n").unwrap();
+        fs::write(project.join("package.json"), "{}").unwrap();
+
+        // `build` is not gitignored, so it may be source and stays put
+        assert_eq!(artifact_dirs(&project, &[]), vec!["node_modules", "dist"]);
+
+        let db = FeatureStore::open(&temp.path().join("db.sqlite")).unwrap();
+        let cold = temp.path().join("cold");
+        let ctx = OperationContext::default();
+        let record = archive_project(&project, cold.to_str().unwrap(), &[], &db, &ctx).unwrap();
+        assert!(!project.join("node_modules").exists());
+        assert!(!project.join("dist").exists());
+        assert!(project.join("build").exists());
+        assert!(Path::new(&record.location).is_file());
+        assert_eq!(record.size_bytes, 21);
+
+        let restored = unarchive_project(&project, &db, &ctx).unwrap();
+        assert_eq!(restored.id, record.id);
+        assert_eq!(fs::read_to_string(project.join("node_modules/left-pad/index.js")).unwrap(), "module.exports = 1");
+        assert_eq!(fs::read_to_string(project.join("dist/app.js")).unwrap(), "app");
+        assert!(db.list_archives(false).unwrap().is_empty());
+
+        // Nothing left to restore
+        assert!(matches!(unarchive_project(&project, &db, &ctx), Err(Error::Archive(_))));
+    }
+}
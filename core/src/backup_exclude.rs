@@ -0,0 +1,374 @@
+//! Backup Exclusion
+//!
+//! `node_modules`, package manager caches and the global store come back
+//! with an install, yet backup tools copy every one of their files. The
+//! trees a scan finds are marked so backups skip them:
+//! - a `CACHEDIR.TAG` (the Cache Directory Tagging specification), which
+//!   borg, restic and tar `--exclude-caches` and others honour
+//! - on macOS, the `com.apple.metadata:com_apple_backup_excludeItem`
+//!   attribute Time Machine checks, as `tmutil addexclusion` sets it
+//! - on Windows, `FILE_ATTRIBUTE_NOT_CONTENT_INDEXED`; Windows has no
+//!   general backup-exclusion attribute, but this keeps the trees out of
+//!   the search index and backup tools that skip unindexed files
+//!
+//! Unmarking removes exactly these, and leaves tags this tool did not write.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::provider_caches::is_provider_cache_dir;
+use crate::safety::ensure_writable;
+use crate::scanner::is_cache_dir;
+use crate::symlink::is_symlink;
+use crate::types::ScanOutput;
+
+pub(crate) const CACHEDIR_TAG: &str = "CACHEDIR.TAG";
+pub(crate) const CACHEDIR_SIGNATURE: &str = "Signature: 8a477f597d28d172789f06886806bc55";
+pub(crate) const CACHEDIR_CREATOR: &str = "created by packagepurge";
+
+/// How a tree is kept out of backups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExclusionMethod {
+    CachedirTag,
+    TimeMachine,
+    NotContentIndexed,
+}
+
+/// Methods available on this platform
+pub fn platform_methods() -> Vec<ExclusionMethod> {
+    let mut methods = vec![ExclusionMethod::CachedirTag];
+    if cfg!(target_os = "macos") {
+        methods.push(ExclusionMethod::TimeMachine);
+    }
+    if cfg!(windows) {
+        methods.push(ExclusionMethod::NotContentIndexed);
+    }
+    methods
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    NodeModules,
+    Cache,
+    Store,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackupTarget {
+    pub path: PathBuf,
+    pub kind: TargetKind,
+}
+
+/// The outermost regenerable trees of `scan`: each project's `node_modules`,
+/// the package manager and provider caches its packages live in, and the
+/// global store when it exists
+pub fn targets(scan: &ScanOutput, store: Option<&Path>) -> Vec<BackupTarget> {
+    let mut found: Vec<BackupTarget> = Vec::new();
+    for project in &scan.projects {
+        found.push(BackupTarget { path: Path::new(&project.path).join("node_modules"), kind: TargetKind::NodeModules });
+    }
+    for package in &scan.packages {
+        let path = Path::new(&package.path);
+        let outer_node_modules = path.ancestors().filter(|a| a.file_name().is_some_and(|n| n == "node_modules")).last();
+        let target = match outer_node_modules {
+            Some(nm) => BackupTarget { path: nm.to_path_buf(), kind: TargetKind::NodeModules },
+            None => match path.ancestors().find(|a| is_cache_dir(a) || is_provider_cache_dir(a)) {
+                Some(cache) => BackupTarget { path: cache.to_path_buf(), kind: TargetKind::Cache },
+                None => continue,
+            },
+        };
+        found.push(target);
+    }
+    if let Some(store) = store {
+        found.push(BackupTarget { path: store.to_path_buf(), kind: TargetKind::Store });
+    }
+
+    found.retain(|t| t.path.is_dir() && !is_symlink(&t.path));
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut outermost: Vec<BackupTarget> = Vec::new();
+    for target in found {
+        if !outermost.iter().any(|kept| target.path.starts_with(&kept.path)) {
+            outermost.push(target);
+        }
+    }
+    outermost
+}
+
+/// Whether `dir` is already excluded by `method`
+pub fn is_marked(dir: &Path, method: ExclusionMethod) -> bool {
+    match method {
+        ExclusionMethod::CachedirTag => fs::read_to_string(dir.join(CACHEDIR_TAG))
+            .is_ok_and(|text| text.starts_with(CACHEDIR_SIGNATURE)),
+        ExclusionMethod::TimeMachine => platform::time_machine_excluded(dir),
+        ExclusionMethod::NotContentIndexed => platform::not_content_indexed(dir),
+    }
+}
+
+/// Exclude `dir` by `method`
+pub fn mark(dir: &Path, method: ExclusionMethod) -> io::Result<()> {
+    ensure_writable("change backup exclusions").map_err(io::Error::other)?;
+    match method {
+        ExclusionMethod::CachedirTag => fs::write(dir.join(CACHEDIR_TAG), format!(
+            "{}\n# This file is a cache directory tag {}.\n# For information about cache directory tags, see:\n#\thttps://bford.info/cachedir/\n",
+            CACHEDIR_SIGNATURE, CACHEDIR_CREATOR,
+        )),
+        ExclusionMethod::TimeMachine => platform::set_time_machine_excluded(dir, true),
+        ExclusionMethod::NotContentIndexed => platform::set_not_content_indexed(dir, true),
+    }
+}
+
+/// Whether `unmark` would remove the mark: tags written by other tools stay
+fn removable(dir: &Path, method: ExclusionMethod) -> bool {
+    is_marked(dir, method) && (method != ExclusionMethod::CachedirTag
+        || fs::read_to_string(dir.join(CACHEDIR_TAG)).is_ok_and(|text| text.contains(CACHEDIR_CREATOR)))
+}
+
+/// Undo `mark`. Returns whether anything was removed.
+pub fn unmark(dir: &Path, method: ExclusionMethod) -> io::Result<bool> {
+    if !removable(dir, method) {
+        return Ok(false);
+    }
+    ensure_writable("change backup exclusions").map_err(io::Error::other)?;
+    match method {
+        ExclusionMethod::CachedirTag => fs::remove_file(dir.join(CACHEDIR_TAG))?,
+        ExclusionMethod::TimeMachine => platform::set_time_machine_excluded(dir, false)?,
+        ExclusionMethod::NotContentIndexed => platform::set_not_content_indexed(dir, false)?,
+    }
+    Ok(true)
+}
+
+/// What marking (or unmarking) did to one tree
+#[derive(Debug, Clone, Serialize)]
+pub struct ExclusionOutcome {
+    pub path: String,
+    pub kind: TargetKind,
+    /// Methods applied (or removed) by this run
+    pub changed: Vec<ExclusionMethod>,
+    /// Methods already in the wanted state
+    pub unchanged: Vec<ExclusionMethod>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExclusionReport {
+    pub dry_run: bool,
+    pub undo: bool,
+    pub changed: usize,
+    pub failed: usize,
+    pub targets: Vec<ExclusionOutcome>,
+}
+
+/// Mark every target with every method, or remove the marks when `undo`.
+/// A dry run reports what would change without writing.
+pub fn apply(targets: &[BackupTarget], methods: &[ExclusionMethod], undo: bool, dry_run: bool) -> crate::Result<ExclusionReport> {
+    if !dry_run {
+        ensure_writable("change backup exclusions")?;
+    }
+    let outcomes: Vec<ExclusionOutcome> = targets.iter().map(|target| {
+        let mut outcome = ExclusionOutcome {
+            path: target.path.to_string_lossy().to_string(),
+            kind: target.kind,
+            changed: Vec::new(),
+            unchanged: Vec::new(),
+            errors: Vec::new(),
+        };
+        for &method in methods {
+            let done = if undo { !removable(&target.path, method) } else { is_marked(&target.path, method) };
+            let result = match (done, dry_run, undo) {
+                (true, _, _) => Ok(false),
+                (false, true, _) => Ok(true),
+                (false, false, false) => mark(&target.path, method).map(|_| true),
+                (false, false, true) => unmark(&target.path, method),
+            };
+            match result {
+                Ok(true) => outcome.changed.push(method),
+                Ok(false) => outcome.unchanged.push(method),
+                Err(e) => outcome.errors.push(format!("{:?}: {}", method, e)),
+            }
+        }
+        outcome
+    })
+    .collect();
+    Ok(ExclusionReport {
+        dry_run,
+        undo,
+        changed: outcomes.iter().filter(|o| !o.changed.is_empty()).count(),
+        failed: outcomes.iter().filter(|o| !o.errors.is_empty()).count(),
+        targets: outcomes,
+    })
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    const ATTR: &str = "com.apple.metadata:com_apple_backup_excludeItem";
+    /// Binary property list of the string `com.apple.backupd`, the value
+    /// `tmutil addexclusion` writes
+    const VALUE: &[u8] = &[
+        0x62, 0x70, 0x6c, 0x69, 0x73, 0x74, 0x30, 0x30, 0x5f, 0x10, 0x11, 0x63, 0x6f, 0x6d, 0x2e, 0x61,
+        0x70, 0x70, 0x6c, 0x65, 0x2e, 0x62, 0x61, 0x63, 0x6b, 0x75, 0x70, 0x64, 0x08, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1c,
+    ];
+
+    fn c_strings(dir: &Path) -> io::Result<(CString, CString)> {
+        let path = CString::new(dir.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let name = CString::new(ATTR).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok((path, name))
+    }
+
+    pub fn time_machine_excluded(dir: &Path) -> bool {
+        let Ok((path, name)) = c_strings(dir) else { return false };
+        let len = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0, 0, libc::XATTR_NOFOLLOW) };
+        len > 0
+    }
+
+    pub fn set_time_machine_excluded(dir: &Path, excluded: bool) -> io::Result<()> {
+        let (path, name) = c_strings(dir)?;
+        let rc = unsafe {
+            if excluded {
+                libc::setxattr(path.as_ptr(), name.as_ptr(), VALUE.as_ptr().cast(), VALUE.len(), 0, libc::XATTR_NOFOLLOW)
+            } else {
+                libc::removexattr(path.as_ptr(), name.as_ptr(), libc::XATTR_NOFOLLOW)
+            }
+        };
+        if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    pub fn not_content_indexed(_dir: &Path) -> bool {
+        false
+    }
+
+    pub fn set_not_content_indexed(_dir: &Path, _set: bool) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Windows only"))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileAttributesW, SetFileAttributesW, FILE_ATTRIBUTE_NOT_CONTENT_INDEXED, INVALID_FILE_ATTRIBUTES,
+    };
+
+    fn wide(dir: &Path) -> Vec<u16> {
+        dir.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    fn attributes(dir: &Path) -> io::Result<u32> {
+        let attrs = unsafe { GetFileAttributesW(wide(dir).as_ptr()) };
+        if attrs == INVALID_FILE_ATTRIBUTES { Err(io::Error::last_os_error()) } else { Ok(attrs) }
+    }
+
+    pub fn time_machine_excluded(_dir: &Path) -> bool {
+        false
+    }
+
+    pub fn set_time_machine_excluded(_dir: &Path, _excluded: bool) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "macOS only"))
+    }
+
+    pub fn not_content_indexed(dir: &Path) -> bool {
+        attributes(dir).is_ok_and(|a| a & FILE_ATTRIBUTE_NOT_CONTENT_INDEXED != 0)
+    }
+
+    pub fn set_not_content_indexed(dir: &Path, set: bool) -> io::Result<()> {
+        let attrs = attributes(dir)?;
+        let attrs = if set { attrs | FILE_ATTRIBUTE_NOT_CONTENT_INDEXED } else { attrs & !FILE_ATTRIBUTE_NOT_CONTENT_INDEXED };
+        if unsafe { SetFileAttributesW(wide(dir).as_ptr(), attrs) } != 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+    use std::io;
+    use std::path::Path;
+
+    pub fn time_machine_excluded(_dir: &Path) -> bool {
+        false
+    }
+
+    pub fn set_time_machine_excluded(_dir: &Path, _excluded: bool) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "macOS only"))
+    }
+
+    pub fn not_content_indexed(_dir: &Path) -> bool {
+        false
+    }
+
+    pub fn set_not_content_indexed(_dir: &Path, _set: bool) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Windows only"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PackageRecord, ProjectRecord};
+    use chrono::Utc;
+    use tempfile::tempdir;
+
+    fn package(path: &Path) -> PackageRecord {
+        fs::create_dir_all(path).unwrap();
+        PackageRecord::for_test(path, "1.0.0", 0)
+    }
+
+    #[test]
+    fn test_mark_and_unmark_scan_targets() {
+        let temp = tempdir().unwrap();
+        let app = temp.path().join("app");
+        let scan = ScanOutput {
+            packages: vec![
+                package(&app.join("node_modules/a/node_modules/b")),
+                package(&temp.path().join("home/.npm/_cacache/index-v5")),
+            ],
+            projects: vec![ProjectRecord { path: app.to_string_lossy().to_string(), manager: None, dependencies: Vec::new(), mtime: Utc::now() }],
+            edges: Vec::new(),
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let store = temp.path().join("store");
+        fs::create_dir_all(&store).unwrap();
+
+        let found = targets(&scan, Some(&store));
+        let kinds: Vec<(PathBuf, TargetKind)> = found.iter().map(|t| (t.path.clone(), t.kind)).collect();
+        assert_eq!(kinds, [
+            (app.join("node_modules"), TargetKind::NodeModules),
+            (temp.path().join("home/.npm"), TargetKind::Cache),
+            (store.clone(), TargetKind::Store),
+        ]);
+
+        let methods = [ExclusionMethod::CachedirTag];
+        let preview = apply(&found, &methods, false, true).unwrap();
+        assert_eq!(preview.changed, 3);
+        assert!(!app.join("node_modules/CACHEDIR.TAG").exists());
+
+        assert_eq!(apply(&found, &methods, false, false).unwrap().changed, 3);
+        assert!(is_marked(&app.join("node_modules"), ExclusionMethod::CachedirTag));
+        assert_eq!(apply(&found, &methods, false, false).unwrap().changed, 0);
+
+        // A tag another tool wrote stays when unmarking
+        fs::write(store.join(CACHEDIR_TAG), format!("{}\n# by another tool\n", CACHEDIR_SIGNATURE)).unwrap();
+        let undone = apply(&found, &methods, true, false).unwrap();
+        assert_eq!(undone.changed, 2);
+        assert!(!app.join("node_modules/CACHEDIR.TAG").exists());
+        assert!(store.join(CACHEDIR_TAG).exists());
+
+        let _read_only = crate::safety::read_only_for_test();
+        assert!(matches!(apply(&found, &methods, false, false), Err(crate::Error::ReadOnly(_))));
+        assert!(apply(&found, &methods, false, true).is_ok());
+        assert!(!app.join("node_modules/CACHEDIR.TAG").exists());
+    }
+}
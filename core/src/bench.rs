@@ -0,0 +1,227 @@
+//! Benchmark Harness
+//!
+//! `bench` generates a synthetic tree of projects with `node_modules` of a
+//! chosen shape, then scans and plans it several times without the scan
+//! cache, so throughput can be compared across releases and machines on
+//! the same input. Every project installs `packages` top-level packages,
+//! each with a chain of `depth - 1` nested dependencies and `files` files
+//! of `file_bytes` bytes besides its manifest. The first `shared` fraction
+//! of them is installed at the same version everywhere (duplicates across
+//! projects); every other package is only declared by even-numbered
+//! projects, so the rest are orphaned and planned.
+//!
+//! The report gives each run's scan and plan times with their phase
+//! breakdown (see `timings`), and the median throughput.
+
+use anyhow::Context;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::canonical::CanonicalStrategy;
+use crate::error::Error;
+use crate::optimization::{plan_basic_cleanup, RulesConfig};
+use crate::progress::OperationContext;
+use crate::scan_cache::CacheValidation;
+use crate::symlink::DedupMode;
+use crate::timings::{self, PhaseTiming};
+
+/// Shape of the generated tree
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchShape {
+    pub projects: usize,
+    /// Top-level packages per project
+    pub packages: usize,
+    /// Packages in each top-level package's nesting chain, itself included
+    pub depth: usize,
+    /// Files per package besides package.json
+    pub files: usize,
+    pub file_bytes: usize,
+    /// Fraction of top-level packages installed at the same version in every project
+    pub shared: f64,
+}
+
+impl Default for BenchShape {
+    fn default() -> Self {
+        Self { projects: 20, packages: 50, depth: 2, files: 4, file_bytes: 2048, shared: 0.5 }
+    }
+}
+
+/// What was generated
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GeneratedTree {
+    pub packages: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub generate_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchRun {
+    pub scan_ms: f64,
+    pub plan_ms: f64,
+    pub packages: usize,
+    pub plan_items: usize,
+    pub phases: Vec<PhaseTiming>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub version: &'static str,
+    pub shape: BenchShape,
+    pub root: String,
+    pub generated: GeneratedTree,
+    pub runs: Vec<BenchRun>,
+    pub scan_median_ms: f64,
+    pub plan_median_ms: f64,
+    /// Packages scanned per second at the median scan time
+    pub scan_packages_per_sec: f64,
+    /// Files scanned per second at the median scan time
+    pub scan_files_per_sec: f64,
+    /// Packages planned per second at the median plan time
+    pub plan_packages_per_sec: f64,
+}
+
+fn write_package(dir: &Path, name: &str, version: &str, shape: &BenchShape, out: &mut GeneratedTree) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    let manifest = serde_json::json!({ "name": name, "version": version }).to_string();
+    fs::write(dir.join("package.json"), &manifest)?;
+    let content = vec![b'x'; shape.file_bytes];
+    for i in 0..shape.files {
+        fs::write(dir.join(format!("file{}.js", i)), &content)?;
+    }
+    out.packages += 1;
+    out.files += 1 + shape.files as u64;
+    out.bytes += (manifest.len() + shape.files * shape.file_bytes) as u64;
+    Ok(())
+}
+
+/// Generate the tree described by `shape` under `root`
+pub fn generate(root: &Path, shape: &BenchShape) -> crate::Result<GeneratedTree> {
+    generate_impl(root, shape).map_err(Error::lift(Error::Scan))
+}
+
+fn generate_impl(root: &Path, shape: &BenchShape) -> anyhow::Result<GeneratedTree> {
+    let started = Instant::now();
+    let mut out = GeneratedTree::default();
+    let shared = (shape.packages as f64 * shape.shared.clamp(0.0, 1.0)).round() as usize;
+    for p in 0..shape.projects {
+        let project = root.join(format!("project-{}", p));
+        let mut dependencies = serde_json::Map::new();
+        for i in 0..shape.packages {
+            let name = format!("pkg-{}", i);
+            let version = if i < shared { "1.0.0".to_string() } else { format!("1.{}.0", p) };
+            let chain = std::iter::once(name.clone()).chain((1..shape.depth).map(|level| format!("{}-dep{}", name, level)));
+            let mut dir = project.clone();
+            for package in chain {
+                dir = dir.join("node_modules").join(&package);
+                write_package(&dir, &package, &version, shape, &mut out)?;
+                // Declared like a lockfile would, nested packages included
+                if i < shared || p % 2 == 0 {
+                    dependencies.insert(package, version.clone().into());
+                }
+            }
+        }
+        let manifest = serde_json::json!({ "name": format!("project-{}", p), "dependencies": dependencies });
+        fs::write(project.join("package.json"), serde_json::to_string_pretty(&manifest)?)
+            .with_context(|| format!("Failed to write {:?}", project))?;
+    }
+    out.generate_ms = started.elapsed().as_secs_f64() * 1000.0;
+    Ok(out)
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    match values.len() {
+        0 => 0.0,
+        n if n % 2 == 1 => values[n / 2],
+        n => (values[n / 2 - 1] + values[n / 2]) / 2.0,
+    }
+}
+
+fn per_sec(count: u64, ms: f64) -> f64 {
+    if ms > 0.0 { count as f64 / ms * 1000.0 } else { 0.0 }
+}
+
+/// Generate a tree of `shape` in `dir` (a temporary directory, removed
+/// afterwards, when `None`), then scan and plan it `iterations` times
+pub fn run(shape: &BenchShape, iterations: usize, dir: Option<&Path>, ctx: &OperationContext) -> crate::Result<BenchReport> {
+    let temp = match dir {
+        Some(_) => None,
+        None => Some(tempfile::Builder::new().prefix("packagepurge-bench").tempdir()
+            .map_err(|e| Error::Scan(anyhow::Error::from(e).context("Failed to create a benchmark directory")))?),
+    };
+    let root: PathBuf = match (dir, &temp) {
+        (Some(dir), _) => dir.to_path_buf(),
+        (None, Some(temp)) => temp.path().to_path_buf(),
+        (None, None) => unreachable!(),
+    };
+    let generated = generate(&root, shape)?;
+
+    let was_enabled = timings::is_enabled();
+    timings::enable();
+    let config = RulesConfig {
+        preserve_days: 90,
+        enable_symlinking: false,
+        enable_ml_prediction: false,
+        lru_max_packages: 1000,
+        lru_max_size_bytes: 10_000_000_000,
+        canonical_strategy: CanonicalStrategy::First,
+        dedup_mode: DedupMode::Symlink,
+        protect_patched: true,
+    };
+    let mut runs = Vec::new();
+    for _ in 0..iterations.max(1) {
+        ctx.check()?;
+        timings::reset();
+        let started = Instant::now();
+        let scan = crate::scanner::scan_validated(std::slice::from_ref(&root), false, CacheValidation::Fast, ctx)?;
+        let scan_ms = started.elapsed().as_secs_f64() * 1000.0;
+        let started = Instant::now();
+        let plan = plan_basic_cleanup(&scan, &config)?;
+        let plan_ms = started.elapsed().as_secs_f64() * 1000.0;
+        runs.push(BenchRun { scan_ms, plan_ms, packages: scan.packages.len(), plan_items: plan.items.len(), phases: timings::report() });
+    }
+    // The command's own breakdown starts after the benchmark
+    timings::reset();
+    if !was_enabled {
+        timings::disable();
+    }
+
+    let scan_median_ms = median(runs.iter().map(|r| r.scan_ms).collect());
+    let plan_median_ms = median(runs.iter().map(|r| r.plan_ms).collect());
+    let packages = runs.first().map_or(0, |r| r.packages as u64);
+    Ok(BenchReport {
+        version: env!("CARGO_PKG_VERSION"),
+        shape: shape.clone(),
+        root: root.to_string_lossy().to_string(),
+        scan_packages_per_sec: per_sec(packages, scan_median_ms),
+        scan_files_per_sec: per_sec(generated.files, scan_median_ms),
+        plan_packages_per_sec: per_sec(packages, plan_median_ms),
+        generated,
+        runs,
+        scan_median_ms,
+        plan_median_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_generated_tree_scans_and_plans() {
+        let temp = tempdir().unwrap();
+        let shape = BenchShape { projects: 3, packages: 4, depth: 2, files: 1, file_bytes: 10, shared: 0.5 };
+        let report = run(&shape, 2, Some(temp.path()), &OperationContext::default()).unwrap();
+        assert_eq!(report.generated.packages, 3 * 4 * 2);
+        assert_eq!(report.generated.files, 24 * 2);
+        assert_eq!(report.runs.len(), 2);
+        assert_eq!(report.runs[0].packages, 24);
+        // pkg-2 and pkg-3 of project-1 are orphaned, with their nested deps
+        assert_eq!(report.runs[0].plan_items, 4);
+        assert!(report.scan_median_ms > 0.0);
+    }
+}
@@ -0,0 +1,301 @@
+//! Configuration Bundles
+//!
+//! A team lead settles on scan rules, retention, activity thresholds, tag
+//! rules and the rest once, then hands every developer machine the same
+//! settings in one file: `bundle export` packs the settings files of the
+//! config directory into a gzipped tar, `bundle import` unpacks it over
+//! another machine's, keeping a copy of what it replaced.
+//!
+//! The archive holds `manifest.json` (name, author, time and the SHA256 of
+//! each file), `manifest.sig` and the files under `config/`. The signature is
+//! an HMAC-SHA256 of the manifest with a team key (`bundle.key` in the config
+//! directory, created by the first export). Copy the key to each machine
+//! once; from then on a bundle imports only if it was signed with it and no
+//! file was changed after signing. Whoever holds the key can sign.
+//!
+//! Only shareable settings travel. Machine-specific ones (the role, digest
+//! destinations), hooks, which run commands, and keys stay behind. The
+//! predictor's weights are built in rather than trained per machine, so
+//! there are none to bundle.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const FORMAT: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+const SIGNATURE: &str = "manifest.sig";
+const CONFIG_PREFIX: &str = "config/";
+
+/// Settings files a bundle carries, as named in the config directory
+pub const BUNDLED: &[&str] = &[
+    "scan_rules.json",
+    "quarantine.json",
+    "project_activity.json",
+    "tags.json",
+    "overhead.json",
+    "privacy.json",
+    "display.json",
+    "approval.json",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    /// SHA256 of each file, by name
+    pub files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Added,
+    Replaced,
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub manifest: BundleManifest,
+    pub files: BTreeMap<String, FileChange>,
+    /// Where the replaced files were copied; `None` when nothing was replaced
+    pub backup: Option<PathBuf>,
+    pub dry_run: bool,
+}
+
+pub fn key_path() -> PathBuf {
+    crate::paths::config_dir().join("bundle.key")
+}
+
+fn backups_dir() -> PathBuf {
+    crate::paths::state_dir().join("bundle-backups")
+}
+
+fn sign(key: &[u8], manifest: &[u8]) -> Result<HmacSha256> {
+    let mut mac = HmacSha256::new_from_slice(key).map_err(|e| anyhow::anyhow!("Invalid bundle key: {}", e))?;
+    mac.update(manifest);
+    Ok(mac)
+}
+
+fn load_or_create_key(path: &Path) -> Result<Vec<u8>> {
+    if let Ok(key) = fs::read(path) {
+        return Ok(key);
+    }
+    let mut key = vec![0u8; 32];
+    getrandom::getrandom(&mut key).map_err(|e| anyhow::anyhow!("Failed to generate bundle key: {}", e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, &key).with_context(|| format!("Failed to write bundle key {:?}", path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(key)
+}
+
+fn load_key(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("No bundle key at {:?}; copy the key the bundle was signed with there, or pass --key", path))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Pack the settings found in the config directory into a bundle at `out`,
+/// signed with the key at `key` (default `bundle.key`, created if missing)
+pub fn export(out: &Path, name: &str, description: Option<String>, key: Option<&Path>) -> crate::Result<BundleManifest> {
+    let key_file = key.map(Path::to_path_buf).unwrap_or_else(key_path);
+    let run = || export_from(&crate::paths::config_dir(), out, name, description, &load_or_create_key(&key_file)?, Utc::now());
+    run().map_err(Error::lift(Error::Config))
+}
+
+fn export_from(config_dir: &Path, out: &Path, name: &str, description: Option<String>, key: &[u8], now: DateTime<Utc>) -> Result<BundleManifest> {
+    let mut contents = BTreeMap::new();
+    for file in BUNDLED {
+        if let Ok(bytes) = fs::read(config_dir.join(file)) {
+            contents.insert(file.to_string(), bytes);
+        }
+    }
+    anyhow::ensure!(!contents.is_empty(), "No settings to bundle in {:?}", config_dir);
+
+    let manifest = BundleManifest {
+        format: FORMAT,
+        name: name.to_string(),
+        description,
+        created_by: crate::approval::current_user(),
+        created_at: now,
+        files: contents.iter().map(|(name, bytes)| (name.clone(), sha256_hex(bytes))).collect(),
+    };
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest)?;
+    let signature = hex::encode(sign(key, &manifest_bytes)?.finalize().into_bytes());
+
+    let file = fs::File::create(out).with_context(|| format!("Failed to create {:?}", out))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, flate2::Compression::default()));
+    let mut append = |path: &str, bytes: &[u8]| -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now.timestamp().max(0) as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, path, bytes).with_context(|| format!("Failed to pack {}", path))
+    };
+    append(MANIFEST, &manifest_bytes)?;
+    append(SIGNATURE, signature.as_bytes())?;
+    for (name, bytes) in &contents {
+        append(&format!("{}{}", CONFIG_PREFIX, name), bytes)?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(manifest)
+}
+
+/// Read a bundle, checking its signature and every file against the manifest
+fn read_bundle(path: &Path, key: &[u8]) -> Result<(BundleManifest, BTreeMap<String, Vec<u8>>)> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut manifest_bytes = None;
+    let mut signature = None;
+    let mut contents = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry.context("Bundle is corrupt")?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        match name.as_str() {
+            MANIFEST => manifest_bytes = Some(bytes),
+            SIGNATURE => signature = Some(bytes),
+            _ => {
+                let file = name.strip_prefix(CONFIG_PREFIX).filter(|f| BUNDLED.contains(f))
+                    .with_context(|| format!("Bundle holds an unexpected entry {:?}", name))?;
+                contents.insert(file.to_string(), bytes);
+            }
+        }
+    }
+    let manifest_bytes = manifest_bytes.context("Bundle has no manifest")?;
+    let signature = signature.and_then(|s| hex::decode(s.trim_ascii()).ok()).context("Bundle is not signed")?;
+    sign(key, &manifest_bytes)?.verify_slice(&signature)
+        .map_err(|_| anyhow::anyhow!("Bundle was not signed with this key, or its manifest was altered"))?;
+
+    let manifest: BundleManifest = serde_json::from_slice(&manifest_bytes).context("Malformed bundle manifest")?;
+    anyhow::ensure!(manifest.format == FORMAT, "Bundle format {} is not supported", manifest.format);
+    anyhow::ensure!(
+        manifest.files.keys().eq(contents.keys()),
+        "Bundle files do not match its manifest"
+    );
+    for (name, bytes) in &contents {
+        anyhow::ensure!(manifest.files[name] == sha256_hex(bytes), "{} was altered after the bundle was signed", name);
+        serde_json::from_slice::<serde_json::Value>(bytes).with_context(|| format!("{} in the bundle is not JSON", name))?;
+    }
+    Ok((manifest, contents))
+}
+
+/// Install the settings from the bundle at `path`, verified with the key at
+/// `key` (default `bundle.key`). Files the bundle replaces are copied to a
+/// backup directory first; `dry_run` only reports what would change.
+pub fn import(path: &Path, key: Option<&Path>, dry_run: bool) -> crate::Result<ImportReport> {
+    let key_file = key.map(Path::to_path_buf).unwrap_or_else(key_path);
+    let backup = backups_dir().join(Utc::now().format("%Y%m%dT%H%M%S").to_string());
+    let run = || import_into(&crate::paths::config_dir(), &backup, path, &load_key(&key_file)?, dry_run);
+    run().map_err(Error::lift(Error::Config))
+}
+
+fn import_into(config_dir: &Path, backup: &Path, path: &Path, key: &[u8], dry_run: bool) -> Result<ImportReport> {
+    let (manifest, contents) = read_bundle(path, key)?;
+    let mut files = BTreeMap::new();
+    let mut backed_up = false;
+    for (name, bytes) in &contents {
+        let target = config_dir.join(name);
+        let change = match fs::read(&target) {
+            Ok(current) if current == *bytes => FileChange::Unchanged,
+            Ok(_) => FileChange::Replaced,
+            Err(_) => FileChange::Added,
+        };
+        if !dry_run && change != FileChange::Unchanged {
+            if change == FileChange::Replaced {
+                fs::create_dir_all(backup)?;
+                fs::copy(&target, backup.join(name)).with_context(|| format!("Failed to back up {:?}", target))?;
+                backed_up = true;
+            }
+            fs::create_dir_all(config_dir)?;
+            fs::write(&target, bytes).with_context(|| format!("Failed to write {:?}", target))?;
+        }
+        files.insert(name.clone(), change);
+    }
+    Ok(ImportReport { manifest, files, backup: backed_up.then(|| backup.to_path_buf()), dry_run })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_round_trip_and_tampering() {
+        let lead = tempfile::tempdir().unwrap();
+        fs::write(lead.path().join("quarantine.json"), r#"{"retention_days": 14}"#).unwrap();
+        fs::write(lead.path().join("tags.json"), r#"{"rules": []}"#).unwrap();
+        fs::write(lead.path().join("hooks.json"), r#"{"hooks": []}"#).unwrap();
+        let out = lead.path().join("team.ppbundle");
+        let key = b"team key".to_vec();
+        let manifest = export_from(lead.path(), &out, "team", None, &key, Utc::now()).unwrap();
+        assert_eq!(manifest.files.keys().collect::<Vec<_>>(), ["quarantine.json", "tags.json"]);
+
+        let dev = tempfile::tempdir().unwrap();
+        let config = dev.path().join("config");
+        let backup = dev.path().join("backup");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(config.join("quarantine.json"), r#"{"retention_days": 90}"#).unwrap();
+
+        assert!(import_into(&config, &backup, &out, b"other key", false).is_err());
+
+        let preview = import_into(&config, &backup, &out, &key, true).unwrap();
+        assert_eq!(preview.files["quarantine.json"], FileChange::Replaced);
+        assert!(!config.join("tags.json").exists());
+
+        let report = import_into(&config, &backup, &out, &key, false).unwrap();
+        assert_eq!(report.files["tags.json"], FileChange::Added);
+        assert_eq!(fs::read_to_string(config.join("quarantine.json")).unwrap(), r#"{"retention_days": 14}"#);
+        assert_eq!(fs::read_to_string(backup.join("quarantine.json")).unwrap(), r#"{"retention_days": 90}"#);
+        assert_eq!(import_into(&config, &backup, &out, &key, false).unwrap().files["tags.json"], FileChange::Unchanged);
+
+        // A file swapped inside the archive no longer matches the signed manifest
+        let forged = lead.path().join("forged.ppbundle");
+        {
+            let mut builder = tar::Builder::new(GzEncoder::new(fs::File::create(&forged).unwrap(), flate2::Compression::default()));
+            let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(&out).unwrap()));
+            for entry in archive.entries().unwrap() {
+                let mut entry = entry.unwrap();
+                let name = entry.path().unwrap().to_string_lossy().to_string();
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes).unwrap();
+                if name == "config/quarantine.json" {
+                    bytes = br#"{"retention_days": 0}"#.to_vec();
+                }
+                let mut header = tar::Header::new_gnu();
+                header.set_size(bytes.len() as u64);
+                header.set_cksum();
+                builder.append_data(&mut header, &name, bytes.as_slice()).unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+        let err = import_into(&config, &backup, &forged, &key, false).unwrap_err();
+        assert!(err.to_string().contains("altered"), "{}", err);
+    }
+}
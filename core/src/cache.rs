@@ -10,7 +10,7 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 use chrono::Utc;
-use crate::types::PackageUsageMetrics;
+use crate::types::{LruStats, PackageUsageMetrics};
 
 /// Generation counter to detect stale indices
 type Generation = u32;
@@ -100,6 +100,13 @@ where
         self.len == 0
     }
 
+    /// Whether `key` is cached, without touching its position
+    pub fn contains(&self, key: &K) -> bool {
+        self.map.get(key).is_some_and(|idx| {
+            self.pool.get(idx.index).is_some_and(|s| s.generation == idx.generation && s.occupied)
+        })
+    }
+
     /// Get a value from the cache, moving it to MRU position
     pub fn get(&mut self, key: &K) -> Option<V> {
         let node_idx = self.map.get(key).copied()?;
@@ -217,6 +224,11 @@ where
         }
     }
 
+    /// Remove and return the least recently used entry
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        self.pop_tail()
+    }
+
     /// Remove and return the tail (LRU) entry
     fn pop_tail(&mut self) -> Option<(K, V)> {
         let tail_idx = self.tail?;
@@ -402,6 +414,11 @@ pub struct PackageLruCache {
     size_map: HashMap<String, u64>,
     max_size_bytes: u64,
     current_size_bytes: u64,
+    max_packages: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    size_evictions: u64,
 }
 
 impl PackageLruCache {
@@ -411,6 +428,11 @@ impl PackageLruCache {
             size_map: HashMap::new(),
             max_size_bytes,
             current_size_bytes: 0,
+            max_packages,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            size_evictions: 0,
         }
     }
 
@@ -419,11 +441,13 @@ impl PackageLruCache {
         let now = Utc::now();
         
         if let Some(metrics) = self.cache.get(&package_key.to_string()) {
+            self.hits += 1;
             let mut updated = metrics;
             updated.last_access_time = now;
             updated.access_count += 1;
             self.cache.put(package_key.to_string(), updated);
         } else {
+            self.misses += 1;
             let metrics = PackageUsageMetrics {
                 package_key: package_key.to_string(),
                 last_access_time: now,
@@ -434,6 +458,7 @@ impl PackageLruCache {
             };
             
             if let Some((evicted_key, _)) = self.cache.put(package_key.to_string(), metrics) {
+                self.evictions += 1;
                 if let Some(evicted_size) = self.size_map.remove(&evicted_key) {
                     self.current_size_bytes = self.current_size_bytes.saturating_sub(evicted_size);
                 }
@@ -448,6 +473,7 @@ impl PackageLruCache {
                 if let Some(lru_key) = lru.first() {
                     if let Some(size) = self.size_map.remove(lru_key) {
                         self.current_size_bytes = self.current_size_bytes.saturating_sub(size);
+                        self.size_evictions += 1;
                     }
                     self.cache.get(lru_key);
                 } else {
@@ -480,6 +506,21 @@ impl PackageLruCache {
         }
     }
 
+    /// Whether `package_key` is cached, without counting an access
+    pub fn contains(&self, package_key: &str) -> bool {
+        self.cache.contains(&package_key.to_string())
+    }
+
+    /// Evict the least recently used package, returning its key
+    pub fn evict_lru(&mut self) -> Option<String> {
+        let (key, _) = self.cache.pop_lru()?;
+        self.evictions += 1;
+        if let Some(size) = self.size_map.remove(&key) {
+            self.current_size_bytes = self.current_size_bytes.saturating_sub(size);
+        }
+        Some(key)
+    }
+
     /// Get metrics for a package
     pub fn get_metrics(&mut self, package_key: &str) -> Option<PackageUsageMetrics> {
         self.cache.get(&package_key.to_string())
@@ -549,6 +590,22 @@ impl PackageLruCache {
     pub fn memory_stats(&self) -> MemoryStats {
         self.cache.memory_stats()
     }
+
+    /// Access and eviction counters since the cache was created
+    pub fn stats(&self) -> LruStats {
+        let accesses = self.hits + self.misses;
+        LruStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            size_evictions: self.size_evictions,
+            entries: self.cache.len(),
+            max_packages: self.max_packages,
+            current_bytes: self.current_size_bytes,
+            budget_bytes: self.max_size_bytes,
+            hit_rate: if accesses > 0 { self.hits as f64 / accesses as f64 } else { 0.0 },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -618,6 +675,24 @@ mod tests {
         assert_eq!(v, 2);
     }
 
+    #[test]
+    fn test_package_cache_stats() {
+        let mut cache = PackageLruCache::new(2, 100);
+        cache.record_access("a@1", 10);
+        cache.record_access("a@1", 10);
+        cache.record_access("b@1", 10);
+        cache.record_access("c@1", 10);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions, stats.size_evictions), (1, 3, 1, 0));
+        assert_eq!((stats.entries, stats.current_bytes), (2, 20));
+        assert!((stats.hit_rate - 0.25).abs() < 1e-9);
+
+        cache.record_access("d@1", 200);
+        let stats = cache.stats();
+        assert!(stats.size_evictions >= 1);
+        assert!(stats.current_bytes <= stats.budget_bytes);
+    }
+
     #[test]
     fn test_memory_stats() {
         let mut lru: IntrusiveLruCache<String, i32> = IntrusiveLruCache::new(100);
@@ -0,0 +1,106 @@
+//! Cache and Vendor Markers
+//!
+//! Tools mark their caches with a `CACHEDIR.TAG` (the Cache Directory
+//! Tagging specification), and package managers of other ecosystems leave
+//! recognizable vendored dependency trees in projects. The scanner treats
+//! both as caches without knowing the tool that wrote them; what it does
+//! with them is up to the `tagged_caches` and `vendor_dirs` scan rules.
+//!
+//! Tags this tool writes with `backup-exclude` mark trees the scanner
+//! already knows, so they are not markers.
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::backup_exclude::{CACHEDIR_CREATOR, CACHEDIR_SIGNATURE, CACHEDIR_TAG};
+use crate::scan_rules::{self, MarkerPolicy};
+use crate::types::Marker;
+
+/// Bytes of a `CACHEDIR.TAG` read: the signature and the comment line that
+/// names the tool that wrote it
+const TAG_PREFIX: u64 = 512;
+
+/// What marks `dir` as a cache, if anything
+pub fn marker(dir: &Path) -> Option<Marker> {
+    if is_tagged(dir) {
+        return Some(Marker::CachedirTag);
+    }
+    let name = dir.file_name()?;
+    if name == "vendor" {
+        if dir.join("modules.txt").is_file() {
+            return Some(Marker::GoVendor);
+        }
+        if dir.join("autoload.php").is_file() && dir.join("composer").is_dir() {
+            return Some(Marker::ComposerVendor);
+        }
+    } else if name == "bundle" {
+        let vendor = dir.parent().filter(|p| p.file_name().is_some_and(|n| n == "vendor"))?;
+        if vendor.parent().is_some_and(|project| project.join("Gemfile").is_file()) {
+            return Some(Marker::BundlerVendor);
+        }
+    }
+    None
+}
+
+/// Whether `dir` holds a `CACHEDIR.TAG` with a valid signature that another
+/// tool wrote
+fn is_tagged(dir: &Path) -> bool {
+    let Ok(file) = fs::File::open(dir.join(CACHEDIR_TAG)) else {
+        return false;
+    };
+    let mut head = String::new();
+    if file.take(TAG_PREFIX).read_to_string(&mut head).is_err() {
+        return false;
+    }
+    head.starts_with(CACHEDIR_SIGNATURE) && !head.contains(CACHEDIR_CREATOR)
+}
+
+/// Whether `dir` is marked and the configured scan rules let cleanup remove it
+pub fn is_cleanable(dir: &Path) -> bool {
+    marker(dir).is_some_and(|m| scan_rules::load().marker_policy(m) == MarkerPolicy::Clean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_markers() {
+        let tmp = tempdir().unwrap();
+        let root = tmp.path();
+
+        let tagged = root.join("build-cache");
+        fs::create_dir_all(&tagged).unwrap();
+        fs::write(tagged.join(CACHEDIR_TAG), format!("{}\n# ccache\n", CACHEDIR_SIGNATURE)).unwrap();
+        assert_eq!(marker(&tagged), Some(Marker::CachedirTag));
+
+        let unsigned = root.join("unsigned");
+        fs::create_dir_all(&unsigned).unwrap();
+        fs::write(unsigned.join(CACHEDIR_TAG), "not a tag\n").unwrap();
+        assert_eq!(marker(&unsigned), None);
+
+        let ours = root.join("app/node_modules");
+        fs::create_dir_all(&ours).unwrap();
+        crate::backup_exclude::mark(&ours, crate::backup_exclude::ExclusionMethod::CachedirTag).unwrap();
+        assert_eq!(marker(&ours), None);
+
+        let go = root.join("svc/vendor");
+        fs::create_dir_all(&go).unwrap();
+        fs::write(go.join("modules.txt"), "# github.com/pkg/errors v0.9.1\n").unwrap();
+        assert_eq!(marker(&go), Some(Marker::GoVendor));
+
+        let php = root.join("site/vendor");
+        fs::create_dir_all(php.join("composer")).unwrap();
+        fs::write(php.join("autoload.php"), "<?php\n").unwrap();
+        assert_eq!(marker(&php), Some(Marker::ComposerVendor));
+
+        let gems = root.join("rails/vendor/bundle");
+        fs::create_dir_all(&gems).unwrap();
+        assert_eq!(marker(&gems), None);
+        fs::write(root.join("rails/Gemfile"), "source 'https://rubygems.org'\n").unwrap();
+        assert_eq!(marker(&gems), Some(Marker::BundlerVendor));
+        assert_eq!(marker(&root.join("rails/vendor")), None);
+    }
+}
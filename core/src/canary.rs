@@ -0,0 +1,359 @@
+//! Canary Purges
+//!
+//! Enabling an aggressive policy on a new machine is safer one slice at a
+//! time. `apply plan.json --canary 5` quarantines a random 5% of the plan (by
+//! item count, or by bytes with `--canary-by bytes`) and records which items
+//! went. Applying the same plan again only goes ahead once the observation
+//! window (`--observe-hours`, 24 by default) has passed without a regression,
+//! and then quarantines the rest.
+//!
+//! While observing, each canary item is watched for two signals:
+//!
+//! - reinstalled: its path exists again while its entry is still held in
+//!   quarantine, so a package manager or build put it back
+//! - rolled back: its entry was restored from quarantine, so someone missed it
+//!
+//! Either one blocks the remainder until the canary is aborted
+//! (`canary abort`). Canaries live in the state directory, one file per plan
+//! hash (see `approval::plan_hash`).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::approval::plan_hash;
+use crate::error::Error;
+use crate::types::{DryRunReport, PlanItem, QuarantineRecord};
+
+/// What the canary percentage is a share of
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CanaryBasis {
+    #[default]
+    Count,
+    Bytes,
+}
+
+impl FromStr for CanaryBasis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "count" | "items" => Ok(Self::Count),
+            "bytes" | "size" => Ok(Self::Bytes),
+            other => Err(format!("unknown canary basis `{}` (expected count or bytes)", other)),
+        }
+    }
+}
+
+impl fmt::Display for CanaryBasis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Count => "count",
+            Self::Bytes => "bytes",
+        })
+    }
+}
+
+/// A canary item and the quarantine entry it went into
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryItem {
+    pub target_path: String,
+    pub quarantine_id: String,
+    pub size_bytes: u64,
+}
+
+/// The applied slice of a plan, awaiting the end of its observation window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Canary {
+    pub plan_hash: String,
+    pub basis: CanaryBasis,
+    pub percent: f64,
+    pub plan_items: usize,
+    pub started_at: DateTime<Utc>,
+    pub observe_until: DateTime<Utc>,
+    pub items: Vec<CanaryItem>,
+}
+
+/// How a canary has fared so far
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum CanaryVerdict {
+    Observing { until: DateTime<Utc> },
+    Regressed { reinstalled: Vec<String>, rolled_back: Vec<String> },
+    Passed,
+}
+
+/// A canary with its verdict, as `canary` lists them
+#[derive(Debug, Clone, Serialize)]
+pub struct CanaryStatus {
+    #[serde(flatten)]
+    pub canary: Canary,
+    pub verdict: CanaryVerdict,
+}
+
+impl Canary {
+    /// Watch the canary items against the entries currently in quarantine
+    pub fn verdict(&self, held: &[QuarantineRecord], now: DateTime<Utc>) -> CanaryVerdict {
+        let held: HashSet<&str> = held.iter().map(|r| r.id.as_str()).collect();
+        let (mut reinstalled, mut rolled_back) = (Vec::new(), Vec::new());
+        for item in self.items.iter().filter(|i| Path::new(&i.target_path).exists()) {
+            if held.contains(item.quarantine_id.as_str()) {
+                reinstalled.push(item.target_path.clone());
+            } else {
+                rolled_back.push(item.target_path.clone());
+            }
+        }
+        if !reinstalled.is_empty() || !rolled_back.is_empty() {
+            CanaryVerdict::Regressed { reinstalled, rolled_back }
+        } else if now < self.observe_until {
+            CanaryVerdict::Observing { until: self.observe_until }
+        } else {
+            CanaryVerdict::Passed
+        }
+    }
+
+    /// Items of `plan` the canary did not cover
+    pub fn remainder(&self, plan: &DryRunReport) -> Vec<PlanItem> {
+        let applied: HashSet<&str> = self.items.iter().map(|i| i.target_path.as_str()).collect();
+        plan.items.iter().filter(|i| !applied.contains(i.target_path.as_str())).cloned().collect()
+    }
+}
+
+fn canary_dir() -> PathBuf {
+    crate::paths::state_dir().join("canaries")
+}
+
+/// Shuffle `items` with a splitmix64 stream from `seed`, then take the first
+/// `percent` of them by `basis`; at least one item is always taken
+pub fn sample(items: &[PlanItem], basis: CanaryBasis, percent: f64, seed: u64) -> Vec<PlanItem> {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let mut shuffled: Vec<&PlanItem> = items.iter().collect();
+    for i in (1..shuffled.len()).rev() {
+        shuffled.swap(i, (next() % (i as u64 + 1)) as usize);
+    }
+
+    let share = percent.clamp(0.0, 100.0) / 100.0;
+    let taken: Vec<&PlanItem> = match basis {
+        CanaryBasis::Count => {
+            let n = (items.len() as f64 * share).ceil() as usize;
+            shuffled.into_iter().take(n.max(1)).collect()
+        }
+        CanaryBasis::Bytes => {
+            // Items that would overshoot the budget are passed over for smaller ones
+            let budget = (items.iter().map(|i| i.estimated_size_bytes).sum::<u64>() as f64 * share) as u64;
+            let mut used = 0u64;
+            let mut taken: Vec<&PlanItem> = shuffled.iter().copied()
+                .filter(|i| {
+                    let fits = used + i.estimated_size_bytes <= budget;
+                    if fits {
+                        used += i.estimated_size_bytes;
+                    }
+                    fits
+                })
+                .collect();
+            if taken.is_empty() {
+                taken.extend(shuffled.iter().copied().min_by_key(|i| i.estimated_size_bytes));
+            }
+            taken
+        }
+    };
+    taken.into_iter().cloned().collect()
+}
+
+/// A random seed for `sample`
+pub fn random_seed() -> u64 {
+    let mut seed = [0u8; 8];
+    if getrandom::getrandom(&mut seed).is_err() {
+        return Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+    }
+    u64::from_le_bytes(seed)
+}
+
+/// Record the sampled items of `plan` that `records` quarantined as its canary
+pub fn start(
+    plan: &DryRunReport,
+    sampled: &[PlanItem],
+    records: &[QuarantineRecord],
+    basis: CanaryBasis,
+    percent: f64,
+    observe_hours: i64,
+) -> crate::Result<Canary> {
+    start_in(&canary_dir(), plan, sampled, records, basis, percent, observe_hours, Utc::now())
+        .map_err(Error::lift(Error::Canary))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_in(
+    dir: &Path,
+    plan: &DryRunReport,
+    sampled: &[PlanItem],
+    records: &[QuarantineRecord],
+    basis: CanaryBasis,
+    percent: f64,
+    observe_hours: i64,
+    now: DateTime<Utc>,
+) -> Result<Canary> {
+    let items = sampled.iter()
+        .filter_map(|item| {
+            let rec = records.iter().rev().find(|r| r.original_path == item.target_path)?;
+            Some(CanaryItem { target_path: item.target_path.clone(), quarantine_id: rec.id.clone(), size_bytes: rec.size_bytes })
+        })
+        .collect();
+    let canary = Canary {
+        plan_hash: plan_hash(plan),
+        basis,
+        percent,
+        plan_items: plan.items.len(),
+        started_at: now,
+        observe_until: now + Duration::hours(observe_hours),
+        items,
+    };
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", canary.plan_hash));
+    fs::write(&path, serde_json::to_string_pretty(&canary)?)
+        .with_context(|| format!("Failed to write canary {:?}", path))?;
+    Ok(canary)
+}
+
+/// The canary applied from `plan`, if any
+pub fn find(plan: &DryRunReport) -> Option<Canary> {
+    find_in(&canary_dir(), &plan_hash(plan))
+}
+
+fn find_in(dir: &Path, hash: &str) -> Option<Canary> {
+    let text = fs::read_to_string(dir.join(format!("{}.json", hash))).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Whether the rest of `canary`'s plan may be applied now
+pub fn check_promote(canary: &Canary, held: &[QuarantineRecord]) -> crate::Result<()> {
+    check_promote_at(canary, held, Utc::now()).map_err(Error::Canary)
+}
+
+fn check_promote_at(canary: &Canary, held: &[QuarantineRecord], now: DateTime<Utc>) -> Result<()> {
+    match canary.verdict(held, now) {
+        CanaryVerdict::Passed => Ok(()),
+        CanaryVerdict::Observing { until } => anyhow::bail!(
+            "Canary of {} items is observed until {}; apply the rest after that",
+            canary.items.len(),
+            until
+        ),
+        CanaryVerdict::Regressed { reinstalled, rolled_back } => anyhow::bail!(
+            "Canary regressed ({} reinstalled, {} rolled back: {}); review the plan, then `canary abort {}`",
+            reinstalled.len(),
+            rolled_back.len(),
+            reinstalled.iter().chain(&rolled_back).cloned().collect::<Vec<_>>().join(", "),
+            canary.plan_hash
+        ),
+    }
+}
+
+/// Every canary with its verdict, oldest first
+pub fn list(held: &[QuarantineRecord]) -> Vec<CanaryStatus> {
+    list_in(&canary_dir(), held, Utc::now())
+}
+
+fn list_in(dir: &Path, held: &[QuarantineRecord], now: DateTime<Utc>) -> Vec<CanaryStatus> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut canaries: Vec<Canary> = entries.filter_map(|e| e.ok())
+        .filter_map(|e| serde_json::from_str(&fs::read_to_string(e.path()).ok()?).ok())
+        .collect();
+    canaries.sort_by_key(|c| c.started_at);
+    canaries.into_iter()
+        .map(|canary| CanaryStatus { verdict: canary.verdict(held, now), canary })
+        .collect()
+}
+
+/// Forget the canary of the plan hashed `hash` (after the remainder was
+/// applied, or to give up on it); false if there was none
+pub fn finish(hash: &str) -> bool {
+    fs::remove_file(canary_dir().join(format!("{}.json", hash))).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PlanReason;
+
+    fn plan(items: &[(&str, u64)]) -> DryRunReport {
+        DryRunReport {
+            items: items.iter().map(|(path, size)| PlanItem {
+                target_path: path.to_string(),
+                estimated_size_bytes: *size,
+                reason: PlanReason::Orphaned,
+                blockers: Vec::new(),
+                risk: None,
+            }).collect(),
+            total_estimated_bytes: items.iter().map(|(_, s)| s).sum(),
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        }
+    }
+
+    fn record(id: &str, path: &Path) -> QuarantineRecord {
+        QuarantineRecord::for_test(id, path, 10)
+    }
+
+    #[test]
+    fn test_sample_by_count_and_bytes() {
+        let paths: Vec<String> = (0..40).map(|i| format!("/p/node_modules/m{}", i)).collect();
+        let mut items: Vec<(&str, u64)> = paths.iter().map(|p| (p.as_str(), 100)).collect();
+        items[0].1 = 10_000;
+        let plan = plan(&items);
+
+        let by_count = sample(&plan.items, CanaryBasis::Count, 5.0, 7);
+        assert_eq!(by_count.len(), 2);
+        assert_eq!(sample(&plan.items, CanaryBasis::Count, 5.0, 7)[0].target_path, by_count[0].target_path);
+        assert_eq!(sample(&plan.items, CanaryBasis::Count, 0.0, 7).len(), 1);
+
+        // 5% of 13900 bytes is 695: six of the small items, never the 10000-byte one
+        let by_bytes = sample(&plan.items, CanaryBasis::Bytes, 5.0, 7);
+        assert_eq!(by_bytes.len(), 6);
+        assert!(by_bytes.iter().all(|i| i.estimated_size_bytes == 100));
+    }
+
+    #[test]
+    fn test_remainder_waits_for_a_clean_window() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("canaries");
+        let (a, b, c) = (temp.path().join("a"), temp.path().join("b"), temp.path().join("c"));
+        let plan = plan(&[(&a.to_string_lossy(), 10), (&b.to_string_lossy(), 10), (&c.to_string_lossy(), 10)]);
+        let records = vec![record("qa", &a), record("qb", &b)];
+        let now = Utc::now();
+
+        let canary = start_in(&dir, &plan, &plan.items[..2], &records, CanaryBasis::Count, 50.0, 24, now).unwrap();
+        assert_eq!(find_in(&dir, &plan_hash(&plan)).unwrap().items.len(), 2);
+        assert_eq!(canary.remainder(&plan).len(), 1);
+
+        assert!(matches!(canary.verdict(&records, now), CanaryVerdict::Observing { .. }));
+        assert!(check_promote_at(&canary, &records, now).is_err());
+        assert_eq!(canary.verdict(&records, now + Duration::hours(25)), CanaryVerdict::Passed);
+        assert!(check_promote_at(&canary, &records, now + Duration::hours(25)).is_ok());
+
+        // a came back while still in quarantine; b was restored from it
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        let verdict = canary.verdict(&records[..1], now + Duration::hours(25));
+        assert_eq!(verdict, CanaryVerdict::Regressed {
+            reinstalled: vec![a.to_string_lossy().to_string()],
+            rolled_back: vec![b.to_string_lossy().to_string()],
+        });
+        assert_eq!(list_in(&dir, &records[..1], now).len(), 1);
+    }
+}
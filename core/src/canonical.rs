@@ -0,0 +1,225 @@
+//! Canonical Copy Selection
+//!
+//! When several copies of the same name@version exist, one stays in place and
+//! seeds the global store while the others become symlinks. The strategy
+//! decides which copy that is; by default the first one the scanner listed.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::types::PackageRecord;
+use crate::verify::owning_project;
+
+/// How to pick the copy that is kept when deduplicating
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CanonicalStrategy {
+    /// First copy in scan order
+    #[default]
+    First,
+    /// Copy owned by the most recently modified project
+    RecentProject,
+    /// Copy on a solid-state disk, where that can be detected
+    FastestDisk,
+    /// Copy whose content hash agrees with the most other copies
+    VerifiedIntegrity,
+}
+
+impl FromStr for CanonicalStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.replace('_', "-").as_str() {
+            "first" => Ok(Self::First),
+            "recent-project" => Ok(Self::RecentProject),
+            "fastest-disk" => Ok(Self::FastestDisk),
+            "verified-integrity" => Ok(Self::VerifiedIntegrity),
+            other => Err(format!(
+                "unknown canonical strategy `{}` (expected first, recent-project, fastest-disk or verified-integrity)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CanonicalStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::First => "first",
+            Self::RecentProject => "recent-project",
+            Self::FastestDisk => "fastest-disk",
+            Self::VerifiedIntegrity => "verified-integrity",
+        })
+    }
+}
+
+/// Which copy was kept for a package, recorded in symlinking reports
+#[derive(Debug, Clone, Serialize)]
+pub struct CanonicalChoice {
+    /// name@version
+    pub package: String,
+    pub canonical: String,
+    pub strategy: CanonicalStrategy,
+    pub candidates: usize,
+    /// Strategy-specific explanation of the choice
+    pub detail: Option<String>,
+}
+
+/// Pick the canonical copy among `copies` (all the same name@version).
+/// Returns its index and an optional explanation. Ties go to the earliest copy.
+pub fn select_canonical(
+    copies: &[&PackageRecord],
+    strategy: CanonicalStrategy,
+    project_mtimes: &HashMap<String, DateTime<Utc>>,
+) -> (usize, Option<String>) {
+    match strategy {
+        CanonicalStrategy::First => (0, None),
+        CanonicalStrategy::RecentProject => {
+            let last_used = |pkg: &PackageRecord| {
+                owning_project(Path::new(&pkg.path))
+                    .and_then(|p| project_mtimes.get(p.to_string_lossy().as_ref()).copied())
+                    .unwrap_or(pkg.atime)
+            };
+            let idx = first_best(copies, |a, b| last_used(a) > last_used(b));
+            (idx, Some(format!("last used {}", last_used(copies[idx]).to_rfc3339())))
+        }
+        CanonicalStrategy::FastestDisk => {
+            let ranks: Vec<u8> = copies.iter().map(|p| disk_rank(Path::new(&p.path))).collect();
+            let idx = (0..copies.len()).min_by_key(|&i| ranks[i]).unwrap_or(0);
+            let detail = match ranks[idx] {
+                0 => "solid-state disk",
+                1 => "disk type unknown",
+                _ => "rotational disk",
+            };
+            (idx, Some(detail.to_string()))
+        }
+        CanonicalStrategy::VerifiedIntegrity => {
+            let hashes: Vec<Option<String>> = copies.iter()
+                .map(|p| content_hash(Path::new(&p.path)))
+                .collect();
+            let mut votes: HashMap<&str, usize> = HashMap::new();
+            for h in hashes.iter().flatten() {
+                *votes.entry(h.as_str()).or_default() += 1;
+            }
+            let agreeing = |i: usize| hashes[i].as_deref().and_then(|h| votes.get(h)).copied().unwrap_or(0);
+            let idx = (0..copies.len()).fold(0, |best, i| if agreeing(i) > agreeing(best) { i } else { best });
+            (idx, Some(format!("{} of {} copies identical", agreeing(idx), copies.len())))
+        }
+    }
+}
+
+fn first_best(copies: &[&PackageRecord], better: impl Fn(&PackageRecord, &PackageRecord) -> bool) -> usize {
+    let mut best = 0;
+    for (i, pkg) in copies.iter().enumerate().skip(1) {
+        if better(pkg, copies[best]) {
+            best = i;
+        }
+    }
+    best
+}
+
+/// Location-independent hash of a package: relative paths plus file contents,
+/// in sorted order. Nested `node_modules` are separate packages and skipped.
+fn content_hash(dir: &Path) -> Option<String> {
+    let mut hasher = Sha256::new();
+    let walker = walkdir::WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || e.file_name() != "node_modules");
+    for entry in walker {
+        let entry = entry.ok()?;
+        let rel = entry.path().strip_prefix(dir).ok()?;
+        hasher.update(rel.to_string_lossy().as_bytes());
+        if entry.file_type().is_file() {
+            hasher.update(std::fs::read(entry.path()).ok()?);
+        }
+    }
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// 0 = solid-state, 1 = unknown, 2 = rotational
+fn disk_rank(path: &Path) -> u8 {
+    match is_rotational(path) {
+        Some(false) => 0,
+        None => 1,
+        Some(true) => 2,
+    }
+}
+
+/// Whether the block device holding `path` is rotational, from sysfs
+#[cfg(target_os = "linux")]
+fn is_rotational(path: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let sys = std::path::PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+    // Partitions carry no queue/ of their own; the parent device does
+    [sys.join("queue/rotational"), sys.join("../queue/rotational")]
+        .iter()
+        .find_map(|p| std::fs::read_to_string(p).ok())
+        .map(|v| v.trim() == "1")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_rotational(_path: &Path) -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn record(path: &Path) -> PackageRecord {
+        PackageRecord { name: "a".into(), ..PackageRecord::for_test(path, "1.0.0", 0) }
+    }
+
+    #[test]
+    fn test_parse_strategy() {
+        assert_eq!("recent-project".parse(), Ok(CanonicalStrategy::RecentProject));
+        assert_eq!("verified_integrity".parse(), Ok(CanonicalStrategy::VerifiedIntegrity));
+        assert!("fastest".parse::<CanonicalStrategy>().is_err());
+        assert_eq!(CanonicalStrategy::FastestDisk.to_string(), "fastest-disk");
+    }
+
+    #[test]
+    fn test_recent_project_wins() {
+        let old = record(Path::new("/old/node_modules/a"));
+        let new = record(Path::new("/new/node_modules/a"));
+        let mut mtimes = HashMap::new();
+        mtimes.insert("/old".to_string(), Utc::now() - Duration::days(30));
+        mtimes.insert("/new".to_string(), Utc::now());
+
+        let (idx, _) = select_canonical(&[&old, &new], CanonicalStrategy::RecentProject, &mtimes);
+        assert_eq!(idx, 1);
+        assert_eq!(select_canonical(&[&old, &new], CanonicalStrategy::First, &mtimes).0, 0);
+    }
+
+    #[test]
+    fn test_verified_integrity_prefers_majority() {
+        let temp = tempdir().unwrap();
+        let copies: Vec<_> = ["x", "y", "z"].iter().map(|d| {
+            let dir = temp.path().join(d);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("index.js"), "module.exports = 1;").unwrap();
+            dir
+        }).collect();
+        // First copy was patched locally
+        fs::write(copies[0].join("index.js"), "module.exports = 2;").unwrap();
+
+        let records: Vec<PackageRecord> = copies.iter().map(|p| record(p)).collect();
+        let refs: Vec<&PackageRecord> = records.iter().collect();
+        let (idx, detail) = select_canonical(&refs, CanonicalStrategy::VerifiedIntegrity, &HashMap::new());
+        assert_eq!(idx, 1);
+        assert_eq!(detail.as_deref(), Some("2 of 3 copies identical"));
+    }
+}
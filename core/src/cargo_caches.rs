@@ -0,0 +1,208 @@
+//! Cargo Build Output and Registry
+//!
+//! Rust projects keep build artifacts per profile under `target/` next to
+//! their `Cargo.toml` (`target/debug`, `target/release`,
+//! `target/<triple>/<profile>`), and Cargo extracts every crate it downloads
+//! into `~/.cargo/registry/src/<index>/<name>-<version>`. Profiles are
+//! enumerated as packages named by their path, registry crates by
+//! name@version, and `Cargo.lock` files make their directories projects
+//! depending on the registry crates they lock.
+//!
+//! Plans keep the two apart: a profile is only planned once it has not been
+//! rebuilt within the preservation window, never as orphaned, while a
+//! registry crate is planned when no scanned lock file uses it or it is
+//! old. Cargo extracts a removed crate again from its downloaded archive.
+
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::types::{PackageManager, PackageRecord, PlanReason};
+
+/// Subdirectories Cargo writes to on every build of a profile
+const BUILD_STATE: [&str; 4] = [".fingerprint", "deps", "build", "incremental"];
+
+/// Whether `path` is the `target/` directory of a Cargo project
+pub fn is_target_dir(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == "target")
+        && path.parent().is_some_and(|p| p.join("Cargo.toml").is_file())
+}
+
+/// Whether `path` is a Cargo registry (`$CARGO_HOME/registry`)
+pub fn is_registry_dir(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == "registry")
+        && path.join("src").is_dir()
+        && (path.join("index").is_dir() || path.join("cache").is_dir())
+}
+
+/// Whether the scanner should enumerate `path` as Cargo output or registry
+pub fn is_cargo_dir(path: &Path) -> bool {
+    is_target_dir(path) || is_registry_dir(path)
+}
+
+/// Whether `path` lies inside a `target/` directory or a registry, so Cargo
+/// rebuilds or extracts it again
+pub fn in_cargo_dir(path: &Path) -> bool {
+    path.ancestors().skip(1).any(is_cargo_dir)
+}
+
+fn is_profile_dir(path: &Path) -> bool {
+    path.join(".fingerprint").is_dir() || path.join("deps").is_dir()
+}
+
+/// The profile `path` holds the build output of (`debug`,
+/// `x86_64-unknown-linux-gnu/release`), if it is one
+pub fn target_profile(path: &Path) -> Option<String> {
+    let target = path.ancestors().skip(1).take(2).find(|a| is_target_dir(a))?;
+    let profile = path.strip_prefix(target).ok()?;
+    Some(profile.to_string_lossy().replace('\\', "/"))
+}
+
+/// Split a registry directory name into crate name and version. Names may
+/// hold dashes followed by digits (`md-5-0.10.6`), so the version is the
+/// first suffix that reads as major.minor.patch.
+pub fn split_crate_dir(dir_name: &str) -> Option<(&str, &str)> {
+    dir_name.match_indices('-').find_map(|(i, _)| {
+        let (name, version) = (&dir_name[..i], &dir_name[i + 1..]);
+        let core = version.split(['-', '+']).next()?;
+        let parts: Vec<&str> = core.split('.').collect();
+        let semver = parts.len() == 3 && parts.iter().all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()));
+        (semver && !name.is_empty()).then_some((name, version))
+    })
+}
+
+/// Profiles of a `target/` directory or crates of a registry:
+/// (directory, name, version, manager)
+pub fn cargo_packages(dir: &Path) -> Vec<(PathBuf, String, String, PackageManager)> {
+    if is_registry_dir(dir) {
+        return subdirs(&dir.join("src")).iter()
+            .flat_map(|index| subdirs(index))
+            .filter_map(|krate| {
+                let dir_name = krate.file_name()?.to_string_lossy().to_string();
+                let (name, version) = split_crate_dir(&dir_name)?;
+                Some((krate.clone(), name.to_string(), version.to_string(), PackageManager::Cargo))
+            })
+            .collect();
+    }
+
+    let mut profiles = Vec::new();
+    for child in subdirs(dir) {
+        if is_profile_dir(&child) {
+            profiles.push(child);
+        } else {
+            // Cross-compiled output: target/<triple>/<profile>
+            profiles.extend(subdirs(&child).into_iter().filter(|p| is_profile_dir(p)));
+        }
+    }
+    profiles.into_iter()
+        .map(|profile| {
+            let name = profile.to_string_lossy().to_string();
+            (profile, name, String::new(), PackageManager::Cargo)
+        })
+        .collect()
+}
+
+/// Last time Cargo wrote to `path` or the build state below it
+pub fn last_modified(path: &Path) -> Option<DateTime<Utc>> {
+    std::iter::once(path.to_path_buf())
+        .chain(BUILD_STATE.iter().map(|d| path.join(d)))
+        .filter_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .max()
+        .map(DateTime::<Utc>::from)
+}
+
+/// Registry crates locked by a `Cargo.lock`, as (name, version); path and
+/// git dependencies are built in place and have nothing in the registry
+pub fn parse_cargo_lock(path: &Path) -> Vec<(String, String)> {
+    let Ok(text) = fs::read_to_string(path) else { return Vec::new() };
+    let mut locked = Vec::new();
+    for block in text.split("[[package]]").skip(1) {
+        let field = |key: &str| {
+            block.lines()
+                .filter_map(|l| l.trim().strip_prefix(key))
+                .find_map(|rest| rest.trim_start().strip_prefix('='))
+                .map(|v| v.trim().trim_matches('"').to_string())
+        };
+        let from_registry = field("source").is_some_and(|s| s.starts_with("registry+") || s.starts_with("sparse+"));
+        if let (true, Some(name), Some(version)) = (from_registry, field("name"), field("version")) {
+            locked.push((name, version));
+        }
+    }
+    locked
+}
+
+/// Why `pkg` (a Cargo package) is planned, if it is: build output only once
+/// it has not been rebuilt since `cutoff`, registry crates when no project
+/// locks them (`locked` false) or they are older than `cutoff`
+pub fn plan_reason(pkg: &PackageRecord, locked: bool, cutoff: DateTime<Utc>) -> Option<PlanReason> {
+    let days = (Utc::now() - pkg.mtime).num_days();
+    let stale = pkg.mtime < cutoff;
+    match target_profile(Path::new(&pkg.path)) {
+        Some(profile) => stale.then_some(PlanReason::StaleBuildOutput { profile, days }),
+        None => (!locked || stale).then_some(PlanReason::RegistryCrate { locked, days }),
+    }
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|rd| rd.filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| e.path())
+            .collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cargo_packages_and_plan() {
+        assert_eq!(split_crate_dir("serde-1.0.197"), Some(("serde", "1.0.197")));
+        assert_eq!(split_crate_dir("md-5-0.10.6"), Some(("md-5", "0.10.6")));
+        assert_eq!(split_crate_dir("windows_x86_64_gnu-0.52.0-rc.1"), Some(("windows_x86_64_gnu", "0.52.0-rc.1")));
+        assert_eq!(split_crate_dir("no-version"), None);
+
+        let temp = tempdir().unwrap();
+        let app = temp.path().join("app");
+        for profile in ["target/debug/.fingerprint", "target/x86_64-unknown-linux-gnu/release/deps", "target/doc"] {
+            fs::create_dir_all(app.join(profile)).unwrap();
+        }
+        fs::write(app.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        assert!(is_target_dir(&app.join("target")));
+        assert!(!is_target_dir(&temp.path().join("target")));
+        assert!(in_cargo_dir(&app.join("target/debug")) && !in_cargo_dir(&app.join("target")));
+
+        let mut profiles: Vec<String> = cargo_packages(&app.join("target")).into_iter()
+            .map(|(path, ..)| target_profile(&path).unwrap())
+            .collect();
+        profiles.sort();
+        assert_eq!(profiles, ["debug", "x86_64-unknown-linux-gnu/release"]);
+
+        let registry = temp.path().join(".cargo/registry");
+        fs::create_dir_all(registry.join("index")).unwrap();
+        fs::create_dir_all(registry.join("src/index.crates.io-6f17d22bba15001f/md-5-0.10.6")).unwrap();
+        assert!(is_registry_dir(&registry));
+        let crates: Vec<String> = cargo_packages(&registry).into_iter().map(|(_, n, v, _)| format!("{}@{}", n, v)).collect();
+        assert_eq!(crates, ["md-5@0.10.6"]);
+
+        let lock = app.join("Cargo.lock");
+        fs::write(&lock, "version = 3\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n\n[[package]]\nname = \"md-5\"\nversion = \"0.10.6\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\"\n").unwrap();
+        assert_eq!(parse_cargo_lock(&lock), vec![("md-5".to_string(), "0.10.6".to_string())]);
+
+        let package = |path: PathBuf, days: i64| PackageRecord {
+            mtime: Utc::now() - Duration::days(days),
+            manager: Some(PackageManager::Cargo),
+            ..PackageRecord::for_test(path, "", 0)
+        };
+        let cutoff = Utc::now() - Duration::days(30);
+        let debug = app.join("target/debug");
+        assert_eq!(plan_reason(&package(debug.clone(), 2), false, cutoff), None);
+        assert!(matches!(plan_reason(&package(debug, 45), false, cutoff), Some(PlanReason::StaleBuildOutput { profile, days: 45 }) if profile == "debug"));
+        let krate = registry.join("src/index.crates.io-6f17d22bba15001f/md-5-0.10.6");
+        assert_eq!(plan_reason(&package(krate.clone(), 2), true, cutoff), None);
+        assert_eq!(plan_reason(&package(krate, 2), false, cutoff), Some(PlanReason::RegistryCrate { locked: false, days: 2 }));
+    }
+}
@@ -0,0 +1,333 @@
+//! Filesystem Change Feed
+//!
+//! Instead of fingerprinting every cached directory again, a scan can ask the
+//! volume's change journal which paths changed since the previous scan: the
+//! FSEvents history on macOS and the NTFS USN journal on Windows. The
+//! position reached is stored with the scan cache as a `FeedCursor`, one per
+//! scan root, and the next scan reads from there.
+//!
+//! A read reports no changes list when the journal cannot vouch for the whole
+//! interval: no earlier cursor, a recreated or truncated journal, or dropped
+//! events. Callers then fall back to fingerprints. Reading the USN journal
+//! needs a volume handle, which usually means running elevated; without one,
+//! and on other platforms, there is no feed at all.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Position in a volume's change journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedCursor {
+    /// Journal the position belongs to (FSEvents device UUID, USN journal ID)
+    pub journal: String,
+    pub position: u64,
+}
+
+/// A path reported changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub path: PathBuf,
+    /// Anything below `path` may have changed as well
+    pub subtree: bool,
+}
+
+/// Result of reading a journal
+#[derive(Debug, Clone)]
+pub struct FeedRead {
+    /// Where the next read starts
+    pub cursor: FeedCursor,
+    /// Changes below the root since the previous cursor; None when the
+    /// journal cannot tell
+    pub changed: Option<Vec<Change>>,
+}
+
+/// Read the changes below `root` since `since`. None when no change journal
+/// is available for `root`.
+pub fn read(root: &Path, since: Option<&FeedCursor>) -> Option<FeedRead> {
+    native_read(root, since)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn native_read(_root: &Path, _since: Option<&FeedCursor>) -> Option<FeedRead> {
+    None
+}
+
+#[cfg(target_os = "macos")]
+fn native_read(root: &Path, since: Option<&FeedCursor>) -> Option<FeedRead> {
+    use fsevent_sys as fse;
+    use fsevent_sys::core_foundation as cf;
+    use std::ffi::{c_char, c_void, CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::time::{Duration, Instant};
+
+    #[link(name = "CoreServices", kind = "framework")]
+    extern "C" {
+        fn FSEventsCopyUUIDForDevice(dev: libc::dev_t) -> cf::CFRef;
+        fn CFUUIDCreateString(alloc: cf::CFAllocatorRef, uuid: cf::CFRef) -> cf::CFStringRef;
+        fn CFRunLoopRunInMode(mode: cf::CFStringRef, seconds: cf::CFTimeInterval, return_after_source_handled: cf::Boolean) -> i32;
+    }
+
+    /// Filled in by the stream callback
+    struct History {
+        changes: Vec<Change>,
+        done: bool,
+        lost: bool,
+    }
+
+    extern "C" fn callback(
+        _stream: fse::FSEventStreamRef,
+        info: *mut c_void,
+        count: usize,
+        paths: *mut c_void,
+        flags: *const fse::FSEventStreamEventFlags,
+        _ids: *const fse::FSEventStreamEventId,
+    ) {
+        let history = unsafe { &mut *(info as *mut History) };
+        let paths = paths as *const *const c_char;
+        for i in 0..count {
+            let flag = unsafe { *flags.add(i) };
+            if flag & fse::kFSEventStreamEventFlagHistoryDone != 0 {
+                history.done = true;
+                continue;
+            }
+            let lost = fse::kFSEventStreamEventFlagUserDropped
+                | fse::kFSEventStreamEventFlagKernelDropped
+                | fse::kFSEventStreamEventFlagEventIdsWrapped
+                | fse::kFSEventStreamEventFlagRootChanged;
+            if flag & lost != 0 {
+                history.lost = true;
+                continue;
+            }
+            let path = unsafe { CStr::from_ptr(*paths.add(i)) };
+            let path = std::ffi::OsStr::from_bytes(path.to_bytes());
+            history.changes.push(Change {
+                // Directory-level events end in a separator
+                path: PathBuf::from(path).components().collect(),
+                subtree: flag & fse::kFSEventStreamEventFlagMustScanSubDirs != 0,
+            });
+        }
+    }
+
+    let dev = std::fs::metadata(root).ok()?.dev() as libc::dev_t;
+    let journal = unsafe {
+        let uuid = FSEventsCopyUUIDForDevice(dev);
+        if uuid.is_null() {
+            // The volume keeps no event history
+            return None;
+        }
+        let text = CFUUIDCreateString(cf::kCFAllocatorDefault, uuid);
+        let mut buf = [0 as c_char; 64];
+        let ok = cf::CFStringGetCString(text, buf.as_mut_ptr(), buf.len() as cf::CFIndex, cf::kCFStringEncodingUTF8);
+        cf::CFRelease(text);
+        cf::CFRelease(uuid);
+        if !ok {
+            return None;
+        }
+        format!("fsevents:{}", CStr::from_ptr(buf.as_ptr()).to_string_lossy())
+    };
+    // Taken before reading, so events racing with this scan are read again next time
+    let cursor = FeedCursor { journal, position: unsafe { fse::FSEventsGetCurrentEventId() } };
+    let start = match since {
+        Some(prev) if prev.journal == cursor.journal && prev.position <= cursor.position => prev.position,
+        _ => return Some(FeedRead { cursor, changed: None }),
+    };
+
+    let c_root = CString::new(root.as_os_str().as_bytes()).ok()?;
+    // Owned through a raw pointer while the callback may write to it
+    let history = Box::into_raw(Box::new(History { changes: Vec::new(), done: false, lost: false }));
+    unsafe {
+        let paths = cf::CFArrayCreateMutable(cf::kCFAllocatorDefault, 1, &cf::kCFTypeArrayCallBacks);
+        let path = cf::CFStringCreateWithCString(cf::kCFAllocatorDefault, c_root.as_ptr(), cf::kCFStringEncodingUTF8);
+        cf::CFArrayAppendValue(paths, path);
+        cf::CFRelease(path);
+        let context = fse::FSEventStreamContext {
+            version: 0,
+            info: history as *mut c_void,
+            retain: None,
+            release: None,
+            copy_description: None,
+        };
+        let stream = fse::FSEventStreamCreate(
+            cf::kCFAllocatorDefault,
+            callback,
+            &context,
+            paths,
+            start,
+            0.0,
+            fse::kFSEventStreamCreateFlagNone,
+        );
+        cf::CFRelease(paths);
+        if stream.is_null() {
+            drop(Box::from_raw(history));
+            return Some(FeedRead { cursor, changed: None });
+        }
+        fse::FSEventStreamScheduleWithRunLoop(stream, cf::CFRunLoopGetCurrent(), cf::kCFRunLoopDefaultMode);
+        if fse::FSEventStreamStart(stream) != 0 {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !(*history).done && Instant::now() < deadline {
+                CFRunLoopRunInMode(cf::kCFRunLoopDefaultMode, 0.25, 1);
+            }
+            fse::FSEventStreamStop(stream);
+        }
+        fse::FSEventStreamInvalidate(stream);
+        fse::FSEventStreamRelease(stream);
+    }
+
+    let history = unsafe { Box::from_raw(history) };
+    let changed = (history.done && !history.lost).then_some(history.changes);
+    Some(FeedRead { cursor, changed })
+}
+
+#[cfg(windows)]
+fn native_read(root: &Path, since: Option<&FeedCursor>) -> Option<FeedRead> {
+    use std::collections::HashMap;
+    use std::ffi::{OsStr, OsString};
+    use std::mem::size_of;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::Foundation::{CloseHandle, GENERIC_READ, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        CreateFileW, FileIdType, GetFinalPathNameByHandleW, GetVolumePathNameW, OpenFileById,
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_ID_DESCRIPTOR, FILE_NAME_NORMALIZED, FILE_READ_ATTRIBUTES,
+        FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    };
+    use windows_sys::Win32::System::Ioctl::{
+        FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_USN_JOURNAL, READ_USN_JOURNAL_DATA_V0, USN_JOURNAL_DATA_V0, USN_RECORD_V2,
+    };
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+
+    struct Handle(HANDLE);
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+    let wide = |s: &OsStr| s.encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+    let share = FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE;
+
+    // `C:\` (or `\\?\C:\`) -> `\\.\C:`
+    let mut volume = [0u16; 261];
+    if unsafe { GetVolumePathNameW(wide(root.as_os_str()).as_ptr(), volume.as_mut_ptr(), volume.len() as u32) } == 0 {
+        return None;
+    }
+    let volume = OsString::from_wide(&volume[..volume.iter().position(|c| *c == 0)?]);
+    let device = format!(r"\\.\{}", volume.to_string_lossy().trim_start_matches(r"\\?\").trim_end_matches('\\'));
+    let handle = unsafe {
+        CreateFileW(wide(device.as_ref()).as_ptr(), GENERIC_READ, share, null(), OPEN_EXISTING, 0, null_mut())
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    let volume = Handle(handle);
+
+    let mut journal = USN_JOURNAL_DATA_V0::default();
+    let mut returned = 0u32;
+    let queried = unsafe {
+        DeviceIoControl(
+            volume.0,
+            FSCTL_QUERY_USN_JOURNAL,
+            null(),
+            0,
+            &mut journal as *mut _ as *mut _,
+            size_of::<USN_JOURNAL_DATA_V0>() as u32,
+            &mut returned,
+            null_mut(),
+        )
+    };
+    if queried == 0 {
+        // Journaling is off on this volume
+        return None;
+    }
+    let cursor = FeedCursor { journal: format!("usn:{:x}", journal.UsnJournalID), position: journal.NextUsn as u64 };
+    let start = match since {
+        Some(prev) if prev.journal == cursor.journal && prev.position as i64 >= journal.FirstUsn => prev.position as i64,
+        _ => return Some(FeedRead { cursor, changed: None }),
+    };
+
+    // Records name a file by its parent directory's ID
+    let mut records: Vec<(u64, OsString)> = Vec::new();
+    let mut buf = vec![0u64; 8 * 1024];
+    let mut request = READ_USN_JOURNAL_DATA_V0 {
+        StartUsn: start,
+        ReasonMask: u32::MAX,
+        ReturnOnlyOnClose: 0,
+        Timeout: 0,
+        BytesToWaitFor: 0,
+        UsnJournalID: journal.UsnJournalID,
+    };
+    while request.StartUsn < journal.NextUsn {
+        let ok = unsafe {
+            DeviceIoControl(
+                volume.0,
+                FSCTL_READ_USN_JOURNAL,
+                &request as *const _ as *const _,
+                size_of::<READ_USN_JOURNAL_DATA_V0>() as u32,
+                buf.as_mut_ptr() as *mut _,
+                (buf.len() * size_of::<u64>()) as u32,
+                &mut returned,
+                null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Some(FeedRead { cursor, changed: None });
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, returned as usize) };
+        if bytes.len() < 8 {
+            break;
+        }
+        let next = i64::from_ne_bytes(bytes[..8].try_into().unwrap());
+        let mut at = 8;
+        while at + size_of::<USN_RECORD_V2>() <= bytes.len() {
+            let record: USN_RECORD_V2 = unsafe { std::ptr::read_unaligned(bytes.as_ptr().add(at) as *const _) };
+            let len = record.RecordLength as usize;
+            if len == 0 || at + len > bytes.len() {
+                break;
+            }
+            if record.MajorVersion == 2 {
+                let name_at = at + record.FileNameOffset as usize;
+                let name: Vec<u16> = bytes[name_at..name_at + record.FileNameLength as usize]
+                    .chunks_exact(2)
+                    .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+                    .collect();
+                records.push((record.ParentFileReferenceNumber, OsString::from_wide(&name)));
+            }
+            at += len;
+        }
+        if next <= request.StartUsn {
+            break;
+        }
+        request.StartUsn = next;
+    }
+
+    let resolve = |id: u64| -> Option<PathBuf> {
+        let mut descriptor = FILE_ID_DESCRIPTOR {
+            dwSize: size_of::<FILE_ID_DESCRIPTOR>() as u32,
+            Type: FileIdType,
+            ..Default::default()
+        };
+        descriptor.Anonymous.FileId = id as i64;
+        let handle = unsafe {
+            OpenFileById(volume.0, &descriptor, FILE_READ_ATTRIBUTES, share, null(), FILE_FLAG_BACKUP_SEMANTICS)
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return None;
+        }
+        let handle = Handle(handle);
+        let mut path = [0u16; 1024];
+        let n = unsafe { GetFinalPathNameByHandleW(handle.0, path.as_mut_ptr(), path.len() as u32, FILE_NAME_NORMALIZED) };
+        (n > 0 && (n as usize) < path.len()).then(|| PathBuf::from(OsString::from_wide(&path[..n as usize])))
+    };
+    // Directories deleted since cannot be resolved; their own deletion is
+    // recorded under a parent that still exists
+    let mut parents: HashMap<u64, Option<PathBuf>> = HashMap::new();
+    let mut changes = Vec::new();
+    for (parent, name) in records {
+        let Some(dir) = parents.entry(parent).or_insert_with(|| resolve(parent)) else { continue };
+        let path = dir.join(name);
+        if path.starts_with(root) {
+            changes.push(Change { path, subtree: false });
+        }
+    }
+    Some(FeedRead { cursor, changed: Some(changes) })
+}
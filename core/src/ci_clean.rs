@@ -0,0 +1,348 @@
+//! CI Runner Cleanup
+//!
+//! Ephemeral and long-lived CI runners accumulate one workspace per build and
+//! tool caches that grow without bound. `ci-clean` matches build workspaces
+//! under a root by glob, keeps the most recent builds of each pipeline, clears
+//! tool caches that exceed a size budget and reports everything as JSON.
+//! Runners need speed rather than undo, so removals delete directly unless
+//! quarantine is requested.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use ignore::overrides::OverrideBuilder;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+use walkdir::WalkDir;
+
+use crate::error::Error;
+use crate::native_walk::tree_totals;
+use crate::progress::{OperationContext, Phase};
+use crate::safety::{is_protected_path, move_to_quarantine_fast};
+
+/// How matched build directories are grouped into pipelines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineKey {
+    /// Builds are the children of a per-pipeline directory (`<pipeline>/<build>`)
+    #[default]
+    Parent,
+    /// Builds are siblings named `<pipeline>` plus an optional `@suffix`
+    /// (Jenkins' `job`, `job@2`, `job@tmp`)
+    Name,
+}
+
+impl FromStr for PipelineKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "parent" => Ok(Self::Parent),
+            "name" => Ok(Self::Name),
+            other => Err(format!("unknown pipeline key `{}` (expected parent or name)", other)),
+        }
+    }
+}
+
+impl fmt::Display for PipelineKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Parent => "parent",
+            Self::Name => "name",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CiCleanConfig {
+    pub workspace_root: PathBuf,
+    /// Globs, relative to `workspace_root`, matching build directories
+    pub patterns: Vec<String>,
+    /// Most recent builds kept per pipeline
+    pub keep_builds: usize,
+    pub pipeline_key: PipelineKey,
+    /// Tool caches cleared when larger than `cache_budget_bytes`
+    pub tool_caches: Vec<PathBuf>,
+    pub cache_budget_bytes: Option<u64>,
+    /// Move removals to quarantine instead of deleting them
+    pub quarantine: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildDir {
+    pub path: String,
+    pub pipeline: String,
+    pub size_bytes: u64,
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolCacheResult {
+    pub path: String,
+    pub size_bytes: u64,
+    pub budget_bytes: Option<u64>,
+    pub cleared: bool,
+}
+
+/// Machine-readable outcome of a `ci-clean` run
+#[derive(Debug, Clone, Serialize)]
+pub struct CiCleanSummary {
+    pub workspace_root: String,
+    pub dry_run: bool,
+    pub quarantined: bool,
+    pub pipelines: usize,
+    pub builds_matched: usize,
+    pub builds_kept: Vec<BuildDir>,
+    pub builds_removed: Vec<BuildDir>,
+    pub tool_caches: Vec<ToolCacheResult>,
+    pub bytes_freed: u64,
+    /// Removals that failed; the run carries on past them
+    pub errors: Vec<String>,
+    pub elapsed_ms: u64,
+}
+
+/// Tool caches CI jobs commonly fill, that exist on this machine
+pub fn default_tool_caches() -> Vec<PathBuf> {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    let cache = dirs::cache_dir().unwrap_or_else(|| home.join(".cache"));
+    let data = dirs::data_local_dir().unwrap_or_else(|| home.join(".local").join("share"));
+    [
+        home.join(".npm").join("_cacache"),
+        cache.join("yarn"),
+        data.join("pnpm").join("store"),
+        cache.join("pip"),
+        cache.join("go-build"),
+        home.join(".gradle").join("caches"),
+        home.join(".m2").join("repository"),
+        cache.join("ccache"),
+        cache.join("sccache"),
+    ]
+    .into_iter()
+    .filter(|p| p.is_dir())
+    .collect()
+}
+
+/// Build directories under `root` matching `patterns`; matched directories
+/// are not searched further
+pub fn match_builds(root: &Path, patterns: &[String]) -> crate::Result<Vec<PathBuf>> {
+    match_builds_impl(root, patterns).map_err(Error::lift(Error::Scan))
+}
+
+fn match_builds_impl(root: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut builder = OverrideBuilder::new(root);
+    for pattern in patterns {
+        builder.add(pattern).with_context(|| format!("Invalid workspace pattern `{}`", pattern))?;
+    }
+    let globs = builder.build()?;
+    // Without `**` nothing deeper than the longest pattern can match
+    let max_depth = if patterns.iter().any(|p| p.contains("**")) {
+        usize::MAX
+    } else {
+        patterns.iter().map(|p| p.trim_matches('/').split('/').count()).max().unwrap_or(0)
+    };
+
+    let mut matched = Vec::new();
+    let mut walker = WalkDir::new(root).min_depth(1).max_depth(max_depth).into_iter();
+    while let Some(entry) = walker.next() {
+        let Ok(entry) = entry else { continue };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        if globs.matched(entry.path(), true).is_whitelist() {
+            matched.push(entry.into_path());
+            walker.skip_current_dir();
+        }
+    }
+    Ok(matched)
+}
+
+fn pipeline_of(build: &Path, key: PipelineKey) -> String {
+    match key {
+        PipelineKey::Parent => build.parent().unwrap_or(build).to_string_lossy().to_string(),
+        PipelineKey::Name => {
+            let name = build.file_name().unwrap_or_default().to_string_lossy();
+            let base = name.split('@').next().unwrap_or_default();
+            build.with_file_name(base).to_string_lossy().to_string()
+        }
+    }
+}
+
+/// Builds per pipeline, newest first
+fn group_builds(builds: Vec<PathBuf>, key: PipelineKey) -> BTreeMap<String, Vec<BuildDir>> {
+    let mut pipelines: BTreeMap<String, Vec<BuildDir>> = BTreeMap::new();
+    for path in builds {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).map(Into::into).unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let pipeline = pipeline_of(&path, key);
+        pipelines.entry(pipeline.clone()).or_default().push(BuildDir {
+            path: path.to_string_lossy().to_string(),
+            pipeline,
+            size_bytes: tree_totals(&path, None).bytes,
+            modified,
+        });
+    }
+    for builds in pipelines.values_mut() {
+        builds.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.path.cmp(&b.path)));
+    }
+    pipelines
+}
+
+/// Remove old builds and oversized tool caches as configured
+pub fn ci_clean(config: &CiCleanConfig, ctx: &OperationContext) -> crate::Result<CiCleanSummary> {
+    ci_clean_impl(config, ctx).map_err(Error::lift(Error::Store))
+}
+
+fn ci_clean_impl(config: &CiCleanConfig, ctx: &OperationContext) -> Result<CiCleanSummary> {
+    let started = Instant::now();
+    if !config.dry_run {
+        crate::safety::ensure_writable("clean CI workspaces")?;
+    }
+    if !config.workspace_root.is_dir() {
+        anyhow::bail!("Workspace root {:?} is not a directory", config.workspace_root);
+    }
+    let root = fs::canonicalize(&config.workspace_root)?;
+    let builds = match_builds_impl(&root, &config.patterns)?;
+    let builds_matched = builds.len();
+    let pipelines = group_builds(builds, config.pipeline_key);
+
+    let mut summary = CiCleanSummary {
+        workspace_root: root.to_string_lossy().to_string(),
+        dry_run: config.dry_run,
+        quarantined: config.quarantine,
+        pipelines: pipelines.len(),
+        builds_matched,
+        builds_kept: Vec::new(),
+        builds_removed: Vec::new(),
+        tool_caches: Vec::new(),
+        bytes_freed: 0,
+        errors: Vec::new(),
+        elapsed_ms: 0,
+    };
+
+    let stale: Vec<BuildDir> = pipelines.into_values().flat_map(|builds| {
+        let mut builds = builds.into_iter();
+        summary.builds_kept.extend(builds.by_ref().take(config.keep_builds));
+        builds
+    }).collect();
+    let total = stale.len() as u64;
+    for (i, build) in stale.into_iter().enumerate() {
+        ctx.check()?;
+        ctx.report(if config.quarantine { Phase::Quarantine } else { Phase::Remove }, i as u64, Some(total), Some(Path::new(&build.path)));
+        match remove(Path::new(&build.path), config) {
+            Ok(()) => {
+                summary.bytes_freed += build.size_bytes;
+                summary.builds_removed.push(build);
+            }
+            Err(e) => summary.errors.push(format!("{}: {:#}", build.path, e)),
+        }
+    }
+
+    for cache in &config.tool_caches {
+        ctx.check()?;
+        let size_bytes = tree_totals(cache, None).bytes;
+        let over = config.cache_budget_bytes.is_some_and(|budget| size_bytes > budget);
+        let cleared = over && match remove(cache, config) {
+            Ok(()) => true,
+            Err(e) => {
+                summary.errors.push(format!("{}: {:#}", cache.display(), e));
+                false
+            }
+        };
+        if cleared {
+            summary.bytes_freed += size_bytes;
+        }
+        summary.tool_caches.push(ToolCacheResult {
+            path: cache.to_string_lossy().to_string(),
+            size_bytes,
+            budget_bytes: config.cache_budget_bytes,
+            cleared,
+        });
+    }
+
+    summary.elapsed_ms = started.elapsed().as_millis() as u64;
+    Ok(summary)
+}
+
+fn remove(path: &Path, config: &CiCleanConfig) -> Result<()> {
+    if is_protected_path(path) {
+        anyhow::bail!("inside PackagePurge's own store or quarantine directory");
+    }
+    if config.dry_run {
+        return Ok(());
+    }
+    if config.quarantine {
+        move_to_quarantine_fast(path)?;
+    } else {
+        fs::remove_dir_all(path).with_context(|| format!("Failed to remove {:?}", path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    fn build(path: &Path, age_days: u64) {
+        fs::create_dir_all(path).unwrap();
+        fs::write(path.join("out.bin"), vec![0u8; 100]).unwrap();
+        let when = SystemTime::now() - Duration::from_secs(age_days * 86_400);
+        fs::File::open(path).unwrap().set_modified(when).unwrap();
+    }
+
+    #[test]
+    fn test_ci_clean_keeps_recent_builds_per_pipeline() {
+        let temp = tempdir().unwrap();
+        let root = temp.path().join("builds");
+        for (i, age) in [1, 2, 3].iter().enumerate() {
+            build(&root.join(format!("app/{}", i)), *age);
+        }
+        build(&root.join("lib/7"), 10);
+        fs::create_dir_all(root.join("other/stuff")).unwrap();
+        let cache = temp.path().join("npm-cache");
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(cache.join("blob"), vec![0u8; 500]).unwrap();
+
+        let mut config = CiCleanConfig {
+            workspace_root: root.clone(),
+            patterns: vec!["app/*".into(), "lib/*".into()],
+            keep_builds: 1,
+            pipeline_key: PipelineKey::Parent,
+            tool_caches: vec![cache.clone()],
+            cache_budget_bytes: Some(100),
+            quarantine: false,
+            dry_run: true,
+        };
+        let planned = ci_clean(&config, &OperationContext::default()).unwrap();
+        assert_eq!((planned.builds_matched, planned.pipelines), (4, 2));
+        assert_eq!(planned.builds_removed.len(), 2);
+        assert!(root.join("app/1").exists());
+
+        config.dry_run = false;
+        let done = ci_clean(&config, &OperationContext::default()).unwrap();
+        assert!(root.join("app/0").exists() && root.join("lib/7").exists());
+        assert!(!root.join("app/1").exists() && !root.join("app/2").exists());
+        assert!(root.join("other/stuff").exists());
+        assert!(done.tool_caches[0].cleared && !cache.exists());
+        assert_eq!(done.bytes_freed, 700);
+        assert!(done.errors.is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_by_name() {
+        let temp = tempdir().unwrap();
+        for name in ["deploy", "deploy@2", "deploy@tmp", "test"] {
+            fs::create_dir_all(temp.path().join("workspace").join(name)).unwrap();
+        }
+        let builds = match_builds(temp.path(), &["workspace/*".to_string()]).unwrap();
+        let pipelines = group_builds(builds, PipelineKey::Name);
+        assert_eq!(pipelines.len(), 2);
+        assert_eq!(pipelines.values().map(|b| b.len()).max(), Some(3));
+        assert_eq!("name".parse::<PipelineKey>(), Ok(PipelineKey::Name));
+    }
+}
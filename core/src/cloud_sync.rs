@@ -0,0 +1,473 @@
+//! Cloud-Synced Folders
+//!
+//! Projects kept in OneDrive, Dropbox or iCloud Drive have their
+//! `node_modules` uploaded file by file with every install, and the sync
+//! client may evict files to online-only placeholders that report a length
+//! but take no space. Sync roots are found from:
+//! - the `OneDrive`, `OneDriveCommercial` and `OneDriveConsumer` variables
+//!   the Windows client sets
+//! - Dropbox's `info.json` (`~/.dropbox`, `%LOCALAPPDATA%\Dropbox`,
+//!   `%APPDATA%\Dropbox`), else `~/Dropbox`
+//! - the File Provider folders in `~/Library/CloudStorage`
+//! - iCloud Drive (`~/Library/Mobile Documents/com~apple~CloudDocs`, with
+//!   `~/Desktop` and `~/Documents` when those are synced, or `~/iCloudDrive`)
+//!
+//! A synced `node_modules` is kept local either by excluding it in place,
+//! where the provider supports that (Dropbox's `com.dropbox.ignored`
+//! attribute, iCloud's `.nosync` suffix behind a symlink), or by relocating
+//! it out of the sync root behind a symlink. None of the clients follow
+//! symlinks, so the link itself is all that syncs.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::native_walk::tree_totals;
+use crate::safety::ensure_writable;
+use crate::symlink::{create_symlink_impl, is_symlink};
+use crate::types::{DryRunReport, PlanWarning, ProjectRecord};
+
+/// `FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN |
+/// FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS`: cloud files whose data is not
+/// on the disk
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) const PLACEHOLDER_ATTRIBUTES: u32 = 0x1000 | 0x40000 | 0x400000;
+
+/// `SF_DATALESS` from `<sys/stat.h>`: a File Provider file evicted to iCloud
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const SF_DATALESS: u32 = 0x40000000;
+
+/// Whether a file's data is online only, so its length takes no space
+pub fn is_placeholder(meta: &fs::Metadata) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        meta.file_attributes() & PLACEHOLDER_ATTRIBUTES != 0
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::macos::fs::MetadataExt;
+        meta.st_flags() & SF_DATALESS != 0
+    }
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        let _ = meta;
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncProvider {
+    #[serde(rename = "onedrive")]
+    OneDrive,
+    Dropbox,
+    #[serde(rename = "icloud")]
+    ICloudDrive,
+}
+
+impl std::fmt::Display for SyncProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SyncProvider::OneDrive => "OneDrive",
+            SyncProvider::Dropbox => "Dropbox",
+            SyncProvider::ICloudDrive => "iCloud Drive",
+        })
+    }
+}
+
+/// A directory a sync client uploads
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncRoot {
+    pub provider: SyncProvider,
+    pub path: PathBuf,
+}
+
+/// Sync roots of the current user
+pub fn sync_roots() -> Vec<SyncRoot> {
+    match dirs::home_dir() {
+        Some(home) => roots_in(&home, |name| std::env::var_os(name)),
+        None => Vec::new(),
+    }
+}
+
+fn roots_in(home: &Path, env: impl Fn(&str) -> Option<OsString>) -> Vec<SyncRoot> {
+    let mut roots = Vec::new();
+    let mut add = |provider, path: PathBuf| {
+        if path.is_dir() && !roots.iter().any(|r: &SyncRoot| r.path == path) {
+            roots.push(SyncRoot { provider, path });
+        }
+    };
+
+    for name in ["OneDrive", "OneDriveCommercial", "OneDriveConsumer"] {
+        if let Some(dir) = env(name) {
+            add(SyncProvider::OneDrive, PathBuf::from(dir));
+        }
+    }
+
+    let info_files = [
+        Some(home.join(".dropbox/info.json")),
+        env("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Dropbox/info.json")),
+        env("APPDATA").map(|dir| PathBuf::from(dir).join("Dropbox/info.json")),
+    ];
+    let mut dropbox = Vec::new();
+    for info in info_files.into_iter().flatten() {
+        let Ok(text) = fs::read_to_string(&info) else { continue };
+        let Ok(serde_json::Value::Object(accounts)) = serde_json::from_str(&text) else { continue };
+        dropbox.extend(accounts.values().filter_map(|a| a.get("path")?.as_str()).map(PathBuf::from));
+    }
+    if dropbox.is_empty() {
+        dropbox.push(home.join("Dropbox"));
+    }
+    for dir in dropbox {
+        add(SyncProvider::Dropbox, dir);
+    }
+
+    if let Ok(entries) = fs::read_dir(home.join("Library/CloudStorage")) {
+        let mut entries: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        entries.sort();
+        for dir in entries {
+            let name = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            if name.starts_with("OneDrive") {
+                add(SyncProvider::OneDrive, dir);
+            } else if name.starts_with("Dropbox") {
+                add(SyncProvider::Dropbox, dir);
+            }
+        }
+    }
+
+    let cloud_docs = home.join("Library/Mobile Documents/com~apple~CloudDocs");
+    if cloud_docs.is_dir() {
+        // "Desktop & Documents Folders" shows up as these two in CloudDocs
+        for name in ["Desktop", "Documents"] {
+            if cloud_docs.join(name).exists() {
+                add(SyncProvider::ICloudDrive, home.join(name));
+            }
+        }
+        add(SyncProvider::ICloudDrive, cloud_docs);
+    }
+    add(SyncProvider::ICloudDrive, home.join("iCloudDrive"));
+    roots
+}
+
+/// The innermost of `roots` containing `path`
+pub fn root_of<'a>(path: &Path, roots: &'a [SyncRoot]) -> Option<&'a SyncRoot> {
+    roots.iter()
+        .filter(|r| path.starts_with(&r.path))
+        .max_by_key(|r| r.path.components().count())
+}
+
+/// How a synced `node_modules` is kept out of the upload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Exclusion {
+    /// Dropbox's `com.dropbox.ignored` attribute is set on it
+    Ignored,
+    /// Renamed to `node_modules.nosync` with a symlink in its place
+    NoSync,
+    /// Moved out of the sync root with a symlink in its place
+    Relocated { to: String },
+}
+
+/// A project's `node_modules` inside a sync root
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncedTree {
+    pub path: String,
+    pub project: String,
+    pub provider: SyncProvider,
+    pub sync_root: String,
+    /// Bytes on this disk; online-only placeholders are not counted
+    pub size_bytes: u64,
+    pub excluded: Option<Exclusion>,
+}
+
+fn exclusion(node_modules: &Path, provider: SyncProvider) -> Option<Exclusion> {
+    if is_symlink(node_modules) {
+        let target = fs::read_link(node_modules).ok()?;
+        if target.extension().is_some_and(|e| e == "nosync") {
+            return Some(Exclusion::NoSync);
+        }
+        return Some(Exclusion::Relocated { to: target.to_string_lossy().to_string() });
+    }
+    (provider == SyncProvider::Dropbox && platform::dropbox_ignored(node_modules)).then_some(Exclusion::Ignored)
+}
+
+/// The `node_modules` of `projects` that lie inside `roots`
+pub fn synced_trees(projects: &[ProjectRecord], roots: &[SyncRoot]) -> Vec<SyncedTree> {
+    projects.iter().filter_map(|project| {
+        let dir = Path::new(&project.path);
+        let root = root_of(dir, roots)?;
+        let node_modules = dir.join("node_modules");
+        fs::symlink_metadata(&node_modules).ok()?;
+        let excluded = exclusion(&node_modules, root.provider);
+        let size_bytes = match excluded {
+            Some(Exclusion::Ignored) | None => tree_totals(&node_modules, None).bytes,
+            Some(_) => 0,
+        };
+        Some(SyncedTree {
+            path: node_modules.to_string_lossy().to_string(),
+            project: project.path.clone(),
+            provider: root.provider,
+            sync_root: root.path.to_string_lossy().to_string(),
+            size_bytes,
+            excluded,
+        })
+    })
+    .collect()
+}
+
+/// Warn about every `node_modules` of `projects` still synced. Returns the
+/// number of warnings added.
+pub fn flag_synced(report: &mut DryRunReport, projects: &[ProjectRecord], roots: &[SyncRoot]) -> usize {
+    let before = report.warnings.len();
+    for tree in synced_trees(projects, roots).into_iter().filter(|t| t.excluded.is_none()) {
+        report.warnings.push(PlanWarning {
+            message: format!(
+                "synced by {} from {}; every install uploads it again (`cloud-sync exclude` or `cloud-sync relocate` keeps it local)",
+                tree.provider, tree.sync_root
+            ),
+            target_path: tree.path,
+        });
+    }
+    report.warnings.len() - before
+}
+
+/// Exclude `node_modules` from its provider's sync in place
+pub fn exclude(node_modules: &Path, roots: &[SyncRoot]) -> crate::Result<Exclusion> {
+    exclude_impl(node_modules, roots).map_err(Error::lift(Error::Store))
+}
+
+fn exclude_impl(node_modules: &Path, roots: &[SyncRoot]) -> Result<Exclusion> {
+    ensure_writable("exclude trees from cloud sync")?;
+    let root = root_of(node_modules, roots).with_context(|| format!("{:?} is not inside a synced folder", node_modules))?;
+    anyhow::ensure!(!is_symlink(node_modules), "{:?} is already a symlink", node_modules);
+    match root.provider {
+        SyncProvider::Dropbox => {
+            platform::set_dropbox_ignored(node_modules)
+                .with_context(|| format!("Failed to mark {:?} ignored by Dropbox", node_modules))?;
+            Ok(Exclusion::Ignored)
+        }
+        SyncProvider::ICloudDrive => {
+            let name = node_modules.file_name().context("No directory name")?;
+            let mut renamed = name.to_os_string();
+            renamed.push(".nosync");
+            let nosync = node_modules.with_file_name(&renamed);
+            anyhow::ensure!(!nosync.exists(), "{:?} already exists", nosync);
+            fs::rename(node_modules, &nosync).with_context(|| format!("Failed to rename {:?}", node_modules))?;
+            create_symlink_impl(node_modules, &nosync)?;
+            Ok(Exclusion::NoSync)
+        }
+        SyncProvider::OneDrive => anyhow::bail!(
+            "OneDrive cannot exclude a folder inside its sync root; `cloud-sync relocate` moves {:?} out of it",
+            node_modules
+        ),
+    }
+}
+
+/// Move `node_modules` below `dest`, outside every sync root, and leave a
+/// symlink to it in its place
+pub fn relocate(node_modules: &Path, dest: &Path, roots: &[SyncRoot]) -> crate::Result<Exclusion> {
+    relocate_impl(node_modules, dest, roots).map_err(Error::lift(Error::Store))
+}
+
+fn relocate_impl(node_modules: &Path, dest: &Path, roots: &[SyncRoot]) -> Result<Exclusion> {
+    ensure_writable("relocate trees out of cloud sync")?;
+    anyhow::ensure!(node_modules.is_dir() && !is_symlink(node_modules), "{:?} is not a directory", node_modules);
+    if let Some(root) = root_of(dest, roots) {
+        anyhow::bail!("{:?} is inside {} ({:?}) as well", dest, root.provider, root.path);
+    }
+    // One directory per project: its name plus a digest of where it lives
+    let project = node_modules.parent().context("No project directory")?;
+    let digest = hex::encode(Sha256::digest(project.to_string_lossy().as_bytes()));
+    let name = project.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let target = dest.join(format!("{}-{}", name, &digest[..8])).join("node_modules");
+    anyhow::ensure!(!target.exists(), "{:?} already exists", target);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {:?}", parent))?;
+    }
+
+    if fs::rename(node_modules, &target).is_err() {
+        // Another volume: copy, then remove the synced tree
+        let opts = fs_extra::dir::CopyOptions::new().copy_inside(true);
+        fs_extra::dir::copy(node_modules, &target, &opts)
+            .map_err(|e| anyhow::anyhow!("Failed to copy {:?} to {:?}: {}", node_modules, target, e))?;
+        fs::remove_dir_all(node_modules).with_context(|| format!("Failed to remove {:?}", node_modules))?;
+    }
+    create_symlink_impl(node_modules, &target)?;
+    Ok(Exclusion::Relocated { to: target.to_string_lossy().to_string() })
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    const ATTR: &str = "com.dropbox.ignored";
+
+    fn c_strings(dir: &Path) -> io::Result<(CString, CString)> {
+        let path = CString::new(dir.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let name = CString::new(ATTR).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok((path, name))
+    }
+
+    pub fn dropbox_ignored(dir: &Path) -> bool {
+        let Ok((path, name)) = c_strings(dir) else { return false };
+        let len = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0, 0, libc::XATTR_NOFOLLOW) };
+        len > 0
+    }
+
+    pub fn set_dropbox_ignored(dir: &Path) -> io::Result<()> {
+        let (path, name) = c_strings(dir)?;
+        let rc = unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), b"1".as_ptr().cast(), 1, 0, libc::XATTR_NOFOLLOW) };
+        if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Linux keeps unprivileged attributes in the `user.` namespace
+    const ATTR: &str = "user.com.dropbox.ignored";
+
+    fn c_strings(dir: &Path) -> io::Result<(CString, CString)> {
+        let path = CString::new(dir.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let name = CString::new(ATTR).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok((path, name))
+    }
+
+    pub fn dropbox_ignored(dir: &Path) -> bool {
+        let Ok((path, name)) = c_strings(dir) else { return false };
+        let len = unsafe { libc::lgetxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        len > 0
+    }
+
+    pub fn set_dropbox_ignored(dir: &Path) -> io::Result<()> {
+        let (path, name) = c_strings(dir)?;
+        let rc = unsafe { libc::lsetxattr(path.as_ptr(), name.as_ptr(), b"1".as_ptr().cast(), 1, 0) };
+        if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    /// Dropbox reads the attribute from an alternate data stream
+    fn stream(dir: &Path) -> std::path::PathBuf {
+        let mut path = dir.as_os_str().to_os_string();
+        path.push(":com.dropbox.ignored");
+        path.into()
+    }
+
+    pub fn dropbox_ignored(dir: &Path) -> bool {
+        fs::read_to_string(stream(dir)).is_ok_and(|v| v.trim() == "1")
+    }
+
+    pub fn set_dropbox_ignored(dir: &Path) -> io::Result<()> {
+        fs::write(stream(dir), "1")
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+mod platform {
+    use std::io;
+    use std::path::Path;
+
+    pub fn dropbox_ignored(_dir: &Path) -> bool {
+        false
+    }
+
+    pub fn set_dropbox_ignored(_dir: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "not supported on this platform"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use tempfile::tempdir;
+
+    fn project(path: &Path) -> ProjectRecord {
+        ProjectRecord { path: path.to_string_lossy().to_string(), manager: None, dependencies: Vec::new(), mtime: Utc::now() }
+    }
+
+    #[test]
+    fn test_sync_roots_and_synced_trees() {
+        let temp = tempdir().unwrap();
+        let home = temp.path();
+        let dropbox = home.join("Work Dropbox");
+        fs::create_dir_all(home.join(".dropbox")).unwrap();
+        fs::write(
+            home.join(".dropbox/info.json"),
+            serde_json::json!({ "business": { "path": dropbox } }).to_string(),
+        ).unwrap();
+        fs::create_dir_all(home.join("Library/CloudStorage/OneDrive-Personal")).unwrap();
+        fs::create_dir_all(home.join("Library/CloudStorage/GoogleDrive-me")).unwrap();
+        fs::create_dir_all(home.join("Library/Mobile Documents/com~apple~CloudDocs/Documents")).unwrap();
+        for dir in [&dropbox, &home.join("Documents"), &home.join("Corp OneDrive")] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let corp = home.join("Corp OneDrive").into_os_string();
+        let roots = roots_in(home, |name| (name == "OneDriveCommercial").then(|| corp.clone()));
+        let found: Vec<(SyncProvider, &Path)> = roots.iter().map(|r| (r.provider, r.path.strip_prefix(home).unwrap())).collect();
+        assert_eq!(found, [
+            (SyncProvider::OneDrive, Path::new("Corp OneDrive")),
+            (SyncProvider::Dropbox, Path::new("Work Dropbox")),
+            (SyncProvider::OneDrive, Path::new("Library/CloudStorage/OneDrive-Personal")),
+            (SyncProvider::ICloudDrive, Path::new("Documents")),
+            (SyncProvider::ICloudDrive, Path::new("Library/Mobile Documents/com~apple~CloudDocs")),
+        ]);
+
+        let synced = home.join("Documents/site");
+        let local = home.join("src/tool");
+        for dir in [&synced, &local] {
+            fs::create_dir_all(dir.join("node_modules/left-pad")).unwrap();
+            fs::write(dir.join("node_modules/left-pad/index.js"), vec![b'x'; 100]).unwrap();
+        }
+        let projects = [project(&synced), project(&local)];
+        let trees = synced_trees(&projects, &roots);
+        assert_eq!(trees.len(), 1);
+        assert_eq!((trees[0].provider, trees[0].size_bytes, &trees[0].excluded), (SyncProvider::ICloudDrive, 100, &None));
+
+        let mut report = DryRunReport {
+            items: Vec::new(),
+            total_estimated_bytes: 0,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+        assert_eq!(flag_synced(&mut report, &projects, &roots), 1);
+        assert!(report.warnings[0].message.contains("iCloud Drive"));
+
+        // iCloud skips `.nosync` names; the symlink keeps the project working
+        let node_modules = synced.join("node_modules");
+        assert_eq!(exclude(&node_modules, &roots).unwrap(), Exclusion::NoSync);
+        assert!(synced.join("node_modules.nosync/left-pad/index.js").is_file());
+        assert!(node_modules.join("left-pad/index.js").is_file());
+        assert_eq!(flag_synced(&mut report, &projects, &roots), 0);
+        assert!(exclude(&local.join("node_modules"), &roots).is_err());
+
+        let onedrive = home.join("Corp OneDrive/app");
+        fs::create_dir_all(onedrive.join("node_modules/react")).unwrap();
+        let moved = relocate(&onedrive.join("node_modules"), &home.join("local"), &roots).unwrap();
+        let Exclusion::Relocated { to } = moved else { panic!("not relocated") };
+        assert!(Path::new(&to).join("react").is_dir());
+        assert!(onedrive.join("node_modules/react").is_dir());
+        assert!(relocate(&local.join("node_modules"), &home.join("Documents/local"), &roots).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Compiler Cache Policies
+//!
+//! Detects ccache and sccache directories, reads their statistics through the
+//! tools themselves when installed, and trims a cache to a size budget by
+//! deleting its least recently used entries. The budget defaults to the
+//! cache's own configured maximum (`max_size` in `ccache.conf`,
+//! `SCCACHE_CACHE_SIZE` for sccache).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+use crate::error::Error;
+use crate::types::{DryRunReport, PlanItem, PlanReason};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompilerCacheKind {
+    Ccache,
+    Sccache,
+}
+
+impl CompilerCacheKind {
+    fn binary(&self) -> &'static str {
+        match self {
+            Self::Ccache => "ccache",
+            Self::Sccache => "sccache",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompilerCache {
+    pub kind: CompilerCacheKind,
+    pub path: String,
+    pub size_bytes: u64,
+    pub file_count: usize,
+    /// Maximum size the cache is configured for, if known
+    pub configured_max_bytes: Option<u64>,
+    /// Statistics reported by the tool itself, when it is installed
+    pub stats: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TrimReport {
+    pub path: String,
+    pub budget_bytes: u64,
+    pub size_before: u64,
+    pub size_after: u64,
+    pub files_removed: usize,
+    pub bytes_freed: u64,
+    pub dry_run: bool,
+}
+
+/// Candidate cache directories, most specific first: `$CCACHE_DIR` /
+/// `$SCCACHE_DIR`, then the platform defaults
+fn candidate_dirs() -> Vec<(CompilerCacheKind, PathBuf)> {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    let cache = dirs::cache_dir().unwrap_or_else(|| home.join(".cache"));
+    let mut dirs = Vec::new();
+    if let Some(dir) = std::env::var_os("CCACHE_DIR") {
+        dirs.push((CompilerCacheKind::Ccache, PathBuf::from(dir)));
+    }
+    dirs.push((CompilerCacheKind::Ccache, cache.join("ccache")));
+    dirs.push((CompilerCacheKind::Ccache, home.join(".ccache")));
+    if let Some(dir) = std::env::var_os("SCCACHE_DIR") {
+        dirs.push((CompilerCacheKind::Sccache, PathBuf::from(dir)));
+    }
+    dirs.push((CompilerCacheKind::Sccache, cache.join("sccache")));
+    dirs.push((CompilerCacheKind::Sccache, cache.join("Mozilla.sccache")));
+    dirs.push((CompilerCacheKind::Sccache, cache.join("Mozilla").join("sccache").join("cache")));
+    dirs
+}
+
+/// Compiler caches present on this machine
+pub fn detect_compiler_caches() -> Vec<CompilerCache> {
+    let mut seen = std::collections::HashSet::new();
+    candidate_dirs().into_iter()
+        .filter(|(_, dir)| dir.is_dir())
+        .filter(|(_, dir)| seen.insert(fs::canonicalize(dir).unwrap_or_else(|_| dir.clone())))
+        .map(|(kind, dir)| inspect(kind, &dir))
+        .collect()
+}
+
+/// Size, configured maximum and tool statistics of the cache at `dir`
+pub fn inspect(kind: CompilerCacheKind, dir: &Path) -> CompilerCache {
+    let files = cache_files(kind, dir);
+    CompilerCache {
+        kind,
+        path: dir.to_string_lossy().to_string(),
+        size_bytes: files.iter().map(|f| f.size).sum(),
+        file_count: files.len(),
+        configured_max_bytes: configured_max(kind, dir),
+        stats: tool_stats(kind, dir),
+    }
+}
+
+fn configured_max(kind: CompilerCacheKind, dir: &Path) -> Option<u64> {
+    match kind {
+        CompilerCacheKind::Ccache => {
+            let conf = fs::read_to_string(dir.join("ccache.conf")).ok();
+            let value = conf.as_deref().and_then(|c| c.lines().find_map(|l| {
+                let (key, value) = l.split_once('=')?;
+                (key.trim() == "max_size").then(|| value.trim().to_string())
+            }));
+            // ccache's built-in default
+            parse_size(value.as_deref().unwrap_or("5G"))
+        }
+        CompilerCacheKind::Sccache => {
+            let value = std::env::var("SCCACHE_CACHE_SIZE").ok();
+            parse_size(value.as_deref().unwrap_or("10G"))
+        }
+    }
+}
+
+/// Parse sizes like `5G`, `500M`, `2.5Gi` or a plain byte count. Unsuffixed
+/// multipliers are decimal as in ccache; an `i` suffix makes them binary.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (number, suffix) = s.split_at(s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len()));
+    let number: f64 = number.trim().parse().ok()?;
+    let suffix = suffix.trim().trim_end_matches(['B', 'b']);
+    let (unit, binary) = match suffix.strip_suffix('i') {
+        Some(u) => (u, true),
+        None => (suffix, false),
+    };
+    let exponent = match unit.to_ascii_uppercase().as_str() {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return None,
+    };
+    let base: f64 = if binary { 1024.0 } else { 1000.0 };
+    Some((number * base.powi(exponent)) as u64)
+}
+
+/// Machine-readable statistics from the tool, if it is on PATH
+fn tool_stats(kind: CompilerCacheKind, dir: &Path) -> Option<serde_json::Value> {
+    let mut cmd = Command::new(kind.binary());
+    let output = match kind {
+        CompilerCacheKind::Ccache => cmd.arg("--print-stats").env("CCACHE_DIR", dir),
+        CompilerCacheKind::Sccache => cmd.args(["--show-stats", "--stats-format=json"]).env("SCCACHE_DIR", dir),
+    }
+    .output()
+    .ok()
+    .filter(|o| o.status.success())?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    match kind {
+        CompilerCacheKind::Ccache => {
+            let map: serde_json::Map<String, serde_json::Value> = text.lines()
+                .filter_map(|l| l.split_once('\t'))
+                .map(|(k, v)| (k.to_string(), v.trim().parse::<u64>().map(Into::into).unwrap_or_else(|_| v.trim().into())))
+                .collect();
+            Some(serde_json::Value::Object(map))
+        }
+        CompilerCacheKind::Sccache => serde_json::from_str(&text).ok(),
+    }
+}
+
+struct CacheFile {
+    path: PathBuf,
+    size: u64,
+    last_used: DateTime<Utc>,
+}
+
+/// Cached objects, excluding the tool's own configuration, statistics and locks
+fn cache_files(kind: CompilerCacheKind, dir: &Path) -> Vec<CacheFile> {
+    let walker = WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || e.file_name() != "tmp");
+    walker.filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy();
+            let bookkeeping = name.ends_with(".lock") || name == "CACHEDIR.TAG"
+                || (kind == CompilerCacheKind::Ccache && (name == "ccache.conf" || name == "stats"));
+            !bookkeeping
+        })
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            let mtime: DateTime<Utc> = meta.modified().ok()?.into();
+            let atime: DateTime<Utc> = meta.accessed().map(Into::into).unwrap_or(mtime);
+            Some(CacheFile { path: e.into_path(), size: meta.len(), last_used: atime.max(mtime) })
+        })
+        .collect()
+}
+
+/// Files to evict, least recently used first, until the cache fits `budget`
+fn eviction_order(mut files: Vec<CacheFile>, budget: u64) -> (u64, Vec<CacheFile>) {
+    let total: u64 = files.iter().map(|f| f.size).sum();
+    files.sort_by_key(|f| f.last_used);
+    let mut remaining = total;
+    let evict = files.into_iter()
+        .take_while(|f| {
+            let over = remaining > budget;
+            if over {
+                remaining -= f.size;
+            }
+            over
+        })
+        .collect();
+    (total, evict)
+}
+
+/// Delete the oldest entries of the cache at `dir` until it fits in
+/// `budget_bytes`. With `dry_run` nothing is deleted.
+pub fn trim_cache(kind: CompilerCacheKind, dir: &Path, budget_bytes: u64, dry_run: bool) -> crate::Result<TrimReport> {
+    trim_cache_impl(kind, dir, budget_bytes, dry_run).map_err(Error::lift(Error::Store))
+}
+
+fn trim_cache_impl(kind: CompilerCacheKind, dir: &Path, budget_bytes: u64, dry_run: bool) -> Result<TrimReport> {
+    if !dry_run {
+        crate::safety::ensure_writable("trim compiler caches")?;
+    }
+    let (size_before, evict) = eviction_order(cache_files(kind, dir), budget_bytes);
+    let mut report = TrimReport {
+        path: dir.to_string_lossy().to_string(),
+        budget_bytes,
+        size_before,
+        dry_run,
+        ..Default::default()
+    };
+    for file in evict {
+        if !dry_run {
+            fs::remove_file(&file.path).with_context(|| format!("Failed to remove {:?}", file.path))?;
+        }
+        report.files_removed += 1;
+        report.bytes_freed += file.size;
+    }
+    report.size_after = size_before - report.bytes_freed;
+    Ok(report)
+}
+
+/// Savings from trimming each cache to `budget_bytes`, or to its configured
+/// maximum when no budget is given
+pub fn plan_compiler_cache_trim(caches: &[CompilerCache], budget_bytes: Option<u64>) -> DryRunReport {
+    let items: Vec<PlanItem> = caches.iter().filter_map(|c| {
+        let budget = budget_bytes.or(c.configured_max_bytes)?;
+        let excess = c.size_bytes.saturating_sub(budget);
+        (excess > 0).then(|| PlanItem {
+            target_path: c.path.clone(),
+            estimated_size_bytes: excess,
+            reason: PlanReason::Regenerable { kind: c.kind.binary().to_string() },
+            blockers: Vec::new(),
+            risk: None,
+        })
+    }).collect();
+    let total = items.iter().map(|i| i.estimated_size_bytes).sum();
+    DryRunReport { items, total_estimated_bytes: total, lru: None, warnings: Vec::new(), confirm: Vec::new(), estimated_inodes: None, eviction: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("5G"), Some(5_000_000_000));
+        assert_eq!(parse_size("2.5 GiB"), Some(2_684_354_560));
+        assert_eq!(parse_size("500M"), Some(500_000_000));
+        assert_eq!(parse_size("1024"), Some(1024));
+        assert_eq!(parse_size("lots"), None);
+    }
+
+    #[test]
+    fn test_trim_evicts_oldest_first() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        fs::write(dir.join("ccache.conf"), "max_size = 250\n").unwrap();
+        fs::create_dir_all(dir.join("a")).unwrap();
+        let now = SystemTime::now();
+        for (i, name) in ["old", "mid", "new"].iter().enumerate() {
+            let path = dir.join("a").join(name);
+            fs::write(&path, vec![0u8; 100]).unwrap();
+            let when = now - Duration::from_secs(3600 * (3 - i as u64));
+            fs::File::options().write(true).open(&path).unwrap()
+                .set_times(fs::FileTimes::new().set_accessed(when).set_modified(when)).unwrap();
+        }
+
+        let cache = inspect(CompilerCacheKind::Ccache, dir);
+        assert_eq!(cache.size_bytes, 300);
+        assert_eq!(cache.configured_max_bytes, Some(250));
+        assert_eq!(plan_compiler_cache_trim(std::slice::from_ref(&cache), None).total_estimated_bytes, 50);
+
+        let dry = trim_cache(CompilerCacheKind::Ccache, dir, 250, true).unwrap();
+        assert_eq!(dry.files_removed, 1);
+        assert!(dir.join("a/old").exists());
+
+        let report = trim_cache(CompilerCacheKind::Ccache, dir, 250, false).unwrap();
+        assert_eq!((report.files_removed, report.size_after), (1, 200));
+        assert!(!dir.join("a/old").exists());
+        assert!(dir.join("a/new").exists());
+        assert!(dir.join("ccache.conf").exists());
+    }
+}
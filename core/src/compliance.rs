@@ -0,0 +1,310 @@
+//! Policy Compliance
+//!
+//! Checks a machine against a policy an organization hands out as a JSON
+//! file:
+//!
+//! ```json
+//! {
+//!   "name": "eng-laptops-2026",
+//!   "max_cache_size": "20G",
+//!   "max_stale_node_modules_days": 90,
+//!   "required_exclusions": ["node_modules", "cache", "store"]
+//! }
+//! ```
+//!
+//! Every field is optional; a check without its field is not run. The
+//! report names the host, its role and the policy, passes or fails each
+//! check, and carries a plan that brings the machine back within the limits
+//! (stale `node_modules` first, then the least recently used cache entries).
+//! Reports from many machines can be collected as-is and aggregated by
+//! `host` and `policy`.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::backup_exclude::{self, ExclusionMethod, TargetKind};
+use crate::compiler_caches::parse_size;
+use crate::error::Error;
+use crate::machine_role::MachineRole;
+use crate::provider_caches::is_provider_cache_dir;
+use crate::scanner::is_cache_dir;
+use crate::types::{DryRunReport, PackageRecord, PlanItem, PlanReason, ScanOutput};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompliancePolicy {
+    /// Reported with every result, to tell policies apart when aggregating
+    pub name: Option<String>,
+    /// Largest combined size of package manager caches, e.g. `"20G"`
+    pub max_cache_size: Option<String>,
+    /// Longest a `node_modules` may go unused
+    pub max_stale_node_modules_days: Option<i64>,
+    /// Trees that must be kept out of backups
+    pub required_exclusions: Vec<TargetKind>,
+}
+
+/// Read and validate a policy file
+pub fn load_policy(path: &Path) -> crate::Result<CompliancePolicy> {
+    load_policy_impl(path).map_err(Error::lift(Error::Config))
+}
+
+fn load_policy_impl(path: &Path) -> Result<CompliancePolicy> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let policy: CompliancePolicy = serde_json::from_str(&text).with_context(|| format!("Failed to parse {:?}", path))?;
+    if let Some(size) = &policy.max_cache_size {
+        parse_size(size).with_context(|| format!("Invalid max_cache_size {:?} in {:?}", size, path))?;
+    }
+    Ok(policy)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    MaxCacheSize,
+    StaleNodeModules,
+    BackupExclusions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub check: Check,
+    pub passed: bool,
+    /// The policy's limit, as written in the policy
+    pub limit: String,
+    /// What this machine has
+    pub actual: String,
+    /// Paths that break the limit
+    pub offenders: Vec<String>,
+    /// How to fix a failure
+    pub remediation: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComplianceReport {
+    pub policy: Option<String>,
+    pub host: String,
+    pub role: MachineRole,
+    pub generated_at: DateTime<Utc>,
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+    /// Cleanup that fixes the size and staleness failures; empty when they pass
+    pub plan: DryRunReport,
+}
+
+/// Name of this machine, for telling reports apart
+pub fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn last_used(pkg: &PackageRecord) -> DateTime<Utc> {
+    pkg.atime.max(pkg.mtime)
+}
+
+/// Outermost `node_modules` a package is installed in
+fn outer_node_modules(path: &Path) -> Option<&Path> {
+    path.ancestors().filter(|a| a.file_name().is_some_and(|n| n == "node_modules")).last()
+}
+
+/// Evaluate `policy` against `scan`. `store` is the global store, checked
+/// when the policy requires it excluded from backups.
+pub fn evaluate(
+    policy: &CompliancePolicy,
+    scan: &ScanOutput,
+    store: Option<&Path>,
+    host: String,
+    role: MachineRole,
+    now: DateTime<Utc>,
+) -> ComplianceReport {
+    let mut checks = Vec::new();
+    let mut items: Vec<PlanItem> = Vec::new();
+
+    if let Some(max_days) = policy.max_stale_node_modules_days {
+        // Newest use of any package in each node_modules, and its size
+        let mut trees: BTreeMap<&Path, (DateTime<Utc>, u64)> = BTreeMap::new();
+        for pkg in &scan.packages {
+            if let Some(nm) = outer_node_modules(Path::new(&pkg.path)) {
+                let entry = trees.entry(nm).or_insert((last_used(pkg), 0));
+                entry.0 = entry.0.max(last_used(pkg));
+                entry.1 += pkg.size_bytes;
+            }
+        }
+        let mut stalest = 0;
+        let mut offenders = Vec::new();
+        for (nm, (used, size)) in trees {
+            let days = (now - used).num_days();
+            stalest = stalest.max(days);
+            if days > max_days {
+                offenders.push(nm.to_string_lossy().to_string());
+                items.push(PlanItem {
+                    target_path: nm.to_string_lossy().to_string(),
+                    estimated_size_bytes: size,
+                    reason: PlanReason::Old { days },
+                    blockers: Vec::new(),
+                    risk: None,
+                });
+            }
+        }
+        checks.push(CheckResult {
+            check: Check::StaleNodeModules,
+            passed: offenders.is_empty(),
+            limit: format!("{} days", max_days),
+            actual: format!("{} days", stalest),
+            remediation: (!offenders.is_empty()).then(|| format!("Apply the plan to remove {} stale node_modules", offenders.len())),
+            offenders,
+        });
+    }
+
+    if let Some(budget) = policy.max_cache_size.as_deref().and_then(parse_size) {
+        let mut cached: Vec<&PackageRecord> = Vec::new();
+        let mut roots: BTreeMap<&Path, u64> = BTreeMap::new();
+        for pkg in &scan.packages {
+            let path = Path::new(&pkg.path);
+            if outer_node_modules(path).is_some() {
+                continue;
+            }
+            if let Some(root) = path.ancestors().filter(|a| is_cache_dir(a) || is_provider_cache_dir(a)).last() {
+                *roots.entry(root).or_default() += pkg.size_bytes;
+                cached.push(pkg);
+            }
+        }
+        let total: u64 = roots.values().sum();
+        let passed = total <= budget;
+        if !passed {
+            // Least recently used entries first, until the rest fits
+            cached.sort_by_key(|p| last_used(p));
+            let mut remaining = total;
+            for pkg in cached {
+                if remaining <= budget {
+                    break;
+                }
+                remaining -= pkg.size_bytes;
+                items.push(PlanItem {
+                    target_path: pkg.path.clone(),
+                    estimated_size_bytes: pkg.size_bytes,
+                    reason: PlanReason::SizePressure { budget },
+                    blockers: Vec::new(),
+                    risk: None,
+                });
+            }
+        }
+        let mut offenders: Vec<(&Path, u64)> = roots.into_iter().collect();
+        offenders.sort_by_key(|o| std::cmp::Reverse(o.1));
+        checks.push(CheckResult {
+            check: Check::MaxCacheSize,
+            passed,
+            limit: policy.max_cache_size.clone().unwrap_or_default(),
+            actual: format!("{} bytes", total),
+            offenders: if passed { Vec::new() } else { offenders.iter().map(|(p, _)| p.to_string_lossy().to_string()).collect() },
+            remediation: (!passed).then(|| format!("Apply the plan to free {} bytes of least recently used cache entries", total - budget)),
+        });
+    }
+
+    if !policy.required_exclusions.is_empty() {
+        let methods = backup_exclude::platform_methods();
+        let required: Vec<_> = backup_exclude::targets(scan, store)
+            .into_iter()
+            .filter(|t| policy.required_exclusions.contains(&t.kind) && t.path.exists())
+            .collect();
+        let offenders: Vec<String> = required.iter()
+            .filter(|t| !methods.iter().any(|m: &ExclusionMethod| backup_exclude::is_marked(&t.path, *m)))
+            .map(|t| t.path.to_string_lossy().to_string())
+            .collect();
+        let kinds: Vec<String> = policy.required_exclusions.iter()
+            .map(|k| serde_json::to_value(k).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default())
+            .collect();
+        checks.push(CheckResult {
+            check: Check::BackupExclusions,
+            passed: offenders.is_empty(),
+            limit: kinds.join(", "),
+            actual: format!("{} of {} excluded", required.len() - offenders.len(), required.len()),
+            remediation: (!offenders.is_empty()).then(|| "Run `backup-exclude` over the same paths".to_string()),
+            offenders,
+        });
+    }
+
+    let total_estimated_bytes = items.iter().map(|i| i.estimated_size_bytes).sum();
+    ComplianceReport {
+        policy: policy.name.clone(),
+        host,
+        role,
+        generated_at: now,
+        passed: checks.iter().all(|c| c.passed),
+        checks,
+        plan: DryRunReport {
+            items,
+            total_estimated_bytes,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn package(path: &str, size: u64, idle_days: i64) -> PackageRecord {
+        let used = Utc::now() - Duration::days(idle_days);
+        PackageRecord { atime: used, mtime: used, ..PackageRecord::for_test(path, "1.0.0", size) }
+    }
+
+    #[test]
+    fn test_compliance_checks_and_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let tagged = dir.path().join("web");
+        let untagged = dir.path().join("api");
+        fs::create_dir_all(tagged.join("node_modules")).unwrap();
+        fs::create_dir_all(untagged.join("node_modules")).unwrap();
+        backup_exclude::mark(&tagged.join("node_modules"), ExclusionMethod::CachedirTag).unwrap();
+
+        let scan = ScanOutput {
+            packages: vec![
+                package(&format!("{}/web/node_modules/react", root), 100, 1),
+                package(&format!("{}/api/node_modules/express", root), 100, 200),
+                package(&format!("{}/api/node_modules/lodash", root), 100, 150),
+                package(&format!("{}/.npm/_cacache/a", root), 600, 30),
+                package(&format!("{}/.npm/_cacache/b", root), 600, 10),
+            ],
+            projects: Vec::new(),
+            edges: Vec::new(),
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let policy = CompliancePolicy {
+            name: Some("laptops".to_string()),
+            max_cache_size: Some("1K".to_string()),
+            max_stale_node_modules_days: Some(90),
+            required_exclusions: vec![TargetKind::NodeModules],
+        };
+        let report = evaluate(&policy, &scan, None, "dev-1".to_string(), MachineRole::Laptop, Utc::now());
+        assert!(!report.passed);
+        assert_eq!(report.checks.len(), 3);
+        assert!(report.checks.iter().all(|c| !c.passed));
+        assert_eq!(report.checks[0].offenders, [format!("{}/api/node_modules", root)]);
+        assert_eq!(report.checks[2].offenders, [format!("{}/api/node_modules", root)]);
+        // The stale tree, then the older cache entry, which brings the cache to 600 bytes
+        let targets: Vec<&str> = report.plan.items.iter().map(|i| i.target_path.as_str()).collect();
+        assert_eq!(targets, [format!("{}/api/node_modules", root), format!("{}/.npm/_cacache/a", root)]);
+        assert_eq!(report.plan.total_estimated_bytes, 800);
+
+        let lenient = CompliancePolicy { max_cache_size: Some("2K".to_string()), ..Default::default() };
+        let report = evaluate(&lenient, &scan, None, "dev-1".to_string(), MachineRole::Laptop, Utc::now());
+        assert!(report.passed && report.plan.items.is_empty());
+    }
+}
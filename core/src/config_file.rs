@@ -0,0 +1,129 @@
+//! Default Flags
+//!
+//! `config.toml` in the config directory, or the file given with `--config`
+//! or `PACKAGEPURGE_CONFIG`, sets defaults for flags otherwise repeated on
+//! every run. A `~/.packagepurge/config.toml` moves to the config directory
+//! with the rest of the legacy directory.
+//!
+//! ```toml
+//! preserve_days = 60
+//! lru_max_packages = 2000
+//! lru_max_size_bytes = 20_000_000_000
+//! enable_symlinking = true
+//! exclude = ["~/src/mirrors", "**/fixtures"]
+//!
+//! [quarantine]
+//! retention_days = 14
+//! max_size_gb = 5
+//! ```
+//!
+//! Flags win over the file and the file over the machine role's policy.
+//! `exclude` globs are added to the scan rules' `skip`. The quarantine values
+//! apply until `cleanup-quarantine` saves settings of its own.
+//!
+//! A file that does not parse is ignored with a warning (`config validate`
+//! says why); one given explicitly must exist.
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size_gb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigFile {
+    /// `--preserve-days` of dry-run, optimize, purge and graph
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preserve_days: Option<i64>,
+    /// `--lru-max-packages` of optimize
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lru_max_packages: Option<usize>,
+    /// `--lru-max-size-bytes` of optimize
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lru_max_size_bytes: Option<u64>,
+    /// `--enable-symlinking` of optimize
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_symlinking: Option<bool>,
+    /// Globs of directories scans never enter, on top of the scan rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub quarantine: QuarantineDefaults,
+}
+
+static PATH: OnceLock<PathBuf> = OnceLock::new();
+static LOADED: OnceLock<ConfigFile> = OnceLock::new();
+
+/// Read defaults from `path` for the rest of the process (the `--config`
+/// flag); only the first call takes effect
+pub fn set_path(path: PathBuf) {
+    let _ = PATH.set(path);
+}
+
+/// The file given with `--config` or `PACKAGEPURGE_CONFIG`, if any
+fn explicit_path() -> Option<PathBuf> {
+    PATH.get().cloned()
+        .or_else(|| std::env::var_os("PACKAGEPURGE_CONFIG").filter(|v| !v.is_empty()).map(PathBuf::from))
+}
+
+/// The file defaults are read from
+pub fn path() -> PathBuf {
+    explicit_path().unwrap_or_else(|| crate::paths::config_dir().join("config.toml"))
+}
+
+pub fn parse(text: &str) -> anyhow::Result<ConfigFile> {
+    Ok(toml::from_str(text)?)
+}
+
+/// Defaults from the file; none when there is no file and none was given
+pub fn load() -> crate::Result<ConfigFile> {
+    let path = path();
+    let loaded = match fs::read_to_string(&path) {
+        Ok(text) => parse(&text).with_context(|| format!("Failed to parse {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && explicit_path().is_none() => Ok(ConfigFile::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    loaded.map_err(Error::lift(Error::Config))
+}
+
+/// Defaults in effect, loaded on first use
+pub fn get() -> &'static ConfigFile {
+    LOADED.get_or_init(|| load().unwrap_or_else(|e| {
+        eprintln!("Warning: ignoring default flags: {}", e);
+        ConfigFile::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = parse(r#"
+            preserve_days = 60
+            lru_max_size_bytes = 20_000_000_000
+            exclude = ["**/fixtures"]
+
+            [quarantine]
+            retention_days = 14
+        "#).unwrap();
+        assert_eq!(config.preserve_days, Some(60));
+        assert_eq!(config.lru_max_size_bytes, Some(20_000_000_000));
+        assert_eq!(config.enable_symlinking, None);
+        assert_eq!(config.exclude, ["**/fixtures"]);
+        assert_eq!(config.quarantine, QuarantineDefaults { retention_days: Some(14), max_size_gb: None });
+        assert!(parse("preserve_days = \"sixty\"").is_err());
+        assert_eq!(parse("").unwrap(), ConfigFile::default());
+    }
+}
@@ -0,0 +1,429 @@
+//! Settings Validation
+//!
+//! Settings live in one JSON file per feature in the config directory, and
+//! each feature falls back to its defaults when its file does not parse, so
+//! a typo in a hand-edited file goes unnoticed until a cleanup behaves
+//! differently than expected. `config validate` parses every file, and the
+//! default flags in `config.toml` (see `config_file`), and reports:
+//!
+//! - files that are not JSON or do not fit their settings (wrong types,
+//!   unknown enum values)
+//! - keys the settings do not know, which a load silently drops
+//! - globs that do not compile, lines of the global `ignore` file (see
+//!   `ignore_file`) among them, and values out of range
+//! - rules that contradict each other: a version retention rule an earlier
+//!   one always matches first, scan rules that keep the walker out of an
+//!   allowed quarantine root or a tagged directory, `node_modules` purged
+//!   whole for active projects
+//!
+//! `config show` prints the settings in effect for a machine role (`--role`
+//! picks another than this machine's): its policy, with `config.toml` and
+//! the saved files (or their defaults) on top.
+
+use globset::Glob;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::approval::ApprovalConfig;
+use crate::config_file::ConfigFile;
+use crate::digest::DigestConfig;
+use crate::display::DisplayConfig;
+use crate::hooks::HooksConfig;
+use crate::machine_role::{MachineRole, RolePolicy, RoleSource};
+use crate::overhead::OverheadConfig;
+use crate::privacy::PrivacyConfig;
+use crate::project_activity::ActivityConfig;
+use crate::project_tags::{check_tag, TagsConfig};
+use crate::safety::QuarantineConfig;
+use crate::scan_rules::{ScanRules, ScanRulesConfig};
+use crate::types::ActivityClass;
+use crate::version_retention::VersionRetentionConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The file, or part of it, is ignored or refused
+    Error,
+    /// Accepted, but probably not what was meant
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub file: String,
+    pub severity: Severity,
+    /// Where in the file, e.g. `rules[2].pattern`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    pub config_dir: String,
+    /// Settings files found and checked
+    pub files: Vec<String>,
+    pub findings: Vec<Finding>,
+    /// No errors (warnings allowed)
+    pub valid: bool,
+}
+
+/// Settings in effect for one machine role
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveConfig {
+    pub role: MachineRole,
+    pub role_source: RoleSource,
+    pub policy: RolePolicy,
+    /// Where `defaults` were read from
+    pub config_file: String,
+    /// Default flags from `config_file`, over `policy`
+    pub defaults: ConfigFile,
+    pub quarantine: QuarantineConfig,
+    pub scan_rules: ScanRulesConfig,
+    pub version_retention: VersionRetentionConfig,
+    pub project_activity: ActivityConfig,
+    pub tags: TagsConfig,
+    pub hooks: HooksConfig,
+    pub approval: ApprovalConfig,
+    pub digest: DigestConfig,
+    pub display: DisplayConfig,
+    pub privacy: PrivacyConfig,
+    pub overhead: OverheadConfig,
+}
+
+/// Settings in effect for the current machine role (`--role`, else this
+/// machine's)
+pub fn effective() -> EffectiveConfig {
+    let (role, role_source) = MachineRole::current();
+    EffectiveConfig {
+        role,
+        role_source,
+        policy: role.policy(),
+        config_file: crate::config_file::path().to_string_lossy().to_string(),
+        defaults: crate::config_file::get().clone(),
+        quarantine: crate::safety::load_config_for(role),
+        scan_rules: crate::scan_rules::load_config(),
+        version_retention: crate::version_retention::load_config(),
+        project_activity: crate::project_activity::load_config(),
+        tags: crate::project_tags::load_config(),
+        hooks: crate::hooks::load_config().unwrap_or_default(),
+        approval: crate::approval::load_config(),
+        digest: crate::digest::load_config(),
+        display: crate::display::load_config(),
+        privacy: crate::privacy::load_config(),
+        overhead: crate::overhead::load_config(),
+    }
+}
+
+/// Keys of `given` that do not survive a round trip through the settings
+/// type (`kept`), with their path
+fn unknown_keys(given: &Value, kept: &Value, at: &str, out: &mut Vec<String>) {
+    match (given, kept) {
+        (Value::Object(given), Value::Object(kept)) => {
+            for (key, value) in given {
+                let path = if at.is_empty() { key.clone() } else { format!("{}.{}", at, key) };
+                match kept.get(key) {
+                    Some(known) => unknown_keys(value, known, &path, out),
+                    // An explicit null for an unset optional value
+                    None if value.is_null() => {}
+                    None => out.push(path),
+                }
+            }
+        }
+        (Value::Array(given), Value::Array(kept)) => {
+            for (i, (value, known)) in given.iter().zip(kept).enumerate() {
+                unknown_keys(value, known, &format!("{}[{}]", at, i), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+struct Lint {
+    dir: PathBuf,
+    files: Vec<String>,
+    findings: Vec<Finding>,
+}
+
+impl Lint {
+    fn push(&mut self, file: &str, severity: Severity, key: Option<String>, message: String) {
+        self.findings.push(Finding { file: file.to_string(), severity, key, message });
+    }
+
+    fn error(&mut self, file: &str, key: impl Into<Option<String>>, message: String) {
+        self.push(file, Severity::Error, key.into(), message);
+    }
+
+    fn warning(&mut self, file: &str, key: impl Into<Option<String>>, message: String) {
+        self.push(file, Severity::Warning, key.into(), message);
+    }
+
+    /// Parse JSON `file` as `T`, reporting why it does not; `None` when
+    /// absent or broken
+    fn parse<T: DeserializeOwned + Serialize>(&mut self, file: &str) -> Option<T> {
+        let text = fs::read_to_string(self.dir.join(file)).ok()?;
+        self.check(file, serde_json::from_str(&text).map_err(|e| format!("not valid JSON ({})", e)))
+    }
+
+    /// `parse` for a TOML file at `path`, reported as `file`
+    fn parse_toml<T: DeserializeOwned + Serialize>(&mut self, file: &str, path: &Path) -> Option<T> {
+        let text = fs::read_to_string(path).ok()?;
+        self.check(file, toml::from_str(&text).map_err(|e| format!("not valid TOML ({})", e.message())))
+    }
+
+    fn check<T: DeserializeOwned + Serialize>(&mut self, file: &str, parsed: Result<Value, String>) -> Option<T> {
+        self.files.push(file.to_string());
+        let value = match parsed {
+            Ok(v) => v,
+            Err(e) => {
+                self.error(file, None, format!("{}; the defaults are used instead", e));
+                return None;
+            }
+        };
+        let config: T = match serde_json::from_value(value.clone()) {
+            Ok(c) => c,
+            Err(e) => {
+                self.error(file, None, format!("{}; the defaults are used instead", e));
+                return None;
+            }
+        };
+        let mut unknown = Vec::new();
+        if let Ok(kept) = serde_json::to_value(&config) {
+            unknown_keys(&value, &kept, "", &mut unknown);
+        }
+        for key in unknown {
+            self.warning(file, key, "unknown key, ignored".to_string());
+        }
+        Some(config)
+    }
+}
+
+/// Check the settings files in `dir`, and the default flags in `config_file`
+pub fn validate_dir(dir: &Path, config_file: &Path) -> ValidationReport {
+    let mut lint = Lint { dir: dir.to_path_buf(), files: Vec::new(), findings: Vec::new() };
+
+    let name = config_file.file_name().map_or_else(|| "config.toml".into(), |n| n.to_string_lossy().to_string());
+    let defaults = lint.parse_toml::<ConfigFile>(&name, config_file);
+    if let Some(d) = &defaults {
+        for (key, value) in [("preserve_days", d.preserve_days), ("quarantine.retention_days", d.quarantine.retention_days)] {
+            if value.is_some_and(|v| v < 0) {
+                lint.error(&name, key.to_string(), "must not be negative".to_string());
+            }
+        }
+        if d.lru_max_packages == Some(0) {
+            lint.error(&name, "lru_max_packages".to_string(), "must be at least 1".to_string());
+        }
+        for (i, glob) in d.exclude.iter().enumerate() {
+            if let Err(e) = Glob::new(glob) {
+                lint.error(&name, format!("exclude[{}]", i), format!("invalid glob: {}", e));
+            }
+        }
+    }
+
+    if let Some(role) = lint.parse::<Value>("role.json") {
+        let name = role.get("role").and_then(Value::as_str).unwrap_or_default();
+        if let Err(e) = name.parse::<MachineRole>() {
+            lint.error("role.json", "role".to_string(), e);
+        }
+    }
+
+    let quarantine = lint.parse::<QuarantineConfig>("quarantine.json");
+    if let Some(q) = &quarantine {
+        if q.retention_days < 0 {
+            lint.error("quarantine.json", "retention_days".to_string(), "must be 0 (keep forever) or more".to_string());
+        }
+        for (i, root) in q.allowed_roots.iter().enumerate() {
+            if !root.is_absolute() {
+                lint.warning("quarantine.json", format!("allowed_roots[{}]", i), format!("{:?} is relative to wherever a command runs", root));
+            } else if !root.is_dir() {
+                lint.warning("quarantine.json", format!("allowed_roots[{}]", i), format!("{:?} does not exist", root));
+            }
+        }
+    }
+
+    let tags = lint.parse::<TagsConfig>("tags.json");
+    if let Some(t) = &tags {
+        for (project, names) in &t.projects {
+            for tag in names {
+                if let Err(e) = check_tag(tag) {
+                    lint.error("tags.json", format!("projects.{}", project), e.to_string());
+                }
+            }
+        }
+        for (i, rule) in t.rules.iter().enumerate() {
+            if let Err(e) = check_tag(&rule.tag) {
+                lint.error("tags.json", format!("rules[{}].tag", i), e.to_string());
+            }
+            if rule.under.is_some() == rule.remote.is_some() {
+                lint.error("tags.json", format!("rules[{}]", i), "needs exactly one of `under` and `remote`".to_string());
+            }
+        }
+    }
+
+    let saved_rules = lint.parse::<ScanRulesConfig>("scan_rules.json");
+    let mut globs_ok = true;
+    if let Some(rules) = &saved_rules {
+        for (list, globs) in [("skip", &rules.skip), ("keep", &rules.keep), ("wide_skip", &rules.wide_skip)] {
+            for (i, glob) in globs.iter().enumerate() {
+                if let Err(e) = Glob::new(glob) {
+                    lint.error("scan_rules.json", format!("{}[{}]", list, i), format!("invalid glob: {}", e));
+                    globs_ok = false;
+                }
+            }
+        }
+        for glob in rules.keep.iter().filter(|g| rules.skip.contains(g)) {
+            lint.warning("scan_rules.json", "keep".to_string(), format!("{:?} is both skipped and kept, so it is walked", glob));
+        }
+    }
+    // Directories other settings point cleanups at, which the walker must
+    // reach past the scan rules (the defaults when none are saved) and
+    // config.toml's `exclude`
+    let mut reached: Vec<(String, PathBuf)> = Vec::new();
+    if let Some(q) = &quarantine {
+        reached.extend(q.allowed_roots.iter().map(|r| ("allowed quarantine root".to_string(), r.clone())));
+    }
+    if let Some(t) = &tags {
+        reached.extend(t.rules.iter().filter_map(|r| Some((format!("directory tagged {:?}", r.tag), r.under.clone()?))));
+    }
+    let mut rules = saved_rules.unwrap_or_default();
+    rules.skip.extend(defaults.iter().flat_map(|d| d.exclude.iter().cloned()));
+    if let (true, Ok(compiled)) = (globs_ok, ScanRules::compile(&rules)) {
+        for (what, dir) in reached {
+            if let Some(pruned) = dir.ancestors().find(|a| compiled.prunes(a)) {
+                lint.warning("scan_rules.json", "skip".to_string(), format!(
+                    "scans never enter {:?}, so nothing below the {} {:?} is planned", pruned, what, dir
+                ));
+            }
+        }
+    }
+
+    let ignore = dir.join("ignore");
+    if ignore.is_file() {
+        lint.files.push("ignore".to_string());
+        for (line, e) in crate::ignore_file::check(&ignore) {
+            lint.error("ignore", format!("line {}", line), format!("{}; the line is skipped", e));
+        }
+    }
+
+    if let Some(retention) = lint.parse::<VersionRetentionConfig>("version_retention.json") {
+        if retention.keep == Some(0) {
+            lint.error("version_retention.json", "keep".to_string(), "must keep at least one version".to_string());
+        }
+        let mut earlier: Vec<(usize, &str, globset::GlobMatcher)> = Vec::new();
+        for (i, rule) in retention.rules.iter().enumerate() {
+            if rule.keep == 0 {
+                lint.error("version_retention.json", format!("rules[{}].keep", i), "must keep at least one version".to_string());
+            }
+            match Glob::new(&rule.pattern) {
+                Err(e) => lint.error("version_retention.json", format!("rules[{}].pattern", i), format!("invalid glob: {}", e)),
+                Ok(glob) => {
+                    // The first matching rule wins, so a rule whose pattern an
+                    // earlier one matches as a name can only apply to less
+                    if let Some((j, pattern, _)) = earlier.iter().find(|(_, p, m)| *p == rule.pattern || m.is_match(&rule.pattern)) {
+                        lint.warning("version_retention.json", format!("rules[{}]", i), format!(
+                            "{:?} is shadowed by {:?} (rules[{}]), which matches first", rule.pattern, pattern, j
+                        ));
+                    }
+                    earlier.push((i, &rule.pattern, glob.compile_matcher()));
+                }
+            }
+        }
+    }
+
+    if let Some(activity) = lint.parse::<ActivityConfig>("project_activity.json") {
+        if activity.active_days <= 0 || activity.dead_days <= activity.active_days {
+            lint.error("project_activity.json", None, format!(
+                "dead_days ({}) must exceed active_days ({}), which must be positive", activity.dead_days, activity.active_days
+            ));
+        }
+        if activity.purge_node_modules.contains(&ActivityClass::Active) {
+            lint.warning("project_activity.json", "purge_node_modules".to_string(), "purges the node_modules of projects in active use".to_string());
+        }
+    }
+
+    if let Some(hooks) = lint.parse::<HooksConfig>("hooks.json") {
+        for (i, hook) in hooks.hooks.iter().enumerate() {
+            if hook.command.is_none() && hook.webhook.is_none() {
+                lint.warning("hooks.json", format!("hooks[{}]", i), "has neither `command` nor `webhook`, so it does nothing".to_string());
+            }
+        }
+    }
+
+    if let Some(overhead) = lint.parse::<OverheadConfig>("overhead.json") {
+        if overhead.warn_percent <= 0.0 {
+            lint.error("overhead.json", "warn_percent".to_string(), "must be positive".to_string());
+        }
+    }
+    if let Some(approval) = lint.parse::<ApprovalConfig>("approval.json") {
+        if approval.token_ttl_hours <= 0 {
+            lint.error("approval.json", "token_ttl_hours".to_string(), "must be positive".to_string());
+        }
+    }
+    if let Some(digest) = lint.parse::<DigestConfig>("digest.json") {
+        if digest.period_days <= 0 {
+            lint.error("digest.json", "period_days".to_string(), "must be positive".to_string());
+        }
+    }
+    lint.parse::<DisplayConfig>("display.json");
+    lint.parse::<PrivacyConfig>("privacy.json");
+
+    let valid = lint.findings.iter().all(|f| f.severity != Severity::Error);
+    ValidationReport {
+        config_dir: dir.to_string_lossy().to_string(),
+        files: lint.files,
+        findings: lint.findings,
+        valid,
+    }
+}
+
+/// Check the settings files in the config directory and config.toml
+pub fn validate() -> ValidationReport {
+    validate_dir(&crate::paths::config_dir(), &crate::config_file::path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_validate_dir() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        let clients = dir.join("clients");
+        fs::create_dir_all(clients.join("mirror")).unwrap();
+        fs::write(dir.join("version_retention.json"), r#"{
+            "keep": 3,
+            "rules": [{ "pattern": "@types/*", "keep": 1 }, { "pattern": "@types/node", "keep": 2 }, { "pattern": "a[", "keep": 1 }]
+        }"#).unwrap();
+        fs::write(dir.join("quarantine.json"), format!(
+            r#"{{ "max_size_gb": 10, "retention_days": 30, "max_entries": 200, "retnetion": 7, "allowed_roots": [{:?}] }}"#,
+            clients.join("mirror/work")
+        )).unwrap();
+        fs::write(dir.join("project_activity.json"), r#"{ "active_days": 30, "dead_days": 365, "purge_node_modules": ["active"] }"#).unwrap();
+        fs::write(dir.join("privacy.json"), "{ not json").unwrap();
+
+        let report = validate_dir(dir, &dir.join("config.toml"));
+        assert!(!report.valid);
+        assert_eq!(report.files.len(), 4);
+        let keys: Vec<(&str, Severity, Option<&str>)> = report.findings.iter()
+            .map(|f| (f.file.as_str(), f.severity, f.key.as_deref()))
+            .collect();
+        assert!(keys.contains(&("quarantine.json", Severity::Warning, Some("retnetion"))));
+        assert!(keys.contains(&("version_retention.json", Severity::Warning, Some("rules[1]"))));
+        assert!(keys.contains(&("version_retention.json", Severity::Error, Some("rules[2].pattern"))));
+        assert!(keys.contains(&("project_activity.json", Severity::Warning, Some("purge_node_modules"))));
+        assert!(keys.contains(&("privacy.json", Severity::Error, None)));
+        // The default scan rules reach the allowed root
+        assert!(!keys.iter().any(|(file, ..)| *file == "scan_rules.json"));
+
+        fs::write(dir.join("config.toml"), "exclude = [\"**/mirror\"]\npreserve_dayz = 3\n").unwrap();
+        let report = validate_dir(dir, &dir.join("config.toml"));
+        assert!(report.findings.iter().any(|f| f.file == "config.toml" && f.key.as_deref() == Some("preserve_dayz")));
+        let pruned = report.findings.iter().find(|f| f.file == "scan_rules.json").unwrap();
+        assert!(pruned.message.contains("allowed quarantine root"));
+    }
+}
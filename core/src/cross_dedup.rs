@@ -0,0 +1,292 @@
+//! Machine-wide Duplicates
+//!
+//! Every tool keeps its own copy of what it downloads: the same package
+//! version sits in the npm cache, the Yarn cache and the pnpm store, the
+//! same crate source is extracted once per Cargo registry index
+//! (`github.com-…` and `index.crates.io-…` after the switch to the sparse
+//! protocol). `find_overlap` groups the scanned packages living in such
+//! stores by name@version, hashes each copy and reports those whose content
+//! is identical across two or more stores, with the overlap between every
+//! pair of stores.
+//!
+//! `consolidate` links the copies through the global store the way
+//! `symlink --mode hardlink` does: every copy stays a real directory where
+//! its tool expects it, but its files become hard links to one store entry.
+//! Store content is written once and never modified, so sharing inodes is
+//! safe there; project `node_modules` and environments are left to
+//! `symlink` and are not considered. A copy is blocked when it lies on
+//! another filesystem than the store, across the WSL boundary or is in use.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::cargo_caches::is_registry_dir;
+use crate::go_caches::is_mod_cache;
+use crate::hash_queue::{hash_dir, DirProgress, RateLimiter};
+use crate::progress::OperationContext;
+use crate::provider_caches::is_provider_cache_dir;
+use crate::python_caches::is_python_cache;
+use crate::scanner::is_cache_dir;
+use crate::symlink::{is_cross_device, is_in_use, SemanticDeduplication};
+use crate::types::{DedupBlocker, PackageRecord, ScanOutput};
+
+/// A package cache or store, by the tool writing it and its root
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Store {
+    pub tool: String,
+    pub root: String,
+}
+
+/// One copy of a duplicated package
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredCopy {
+    pub path: String,
+    pub store: Store,
+    /// Its files are already hard links shared with another copy
+    pub linked: bool,
+    pub blockers: Vec<DedupBlocker>,
+}
+
+/// A package version whose content is identical in several stores
+#[derive(Debug, Clone, Serialize)]
+pub struct CrossDuplicate {
+    pub name: String,
+    pub version: String,
+    /// Content hash shared by every copy
+    pub digest: String,
+    pub size_bytes: u64,
+    pub copies: Vec<StoredCopy>,
+    /// Bytes freed by linking every unblocked copy
+    pub reclaimable_bytes: u64,
+}
+
+/// Identical content two stores hold
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreOverlap {
+    pub stores: [Store; 2],
+    pub packages: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OverlapReport {
+    pub duplicates: Vec<CrossDuplicate>,
+    pub overlap: Vec<StoreOverlap>,
+    pub total_reclaimable_bytes: u64,
+}
+
+/// Copies of one name@version and the stores holding them
+type Copies<'a> = Vec<(&'a PackageRecord, Store)>;
+
+/// The store holding `path`, if it lies in a package cache or store rather
+/// than a project
+pub fn store_of(path: &Path) -> Option<Store> {
+    let store = |tool: &str, root: &Path| Some(Store { tool: tool.to_string(), root: root.to_string_lossy().to_string() });
+    for dir in path.ancestors().skip(1) {
+        if is_cache_dir(dir) {
+            let p = dir.to_string_lossy().to_lowercase();
+            let tool = if p.contains("pnpm") { "pnpm" } else if p.contains("yarn") { "yarn" } else { "npm" };
+            return store(tool, dir);
+        }
+        // Crates are extracted per index: `registry/src/<index>/<crate>`
+        if dir.parent().is_some_and(|src| src.file_name().is_some_and(|n| n == "src") && src.parent().is_some_and(is_registry_dir)) {
+            return store("cargo", dir);
+        }
+        if is_python_cache(dir) {
+            return store("python", dir);
+        }
+        if is_mod_cache(dir) {
+            return store("go", dir);
+        }
+        if is_provider_cache_dir(dir) {
+            let tool = if dir.file_name().is_some_and(|n| n == "releases") { "serverless" } else { "terraform" };
+            return store(tool, dir);
+        }
+        if dir.file_name().is_some_and(|n| n == "node_modules") {
+            return None;
+        }
+    }
+    None
+}
+
+/// Whether every file of `dir` has another link (Unix only)
+#[cfg(unix)]
+fn is_linked(dir: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let mut files = walkdir::WalkDir::new(dir).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()).peekable();
+    files.peek().is_some() && files.all(|e| e.metadata().is_ok_and(|m| m.nlink() > 1))
+}
+
+#[cfg(not(unix))]
+fn is_linked(_dir: &Path) -> bool {
+    false
+}
+
+/// Content hash and size of a copy, independent of where it lies
+fn digest(dir: &Path) -> Option<(String, u64)> {
+    let mut progress = DirProgress::default();
+    let digest = hash_dir(dir, &mut progress, &mut RateLimiter::unlimited(), &mut |_| Ok(())).ok()?;
+    Some((digest, progress.bytes))
+}
+
+/// Group the stored packages of `scan` by identical content and report
+/// those held by more than one store. Only name@versions found in two
+/// stores are hashed.
+pub fn find_overlap(scan: &ScanOutput, store_path: &Path, open_files: &HashSet<PathBuf>) -> OverlapReport {
+    let mut by_version: BTreeMap<(&str, &str), Copies> = BTreeMap::new();
+    for pkg in &scan.packages {
+        if let Some(store) = store_of(Path::new(&pkg.path)) {
+            by_version.entry((pkg.name.as_str(), pkg.version.as_str())).or_default().push((pkg, store));
+        }
+    }
+
+    let mut duplicates = Vec::new();
+    for ((name, version), copies) in by_version {
+        if copies.iter().map(|(_, s)| s).collect::<HashSet<_>>().len() < 2 {
+            continue;
+        }
+        let mut by_digest: HashMap<String, (u64, Copies)> = HashMap::new();
+        for (pkg, store) in copies {
+            if let Some((digest, bytes)) = digest(Path::new(&pkg.path)) {
+                by_digest.entry(digest).or_insert_with(|| (bytes, Vec::new())).1.push((pkg, store));
+            }
+        }
+        for (digest, (size_bytes, copies)) in by_digest {
+            if copies.iter().map(|(_, s)| s).collect::<HashSet<_>>().len() < 2 {
+                continue;
+            }
+            let copies: Vec<StoredCopy> = copies.into_iter().map(|(pkg, store)| {
+                let path = Path::new(&pkg.path);
+                let mut blockers = Vec::new();
+                if is_cross_device(path, store_path) {
+                    blockers.push(DedupBlocker::CrossDevice);
+                }
+                if crate::wsl::crosses_boundary(path) || crate::wsl::crosses_boundary(store_path) {
+                    blockers.push(DedupBlocker::WslBoundary);
+                }
+                if is_in_use(path, open_files) {
+                    blockers.push(DedupBlocker::InUse);
+                }
+                StoredCopy { path: pkg.path.clone(), store, linked: is_linked(path), blockers }
+            })
+            .collect();
+            // One copy seeds the store entry unless a linked one already did
+            let candidates = copies.iter().filter(|c| c.blockers.is_empty() && !c.linked).count();
+            let seeded = copies.iter().any(|c| c.linked);
+            let reclaimable_bytes = size_bytes * candidates.saturating_sub(usize::from(!seeded)) as u64;
+            duplicates.push(CrossDuplicate {
+                name: name.to_string(),
+                version: version.to_string(),
+                digest,
+                size_bytes,
+                copies,
+                reclaimable_bytes,
+            });
+        }
+    }
+    duplicates.sort_by_key(|d| std::cmp::Reverse(d.reclaimable_bytes));
+
+    let mut pairs: BTreeMap<(Store, Store), (usize, u64)> = BTreeMap::new();
+    for dup in &duplicates {
+        let stores: Vec<&Store> = dup.copies.iter().map(|c| &c.store).collect::<BTreeSet<_>>().into_iter().collect();
+        for (i, a) in stores.iter().enumerate() {
+            for b in &stores[i + 1..] {
+                let entry = pairs.entry(((*a).clone(), (*b).clone())).or_default();
+                entry.0 += 1;
+                entry.1 += dup.size_bytes;
+            }
+        }
+    }
+    let mut overlap: Vec<StoreOverlap> = pairs.into_iter()
+        .map(|((a, b), (packages, bytes))| StoreOverlap { stores: [a, b], packages, bytes })
+        .collect();
+    overlap.sort_by_key(|o| std::cmp::Reverse(o.bytes));
+
+    let total_reclaimable_bytes = duplicates.iter().map(|d| d.reclaimable_bytes).sum();
+    OverlapReport { duplicates, overlap, total_reclaimable_bytes }
+}
+
+/// Outcome of `consolidate`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Consolidation {
+    pub copies_linked: usize,
+    pub files_linked: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Hard link every unblocked copy of each duplicate to its global store entry
+pub fn consolidate(report: &OverlapReport, ctx: &OperationContext) -> crate::Result<Consolidation> {
+    let dedup = SemanticDeduplication::new()?;
+    let mut out = Consolidation::default();
+    for dup in &report.duplicates {
+        for copy in dup.copies.iter().filter(|c| c.blockers.is_empty() && !c.linked) {
+            ctx.check()?;
+            match dedup.hardlink_package(Path::new(&copy.path), &dup.name, &dup.version) {
+                Ok(files) => {
+                    out.copies_linked += 1;
+                    out.files_linked += files;
+                }
+                Err(e) => out.failed.push((copy.path.clone(), e.to_string())),
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn package(dir: &Path, name: &str, version: &str, content: &str) -> PackageRecord {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("lib.rs"), content).unwrap();
+        PackageRecord {
+            name: name.to_string(),
+            file_count: 1,
+            inode_count: 2,
+            ..PackageRecord::for_test(dir, version, content.len() as u64)
+        }
+    }
+
+    #[test]
+    fn test_identical_content_across_stores() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let registry = root.join(".cargo/registry");
+        fs::create_dir_all(registry.join("index")).unwrap();
+        let scan = ScanOutput {
+            packages: vec![
+                package(&registry.join("src/github.com-1ecc6299db9ec823/serde-1.0.0"), "serde", "1.0.0", "serde"),
+                package(&registry.join("src/index.crates.io-6f17d22bba15001f/serde-1.0.0"), "serde", "1.0.0", "serde"),
+                package(&root.join(".npm/lodash"), "lodash", "4.17.21", "lodash"),
+                package(&root.join(".yarn/cache/lodash"), "lodash", "4.17.21", "lodash"),
+                package(&root.join("pnpm/store/lodash"), "lodash", "4.17.21", "patched"),
+                package(&root.join("app/node_modules/lodash"), "lodash", "4.17.21", "lodash"),
+            ],
+            projects: Vec::new(),
+            edges: Vec::new(),
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        assert_eq!(store_of(Path::new(&scan.packages[0].path)).unwrap().tool, "cargo");
+        assert_eq!(store_of(Path::new(&scan.packages[5].path)), None);
+
+        let report = find_overlap(&scan, root, &HashSet::new());
+        let found: Vec<(&str, Vec<&str>)> = report.duplicates.iter()
+            .map(|d| (d.name.as_str(), d.copies.iter().map(|c| c.store.tool.as_str()).collect()))
+            .collect();
+        // The pnpm copy differs and project installs are not stores
+        assert_eq!(found, [("lodash", vec!["npm", "yarn"]), ("serde", vec!["cargo", "cargo"])]);
+        assert_eq!(report.duplicates[0].reclaimable_bytes, 6);
+        assert_eq!(report.total_reclaimable_bytes, 11);
+        assert_eq!(report.overlap.len(), 2);
+
+        let open: HashSet<PathBuf> = [root.join(".npm/lodash/lib.rs")].into_iter().collect();
+        let busy = find_overlap(&scan, root, &open);
+        assert_eq!(busy.total_reclaimable_bytes, 5);
+    }
+}
@@ -0,0 +1,342 @@
+//! Activity Digest
+//!
+//! A periodic summary of what PackagePurge saw and did: how much the scanned
+//! package trees grew, what applied plans reclaimed, plans awaiting approval
+//! and quarantine entries about to expire. `digest send` renders it as text
+//! or HTML and delivers it to every destination configured in `digest.json`:
+//! a file, `sendmail` or a webhook (a JSON POST carrying both the digest and
+//! its text).
+//!
+//! There is no resident process to send it; run `digest send --if-due` from
+//! whatever runs PackagePurge unattended (cron, a systemd timer, a scheduled
+//! task) and it only sends once `period_days` have passed since the last one.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::approval::PendingPlan;
+use crate::display::{DisplayConfig, Message};
+use crate::error::Error;
+use crate::feature_store::FeatureStore;
+use crate::types::QuarantineRecord;
+
+/// Timeout of the webhook POST
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestConfig {
+    /// Days a digest covers, and between two digests sent with `--if-due`
+    pub period_days: i64,
+    /// Quarantine entries expiring within this many days are listed
+    pub expiry_window_days: i64,
+    /// Render HTML instead of plain text for the file and sendmail
+    #[serde(default)]
+    pub html: bool,
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+    #[serde(default)]
+    pub sendmail_to: Option<String>,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            period_days: 7,
+            expiry_window_days: 7,
+            html: false,
+            output: None,
+            sendmail_to: None,
+            webhook_url: None,
+        }
+    }
+}
+
+/// A quarantine entry that is about to be removed for good
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiringEntry {
+    pub id: String,
+    pub original_path: String,
+    pub size_bytes: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    /// Change in package bytes over the period; None without two scans of the same roots
+    pub growth_bytes: Option<i64>,
+    pub applied_plans: usize,
+    pub reclaimed_bytes: u64,
+    pub pending_approvals: Vec<PendingPlan>,
+    pub expiring: Vec<ExpiringEntry>,
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("digest.json")
+}
+
+fn state_path() -> PathBuf {
+    crate::paths::state_dir().join("digest-state.json")
+}
+
+pub fn load_config() -> DigestConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &DigestConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &DigestConfig) -> Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save digest config to {:?}", path))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DigestState {
+    last_sent: Option<DateTime<Utc>>,
+}
+
+/// When the last digest was sent
+pub fn last_sent() -> Option<DateTime<Utc>> {
+    fs::read_to_string(state_path()).ok()
+        .and_then(|text| serde_json::from_str::<DigestState>(&text).ok())
+        .and_then(|state| state.last_sent)
+}
+
+/// Whether a digest is due at `now`
+pub fn is_due(config: &DigestConfig, last_sent: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    last_sent.is_none_or(|sent| now - sent >= Duration::days(config.period_days))
+}
+
+/// Summarize the `config.period_days` before `now`. Without a feature store
+/// growth and reclaimed space are left empty.
+pub fn build(
+    config: &DigestConfig,
+    db: Option<&FeatureStore>,
+    pending_approvals: Vec<PendingPlan>,
+    quarantine: &[QuarantineRecord],
+    now: DateTime<Utc>,
+) -> Digest {
+    let period_start = now - Duration::days(config.period_days);
+    let growth_bytes = db.and_then(|db| db.footprint_growth(period_start).ok().flatten());
+    let (applied_plans, reclaimed_bytes) = db.and_then(|db| db.reclaimed_since(period_start).ok()).unwrap_or_default();
+    let horizon = now + Duration::days(config.expiry_window_days);
+    let mut expiring: Vec<ExpiringEntry> = quarantine.iter()
+        .filter_map(|r| {
+            let expires_at = r.expires_at.filter(|at| *at <= horizon)?;
+            Some(ExpiringEntry {
+                id: r.id.clone(),
+                original_path: r.original_path.clone(),
+                size_bytes: r.size_bytes,
+                expires_at,
+            })
+        })
+        .collect();
+    expiring.sort_by_key(|e| e.expires_at);
+    Digest {
+        period_start,
+        period_end: now,
+        growth_bytes,
+        applied_plans,
+        reclaimed_bytes,
+        pending_approvals,
+        expiring,
+    }
+}
+
+fn growth_text(growth: Option<i64>, display: &DisplayConfig) -> String {
+    match growth {
+        Some(g) if g < 0 => format!("-{}", display.size(g.unsigned_abs())),
+        Some(g) => format!("+{}", display.size(g as u64)),
+        None => display.text(Message::GrowthUnknown).to_string(),
+    }
+}
+
+/// Sections as (heading, lines), shared by both renderings
+fn sections(digest: &Digest, display: &DisplayConfig) -> Vec<(String, Vec<String>)> {
+    let now = digest.period_end;
+    vec![
+        (display.text(Message::Overview).to_string(), vec![
+            format!("{}: {}", display.text(Message::PackageGrowth), growth_text(digest.growth_bytes, display)),
+            display.text(Message::Reclaimed)
+                .replace("{size}", &display.size(digest.reclaimed_bytes))
+                .replace("{count}", &digest.applied_plans.to_string()),
+        ]),
+        (format!("{} ({})", display.text(Message::AwaitingApproval), digest.pending_approvals.len()), digest.pending_approvals.iter()
+            .map(|p| display.text(Message::PendingPlan)
+                .replace("{items}", &p.items.to_string())
+                .replace("{size}", &display.size(p.estimated_bytes))
+                .replace("{who}", &p.requested_by)
+                .replace("{when}", &display.date(p.requested_at, now)))
+            .collect()),
+        (format!("{} ({})", display.text(Message::ExpiringSoon), digest.expiring.len()), digest.expiring.iter()
+            .map(|e| display.text(Message::ExpiringEntry)
+                .replace("{path}", &e.original_path)
+                .replace("{size}", &display.size(e.size_bytes))
+                .replace("{when}", &display.date(e.expires_at, now))
+                .replace("{id}", &e.id))
+            .collect()),
+    ]
+}
+
+fn title(digest: &Digest, display: &DisplayConfig) -> String {
+    format!("{} {} – {}", display.text(Message::DigestTitle), display.day(digest.period_start), display.day(digest.period_end))
+}
+
+pub fn render_text(digest: &Digest, display: &DisplayConfig) -> String {
+    let mut out = format!("{}\n", title(digest, display));
+    for (heading, lines) in sections(digest, display) {
+        out.push_str(&format!("\n{}\n", heading));
+        if lines.is_empty() {
+            out.push_str(&format!("  ({})\n", display.text(Message::NoneListed)));
+        }
+        for line in lines {
+            out.push_str(&format!("  - {}\n", line));
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+pub fn render_html(digest: &Digest, display: &DisplayConfig) -> String {
+    let mut out = format!("<!DOCTYPE html>\n<html lang=\"{1}\"><head><meta charset=\"utf-8\"><title>{0}</title></head><body>\n<h1>{0}</h1>\n",
+        escape_html(&title(digest, display)), escape_html(&display.locale));
+    for (heading, lines) in sections(digest, display) {
+        out.push_str(&format!("<h2>{}</h2>\n", escape_html(&heading)));
+        if lines.is_empty() {
+            out.push_str(&format!("<p>({})</p>\n", escape_html(display.text(Message::NoneListed))));
+            continue;
+        }
+        out.push_str("<ul>\n");
+        for line in lines {
+            out.push_str(&format!("<li>{}</li>\n", escape_html(&line)));
+        }
+        out.push_str("</ul>\n");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+/// Deliver `digest` to every configured destination and remember when.
+/// Returns the destinations it went to; fails if none is configured or any
+/// delivery fails.
+pub fn send(digest: &Digest, config: &DigestConfig, display: &DisplayConfig) -> crate::Result<Vec<String>> {
+    send_impl(digest, config, display).map_err(Error::lift(Error::Delivery))
+}
+
+fn send_impl(digest: &Digest, config: &DigestConfig, display: &DisplayConfig) -> Result<Vec<String>> {
+    let body = if config.html { render_html(digest, display) } else { render_text(digest, display) };
+    let mut delivered = Vec::new();
+    if let Some(path) = &config.output {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &body).with_context(|| format!("Failed to write digest to {:?}", path))?;
+        delivered.push(format!("file:{}", path.display()));
+    }
+    if let Some(to) = &config.sendmail_to {
+        sendmail(to, &title(digest, display), &body, config.html)?;
+        delivered.push(format!("sendmail:{}", to));
+    }
+    if let Some(url) = &config.webhook_url {
+        let payload = serde_json::json!({ "text": render_text(digest, display), "digest": digest });
+        ureq::post(url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .set("Content-Type", "application/json")
+            .send_bytes(&serde_json::to_vec(&payload)?)
+            .map_err(|e| anyhow::anyhow!("POST {} failed: {}", url, e))?;
+        delivered.push(format!("webhook:{}", url));
+    }
+    if delivered.is_empty() {
+        anyhow::bail!("No digest destination configured; set an output file, sendmail address or webhook");
+    }
+    let path = state_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string(&DigestState { last_sent: Some(digest.period_end) })?)
+        .with_context(|| format!("Failed to record digest delivery in {:?}", path))?;
+    Ok(delivered)
+}
+
+fn sendmail(to: &str, subject: &str, body: &str, html: bool) -> Result<()> {
+    let content_type = if html { "text/html" } else { "text/plain" };
+    let message = format!(
+        "To: {}\nSubject: {}\nMIME-Version: 1.0\nContent-Type: {}; charset=utf-8\n\n{}",
+        to, subject, content_type, body
+    );
+    let mut child = Command::new("sendmail")
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run sendmail")?;
+    child.stdin.take().context("sendmail has no stdin")?.write_all(message.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("sendmail exited with {}", status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_digest_contents_and_schedule() {
+        let temp = tempdir().unwrap();
+        let db = FeatureStore::open(&temp.path().join("features.db")).unwrap();
+        let now = Utc::now();
+        let record = |id: &str, days: i64| QuarantineRecord {
+            created_at: now,
+            expires_at: Some(now + Duration::days(days)),
+            ..QuarantineRecord::for_test(id, format!("/work/app/node_modules/{}", id), 2048)
+        };
+        let pending = vec![PendingPlan {
+            plan_hash: "abc".into(),
+            items: 3,
+            estimated_bytes: 5 << 30,
+            requested_by: "bob".into(),
+            requested_at: now,
+        }];
+        let config = DigestConfig::default();
+        let digest = build(&config, Some(&db), pending, &[record("soon", 2), record("later", 30)], now);
+        assert_eq!(digest.growth_bytes, None);
+        assert_eq!(digest.expiring.len(), 1);
+        assert_eq!(digest.expiring[0].id, "soon");
+
+        let display = DisplayConfig::default();
+        let text = render_text(&digest, &display);
+        assert!(text.contains("5.0 GiB — requested by bob"));
+        assert!(text.contains("node_modules/soon (2.0 KiB)"));
+        let html = render_html(&digest, &display);
+        assert!(html.contains("<h2>Awaiting approval (1)</h2>"));
+        let spanish = DisplayConfig { locale: "es".into(), dates: crate::display::DateStyle::Relative, ..display };
+        let text = render_text(&digest, &spanish);
+        assert!(text.contains("Pendiente de aprobación (1)"));
+        assert!(text.contains("caduca dentro de 2 días"));
+
+        assert!(is_due(&config, None, now));
+        assert!(!is_due(&config, Some(now - Duration::days(3)), now));
+        assert!(is_due(&config, Some(now - Duration::days(7)), now));
+    }
+}
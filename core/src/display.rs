@@ -0,0 +1,310 @@
+//! Human Output Formatting
+//!
+//! How sizes, dates and report strings appear in human-readable output (the
+//! text and HTML digest). Settings live in `display.json` in the config
+//! directory and can be overridden per run through `PACKAGEPURGE_SIZE_UNITS`
+//! (`binary`, `si`), `PACKAGEPURGE_DATES` (`absolute`, `relative`),
+//! `PACKAGEPURGE_TIME_ZONE` (`local`, `utc`) and `PACKAGEPURGE_LOCALE`, which
+//! the CLI wrapper sets from its own options so both halves agree.
+//!
+//! Report strings are translated for the locales in `CATALOG`; any other
+//! locale falls back to English. JSON output is never affected.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::Error;
+
+/// Which multiples sizes are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeUnits {
+    /// 1024-based, shown as KiB, MiB, GiB
+    #[default]
+    Binary,
+    /// 1000-based, shown as kB, MB, GB
+    Si,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateStyle {
+    /// A calendar date and time
+    #[default]
+    Absolute,
+    /// Distance from now: `3 days ago`, `in 2 days`
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeZoneChoice {
+    #[default]
+    Local,
+    Utc,
+}
+
+macro_rules! parse_choice {
+    ($ty:ty, $($text:literal => $value:expr),+) => {
+        impl std::str::FromStr for $ty {
+            type Err = String;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                match s {
+                    $($text => Ok($value),)+
+                    other => Err(format!("unknown value `{}` (expected {})", other, [$($text),+].join(" or "))),
+                }
+            }
+        }
+    };
+}
+
+parse_choice!(SizeUnits, "binary" => SizeUnits::Binary, "si" => SizeUnits::Si);
+parse_choice!(DateStyle, "absolute" => DateStyle::Absolute, "relative" => DateStyle::Relative);
+parse_choice!(TimeZoneChoice, "local" => TimeZoneChoice::Local, "utc" => TimeZoneChoice::Utc);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayConfig {
+    #[serde(default)]
+    pub size_units: SizeUnits,
+    #[serde(default)]
+    pub dates: DateStyle,
+    #[serde(default)]
+    pub time_zone: TimeZoneChoice,
+    /// Language of report strings, such as `en`, `de` or `es-MX`
+    #[serde(default = "default_locale")]
+    pub locale: String,
+}
+
+fn default_locale() -> String {
+    "en".to_string()
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            size_units: SizeUnits::default(),
+            dates: DateStyle::default(),
+            time_zone: TimeZoneChoice::default(),
+            locale: default_locale(),
+        }
+    }
+}
+
+/// Report strings with a translation in `CATALOG`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    DigestTitle,
+    Overview,
+    PackageGrowth,
+    GrowthUnknown,
+    /// `{size}`, `{count}`
+    Reclaimed,
+    AwaitingApproval,
+    /// `{items}`, `{size}`, `{who}`, `{when}`
+    PendingPlan,
+    ExpiringSoon,
+    /// `{path}`, `{size}`, `{when}`, `{id}`
+    ExpiringEntry,
+    NoneListed,
+    Today,
+    /// `{n}`
+    DaysAgo,
+    /// `{n}`
+    InDays,
+}
+
+/// (locale, translations in `Message` order)
+const CATALOG: &[(&str, [&str; 13])] = &[
+    ("en", [
+        "PackagePurge digest",
+        "Overview",
+        "Package growth",
+        "unknown (fewer than two scans of the same roots)",
+        "Reclaimed: {size} by {count} applied plan(s)",
+        "Awaiting approval",
+        "{items} items, {size} — requested by {who}, {when}",
+        "Quarantine expiring soon",
+        "{path} ({size}) expires {when} — id {id}",
+        "none",
+        "today",
+        "{n} days ago",
+        "in {n} days",
+    ]),
+    ("de", [
+        "PackagePurge-Übersicht",
+        "Überblick",
+        "Paketwachstum",
+        "unbekannt (weniger als zwei Scans derselben Verzeichnisse)",
+        "Freigegeben: {size} durch {count} angewendete(n) Plan/Pläne",
+        "Wartet auf Freigabe",
+        "{items} Einträge, {size} — angefordert von {who}, {when}",
+        "Quarantäne läuft bald ab",
+        "{path} ({size}) läuft ab: {when} — ID {id}",
+        "keine",
+        "heute",
+        "vor {n} Tagen",
+        "in {n} Tagen",
+    ]),
+    ("es", [
+        "Resumen de PackagePurge",
+        "Resumen",
+        "Crecimiento de paquetes",
+        "desconocido (menos de dos análisis de las mismas raíces)",
+        "Recuperado: {size} con {count} plan(es) aplicado(s)",
+        "Pendiente de aprobación",
+        "{items} elementos, {size} — solicitado por {who}, {when}",
+        "Cuarentena a punto de caducar",
+        "{path} ({size}) caduca {when} — id {id}",
+        "ninguno",
+        "hoy",
+        "hace {n} días",
+        "dentro de {n} días",
+    ]),
+];
+
+impl DisplayConfig {
+    /// Overlay the `PACKAGEPURGE_*` display variables; unparseable values are ignored
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        if let Some(units) = var("PACKAGEPURGE_SIZE_UNITS").and_then(|v| v.parse().ok()) {
+            self.size_units = units;
+        }
+        if let Some(dates) = var("PACKAGEPURGE_DATES").and_then(|v| v.parse().ok()) {
+            self.dates = dates;
+        }
+        if let Some(zone) = var("PACKAGEPURGE_TIME_ZONE").and_then(|v| v.to_lowercase().parse().ok()) {
+            self.time_zone = zone;
+        }
+        if let Some(locale) = var("PACKAGEPURGE_LOCALE").filter(|v| !v.is_empty()) {
+            self.locale = locale;
+        }
+        self
+    }
+
+    pub fn size(&self, bytes: u64) -> String {
+        let (base, units) = match self.size_units {
+            SizeUnits::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB"]),
+            SizeUnits::Si => (1000.0, ["B", "kB", "MB", "GB", "TB"]),
+        };
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= base && unit < units.len() - 1 {
+            value /= base;
+            unit += 1;
+        }
+        let number = if unit == 0 { bytes.to_string() } else { format!("{:.1}", value) };
+        format!("{} {}", self.decimal(number), units[unit])
+    }
+
+    /// Locales that write decimal commas
+    fn decimal(&self, number: String) -> String {
+        if ["de", "es", "fr", "it", "nl", "pt"].contains(&self.language()) {
+            number.replace('.', ",")
+        } else {
+            number
+        }
+    }
+
+    fn language(&self) -> &str {
+        self.locale.split(['-', '_']).next().unwrap_or("en")
+    }
+
+    /// `at` as configured; relative dates are counted from `now`
+    pub fn date(&self, at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        match self.dates {
+            DateStyle::Relative => {
+                let days = (at - now).num_days();
+                match days {
+                    0 => self.text(Message::Today).to_string(),
+                    d if d < 0 => self.text(Message::DaysAgo).replace("{n}", &(-d).to_string()),
+                    d => self.text(Message::InDays).replace("{n}", &d.to_string()),
+                }
+            }
+            DateStyle::Absolute => match self.time_zone {
+                TimeZoneChoice::Utc => at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                TimeZoneChoice::Local => at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+            },
+        }
+    }
+
+    /// A calendar day, for period boundaries
+    pub fn day(&self, at: DateTime<Utc>) -> String {
+        match self.time_zone {
+            TimeZoneChoice::Utc => at.format("%Y-%m-%d").to_string(),
+            TimeZoneChoice::Local => at.with_timezone(&Local).format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// `message` in the configured locale, English when it has no translation
+    pub fn text(&self, message: Message) -> &'static str {
+        let strings = CATALOG.iter()
+            .find(|(locale, _)| *locale == self.language())
+            .unwrap_or(&CATALOG[0]).1;
+        strings[message as usize]
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("display.json")
+}
+
+pub fn load_config() -> DisplayConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Saved settings with the environment overrides of this run applied
+pub fn load() -> DisplayConfig {
+    load_config().with_env(|name| std::env::var(name).ok())
+}
+
+pub fn save_config(config: &DisplayConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &DisplayConfig) -> Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save display config to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_sizes_dates_and_strings() {
+        let binary = DisplayConfig::default();
+        assert_eq!(binary.size(1536), "1.5 KiB");
+        assert_eq!(binary.size(512), "512 B");
+
+        let german = DisplayConfig::default().with_env(|name| match name {
+            "PACKAGEPURGE_SIZE_UNITS" => Some("si".into()),
+            "PACKAGEPURGE_DATES" => Some("relative".into()),
+            "PACKAGEPURGE_LOCALE" => Some("de-AT".into()),
+            "PACKAGEPURGE_TIME_ZONE" => Some("bogus".into()),
+            _ => None,
+        });
+        assert_eq!(german.size(1_500_000), "1,5 MB");
+        assert_eq!(german.time_zone, TimeZoneChoice::Local);
+        let now = Utc::now();
+        assert_eq!(german.date(now - Duration::days(3), now), "vor 3 Tagen");
+        assert_eq!(german.text(Message::Overview), "Überblick");
+
+        let utc = DisplayConfig { time_zone: TimeZoneChoice::Utc, locale: "xx".into(), ..Default::default() };
+        let at = DateTime::parse_from_rfc3339("2024-05-01T08:30:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(utc.date(at, now), "2024-05-01 08:30 UTC");
+        assert_eq!(utc.text(Message::NoneListed), "none");
+        for (locale, strings) in CATALOG {
+            assert!(strings.iter().all(|s| !s.is_empty()), "{} has an empty string", locale);
+        }
+    }
+}
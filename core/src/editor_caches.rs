@@ -0,0 +1,343 @@
+//! Editor Cache Detection
+//!
+//! Finds the caches VS Code (and its forks) and JetBrains IDEs accumulate:
+//! superseded extension versions, per-workspace storage, V8 `CachedData` from
+//! previous editor builds, Chromium caches and JetBrains system directories.
+//! Everything reported here is regenerated by the editor on demand, so stale
+//! entries can go through the regular quarantine flow.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::types::{DryRunReport, PlanItem, PlanReason};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditorCacheKind {
+    /// Extension folder superseded by a newer version of the same extension
+    ExtensionOldVersion,
+    /// `User/workspaceStorage/<hash>` state for one workspace
+    WorkspaceStorage,
+    /// V8 code cache for one editor build
+    CachedData,
+    /// Chromium caches and downloaded extension packages
+    AppCache,
+    /// A JetBrains product's cache directory (indexes, caches, logs)
+    JetbrainsCache,
+}
+
+impl EditorCacheKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::ExtensionOldVersion => "editor_extension_old_version",
+            Self::WorkspaceStorage => "editor_workspace_storage",
+            Self::CachedData => "editor_cached_data",
+            Self::AppCache => "editor_app_cache",
+            Self::JetbrainsCache => "jetbrains_cache",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EditorCacheEntry {
+    pub path: String,
+    /// Editor flavour (`Code`, `VSCodium`, ...) or JetBrains product directory
+    pub editor: String,
+    pub kind: EditorCacheKind,
+    pub size_bytes: u64,
+    /// Newest modification time of anything inside the entry
+    pub last_modified: DateTime<Utc>,
+    pub stale: bool,
+    /// Why the entry is (or is not) considered stale
+    pub detail: Option<String>,
+}
+
+/// One VS Code-style editor install: its user data and extensions directories
+#[derive(Debug, Clone)]
+pub struct VsCodeInstall {
+    pub name: String,
+    pub user_data: PathBuf,
+    pub extensions: PathBuf,
+}
+
+/// Where editor caches live on this machine
+#[derive(Debug, Clone, Default)]
+pub struct EditorLocations {
+    pub vscode: Vec<VsCodeInstall>,
+    /// Directories holding one subdirectory per JetBrains product version
+    pub jetbrains: Vec<PathBuf>,
+}
+
+impl EditorLocations {
+    /// Platform default locations that exist for the current user
+    pub fn detect() -> Self {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        let config = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+        let vscode = [
+            ("Code", ".vscode"),
+            ("Code - Insiders", ".vscode-insiders"),
+            ("VSCodium", ".vscode-oss"),
+            ("Cursor", ".cursor"),
+        ]
+        .iter()
+        .map(|(name, dot_dir)| VsCodeInstall {
+            name: name.to_string(),
+            user_data: config.join(name),
+            extensions: home.join(dot_dir).join("extensions"),
+        })
+        .filter(|i| i.user_data.is_dir() || i.extensions.is_dir())
+        .collect();
+        let jetbrains = dirs::cache_dir()
+            .map(|c| c.join("JetBrains"))
+            .into_iter()
+            .filter(|p| p.is_dir())
+            .collect();
+        Self { vscode, jetbrains }
+    }
+
+    /// Whether `path` lies strictly inside one of the editor cache locations
+    pub fn contains(&self, path: &Path) -> bool {
+        self.vscode.iter()
+            .flat_map(|i| [&i.user_data, &i.extensions])
+            .chain(self.jetbrains.iter())
+            .any(|root| path.starts_with(root) && path != root)
+    }
+}
+
+/// Whether `path` is inside a detected editor cache location
+pub fn is_editor_cache(path: &Path) -> bool {
+    EditorLocations::detect().contains(path)
+}
+
+/// Find editor caches under `locations`. Entries not modified within
+/// `preserve_days` are stale, as are superseded extension versions, `CachedData`
+/// of older builds, older JetBrains versions and storage of deleted workspaces.
+pub fn scan_editor_caches(locations: &EditorLocations, preserve_days: i64) -> Vec<EditorCacheEntry> {
+    let cutoff = Utc::now() - Duration::days(preserve_days);
+    let mut out = Vec::new();
+    for install in &locations.vscode {
+        old_extension_versions(install, &mut out);
+        workspace_storage(install, cutoff, &mut out);
+        cached_data(install, &mut out);
+        for dir in ["Cache", "Code Cache", "GPUCache", "CachedExtensionVSIXs", "logs"] {
+            let path = install.user_data.join(dir);
+            if path.is_dir() {
+                let mut entry = entry(&path, &install.name, EditorCacheKind::AppCache);
+                entry.stale = entry.last_modified < cutoff;
+                out.push(entry);
+            }
+        }
+    }
+    for root in &locations.jetbrains {
+        jetbrains_caches(root, cutoff, &mut out);
+    }
+    out
+}
+
+/// Plan quarantining the stale entries
+pub fn plan_editor_cleanup(entries: &[EditorCacheEntry]) -> DryRunReport {
+    let items: Vec<PlanItem> = entries.iter().filter(|e| e.stale).map(|e| PlanItem {
+        target_path: e.path.clone(),
+        estimated_size_bytes: e.size_bytes,
+        reason: PlanReason::Regenerable { kind: e.kind.label().to_string() },
+        blockers: Vec::new(),
+        risk: None,
+    }).collect();
+    let total = items.iter().map(|i| i.estimated_size_bytes).sum();
+    DryRunReport { items, total_estimated_bytes: total, lru: None, warnings: Vec::new(), confirm: Vec::new(), estimated_inodes: None, eviction: None }
+}
+
+fn entry(path: &Path, editor: &str, kind: EditorCacheKind) -> EditorCacheEntry {
+    let (size_bytes, last_modified) = size_and_mtime(path);
+    EditorCacheEntry {
+        path: path.to_string_lossy().to_string(),
+        editor: editor.to_string(),
+        kind,
+        size_bytes,
+        last_modified,
+        stale: false,
+        detail: None,
+    }
+}
+
+/// Total file size and newest modification time below `path`
+fn size_and_mtime(path: &Path) -> (u64, DateTime<Utc>) {
+    let mut size = 0;
+    let mut newest = DateTime::<Utc>::MIN_UTC;
+    for meta in WalkDir::new(path).into_iter().filter_map(|e| e.ok()).filter_map(|e| e.metadata().ok()) {
+        if meta.is_file() {
+            size += meta.len();
+        }
+        if let Ok(m) = meta.modified() {
+            newest = newest.max(m.into());
+        }
+    }
+    (size, newest)
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|rd| rd.filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| e.path())
+            .collect())
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// Split a trailing dotted numeric version off a name: `publisher.ext-1.2.3`
+/// (optionally followed by `-<platform>`) or `IntelliJIdea2023.2`
+fn split_version(name: &str, separator: Option<char>) -> Option<(&str, Vec<u64>)> {
+    let start = match separator {
+        Some(sep) => name.char_indices()
+            .find(|&(i, c)| c == sep && name[i + 1..].starts_with(|d: char| d.is_ascii_digit()))
+            .map(|(i, _)| i + 1)?,
+        None => name.find(|c: char| c.is_ascii_digit())?,
+    };
+    let version = name[start..].split('-').next()?;
+    let parts: Option<Vec<u64>> = version.split('.').map(|p| p.parse().ok()).collect();
+    let id = name[..start].trim_end_matches(['-']);
+    Some((id, parts?)).filter(|(id, _)| !id.is_empty())
+}
+
+fn old_extension_versions(install: &VsCodeInstall, out: &mut Vec<EditorCacheEntry>) {
+    let mut by_id: HashMap<String, Vec<(Vec<u64>, PathBuf)>> = HashMap::new();
+    for dir in subdirs(&install.extensions) {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if let Some((id, version)) = split_version(&name, Some('-')) {
+            by_id.entry(id.to_lowercase()).or_default().push((version, dir.clone()));
+        }
+    }
+    for versions in by_id.values_mut() {
+        versions.sort();
+        let (newest, _) = versions.pop().expect("non-empty");
+        for (_, dir) in versions.iter() {
+            let mut e = entry(dir, &install.name, EditorCacheKind::ExtensionOldVersion);
+            e.stale = true;
+            e.detail = Some(format!("superseded by {}", newest.iter().map(u64::to_string).collect::<Vec<_>>().join(".")));
+            out.push(e);
+        }
+    }
+}
+
+/// Folder a workspace storage entry belongs to, from its `workspace.json`
+fn workspace_folder(storage: &Path) -> Option<PathBuf> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(storage.join("workspace.json")).ok()?).ok()?;
+    let uri = json.get("folder").or_else(|| json.get("workspace"))?.as_str()?;
+    let path = uri.strip_prefix("file://")?;
+    let decoded = path.replace("%20", " ").replace("%3A", ":").replace("%3a", ":");
+    // Windows URIs look like file:///c:/Users/...
+    let decoded = if decoded.get(2..3) == Some(":") { decoded[1..].to_string() } else { decoded };
+    Some(PathBuf::from(decoded))
+}
+
+fn workspace_storage(install: &VsCodeInstall, cutoff: DateTime<Utc>, out: &mut Vec<EditorCacheEntry>) {
+    for dir in subdirs(&install.user_data.join("User").join("workspaceStorage")) {
+        let mut e = entry(&dir, &install.name, EditorCacheKind::WorkspaceStorage);
+        match workspace_folder(&dir) {
+            Some(folder) if !folder.exists() => {
+                e.stale = true;
+                e.detail = Some(format!("workspace {} no longer exists", folder.display()));
+            }
+            Some(folder) => {
+                e.stale = e.last_modified < cutoff;
+                e.detail = Some(format!("workspace {}", folder.display()));
+            }
+            None => e.stale = e.last_modified < cutoff,
+        }
+        out.push(e);
+    }
+}
+
+fn cached_data(install: &VsCodeInstall, out: &mut Vec<EditorCacheEntry>) {
+    let mut builds: Vec<EditorCacheEntry> = subdirs(&install.user_data.join("CachedData"))
+        .iter()
+        .map(|d| entry(d, &install.name, EditorCacheKind::CachedData))
+        .collect();
+    builds.sort_by_key(|e| e.last_modified);
+    let current = builds.pop();
+    for mut e in builds {
+        e.stale = true;
+        e.detail = Some("code cache of an older editor build".into());
+        out.push(e);
+    }
+    out.extend(current);
+}
+
+fn jetbrains_caches(root: &Path, cutoff: DateTime<Utc>, out: &mut Vec<EditorCacheEntry>) {
+    let mut by_product: HashMap<String, Vec<(Vec<u64>, PathBuf)>> = HashMap::new();
+    for dir in subdirs(root) {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if let Some((product, version)) = split_version(&name, None) {
+            by_product.entry(product.to_string()).or_default().push((version, dir.clone()));
+        }
+    }
+    for versions in by_product.values_mut() {
+        versions.sort();
+        let newest = versions.len() - 1;
+        for (i, (_, dir)) in versions.iter().enumerate() {
+            let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let mut e = entry(dir, &name, EditorCacheKind::JetbrainsCache);
+            if i < newest {
+                e.stale = true;
+                e.detail = Some("a newer version of this IDE is installed".into());
+            } else {
+                e.stale = e.last_modified < cutoff;
+            }
+            out.push(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_split_version() {
+        assert_eq!(split_version("ms-python.python-2023.20.0", Some('-')), Some(("ms-python.python", vec![2023, 20, 0])));
+        assert_eq!(split_version("ms-vscode.cpptools-1.18.5-linux-x64", Some('-')), Some(("ms-vscode.cpptools", vec![1, 18, 5])));
+        assert_eq!(split_version("IntelliJIdea2023.2", None), Some(("IntelliJIdea", vec![2023, 2])));
+        assert_eq!(split_version("extensions.json", Some('-')), None);
+    }
+
+    #[test]
+    fn test_scan_vscode_and_jetbrains() {
+        let temp = tempdir().unwrap();
+        let install = VsCodeInstall {
+            name: "Code".into(),
+            user_data: temp.path().join("Code"),
+            extensions: temp.path().join("extensions"),
+        };
+        for ext in ["esbenp.prettier-vscode-9.0.0", "esbenp.prettier-vscode-10.1.0"] {
+            fs::create_dir_all(install.extensions.join(ext)).unwrap();
+            fs::write(install.extensions.join(ext).join("package.json"), "{}").unwrap();
+        }
+        let gone = install.user_data.join("User/workspaceStorage/abc");
+        fs::create_dir_all(&gone).unwrap();
+        fs::write(gone.join("workspace.json"), r#"{"folder":"file:///no/such/project"}"#).unwrap();
+        let jetbrains = temp.path().join("JetBrains");
+        fs::create_dir_all(jetbrains.join("PyCharm2022.3")).unwrap();
+        fs::create_dir_all(jetbrains.join("PyCharm2023.1")).unwrap();
+
+        let locations = EditorLocations { vscode: vec![install], jetbrains: vec![jetbrains.clone()] };
+        let entries = scan_editor_caches(&locations, 90);
+        let stale: Vec<&str> = entries.iter().filter(|e| e.stale).map(|e| e.path.as_str()).collect();
+        assert_eq!(stale.len(), 3);
+        assert!(stale.iter().any(|p| p.ends_with("prettier-vscode-9.0.0")));
+        assert!(stale.iter().any(|p| p.ends_with("abc")));
+        assert!(stale.iter().any(|p| p.ends_with("PyCharm2022.3")));
+        assert!(locations.contains(&jetbrains.join("PyCharm2022.3")));
+        assert!(!locations.contains(&jetbrains));
+
+        let plan = plan_editor_cleanup(&entries);
+        assert_eq!(plan.items.len(), 3);
+        assert!(plan.items.iter().all(|i| matches!(i.reason, PlanReason::Regenerable { .. })));
+    }
+}
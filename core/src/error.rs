@@ -0,0 +1,108 @@
+//! Error Taxonomy
+//!
+//! Typed errors for the library's public entry points. Each variant names the
+//! subsystem that failed and wraps the underlying `anyhow` chain, so embedders
+//! can match on the category while still getting the full context when printed.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Walking or reading the filesystem during a scan failed
+    #[error("scan failed: {0:#}")]
+    Scan(anyhow::Error),
+    /// Building a cleanup or deduplication plan failed
+    #[error("planning failed: {0:#}")]
+    Plan(anyhow::Error),
+    /// Moving into, restoring from or pruning the quarantine failed
+    #[error("quarantine operation failed: {0:#}")]
+    Quarantine(anyhow::Error),
+    /// Global store or symlink manipulation failed
+    #[error("store operation failed: {0:#}")]
+    Store(anyhow::Error),
+    /// Feature store database access failed
+    #[error("database error: {0:#}")]
+    Db(anyhow::Error),
+    /// Reading or writing PackagePurge settings failed
+    #[error("configuration error: {0:#}")]
+    Config(anyhow::Error),
+    /// A plan's approval token was missing, invalid or expired
+    #[error("approval check failed: {0:#}")]
+    Approval(anyhow::Error),
+    /// The rest of a canary-applied plan is not cleared to apply yet
+    #[error("canary check failed: {0:#}")]
+    Canary(anyhow::Error),
+    /// Packing build artifacts into cold storage or restoring them failed
+    #[error("archive operation failed: {0:#}")]
+    Archive(anyhow::Error),
+    /// Reinstalling a purged project's dependencies failed
+    #[error("reinstall failed: {0:#}")]
+    Install(anyhow::Error),
+    /// A gating lifecycle hook (`pre-scan`, `pre-apply`) failed
+    #[error("hook failed: {0:#}")]
+    Hook(anyhow::Error),
+    /// Delivering a digest to a file, sendmail or a webhook failed
+    #[error("delivery failed: {0:#}")]
+    Delivery(anyhow::Error),
+    /// Taking or releasing a filesystem snapshot failed
+    #[error("snapshot failed: {0:#}")]
+    Snapshot(anyhow::Error),
+    /// The operation was cancelled through its `CancellationToken`
+    #[error("operation cancelled")]
+    Cancelled,
+    /// A mutation was attempted in read-only mode; names what was refused
+    #[error("read-only mode: refusing to {0}")]
+    ReadOnly(&'static str),
+}
+
+impl Error {
+    /// Adapter for `map_err` that files an `anyhow` error under `kind`, unless
+    /// it already is a typed error (e.g. `Cancelled` raised by a nested call)
+    pub(crate) fn lift(kind: fn(anyhow::Error) -> Error) -> impl Fn(anyhow::Error) -> Error {
+        move |e| match e.downcast::<Error>() {
+            Ok(typed) => typed,
+            Err(e) => kind(e),
+        }
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Db(e.into())
+    }
+}
+
+/// Build a `map_err` adapter that adds `msg` as context and files the error
+/// under the database category
+pub(crate) fn db_err<E: Into<anyhow::Error>>(msg: &'static str) -> impl FnOnce(E) -> Error {
+    move |e| Error::Db(e.into().context(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_includes_context_chain() {
+        let inner = anyhow::anyhow!("disk full").context("Failed to write quarantine index");
+        let err = Error::Quarantine(inner);
+        let text = err.to_string();
+        assert!(text.starts_with("quarantine operation failed"));
+        assert!(text.contains("disk full"));
+        assert!(matches!(err, Error::Quarantine(_)));
+    }
+
+    #[test]
+    fn test_lift_keeps_typed_errors() {
+        let cancelled = anyhow::Error::new(Error::Cancelled);
+        assert!(matches!(Error::lift(Error::Scan)(cancelled), Error::Cancelled));
+
+        let refused = anyhow::Error::new(Error::ReadOnly("quarantine"));
+        assert!(matches!(Error::lift(Error::Quarantine)(refused), Error::ReadOnly("quarantine")));
+
+        let plain = anyhow::anyhow!("boom");
+        assert!(matches!(Error::lift(Error::Scan)(plain), Error::Scan(_)));
+    }
+}
@@ -0,0 +1,332 @@
+//! Eviction Policies
+//!
+//! One interface over the package caches' eviction policies: LRU
+//! (`PackageLruCache`), SLRU, LFU, ARC and W-TinyLFU. The optimization engine
+//! and `simulate` drive any of them through `EvictionPolicy`, and each
+//! reports the same `PolicyStats`, so policies can be compared on one access
+//! trace.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::arc_lfu::{ArcPolicy, SimpleLfu, SlruPolicy, WTinyLfu};
+use crate::cache::PackageLruCache;
+use crate::types::{PolicyStats, SegmentStats};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyKind {
+    #[default]
+    Lru,
+    /// Segmented LRU: probationary and protected segments
+    Slru,
+    Lfu,
+    /// Adaptive Replacement Cache
+    Arc,
+    /// Frequency-sketch admission in front of a segmented LRU
+    WTinyLfu,
+}
+
+impl PolicyKind {
+    pub const ALL: [PolicyKind; 5] = [Self::Lru, Self::Slru, Self::Lfu, Self::Arc, Self::WTinyLfu];
+}
+
+impl FromStr for PolicyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "lru" => Ok(Self::Lru),
+            "slru" => Ok(Self::Slru),
+            "lfu" => Ok(Self::Lfu),
+            "arc" => Ok(Self::Arc),
+            "w-tiny-lfu" | "wtinylfu" => Ok(Self::WTinyLfu),
+            other => Err(format!(
+                "unknown eviction policy `{}` (expected lru, slru, lfu, arc or w-tiny-lfu)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PolicyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lru => "lru",
+            Self::Slru => "slru",
+            Self::Lfu => "lfu",
+            Self::Arc => "arc",
+            Self::WTinyLfu => "w-tiny-lfu",
+        })
+    }
+}
+
+/// Hit, miss and eviction counts kept by each policy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PolicyCounters {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl PolicyCounters {
+    pub fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    fn stats(&self, policy: PolicyKind, entries: usize, capacity: Option<usize>, segments: Vec<SegmentStats>) -> PolicyStats {
+        let accesses = self.hits + self.misses;
+        PolicyStats {
+            policy,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            entries,
+            capacity,
+            hit_rate: if accesses > 0 { self.hits as f64 / accesses as f64 } else { 0.0 },
+            segments,
+        }
+    }
+}
+
+fn segment(name: &str, entries: usize, capacity: Option<usize>) -> SegmentStats {
+    SegmentStats { name: name.to_string(), entries, capacity }
+}
+
+/// A policy deciding which package keys stay cached
+pub trait EvictionPolicy {
+    fn kind(&self) -> PolicyKind;
+
+    /// Count an access to `key`, admitting it if it is not cached
+    fn record_access(&mut self, key: &str);
+
+    /// Count an access to `key` only if it is cached
+    fn record_hit(&mut self, key: &str) {
+        if self.contains(key) {
+            self.record_access(key);
+        }
+    }
+
+    /// Whether `key` is cached; does not count as an access
+    fn contains(&self, key: &str) -> bool;
+
+    /// Evict up to `n` keys, in the order the policy gives them up
+    fn select_victims(&mut self, n: usize) -> Vec<String>;
+
+    fn stats(&self) -> PolicyStats;
+}
+
+impl EvictionPolicy for PackageLruCache {
+    fn kind(&self) -> PolicyKind {
+        PolicyKind::Lru
+    }
+
+    fn record_access(&mut self, key: &str) {
+        PackageLruCache::record_access(self, key, 0);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        PackageLruCache::contains(self, key)
+    }
+
+    fn select_victims(&mut self, n: usize) -> Vec<String> {
+        (0..n).map_while(|_| self.evict_lru()).collect()
+    }
+
+    fn stats(&self) -> PolicyStats {
+        let lru = PackageLruCache::stats(self);
+        let counters = PolicyCounters { hits: lru.hits, misses: lru.misses, evictions: lru.evictions + lru.size_evictions };
+        counters.stats(PolicyKind::Lru, lru.entries, Some(lru.max_packages), Vec::new())
+    }
+}
+
+impl EvictionPolicy for SlruPolicy {
+    fn kind(&self) -> PolicyKind {
+        PolicyKind::Slru
+    }
+
+    fn record_access(&mut self, key: &str) {
+        SlruPolicy::record_hit(self, key);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        SlruPolicy::contains(self, key)
+    }
+
+    fn select_victims(&mut self, n: usize) -> Vec<String> {
+        (0..n).map_while(|_| self.select_victim()).collect()
+    }
+
+    fn stats(&self) -> PolicyStats {
+        let s = self.segment_stats();
+        self.counters.stats(
+            PolicyKind::Slru,
+            self.len(),
+            Some(s.cap_probationary + s.cap_protected),
+            vec![
+                segment("probationary", s.probationary, Some(s.cap_probationary)),
+                segment("protected", s.protected, Some(s.cap_protected)),
+            ],
+        )
+    }
+}
+
+impl EvictionPolicy for SimpleLfu {
+    fn kind(&self) -> PolicyKind {
+        PolicyKind::Lfu
+    }
+
+    fn record_access(&mut self, key: &str) {
+        self.increment(key);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        SimpleLfu::contains(self, key)
+    }
+
+    fn select_victims(&mut self, n: usize) -> Vec<String> {
+        (0..n).map_while(|_| self.victim()).collect()
+    }
+
+    fn stats(&self) -> PolicyStats {
+        self.counters.stats(PolicyKind::Lfu, self.len(), self.capacity(), Vec::new())
+    }
+}
+
+impl EvictionPolicy for ArcPolicy {
+    fn kind(&self) -> PolicyKind {
+        PolicyKind::Arc
+    }
+
+    fn record_access(&mut self, key: &str) {
+        ArcPolicy::record_access(self, key);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        ArcPolicy::contains(self, key)
+    }
+
+    fn select_victims(&mut self, n: usize) -> Vec<String> {
+        (0..n).map_while(|_| self.select_victim()).collect()
+    }
+
+    fn stats(&self) -> PolicyStats {
+        // The recency/frequency split adapts, so the lists have no fixed capacity
+        let (t1, t2, b1, b2) = self.list_lengths();
+        self.counters.stats(
+            PolicyKind::Arc,
+            self.len(),
+            Some(self.capacity()),
+            vec![
+                segment("recency", t1, None),
+                segment("frequency", t2, None),
+                segment("recency_ghosts", b1, None),
+                segment("frequency_ghosts", b2, None),
+            ],
+        )
+    }
+}
+
+impl EvictionPolicy for WTinyLfu {
+    fn kind(&self) -> PolicyKind {
+        PolicyKind::WTinyLfu
+    }
+
+    fn record_access(&mut self, key: &str) {
+        WTinyLfu::record_access(self, key);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        WTinyLfu::contains(self, key)
+    }
+
+    fn select_victims(&mut self, n: usize) -> Vec<String> {
+        (0..n).map_while(|_| self.select_victim()).collect()
+    }
+
+    fn stats(&self) -> PolicyStats {
+        let segments = self.segment_lengths();
+        self.counters.stats(
+            PolicyKind::WTinyLfu,
+            self.len(),
+            Some(segments.iter().map(|(_, cap)| cap).sum()),
+            ["window", "probationary", "protected"].iter().zip(segments)
+                .map(|(name, (len, cap))| segment(name, len, Some(cap)))
+                .collect(),
+        )
+    }
+}
+
+/// A `kind` policy holding at most `capacity` keys
+pub fn build(kind: PolicyKind, capacity: usize) -> Box<dyn EvictionPolicy> {
+    match kind {
+        PolicyKind::Lru => Box::new(PackageLruCache::new(capacity, u64::MAX)),
+        PolicyKind::Slru => Box::new(SlruPolicy::new(capacity)),
+        PolicyKind::Lfu => {
+            let mut lfu = SimpleLfu::new();
+            lfu.set_capacity(Some(capacity));
+            Box::new(lfu)
+        }
+        PolicyKind::Arc => Box::new(ArcPolicy::new(capacity)),
+        PolicyKind::WTinyLfu => Box::new(WTinyLfu::new(capacity)),
+    }
+}
+
+/// Replay `trace` (package keys in access order) through `policy`
+pub fn simulate<'a>(policy: &mut dyn EvictionPolicy, trace: impl IntoIterator<Item = &'a str>) -> PolicyStats {
+    for key in trace {
+        policy.record_access(key);
+    }
+    policy.stats()
+}
+
+/// Statistics of every policy at `capacity` over the same trace
+pub fn compare(capacity: usize, trace: &[&str]) -> Vec<PolicyStats> {
+    PolicyKind::ALL.iter()
+        .map(|kind| simulate(build(*kind, capacity).as_mut(), trace.iter().copied()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policies_report_comparable_stats() {
+        // A hot set of 4 keys, each read twice in a row, between one-off scans of 20 cold keys
+        let cold: Vec<String> = (0..60).map(|i| format!("cold{}", i)).collect();
+        let mut trace: Vec<&str> = Vec::new();
+        for round in 0..3 {
+            for _ in 0..5 {
+                trace.extend(["a", "a", "b", "b", "c", "c", "d", "d"]);
+            }
+            trace.extend(cold[round * 20..(round + 1) * 20].iter().map(String::as_str));
+        }
+
+        let stats = compare(10, &trace);
+        assert_eq!(stats.iter().map(|s| s.policy).collect::<Vec<_>>(), PolicyKind::ALL);
+        for s in &stats {
+            assert_eq!(s.hits + s.misses, trace.len() as u64, "{}", s.policy);
+            assert!(s.entries <= 10 && s.capacity == Some(10), "{}", s.policy);
+            assert_eq!(s.evictions, s.misses - s.entries as u64, "{}", s.policy);
+        }
+        // The cold scans flush the hot keys out of plain LRU every round
+        let hit_rate = |kind| stats.iter().find(|s| s.policy == kind).unwrap().hit_rate;
+        for kind in [PolicyKind::Slru, PolicyKind::Lfu, PolicyKind::Arc, PolicyKind::WTinyLfu] {
+            assert!(hit_rate(kind) > hit_rate(PolicyKind::Lru), "{}", kind);
+        }
+
+        let mut lru = build(PolicyKind::Lru, 2);
+        simulate(lru.as_mut(), ["x", "y", "x"]);
+        assert_eq!(lru.select_victims(5), ["y", "x"]);
+        assert!(!lru.contains("x"));
+        lru.record_hit("z");
+        assert_eq!((lru.stats().hits, lru.stats().misses), (1, 2));
+        assert_eq!("W_TINY_LFU".parse::<PolicyKind>(), Ok(PolicyKind::WTinyLfu));
+    }
+}
@@ -0,0 +1,146 @@
+//! Exec Wrapper
+//!
+//! `packagepurge exec -- <command>` runs a command inside a project after
+//! preparing it: packages of the project that sit in quarantine are restored
+//! first, the command is logged as a behavior event, and every dependency
+//! the project's lockfile pins gets an access recorded, plus a script
+//! execution for the tools a `<pm> run <script>` invokes. Aggressive
+//! policies become survivable: whatever a command still needs is back
+//! before it runs, and using it keeps it from looking stale.
+
+use std::path::{Path, PathBuf};
+
+use crate::feature_store::FeatureStore;
+use crate::lockfiles::{parse_npm_package_lock, parse_pnpm_lock, parse_yarn_lock};
+use crate::reinstall::PackageManager;
+use crate::types::QuarantineRecord;
+use crate::usage_tracker::detect_script_execution;
+
+/// Nearest directory at or above `dir` with a package.json
+pub fn find_project(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().find(|d| d.join("package.json").is_file()).map(Path::to_path_buf)
+}
+
+/// `name@version` keys of the packages `project`'s lockfile pins
+pub fn project_dependencies(project: &Path) -> Vec<String> {
+    let deps = match PackageManager::detect(project) {
+        (PackageManager::Npm, Some(lockfile)) => parse_npm_package_lock(&project.join(lockfile)),
+        (PackageManager::Yarn, Some(lockfile)) => parse_yarn_lock(&project.join(lockfile)),
+        (PackageManager::Pnpm, Some(lockfile)) => parse_pnpm_lock(&project.join(lockfile)),
+        _ => Vec::new(),
+    };
+    deps.into_iter().map(|(name, version)| format!("{}@{}", name, version)).collect()
+}
+
+/// Script a command runs through its package manager: `npm run build`,
+/// `pnpm test`, `yarn lint`
+fn script_name(command: &[String]) -> Option<&str> {
+    let (tool, rest) = command.split_first()?;
+    if !["npm", "pnpm", "yarn", "bun"].contains(&tool.as_str()) {
+        return None;
+    }
+    match rest.first().map(String::as_str) {
+        Some("run" | "run-script") => rest.get(1).map(String::as_str),
+        Some(script @ ("test" | "start" | "build")) => Some(script),
+        Some(script) if tool != "npm" && !script.starts_with('-') => Some(script),
+        _ => None,
+    }
+}
+
+/// Log `command` as an `exec` event of `project` and record an access to
+/// each of its dependencies. Returns the package keys touched.
+pub fn record_usage(db: &FeatureStore, project: &Path, command: &[String]) -> crate::Result<Vec<String>> {
+    let project_str = project.to_string_lossy();
+    db.log_event("exec", Some(&command.join(" ")), Some(&project_str))?;
+    let touched = project_dependencies(project);
+    for key in &touched {
+        db.record_package_access(key, 0)?;
+    }
+    // Scripts name tools by their package.json range; metrics are keyed by
+    // the version the lockfile resolved
+    if let Some(script) = script_name(command) {
+        for tool in detect_script_execution(project, script) {
+            let Some((name, _)) = tool.rsplit_once('@') else { continue };
+            for key in touched.iter().filter(|k| k.rsplit_once('@').is_some_and(|(n, _)| n == name)) {
+                db.record_script_execution(key)?;
+            }
+        }
+    }
+    Ok(touched)
+}
+
+/// Quarantine entries taken from `project`'s `node_modules`, in the order
+/// they can be put back: enclosing directories before anything inside them
+pub fn quarantined_under(project: &Path, records: &[QuarantineRecord]) -> Vec<QuarantineRecord> {
+    let node_modules = project.join("node_modules");
+    let mut found: Vec<QuarantineRecord> = records.iter()
+        .filter(|r| Path::new(&r.original_path).starts_with(&node_modules))
+        .cloned()
+        .collect();
+    found.sort_by_key(|r| r.original_path.len());
+    found
+}
+
+/// Restore every quarantined package of `project`. Entries whose original
+/// path is occupied again are left in quarantine; failures are reported
+/// alongside the restored records rather than stopping the rest.
+pub fn restore_project(project: &Path) -> (Vec<QuarantineRecord>, Vec<(QuarantineRecord, crate::Error)>) {
+    let (mut restored, mut failed) = (Vec::new(), Vec::new());
+    for rec in quarantined_under(project, &crate::safety::list_quarantine()) {
+        if Path::new(&rec.original_path).exists() {
+            continue;
+        }
+        match crate::safety::rollback_record(&rec) {
+            Ok(()) => restored.push(rec),
+            Err(e) => failed.push((rec, e)),
+        }
+    }
+    (restored, failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn record(original: &Path) -> QuarantineRecord {
+        QuarantineRecord::for_test(&original.to_string_lossy(), original, 0)
+    }
+
+    #[test]
+    fn test_records_usage_of_project_dependencies() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("app");
+        fs::create_dir_all(project.join("src/deep")).unwrap();
+        fs::write(project.join("package.json"),
+            r#"{"scripts": {"build": "vite build"}, "devDependencies": {"vite": "^5.0.0"}}"#).unwrap();
+        fs::write(project.join("package-lock.json"),
+            r#"{"lockfileVersion": 3, "packages": {"node_modules/vite": {"version": "5.1.0"}}}"#).unwrap();
+        assert_eq!(find_project(&project.join("src/deep")), Some(project.clone()));
+
+        let db = FeatureStore::open(&temp.path().join("features.db")).unwrap();
+        let command: Vec<String> = ["npm", "run", "build"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(record_usage(&db, &project, &command).unwrap(), vec!["vite@5.1.0"]);
+        let metrics = db.get_package_metrics("vite@5.1.0").unwrap().unwrap();
+        assert_eq!(metrics.access_count, 1);
+        assert_eq!(metrics.script_execution_count, 1);
+        assert_eq!(script_name(&command), Some("build"));
+        assert_eq!(script_name(&["pnpm".into(), "lint".into()]), Some("lint"));
+        assert_eq!(script_name(&["npm".into(), "install".into()]), None);
+    }
+
+    #[test]
+    fn test_quarantined_under_orders_parents_first() {
+        let project = Path::new("/work/app");
+        let records = vec![
+            record(&project.join("node_modules/lodash")),
+            record(Path::new("/work/other/node_modules")),
+            record(&project.join("node_modules")),
+            record(&project.join("dist")),
+        ];
+        let found = quarantined_under(project, &records);
+        let paths: Vec<&str> = found.iter().map(|r| r.original_path.as_str()).collect();
+        assert_eq!(paths, vec!["/work/app/node_modules", "/work/app/node_modules/lodash"]);
+    }
+}
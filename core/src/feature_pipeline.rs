@@ -0,0 +1,195 @@
+//! Batch Feature Computation
+//!
+//! `ml compute-features` computes the feature vector of every package in the
+//! latest scan snapshot at once and stores them, so planning with
+//! `--enable-ml` looks vectors up instead of extracting them per item.
+//!
+//! Each package is joined with its recorded usage metrics, the project owning
+//! it (stored metadata, refreshed from its package.json and git log while it
+//! is still on disk) and the developer behavior recorded there. A package
+//! installed in several projects gets the vector of the copy whose project
+//! was active most recently, the one most likely to keep it.
+
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::feature_store::FeatureStore;
+use crate::ml::{PredictiveOptimizer, FEATURE_VERSION};
+use crate::optimization::detect_project_type;
+use crate::repo_activity::local_last_commit;
+use crate::types::{DeveloperBehavior, PackageSnapshot, PackageUsageMetrics, ProjectMetadata};
+use crate::verify::owning_project;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureJobReport {
+    /// Scan whose snapshot was used (None: no scan recorded yet)
+    pub scan_id: Option<i64>,
+    pub scanned_at: Option<DateTime<Utc>>,
+    /// Package copies in the snapshot
+    pub packages: usize,
+    pub projects: usize,
+    /// Feature vectors stored, one per name@version
+    pub computed: usize,
+    pub feature_version: u32,
+    pub elapsed_ms: u64,
+}
+
+/// Metadata of the project at `path` as it is on disk, if it still exists
+fn project_on_disk(path: &Path) -> Option<ProjectMetadata> {
+    let manifest = path.join("package.json");
+    let modified = fs::metadata(&manifest).and_then(|m| m.modified()).ok()?;
+    let json: serde_json::Value = fs::read_to_string(&manifest).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    let dependency_count = ["dependencies", "devDependencies"].iter()
+        .filter_map(|field| json.get(field).and_then(|d| d.as_object()))
+        .map(|deps| deps.len())
+        .sum();
+    let path = path.to_string_lossy().to_string();
+    Some(ProjectMetadata {
+        project_type: detect_project_type(&path),
+        last_commit_date: local_last_commit(Path::new(&path)),
+        dependency_count,
+        last_modified: modified.into(),
+        path,
+    })
+}
+
+/// Stand-in for a package no known project owns
+fn unowned(package: &PackageSnapshot) -> ProjectMetadata {
+    ProjectMetadata {
+        path: String::new(),
+        project_type: "unknown".into(),
+        last_commit_date: None,
+        dependency_count: 0,
+        last_modified: package.atime,
+    }
+}
+
+/// Known projects first, then the most recently committed to or modified
+fn rank(project: &ProjectMetadata) -> (bool, DateTime<Utc>) {
+    let active = project.last_commit_date.map_or(project.last_modified, |c| c.max(project.last_modified));
+    (!project.path.is_empty(), active)
+}
+
+/// Compute and store the feature vectors of the latest scan's packages
+pub fn compute_features(store: &FeatureStore, predictor: &PredictiveOptimizer) -> crate::Result<FeatureJobReport> {
+    let started = Instant::now();
+    let snapshots = store.latest_snapshots()?;
+    let latest = snapshots.iter().map(|(_, id, at)| (*id, *at)).max_by_key(|(id, _)| *id);
+    let packages: Vec<PackageSnapshot> = snapshots.into_iter()
+        .filter(|(_, id, _)| Some(*id) == latest.map(|(l, _)| l))
+        .map(|(package, _, _)| package)
+        .collect();
+
+    // Reading manifests and git logs is the slow part; the store is not shared across threads
+    let owners: BTreeSet<PathBuf> = packages.iter().filter_map(|p| owning_project(Path::new(&p.path))).collect();
+    let on_disk: Vec<(PathBuf, Option<ProjectMetadata>)> = owners.into_par_iter()
+        .map(|path| {
+            let project = project_on_disk(&path);
+            (path, project)
+        })
+        .collect();
+    let mut projects: HashMap<PathBuf, (ProjectMetadata, DeveloperBehavior)> = HashMap::new();
+    for (path, fresh) in on_disk {
+        let key = path.to_string_lossy();
+        let Some(project) = fresh.or(store.get_project(&key)?) else { continue };
+        let behavior = store.developer_behavior(&key)?;
+        projects.insert(path, (project, behavior));
+    }
+
+    // One input per name@version: the copy in the most recently active project
+    let fallback = DeveloperBehavior::default();
+    let mut inputs: HashMap<String, (PackageUsageMetrics, ProjectMetadata, &DeveloperBehavior)> = HashMap::new();
+    for package in &packages {
+        let key = format!("{}@{}", package.name, package.version);
+        let (project, behavior) = match owning_project(Path::new(&package.path)).and_then(|p| projects.get(&p)) {
+            Some((project, behavior)) => (project.clone(), behavior),
+            None => (unowned(package), &fallback),
+        };
+        if let Some((metrics, current, _)) = inputs.get_mut(&key) {
+            metrics.last_access_time = metrics.last_access_time.max(package.atime);
+            if rank(&project) <= rank(current) {
+                continue;
+            }
+        }
+        let mut metrics = match inputs.remove(&key) {
+            Some((metrics, _, _)) => metrics,
+            None => store.get_package_metrics(&key)?.unwrap_or(PackageUsageMetrics {
+                package_key: key.clone(),
+                last_access_time: package.atime,
+                last_script_execution: None,
+                access_count: 1,
+                script_execution_count: 0,
+                last_successful_build: None,
+            }),
+        };
+        metrics.last_access_time = metrics.last_access_time.max(package.atime);
+        inputs.insert(key, (metrics, project, behavior));
+    }
+
+    let vectors: Vec<(&String, Vec<f64>)> = inputs.par_iter()
+        .map(|(key, (metrics, project, behavior))| (key, predictor.extract_features(metrics, project, behavior)))
+        .collect();
+    let computed = store.store_features_batch(vectors.iter().map(|(key, features)| (key.as_str(), features.as_slice())))?;
+
+    Ok(FeatureJobReport {
+        scan_id: latest.map(|(id, _)| id),
+        scanned_at: latest.map(|(_, at)| at),
+        packages: packages.len(),
+        projects: projects.len(),
+        computed,
+        feature_version: FEATURE_VERSION,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ml::FEATURE_COUNT;
+    use crate::types::ScanStats;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_computes_one_vector_per_package_of_the_latest_scan() {
+        let temp = tempdir().unwrap();
+        let store = FeatureStore::open(&temp.path().join("features.db")).unwrap();
+        let app = temp.path().join("app");
+        fs::create_dir_all(app.join("node_modules/react")).unwrap();
+        fs::write(app.join("package.json"), r#"{"dependencies":{"react":"18.2.0","left-pad":"1.3.0"}}"#).unwrap();
+
+        let snapshot = |path: PathBuf, name: &str, version: &str| PackageSnapshot {
+            path: path.to_string_lossy().to_string(),
+            name: name.into(),
+            version: version.into(),
+            size_bytes: 10,
+            atime: Utc::now(),
+        };
+        let first = store.record_scan(&[temp.path().to_path_buf()], 1, 10, &ScanStats::default()).unwrap();
+        store.record_snapshot(first, &[snapshot(temp.path().join("gone/node_modules/old"), "old", "1.0.0")]).unwrap();
+        let latest = store.record_scan(&[temp.path().to_path_buf()], 3, 30, &ScanStats::default()).unwrap();
+        store.record_snapshot(latest, &[
+            snapshot(app.join("node_modules/react"), "react", "18.2.0"),
+            snapshot(temp.path().join("other/node_modules/react"), "react", "18.2.0"),
+            snapshot(temp.path().join("cache/left-pad"), "left-pad", "1.3.0"),
+        ]).unwrap();
+
+        let report = compute_features(&store, &PredictiveOptimizer::new(90)).unwrap();
+        assert_eq!((report.scan_id, report.packages, report.projects, report.computed), (Some(latest), 3, 1, 2));
+
+        let features = store.load_features().unwrap();
+        let mut keys: Vec<&String> = features.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["left-pad@1.3.0", "react@18.2.0"]);
+        // react took the context of the project still on disk: a react app with two dependencies
+        let react = &features["react@18.2.0"];
+        assert_eq!(react.len(), FEATURE_COUNT);
+        assert_eq!((react[6], react[7]), (1.0, 0.02));
+    }
+}
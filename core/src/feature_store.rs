@@ -5,26 +5,42 @@
 //! - Project metadata
 //! - Developer behavior patterns
 //! - ML feature vectors
+//! - Per-scan performance statistics
+//! - LRU cache telemetry of optimize runs
+//! - Estimated against reclaimed bytes of applied plans
+//! - Projects archived to cold storage
+//! - Per-package sizes of recent scans, for `top`
 //!
 //! This replaces JSON file storage with SQLite for better performance and querying.
+//!
+//! Project paths and commands are written as the privacy settings allow (see
+//! `privacy`); readers treat hashed paths and bare commands as less detail,
+//! not as errors.
 
-use anyhow::{Context, Result};
+use anyhow::Context;
 use chrono::{DateTime, Utc};
 use rusqlite::{params, Connection, OptionalExtension};
 use std::path::{Path, PathBuf};
 
-use crate::types::{PackageUsageMetrics, ProjectMetadata};
+use crate::error::{db_err, Error, Result};
+use crate::ml::{validate_features, FEATURE_COUNT, FEATURE_VERSION};
+use crate::privacy::PrivacyConfig;
+use crate::types::{ApplyAccuracy, ApplyReconciliation, ArchiveRecord, DeveloperBehavior, LruStats, PackageUsageMetrics, PackageSnapshot, ProjectMetadata, ScanStats};
+
+/// Snapshots older than this are dropped once a newer one of the same
+/// package exists
+const SNAPSHOT_RETENTION_DAYS: i64 = 90;
 
 /// SQLite-backed feature store
 pub struct FeatureStore {
     conn: Connection,
+    privacy: PrivacyConfig,
 }
 
 impl FeatureStore {
     /// Default path for the feature store database
     pub fn default_db_path() -> PathBuf {
-        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-        home.join(".packagepurge").join("features.db")
+        crate::paths::feature_db()
     }
 
     /// Open or create a feature store at the given path
@@ -32,21 +48,30 @@ impl FeatureStore {
         // Ensure parent directory exists
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+                .with_context(|| format!("Failed to create directory {:?}", parent))
+                .map_err(Error::Db)?;
         }
 
         let conn = Connection::open(db_path)
-            .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+            .with_context(|| format!("Failed to open database at {:?}", db_path))
+            .map_err(Error::Db)?;
+        conn.busy_timeout(crate::scan_lease::DB_BUSY_TIMEOUT)?;
 
-        let store = Self { conn };
+        let store = Self { conn, privacy: PrivacyConfig::default() };
         store.initialize_schema()?;
         
         Ok(store)
     }
 
-    /// Open the default feature store
+    /// Open the default feature store under the configured privacy settings
     pub fn open_default() -> Result<Self> {
-        Self::open(&Self::default_db_path())
+        Ok(Self::open(&Self::default_db_path())?.with_privacy(crate::privacy::load_config()))
+    }
+
+    /// Record under `privacy` from now on
+    pub fn with_privacy(mut self, privacy: PrivacyConfig) -> Self {
+        self.privacy = privacy;
+        self
     }
 
     /// Initialize database schema
@@ -93,14 +118,96 @@ impl FeatureStore {
                 computed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
             );
 
+            -- Per-scan I/O and timing figures
+            CREATE TABLE IF NOT EXISTS scan_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at TEXT NOT NULL,
+                roots TEXT NOT NULL,
+                package_count INTEGER NOT NULL,
+                dirs_walked INTEGER NOT NULL,
+                files_stated INTEGER NOT NULL,
+                bytes_read INTEGER NOT NULL,
+                wall_ms INTEGER NOT NULL,
+                cpu_ms INTEGER,
+                cache_hits INTEGER NOT NULL,
+                cache_misses INTEGER NOT NULL,
+                package_bytes INTEGER
+            );
+
+            -- LRU cache counters of each optimize run
+            CREATE TABLE IF NOT EXISTS lru_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                recorded_at TEXT NOT NULL,
+                hits INTEGER NOT NULL,
+                misses INTEGER NOT NULL,
+                evictions INTEGER NOT NULL,
+                size_evictions INTEGER NOT NULL,
+                entries INTEGER NOT NULL,
+                max_packages INTEGER NOT NULL,
+                current_bytes INTEGER NOT NULL,
+                budget_bytes INTEGER NOT NULL
+            );
+
+            -- Estimated against reclaimed bytes of each applied plan
+            CREATE TABLE IF NOT EXISTS apply_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                applied_at TEXT NOT NULL,
+                items INTEGER NOT NULL,
+                estimated_bytes INTEGER NOT NULL,
+                actual_bytes INTEGER NOT NULL,
+                run_id TEXT
+            );
+
+            -- Build artifacts moved to cold storage by `archive`
+            CREATE TABLE IF NOT EXISTS archives (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_path TEXT NOT NULL,
+                location TEXT NOT NULL,
+                dirs TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                archive_bytes INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                restored_at TEXT
+            );
+
+            -- Packages as each scan found them
+            CREATE TABLE IF NOT EXISTS package_snapshots (
+                scan_id INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                atime TEXT NOT NULL,
+                PRIMARY KEY (scan_id, path)
+            );
+
             -- Indexes for common queries
+            CREATE INDEX IF NOT EXISTS idx_package_snapshots_path
+                ON package_snapshots(path, scan_id);
             CREATE INDEX IF NOT EXISTS idx_package_metrics_access 
                 ON package_metrics(last_access_time);
             CREATE INDEX IF NOT EXISTS idx_behavior_events_timestamp 
                 ON behavior_events(timestamp);
             CREATE INDEX IF NOT EXISTS idx_projects_modified 
                 ON projects(last_modified);
-        "#).context("Failed to initialize database schema")?;
+        "#).map_err(db_err("Failed to initialize database schema"))?;
+
+        // Columns added after their table first shipped
+        let has_package_bytes = self.conn
+            .prepare("SELECT 1 FROM pragma_table_info('scan_runs') WHERE name = 'package_bytes'")?
+            .exists([])?;
+        if !has_package_bytes {
+            self.conn.execute("ALTER TABLE scan_runs ADD COLUMN package_bytes INTEGER", [])
+                .map_err(db_err("Failed to migrate database schema"))?;
+        }
+        let has_run_id = self.conn
+            .prepare("SELECT 1 FROM pragma_table_info('apply_runs') WHERE name = 'run_id'")?
+            .exists([])?;
+        if !has_run_id {
+            self.conn.execute("ALTER TABLE apply_runs ADD COLUMN run_id TEXT", [])
+                .map_err(db_err("Failed to migrate database schema"))?;
+        }
 
         Ok(())
     }
@@ -124,7 +231,7 @@ impl FeatureStore {
                 updated_at = ?2
             "#,
             params![package_key, now],
-        ).context("Failed to record package access")?;
+        ).map_err(db_err("Failed to record package access"))?;
         
         Ok(())
     }
@@ -142,7 +249,7 @@ impl FeatureStore {
             WHERE package_key = ?1
             "#,
             params![package_key, now],
-        ).context("Failed to record script execution")?;
+        ).map_err(db_err("Failed to record script execution"))?;
         
         Ok(())
     }
@@ -159,7 +266,7 @@ impl FeatureStore {
             WHERE package_key = ?1
             "#,
             params![package_key, now],
-        ).context("Failed to record build")?;
+        ).map_err(db_err("Failed to record build"))?;
         
         Ok(())
     }
@@ -183,7 +290,7 @@ impl FeatureStore {
                 
                 Ok((package_key, last_access_str, last_script_str, access_count, script_count, last_build_str))
             },
-        ).optional().context("Failed to query package metrics")?;
+        ).optional().map_err(db_err("Failed to query package metrics"))?;
 
         match result {
             Some((key, access_str, script_str, access_count, script_count, build_str)) => {
@@ -222,7 +329,7 @@ impl FeatureStore {
         
         let packages = stmt.query_map(params![cutoff], |row| row.get(0))?
             .collect::<std::result::Result<Vec<String>, _>>()
-            .context("Failed to get stale packages")?;
+            .map_err(db_err("Failed to get stale packages"))?;
         
         Ok(packages)
     }
@@ -238,7 +345,7 @@ impl FeatureStore {
             Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
         })?
         .collect::<std::result::Result<Vec<_>, _>>()
-        .context("Failed to get top packages")?;
+        .map_err(db_err("Failed to get top packages"))?;
         
         Ok(packages)
     }
@@ -264,73 +371,556 @@ impl FeatureStore {
                 last_modified = ?5,
                 updated_at = ?6
             "#,
-            params![project.path, project.project_type, last_commit, project.dependency_count as i64, last_modified, now],
-        ).context("Failed to upsert project")?;
+            params![
+                self.privacy.project_key(&project.path), project.project_type, last_commit,
+                project.dependency_count as i64, last_modified, now,
+            ],
+        ).map_err(db_err("Failed to upsert project"))?;
         
         Ok(())
     }
 
+    /// Stored metadata of the project at `path`
+    pub fn get_project(&self, path: &str) -> Result<Option<ProjectMetadata>> {
+        self.conn.query_row(
+            "SELECT project_type, last_commit_date, dependency_count, last_modified FROM projects WHERE path = ?1",
+            params![self.privacy.project_key(path)],
+            |row| {
+                let last_commit: Option<String> = row.get(1)?;
+                let last_modified: String = row.get(3)?;
+                Ok(ProjectMetadata {
+                    path: path.to_string(),
+                    project_type: row.get::<_, Option<String>>(0)?.unwrap_or_else(|| "unknown".into()),
+                    last_commit_date: last_commit.as_deref().map(parse_time),
+                    dependency_count: row.get::<_, i64>(2)? as usize,
+                    last_modified: parse_time(&last_modified),
+                })
+            },
+        ).optional().map_err(db_err("Failed to get project"))
+    }
+
     // =========================================================================
     // Behavior Events
     // =========================================================================
 
-    /// Log a developer behavior event
+    /// Log a developer behavior event (nothing when event logging is disabled)
     pub fn log_event(&self, event_type: &str, command: Option<&str>, project_path: Option<&str>) -> Result<()> {
+        if self.privacy.disable_event_logging {
+            return Ok(());
+        }
         let now = Utc::now().to_rfc3339();
+        let command = command.map(|c| self.privacy.command_text(c));
+        let project_path = project_path.map(|p| self.privacy.project_key(p));
         
         self.conn.execute(
             "INSERT INTO behavior_events (event_type, command, project_path, timestamp) VALUES (?1, ?2, ?3, ?4)",
             params![event_type, command, project_path, now],
-        ).context("Failed to log event")?;
+        ).map_err(db_err("Failed to log event"))?;
         
         Ok(())
     }
 
+    /// Recorded behavior in `project_path`, for the predictor. Hashed paths
+    /// are matched through the same hash; when commands were stored without
+    /// arguments builds cannot be told apart, so `days_since_last_build`
+    /// stays unknown, and without events everything is empty.
+    pub fn developer_behavior(&self, project_path: &str) -> Result<DeveloperBehavior> {
+        let mut stmt = self.conn.prepare(
+            "SELECT command, timestamp FROM behavior_events
+             WHERE project_path = ?1 AND command IS NOT NULL ORDER BY timestamp",
+        )?;
+        let commands: Vec<(String, DateTime<Utc>)> = stmt
+            .query_map(params![self.privacy.project_key(project_path)], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .filter_map(|row| row.ok())
+            .filter_map(|(command, at)| Some((command, DateTime::parse_from_rfc3339(&at).ok()?.with_timezone(&Utc))))
+            .collect();
+        let last_build = commands.iter().rev()
+            .find(|(command, _)| command.split_whitespace().skip(1).any(|w| w == "build"))
+            .map(|(_, at)| (Utc::now() - *at).num_days());
+        Ok(DeveloperBehavior {
+            file_access_frequency: commands.len() as u64,
+            days_since_last_build: last_build,
+            npm_commands_executed: commands,
+        })
+    }
+
+    /// Time of the newest behavior event recorded in `project_path`
+    pub fn last_event(&self, project_path: &str) -> Result<Option<DateTime<Utc>>> {
+        let last: Option<String> = self.conn.query_row(
+            "SELECT MAX(timestamp) FROM behavior_events WHERE project_path = ?1",
+            params![self.privacy.project_key(project_path)],
+            |row| row.get(0),
+        ).map_err(db_err("Failed to get last event"))?;
+        Ok(last.as_deref().map(parse_time))
+    }
+
+    /// Rewrite recorded data to comply with `privacy`: hash project paths and
+    /// scan roots, strip command arguments or delete all behavior events, then
+    /// vacuum so the old values do not linger in free pages
+    pub fn redact(&mut self, privacy: &PrivacyConfig) -> Result<RedactionReport> {
+        let mut report = RedactionReport::default();
+        let tx = self.conn.transaction()?;
+        if privacy.disable_event_logging {
+            report.events_deleted = tx.execute("DELETE FROM behavior_events", [])?;
+        } else {
+            let events: Vec<(i64, Option<String>, Option<String>)> = tx
+                .prepare("SELECT id, command, project_path FROM behavior_events")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+                .collect::<std::result::Result<_, _>>()?;
+            let mut update = tx.prepare("UPDATE behavior_events SET command = ?2, project_path = ?3 WHERE id = ?1")?;
+            for (id, command, path) in events {
+                let new_command = command.as_deref().map(|c| privacy.command_text(c));
+                let new_path = path.as_deref().map(|p| privacy.project_key(p));
+                if new_command != command || new_path != path {
+                    update.execute(params![id, new_command, new_path])?;
+                    report.events_rewritten += 1;
+                }
+            }
+        }
+        if privacy.hash_project_paths {
+            let paths: Vec<String> = tx.prepare("SELECT path FROM projects")?
+                .query_map([], |row| row.get(0))?
+                .collect::<std::result::Result<_, _>>()?;
+            let mut update = tx.prepare("UPDATE OR REPLACE projects SET path = ?2 WHERE path = ?1")?;
+            for path in paths.iter().filter(|p| !crate::privacy::is_hashed(p)) {
+                update.execute(params![path, privacy.project_key(path)])?;
+                report.projects_rewritten += 1;
+            }
+            let runs: Vec<(i64, String)> = tx.prepare("SELECT id, roots FROM scan_runs")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<std::result::Result<_, _>>()?;
+            let mut update = tx.prepare("UPDATE scan_runs SET roots = ?2 WHERE id = ?1")?;
+            for (id, roots) in runs {
+                let parsed: Vec<String> = serde_json::from_str(&roots).unwrap_or_default();
+                let hashed: Vec<String> = parsed.iter().map(|r| privacy.project_key(r)).collect();
+                if hashed != parsed {
+                    update.execute(params![id, serde_json::to_string(&hashed).unwrap_or_default()])?;
+                    report.scans_rewritten += 1;
+                }
+            }
+            let paths: Vec<String> = tx.prepare("SELECT DISTINCT path FROM package_snapshots")?
+                .query_map([], |row| row.get(0))?
+                .collect::<std::result::Result<_, _>>()?;
+            let mut update = tx.prepare("UPDATE OR REPLACE package_snapshots SET path = ?2 WHERE path = ?1")?;
+            for path in paths.iter().filter(|p| !crate::privacy::is_hashed(p)) {
+                update.execute(params![path, privacy.project_key(path)])?;
+                report.snapshots_rewritten += 1;
+            }
+        }
+        tx.commit()?;
+        self.vacuum()?;
+        Ok(report)
+    }
+
     // =========================================================================
     // Feature Vectors
     // =========================================================================
 
-    /// Store pre-computed feature vector for a package
+    /// Store pre-computed feature vector for a package under the current
+    /// `FEATURE_VERSION`; vectors that do not fit the schema are refused
     pub fn store_features(&self, package_key: &str, features: &[f64]) -> Result<()> {
+        self.store_features_batch([(package_key, features)]).map(|_| ())
+    }
+
+    /// Store many feature vectors in one transaction; nothing is written if
+    /// any of them does not fit the schema. Returns the number stored.
+    pub fn store_features_batch<'a>(&self, vectors: impl IntoIterator<Item = (&'a str, &'a [f64])>) -> Result<usize> {
         let now = Utc::now().to_rfc3339();
-        let blob: Vec<u8> = features.iter()
-            .flat_map(|f| f.to_le_bytes())
-            .collect();
-        
+        let tx = self.conn.unchecked_transaction()?;
+        let mut stored = 0;
+        {
+            let mut upsert = tx.prepare(
+                r#"
+                INSERT INTO feature_vectors (package_key, feature_version, features, computed_at)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT(package_key) DO UPDATE SET
+                    feature_version = ?2,
+                    features = ?3,
+                    computed_at = ?4
+                "#,
+            )?;
+            for (package_key, features) in vectors {
+                validate_features(FEATURE_VERSION, features)
+                    .map_err(|drift| Error::Db(anyhow::anyhow!("Refusing to store features of {}: {}", package_key, drift)))?;
+                let blob: Vec<u8> = features.iter()
+                    .flat_map(|f| f.to_le_bytes())
+                    .collect();
+                upsert.execute(params![package_key, FEATURE_VERSION, blob, now])
+                    .map_err(db_err("Failed to store features"))?;
+                stored += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    /// Get feature vector for a package. Vectors written under another
+    /// schema are stale and read as missing, so callers recompute them.
+    pub fn get_features(&self, package_key: &str) -> Result<Option<Vec<f64>>> {
+        // Cached: planning looks up one vector per package
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT feature_version, features FROM feature_vectors WHERE package_key = ?1",
+        )?;
+        let row: Option<(u32, Vec<u8>)> = stmt.query_row(
+            params![package_key],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional().map_err(db_err("Failed to get features"))?;
+
+        Ok(row.and_then(|(version, bytes)| decode_features(version, &bytes)))
+    }
+
+    /// Every current feature vector by package key, for lookups while planning
+    pub fn load_features(&self) -> Result<std::collections::HashMap<String, Vec<f64>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT package_key, feature_version, features FROM feature_vectors WHERE feature_version = ?1",
+        )?;
+        let rows = stmt.query_map(params![FEATURE_VERSION], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?, row.get::<_, Vec<u8>>(2)?))
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(db_err("Failed to load features"))?;
+        Ok(rows.into_iter()
+            .filter_map(|(key, version, bytes)| Some((key, decode_features(version, &bytes)?)))
+            .collect())
+    }
+
+    /// Write `features` as stored under `version`, bypassing validation, for tests
+    #[cfg(test)]
+    pub(crate) fn store_features_as(&self, package_key: &str, version: u32, features: &[f64]) {
+        let blob: Vec<u8> = features.iter().flat_map(|f| f.to_le_bytes()).collect();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO feature_vectors (package_key, feature_version, features, computed_at) VALUES (?1, ?2, ?3, ?4)",
+            params![package_key, version, blob, Utc::now().to_rfc3339()],
+        ).unwrap();
+    }
+
+    // =========================================================================
+    // Scan Statistics
+    // =========================================================================
+
+    /// Log the statistics of one scan over `roots`, returning its id
+    pub fn record_scan(&self, roots: &[PathBuf], package_count: usize, package_bytes: u64, stats: &ScanStats) -> Result<i64> {
+        let started_at = stats.started_at.unwrap_or_else(Utc::now).to_rfc3339();
+        let roots = self.roots_key(roots);
+
         self.conn.execute(
             r#"
-            INSERT INTO feature_vectors (package_key, features, computed_at)
-            VALUES (?1, ?2, ?3)
-            ON CONFLICT(package_key) DO UPDATE SET
-                features = ?2,
-                computed_at = ?3
+            INSERT INTO scan_runs (started_at, roots, package_count, dirs_walked, files_stated, bytes_read,
+                                   wall_ms, cpu_ms, cache_hits, cache_misses, package_bytes)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
             "#,
-            params![package_key, blob, now],
-        ).context("Failed to store features")?;
-        
-        Ok(())
+            params![
+                started_at, roots, package_count as i64, stats.dirs_walked as i64, stats.files_stated as i64,
+                stats.bytes_read as i64, stats.wall_ms as i64, stats.cpu_ms.map(|c| c as i64),
+                stats.cache_hits as i64, stats.cache_misses as i64, package_bytes as i64,
+            ],
+        ).map_err(db_err("Failed to record scan statistics"))?;
+
+        Ok(self.conn.last_insert_rowid())
     }
 
-    /// Get feature vector for a package
-    pub fn get_features(&self, package_key: &str) -> Result<Option<Vec<f64>>> {
-        let blob: Option<Vec<u8>> = self.conn.query_row(
-            "SELECT features FROM feature_vectors WHERE package_key = ?1",
-            params![package_key],
-            |row| row.get(0),
-        ).optional().context("Failed to get features")?;
-
-        match blob {
-            Some(bytes) => {
-                let features: Vec<f64> = bytes.chunks(8)
-                    .map(|chunk| {
-                        let arr: [u8; 8] = chunk.try_into().unwrap_or([0; 8]);
-                        f64::from_le_bytes(arr)
-                    })
-                    .collect();
-                Ok(Some(features))
+    /// Wall time of the last scan over exactly `roots`
+    pub fn last_scan_ms(&self, roots: &[PathBuf]) -> Result<Option<u64>> {
+        self.conn.query_row(
+            "SELECT wall_ms FROM scan_runs WHERE roots = ?1 ORDER BY id DESC LIMIT 1",
+            params![self.roots_key(roots)],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .map(|ms| ms.map(|ms| ms as u64))
+        .map_err(db_err("Failed to get scan history"))
+    }
+
+    /// `scan_runs.roots` as stored for `roots`
+    fn roots_key(&self, roots: &[PathBuf]) -> String {
+        let roots: Vec<String> = roots.iter().map(|r| self.privacy.project_key(&r.to_string_lossy())).collect();
+        serde_json::to_string(&roots).unwrap_or_default()
+    }
+
+    /// Keep the packages found by scan `scan_id`, dropping snapshots past
+    /// retention that a newer one of the same package supersedes
+    pub fn record_snapshot(&self, scan_id: i64, packages: &[PackageSnapshot]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO package_snapshots (scan_id, path, name, version, size_bytes, atime) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for package in packages {
+                insert.execute(params![
+                    scan_id, self.privacy.project_key(&package.path), package.name, package.version,
+                    package.size_bytes as i64, package.atime.to_rfc3339(),
+                ]).map_err(db_err("Failed to record package snapshot"))?;
             }
-            None => Ok(None),
         }
+        let cutoff = (Utc::now() - chrono::Duration::days(SNAPSHOT_RETENTION_DAYS)).to_rfc3339();
+        tx.execute(
+            r#"
+            DELETE FROM package_snapshots
+            WHERE scan_id IN (SELECT id FROM scan_runs WHERE started_at < ?1)
+              AND EXISTS (SELECT 1 FROM package_snapshots newer
+                          WHERE newer.path = package_snapshots.path AND newer.scan_id > package_snapshots.scan_id)
+            "#,
+            params![cutoff],
+        ).map_err(db_err("Failed to prune package snapshots"))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The newest snapshot of every package, with the id and time of its scan
+    pub fn latest_snapshots(&self) -> Result<Vec<(PackageSnapshot, i64, DateTime<Utc>)>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT s.path, s.name, s.version, s.size_bytes, s.atime, r.started_at, s.scan_id
+            FROM package_snapshots s JOIN scan_runs r ON r.id = s.scan_id
+            WHERE s.scan_id = (SELECT MAX(scan_id) FROM package_snapshots WHERE path = s.path)
+            "#,
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let atime: String = row.get(4)?;
+            let scanned_at: String = row.get(5)?;
+            Ok((PackageSnapshot {
+                path: row.get(0)?,
+                name: row.get(1)?,
+                version: row.get(2)?,
+                size_bytes: row.get::<_, i64>(3)? as u64,
+                atime: parse_time(&atime),
+            }, row.get(6)?, parse_time(&scanned_at)))
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(db_err("Failed to get package snapshots"))?;
+        Ok(rows)
+    }
+
+    /// Size of every package in its oldest snapshot since `since`, with
+    /// that snapshot's scan id
+    pub fn baseline_sizes(&self, since: DateTime<Utc>) -> Result<std::collections::HashMap<String, (u64, i64)>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT s.path, s.size_bytes, MIN(s.scan_id)
+            FROM package_snapshots s JOIN scan_runs r ON r.id = s.scan_id
+            WHERE r.started_at >= ?1
+            GROUP BY s.path
+            "#,
+        )?;
+        let rows = stmt.query_map(params![since.to_rfc3339()], |row| {
+            Ok((row.get::<_, String>(0)?, (row.get::<_, i64>(1)? as u64, row.get::<_, i64>(2)?)))
+        })?
+        .collect::<std::result::Result<_, _>>()
+        .map_err(db_err("Failed to get package baselines"))?;
+        Ok(rows)
+    }
+
+    /// Change in the bytes held by packages under each scanned set of roots
+    /// between its first and last scan since `since`, summed over the sets.
+    /// None when no set was scanned twice.
+    pub fn footprint_growth(&self, since: DateTime<Utc>) -> Result<Option<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT roots, package_bytes FROM scan_runs WHERE started_at >= ?1 AND package_bytes IS NOT NULL ORDER BY id",
+        )?;
+        let samples: Vec<(String, i64)> = stmt
+            .query_map(params![since.to_rfc3339()], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<_, _>>()
+            .map_err(db_err("Failed to get scan footprints"))?;
+        let mut spans: std::collections::HashMap<&str, (i64, i64, usize)> = std::collections::HashMap::new();
+        for (roots, bytes) in &samples {
+            let span = spans.entry(roots.as_str()).or_insert((*bytes, *bytes, 0));
+            span.1 = *bytes;
+            span.2 += 1;
+        }
+        let repeated: Vec<i64> = spans.values().filter(|s| s.2 > 1).map(|s| s.1 - s.0).collect();
+        Ok((!repeated.is_empty()).then(|| repeated.iter().sum()))
+    }
+
+    /// Statistics of the most recent scans, newest first
+    pub fn recent_scans(&self, limit: usize) -> Result<Vec<ScanStats>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT started_at, dirs_walked, files_stated, bytes_read, wall_ms, cpu_ms, cache_hits, cache_misses
+            FROM scan_runs ORDER BY id DESC LIMIT ?1
+            "#
+        )?;
+
+        let scans = stmt.query_map(params![limit as i64], |row| {
+            let started_at: String = row.get(0)?;
+            let cpu_ms: Option<i64> = row.get(5)?;
+            let hits = row.get::<_, i64>(6)? as u64;
+            let misses = row.get::<_, i64>(7)? as u64;
+            Ok(ScanStats {
+                started_at: DateTime::parse_from_rfc3339(&started_at).ok().map(|d| d.with_timezone(&Utc)),
+                dirs_walked: row.get::<_, i64>(1)? as u64,
+                files_stated: row.get::<_, i64>(2)? as u64,
+                bytes_read: row.get::<_, i64>(3)? as u64,
+                wall_ms: row.get::<_, i64>(4)? as u64,
+                cpu_ms: cpu_ms.map(|c| c as u64),
+                cache_hits: hits,
+                cache_misses: misses,
+                cache_hit_rate: if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 },
+                dirs_skipped: 0,
+                lockfiles_reused: 0,
+                sizes_deferred: 0,
+                dirs_pruned: 0,
+                parse_ms: 0,
+                slowest_files: Vec::new(),
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(db_err("Failed to get recent scans"))?;
+
+        Ok(scans)
+    }
+
+    /// Log the LRU cache counters of an optimize run
+    pub fn record_lru_stats(&self, stats: &LruStats) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+
+        self.conn.execute(
+            r#"
+            INSERT INTO lru_runs (recorded_at, hits, misses, evictions, size_evictions, entries,
+                                  max_packages, current_bytes, budget_bytes)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            "#,
+            params![
+                now, stats.hits as i64, stats.misses as i64, stats.evictions as i64, stats.size_evictions as i64,
+                stats.entries as i64, stats.max_packages as i64, stats.current_bytes as i64, stats.budget_bytes as i64,
+            ],
+        ).map_err(db_err("Failed to record LRU statistics"))?;
+
+        Ok(())
+    }
+
+    /// LRU cache counters of the most recent optimize run
+    pub fn last_lru_stats(&self) -> Result<Option<LruStats>> {
+        let stats = self.conn.query_row(
+            r#"
+            SELECT hits, misses, evictions, size_evictions, entries, max_packages, current_bytes, budget_bytes
+            FROM lru_runs ORDER BY id DESC LIMIT 1
+            "#,
+            [],
+            |row| {
+                let hits = row.get::<_, i64>(0)? as u64;
+                let misses = row.get::<_, i64>(1)? as u64;
+                Ok(LruStats {
+                    hits,
+                    misses,
+                    evictions: row.get::<_, i64>(2)? as u64,
+                    size_evictions: row.get::<_, i64>(3)? as u64,
+                    entries: row.get::<_, i64>(4)? as usize,
+                    max_packages: row.get::<_, i64>(5)? as usize,
+                    current_bytes: row.get::<_, i64>(6)? as u64,
+                    budget_bytes: row.get::<_, i64>(7)? as u64,
+                    hit_rate: if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 },
+                })
+            },
+        ).optional().map_err(db_err("Failed to get LRU statistics"))?;
+
+        Ok(stats)
+    }
+
+    /// Log how much an applied plan reclaimed against its estimate, with
+    /// the id of the run's manifest
+    pub fn record_apply(&self, reconciliation: &ApplyReconciliation, run_id: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO apply_runs (applied_at, items, estimated_bytes, actual_bytes, run_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                Utc::now().to_rfc3339(), reconciliation.items as i64,
+                reconciliation.estimated_bytes as i64, reconciliation.actual_bytes as i64, run_id,
+            ],
+        ).map_err(db_err("Failed to record apply results"))?;
+
+        Ok(())
+    }
+
+    /// Estimate accuracy over the last `limit` apply runs (None before the first)
+    pub fn apply_accuracy(&self, limit: usize) -> Result<Option<ApplyAccuracy>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT estimated_bytes, actual_bytes FROM apply_runs ORDER BY id DESC LIMIT ?1",
+        )?;
+        let runs: Vec<(u64, u64)> = stmt
+            .query_map(params![limit as i64], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)))?
+            .collect::<std::result::Result<_, _>>()
+            .map_err(db_err("Failed to get apply results"))?;
+        let ratio = |estimated: u64, actual: u64| (estimated > 0).then(|| actual as f64 / estimated as f64);
+        let Some(&(last_estimated, last_actual)) = runs.first() else { return Ok(None) };
+        let (estimated_bytes, actual_bytes) = runs.iter().fold((0, 0), |(e, a), (re, ra)| (e + re, a + ra));
+        Ok(Some(ApplyAccuracy {
+            runs: runs.len(),
+            estimated_bytes,
+            actual_bytes,
+            accuracy: ratio(estimated_bytes, actual_bytes),
+            last_accuracy: ratio(last_estimated, last_actual),
+        }))
+    }
+
+    /// Number of applied plans since `since` and the bytes they reclaimed
+    pub fn reclaimed_since(&self, since: DateTime<Utc>) -> Result<(usize, u64)> {
+        self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(actual_bytes), 0) FROM apply_runs WHERE applied_at >= ?1",
+            params![since.to_rfc3339()],
+            |row| Ok((row.get::<_, i64>(0)? as usize, row.get::<_, i64>(1)? as u64)),
+        ).map_err(db_err("Failed to get apply results"))
+    }
+
+    // =========================================================================
+    // Archives
+    // =========================================================================
+
+    /// Record a new archive and return its id
+    pub fn record_archive(&self, archive: &ArchiveRecord) -> Result<i64> {
+        self.conn.execute(
+            r#"
+            INSERT INTO archives (project_path, location, dirs, size_bytes, archive_bytes, sha256, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+            params![
+                archive.project_path, archive.location, serde_json::to_string(&archive.dirs).unwrap_or_default(),
+                archive.size_bytes as i64, archive.archive_bytes as i64, archive.sha256,
+                archive.created_at.to_rfc3339(),
+            ],
+        ).map_err(db_err("Failed to record archive"))?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Archives, newest first; only those not yet restored unless `all`
+    pub fn list_archives(&self, all: bool) -> Result<Vec<ArchiveRecord>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT id, project_path, location, dirs, size_bytes, archive_bytes, sha256, created_at, restored_at
+            FROM archives WHERE ?1 OR restored_at IS NULL ORDER BY id DESC
+            "#
+        )?;
+
+        let parse_time = |s: String| DateTime::parse_from_rfc3339(&s).ok().map(|d| d.with_timezone(&Utc));
+        let archives = stmt.query_map(params![all], |row| {
+            let dirs: String = row.get(3)?;
+            Ok(ArchiveRecord {
+                id: row.get(0)?,
+                project_path: row.get(1)?,
+                location: row.get(2)?,
+                dirs: serde_json::from_str(&dirs).unwrap_or_default(),
+                size_bytes: row.get::<_, i64>(4)? as u64,
+                archive_bytes: row.get::<_, i64>(5)? as u64,
+                sha256: row.get(6)?,
+                created_at: parse_time(row.get(7)?).unwrap_or_else(Utc::now),
+                restored_at: row.get::<_, Option<String>>(8)?.and_then(parse_time),
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(db_err("Failed to list archives"))?;
+
+        Ok(archives)
+    }
+
+    /// Mark an archive as restored into its project
+    pub fn mark_archive_restored(&self, id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE archives SET restored_at = ?1 WHERE id = ?2",
+            params![Utc::now().to_rfc3339(), id],
+        ).map_err(db_err("Failed to update archive"))?;
+
+        Ok(())
     }
 
     // =========================================================================
@@ -355,17 +945,24 @@ impl FeatureStore {
             "SELECT COUNT(*) FROM feature_vectors", [], |row| row.get(0)
         )?;
 
+        let stale_feature_count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM feature_vectors WHERE feature_version != ?1 OR length(features) != ?2",
+            params![FEATURE_VERSION, (FEATURE_COUNT * 8) as i64],
+            |row| row.get(0),
+        )?;
+
         Ok(FeatureStoreStats {
             package_count: package_count as usize,
             project_count: project_count as usize,
             event_count: event_count as usize,
             feature_count: feature_count as usize,
+            stale_feature_count: stale_feature_count as usize,
         })
     }
 
     /// Vacuum the database to reclaim space
     pub fn vacuum(&self) -> Result<()> {
-        self.conn.execute("VACUUM", []).context("Failed to vacuum database")?;
+        self.conn.execute("VACUUM", []).map_err(db_err("Failed to vacuum database"))?;
         Ok(())
     }
 
@@ -376,12 +973,37 @@ impl FeatureStore {
         let deleted = self.conn.execute(
             "DELETE FROM behavior_events WHERE timestamp < ?1",
             params![cutoff],
-        ).context("Failed to prune old events")?;
+        ).map_err(db_err("Failed to prune old events"))?;
         
         Ok(deleted)
     }
 }
 
+/// A stored feature vector, if it fits the current schema
+fn decode_features(version: u32, bytes: &[u8]) -> Option<Vec<f64>> {
+    let features: Vec<f64> = bytes.chunks(8)
+        .map(|chunk| {
+            let arr: [u8; 8] = chunk.try_into().unwrap_or([0xff; 8]);
+            f64::from_le_bytes(arr)
+        })
+        .collect();
+    validate_features(version, &features).is_ok().then_some(features)
+}
+
+fn parse_time(text: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(text).map(|d| d.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now())
+}
+
+/// Rows `FeatureStore::redact` changed
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct RedactionReport {
+    pub events_deleted: usize,
+    pub events_rewritten: usize,
+    pub projects_rewritten: usize,
+    pub scans_rewritten: usize,
+    pub snapshots_rewritten: usize,
+}
+
 /// Statistics about the feature store
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct FeatureStoreStats {
@@ -389,6 +1011,8 @@ pub struct FeatureStoreStats {
     pub project_count: usize,
     pub event_count: usize,
     pub feature_count: usize,
+    /// Feature vectors written under another schema, recomputed on next use
+    pub stale_feature_count: usize,
 }
 
 #[cfg(test)]
@@ -445,7 +1069,7 @@ mod tests {
         
         let store = FeatureStore::open(&db_path).unwrap();
         
-        let features = vec![1.0, 2.5, 3.7, 0.0, -1.5];
+        let features = vec![1.0, 2.5, 3.7, 0.0, -1.5, 30.0, 0.8, 0.1, 365.0, 0.2];
         store.store_features("test-pkg@1.0.0", &features).unwrap();
         
         let loaded = store.get_features("test-pkg@1.0.0").unwrap().unwrap();
@@ -453,5 +1077,122 @@ mod tests {
         for (a, b) in loaded.iter().zip(features.iter()) {
             assert!((a - b).abs() < 0.0001);
         }
+
+        // Vectors that do not fit the schema are refused, older versions read as missing
+        assert!(store.store_features("short@1.0.0", &features[..5]).is_err());
+        assert!(store.store_features("nan@1.0.0", &[f64::NAN; FEATURE_COUNT]).is_err());
+        store.conn.execute("UPDATE feature_vectors SET feature_version = ?1", params![FEATURE_VERSION + 1]).unwrap();
+        assert_eq!(store.get_features("test-pkg@1.0.0").unwrap(), None);
+        assert_eq!(store.get_stats().unwrap().stale_feature_count, 1);
+        store.store_features("test-pkg@1.0.0", &features).unwrap();
+        assert_eq!(store.get_stats().unwrap().stale_feature_count, 0);
+    }
+
+    #[test]
+    fn test_run_stats() {
+        let temp = tempdir().unwrap();
+        let store = FeatureStore::open(&temp.path().join("test.db")).unwrap();
+
+        let stats = ScanStats { dirs_walked: 10, cache_hits: 3, cache_misses: 1, ..Default::default() };
+        store.record_scan(&[PathBuf::from("/repo")], 5, 1000, &stats).unwrap();
+        store.record_scan(&[PathBuf::from("/repo")], 5, 1500, &ScanStats { wall_ms: 7, ..Default::default() }).unwrap();
+
+        let recent = store.recent_scans(10).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].wall_ms, 7);
+        assert_eq!(recent[1].dirs_walked, 10);
+        assert!((recent[1].cache_hit_rate - 0.75).abs() < 1e-9);
+        let hour_ago = Utc::now() - chrono::Duration::hours(1);
+        assert_eq!(store.footprint_growth(hour_ago).unwrap(), Some(500));
+        assert_eq!(store.footprint_growth(Utc::now() + chrono::Duration::hours(1)).unwrap(), None);
+
+        assert!(store.last_lru_stats().unwrap().is_none());
+        store.record_lru_stats(&LruStats { hits: 1, misses: 1, budget_bytes: 100, ..Default::default() }).unwrap();
+        let lru = store.last_lru_stats().unwrap().unwrap();
+        assert_eq!(lru.budget_bytes, 100);
+        assert!((lru.hit_rate - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_archives() {
+        let temp = tempdir().unwrap();
+        let store = FeatureStore::open(&temp.path().join("test.db")).unwrap();
+
+        let archive = ArchiveRecord {
+            id: 0,
+            project_path: "/repo/web".into(),
+            location: "s3://cold/web.tar.gz".into(),
+            dirs: vec!["node_modules".into(), ".next".into()],
+            size_bytes: 1000,
+            archive_bytes: 300,
+            sha256: "ab".into(),
+            created_at: Utc::now(),
+            restored_at: None,
+        };
+        let id = store.record_archive(&archive).unwrap();
+        let listed = store.list_archives(false).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, id);
+        assert_eq!(listed[0].dirs, archive.dirs);
+
+        store.mark_archive_restored(id).unwrap();
+        assert!(store.list_archives(false).unwrap().is_empty());
+        assert!(store.list_archives(true).unwrap()[0].restored_at.is_some());
+    }
+
+    #[test]
+    fn test_privacy_and_redaction() {
+        let temp = tempdir().unwrap();
+        let mut store = FeatureStore::open(&temp.path().join("test.db")).unwrap();
+        store.log_event("exec", Some("npm run build --secret x"), Some("/home/ada/client")).unwrap();
+        assert_eq!(store.developer_behavior("/home/ada/client").unwrap().days_since_last_build, Some(0));
+        let scan_id = store.record_scan(&[PathBuf::from("/home/ada/client")], 1, 10, &ScanStats::default()).unwrap();
+        let snapshot = PackageSnapshot {
+            path: "/home/ada/client/node_modules/left-pad".into(),
+            name: "left-pad".into(),
+            version: "1.3.0".into(),
+            size_bytes: 10,
+            atime: Utc::now(),
+        };
+        store.record_snapshot(scan_id, &[snapshot]).unwrap();
+
+        let privacy = PrivacyConfig { hash_project_paths: true, drop_command_args: true, ..Default::default() };
+        let report = store.redact(&privacy).unwrap();
+        assert_eq!(report.events_rewritten, 1);
+        assert_eq!(report.snapshots_rewritten, 1);
+        let (snapshot, _, _) = store.latest_snapshots().unwrap().remove(0);
+        assert!(crate::privacy::is_hashed(&snapshot.path));
+        assert_eq!(snapshot.name, "left-pad");
+        let (command, path): (String, String) = store.conn
+            .query_row("SELECT command, project_path FROM behavior_events", [], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!(command, "npm");
+        assert!(crate::privacy::is_hashed(&path));
+
+        // Redacted history still counts, but no longer reveals builds
+        let mut store = store.with_privacy(privacy.clone());
+        let behavior = store.developer_behavior("/home/ada/client").unwrap();
+        assert_eq!(behavior.file_access_frequency, 1);
+        assert_eq!(behavior.days_since_last_build, None);
+        assert_eq!(store.redact(&privacy).unwrap().events_rewritten, 0);
+
+        store.privacy.disable_event_logging = true;
+        store.log_event("exec", Some("npm test"), None).unwrap();
+        assert_eq!(store.get_stats().unwrap().event_count, 1);
+        assert_eq!(store.redact(&store.privacy.clone()).unwrap().events_deleted, 1);
+    }
+
+    #[test]
+    fn test_apply_accuracy() {
+        let temp = tempdir().unwrap();
+        let store = FeatureStore::open(&temp.path().join("test.db")).unwrap();
+        assert!(store.apply_accuracy(10).unwrap().is_none());
+        for (estimated, actual) in [(1000, 800), (1000, 1200)] {
+            store.record_apply(&ApplyReconciliation { items: 1, estimated_bytes: estimated, actual_bytes: actual, ..Default::default() }, None).unwrap();
+        }
+        let accuracy = store.apply_accuracy(10).unwrap().unwrap();
+        assert_eq!((accuracy.runs, accuracy.actual_bytes), (2, 2000));
+        assert_eq!(accuracy.accuracy, Some(1.0));
+        assert_eq!(accuracy.last_accuracy, Some(1.2));
     }
 }
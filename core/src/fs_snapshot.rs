@@ -0,0 +1,382 @@
+//! Snapshot-Consistent Scans
+//!
+//! A scan walks the tree for seconds to minutes while builds and installs
+//! keep writing to it, so sizes, lockfiles and the dependency graph can come
+//! from different moments. On filesystems that can snapshot, `--snapshot`
+//! takes a read-only snapshot of every volume holding a root, scans the
+//! snapshots and maps the results back to the live paths:
+//! - Btrfs: `btrfs subvolume snapshot -r` of the mounted subvolume, placed
+//!   next to it (nested subvolumes appear empty in the snapshot)
+//! - ZFS: `zfs snapshot`, read through the dataset's `.zfs/snapshot`
+//! - LVM: a `lvcreate --snapshot` volume mounted read-only under the state
+//!   directory
+//! - Windows: a VSS shadow copy, read through its device path
+//!
+//! Applying with `--snapshot` hashes each target in the snapshot before it
+//! is moved, so the quarantine checksum is that of one stable state. Taking
+//! snapshots needs the privileges the tools above need. Snapshot scans skip
+//! the incremental cache, whose entries are keyed by live paths.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::Error;
+use crate::progress::OperationContext;
+use crate::scan_cache::CacheValidation;
+use crate::types::ScanOutput;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Scan and hash in snapshots for the rest of the process (`--snapshot`)
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether scans and applies work from snapshots, by `enable` or
+/// `PACKAGEPURGE_SNAPSHOT`
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+        || std::env::var("PACKAGEPURGE_SNAPSHOT").is_ok_and(|v| !v.is_empty() && v != "0" && v != "false")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotKind {
+    Btrfs,
+    Zfs,
+    Lvm,
+    Vss,
+}
+
+/// A mounted filesystem that can be snapshotted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Volume {
+    pub kind: SnapshotKind,
+    pub mount_point: PathBuf,
+    /// ZFS dataset, LVM `vg/lv`, Btrfs device or Windows volume
+    pub source: String,
+    pub fstype: String,
+}
+
+/// Undo the octal escapes (`\040` for a space) of a mountinfo field
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u8::from_str_radix(d, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(b)) => {
+                out.push(b);
+                i += 4;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// `vg/lv` of a device-mapper or `/dev/<vg>/<lv>` LVM device. Device-mapper
+/// names double the dashes inside volume and group names.
+fn lvm_name(source: &str) -> Option<String> {
+    if let Some(name) = source.strip_prefix("/dev/mapper/") {
+        let name = name.replace("--", "\0");
+        let (vg, lv) = name.split_once('-')?;
+        return Some(format!("{}/{}", vg.replace('\0', "-"), lv.replace('\0', "-")));
+    }
+    let rest = source.strip_prefix("/dev/")?;
+    let (vg, lv) = rest.split_once('/')?;
+    (!lv.contains('/') && !vg.is_empty() && !lv.is_empty()).then(|| format!("{}/{}", vg, lv))
+}
+
+/// The volume holding `path`, from the text of `/proc/self/mountinfo`
+pub fn volume_in(path: &Path, mountinfo: &str) -> Option<Volume> {
+    let mut best: Option<(PathBuf, String, String)> = None;
+    for line in mountinfo.lines() {
+        let Some((mount, fs)) = line.split_once(" - ") else { continue };
+        let Some(mount_point) = mount.split(' ').nth(4).map(|m| PathBuf::from(unescape(m))) else { continue };
+        let mut fs = fs.split(' ');
+        let (Some(fstype), Some(source)) = (fs.next(), fs.next()) else { continue };
+        // Later mounts over the same point hide earlier ones
+        if path.starts_with(&mount_point) && best.as_ref().is_none_or(|(b, _, _)| mount_point.as_os_str().len() >= b.as_os_str().len()) {
+            best = Some((mount_point, fstype.to_string(), unescape(source)));
+        }
+    }
+    let (mount_point, fstype, source) = best?;
+    let kind = match fstype.as_str() {
+        "btrfs" => SnapshotKind::Btrfs,
+        "zfs" => SnapshotKind::Zfs,
+        "ext2" | "ext3" | "ext4" | "xfs" => {
+            let name = lvm_name(&source)?;
+            return Some(Volume { kind: SnapshotKind::Lvm, mount_point, source: name, fstype });
+        }
+        _ => return None,
+    };
+    Some(Volume { kind, mount_point, source, fstype })
+}
+
+/// The volume holding `path`, when its filesystem can be snapshotted
+pub fn volume_of(path: &Path) -> Option<Volume> {
+    if cfg!(windows) {
+        let drive = path.components().next()?;
+        let std::path::Component::Prefix(prefix) = drive else { return None };
+        let root = format!("{}\\", prefix.as_os_str().to_string_lossy());
+        return Some(Volume { kind: SnapshotKind::Vss, mount_point: PathBuf::from(&root), source: root, fstype: "ntfs".to_string() });
+    }
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+    volume_in(path, &mountinfo)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program).args(args).output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!("{} {} failed: {}", program, args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn powershell(script: &str) -> Result<String> {
+    run("powershell", &["-NoProfile", "-NonInteractive", "-Command", script])
+}
+
+/// A read-only snapshot of a volume, released when dropped
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    pub volume: Volume,
+    /// Snapshot name, or the shadow copy ID for VSS
+    pub name: String,
+    /// Where the snapshot's view of `volume.mount_point` is readable
+    pub view: PathBuf,
+    #[serde(skip)]
+    released: bool,
+}
+
+impl Snapshot {
+    /// Snapshot `volume`
+    pub fn take(volume: &Volume) -> crate::Result<Self> {
+        take_impl(volume).map_err(Error::lift(Error::Snapshot))
+    }
+
+    /// Where `live` (a path on the volume) is found in the snapshot
+    pub fn view_of(&self, live: &Path) -> Option<PathBuf> {
+        let rel = live.strip_prefix(&self.volume.mount_point).ok()?;
+        Some(if rel.as_os_str().is_empty() { self.view.clone() } else { self.view.join(rel) })
+    }
+
+    /// The live path that `view` (a path in the snapshot) shows
+    pub fn live_of(&self, view: &Path) -> Option<PathBuf> {
+        let rel = view.strip_prefix(&self.view).ok()?;
+        Some(if rel.as_os_str().is_empty() { self.volume.mount_point.clone() } else { self.volume.mount_point.join(rel) })
+    }
+
+    /// Delete the snapshot
+    pub fn release(mut self) -> crate::Result<()> {
+        self.released = true;
+        release_impl(&self).map_err(Error::lift(Error::Snapshot))
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if !self.released {
+            if let Err(e) = release_impl(self) {
+                eprintln!("Warning: Failed to release snapshot {}: {:#}", self.name, e);
+            }
+        }
+    }
+}
+
+fn take_impl(volume: &Volume) -> Result<Snapshot> {
+    let name = format!("packagepurge-{}", Utc::now().format("%Y%m%d%H%M%S%f"));
+    let mount = volume.mount_point.to_string_lossy().to_string();
+    let view = match volume.kind {
+        SnapshotKind::Btrfs => {
+            let view = volume.mount_point.join(format!(".{}", name));
+            run("btrfs", &["subvolume", "snapshot", "-r", &mount, &view.to_string_lossy()])?;
+            view
+        }
+        SnapshotKind::Zfs => {
+            run("zfs", &["snapshot", &format!("{}@{}", volume.source, name)])?;
+            volume.mount_point.join(".zfs/snapshot").join(&name)
+        }
+        SnapshotKind::Lvm => {
+            let (vg, _) = volume.source.split_once('/').context("LVM volume without a volume group")?;
+            run("lvcreate", &["--snapshot", "--name", &name, "--extents", "10%ORIGIN", &volume.source])?;
+            let view = crate::paths::state_dir().join("snapshots").join(&name);
+            let device = format!("/dev/{}/{}", vg, name);
+            // XFS refuses a second mount of the same filesystem UUID
+            let options = if volume.fstype == "xfs" { "ro,nouuid" } else { "ro" };
+            let mounted = std::fs::create_dir_all(&view).map_err(anyhow::Error::from)
+                .and_then(|_| run("mount", &["-o", options, &device, &view.to_string_lossy()]));
+            if let Err(e) = mounted {
+                let _ = run("lvremove", &["--yes", &format!("{}/{}", vg, name)]);
+                let _ = std::fs::remove_dir(&view);
+                return Err(e);
+            }
+            view
+        }
+        SnapshotKind::Vss => {
+            let script = format!(
+                "$r = Invoke-CimMethod -ClassName Win32_ShadowCopy -MethodName Create -Arguments @{{Volume='{}'}}; \
+                 if ($r.ReturnValue -ne 0) {{ throw \"shadow copy failed: $($r.ReturnValue)\" }}; \
+                 $s = Get-CimInstance Win32_ShadowCopy -Filter \"ID='$($r.ShadowID)'\"; \
+                 Write-Output $s.ID; Write-Output $s.DeviceObject",
+                mount,
+            );
+            let out = powershell(&script)?;
+            let mut lines = out.lines().map(str::trim).filter(|l| !l.is_empty());
+            let (Some(id), Some(device)) = (lines.next(), lines.next()) else {
+                anyhow::bail!("Unexpected shadow copy output: {}", out.trim());
+            };
+            return Ok(Snapshot { volume: volume.clone(), name: id.to_string(), view: PathBuf::from(format!("{}\\", device)), released: false });
+        }
+    };
+    anyhow::ensure!(view.is_dir(), "Snapshot {} is not readable at {:?}", name, view);
+    Ok(Snapshot { volume: volume.clone(), name, view, released: false })
+}
+
+fn release_impl(snapshot: &Snapshot) -> Result<()> {
+    let view = snapshot.view.to_string_lossy().to_string();
+    match snapshot.volume.kind {
+        SnapshotKind::Btrfs => run("btrfs", &["subvolume", "delete", &view]).map(drop),
+        SnapshotKind::Zfs => run("zfs", &["destroy", &format!("{}@{}", snapshot.volume.source, snapshot.name)]).map(drop),
+        SnapshotKind::Lvm => {
+            run("umount", &[&view])?;
+            let _ = std::fs::remove_dir(&snapshot.view);
+            let vg = snapshot.volume.source.split('/').next().unwrap_or_default();
+            run("lvremove", &["--yes", &format!("{}/{}", vg, snapshot.name)]).map(drop)
+        }
+        SnapshotKind::Vss => powershell(&format!(
+            "Get-CimInstance Win32_ShadowCopy -Filter \"ID='{}'\" | Remove-CimInstance",
+            snapshot.name,
+        )).map(drop),
+    }
+}
+
+/// Snapshot every volume holding one of `roots`. Fails if a root's
+/// filesystem cannot be snapshotted.
+pub fn snapshot_roots(roots: &[PathBuf]) -> crate::Result<Vec<Snapshot>> {
+    let mut snapshots: Vec<Snapshot> = Vec::new();
+    for root in roots {
+        let root = root.canonicalize().unwrap_or_else(|_| root.clone());
+        if snapshots.iter().any(|s| s.view_of(&root).is_some()) {
+            continue;
+        }
+        let volume = volume_of(&root).ok_or_else(|| Error::Snapshot(anyhow::anyhow!(
+            "{:?} is not on a Btrfs, ZFS, LVM or VSS volume", root,
+        )))?;
+        snapshots.push(Snapshot::take(&volume)?);
+    }
+    Ok(snapshots)
+}
+
+/// Map every path in `scan` from the snapshots back to the live tree
+fn to_live(scan: &mut ScanOutput, snapshots: &[Snapshot]) {
+    let live = |path: &mut String| {
+        if let Some(p) = snapshots.iter().find_map(|s| s.live_of(Path::new(path.as_str()))) {
+            *path = p.to_string_lossy().to_string();
+        }
+    };
+    for package in &mut scan.packages {
+        live(&mut package.path);
+        package.project_paths.iter_mut().for_each(live);
+    }
+    for project in &mut scan.projects {
+        live(&mut project.path);
+    }
+    for (from, to) in &mut scan.edges {
+        live(from);
+        live(to);
+    }
+    scan.deferred_sizes.iter_mut().for_each(live);
+    for dir in &mut scan.marked_dirs {
+        live(&mut dir.path);
+    }
+}
+
+/// Scan `paths` (the working directory when empty) in snapshots of their
+/// volumes, reporting live paths. The snapshots are released afterwards.
+pub fn scan(paths: &[PathBuf], validation: CacheValidation, ctx: &OperationContext) -> crate::Result<ScanOutput> {
+    let roots: Vec<PathBuf> = if paths.is_empty() {
+        vec![std::env::current_dir().map_err(|e| Error::Snapshot(e.into()))?]
+    } else {
+        paths.to_vec()
+    };
+    let snapshots = snapshot_roots(&roots)?;
+    let views: Vec<PathBuf> = roots.iter()
+        .map(|r| r.canonicalize().unwrap_or_else(|_| r.clone()))
+        .filter_map(|r| snapshots.iter().find_map(|s| s.view_of(&r)))
+        .collect();
+    let mut scan = crate::scanner::scan_validated(&views, false, validation, ctx)?;
+    to_live(&mut scan, &snapshots);
+    for snapshot in snapshots {
+        snapshot.release()?;
+    }
+    Ok(scan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw
+40 22 0:35 /@home /home rw,relatime shared:20 - btrfs /dev/nvme0n1p3 rw,subvol=/@home
+41 22 0:40 / /srv/data rw shared:21 - zfs tank/data rw,xattr
+42 22 253:1 / /var/build\\040cache rw shared:22 - xfs /dev/mapper/ci--vg-build--lv rw
+43 22 0:22 / /tmp rw shared:23 - tmpfs tmpfs rw
+";
+
+    #[test]
+    fn test_volumes_from_mountinfo() {
+        let home = volume_in(Path::new("/home/ada/dev"), MOUNTINFO).unwrap();
+        assert_eq!((home.kind, home.mount_point.as_path()), (SnapshotKind::Btrfs, Path::new("/home")));
+
+        let zfs = volume_in(Path::new("/srv/data/app"), MOUNTINFO).unwrap();
+        assert_eq!((zfs.kind, zfs.source.as_str()), (SnapshotKind::Zfs, "tank/data"));
+
+        let lvm = volume_in(Path::new("/var/build cache/x"), MOUNTINFO).unwrap();
+        assert_eq!((lvm.kind, lvm.source.as_str(), lvm.fstype.as_str()), (SnapshotKind::Lvm, "ci-vg/build-lv", "xfs"));
+
+        // Plain partitions and tmpfs cannot be snapshotted
+        assert!(volume_in(Path::new("/opt/app"), MOUNTINFO).is_none());
+        assert!(volume_in(Path::new("/tmp/x"), MOUNTINFO).is_none());
+        assert_eq!(lvm_name("/dev/vg0/root").as_deref(), Some("vg0/root"));
+    }
+
+    #[test]
+    fn test_view_and_live_paths() {
+        let snapshot = Snapshot {
+            volume: Volume { kind: SnapshotKind::Zfs, mount_point: "/srv/data".into(), source: "tank/data".into(), fstype: "zfs".into() },
+            name: "packagepurge-1".into(),
+            view: "/srv/data/.zfs/snapshot/packagepurge-1".into(),
+            // Nothing to release in a test
+            released: true,
+        };
+        let view = snapshot.view_of(Path::new("/srv/data/app/node_modules")).unwrap();
+        assert_eq!(view, Path::new("/srv/data/.zfs/snapshot/packagepurge-1/app/node_modules"));
+        assert_eq!(snapshot.live_of(&view).unwrap(), Path::new("/srv/data/app/node_modules"));
+        assert!(snapshot.view_of(Path::new("/home/ada")).is_none());
+
+        let mut scan = ScanOutput {
+            packages: Vec::new(),
+            projects: Vec::new(),
+            edges: vec![("/srv/data/.zfs/snapshot/packagepurge-1/app".into(), view.to_string_lossy().to_string())],
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        to_live(&mut scan, std::slice::from_ref(&snapshot));
+        assert_eq!(scan.edges[0], ("/srv/data/app".to_string(), "/srv/data/app/node_modules".to_string()));
+    }
+}
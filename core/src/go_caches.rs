@@ -0,0 +1,144 @@
+//! Go Module Cache
+//!
+//! Go extracts every module version it downloads into the module cache
+//! (`$GOMODCACHE`, by default `$GOPATH/pkg/mod` or `~/go/pkg/mod`) as
+//! `<module>@<version>`, upper-case letters of the module path escaped as
+//! `!` plus the lower-case letter. Each extracted version is enumerated as a
+//! package, and each `go.sum` makes its directory a project depending on the
+//! module versions it checksums.
+//!
+//! Versions no scanned `go.sum` lists are planned. The download cache
+//! (`cache/download`) is left alone: it keeps the zip of every version, so Go
+//! extracts a removed one again without going to the network.
+//!
+//! Go makes extracted trees read-only; quarantine opens them up to move them
+//! and seals them again on rollback (see `safety`).
+
+use chrono::Utc;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::types::{PackageManager, PackageRecord, PlanReason};
+
+/// The module cache Go itself would use
+pub fn mod_cache_dir() -> Option<PathBuf> {
+    let env = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty());
+    if let Some(dir) = env("GOMODCACHE") {
+        return Some(PathBuf::from(dir));
+    }
+    let gopath = env("GOPATH")
+        .and_then(|p| std::env::split_paths(&p).next())
+        .or_else(|| dirs::home_dir().map(|h| h.join("go")))?;
+    Some(gopath.join("pkg").join("mod"))
+}
+
+/// Whether `path` is a Go module cache
+pub fn is_mod_cache(path: &Path) -> bool {
+    let layout = path.file_name().is_some_and(|n| n == "mod")
+        && path.parent().and_then(|p| p.file_name()).is_some_and(|n| n == "pkg");
+    // Looked up once: the walker asks for every directory
+    static CONFIGURED: OnceLock<Option<PathBuf>> = OnceLock::new();
+    let configured = CONFIGURED.get_or_init(mod_cache_dir).as_deref() == Some(path);
+    (layout || configured) && path.join("cache").join("download").is_dir()
+}
+
+/// Whether `path` lies inside a Go module cache
+pub fn in_mod_cache(path: &Path) -> bool {
+    path.ancestors().skip(1).any(is_mod_cache)
+}
+
+/// Undo the module cache's case escaping: `github.com/!azure/go` is
+/// `github.com/Azure/go`
+pub fn unescape(escaped: &str) -> String {
+    let mut out = String::with_capacity(escaped.len());
+    let mut upper = false;
+    for c in escaped.chars() {
+        if c == '!' {
+            upper = true;
+        } else if upper {
+            out.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Extracted module versions of a module cache: (directory, module, version, manager)
+pub fn go_packages(cache: &Path) -> Vec<(PathBuf, String, String, PackageManager)> {
+    let mut packages = Vec::new();
+    let mut walker = walkdir::WalkDir::new(cache).min_depth(1).into_iter();
+    while let Some(entry) = walker.next() {
+        let Ok(entry) = entry else { continue };
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        if entry.depth() == 1 && entry.file_name() == "cache" {
+            walker.skip_current_dir();
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy();
+        let Some((last, version)) = name.split_once('@') else { continue };
+        walker.skip_current_dir();
+        let parent = entry.path().parent().and_then(|p| p.strip_prefix(cache).ok()).unwrap_or(Path::new(""));
+        let escaped = parent.join(last).to_string_lossy().replace('\\', "/");
+        packages.push((entry.path().to_path_buf(), unescape(&escaped), version.to_string(), PackageManager::Go));
+    }
+    packages
+}
+
+/// Module versions a `go.sum` checksums, as (module, version); versions
+/// listed for their `go.mod` only were never extracted for the build
+pub fn parse_go_sum(path: &Path) -> Vec<(String, String)> {
+    let Ok(text) = fs::read_to_string(path) else { return Vec::new() };
+    let mut sums: Vec<(String, String)> = text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (module, version) = (fields.next()?, fields.next()?);
+            (!version.ends_with("/go.mod")).then(|| (module.to_string(), version.to_string()))
+        })
+        .collect();
+    sums.dedup();
+    sums
+}
+
+/// Why `pkg` (an extracted module version) is planned, if it is: when no
+/// scanned `go.sum` lists it (`locked` false)
+pub fn plan_reason(pkg: &PackageRecord, locked: bool) -> Option<PlanReason> {
+    let days = (Utc::now() - pkg.mtime).num_days();
+    (!locked).then_some(PlanReason::UnusedGoModule { days })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_go_packages_and_sums() {
+        assert_eq!(unescape("github.com/!azure/azure-sdk-for-go"), "github.com/Azure/azure-sdk-for-go");
+
+        let temp = tempdir().unwrap();
+        let cache = temp.path().join("go/pkg/mod");
+        for dir in [
+            "cache/download/github.com/pkg/errors/@v",
+            "github.com/pkg/errors@v0.9.1/internal",
+            "github.com/!burnt!sushi/toml@v1.3.2",
+            "golang.org/x/sys@v0.15.0/unix",
+        ] {
+            fs::create_dir_all(cache.join(dir)).unwrap();
+        }
+        assert!(is_mod_cache(&cache));
+        assert!(in_mod_cache(&cache.join("github.com/pkg/errors@v0.9.1")));
+
+        let mut modules: Vec<String> = go_packages(&cache).into_iter().map(|(_, m, v, _)| format!("{}@{}", m, v)).collect();
+        modules.sort();
+        assert_eq!(modules, ["github.com/BurntSushi/toml@v1.3.2", "github.com/pkg/errors@v0.9.1", "golang.org/x/sys@v0.15.0"]);
+
+        let sum = temp.path().join("go.sum");
+        fs::write(&sum, "github.com/pkg/errors v0.9.1 h1:abc=\ngithub.com/pkg/errors v0.9.1/go.mod h1:def=\ngolang.org/x/sys v0.14.0/go.mod h1:ghi=\n").unwrap();
+        assert_eq!(parse_go_sum(&sum), [("github.com/pkg/errors".to_string(), "v0.9.1".to_string())]);
+    }
+}
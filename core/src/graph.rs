@@ -0,0 +1,304 @@
+//! Dependency Graph Export
+//!
+//! Builds the project→package / package→package DAG from a scan and renders
+//! it as Graphviz DOT or GraphML. Package nodes carry their size and whether
+//! they are stale (not accessed within the preserve window), which makes it
+//! visible why a large transitive subtree is retained.
+
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::types::ScanOutput;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Project,
+    Package,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    /// Filesystem path, unique per node
+    pub id: String,
+    /// `name@version` for packages, the directory name for projects
+    pub label: String,
+    pub kind: NodeKind,
+    pub size_bytes: u64,
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    /// (from id, to id)
+    pub edges: Vec<(String, String)>,
+}
+
+impl DependencyGraph {
+    /// Build the graph from scan output; packages not accessed for
+    /// `preserve_days` are marked stale
+    pub fn from_scan(scan: &ScanOutput, preserve_days: i64) -> Self {
+        let cutoff = Utc::now() - Duration::days(preserve_days);
+        let mut nodes: Vec<GraphNode> = scan.projects.iter().map(|p| GraphNode {
+            id: p.path.clone(),
+            label: std::path::Path::new(&p.path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| p.path.clone()),
+            kind: NodeKind::Project,
+            size_bytes: 0,
+            stale: false,
+        }).collect();
+        nodes.extend(scan.packages.iter().map(|p| GraphNode {
+            id: p.path.clone(),
+            label: format!("{}@{}", p.name, p.version),
+            kind: NodeKind::Package,
+            size_bytes: p.size_bytes,
+            stale: p.atime.max(p.mtime) < cutoff,
+        }));
+
+        let ids: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+        let edges = scan.edges.iter()
+            .filter(|(from, to)| ids.contains(from.as_str()) && ids.contains(to.as_str()))
+            .cloned()
+            .collect();
+        Self { nodes, edges }
+    }
+
+    /// Restrict the graph to `root` and everything reachable from it
+    pub fn reachable_from(&self, root: &str) -> Self {
+        let mut adjacency: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (from, to) in &self.edges {
+            adjacency.entry(from.as_str()).or_default().push(to.as_str());
+        }
+
+        let mut keep: HashSet<&str> = HashSet::new();
+        let mut queue: VecDeque<&str> = VecDeque::new();
+        if self.nodes.iter().any(|n| n.id == root) {
+            keep.insert(root);
+            queue.push_back(root);
+        }
+        while let Some(id) = queue.pop_front() {
+            for next in adjacency.get(id).into_iter().flatten() {
+                if keep.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        Self {
+            nodes: self.nodes.iter().filter(|n| keep.contains(n.id.as_str())).cloned().collect(),
+            edges: self.edges.iter()
+                .filter(|(from, to)| keep.contains(from.as_str()) && keep.contains(to.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Render as Graphviz DOT. Projects are boxes, stale packages are grey.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph dependencies {\n    rankdir=LR;\n");
+        for n in &self.nodes {
+            let shape = match n.kind {
+                NodeKind::Project => "box",
+                NodeKind::Package => "ellipse",
+            };
+            let label = match n.kind {
+                NodeKind::Project => dot_escape(&n.label),
+                NodeKind::Package => format!("{}\\n{} bytes", dot_escape(&n.label), n.size_bytes),
+            };
+            let style = if n.stale { ", style=filled, fillcolor=lightgrey" } else { "" };
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{}\", shape={}, size_bytes={}, stale={}{}];\n",
+                dot_escape(&n.id), label, shape, n.size_bytes, n.stale, style
+            ));
+        }
+        for (from, to) in &self.edges {
+            out.push_str(&format!("    \"{}\" -> \"{}\";\n", dot_escape(from), dot_escape(to)));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Render as GraphML with `label`, `kind`, `size_bytes` and `stale` node data
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <key id=\"size_bytes\" for=\"node\" attr.name=\"size_bytes\" attr.type=\"long\"/>\n",
+            "  <key id=\"stale\" for=\"node\" attr.name=\"stale\" attr.type=\"boolean\"/>\n",
+            "  <graph id=\"dependencies\" edgedefault=\"directed\">\n",
+        ));
+        for n in &self.nodes {
+            let kind = match n.kind {
+                NodeKind::Project => "project",
+                NodeKind::Package => "package",
+            };
+            out.push_str(&format!(
+                "    <node id=\"{}\">\n      <data key=\"label\">{}</data>\n      <data key=\"kind\">{}</data>\n      <data key=\"size_bytes\">{}</data>\n      <data key=\"stale\">{}</data>\n    </node>\n",
+                xml_escape(&n.id), xml_escape(&n.label), kind, n.size_bytes, n.stale
+            ));
+        }
+        for (i, (from, to)) in self.edges.iter().enumerate() {
+            out.push_str(&format!(
+                "    <edge id=\"e{}\" source=\"{}\" target=\"{}\"/>\n",
+                i, xml_escape(from), xml_escape(to)
+            ));
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+/// A project or package that (transitively) requires the queried package
+#[derive(Debug, Clone, Serialize)]
+pub struct Dependent {
+    pub path: String,
+    pub label: String,
+    pub kind: NodeKind,
+    /// Path of the dependency this node requires on the way to the target
+    pub requires: String,
+    /// Number of edges between this node and the target (1 = direct)
+    pub depth: usize,
+}
+
+/// Split `name@version` (scoped names keep their leading `@`). The version
+/// part is optional.
+pub fn parse_package_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.rfind('@') {
+        Some(i) if i > 0 => (&spec[..i], Some(&spec[i + 1..])),
+        _ => (spec, None),
+    }
+}
+
+impl DependencyGraph {
+    /// Walk the edges backwards from every package matching `name` (and
+    /// `version`, if given) and list each project and package requiring it,
+    /// nearest first
+    pub fn dependents(&self, name: &str, version: Option<&str>) -> Vec<Dependent> {
+        let targets: Vec<&GraphNode> = self.nodes.iter()
+            .filter(|n| n.kind == NodeKind::Package)
+            .filter(|n| {
+                let (n_name, n_version) = parse_package_spec(&n.label);
+                n_name == name && (version.is_none() || n_version == version)
+            })
+            .collect();
+
+        let mut reverse: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (from, to) in &self.edges {
+            reverse.entry(to.as_str()).or_default().push(from.as_str());
+        }
+        let by_id: HashMap<&str, &GraphNode> = self.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+
+        let mut seen: HashSet<&str> = targets.iter().map(|n| n.id.as_str()).collect();
+        let mut queue: VecDeque<(&str, usize)> = targets.iter().map(|n| (n.id.as_str(), 0)).collect();
+        let mut out = Vec::new();
+        while let Some((id, depth)) = queue.pop_front() {
+            for parent in reverse.get(id).into_iter().flatten() {
+                if !seen.insert(parent) {
+                    continue;
+                }
+                if let Some(node) = by_id.get(parent) {
+                    out.push(Dependent {
+                        path: node.id.clone(),
+                        label: node.label.clone(),
+                        kind: node.kind,
+                        requires: id.to_string(),
+                        depth: depth + 1,
+                    });
+                }
+                queue.push_back((parent, depth + 1));
+            }
+        }
+        out
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PackageRecord, ProjectRecord};
+
+    fn package(path: &str, name: &str, days_old: i64) -> PackageRecord {
+        let when = Utc::now() - Duration::days(days_old);
+        PackageRecord { name: name.to_string(), atime: when, mtime: when, ..PackageRecord::for_test(path, "1.0.0", 100) }
+    }
+
+    fn sample() -> ScanOutput {
+        ScanOutput {
+            packages: vec![
+                package("/app/node_modules/a", "a", 1),
+                package("/app/node_modules/b", "b", 200),
+                package("/other/node_modules/c", "c", 1),
+            ],
+            projects: vec![
+                ProjectRecord { path: "/app".into(), manager: None, dependencies: Vec::new(), mtime: Utc::now() },
+                ProjectRecord { path: "/other".into(), manager: None, dependencies: Vec::new(), mtime: Utc::now() },
+            ],
+            edges: vec![
+                ("/app".into(), "/app/node_modules/a".into()),
+                ("/app/node_modules/a".into(), "/app/node_modules/b".into()),
+                ("/other".into(), "/other/node_modules/c".into()),
+            ],
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_reachable_from_project() {
+        let graph = DependencyGraph::from_scan(&sample(), 90).reachable_from("/app");
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 2);
+        assert!(graph.nodes.iter().find(|n| n.label == "b@1.0.0").unwrap().stale);
+        assert!(!graph.nodes.iter().any(|n| n.id == "/other"));
+    }
+
+    #[test]
+    fn test_parse_package_spec() {
+        assert_eq!(parse_package_spec("lodash@4.17.21"), ("lodash", Some("4.17.21")));
+        assert_eq!(parse_package_spec("@babel/core@7.0.0"), ("@babel/core", Some("7.0.0")));
+        assert_eq!(parse_package_spec("@babel/core"), ("@babel/core", None));
+    }
+
+    #[test]
+    fn test_dependents_walks_backwards() {
+        let graph = DependencyGraph::from_scan(&sample(), 90);
+        let deps = graph.dependents("b", Some("1.0.0"));
+        let found: Vec<(&str, usize)> = deps.iter().map(|d| (d.path.as_str(), d.depth)).collect();
+        assert_eq!(found, vec![("/app/node_modules/a", 1), ("/app", 2)]);
+        assert_eq!(deps[1].kind, NodeKind::Project);
+
+        assert!(graph.dependents("b", Some("2.0.0")).is_empty());
+        assert_eq!(graph.dependents("c", None).len(), 1);
+    }
+
+    #[test]
+    fn test_render_formats() {
+        let graph = DependencyGraph::from_scan(&sample(), 90);
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.contains("\"/app\" -> \"/app/node_modules/a\";"));
+        assert!(dot.contains("a@1.0.0\\n100 bytes"));
+
+        let xml = graph.to_graphml();
+        assert!(xml.contains("<edge id=\"e0\" source=\"/app\" target=\"/app/node_modules/a\"/>"));
+        assert!(xml.contains("<data key=\"stale\">true</data>"));
+    }
+}
@@ -0,0 +1,560 @@
+//! Background Hashing Queue - SQLite-backed queue of deferred hashing work
+//!
+//! Hashing reads every byte of a tree, which is what makes quarantine,
+//! store checks and integrity verification slow. Interactive commands queue
+//! that work instead:
+//! - `apply --fast` / `quarantine --fast` queue the checksum of each
+//!   quarantine entry, filled into its record once computed
+//! - `store hash` queues a content hash of every global store entry; an
+//!   entry whose content differs from its previous hash fails
+//! - `verify --background` queues the lockfile integrity check of every
+//!   scanned package
+//! - `installed` queues a rescan of a project a package manager just
+//!   installed into (see `install_events`)
+//!
+//! `hash-queue run` works through the queue with reads throttled to a byte
+//! rate. Directory hashes checkpoint the files done so far, so a worker that
+//! is stopped or killed resumes where it left off; jobs whose worker has
+//! not reported for `STALE_AFTER` are taken over. There is no resident
+//! daemon: `hash-queue run --watch` is the long-running worker, meant to be
+//! started by a systemd user service, launchd agent or scheduled task.
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::error::{db_err, Error, Result};
+use crate::integrity::{IntegrityChecker, IntegrityStatus};
+use crate::progress::OperationContext;
+use crate::scan_cache::CacheValidation;
+use crate::timings::{self, TimedPhase};
+use crate::types::PackageRecord;
+
+/// A running job whose worker has been silent this long is taken over
+const STALE_AFTER: Duration = Duration::minutes(5);
+/// Bytes hashed between progress checkpoints
+const CHECKPOINT_BYTES: u64 = 64 * 1024 * 1024;
+/// Read size while hashing a file
+const CHUNK: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Checksum of a quarantine entry; the payload is the record id
+    QuarantineChecksum,
+    /// Content hash of a global store entry
+    StoreEntry,
+    /// Lockfile integrity check; the payload is the package record
+    VerifyPackage,
+    /// Cached rescan of a project after an install
+    RefreshProject,
+}
+
+impl JobKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::QuarantineChecksum => "quarantine_checksum",
+            JobKind::StoreEntry => "store_entry",
+            JobKind::VerifyPackage => "verify_package",
+            JobKind::RefreshProject => "refresh_project",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "quarantine_checksum" => Some(JobKind::QuarantineChecksum),
+            "store_entry" => Some(JobKind::StoreEntry),
+            "verify_package" => Some(JobKind::VerifyPackage),
+            "refresh_project" => Some(JobKind::RefreshProject),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HashJob {
+    pub id: i64,
+    pub kind: JobKind,
+    pub path: String,
+    #[serde(skip)]
+    pub payload: Option<String>,
+    /// queued, running, done or failed
+    pub status: String,
+    pub bytes_done: u64,
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub queued_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Jobs per status and bytes hashed so far
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueueStatus {
+    pub queued: usize,
+    pub running: usize,
+    pub done: usize,
+    pub failed: usize,
+    pub bytes_done: u64,
+}
+
+/// Files of a directory hashed so far, in walk order
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirProgress {
+    /// (path relative to the directory, SHA256 of its content)
+    pub files: Vec<(String, String)>,
+    pub bytes: u64,
+}
+
+/// Sleeps as needed to keep reads at or under a byte rate
+pub struct RateLimiter {
+    bytes_per_sec: Option<u64>,
+    started: Instant,
+    consumed: u64,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self { bytes_per_sec: bytes_per_sec.filter(|&r| r > 0), started: Instant::now(), consumed: 0 }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None)
+    }
+
+    /// Account for `bytes` read, sleeping until the rate allows them
+    pub fn consume(&mut self, bytes: u64) {
+        self.consumed += bytes;
+        let Some(rate) = self.bytes_per_sec else { return };
+        let due = std::time::Duration::from_secs_f64(self.consumed as f64 / rate as f64);
+        if let Some(wait) = due.checked_sub(self.started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+fn hash_file(path: &Path, limiter: &mut RateLimiter) -> anyhow::Result<(String, u64)> {
+    let mut file = fs::File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; CHUNK];
+    let mut total = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        total += n as u64;
+        limiter.consume(n as u64);
+    }
+    Ok((hex::encode(hasher.finalize()), total))
+}
+
+/// SHA256 of a directory tree: of every file's relative path and content
+/// hash, in name order, so a copy of the tree anywhere hashes the same.
+/// Files already in `progress` (from an interrupted run over the same
+/// tree) are not read again; `checkpoint` is handed the progress every
+/// `CHECKPOINT_BYTES`.
+pub fn hash_dir(
+    dir: &Path,
+    progress: &mut DirProgress,
+    limiter: &mut RateLimiter,
+    checkpoint: &mut dyn FnMut(&DirProgress) -> anyhow::Result<()>,
+) -> anyhow::Result<String> {
+    let _timing = timings::span(TimedPhase::Hash);
+    let mut since_checkpoint = 0u64;
+    let mut index = 0usize;
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(dir).unwrap_or(entry.path()).to_string_lossy().replace('\\', "/");
+        match progress.files.get(index) {
+            Some((done, _)) if *done == rel => {}
+            // The tree changed since the checkpoint: start over
+            Some(_) => {
+                *progress = DirProgress::default();
+                return hash_dir(dir, progress, limiter, checkpoint);
+            }
+            None => {}
+        }
+        if index == progress.files.len() {
+            let (hash, bytes) = hash_file(entry.path(), limiter)?;
+            progress.files.push((rel, hash));
+            progress.bytes += bytes;
+            since_checkpoint += bytes;
+            if since_checkpoint >= CHECKPOINT_BYTES {
+                checkpoint(progress)?;
+                since_checkpoint = 0;
+            }
+        }
+        index += 1;
+    }
+    progress.files.truncate(index);
+    let mut hasher = Sha256::new();
+    for (rel, hash) in &progress.files {
+        hasher.update(rel.as_bytes());
+        hasher.update([0]);
+        hasher.update(hash.as_bytes());
+        hasher.update([b'\n']);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Options of a worker run
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Read at most this many bytes per second (unlimited when `None`)
+    pub bytes_per_sec: Option<u64>,
+    /// Stop taking new jobs after this long
+    pub max_duration: Option<std::time::Duration>,
+}
+
+/// What a worker run did
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunSummary {
+    pub done: usize,
+    pub failed: usize,
+    pub bytes_hashed: u64,
+    /// Jobs still queued when the run stopped
+    pub remaining: usize,
+}
+
+/// SQLite-backed queue of hashing jobs
+pub struct HashQueue {
+    conn: Connection,
+}
+
+impl HashQueue {
+    /// Default path for the queue database
+    pub fn default_db_path() -> PathBuf {
+        crate::paths::state_dir().join("hash_queue.db")
+    }
+
+    /// Open or create a queue at the given path
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))
+                .map_err(Error::Db)?;
+        }
+
+        let conn = Connection::open(db_path)
+            .with_context(|| format!("Failed to open database at {:?}", db_path))
+            .map_err(Error::Db)?;
+        conn.busy_timeout(crate::scan_lease::DB_BUSY_TIMEOUT)?;
+
+        conn.execute_batch(r#"
+            CREATE TABLE IF NOT EXISTS hash_jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                path TEXT NOT NULL,
+                payload TEXT,
+                status TEXT NOT NULL DEFAULT 'queued',
+                progress TEXT,
+                bytes_done INTEGER NOT NULL DEFAULT 0,
+                result TEXT,
+                error TEXT,
+                queued_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_hash_jobs_status ON hash_jobs(status, id);
+        "#).map_err(db_err("Failed to initialize hash queue schema"))?;
+
+        Ok(Self { conn })
+    }
+
+    /// Open the default queue
+    pub fn open_default() -> Result<Self> {
+        Self::open(&Self::default_db_path())
+    }
+
+    /// Queue `kind` for `path` unless it is already queued or running.
+    /// Returns whether a job was added.
+    pub fn enqueue(&self, kind: JobKind, path: &Path, payload: Option<&str>) -> Result<bool> {
+        let path = path.to_string_lossy();
+        let pending: bool = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM hash_jobs WHERE kind = ?1 AND path = ?2 AND status IN ('queued', 'running'))",
+            params![kind.as_str(), path],
+            |row| row.get(0),
+        ).map_err(db_err("Failed to read hash queue"))?;
+        if pending {
+            return Ok(false);
+        }
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT INTO hash_jobs (kind, path, payload, queued_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![kind.as_str(), path, payload, now],
+        ).map_err(db_err("Failed to queue hash job"))?;
+        Ok(true)
+    }
+
+    fn job_from_row(row: &rusqlite::Row) -> rusqlite::Result<HashJob> {
+        let time = |s: String| DateTime::parse_from_rfc3339(&s).map(|t| t.with_timezone(&Utc)).unwrap_or_else(|_| Utc::now());
+        Ok(HashJob {
+            id: row.get(0)?,
+            kind: JobKind::parse(&row.get::<_, String>(1)?).unwrap_or(JobKind::StoreEntry),
+            path: row.get(2)?,
+            payload: row.get(3)?,
+            status: row.get(4)?,
+            bytes_done: row.get::<_, i64>(5)? as u64,
+            result: row.get(6)?,
+            error: row.get(7)?,
+            queued_at: time(row.get(8)?),
+            updated_at: time(row.get(9)?),
+        })
+    }
+
+    /// Take the oldest queued job, or a running one whose worker went silent
+    pub fn claim(&self, now: DateTime<Utc>) -> Result<Option<HashJob>> {
+        let stale = (now - STALE_AFTER).to_rfc3339();
+        let id: Option<i64> = self.conn.query_row(
+            "SELECT id FROM hash_jobs WHERE status = 'queued' OR (status = 'running' AND updated_at < ?1) ORDER BY id LIMIT 1",
+            params![stale],
+            |row| row.get(0),
+        ).optional().map_err(db_err("Failed to read hash queue"))?;
+        let Some(id) = id else { return Ok(None) };
+        self.conn.execute(
+            "UPDATE hash_jobs SET status = 'running', updated_at = ?2 WHERE id = ?1",
+            params![id, now.to_rfc3339()],
+        ).map_err(db_err("Failed to claim hash job"))?;
+        self.conn.query_row(
+            "SELECT id, kind, path, payload, status, bytes_done, result, error, queued_at, updated_at FROM hash_jobs WHERE id = ?1",
+            params![id],
+            Self::job_from_row,
+        ).optional().map_err(db_err("Failed to read hash job"))
+    }
+
+    /// Progress saved by an interrupted run of job `id`
+    pub fn progress(&self, id: i64) -> Result<DirProgress> {
+        let text: Option<String> = self.conn.query_row(
+            "SELECT progress FROM hash_jobs WHERE id = ?1", params![id], |row| row.get(0),
+        ).optional().map_err(db_err("Failed to read hash job"))?.flatten();
+        Ok(text.and_then(|t| serde_json::from_str(&t).ok()).unwrap_or_default())
+    }
+
+    pub fn save_progress(&self, id: i64, progress: &DirProgress) -> Result<()> {
+        let text = serde_json::to_string(progress).map_err(|e| Error::Db(e.into()))?;
+        self.conn.execute(
+            "UPDATE hash_jobs SET progress = ?2, bytes_done = ?3, updated_at = ?4 WHERE id = ?1",
+            params![id, text, progress.bytes as i64, Utc::now().to_rfc3339()],
+        ).map_err(db_err("Failed to save hash job progress"))?;
+        Ok(())
+    }
+
+    /// Record the outcome of job `id`; `error` marks it failed
+    pub fn finish(&self, id: i64, bytes: u64, result: Option<&str>, error: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE hash_jobs SET status = ?2, progress = NULL, bytes_done = ?3, result = ?4, error = ?5, updated_at = ?6 WHERE id = ?1",
+            params![id, if error.is_some() { "failed" } else { "done" }, bytes as i64, result, error, Utc::now().to_rfc3339()],
+        ).map_err(db_err("Failed to finish hash job"))?;
+        Ok(())
+    }
+
+    /// Result of the last finished job of `kind` for `path` before job `before`
+    fn previous_result(&self, kind: JobKind, path: &str, before: i64) -> Result<Option<String>> {
+        self.conn.query_row(
+            "SELECT result FROM hash_jobs WHERE kind = ?1 AND path = ?2 AND id < ?3 AND result IS NOT NULL ORDER BY id DESC LIMIT 1",
+            params![kind.as_str(), path, before],
+            |row| row.get(0),
+        ).optional().map_err(db_err("Failed to read hash queue"))
+    }
+
+    /// Jobs with `status` (all when `None`), oldest first
+    pub fn jobs(&self, status: Option<&str>) -> Result<Vec<HashJob>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, kind, path, payload, status, bytes_done, result, error, queued_at, updated_at FROM hash_jobs \
+             WHERE ?1 IS NULL OR status = ?1 ORDER BY id",
+        ).map_err(db_err("Failed to read hash queue"))?;
+        let rows = stmt.query_map(params![status], Self::job_from_row).map_err(db_err("Failed to read hash queue"))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>().map_err(db_err("Failed to read hash queue"))
+    }
+
+    pub fn status(&self) -> Result<QueueStatus> {
+        let mut out = QueueStatus::default();
+        let mut stmt = self.conn.prepare("SELECT status, COUNT(*), SUM(bytes_done) FROM hash_jobs GROUP BY status")
+            .map_err(db_err("Failed to read hash queue"))?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<i64>>(2)?)))
+            .map_err(db_err("Failed to read hash queue"))?;
+        for row in rows {
+            let (status, count, bytes) = row.map_err(db_err("Failed to read hash queue"))?;
+            match status.as_str() {
+                "queued" => out.queued = count as usize,
+                "running" => out.running = count as usize,
+                "done" => out.done = count as usize,
+                "failed" => out.failed = count as usize,
+                _ => {}
+            }
+            out.bytes_done += bytes.unwrap_or(0) as u64;
+        }
+        Ok(out)
+    }
+
+    /// Forget finished jobs older than `keep_days`. Returns how many.
+    pub fn clear_finished(&self, keep_days: i64) -> Result<usize> {
+        let cutoff = (Utc::now() - Duration::days(keep_days)).to_rfc3339();
+        self.conn.execute(
+            "DELETE FROM hash_jobs WHERE status IN ('done', 'failed') AND updated_at < ?1",
+            params![cutoff],
+        ).map_err(db_err("Failed to clear hash queue"))
+    }
+
+    /// Hash the tree of `job`, resuming from its saved progress
+    fn hash_job_dir(&self, job: &HashJob, limiter: &mut RateLimiter) -> anyhow::Result<(String, u64)> {
+        let mut progress = self.progress(job.id)?;
+        let hash = hash_dir(Path::new(&job.path), &mut progress, limiter, &mut |p| Ok(self.save_progress(job.id, p)?))?;
+        Ok((hash, progress.bytes))
+    }
+
+    /// Run one job; returns (bytes read, result, error)
+    fn process(&self, job: &HashJob, limiter: &mut RateLimiter, ctx: &OperationContext) -> anyhow::Result<(u64, Option<String>, Option<String>)> {
+        match job.kind {
+            JobKind::QuarantineChecksum => {
+                let id = job.payload.as_deref().context("Checksum job without a quarantine record")?;
+                if !Path::new(&job.path).exists() {
+                    return Ok((0, None, Some("quarantine entry no longer exists".to_string())));
+                }
+                let (hash, bytes) = self.hash_job_dir(job, limiter)?;
+                crate::safety::record_checksum(id, &hash)?;
+                Ok((bytes, Some(hash), None))
+            }
+            JobKind::StoreEntry => {
+                let (hash, bytes) = self.hash_job_dir(job, limiter)?;
+                let error = self.previous_result(job.kind, &job.path, job.id)?
+                    .filter(|previous| *previous != hash)
+                    .map(|previous| format!("content changed since it was hashed as {}", previous));
+                Ok((bytes, Some(hash), error))
+            }
+            JobKind::VerifyPackage => {
+                let pkg: PackageRecord = serde_json::from_str(job.payload.as_deref().unwrap_or_default())
+                    .context("Verify job without a package record")?;
+                let checked = IntegrityChecker::default().check(&pkg);
+                limiter.consume(pkg.size_bytes);
+                let error = match &checked.status {
+                    IntegrityStatus::Mismatch { files } => Some(format!("{} files differ from the registry tarball", files.len())),
+                    IntegrityStatus::CorruptTarball => Some("cached tarball does not match the lockfile".to_string()),
+                    _ => None,
+                };
+                Ok((pkg.size_bytes, Some(serde_json::to_string(&checked.status)?), error))
+            }
+            JobKind::RefreshProject => {
+                if !Path::new(&job.path).is_dir() {
+                    return Ok((0, None, Some("project no longer exists".to_string())));
+                }
+                let paths = [PathBuf::from(&job.path)];
+                let scan = crate::scanner::scan_validated(&paths, true, CacheValidation::Fast, ctx)?;
+                let summary = serde_json::json!({ "packages": scan.packages.len(), "projects": scan.projects.len() });
+                Ok((0, Some(summary.to_string()), None))
+            }
+        }
+    }
+
+    /// Work through the queue until it is empty, `options.max_duration` has
+    /// passed or `ctx` is cancelled. A cancelled job keeps its progress.
+    pub fn run(&self, options: &RunOptions, ctx: &OperationContext) -> Result<RunSummary> {
+        let started = Instant::now();
+        let mut limiter = RateLimiter::new(options.bytes_per_sec);
+        let mut summary = RunSummary::default();
+        while options.max_duration.is_none_or(|max| started.elapsed() < max) && !ctx.cancel.is_cancelled() {
+            let Some(job) = self.claim(Utc::now())? else { break };
+            match self.process(&job, &mut limiter, ctx) {
+                Ok((bytes, result, error)) => {
+                    self.finish(job.id, bytes, result.as_deref(), error.as_deref())?;
+                    summary.bytes_hashed += bytes;
+                    if error.is_some() { summary.failed += 1 } else { summary.done += 1 }
+                }
+                Err(e) => {
+                    self.finish(job.id, 0, None, Some(&format!("{:#}", e)))?;
+                    summary.failed += 1;
+                }
+            }
+        }
+        summary.remaining = self.status()?.queued;
+        Ok(summary)
+    }
+}
+
+/// Queue a hash job in the default queue, warning instead of failing
+pub fn enqueue_default(kind: JobKind, path: &Path, payload: Option<&str>) {
+    if let Err(e) = HashQueue::open_default().and_then(|q| q.enqueue(kind, path, payload)) {
+        eprintln!("Warning: Failed to queue hashing of {:?}: {}", path, e);
+    }
+}
+
+/// Global store entries: `<store>/<name>/<version>/<hash>` directories
+pub fn store_entries(store: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(store).min_depth(3).max_depth(3)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir())
+        .map(|e| e.into_path())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_hash_dir_resumes_and_matches_copies() {
+        let temp = tempdir().unwrap();
+        let a = temp.path().join("a");
+        fs::create_dir_all(a.join("lib")).unwrap();
+        fs::write(a.join("package.json"), "{}").unwrap();
+        fs::write(a.join("lib/index.js"), "module.exports = 1").unwrap();
+
+        let mut full = DirProgress::default();
+        let hash = hash_dir(&a, &mut full, &mut RateLimiter::unlimited(), &mut |_| Ok(())).unwrap();
+        assert_eq!(full.bytes, 20);
+
+        // Resuming after the first file reads only the second
+        let mut partial = DirProgress { files: full.files[..1].to_vec(), bytes: full.bytes - 2 };
+        let mut limiter = RateLimiter::unlimited();
+        assert_eq!(hash_dir(&a, &mut partial, &mut limiter, &mut |_| Ok(())).unwrap(), hash);
+        assert_eq!(limiter.consumed, 2);
+
+        let b = temp.path().join("b");
+        fs::create_dir_all(b.join("lib")).unwrap();
+        fs::write(b.join("lib/index.js"), "module.exports = 1").unwrap();
+        fs::write(b.join("package.json"), "{}").unwrap();
+        assert_eq!(hash_dir(&b, &mut DirProgress::default(), &mut RateLimiter::unlimited(), &mut |_| Ok(())).unwrap(), hash);
+        fs::write(b.join("package.json"), "{\"x\":1}").unwrap();
+        assert_ne!(hash_dir(&b, &mut DirProgress::default(), &mut RateLimiter::unlimited(), &mut |_| Ok(())).unwrap(), hash);
+    }
+
+    #[test]
+    fn test_queue_claims_dedupes_and_flags_changed_store_entries() {
+        let temp = tempdir().unwrap();
+        let queue = HashQueue::open(&temp.path().join("q.db")).unwrap();
+        let entry = temp.path().join("store/lodash/4.17.21/abc");
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(entry.join("index.js"), "1").unwrap();
+
+        assert!(queue.enqueue(JobKind::StoreEntry, &entry, None).unwrap());
+        assert!(!queue.enqueue(JobKind::StoreEntry, &entry, None).unwrap());
+        let summary = queue.run(&RunOptions::default(), &OperationContext::default()).unwrap();
+        assert_eq!((summary.done, summary.failed, summary.remaining), (1, 0, 0));
+
+        fs::write(entry.join("index.js"), "2").unwrap();
+        assert!(queue.enqueue(JobKind::StoreEntry, &entry, None).unwrap());
+        let summary = queue.run(&RunOptions::default(), &OperationContext::default()).unwrap();
+        assert_eq!(summary.failed, 1);
+        let failed = queue.jobs(Some("failed")).unwrap();
+        assert!(failed[0].error.as_deref().unwrap().contains("content changed"));
+
+        // A job whose worker went silent is taken over
+        assert!(queue.enqueue(JobKind::StoreEntry, &entry, None).unwrap());
+        let claimed = queue.claim(Utc::now()).unwrap().unwrap();
+        assert!(queue.claim(Utc::now()).unwrap().is_none());
+        let later = Utc::now() + STALE_AFTER + Duration::seconds(1);
+        assert_eq!(queue.claim(later).unwrap().unwrap().id, claimed.id);
+        assert_eq!(queue.status().unwrap().running, 1);
+    }
+}
@@ -0,0 +1,153 @@
+//! Hoisting-Aware Duplicate Detection
+//!
+//! Two copies of the same name@version are not necessarily redundant. In a
+//! hoisted install a package is nested under each dependent that needs a
+//! version other than the hoisted one, so identical nested copies are required
+//! for resolution and deleting one breaks its dependent. A copy is only
+//! redundant when Node's resolution would reach an identical copy without it
+//! (the nearest ancestor `node_modules` holds the same version), or when the
+//! npm lockfile covering it does not list its install path (extraneous).
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::types::{PackageRecord, ScanOutput};
+
+/// Why a copy can be removed without breaking resolution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedundantCopy {
+    /// Removing it makes resolution fall through to this identical copy
+    Shadowed { by: String },
+    /// Not an install location in the npm lockfile; `other` is a listed copy
+    Extraneous { other: String },
+}
+
+/// Classify every copy of every duplicated name@version. Copies that are not
+/// returned are legitimate (version-conflict nesting or another project's install).
+pub fn redundant_copies(scan: &ScanOutput, include: impl Fn(&PackageRecord) -> bool) -> Vec<(&PackageRecord, RedundantCopy)> {
+    let by_path: HashMap<&Path, &PackageRecord> = scan.packages.iter()
+        .map(|p| (Path::new(&p.path), p))
+        .collect();
+    let mut groups: HashMap<(&str, &str), Vec<&PackageRecord>> = HashMap::new();
+    for pkg in scan.packages.iter().filter(|p| include(p)) {
+        groups.entry((&pkg.name, &pkg.version)).or_default().push(pkg);
+    }
+
+    let mut locks = LockPaths::default();
+    let mut out = Vec::new();
+    for pkg in scan.packages.iter().filter(|p| include(p)) {
+        let copies = &groups[&(pkg.name.as_str(), pkg.version.as_str())];
+        if copies.len() < 2 {
+            continue;
+        }
+        if let Some(by) = nearest_ancestor_copy(Path::new(&pkg.path), &pkg.name, &by_path) {
+            // Copies without a readable version cannot be told apart
+            if by.version == pkg.version && pkg.version != "unknown" {
+                out.push((pkg, RedundantCopy::Shadowed { by: by.path.clone() }));
+                continue;
+            }
+        }
+        if locks.listed(Path::new(&pkg.path)) == Some(false) {
+            let other = copies.iter().find(|c| c.path != pkg.path && locks.listed(Path::new(&c.path)) != Some(false));
+            if let Some(other) = other {
+                out.push((pkg, RedundantCopy::Extraneous { other: other.path.clone() }));
+            }
+        }
+    }
+    out
+}
+
+/// The copy Node would resolve `name` to from the dependent owning `pkg_path`
+/// if `pkg_path` did not exist
+fn nearest_ancestor_copy<'a>(pkg_path: &Path, name: &str, by_path: &HashMap<&Path, &'a PackageRecord>) -> Option<&'a PackageRecord> {
+    let modules_dir = pkg_path.ancestors().find(|a| a.file_name().is_some_and(|n| n == "node_modules"))?;
+    let dependent = modules_dir.parent()?;
+    dependent.ancestors()
+        .skip(1)
+        .filter(|dir| dir.file_name().is_none_or(|n| n != "node_modules"))
+        .find_map(|dir| by_path.get(dir.join("node_modules").join(name).as_path()).copied())
+}
+
+/// Install paths from npm v2/v3 lockfiles (`packages` keys), per lockfile
+#[derive(Default)]
+struct LockPaths {
+    lockfiles: HashMap<PathBuf, Option<HashSet<String>>>,
+}
+
+impl LockPaths {
+    /// Whether the nearest npm lockfile above the package lists its install
+    /// path; None when there is no lockfile with install paths
+    fn listed(&mut self, pkg_path: &Path) -> Option<bool> {
+        let root = pkg_path.ancestors().skip(1).find(|a| a.join("package-lock.json").is_file())?;
+        let keys = self.lockfiles.entry(root.to_path_buf())
+            .or_insert_with(|| install_paths(&root.join("package-lock.json")))
+            .as_ref()?;
+        let rel = pkg_path.strip_prefix(root).ok()?;
+        let key: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+        Some(keys.contains(&key.join("/")))
+    }
+}
+
+fn install_paths(lockfile: &Path) -> Option<HashSet<String>> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(lockfile).ok()?).ok()?;
+    let packages = json.get("packages")?.as_object()?;
+    Some(packages.keys().filter(|k| !k.is_empty()).cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn package(path: &Path, name: &str, version: &str) -> PackageRecord {
+        PackageRecord { name: name.into(), ..PackageRecord::for_test(path, version, 10) }
+    }
+
+    fn scan(packages: Vec<PackageRecord>) -> ScanOutput {
+        ScanOutput { packages, projects: Vec::new(), edges: Vec::new(), stats: Default::default(), deferred_sizes: Vec::new(), marked_dirs: Vec::new() }
+    }
+
+    #[test]
+    fn test_version_conflict_nesting_is_legitimate() {
+        let root = Path::new("/repo/node_modules");
+        let out = scan(vec![
+            package(&root.join("x"), "x", "2.0.0"),
+            package(&root.join("a/node_modules/x"), "x", "1.0.0"),
+            package(&root.join("b/node_modules/x"), "x", "1.0.0"),
+        ]);
+        assert!(redundant_copies(&out, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_copy_is_redundant() {
+        let root = Path::new("/repo/node_modules");
+        let out = scan(vec![
+            package(&root.join("x"), "x", "1.0.0"),
+            package(&root.join("a"), "a", "1.0.0"),
+            package(&root.join("a/node_modules/x"), "x", "1.0.0"),
+        ]);
+        let found = redundant_copies(&out, |_| true);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0.path, "/repo/node_modules/a/node_modules/x");
+        assert_eq!(found[0].1, RedundantCopy::Shadowed { by: "/repo/node_modules/x".into() });
+    }
+
+    #[test]
+    fn test_copy_missing_from_lockfile_is_extraneous() {
+        let temp = tempdir().unwrap();
+        let repo = temp.path();
+        fs::write(repo.join("package-lock.json"), r#"{"lockfileVersion":3,"packages":{
+            "":{},"node_modules/x":{"version":"2.0.0"},"node_modules/a/node_modules/x":{"version":"1.0.0"}}}"#).unwrap();
+        let nm = repo.join("node_modules");
+        let out = scan(vec![
+            package(&nm.join("x"), "x", "2.0.0"),
+            package(&nm.join("a/node_modules/x"), "x", "1.0.0"),
+            package(&nm.join("b/node_modules/x"), "x", "1.0.0"),
+        ]);
+        let found = redundant_copies(&out, |_| true);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].0.path.ends_with("b/node_modules/x"));
+        assert!(matches!(&found[0].1, RedundantCopy::Extraneous { other } if other.ends_with("a/node_modules/x")));
+    }
+}
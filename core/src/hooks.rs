@@ -0,0 +1,304 @@
+//! Lifecycle Hooks
+//!
+//! Commands and webhooks run around scans, plans, quarantines and rollbacks,
+//! configured in `hooks.json` in the config directory:
+//!
+//! ```json
+//! { "hooks": [
+//!     { "event": "post-plan", "webhook": "https://hooks.slack.com/services/..." },
+//!     { "event": "pre-apply", "command": "./check-change-window.sh" }
+//! ] }
+//! ```
+//!
+//! Commands run through the platform shell with the event payload as JSON on
+//! stdin and `PACKAGEPURGE_HOOK_EVENT` set; their output goes to stderr.
+//! Webhooks receive the same payload as a POST body. A failing `pre-*` hook
+//! (non-zero exit, unreachable or non-2xx webhook) aborts the operation, so
+//! hooks can gate it; failures of other hooks are only reported. A hook that
+//! takes longer than its `timeout_secs` fails too; commands are killed.
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use wait_timeout::ChildExt;
+
+use crate::error::Error;
+use crate::verify::shell_command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// Before scanning; payload: the roots
+    PreScan,
+    /// After a cleanup plan was built; payload: the plan
+    PostPlan,
+    /// Before quarantining; payload: the targets or plan
+    PreApply,
+    /// After quarantining; payload: the records
+    PostApply,
+    /// After a rollback; payload: the restored record
+    OnRollback,
+}
+
+impl HookEvent {
+    /// Whether a failing hook aborts the operation
+    pub fn gates(self) -> bool {
+        matches!(self, Self::PreScan | Self::PreApply)
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pre-scan" => Ok(Self::PreScan),
+            "post-plan" => Ok(Self::PostPlan),
+            "pre-apply" => Ok(Self::PreApply),
+            "post-apply" => Ok(Self::PostApply),
+            "on-rollback" => Ok(Self::OnRollback),
+            other => Err(format!(
+                "unknown hook event `{}` (expected pre-scan, post-plan, pre-apply, post-apply or on-rollback)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PreScan => "pre-scan",
+            Self::PostPlan => "post-plan",
+            Self::PreApply => "pre-apply",
+            Self::PostApply => "post-apply",
+            Self::OnRollback => "on-rollback",
+        })
+    }
+}
+
+/// A command or webhook run on an event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    /// Seconds to wait for the command to exit and the webhook to respond
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+}
+
+/// Body sent to every hook
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    event: HookEvent,
+    timestamp: String,
+    report: &'a serde_json::Value,
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("hooks.json")
+}
+
+/// Configured hooks; a missing file means none, an unreadable one is reported
+pub fn load_config() -> crate::Result<HooksConfig> {
+    load_config_impl().map_err(Error::lift(Error::Config))
+}
+
+fn load_config_impl() -> Result<HooksConfig> {
+    let path = config_path();
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text).with_context(|| format!("Failed to parse {:?}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HooksConfig::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    }
+}
+
+/// Run the hooks configured for `event` with `report` attached. Fails if a
+/// gating hook failed; other failures are printed to stderr.
+pub fn run_hooks(event: HookEvent, report: &serde_json::Value) -> crate::Result<()> {
+    let config = load_config()?;
+    run_hooks_impl(&config, event, report).map_err(Error::lift(Error::Hook))
+}
+
+fn run_hooks_impl(config: &HooksConfig, event: HookEvent, report: &serde_json::Value) -> Result<()> {
+    let payload = serde_json::to_vec(&Payload { event, timestamp: Utc::now().to_rfc3339(), report })?;
+    for hook in config.hooks.iter().filter(|h| h.event == event) {
+        let result = run_hook(hook, &payload);
+        if let Err(e) = result {
+            if event.gates() {
+                return Err(e.context(format!("{} hook failed", event)));
+            }
+            eprintln!("Warning: {} hook failed: {:#}", event, e);
+        }
+    }
+    Ok(())
+}
+
+fn run_hook(hook: &Hook, payload: &[u8]) -> Result<()> {
+    let timeout = Duration::from_secs(hook.timeout_secs);
+    if let Some(cmd) = &hook.command {
+        run_command(cmd, hook.event, payload, timeout)?;
+    }
+    if let Some(url) = &hook.webhook {
+        post_webhook(url, payload, timeout)?;
+    }
+    Ok(())
+}
+
+fn run_command(cmd: &str, event: HookEvent, payload: &[u8], timeout: Duration) -> Result<()> {
+    let mut child = shell_command(cmd)
+        .env("PACKAGEPURGE_HOOK_EVENT", event.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to spawn `{}`", cmd))?;
+    // Written and read from threads so a hook that ignores stdin cannot block on its own output.
+    // Processes the hook starts in the background may hold its pipes past its exit, so the
+    // threads are never waited on beyond the timeout.
+    let mut stdin = child.stdin.take();
+    let body = payload.to_vec();
+    std::thread::spawn(move || {
+        if let Some(stdin) = stdin.as_mut() {
+            let _ = stdin.write_all(&body);
+        }
+    });
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let deadline = Instant::now() + timeout;
+    let Some(status) = child.wait_timeout(timeout)? else {
+        let _ = child.kill();
+        let _ = child.wait();
+        anyhow::bail!("`{}` did not finish within {}s and was killed", cmd, timeout.as_secs());
+    };
+    let output = |reader: mpsc::Receiver<Vec<u8>>| reader.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok();
+    let (out, err) = (output(stdout), output(stderr));
+    eprint!(
+        "{}{}",
+        String::from_utf8_lossy(out.as_deref().unwrap_or_default()),
+        String::from_utf8_lossy(err.as_deref().unwrap_or_default()),
+    );
+    if out.is_none() || err.is_none() {
+        eprintln!("Warning: output of `{}` cut short: a process it started still holds its pipes", cmd);
+    }
+    if !status.success() {
+        anyhow::bail!("`{}` exited with {}", cmd, status);
+    }
+    Ok(())
+}
+
+/// Read `pipe` to the end on a thread, sending what was read
+fn drain(pipe: Option<impl std::io::Read + Send + 'static>) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut out);
+        }
+        let _ = tx.send(out);
+    });
+    rx
+}
+
+fn post_webhook(url: &str, payload: &[u8], timeout: Duration) -> Result<()> {
+    ureq::post(url)
+        .timeout(timeout)
+        .set("Content-Type", "application/json")
+        .send_bytes(payload)
+        .map_err(|e| anyhow::anyhow!("POST {} failed: {}", url, e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_gating_and_payload() {
+        let temp = tempfile::tempdir().unwrap();
+        let out = temp.path().join("payload.json");
+        let config = HooksConfig {
+            hooks: vec![
+                Hook {
+                    event: HookEvent::PostPlan,
+                    command: Some(format!("cat > {:?}", out)),
+                    webhook: None,
+                    timeout_secs: 10,
+                },
+                Hook { event: HookEvent::PreApply, command: Some("exit 3".into()), webhook: None, timeout_secs: 10 },
+                Hook { event: HookEvent::PostApply, command: Some("exit 3".into()), webhook: None, timeout_secs: 10 },
+            ],
+        };
+        let report = serde_json::json!({ "total_estimated_bytes": 42 });
+
+        run_hooks_impl(&config, HookEvent::PostPlan, &report).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(payload["event"], "post-plan");
+        assert_eq!(payload["report"]["total_estimated_bytes"], 42);
+
+        // A failing pre-apply hook blocks; a failing post-apply hook only warns
+        assert!(run_hooks_impl(&config, HookEvent::PreApply, &report).is_err());
+        assert!(run_hooks_impl(&config, HookEvent::PostApply, &report).is_ok());
+        assert!(run_hooks_impl(&config, HookEvent::OnRollback, &report).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_slow_command_is_killed() {
+        let temp = tempfile::tempdir().unwrap();
+        let marker = temp.path().join("finished");
+        let config = HooksConfig {
+            hooks: vec![Hook {
+                event: HookEvent::PreApply,
+                command: Some(format!("sleep 30; touch {:?}", marker)),
+                webhook: None,
+                timeout_secs: 1,
+            }],
+        };
+
+        let started = std::time::Instant::now();
+        let err = run_hooks_impl(&config, HookEvent::PreApply, &serde_json::json!({})).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(format!("{:#}", err).contains("did not finish within 1s"), "{:#}", err);
+        assert!(!marker.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_background_process_does_not_hold_up_hook() {
+        let config = HooksConfig {
+            hooks: vec![Hook {
+                event: HookEvent::PreApply,
+                command: Some("echo started; sleep 30 & exit 0".into()),
+                webhook: None,
+                timeout_secs: 1,
+            }],
+        };
+
+        let started = std::time::Instant::now();
+        run_hooks_impl(&config, HookEvent::PreApply, &serde_json::json!({})).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
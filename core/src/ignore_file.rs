@@ -0,0 +1,106 @@
+//! Ignore Files
+//!
+//! A `.packagepurgeignore` at a scan root lists, in gitignore syntax, what
+//! scans of that root never enter: projects to be left as they are,
+//! vendored trees, caches managed by hand. `ignore` in the config directory
+//! holds patterns applied under every root, like git's global excludes.
+//!
+//! ```text
+//! # relative to the scan root
+//! /clients/legacy-app/
+//! vendor/
+//! *.bak
+//! !vendor/keep-me/
+//! ```
+//!
+//! A pattern without a slash matches at any depth, a leading `/` anchors it
+//! to the root, a trailing `/` matches directories only, and `!` takes an
+//! earlier match back (but, as in git, not below an ignored directory). The
+//! root's file is read after the global one, so its negations win. What the
+//! walker does not enter is neither scanned nor planned; the scan rules
+//! (`scan_rules`) prune the same way by absolute globs.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File read at every scan root
+pub const FILE_NAME: &str = ".packagepurgeignore";
+
+/// Patterns applied under every scan root
+pub fn global_path() -> PathBuf {
+    crate::paths::config_dir().join("ignore")
+}
+
+/// Add the lines of `file` to `builder`, returning the lines that are not
+/// valid patterns with the reason
+fn add_file(builder: &mut GitignoreBuilder, file: &Path) -> Vec<(usize, String)> {
+    let Ok(text) = fs::read_to_string(file) else { return Vec::new() };
+    text.lines().enumerate()
+        .filter_map(|(i, line)| builder.add_line(Some(file.to_path_buf()), line).err().map(|e| (i + 1, e.to_string())))
+        .collect()
+}
+
+/// Compiled ignore patterns of one scan root
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    matcher: Gitignore,
+}
+
+impl IgnoreRules {
+    /// The global patterns and those of `root`'s ignore file, relative to
+    /// `root`. Lines that are not valid patterns are skipped with a warning.
+    pub fn for_root(root: &Path) -> Self {
+        Self::from_files(root, &[global_path(), root.join(FILE_NAME)])
+    }
+
+    pub fn from_files(root: &Path, files: &[PathBuf]) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for file in files {
+            for (line, e) in add_file(&mut builder, file) {
+                eprintln!("Warning: ignoring line {} of {:?}: {}", line, file, e);
+            }
+        }
+        let matcher = builder.build().unwrap_or_else(|e| {
+            eprintln!("Warning: ignoring {}: {}", FILE_NAME, e);
+            Gitignore::empty()
+        });
+        Self { matcher }
+    }
+
+    /// Whether the walker should stay out of `path`, below the root
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matcher.matched(path, is_dir).is_ignore()
+    }
+}
+
+/// Lines of an ignore file that are not valid patterns, with the reason
+pub fn check(file: &Path) -> Vec<(usize, String)> {
+    add_file(&mut GitignoreBuilder::new(file.parent().unwrap_or(file)), file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_root_file_over_global() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let global = root.join("global-ignore");
+        fs::write(&global, "vendor/\n*.bak\n").unwrap();
+        fs::write(root.join(FILE_NAME), "# hand-managed\n/clients/legacy/\n!*.bak\n").unwrap();
+
+        let rules = IgnoreRules::from_files(root, &[global, root.join(FILE_NAME)]);
+        assert!(rules.is_ignored(&root.join("clients/legacy"), true));
+        assert!(!rules.is_ignored(&root.join("other/clients/legacy"), true));
+        assert!(rules.is_ignored(&root.join("app/vendor"), true));
+        assert!(!rules.is_ignored(&root.join("app/vendor"), false));
+        assert!(!rules.is_ignored(&root.join("app/old.bak"), true));
+        assert!(!rules.is_ignored(&root.join("app"), true));
+
+        fs::write(root.join("bad"), "ok/\na/{b\n").unwrap();
+        assert_eq!(check(&root.join("bad")).iter().map(|(line, _)| *line).collect::<Vec<_>>(), [2]);
+    }
+}
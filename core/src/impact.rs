@@ -0,0 +1,225 @@
+//! Plan Impact Analysis
+//!
+//! Before a plan is approved or applied, lists what it would break: for
+//! every item, the projects whose installs would be left incomplete. A
+//! project is hit when an item removes a package it reaches through the
+//! dependency DAG, or anything installed in its own `node_modules`. The
+//! projects hit are grouped by activity class, so a plan that only breaks
+//! dead projects reads differently from one that breaks active ones.
+
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use crate::feature_store::FeatureStore;
+use crate::project_activity::{classify_projects, load_config, EditorRecents, ProjectActivity};
+use crate::types::{ActivityClass, DryRunReport, ProjectRecord, ScanOutput};
+
+/// The projects one plan item breaks
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemImpact {
+    pub target_path: String,
+    pub projects: Vec<String>,
+}
+
+/// A project one or more items break
+#[derive(Debug, Clone, Serialize)]
+pub struct AffectedProject {
+    pub project: String,
+    /// `None` when the project was not classified
+    pub activity: Option<ActivityClass>,
+    /// Plan items that break it
+    pub items: Vec<String>,
+}
+
+/// Broken projects per activity class
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ImpactCounts {
+    pub active: usize,
+    pub dormant: usize,
+    pub dead: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanImpact {
+    /// One line for people deciding whether to approve
+    pub summary: String,
+    pub counts: ImpactCounts,
+    pub projects: Vec<AffectedProject>,
+    /// Items that break at least one project
+    pub items: Vec<ItemImpact>,
+}
+
+/// The path before the first `node_modules` component: the project a
+/// package is installed for
+fn installed_for(path: &Path) -> Option<&Path> {
+    path.ancestors().filter(|a| a.file_name().is_some_and(|n| n == "node_modules")).last()?.parent()
+}
+
+/// The projects each plan item breaks, in plan order
+pub(crate) fn broken_by_item(plan: &DryRunReport, scan: &ScanOutput) -> Vec<ItemImpact> {
+    let projects: HashSet<&str> = scan.projects.iter().map(|p| p.path.as_str()).collect();
+    let mut reverse: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (from, to) in &scan.edges {
+        reverse.entry(to.as_str()).or_default().push(from.as_str());
+    }
+    let mut packages: Vec<&str> = scan.packages.iter().map(|p| p.path.as_str()).collect();
+    packages.sort_unstable();
+
+    plan.items.iter().filter_map(|item| {
+        let target = Path::new(&item.target_path);
+        // Packages at or below the target sort right after it
+        let start = packages.partition_point(|p| *p < item.target_path.as_str());
+        let removed: Vec<&str> = packages[start..].iter()
+            .take_while(|p| p.starts_with(item.target_path.as_str()))
+            .filter(|p| Path::new(p).starts_with(target))
+            .copied()
+            .collect();
+
+        let mut broken: BTreeSet<String> = BTreeSet::new();
+        for path in removed.iter().copied().chain([item.target_path.as_str()]) {
+            if let Some(owner) = installed_for(Path::new(path)).and_then(|o| o.to_str()) {
+                if projects.contains(owner) {
+                    broken.insert(owner.to_string());
+                }
+            }
+        }
+        let mut seen: HashSet<&str> = removed.iter().copied().collect();
+        let mut queue: VecDeque<&str> = removed.into_iter().collect();
+        while let Some(id) = queue.pop_front() {
+            for parent in reverse.get(id).into_iter().flatten() {
+                if seen.insert(parent) {
+                    if projects.contains(parent) {
+                        broken.insert(parent.to_string());
+                    }
+                    queue.push_back(parent);
+                }
+            }
+        }
+        (!broken.is_empty()).then(|| ItemImpact { target_path: item.target_path.clone(), projects: broken.into_iter().collect() })
+    })
+    .collect()
+}
+
+fn projects_phrase(n: usize, class: &str) -> String {
+    format!("{} {} project{}", n, class, if n == 1 { "" } else { "s" })
+}
+
+/// What applying `plan` breaks, given the activity of the scanned projects
+pub fn analyze(plan: &DryRunReport, scan: &ScanOutput, activities: &[ProjectActivity]) -> PlanImpact {
+    let items = broken_by_item(plan, scan);
+    let class: HashMap<&str, ActivityClass> = activities.iter().map(|a| (a.project.as_str(), a.activity)).collect();
+
+    let mut by_project: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for item in &items {
+        for project in &item.projects {
+            by_project.entry(project).or_default().push(item.target_path.clone());
+        }
+    }
+    let mut counts = ImpactCounts::default();
+    let projects: Vec<AffectedProject> = by_project.into_iter().map(|(project, items)| {
+        let activity = class.get(project).copied();
+        match activity {
+            Some(ActivityClass::Active) => counts.active += 1,
+            Some(ActivityClass::Dormant) => counts.dormant += 1,
+            Some(ActivityClass::Dead) => counts.dead += 1,
+            None => {}
+        }
+        AffectedProject { project: project.to_string(), activity, items }
+    })
+    .collect();
+
+    let summary = format!(
+        "Applying this plan breaks installs for {}, {} and {}",
+        projects_phrase(counts.active, "active"),
+        projects_phrase(counts.dormant, "dormant"),
+        projects_phrase(counts.dead, "dead"),
+    );
+    PlanImpact { summary, counts, projects, items }
+}
+
+/// `analyze` with the projects it breaks classified the way `projects` does
+pub fn analyze_configured(plan: &DryRunReport, scan: &ScanOutput) -> PlanImpact {
+    let broken: HashSet<String> = broken_by_item(plan, scan).into_iter().flat_map(|i| i.projects).collect();
+    let hit: Vec<ProjectRecord> = scan.projects.iter().filter(|p| broken.contains(&p.path)).cloned().collect();
+    let db = FeatureStore::open_default().ok();
+    let activities = classify_projects(&hit, db.as_ref(), &EditorRecents::detect(), &load_config(), Utc::now());
+    analyze(plan, scan, &activities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project_activity::ActivitySignals;
+    use crate::types::{PackageRecord, PlanItem, PlanReason};
+
+    fn package(path: &str) -> PackageRecord {
+        PackageRecord::for_test(path, "1.0.0", 10)
+    }
+
+    fn project(path: &str) -> ProjectRecord {
+        ProjectRecord { path: path.to_string(), manager: None, dependencies: Vec::new(), mtime: Utc::now() }
+    }
+
+    fn activity(project: &str, activity: ActivityClass) -> ProjectActivity {
+        ProjectActivity { project: project.to_string(), activity, last_activity: None, signals: ActivitySignals::default() }
+    }
+
+    fn plan(targets: &[&str]) -> DryRunReport {
+        DryRunReport {
+            items: targets.iter().map(|t| PlanItem {
+                target_path: t.to_string(),
+                estimated_size_bytes: 0,
+                reason: PlanReason::Orphaned,
+                blockers: Vec::new(),
+                risk: None,
+            }).collect(),
+            total_estimated_bytes: 0,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        }
+    }
+
+    #[test]
+    fn test_impact_follows_dag_and_groups_by_activity() {
+        // web requires shared (linked from a workspace store), which requires util;
+        // api has its own node_modules; old is dead
+        let scan = ScanOutput {
+            packages: vec![
+                package("/store/shared"),
+                package("/store/util"),
+                package("/dev/api/node_modules/express"),
+                package("/dev/old/node_modules/left-pad"),
+                package("/cache/.npm/lodash"),
+            ],
+            projects: vec![project("/dev/web"), project("/dev/api"), project("/dev/old")],
+            edges: vec![
+                ("/dev/web".into(), "/store/shared".into()),
+                ("/store/shared".into(), "/store/util".into()),
+            ],
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let activities = [
+            activity("/dev/web", ActivityClass::Active),
+            activity("/dev/api", ActivityClass::Dormant),
+            activity("/dev/old", ActivityClass::Dead),
+        ];
+
+        let impact = analyze(&plan(&["/store/util", "/dev/api/node_modules", "/dev/old/node_modules", "/cache/.npm/lodash"]), &scan, &activities);
+        assert_eq!(impact.items.len(), 3);
+        assert_eq!(impact.items[0].projects, ["/dev/web"]);
+        assert_eq!(impact.items[1].projects, ["/dev/api"]);
+        assert_eq!((impact.counts.active, impact.counts.dormant, impact.counts.dead), (1, 1, 1));
+        assert_eq!(impact.summary, "Applying this plan breaks installs for 1 active project, 1 dormant project and 1 dead project");
+
+        let harmless = analyze(&plan(&["/cache/.npm/lodash", "/dev/apiary"]), &scan, &activities);
+        assert!(harmless.items.is_empty() && harmless.projects.is_empty());
+        assert_eq!(harmless.summary, "Applying this plan breaks installs for 0 active projects, 0 dormant projects and 0 dead projects");
+    }
+}
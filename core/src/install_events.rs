@@ -0,0 +1,119 @@
+//! Package-Manager Install Events
+//!
+//! An install rewrites a project's `node_modules` wholesale, but the scan
+//! cache only re-checks what its fingerprints, TTLs and the change feed point
+//! at, so a plan built right after `npm install` could still see the tree from
+//! before it. Installs are picked up two ways:
+//!
+//! - `installed [project]`, run by the package manager after each install
+//!   (a `postinstall` script, which npm, Yarn and pnpm all run with the
+//!   project as working directory), forgets what the scan cache knows about
+//!   the project and queues a background rescan of it in the hash queue
+//!   (`hash-queue run`)
+//! - every cached scan compares the markers npm, Yarn and pnpm write into
+//!   `node_modules` on install with when the project's lockfile was last
+//!   parsed, and rescans projects installed into since
+//!
+//! The first also covers projects the cache has never seen, e.g. a fresh clone
+//! below a directory remembered as holding no packages.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::hash_queue::{HashQueue, JobKind};
+use crate::scan_cache::ScanCache;
+use crate::types::PackageManager;
+
+/// Files a package manager rewrites at the end of every install, relative to
+/// the project
+pub const INSTALL_MARKERS: [(&str, PackageManager); 4] = [
+    ("node_modules/.package-lock.json", PackageManager::Npm),
+    ("node_modules/.yarn-integrity", PackageManager::Yarn),
+    ("node_modules/.yarn-state.yml", PackageManager::Yarn),
+    ("node_modules/.modules.yaml", PackageManager::Pnpm),
+];
+
+/// The most recent install into `project` its markers show, and which
+/// package manager made it
+pub fn last_install(project: &Path) -> Option<(PackageManager, DateTime<Utc>)> {
+    INSTALL_MARKERS.iter()
+        .filter_map(|(marker, manager)| {
+            let mtime = fs::metadata(project.join(marker)).and_then(|m| m.modified()).ok()?;
+            Some((manager.clone(), DateTime::<Utc>::from(mtime)))
+        })
+        .max_by_key(|(_, at)| *at)
+}
+
+/// Projects of `cache` installed into since their lockfile was parsed
+pub fn installed_since_scan(cache: &ScanCache) -> Vec<PathBuf> {
+    cache.lockfile_projects()
+        .filter(|(project, parsed)| last_install(project).is_some_and(|(_, at)| at > *parsed))
+        .map(|(project, _)| project.to_path_buf())
+        .collect()
+}
+
+/// What `record` did about an install
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallEvent {
+    pub project: String,
+    /// Package manager and time of the install, from its markers
+    pub manager: Option<PackageManager>,
+    pub installed_at: Option<DateTime<Utc>>,
+    /// Scan cache rows forgotten
+    pub invalidated: usize,
+    /// Whether a rescan was queued (false when one already was)
+    pub refresh_queued: bool,
+}
+
+/// Record an install into `project`: forget what the scan cache knows about
+/// it and queue a background rescan
+pub fn record(project: &Path) -> crate::Result<InstallEvent> {
+    let project = std::path::absolute(project).map_err(|e| Error::Scan(e.into()))?;
+    let invalidated = ScanCache::invalidate(&ScanCache::default_cache_path(), &project)
+        .map_err(Error::lift(Error::Scan))?;
+    let refresh_queued = HashQueue::open_default()?.enqueue(JobKind::RefreshProject, &project, None)?;
+    let install = last_install(&project);
+    Ok(InstallEvent {
+        project: project.to_string_lossy().to_string(),
+        manager: install.as_ref().map(|(m, _)| m.clone()),
+        installed_at: install.map(|(_, at)| at),
+        invalidated,
+        refresh_queued,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan_cache::LockfileEntry;
+    use chrono::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_installed_since_scan() {
+        let temp = tempdir().unwrap();
+        let (fresh, installed) = (temp.path().join("fresh"), temp.path().join("installed"));
+        for project in [&fresh, &installed] {
+            fs::create_dir_all(project.join("node_modules")).unwrap();
+        }
+        fs::write(installed.join("node_modules/.modules.yaml"), "layoutVersion: 5").unwrap();
+        assert!(last_install(&fresh).is_none());
+        assert!(matches!(last_install(&installed), Some((PackageManager::Pnpm, _))));
+
+        let mut cache = ScanCache::new();
+        let parsed = |at| LockfileEntry { lock_hash: String::new(), deps_hash: String::new(), dependencies: Vec::new(), cached_at: at };
+        cache.update_lockfile(&fresh, parsed(Utc::now()));
+        cache.update_lockfile(&installed, parsed(Utc::now() - Duration::hours(1)));
+        cache.update(&installed.join("node_modules"), 42).unwrap();
+        cache.mark_empty(temp.path()).unwrap();
+        assert_eq!(installed_since_scan(&cache), std::slice::from_ref(&installed));
+
+        // The project, what is below it and the negative entry above it go
+        assert_eq!(cache.forget_below(&installed), 3);
+        assert!(installed_since_scan(&cache).is_empty());
+        assert_eq!(cache.lockfile_projects().count(), 1);
+    }
+}
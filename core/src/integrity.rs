@@ -0,0 +1,275 @@
+//! Lockfile Integrity Verification
+//!
+//! Checks installed packages against the `integrity` hashes their project's
+//! lockfile records. Those hashes cover the registry tarball, not the unpacked
+//! directory, so the tarball is looked up by hash in the local npm cache
+//! (`~/.npm/_cacache`, or `$npm_config_cache`), checked against the lockfile
+//! and then compared file by file with the installed copy. Packages whose
+//! tarball is not cached cannot be verified and are reported as such.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::lockfiles::{parse_integrity, IntegrityMap};
+use crate::progress::{OperationContext, Phase};
+use crate::types::{PackageRecord, ScanOutput};
+use crate::verify::owning_project;
+
+/// Outcome of checking one installed package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    /// Every file of the registry tarball matches the installed copy
+    Verified,
+    /// Installed files differ from or are missing compared to the tarball
+    /// (tampered, patched or corrupted)
+    Mismatch { files: Vec<String> },
+    /// The cached tarball itself does not match the lockfile hash
+    CorruptTarball,
+    /// The lockfile records no integrity for this name@version
+    NoLockfileHash,
+    /// The tarball is not cached locally, or the hash algorithm is unsupported
+    Unverifiable { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageIntegrity {
+    pub path: String,
+    pub name: String,
+    pub version: String,
+    pub integrity: Option<String>,
+    #[serde(flatten)]
+    pub status: IntegrityStatus,
+}
+
+/// Verifies packages against their project's lockfile, reading each lockfile once
+pub struct IntegrityChecker {
+    cache_dir: PathBuf,
+    locks: HashMap<PathBuf, IntegrityMap>,
+}
+
+impl Default for IntegrityChecker {
+    fn default() -> Self {
+        Self::with_cache_dir(default_npm_cache())
+    }
+}
+
+impl IntegrityChecker {
+    /// Look tarballs up in the npm cache at `cache_dir` (the directory holding `_cacache`)
+    pub fn with_cache_dir(cache_dir: PathBuf) -> Self {
+        Self { cache_dir, locks: HashMap::new() }
+    }
+
+    /// Integrity the owning project's lockfile records for the package
+    pub fn expected(&mut self, pkg: &PackageRecord) -> Option<String> {
+        let project = owning_project(Path::new(&pkg.path))?;
+        self.locks.entry(project.clone())
+            .or_insert_with(|| parse_integrity(&project))
+            .get(&(pkg.name.clone(), pkg.version.clone()))
+            .cloned()
+    }
+
+    pub fn check(&mut self, pkg: &PackageRecord) -> PackageIntegrity {
+        let integrity = self.expected(pkg);
+        let status = match &integrity {
+            Some(sri) => verify_against(Path::new(&pkg.path), sri, &self.cache_dir),
+            None => IntegrityStatus::NoLockfileHash,
+        };
+        PackageIntegrity {
+            path: pkg.path.clone(),
+            name: pkg.name.clone(),
+            version: pkg.version.clone(),
+            integrity,
+            status,
+        }
+    }
+
+    /// Whether the package is known to differ from its lockfile hash.
+    /// Packages that cannot be verified do not count as diverged.
+    pub fn diverges(&mut self, pkg: &PackageRecord) -> bool {
+        matches!(self.check(pkg).status, IntegrityStatus::Mismatch { .. })
+    }
+}
+
+fn default_npm_cache() -> PathBuf {
+    if let Some(dir) = std::env::var_os("npm_config_cache") {
+        return PathBuf::from(dir);
+    }
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")).join(".npm")
+}
+
+/// Check every scanned package that belongs to a project
+pub fn verify_scan(scan: &ScanOutput, ctx: &OperationContext) -> crate::Result<Vec<PackageIntegrity>> {
+    verify_scan_impl(scan, ctx, &mut IntegrityChecker::default()).map_err(Error::lift(Error::Scan))
+}
+
+fn verify_scan_impl(scan: &ScanOutput, ctx: &OperationContext, checker: &mut IntegrityChecker) -> Result<Vec<PackageIntegrity>> {
+    let total = scan.packages.len() as u64;
+    let mut results = Vec::new();
+    for (i, pkg) in scan.packages.iter().enumerate() {
+        ctx.check()?;
+        ctx.report(Phase::Verify, i as u64 + 1, Some(total), Some(Path::new(&pkg.path)));
+        results.push(checker.check(pkg));
+    }
+    Ok(results)
+}
+
+/// Decoded SRI hash: (algorithm, digest). The first supported algorithm of a
+/// multi-hash SRI string wins.
+fn parse_sri(sri: &str) -> Option<(&str, Vec<u8>)> {
+    sri.split_whitespace().find_map(|h| {
+        let (algo, rest) = h.split_once('-')?;
+        if !matches!(algo, "sha512" | "sha256") {
+            return None;
+        }
+        let b64 = rest.split('?').next().unwrap_or(rest);
+        let digest = base64::engine::general_purpose::STANDARD.decode(b64).ok()?;
+        Some((algo, digest))
+    })
+}
+
+/// Verify the package at `dir` against the tarball the npm cache holds for `sri`
+pub fn verify_against(dir: &Path, sri: &str, cache_dir: &Path) -> IntegrityStatus {
+    let Some((algo, digest)) = parse_sri(sri) else {
+        return IntegrityStatus::Unverifiable { reason: "unsupported hash algorithm".into() };
+    };
+    let hex = hex::encode(&digest);
+    let tarball = cache_dir.join("_cacache").join("content-v2").join(algo)
+        .join(&hex[..2]).join(&hex[2..4]).join(&hex[4..]);
+    let Ok(bytes) = fs::read(&tarball) else {
+        return IntegrityStatus::Unverifiable { reason: "tarball not in npm cache".into() };
+    };
+
+    let actual = match algo {
+        "sha512" => Sha512::digest(&bytes).to_vec(),
+        _ => Sha256::digest(&bytes).to_vec(),
+    };
+    if actual != digest {
+        return IntegrityStatus::CorruptTarball;
+    }
+
+    match diff_tarball(dir, &bytes) {
+        Ok(files) if files.is_empty() => IntegrityStatus::Verified,
+        Ok(files) => IntegrityStatus::Mismatch { files },
+        Err(e) => IntegrityStatus::Unverifiable { reason: format!("{:#}", e) },
+    }
+}
+
+/// Tarball files (relative to the package root) whose installed copy is
+/// missing or different. Extra installed files, such as native build output,
+/// are not reported.
+fn diff_tarball(dir: &Path, tgz: &[u8]) -> Result<Vec<String>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(tgz));
+    let mut differing = Vec::new();
+    for entry in archive.entries().context("Failed to read package tarball")? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        // Tarball paths carry a top-level directory, usually `package/`
+        let rel: PathBuf = entry.path()?.components().skip(1).collect();
+        let mut expected = Vec::new();
+        entry.read_to_end(&mut expected)?;
+        if fs::read(dir.join(&rel)).ok().as_deref() != Some(expected.as_slice()) {
+            differing.push(rel.to_string_lossy().to_string());
+        }
+    }
+    differing.sort();
+    Ok(differing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use tempfile::tempdir;
+
+    /// Build a package tarball and store it in a fake npm cache; returns its SRI
+    fn cache_tarball(cache: &Path, files: &[(&str, &str)]) -> String {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("package/{}", name), content.as_bytes()).unwrap();
+        }
+        let tgz = builder.into_inner().unwrap().finish().unwrap();
+        let digest = Sha512::digest(&tgz);
+        let hex = hex::encode(digest);
+        let dest = cache.join("_cacache/content-v2/sha512").join(&hex[..2]).join(&hex[2..4]);
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(dest.join(&hex[4..]), &tgz).unwrap();
+        format!("sha512-{}", base64::engine::general_purpose::STANDARD.encode(digest))
+    }
+
+    #[test]
+    fn test_verify_against_cached_tarball() {
+        let temp = tempdir().unwrap();
+        let cache = temp.path().join("npm");
+        let sri = cache_tarball(&cache, &[("index.js", "module.exports = 1;"), ("lib/a.js", "a")]);
+
+        let pkg = temp.path().join("app/node_modules/a");
+        fs::create_dir_all(pkg.join("lib")).unwrap();
+        fs::write(pkg.join("index.js"), "module.exports = 1;").unwrap();
+        fs::write(pkg.join("lib/a.js"), "a").unwrap();
+        fs::write(pkg.join("extra.node"), "built locally").unwrap();
+        assert_eq!(verify_against(&pkg, &sri, &cache), IntegrityStatus::Verified);
+
+        fs::write(pkg.join("lib/a.js"), "patched").unwrap();
+        assert_eq!(
+            verify_against(&pkg, &sri, &cache),
+            IntegrityStatus::Mismatch { files: vec!["lib/a.js".into()] }
+        );
+
+        let elsewhere = temp.path().join("empty-cache");
+        assert!(matches!(verify_against(&pkg, &sri, &elsewhere), IntegrityStatus::Unverifiable { .. }));
+        assert!(matches!(verify_against(&pkg, "sha1-AAAA", &cache), IntegrityStatus::Unverifiable { .. }));
+    }
+
+    #[test]
+    fn test_checker_reads_project_lockfile() {
+        let temp = tempdir().unwrap();
+        let cache = temp.path().join("npm");
+        let sri = cache_tarball(&cache, &[("index.js", "x")]);
+        let project = temp.path().join("app");
+        let pkg_dir = project.join("node_modules/a");
+        fs::create_dir_all(&pkg_dir).unwrap();
+        fs::write(pkg_dir.join("index.js"), "y").unwrap();
+        fs::write(project.join("package-lock.json"), serde_json::json!({
+            "lockfileVersion": 3,
+            "packages": {
+                "": {},
+                "node_modules/a": { "version": "1.0.0", "integrity": sri },
+            }
+        }).to_string()).unwrap();
+
+        let record = |name: &str| PackageRecord::for_test(project.join("node_modules").join(name), "1.0.0", 0);
+        let mut checker = IntegrityChecker::with_cache_dir(cache);
+        assert!(checker.diverges(&record("a")));
+        assert_eq!(checker.check(&record("b")).status, IntegrityStatus::NoLockfileHash);
+    }
+
+    #[test]
+    fn test_parse_yarn_and_pnpm_integrity() {
+        let temp = tempdir().unwrap();
+        let yarn = temp.path().join("yarn.lock");
+        fs::write(&yarn, "\"@babel/core@^7.0.0\":\n  version \"7.2.0\"\n  resolved \"https://r/core.tgz\"\n  integrity sha512-abc\n").unwrap();
+        let map = crate::lockfiles::parse_yarn_integrity(&yarn);
+        assert_eq!(map.get(&("@babel/core".into(), "7.2.0".into())).map(String::as_str), Some("sha512-abc"));
+
+        let pnpm = temp.path().join("pnpm-lock.yaml");
+        fs::write(&pnpm, "packages:\n  /lodash@4.17.21:\n    resolution: {integrity: sha512-v6}\n  /@scope/pkg/1.0.0_react@18.0.0:\n    resolution: {integrity: sha512-v5}\n").unwrap();
+        let map = crate::lockfiles::parse_pnpm_integrity(&pnpm);
+        assert_eq!(map.get(&("lodash".into(), "4.17.21".into())).map(String::as_str), Some("sha512-v6"));
+        assert_eq!(map.get(&("@scope/pkg".into(), "1.0.0".into())).map(String::as_str), Some("sha512-v5"));
+    }
+}
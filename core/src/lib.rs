@@ -0,0 +1,88 @@
+//! PackagePurge core library
+//!
+//! Scanning, planning, deduplication and quarantine engine behind the
+//! `packagepurge-core` binary. Public entry points return [`error::Result`].
+
+pub mod types;
+pub mod error;
+pub mod scanner;
+pub mod native_walk;
+pub mod safety;
+pub mod optimization;
+pub mod cache;
+pub mod ml;
+pub mod arc_lfu;
+pub mod eviction;
+pub mod lockfiles;
+pub mod integrity;
+pub mod patches;
+pub mod hoisting;
+pub mod tree_share;
+pub mod cross_dedup;
+pub mod top;
+pub mod editor_caches;
+pub mod compiler_caches;
+pub mod model_caches;
+pub mod machine_role;
+pub mod ci_clean;
+pub mod approval;
+pub mod canary;
+pub mod soft_disable;
+pub mod reconcile;
+pub mod digest;
+pub mod display;
+pub mod output;
+pub mod hooks;
+pub mod repo_activity;
+pub mod archive;
+pub mod reinstall;
+pub mod exec;
+pub mod pm_verify;
+pub mod overhead;
+pub mod s3;
+pub mod provider_caches;
+pub mod cargo_caches;
+pub mod python_caches;
+pub mod go_caches;
+pub mod symlink;
+pub mod wsl;
+pub mod usage_tracker;
+pub mod access_watch;
+pub mod scan_cache;
+pub mod install_events;
+pub mod scan_lease;
+pub mod scan_rules;
+pub mod ignore_file;
+pub mod scan_import;
+pub mod change_feed;
+pub mod feature_store;
+pub mod feature_pipeline;
+pub mod privacy;
+pub mod verify;
+pub mod progress;
+pub mod paths;
+pub mod graph;
+pub mod canonical;
+pub mod relocate;
+pub mod store_index;
+pub mod project_activity;
+pub mod wide_scan;
+pub mod backup_exclude;
+pub mod cache_markers;
+pub mod impact;
+pub mod risk;
+pub mod fs_snapshot;
+pub mod hash_queue;
+pub mod timings;
+pub mod bench;
+pub mod compliance;
+pub mod project_tags;
+pub mod bundle;
+pub mod config_lint;
+pub mod config_file;
+pub mod version_retention;
+pub mod cloud_sync;
+pub mod run_manifest;
+pub mod purge;
+
+pub use error::{Error, Result};
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
@@ -110,3 +111,109 @@ pub fn parse_pnpm_lock(path: &Path) -> DepList {
 	}
 	list
 }
+
+/// Lockfile `integrity` values (SRI strings, e.g. `sha512-<base64>`) keyed by (name, version)
+pub type IntegrityMap = HashMap<(String, String), String>;
+
+/// Read the integrity hashes recorded by whichever lockfile `project_dir` has
+pub fn parse_integrity(project_dir: &Path) -> IntegrityMap {
+	let npm = project_dir.join("package-lock.json");
+	let yarn = project_dir.join("yarn.lock");
+	let pnpm = project_dir.join("pnpm-lock.yaml");
+	if npm.exists() {
+		parse_npm_integrity(&npm)
+	} else if yarn.exists() {
+		parse_yarn_integrity(&yarn)
+	} else if pnpm.exists() {
+		parse_pnpm_integrity(&pnpm)
+	} else {
+		IntegrityMap::new()
+	}
+}
+
+pub fn parse_npm_integrity(path: &Path) -> IntegrityMap {
+	let mut map = IntegrityMap::new();
+	let Some(json) = fs::read_to_string(path).ok().and_then(|t| serde_json::from_str::<serde_json::Value>(&t).ok()) else {
+		return map;
+	};
+
+	fn walk_v1(node: &serde_json::Value, map: &mut IntegrityMap) {
+		if let Some(deps) = node.get("dependencies").and_then(|d| d.as_object()) {
+			for (name, dep) in deps {
+				let version = dep.get("version").and_then(|v| v.as_str());
+				let integrity = dep.get("integrity").and_then(|v| v.as_str());
+				if let (Some(v), Some(i)) = (version, integrity) {
+					map.insert((name.clone(), v.to_string()), i.to_string());
+				}
+				walk_v1(dep, map);
+			}
+		}
+	}
+	walk_v1(&json, &mut map);
+
+	if let Some(packages) = json.get("packages").and_then(|p| p.as_object()) {
+		for (key, pkg) in packages {
+			let Some(idx) = key.rfind("node_modules/") else { continue };
+			let name = pkg.get("name").and_then(|n| n.as_str())
+				.unwrap_or(&key[idx + "node_modules/".len()..]);
+			let version = pkg.get("version").and_then(|v| v.as_str());
+			let integrity = pkg.get("integrity").and_then(|v| v.as_str());
+			if let (Some(v), Some(i)) = (version, integrity) {
+				map.insert((name.to_string(), v.to_string()), i.to_string());
+			}
+		}
+	}
+	map
+}
+
+/// Yarn classic lockfiles; berry's `checksum` is not an SRI hash of the
+/// registry tarball and is ignored
+pub fn parse_yarn_integrity(path: &Path) -> IntegrityMap {
+	let mut map = IntegrityMap::new();
+	let Ok(text) = fs::read_to_string(path) else { return map };
+
+	let mut name: Option<String> = None;
+	let mut version: Option<String> = None;
+	for line in text.lines() {
+		let trimmed = line.trim();
+		if trimmed.is_empty() || trimmed.starts_with('#') { continue; }
+		if !line.starts_with(' ') {
+			let first = trimmed.trim_end_matches(':').split(',').next().unwrap_or("").trim().trim_matches('"');
+			name = first.rfind('@').filter(|&i| i > 0).map(|i| first[..i].to_string());
+			version = None;
+		} else if let Some(v) = trimmed.strip_prefix("version ") {
+			version = Some(v.trim().trim_matches('"').to_string());
+		} else if let Some(i) = trimmed.strip_prefix("integrity ") {
+			if let (Some(n), Some(v)) = (&name, &version) {
+				map.insert((n.clone(), v.clone()), i.trim().trim_matches('"').to_string());
+			}
+		}
+	}
+	map
+}
+
+pub fn parse_pnpm_integrity(path: &Path) -> IntegrityMap {
+	let mut map = IntegrityMap::new();
+	let Some(yaml) = fs::read_to_string(path).ok().and_then(|t| serde_yaml::from_str::<serde_yaml::Value>(&t).ok()) else {
+		return map;
+	};
+	let Some(packages) = yaml.get("packages").and_then(|p| p.as_mapping()) else { return map };
+
+	for (key, pkg) in packages {
+		let Some(key) = key.as_str() else { continue };
+		let Some(integrity) = pkg.get("resolution").and_then(|r| r.get("integrity")).and_then(|i| i.as_str()) else {
+			continue;
+		};
+		// v5: /name/1.0.0_peer, v6: /name@1.0.0(peer), v9: name@1.0.0(peer)
+		let key = key.trim_start_matches('/');
+		let key = key.split('(').next().unwrap_or(key);
+		let v5 = key.rfind('/')
+			.filter(|&i| key[i + 1..].starts_with(|c: char| c.is_ascii_digit()))
+			.map(|i| (&key[..i], key[i + 1..].split('_').next().unwrap_or("")));
+		let split = v5.or_else(|| key.rfind('@').filter(|&i| i > 0).map(|i| (&key[..i], &key[i + 1..])));
+		if let Some((name, version)) = split {
+			map.insert((name.to_string(), version.to_string()), integrity.to_string());
+		}
+	}
+	map
+}
@@ -0,0 +1,277 @@
+//! Machine Roles
+//!
+//! Default policies differ by what a machine is for: a CI runner rebuilds
+//! everything per job and can clean aggressively without keeping anything in
+//! quarantine, while a laptop holds the only copy of a developer's caches and
+//! should only ever suggest. The role is taken from `--role`, then
+//! `PACKAGEPURGE_ROLE`, then `role.json` in the config directory (`role set`), and
+//! otherwise detected from CI variables, batteries and the presence of a display.
+//!
+//! The CLI applies `preserve_days` and the quarantine limits. `automation` and
+//! `schedule` are reported for whatever runs PackagePurge unattended (cron, a
+//! CI step, the Node wrapper) to act on.
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::error::{Error, Result};
+use crate::safety::QuarantineConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MachineRole {
+    Laptop,
+    Workstation,
+    CiRunner,
+    BuildServer,
+}
+
+impl FromStr for MachineRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "laptop" => Ok(Self::Laptop),
+            "workstation" => Ok(Self::Workstation),
+            "ci-runner" | "ci" => Ok(Self::CiRunner),
+            "build-server" => Ok(Self::BuildServer),
+            other => Err(format!(
+                "unknown machine role `{}` (expected laptop, workstation, ci-runner or build-server)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MachineRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Laptop => "laptop",
+            Self::Workstation => "workstation",
+            Self::CiRunner => "ci-runner",
+            Self::BuildServer => "build-server",
+        })
+    }
+}
+
+/// How much PackagePurge may do without a person confirming it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Automation {
+    /// Report plans only
+    SuggestOnly,
+    /// Act after interactive confirmation
+    Confirm,
+    /// Clean unattended
+    Auto,
+}
+
+/// When unattended cleanups should run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    Manual,
+    /// At the end of every job
+    EveryRun,
+    Daily,
+    Weekly,
+}
+
+/// Where the active role came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoleSource {
+    Flag,
+    Env,
+    Configured,
+    Detected,
+}
+
+/// Defaults selected by a machine role
+#[derive(Debug, Clone, Serialize)]
+pub struct RolePolicy {
+    pub role: MachineRole,
+    pub preserve_days: i64,
+    pub automation: Automation,
+    pub schedule: Schedule,
+    pub quarantine_retention_days: i64,
+    pub quarantine_max_size_gb: u64,
+}
+
+impl MachineRole {
+    pub fn policy(self) -> RolePolicy {
+        let (preserve_days, automation, schedule, quarantine_retention_days, quarantine_max_size_gb) = match self {
+            Self::Laptop => (120, Automation::SuggestOnly, Schedule::Manual, 60, 5),
+            Self::Workstation => (90, Automation::Confirm, Schedule::Weekly, 30, 10),
+            // One day is the shortest retention the quarantine expresses (0 keeps forever)
+            Self::CiRunner => (7, Automation::Auto, Schedule::EveryRun, 1, 2),
+            Self::BuildServer => (30, Automation::Auto, Schedule::Daily, 7, 20),
+        };
+        RolePolicy {
+            role: self,
+            preserve_days,
+            automation,
+            schedule,
+            quarantine_retention_days,
+            quarantine_max_size_gb,
+        }
+    }
+
+    /// Guess the role of this machine
+    pub fn detect() -> Self {
+        let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Self::detect_from(|k| std::env::var(k).ok(), has_battery(), is_headless(), cpus)
+    }
+
+    fn detect_from(env: impl Fn(&str) -> Option<String>, battery: bool, headless: bool, cpus: usize) -> Self {
+        let ci = env("CI").is_some_and(|v| !v.is_empty() && v != "false" && v != "0")
+            || CI_VARIABLES.iter().any(|k| env(k).is_some());
+        if ci {
+            Self::CiRunner
+        } else if battery {
+            Self::Laptop
+        } else if headless && cpus >= 16 {
+            Self::BuildServer
+        } else {
+            Self::Workstation
+        }
+    }
+
+    /// The role in effect: the `--role` override, then `PACKAGEPURGE_ROLE`,
+    /// then the configured role, then detection
+    pub fn current() -> (Self, RoleSource) {
+        if let Some(role) = ROLE_OVERRIDE.get() {
+            return (*role, RoleSource::Flag);
+        }
+        if let Some(role) = std::env::var("PACKAGEPURGE_ROLE").ok().and_then(|v| v.parse().ok()) {
+            return (role, RoleSource::Env);
+        }
+        if let Some(role) = configured_role() {
+            return (role, RoleSource::Configured);
+        }
+        (Self::detect(), RoleSource::Detected)
+    }
+}
+
+static ROLE_OVERRIDE: OnceLock<MachineRole> = OnceLock::new();
+
+/// Use `role` for the rest of the process (the `--role` flag); only the first call takes effect
+pub fn override_role(role: MachineRole) {
+    let _ = ROLE_OVERRIDE.set(role);
+}
+
+impl RolePolicy {
+    /// Quarantine configuration used when none was saved
+    pub fn quarantine_config(&self) -> QuarantineConfig {
+        QuarantineConfig {
+            max_size_gb: self.quarantine_max_size_gb,
+            retention_days: self.quarantine_retention_days,
+            ..QuarantineConfig::default()
+        }
+    }
+}
+
+/// Variables set by common CI systems
+const CI_VARIABLES: [&str; 8] = [
+    "GITHUB_ACTIONS",
+    "GITLAB_CI",
+    "JENKINS_URL",
+    "BUILDKITE",
+    "CIRCLECI",
+    "TF_BUILD",
+    "TEAMCITY_VERSION",
+    "BITBUCKET_BUILD_NUMBER",
+];
+
+fn role_path() -> PathBuf {
+    crate::paths::config_dir().join("role.json")
+}
+
+/// Role saved with `role set`
+pub fn configured_role() -> Option<MachineRole> {
+    let text = fs::read_to_string(role_path()).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&text).ok()?;
+    value.get("role")?.as_str()?.parse().ok()
+}
+
+/// Save `role` (None removes the saved role, returning to detection)
+pub fn save_role(role: Option<MachineRole>) -> Result<()> {
+    save_role_impl(role).map_err(Error::lift(Error::Config))
+}
+
+fn save_role_impl(role: Option<MachineRole>) -> anyhow::Result<()> {
+    let path = role_path();
+    match role {
+        Some(role) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, serde_json::to_string_pretty(&serde_json::json!({ "role": role }))?)
+                .with_context(|| format!("Failed to save machine role to {:?}", path))?;
+        }
+        None if path.exists() => fs::remove_file(&path)?,
+        None => {}
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn has_battery() -> bool {
+    fs::read_dir("/sys/class/power_supply")
+        .map(|rd| rd.filter_map(|e| e.ok()).any(|e| {
+            fs::read_to_string(e.path().join("type")).is_ok_and(|t| t.trim() == "Battery")
+        }))
+        .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_battery() -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn is_headless() -> bool {
+    std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_headless() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_role() {
+        let none = |_: &str| None;
+        assert_eq!(MachineRole::detect_from(none, true, false, 8), MachineRole::Laptop);
+        assert_eq!(MachineRole::detect_from(none, false, false, 8), MachineRole::Workstation);
+        assert_eq!(MachineRole::detect_from(none, false, true, 64), MachineRole::BuildServer);
+        assert_eq!(MachineRole::detect_from(none, false, true, 4), MachineRole::Workstation);
+
+        let ci = |k: &str| (k == "GITHUB_ACTIONS").then(|| "true".to_string());
+        assert_eq!(MachineRole::detect_from(ci, true, false, 2), MachineRole::CiRunner);
+        let ci_false = |k: &str| (k == "CI").then(|| "false".to_string());
+        assert_eq!(MachineRole::detect_from(ci_false, false, false, 2), MachineRole::Workstation);
+    }
+
+    #[test]
+    fn test_role_policies() {
+        let laptop = MachineRole::Laptop.policy();
+        let ci = MachineRole::CiRunner.policy();
+        assert_eq!(laptop.automation, Automation::SuggestOnly);
+        assert_eq!(ci.automation, Automation::Auto);
+        assert!(ci.preserve_days < laptop.preserve_days);
+        assert!(ci.quarantine_config().retention_days < laptop.quarantine_config().retention_days);
+        assert_eq!("ci".parse::<MachineRole>(), Ok(MachineRole::CiRunner));
+        assert_eq!(MachineRole::BuildServer.to_string().parse::<MachineRole>(), Ok(MachineRole::BuildServer));
+        assert!("server".parse::<MachineRole>().is_err());
+    }
+}
@@ -1,27 +1,86 @@
-mod types;
-mod scanner;
-mod safety;
-mod optimization;
-mod cache;
-mod ml;
-mod arc_lfu;
-mod lockfiles;
-mod symlink;
-mod usage_tracker;
-mod scan_cache;
-mod feature_store;
-
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use optimization::{plan_basic_cleanup, RulesConfig, OptimizationEngine};
-use safety::{get_quarantine_stats, cleanup_quarantine, save_config};
-use scan_cache::ScanCache;
+use packagepurge_core::{access_watch, approval, archive, backup_exclude, bench, bundle, cloud_sync, config_file, config_lint, compliance, cross_dedup, digest, display, exec, feature_pipeline, feature_store, fs_snapshot, hash_queue, hooks, impact, install_events, overhead, paths, pm_verify, privacy, project_activity, purge, project_tags, reconcile, reinstall, repo_activity, risk, run_manifest, safety, scan_import, scan_rules, scanner, timings, top, tree_share, version_retention, wide_scan, wsl};
+use packagepurge_core::scan_import::ImportFormat;
+use packagepurge_core::output::{self, OutputFormat};
+use packagepurge_core::top::TopBy;
+use packagepurge_core::hooks::HookEvent;
+use packagepurge_core::paths::StateOverrides;
+use packagepurge_core::canonical::CanonicalStrategy;
+use packagepurge_core::eviction::PolicyKind;
+use packagepurge_core::ml::PredictiveOptimizer;
+use packagepurge_core::ci_clean::{ci_clean, default_tool_caches, CiCleanConfig, PipelineKey};
+use packagepurge_core::integrity::{verify_scan, IntegrityStatus};
+use packagepurge_core::compiler_caches::{detect_compiler_caches, parse_size, plan_compiler_cache_trim, trim_cache};
+use packagepurge_core::editor_caches::{plan_editor_cleanup, scan_editor_caches, EditorLocations};
+use packagepurge_core::machine_role::{self, MachineRole};
+use packagepurge_core::model_caches::{plan_model_cleanup, scan_model_caches, ModelLocations};
+use packagepurge_core::graph::{parse_package_spec, DependencyGraph};
+use packagepurge_core::relocate::relocate_store;
+use packagepurge_core::store_index::StoreIndex;
+use packagepurge_core::hash_queue::{HashQueue, JobKind};
+use packagepurge_core::types::{ActivityClass, DryRunReport, PlanItem, QuarantineRecord, ScanOutput};
+use packagepurge_core::canary::{self, CanaryBasis};
+use packagepurge_core::soft_disable::{self, SoftMode};
+use packagepurge_core::symlink::{get_global_store_path, open_file_snapshot, DedupMode};
+use packagepurge_core::optimization::{plan_basic_cleanup, plan_symlinking, RulesConfig, OptimizationEngine};
+use packagepurge_core::safety::{get_quarantine_stats, cleanup_quarantine, save_config};
+use packagepurge_core::progress::{CancellationToken, JsonLinesProgress, NoopProgress, OperationContext, ProgressSink};
+use packagepurge_core::scan_cache::{CacheValidation, ScanCache};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "packagepurge-core", version)]
 struct Cli {
+    /// Emit JSON progress events on stderr
+    #[arg(long, global = true)]
+    progress: bool,
+    /// How cached package sizes are validated: fast (directory fingerprint)
+    /// or thorough (also every file's mtime and size)
+    #[arg(long, global = true, default_value_t = CacheValidation::Fast)]
+    cache_validation: CacheValidation,
+    /// TOML file with default flags [env: PACKAGEPURGE_CONFIG] (default:
+    /// config.toml in the config directory)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Machine role whose default policy applies: laptop, workstation,
+    /// ci-runner or build-server (default: configured or detected)
+    #[arg(long, global = true)]
+    role: Option<MachineRole>,
+    /// Directory for all PackagePurge state [env: PACKAGEPURGE_STATE_DIR] (default: platform state dir)
+    #[arg(long, global = true)]
+    state_dir: Option<PathBuf>,
+    /// Directory for the scan cache [env: PACKAGEPURGE_CACHE_DIR] (default: platform cache dir)
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+    /// Feature store database file [env: PACKAGEPURGE_DB]
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+    /// Quarantine directory [env: PACKAGEPURGE_QUARANTINE_DIR]
+    #[arg(long, global = true)]
+    quarantine_dir: Option<PathBuf>,
+    /// Global store directory [env: PACKAGEPURGE_STORE_DIR]
+    #[arg(long, global = true)]
+    store_dir: Option<PathBuf>,
+    /// Refuse every quarantine, symlink, store or cache mutation [env: PACKAGEPURGE_READ_ONLY]
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Scan, and hash quarantined targets, in read-only filesystem snapshots
+    /// (Btrfs, ZFS, LVM or VSS) so results reflect one moment [env: PACKAGEPURGE_SNAPSHOT]
+    #[arg(long, global = true)]
+    snapshot: bool,
+    /// Narrow every scan to projects carrying any of these tags (repeatable or
+    /// comma separated) and what they install [env: PACKAGEPURGE_TAGS]
+    #[arg(long = "tag", global = true, value_delimiter = ',')]
+    tags: Vec<String>,
+    /// Print the time spent walking, reading lockfiles, sizing, planning,
+    /// hashing and moving to stderr when done [env: PACKAGEPURGE_TIMINGS]
+    #[arg(long, global = true)]
+    timings: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -33,44 +92,282 @@ enum Commands {
         #[arg(short, long)] 
         paths: Vec<PathBuf>,
         /// Skip cache (force fresh scan)
-        #[arg(long)]
+        #[arg(long, conflicts_with = "lazy_sizes")]
         no_cache: bool,
+        /// Record packages without sizing them; sizes come from the cache where known
+        #[arg(long)]
+        lazy_sizes: bool,
+        /// Write one JSON object per line as records are produced, ending with a summary, instead of holding them all (not recorded for `top`)
+        #[arg(long)]
+        stream: bool,
+        /// Output format: json, ndjson, csv or table (default: table on a
+        /// terminal, json otherwise)
+        #[arg(long)]
+        format: Option<OutputFormat>,
     },
     /// Produce cleanup plan without mutating filesystem
     DryRun { 
-        #[arg(short, long, default_value_t = 90)] 
-        preserve_days: i64, 
+        /// Days to preserve packages (default: the machine role's)
+        #[arg(short = 'd', long)] 
+        preserve_days: Option<i64>, 
         #[arg(short, long)] 
-        paths: Vec<PathBuf> 
+        paths: Vec<PathBuf>,
+        /// Also evict or deduplicate packages patched via patch-package or pnpm patchedDependencies
+        #[arg(long)]
+        include_patched: bool,
+        /// Skip sizing packages up front; only plan candidates are sized (from the cache where possible)
+        #[arg(long)]
+        lazy_sizes: bool,
+        /// Ask each project's package manager which packages it still has installed and
+        /// hold back orphaned items it lists (reported under `warnings`)
+        #[arg(long)]
+        verify_with_pm: bool,
+        /// Plan from a scan saved by `scan` or `import` (`-` for stdin) instead of scanning
+        #[arg(long, conflicts_with_all = ["paths", "lazy_sizes", "verify_with_pm"])]
+        from_scan: Option<PathBuf>,
+        /// List the riskiest items first
+        #[arg(long)]
+        sort_by_risk: bool,
+        /// Output format: json, ndjson, csv or table (default: table on a
+        /// terminal, json otherwise)
+        #[arg(long)]
+        format: Option<OutputFormat>,
+    },
+    /// Turn an ncdu JSON export, `du` output or a WizTree CSV into a scan
+    /// that `dry-run --from-scan` can plan from
+    Import {
+        /// Export file, or `-` for stdin
+        file: PathBuf,
+        /// Export format (default: detected from the content)
+        #[arg(long, value_enum)]
+        format: Option<ImportKind>,
+        /// Bytes per unit of unsuffixed `du` sizes (1024 for `du -k`, 1 for `du -b`)
+        #[arg(long, default_value_t = 1024)]
+        du_block_size: u64,
     },
     /// Move targets to quarantine (atomic move) based on paths provided
+    #[command(args_conflicts_with_subcommands = true)]
     Quarantine { 
-        #[arg(required=true)] 
+        #[command(subcommand)]
+        action: Option<QuarantineAction>,
         targets: Vec<PathBuf>,
-        /// Skip SHA256 verification for faster cleanup
+        /// Leave the SHA256 of each entry to the background hash queue
         #[arg(long)]
         fast: bool,
+        /// Roots targets must live under (adds to the configured allowed_roots)
+        #[arg(long)]
+        roots: Vec<PathBuf>,
+        /// Quarantine targets that fail the safety checks (asks for confirmation)
+        #[arg(long)]
+        force: bool,
+        /// Skip the confirmation prompt for --force
+        #[arg(long)]
+        yes: bool,
+        /// Leave a marker in projects whose node_modules is purged so
+        /// `restore-deps` can reinstall them later
+        #[arg(long)]
+        reinstall_on_demand: bool,
+        /// Pack each entry into a zstd-compressed tarball (rollback unpacks it)
+        #[arg(long)]
+        compress: bool,
     },
     /// Rollback by id or latest
     Rollback {
         #[arg(long)] id: Option<String>,
         #[arg(long)] latest: bool,
     },
+    /// Print the before/after manifest of an apply, or list recorded runs
+    ShowRun {
+        /// Run id from an apply's output, or `latest`
+        id: Option<String>,
+    },
     /// Optimize with ML/LRU and symlinking (dry run)
     Optimize {
-        #[arg(short, long, default_value_t = 90)] preserve_days: i64,
+        /// Days to preserve packages (default: the machine role's)
+        #[arg(short = 'd', long)] preserve_days: Option<i64>,
         #[arg(short, long)] paths: Vec<PathBuf>,
+        /// Also plan symlinking duplicates (default: config.toml's `enable_symlinking`)
         #[arg(long)] enable_symlinking: bool,
         #[arg(long)] enable_ml: bool,
-        #[arg(long, default_value_t = 1000)] lru_max_packages: usize,
-        #[arg(long, default_value_t = 10_000_000_000)] lru_max_size_bytes: u64,
+        /// Packages the LRU cache keeps (default: config.toml's, else 1000)
+        #[arg(long)] lru_max_packages: Option<usize>,
+        /// Bytes the LRU cache keeps (default: config.toml's, else 10 GB)
+        #[arg(long)] lru_max_size_bytes: Option<u64>,
+        /// Keep the old packages this policy would still cache (lru, slru, lfu, arc or
+        /// w-tiny-lfu), with room for --lru-max-packages; its statistics go under `eviction`
+        #[arg(long)]
+        eviction_policy: Option<PolicyKind>,
+        /// Also evict or deduplicate packages patched via patch-package or pnpm patchedDependencies
+        #[arg(long)]
+        include_patched: bool,
+        /// Ask GitHub/GitLab about each project's origin remote (last push, open
+        /// pull requests, archived) to judge whether it is still alive
+        #[arg(long)]
+        remote_activity: bool,
+        /// Skip sizing packages up front; only plan candidates are sized. The
+        /// LRU byte budget then only sees sizes the scan cache already knew
+        #[arg(long)]
+        lazy_sizes: bool,
+        /// Ask each project's package manager which packages it still has installed and
+        /// hold back orphaned items it lists (reported under `warnings`)
+        #[arg(long)]
+        verify_with_pm: bool,
+        /// List the riskiest items first
+        #[arg(long)]
+        sort_by_risk: bool,
+        /// Output format: json, ndjson, csv or table (default: table on a
+        /// terminal, json otherwise)
+        #[arg(long)]
+        format: Option<OutputFormat>,
     },
     /// Execute symlinking for duplicate packages
     Symlink {
         #[arg(short, long)] paths: Vec<PathBuf>,
+        /// List planned store moves, symlinks and blockers without mutating
+        #[arg(long)]
+        dry_run: bool,
+        /// Command run in each affected project after symlinking; the project's
+        /// packages are restored if it fails. `{name}` expands per package.
+        #[arg(long)]
+        verify: Option<String>,
+        /// Which duplicate copy to keep: first, recent-project, fastest-disk or verified-integrity
+        #[arg(long, default_value_t = CanonicalStrategy::First)]
+        canonical: CanonicalStrategy,
+        /// symlink, or hardlink to keep real directories with files linked to the
+        /// store. Projects can override it with `packagepurge.dedupMode` in package.json
+        #[arg(long, default_value_t = DedupMode::Symlink)]
+        mode: DedupMode,
+        /// Also evict or deduplicate packages patched via patch-package or pnpm patchedDependencies
+        #[arg(long)]
+        include_patched: bool,
+    },
+    /// Share one node_modules tree between projects that install identical ones
+    ShareTrees {
+        #[arg(short, long)] paths: Vec<PathBuf>,
+        /// List the trees that could be shared, and their blockers, without mutating
+        #[arg(long)]
+        dry_run: bool,
+        /// Roots the trees must live under besides the paths (adds to the
+        /// configured allowed_roots)
+        #[arg(long)]
+        roots: Vec<PathBuf>,
+    },
+    /// Packages stored with identical content by more than one tool (npm,
+    /// Yarn and pnpm caches, Cargo registry indexes, ...)
+    Duplicates {
+        #[arg(short, long)] paths: Vec<PathBuf>,
+        /// Hard link the unblocked copies to one global store entry
+        #[arg(long)]
+        consolidate: bool,
+    },
+    /// Keep node_modules, package caches and the global store out of
+    /// backups (CACHEDIR.TAG, Time Machine exclusion, Windows attributes)
+    BackupExclude {
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+        /// Take the trees from a scan saved by `scan` or `import` instead of scanning
+        #[arg(long)]
+        from_scan: Option<PathBuf>,
+        /// Remove the exclusions this command added
+        #[arg(long)]
+        undo: bool,
+        /// List what would change without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Scan and plan a generated tree of the given shape several times and
+    /// report throughput, to compare releases and machines
+    Bench {
+        /// Projects in the tree
+        #[arg(long, default_value_t = 20)]
+        projects: usize,
+        /// Top-level packages per project
+        #[arg(long, default_value_t = 50)]
+        packages: usize,
+        /// Nesting depth of each top-level package's dependencies
+        #[arg(long, default_value_t = 2)]
+        depth: usize,
+        /// Files per package
+        #[arg(long, default_value_t = 4)]
+        files: usize,
+        /// Size of each file
+        #[arg(long, default_value_t = 2048)]
+        file_bytes: usize,
+        /// Fraction of packages installed at the same version in every project
+        #[arg(long, default_value_t = 0.5)]
+        shared: f64,
+        /// Scans and plans of the tree
+        #[arg(long, default_value_t = 3)]
+        iterations: usize,
+        /// Generate the tree here and keep it (default: a temporary directory)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Record package uses in the feature store as they happen, until
+    /// Ctrl-C, by watching the node_modules trees of the scanned projects
+    Watch {
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+        /// Seconds before another use of the same package is recorded
+        #[arg(long, default_value_t = 60)]
+        min_interval: u64,
+        /// Stop after this many seconds
+        #[arg(long)]
+        duration: Option<u64>,
     },
     /// Show statistics about quarantine and cache
     Stats,
+    /// Largest, fastest-growing or stalest packages as of the last scans,
+    /// without scanning again
+    Top {
+        #[arg(long, value_enum, default_value = "size")]
+        by: TopKind,
+        /// Packages listed
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Days back growth is measured over
+        #[arg(long, default_value_t = 30)]
+        growth_days: i64,
+    },
+    /// Classify projects as active, dormant or dead from git history, file
+    /// times, recorded events and editor recent-project lists
+    Projects {
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+    },
+    /// Share settings between machines as one signed file
+    Bundle {
+        #[command(subcommand)]
+        action: BundleAction,
+    },
+    /// Tag projects by hand or by rule; `--tag` narrows any command to them
+    Tag {
+        #[command(subcommand)]
+        action: Option<TagAction>,
+    },
+    /// Show or set the activity thresholds and the classes whose
+    /// `node_modules` dry-run and optimize plan to purge whole
+    Activity {
+        #[command(subcommand)]
+        action: Option<ActivityAction>,
+    },
+    /// Show or set how many versions of each package caches and the store
+    /// keep; dry-run and optimize plan the older ones
+    KeepVersions {
+        #[command(subcommand)]
+        action: Option<KeepVersionsAction>,
+    },
+    /// List project `node_modules` inside OneDrive, Dropbox or iCloud Drive
+    /// folders, or keep one out of the sync
+    CloudSync {
+        #[command(subcommand)]
+        action: Option<CloudSyncAction>,
+    },
+    /// Jobs of the ML predictor
+    Ml {
+        #[command(subcommand)]
+        action: MlAction,
+    },
     /// Cleanup old quarantine entries based on retention policy
     CleanupQuarantine {
         /// Maximum quarantine size in GB
@@ -79,47 +376,1441 @@ enum Commands {
         /// Days to retain quarantine entries
         #[arg(long)]
         retention_days: Option<i64>,
+        /// Pack new entries into zstd-compressed tarballs (true) or keep them as trees (false)
+        #[arg(long)]
+        compress: Option<bool>,
     },
     /// Clear the scan cache (force fresh scans)
     ClearCache,
+    /// Export the project/package dependency graph
+    Graph {
+        #[arg(short, long)] paths: Vec<PathBuf>,
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+        /// Only include this project and what it (transitively) depends on
+        #[arg(long)]
+        project: Option<PathBuf>,
+        /// Packages not accessed for this many days are marked stale
+        #[arg(long, default_value_t = 90)]
+        preserve_days: i64,
+    },
+    /// List every project and package that requires a package (name[@version])
+    Rdeps {
+        package: String,
+        #[arg(short, long)] paths: Vec<PathBuf>,
+    },
+    /// Hash installed packages against their lockfile integrity (via the npm
+    /// cache) and report tampered, patched or corrupted packages
+    Verify {
+        #[arg(short, long)] paths: Vec<PathBuf>,
+        /// Queue the checks for `hash-queue run` instead of running them now
+        #[arg(long)]
+        background: bool,
+    },
+    /// Inspect and work through the queue of deferred hashing (quarantine
+    /// checksums, store content hashes, background verification, project
+    /// rescans)
+    HashQueue {
+        #[command(subcommand)]
+        action: Option<HashQueueAction>,
+    },
+    /// Record a package-manager install: forget the project's scan cache
+    /// entries and queue a rescan. Meant for a `postinstall` script
+    Installed {
+        /// Project installed into (default: the working directory)
+        project: Option<PathBuf>,
+    },
+    /// Report VS Code and JetBrains caches with their sizes and stale entries
+    EditorCaches {
+        /// Entries not modified for this many days are stale
+        #[arg(long, default_value_t = 90)]
+        preserve_days: i64,
+        /// Print a cleanup plan of the stale entries instead
+        #[arg(long)]
+        plan: bool,
+    },
+    /// Report Hugging Face, Ollama, torch hub and Keras models with their sizes and ages
+    ModelCaches {
+        /// Models not used for this many days are stale
+        #[arg(long, default_value_t = 60)]
+        retention_days: i64,
+        /// Print a cleanup plan of the stale models instead
+        #[arg(long)]
+        plan: bool,
+    },
+    /// Report ccache/sccache directories and optionally trim them to a size budget
+    CompilerCaches {
+        /// Delete least recently used entries until each cache fits its budget
+        #[arg(long)]
+        trim: bool,
+        /// Budget such as 2G or 500M (default: each cache's configured maximum)
+        #[arg(long, value_parser = parse_budget)]
+        budget: Option<u64>,
+        /// With --trim, only report what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage the global package store
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+    /// Show what this machine sees of the WSL/Windows boundary, or scan
+    /// both sides into one report
+    Wsl {
+        #[command(subcommand)]
+        action: Option<WslAction>,
+    },
+    /// Inspect and maintain the incremental scan cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Clean a CI runner: old build workspaces and oversized tool caches.
+    /// Deletes without quarantine unless --quarantine is given
+    CiClean {
+        /// Directory holding the runner's build workspaces
+        #[arg(long)]
+        workspace_root: PathBuf,
+        /// Globs relative to the root matching build directories (e.g. `*/*`, `workspace/*`)
+        #[arg(long = "pattern", required = true)]
+        patterns: Vec<String>,
+        /// Most recent builds kept per pipeline
+        #[arg(long, default_value_t = 3)]
+        keep: usize,
+        /// Group builds by parent directory, or by name without an `@suffix` (Jenkins)
+        #[arg(long, default_value_t = PipelineKey::Parent)]
+        pipeline_key: PipelineKey,
+        /// Tool caches to check (default: npm, yarn, pnpm, pip, Go, Gradle, Maven, ccache, sccache)
+        #[arg(long = "tool-cache")]
+        tool_caches: Vec<PathBuf>,
+        /// Clear tool caches larger than this (e.g. 5G); caches are left alone without it
+        #[arg(long)]
+        cache_budget: Option<String>,
+        /// Move removals to quarantine instead of deleting them
+        #[arg(long)]
+        quarantine: bool,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List the projects whose installs a plan (dry-run/optimize output)
+    /// would leave incomplete, grouped by activity class
+    Impact {
+        /// Plan JSON file, or - for stdin
+        plan: PathBuf,
+        /// Paths the plan was made from
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+        /// Take projects and dependencies from a scan saved by `scan` or `import` instead of scanning
+        #[arg(long, conflicts_with = "paths")]
+        from_scan: Option<PathBuf>,
+    },
+    /// Check this machine against an organization's policy file (cache size,
+    /// stale node_modules, backup exclusions); exits 1 when a check fails
+    Compliance {
+        /// Policy JSON file
+        policy: PathBuf,
+        /// Paths to check
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+        /// Take packages from a scan saved by `scan` or `import` instead of scanning
+        #[arg(long, conflicts_with = "paths")]
+        from_scan: Option<PathBuf>,
+        /// Also write the remediation plan here, ready for `approve` or `apply`
+        #[arg(long)]
+        plan_out: Option<PathBuf>,
+    },
+    /// Approve a plan (dry-run/optimize output) for someone else to apply;
+    /// prints the approval token
+    Approve {
+        /// Plan JSON file, or - for stdin
+        plan: PathBuf,
+    },
+    /// Quarantine every item of a plan (dry-run/optimize output). Plans above
+    /// the approval threshold need a token from a second person
+    Apply {
+        /// Plan JSON file, or - for stdin
+        plan: PathBuf,
+        /// Token printed by `approve`
+        #[arg(long)]
+        approval: Option<String>,
+        /// Leave the SHA256 of each entry to the background hash queue
+        #[arg(long)]
+        fast: bool,
+        /// Roots targets must live under (adds to the configured allowed_roots)
+        #[arg(long)]
+        roots: Vec<PathBuf>,
+        /// Leave a marker in projects whose node_modules is purged so
+        /// `restore-deps` can reinstall them later
+        #[arg(long)]
+        reinstall_on_demand: bool,
+        /// Quarantine the items the plan asks to confirm without asking
+        /// (outside package and cache directories, from a scan of `~` or `/`)
+        #[arg(short, long)]
+        yes: bool,
+        /// Quarantine only this percentage of the plan, picked at random;
+        /// applying the plan again applies the rest once the canary passed
+        #[arg(long, value_name = "PERCENT")]
+        canary: Option<f64>,
+        /// Whether the canary percentage counts items or bytes
+        #[arg(long, default_value_t = CanaryBasis::Count)]
+        canary_by: CanaryBasis,
+        /// Hours to watch the canary for reinstalls and rollbacks
+        #[arg(long, default_value_t = 24)]
+        observe_hours: i64,
+        /// Disable the targets in place for a grace period instead of
+        /// quarantining them; `soft-disabled commit` quarantines them after
+        #[arg(long, conflicts_with = "canary")]
+        soft_disable: bool,
+        /// How targets are disabled: rename (with a suffix) or permissions
+        #[arg(long, default_value_t = SoftMode::Rename)]
+        soft_mode: SoftMode,
+        /// Hours a soft-disabled batch must go without complaints
+        #[arg(long, default_value_t = 48)]
+        grace_hours: i64,
+        /// Leave out items whose risk score (0-100) is above this, or that
+        /// have no score
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        max_risk: Option<u8>,
+    },
+    /// Quarantine every item of a plan after a confirmation, then report the
+    /// bytes reclaimed. Plans from --paths like `dry-run` unless given --plan
+    Purge {
+        /// Plan JSON file (dry-run/optimize output), or - for stdin
+        #[arg(long, conflicts_with_all = ["paths", "preserve_days", "include_patched"])]
+        plan: Option<PathBuf>,
+        /// Days to preserve packages (default: the machine role's)
+        #[arg(short = 'd', long)]
+        preserve_days: Option<i64>,
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+        /// Also evict packages patched via patch-package or pnpm patchedDependencies
+        #[arg(long)]
+        include_patched: bool,
+        /// Token printed by `approve`, for plans above the approval threshold
+        #[arg(long)]
+        approval: Option<String>,
+        /// Roots targets must live under (adds to the configured allowed_roots
+        /// and the scanned paths)
+        #[arg(long)]
+        roots: Vec<PathBuf>,
+        /// Leave the SHA256 of each entry to the background hash queue
+        #[arg(long)]
+        fast: bool,
+        /// Leave a marker in projects whose node_modules is purged so
+        /// `restore-deps` can reinstall them later
+        #[arg(long)]
+        reinstall_on_demand: bool,
+        /// Purge without asking, including items the plan asks to confirm
+        #[arg(short, long)]
+        yes: bool,
+        /// Leave out items whose risk score (0-100) is above this, or that
+        /// have no score
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        max_risk: Option<u8>,
+    },
+    /// List soft-disabled batches, restore one instantly, or quarantine the
+    /// batches whose grace period passed without complaints
+    SoftDisabled {
+        #[command(subcommand)]
+        action: Option<SoftAction>,
+    },
+    /// List canary purges with their verdicts, or abort one
+    Canary {
+        #[command(subcommand)]
+        action: Option<CanaryAction>,
+    },
+    /// Show or configure when plans need a second person's approval
+    Approval {
+        #[command(subcommand)]
+        action: Option<ApprovalAction>,
+    },
+    /// Summarize growth, reclaimed space, pending approvals and expiring
+    /// quarantine for a period, and deliver it
+    Digest {
+        #[command(subcommand)]
+        action: Option<DigestAction>,
+    },
+    /// Show or set how sizes, dates and report strings are displayed
+    Display {
+        #[command(subcommand)]
+        action: Option<DisplayAction>,
+    },
+    /// Show or set what the feature store may record about you
+    Privacy {
+        #[command(subcommand)]
+        action: Option<PrivacyAction>,
+    },
+    /// Maintain the feature store database
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// Show or cap the disk space held by the quarantine, store and databases
+    Overhead {
+        #[command(subcommand)]
+        action: Option<OverheadAction>,
+    },
+    /// List the lifecycle hooks configured in hooks.json
+    Hooks,
+    /// Show the globs in scan_rules.json that keep scans out of irrelevant trees
+    ScanRules,
+    /// Report each project's remote repository activity (GitHub/GitLab) and
+    /// the liveness it implies
+    RepoActivity {
+        #[arg(short, long)] paths: Vec<PathBuf>,
+        /// Days without a push after which a repository counts as dormant (default: the machine role's)
+        #[arg(short = 'd', long)]
+        preserve_days: Option<i64>,
+    },
+    /// Pack a project's node_modules and build artifacts into cold storage
+    /// and delete the originals
+    Archive {
+        project: PathBuf,
+        /// Directory or s3://bucket/prefix to store the archive in (default: <state dir>/archives)
+        #[arg(long)]
+        to: Option<String>,
+        /// Extra directories to pack, relative to the project
+        #[arg(long)]
+        include: Vec<String>,
+        /// List the directories that would be archived without touching them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Restore a project's most recent archive
+    Unarchive {
+        project: PathBuf,
+    },
+    /// Reinstall the dependencies of projects purged with --reinstall-on-demand
+    /// (npm ci, pnpm install --frozen-lockfile, ...)
+    RestoreDeps {
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+        /// Print the commands without running them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Run a command in the current project, first restoring its quarantined
+    /// packages and recording the run as usage of its dependencies
+    Exec {
+        /// Do not restore quarantined packages before running
+        #[arg(long)]
+        no_restore: bool,
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// List recorded archives
+    Archives {
+        /// Include archives that were already restored
+        #[arg(long)]
+        all: bool,
+    },
+    /// Show or configure the machine role that selects default policies
+    Role {
+        #[command(subcommand)]
+        action: Option<RoleAction>,
+    },
+    /// Show the settings in effect, or check the settings files for mistakes
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Entry counts, cached bytes and expired entries
+    Stats,
+    /// Delete every entry, forcing the next scan to walk and size everything
+    Clear,
+    /// Delete expired entries and entries for paths that no longer exist
+    Prune,
+}
+
+#[derive(Subcommand)]
+enum WslAction {
+    /// Side, Windows drives or distributions, and the other side's roots (the default)
+    Show,
+    /// Scan the given paths (default: the working directory) and the other
+    /// side's home directories; report packages per side with translated paths
+    Scan {
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RoleAction {
+    /// Active role, where it came from and its policy (the default)
+    Show,
+    /// Save a role, overriding detection
+    Set { role: MachineRole },
+    /// Forget the saved role and go back to detection
+    Reset,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Settings in effect for the machine role (`--role`, else this
+    /// machine's): its policy with the saved settings on top (the default)
+    Show,
+    /// Parse every settings file and report unknown keys, invalid globs
+    /// and values, and rules that contradict each other; exits 1 on errors
+    Validate {
+        /// Also print the settings in effect, as `config show` does
+        #[arg(long)]
+        effective: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CanaryAction {
+    /// Canaries with their verdicts (the default)
+    Show,
+    /// Forget a canary; its quarantined items stay where they are
+    Abort {
+        /// Plan hash, as listed
+        plan_hash: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SoftAction {
+    /// Batches with their state (the default)
+    Show,
+    /// Re-enable a batch or a single item; counts as a complaint
+    Restore {
+        /// Batch id, or the original path of an item
+        target: String,
+        /// Quarantine an original path that was recreated, then restore over it
+        #[arg(long)]
+        force: bool,
+    },
+    /// Quarantine every batch whose grace period passed without complaints
+    Commit,
+}
+
+#[derive(Subcommand)]
+enum ApprovalAction {
+    /// Threshold and token lifetime (the default)
+    Show,
+    /// Create this account's signing key and list it in the approvers
+    /// keyring, so it can `approve` plans
+    Enroll,
+    /// Accounts in the approvers keyring
+    Approvers,
+    /// Require approval for plans above a size
+    Set {
+        /// Size such as 50G; 0 disables approvals
+        #[arg(long, value_parser = parse_budget)]
+        threshold: u64,
+        /// Hours a token stays valid
+        #[arg(long, default_value_t = 24)]
+        ttl_hours: i64,
+    },
+}
+
+#[derive(Subcommand)]
+enum PrivacyAction {
+    /// Current settings (the default)
+    Show,
+    /// Replace the settings; they apply to new records, `db redact` rewrites old ones
+    Set {
+        /// Store projects, events and scan roots under a hash of their path
+        #[arg(long)]
+        hash_project_paths: bool,
+        /// Keep only the program of recorded commands
+        #[arg(long)]
+        drop_command_args: bool,
+        /// Record no behavior events at all
+        #[arg(long)]
+        disable_event_logging: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ActivityAction {
+    /// Saved settings (the default)
+    Show,
+    /// Replace the saved settings
+    Set {
+        /// Days since last activity within which a project is active
+        #[arg(long, default_value_t = 30)]
+        active_days: i64,
+        /// Days since last activity after which a project is dead
+        #[arg(long, default_value_t = 365)]
+        dead_days: i64,
+        /// Class whose projects' node_modules are purged whole (repeatable)
+        #[arg(long = "purge-node-modules", value_enum)]
+        purge_node_modules: Vec<ActivityKind>,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeepVersionsAction {
+    /// Saved settings (the default)
+    Show,
+    /// Replace the saved settings
+    Set {
+        /// Versions kept of packages no rule matches
+        #[arg(long)]
+        keep: Option<usize>,
+        /// `PATTERN=N`: keep N versions of packages whose name matches the glob (repeatable, first match wins)
+        #[arg(long = "rule", value_parser = parse_version_rule)]
+        rules: Vec<version_retention::VersionRule>,
+    },
+    /// Stop limiting versions
+    Off,
+}
+
+#[derive(Subcommand)]
+enum MlAction {
+    /// Compute and store the feature vector of every package in the latest
+    /// scan, so optimize --enable-ml only looks them up
+    ComputeFeatures,
+}
+
+#[derive(Subcommand)]
+enum CloudSyncAction {
+    /// Sync roots and the synced `node_modules` below the paths (the default)
+    List {
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+    },
+    /// Exclude a `node_modules` in place: ignored by Dropbox, `.nosync` for iCloud
+    Exclude {
+        node_modules: PathBuf,
+    },
+    /// Move a `node_modules` out of the sync root and link it back
+    Relocate {
+        node_modules: PathBuf,
+        /// Directory outside every sync root to move it below
+        #[arg(long)]
+        to: PathBuf,
+    },
+}
+
+fn parse_version_rule(s: &str) -> Result<version_retention::VersionRule, String> {
+    let (pattern, keep) = s.rsplit_once('=').ok_or_else(|| format!("invalid rule `{}` (expected PATTERN=N)", s))?;
+    let keep = keep.parse().map_err(|_| format!("invalid version count in `{}`", s))?;
+    Ok(version_retention::VersionRule { pattern: pattern.to_string(), keep })
+}
+
+#[derive(Subcommand)]
+enum DisplayAction {
+    /// Settings in effect for this run, environment overrides included (the default)
+    Show,
+    /// Replace the saved settings
+    Set {
+        /// binary (KiB, MiB) or si (kB, MB)
+        #[arg(long, default_value = "binary")]
+        size_units: display::SizeUnits,
+        /// absolute or relative (`3 days ago`)
+        #[arg(long, default_value = "absolute")]
+        dates: display::DateStyle,
+        /// local or utc, for absolute dates
+        #[arg(long, default_value = "local")]
+        time_zone: display::TimeZoneChoice,
+        /// Language of report strings (en, de, es; others fall back to en)
+        #[arg(long, default_value = "en")]
+        locale: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DigestAction {
+    /// Print the digest for the current period (the default)
+    Show {
+        /// Render HTML instead of text
+        #[arg(long)]
+        html: bool,
+    },
+    /// Deliver the digest to the configured file, sendmail address and webhook
+    Send {
+        /// Only send once the configured period has passed since the last digest
+        #[arg(long)]
+        if_due: bool,
+    },
+    /// Replace the digest settings
+    Set {
+        /// Days a digest covers
+        #[arg(long, default_value_t = 7)]
+        period_days: i64,
+        /// List quarantine entries expiring within this many days
+        #[arg(long, default_value_t = 7)]
+        expiry_window_days: i64,
+        /// Deliver HTML instead of text to the file and sendmail
+        #[arg(long)]
+        html: bool,
+        /// File the digest is written to
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Address the digest is mailed to through `sendmail -t`
+        #[arg(long)]
+        sendmail_to: Option<String>,
+        /// URL the digest is POSTed to as JSON
+        #[arg(long)]
+        webhook_url: Option<String>,
+    },
+    /// Current settings
+    Config,
+}
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Rewrite recorded data to match the current privacy settings
+    Redact,
+}
+
+#[derive(Subcommand)]
+enum OverheadAction {
+    /// Current footprint, savings and cap (the default)
+    Show,
+    /// Cap the footprint and set when to warn about it
+    Set {
+        /// Size such as 20G; 0 removes the cap
+        #[arg(long, value_parser = parse_budget)]
+        max: u64,
+        /// Warn when the footprint exceeds this percentage of the space saved
+        #[arg(long, default_value_t = 50.0)]
+        warn_percent: f64,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreAction {
+    /// Relocate the store and rewrite every project symlink pointing into it
+    Move {
+        new_path: PathBuf,
+        /// Walk these roots for store symlinks instead of using the reference index
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+    },
+    /// Check every indexed symlink still resolves to its store entry
+    Verify,
+    /// Queue a content hash of every store entry; entries that changed since
+    /// their last hash are reported as failed by `hash-queue`
+    Hash,
+    /// Delete store entries no indexed symlink references. Links created by
+    /// older versions are only indexed once a scan has covered their project.
+    Gc {
+        /// Only list what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum BundleAction {
+    /// Pack this machine's shareable settings into a signed bundle
+    Export {
+        /// Bundle file to write
+        out: PathBuf,
+        /// Name shown when the bundle is imported
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+        /// Signing key (default: bundle.key in the config directory, created if missing)
+        #[arg(long)]
+        key: Option<PathBuf>,
+    },
+    /// Verify a bundle and install its settings, backing up the ones replaced
+    Import {
+        bundle: PathBuf,
+        /// Key the bundle was signed with (default: bundle.key in the config directory)
+        #[arg(long)]
+        key: Option<PathBuf>,
+        /// Report what would change without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagAction {
+    /// Scanned projects with their tags (the default)
+    List {
+        #[arg(short, long)]
+        paths: Vec<PathBuf>,
+    },
+    /// Tag a project
+    Add {
+        project: PathBuf,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Take tags given by `add` off a project (all of them when none are named)
+    Remove {
+        project: PathBuf,
+        tags: Vec<String>,
+    },
+    /// Tag every project below a directory, or cloned from a remote org or repository
+    Rule {
+        tag: String,
+        /// Directory the projects are in
+        #[arg(long, required_unless_present = "remote", conflicts_with = "remote")]
+        under: Option<PathBuf>,
+        /// `host/owner`, `owner` or `owner/repo` of the projects' `origin`
+        #[arg(long)]
+        remote: Option<String>,
+    },
+    /// Delete the rules for a tag
+    Unrule {
+        tag: String,
+    },
+    /// Print the configured tags and rules
+    Config,
+}
+
+#[derive(Subcommand)]
+enum HashQueueAction {
+    /// Count jobs per status (the default)
+    Status,
+    /// List jobs, oldest first
+    List {
+        /// Only jobs with this status: queued, running, done or failed
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Work through the queue with reads throttled
+    Run {
+        /// Read at most this many MiB per second (0 for unlimited)
+        #[arg(long, default_value_t = 32)]
+        rate_mib: u64,
+        /// Stop taking new jobs after this many seconds
+        #[arg(long)]
+        max_seconds: Option<u64>,
+        /// Keep running, checking for new jobs every this many seconds
+        #[arg(long)]
+        watch: Option<u64>,
+    },
+    /// Forget finished jobs older than this many days
+    Clear {
+        #[arg(long, default_value_t = 30)]
+        keep_days: i64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TopKind {
+    Size,
+    Growth,
+    Staleness,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ActivityKind {
+    Active,
+    Dormant,
+    Dead,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportKind {
+    Ncdu,
+    Du,
+    Wiztree,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    Graphml,
+}
+
+#[derive(Subcommand)]
+enum QuarantineAction {
+    /// Permanently delete entries past their expiry
+    Gc {
+        /// Also warn about entries expiring within this many days
+        #[arg(long, default_value_t = 3)]
+        warn_days: i64,
+    },
+    /// Entries newest first, with the days left until each expires
+    List,
+    /// One entry and the files it holds
+    Show {
+        id: String,
+        /// List at most this many files
+        #[arg(long, default_value_t = 200)]
+        limit: usize,
+    },
+    /// Permanently delete one entry now (`rollback --id` restores it instead)
+    Delete {
+        id: String,
+    },
+    /// Permanently delete entries quarantined longer than the retention window
+    /// ago, including ones quarantined before the window was shortened
+    Prune {
+        /// Age in days past which entries go (default: the saved retention window)
+        #[arg(long)]
+        older_than: Option<i64>,
+        /// Save this retention window (0 = keep forever) and prune by it
+        #[arg(long, conflicts_with = "older_than")]
+        retention_days: Option<i64>,
+        /// Delete expired entries before each quarantine run from now on
+        #[arg(long, conflicts_with = "no_auto")]
+        auto: bool,
+        /// Stop deleting expired entries before quarantine runs
+        #[arg(long)]
+        no_auto: bool,
+        /// List what would be deleted without deleting it or saving settings
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn parse_budget(s: &str) -> Result<u64, String> {
+    parse_size(s).ok_or_else(|| format!("invalid size `{}` (expected e.g. 2G, 500M or a byte count)", s))
+}
+
+/// Ask a yes/no question on stderr; non-interactive stdin counts as "no"
+fn confirm(question: &str) -> Result<bool> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Build the operation context; Ctrl-C cancels at the next checkpoint
+fn operation_context(progress: bool) -> Result<OperationContext> {
+    let sink: Arc<dyn ProgressSink> = if progress {
+        Arc::new(JsonLinesProgress)
+    } else {
+        Arc::new(NoopProgress)
+    };
+    let cancel = CancellationToken::new();
+    let handler_token = cancel.clone();
+    ctrlc::set_handler(move || {
+        eprintln!("Cancelling...");
+        handler_token.cancel();
+    })?;
+    Ok(OperationContext::new(sink, cancel))
+}
+
+/// Scan after running the `pre-scan` hooks
+fn hooked_scan(paths: &[PathBuf], use_cache: bool, validation: CacheValidation, ctx: &OperationContext) -> Result<ScanOutput> {
+    hooks::run_hooks(HookEvent::PreScan, &serde_json::json!({ "paths": paths }))?;
+    let scan = if fs_snapshot::is_enabled() {
+        fs_snapshot::scan(paths, validation, ctx)?
+    } else {
+        scanner::scan_validated(paths, use_cache, validation, ctx)?
+    };
+    Ok(project_tags::apply_filter(scan))
+}
+
+/// `hooked_scan`, leaving package sizes to planning when `lazy`
+fn hooked_scan_sized(paths: &[PathBuf], lazy: bool, validation: CacheValidation, ctx: &OperationContext) -> Result<ScanOutput> {
+    // Snapshot scans cannot take sizes from the cache, so they size everything
+    if !lazy || fs_snapshot::is_enabled() {
+        return hooked_scan(paths, true, validation, ctx);
+    }
+    hooks::run_hooks(HookEvent::PreScan, &serde_json::json!({ "paths": paths }))?;
+    Ok(project_tags::apply_filter(scanner::scan_lazy(paths, validation, ctx)?))
+}
+
+/// `hooked_scan` printing each record as a JSON line as it is produced,
+/// then a summary line
+fn stream_scan(paths: &[PathBuf], use_cache: bool, validation: CacheValidation, lazy: bool, ctx: &OperationContext) -> Result<()> {
+    use std::io::Write;
+    anyhow::ensure!(!fs_snapshot::is_enabled(), "--snapshot cannot be combined with --stream");
+    anyhow::ensure!(project_tags::filter().is_empty(), "--tag cannot be combined with --stream");
+    hooks::run_hooks(HookEvent::PreScan, &serde_json::json!({ "paths": paths }))?;
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let summary = scanner::scan_each(paths, use_cache, validation, lazy, ctx, |item| {
+        serde_json::to_writer(&mut out, &item)?;
+        out.write_all(b"\n")?;
+        Ok(())
+    })?;
+    let mut line = serde_json::to_value(&summary)?;
+    line["type"] = "summary".into();
+    writeln!(out, "{}", line)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// `dry-run`'s plan of `scan`: basic cleanup with the configured retention,
+/// activity, wide-scan and cloud-sync checks applied
+fn basic_plan(
+    scan: &ScanOutput,
+    paths: &[PathBuf],
+    preserve_days: Option<i64>,
+    include_patched: bool,
+    verify_with_pm: bool,
+) -> Result<DryRunReport> {
+    let mut report = plan_basic_cleanup(scan, &RulesConfig {
+        preserve_days: preserve_days.unwrap_or_else(default_preserve_days),
+        enable_symlinking: false,
+        enable_ml_prediction: false,
+        lru_max_packages: 1000,
+        lru_max_size_bytes: 10_000_000_000,
+        canonical_strategy: CanonicalStrategy::First,
+        dedup_mode: DedupMode::Symlink,
+        protect_patched: !include_patched,
+    })?;
+    if verify_with_pm {
+        pm_verify::cross_check(&mut report, scan, pm_verify::list_installed);
+    }
+    scanner::size_plan_items(&mut report, scan);
+    version_retention::apply_configured_retention(&mut report, scan);
+    project_activity::apply_configured_retention(&mut report, &scan.projects);
+    wide_scan::flag_unrecognized(&mut report, paths);
+    cloud_sync::flag_synced(&mut report, &scan.projects, &cloud_sync::sync_roots());
+    scanner::count_plan_inodes(&mut report, scan);
+    risk::score_configured(&mut report, scan);
+    Ok(report)
+}
+
+/// `items` without those scoring above `max_risk` or not scored, which are reported
+fn within_risk(items: Vec<PlanItem>, max_risk: Option<u8>) -> Vec<PlanItem> {
+    let Some(max) = max_risk else { return items };
+    let (kept, held) = risk::within(items, max);
+    for item in &held {
+        match &item.risk {
+            Some(r) => eprintln!("Leaving out {} (risk {} > {})", item.target_path, r.score, max),
+            None => eprintln!("Leaving out {} (no risk score; plan again to score it)", item.target_path),
+        }
+    }
+    kept
+}
+
+/// Print a plan after running the `post-plan` hooks on it
+fn print_plan(report: &DryRunReport, format: Option<OutputFormat>) -> Result<()> {
+    let value = serde_json::to_value(report)?;
+    hooks::run_hooks(HookEvent::PostPlan, &value)?;
+    output::write_plan(&mut std::io::stdout().lock(), report, OutputFormat::resolve(format), &display::load())?;
+    Ok(())
+}
+
+/// The configured allowed roots plus `roots`, or the current directory when
+/// there are none.
+fn allowed_roots(roots: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut allowed = safety::load_config().allowed_roots;
+    allowed.extend(roots);
+    if allowed.is_empty() {
+        allowed.push(std::env::current_dir()?);
+    }
+    Ok(allowed)
+}
+
+/// Targets of `items` (from `plan`) that pass `validate_target` under the
+/// configured allowed roots plus `roots`. Targets the plan asks to confirm
+/// are kept only with `yes` or a yes at the prompt.
+fn accept_targets(plan: &DryRunReport, items: &[PlanItem], roots: Vec<PathBuf>, yes: bool) -> Result<Vec<PathBuf>> {
+    let allowed = allowed_roots(roots)?;
+    let mut accepted = Vec::new();
+    for item in items {
+        let t = PathBuf::from(&item.target_path);
+        match safety::validate_target(&t, &allowed) {
+            Ok(()) => accepted.push(t),
+            Err(e) => eprintln!("Refusing to quarantine: {}", e),
+        }
+    }
+    let unrecognized: Vec<&PathBuf> = accepted.iter().filter(|t| plan.confirm.iter().any(|c| t.as_path() == std::path::Path::new(c))).collect();
+    if !unrecognized.is_empty() && !yes {
+        for t in &unrecognized {
+            eprintln!("Outside package and cache directories: {}", t.display());
+        }
+        let question = format!("Quarantine these {} items too?", unrecognized.len());
+        if !confirm(&question)? {
+            eprintln!("Skipping them (pass --yes to include them)");
+            accepted.retain(|t| !plan.confirm.iter().any(|c| t.as_path() == std::path::Path::new(c)));
+        }
+    }
+    Ok(accepted)
+}
+
+/// Quarantine `targets` between the `pre-apply` and `post-apply` hooks and
+/// print the records. Targets from a plan (`items`) are reconciled with their
+/// estimates and the outcome is recorded in the feature store. The run's
+/// manifest is written under `command`.
+fn apply_targets(
+    command: &str,
+    targets: &[PathBuf],
+    items: Option<&[PlanItem]>,
+    fast: bool,
+    reinstall_on_demand: bool,
+    ctx: &OperationContext,
+    approved_by: Option<String>,
+) -> Result<Vec<QuarantineRecord>> {
+    hooks::run_hooks(HookEvent::PreApply, &serde_json::json!({
+        "targets": targets,
+        "approved_by": approved_by,
+    }))?;
+    if let Some((evicted, freed)) = overhead::enforce_cap(overhead::incoming_bytes(targets))? {
+        eprintln!("Evicted {} oldest quarantine entries ({} bytes) to stay under the overhead cap", evicted, freed);
+    }
+    if safety::load_config().auto_prune {
+        for r in safety::expire_quarantine()? {
+            eprintln!("Expired quarantine entry {} ({}), {} bytes freed", r.id, r.original_path, r.size_bytes);
+        }
+    }
+    let snapshots = if fs_snapshot::is_enabled() && !fast { fs_snapshot::snapshot_roots(targets)? } else { Vec::new() };
+    let mut run = run_manifest::RunRecorder::start(command, approved_by.clone());
+    let before: HashMap<&PathBuf, run_manifest::PathState> =
+        targets.iter().map(|t| (t, run_manifest::PathState::probe(t))).collect();
+    let batch = safety::quarantine_targets(targets, fast, &snapshots, ctx)?;
+    for snapshot in snapshots {
+        snapshot.release()?;
+    }
+    let mut recs = Vec::new();
+    for (t, result) in batch.results {
+        run.record(&t, run_manifest::RunAction::Quarantine, before[&t].clone(), &result);
+        match result {
+            Ok(r) => recs.push(r),
+            Err(e) => eprintln!("Failed to quarantine {:?}: {}", t, e),
+        }
+    }
+    let run_id = run.finish()?.id;
+    let mut out = serde_json::json!({
+        "status": "ok",
+        "run_id": run_id,
+        "records": recs,
+        "throughput": batch.throughput,
+    });
+    if let Some(approver) = approved_by {
+        out["approved_by"] = approver.into();
+    }
+    if reinstall_on_demand {
+        out["reinstall_markers"] = serde_json::to_value(reinstall::mark_purged(&recs)?)?;
+    }
+    if let Some(items) = items {
+        let reconciliation = reconcile::reconcile(items, &recs);
+        let recorded = feature_store::FeatureStore::open_default().and_then(|db| db.record_apply(&reconciliation, Some(&run_id)));
+        if let Err(e) = recorded {
+            eprintln!("Warning: Failed to record apply results: {}", e);
+        }
+        out["reconciliation"] = serde_json::to_value(reconciliation)?;
+    }
+    warn_overhead();
+    hooks::run_hooks(HookEvent::PostApply, &out)?;
+    println!("{}", serde_json::to_string_pretty(&out)?);
+    Ok(recs)
+}
+
+/// Replace every unblocked duplicate tree in `shared` with a link to its canonical tree
+fn share_trees(shared: &[tree_share::SharedTree], roots: &[PathBuf], ctx: &OperationContext) -> Result<()> {
+    let pairs: Vec<(&str, &str)> = shared.iter()
+        .flat_map(|s| s.duplicates.iter()
+            .filter(|d| d.blockers.is_empty())
+            .map(move |d| (s.canonical.as_str(), d.node_modules.as_str())))
+        .collect();
+    hooks::run_hooks(HookEvent::PreApply, &serde_json::json!({
+        "targets": pairs.iter().map(|(_, d)| d).collect::<Vec<_>>(),
+    }))?;
+    let mut run = run_manifest::RunRecorder::start("share-trees", None);
+    let mut records = Vec::new();
+    for (canonical, duplicate) in pairs {
+        if let Err(e) = ctx.check() {
+            run.finish()?;
+            return Err(e.into());
+        }
+        let duplicate = std::path::Path::new(duplicate);
+        let before = run_manifest::PathState::probe(duplicate);
+        let result = tree_share::share_tree(std::path::Path::new(canonical), duplicate, roots);
+        run.record(duplicate, run_manifest::RunAction::ShareTree, before, &result);
+        match result {
+            Ok(r) => records.push(serde_json::json!({ "canonical": canonical, "record": r })),
+            Err(e) => eprintln!("Failed to share {:?}: {}", duplicate, e),
+        }
+    }
+    let out = serde_json::json!({
+        "status": "ok",
+        "run_id": run.finish()?.id,
+        "shared_count": records.len(),
+        "shared": records,
+    });
+    warn_overhead();
+    hooks::run_hooks(HookEvent::PostApply, &out)?;
+    println!("{}", serde_json::to_string_pretty(&out)?);
+    Ok(())
+}
+
+/// Read a file, or stdin for `-`
+fn read_input(path: &std::path::Path) -> Result<String> {
+    use anyhow::Context;
+    if path.as_os_str() == "-" {
+        Ok(std::io::read_to_string(std::io::stdin())?)
+    } else {
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))
+    }
+}
+
+/// Read a scan saved by `scan` or `import`, narrowed to the `--tag`s
+fn read_scan(path: &std::path::Path) -> Result<ScanOutput> {
+    use anyhow::Context;
+    let scan = serde_json::from_str(&read_input(path)?).with_context(|| format!("{:?} is not a scan", path))?;
+    Ok(project_tags::apply_filter(scan))
+}
+
+/// Read a plan written by `dry-run` or `optimize`
+fn read_plan(path: &std::path::Path) -> Result<DryRunReport> {
+    use anyhow::Context;
+    serde_json::from_str(&read_input(path)?).with_context(|| format!("{:?} is not a cleanup plan", path))
+}
+
+/// Prepare the project around the working directory for `command`, run it
+/// with inherited stdio and return its exit code
+fn exec_command(command: &[String], restore: bool) -> Result<i32> {
+    use anyhow::Context;
+    if let Some(project) = exec::find_project(&std::env::current_dir()?) {
+        if restore {
+            let (restored, failed) = exec::restore_project(&project);
+            for r in &restored {
+                eprintln!("Restored {} from quarantine", r.original_path);
+                reinstall::forget_record(r);
+                hooks::run_hooks(HookEvent::OnRollback, &serde_json::to_value(r)?)?;
+            }
+            for (r, e) in &failed {
+                eprintln!("Warning: could not restore {}: {}", r.original_path, e);
+            }
+        }
+        let recorded = feature_store::FeatureStore::open_default()
+            .and_then(|db| exec::record_usage(&db, &project, command));
+        if let Err(e) = recorded {
+            eprintln!("Warning: Failed to record usage: {}", e);
+        }
+    }
+    let status = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .with_context(|| format!("Failed to run `{}`", command[0]))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Print the overhead warning, if the tool's own footprint calls for one
+fn warn_overhead() {
+    if let Some(warning) = overhead::measure(&overhead::load_config()).warning {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Preservation window when no flag gives one: config.toml's, else the
+/// machine role's
+fn default_preserve_days() -> i64 {
+    config_file::get().preserve_days.unwrap_or_else(|| MachineRole::current().0.policy().preserve_days)
+}
+
+fn main() -> Result<()> {
+    let started = Instant::now();
+    let cli = Cli::parse();
+    paths::set_overrides(StateOverrides {
+        state_dir: cli.state_dir.clone(),
+        cache_dir: cli.cache_dir.clone(),
+        db: cli.db.clone(),
+        quarantine_dir: cli.quarantine_dir.clone(),
+        store_dir: cli.store_dir.clone(),
+    });
+    if let Some(role) = cli.role {
+        machine_role::override_role(role);
+    }
+    if let Some(file) = cli.config.clone() {
+        config_file::set_path(file);
+        // A file given explicitly must load; `config validate` reports why it does not
+        if !matches!(cli.command, Commands::Config { .. }) {
+            config_file::load()?;
+        }
+    }
+    if cli.read_only {
+        safety::set_read_only();
+    }
+    if cli.snapshot {
+        fs_snapshot::enable();
+    }
+    project_tags::set_filter(cli.tags.clone());
+    if cli.timings {
+        timings::enable();
+    }
+    let ctx = operation_context(cli.progress)?;
+    let result = run(cli, ctx);
+    if timings::is_enabled() {
+        eprint!("{}", timings::render(started.elapsed()));
+    }
+    result
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn run(cli: Cli, ctx: OperationContext) -> Result<()> {
     match cli.command {
-        Commands::Scan { paths, no_cache } => {
-            let out = if no_cache {
-                scanner::scan_no_cache(&paths)?
+        Commands::Scan { paths, no_cache, lazy_sizes, stream: true, format } => {
+            anyhow::ensure!(matches!(format, None | Some(OutputFormat::Ndjson)), "--stream only writes ndjson");
+            stream_scan(&paths, !no_cache, cli.cache_validation, lazy_sizes, &ctx)?;
+        }
+        Commands::Scan { paths, no_cache, lazy_sizes, stream: false, format } => {
+            let out = if lazy_sizes {
+                hooked_scan_sized(&paths, true, cli.cache_validation, &ctx)?
             } else {
-                scanner::scan(&paths)?
+                hooked_scan(&paths, !no_cache, cli.cache_validation, &ctx)?
             };
-            println!("{}", serde_json::to_string_pretty(&out)?);
+            output::write_scan(&mut std::io::stdout().lock(), &out, OutputFormat::resolve(format), &display::load())?;
         }
-        Commands::DryRun { preserve_days, paths } => {
-            let scan = scanner::scan(&paths)?;
-            let report = plan_basic_cleanup(&scan, &RulesConfig {
-                preserve_days,
-                enable_symlinking: false,
-                enable_ml_prediction: false,
-                lru_max_packages: 1000,
-                lru_max_size_bytes: 10_000_000_000,
-            })?;
+        Commands::DryRun { preserve_days, paths, include_patched, lazy_sizes, verify_with_pm, from_scan, sort_by_risk, format } => {
+            let scan = match from_scan {
+                Some(file) => read_scan(&file)?,
+                None => hooked_scan_sized(&paths, lazy_sizes, cli.cache_validation, &ctx)?,
+            };
+            let mut report = basic_plan(&scan, &paths, preserve_days, include_patched, verify_with_pm)?;
+            if sort_by_risk {
+                risk::sort_by_risk(&mut report);
+            }
+            print_plan(&report, format)?;
+        }
+        Commands::Projects { paths } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let db = feature_store::FeatureStore::open_default().ok();
+            let activities = project_activity::classify_projects(
+                &scan.projects,
+                db.as_ref(),
+                &project_activity::EditorRecents::detect(),
+                &project_activity::load_config(),
+                chrono::Utc::now(),
+            );
+            println!("{}", serde_json::to_string_pretty(&activities)?);
+        }
+        Commands::Bundle { action: BundleAction::Export { out, name, description, key } } => {
+            let manifest = bundle::export(&out, &name, description, key.as_deref())?;
+            eprintln!("Signed with {:?}; machines importing the bundle need this key", key.unwrap_or_else(bundle::key_path));
+            println!("{}", serde_json::to_string_pretty(&manifest)?);
+        }
+        Commands::Bundle { action: BundleAction::Import { bundle: file, key, dry_run } } => {
+            let report = bundle::import(&file, key.as_deref(), dry_run)?;
             println!("{}", serde_json::to_string_pretty(&report)?);
         }
-        Commands::Quarantine { targets, fast } => {
-            let mut recs = Vec::new();
-            for t in targets {
-                let result = if fast {
-                    safety::move_to_quarantine_fast(&t)
+        Commands::CloudSync { action } => {
+            let roots = cloud_sync::sync_roots();
+            match action.unwrap_or(CloudSyncAction::List { paths: Vec::new() }) {
+                CloudSyncAction::List { paths } => {
+                    let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                        "roots": roots,
+                        "trees": cloud_sync::synced_trees(&scan.projects, &roots),
+                    }))?);
+                }
+                CloudSyncAction::Exclude { node_modules } => {
+                    let node_modules = std::path::absolute(&node_modules)?;
+                    println!("{}", serde_json::to_string_pretty(&cloud_sync::exclude(&node_modules, &roots)?)?);
+                }
+                CloudSyncAction::Relocate { node_modules, to } => {
+                    let (node_modules, to) = (std::path::absolute(&node_modules)?, std::path::absolute(&to)?);
+                    println!("{}", serde_json::to_string_pretty(&cloud_sync::relocate(&node_modules, &to, &roots)?)?);
+                }
+            }
+        }
+        Commands::Ml { action: MlAction::ComputeFeatures } => {
+            let db = feature_store::FeatureStore::open_default()?;
+            let report = feature_pipeline::compute_features(&db, &PredictiveOptimizer::new(default_preserve_days()))?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Commands::KeepVersions { action } => {
+            match action.unwrap_or(KeepVersionsAction::Show) {
+                KeepVersionsAction::Show => {}
+                KeepVersionsAction::Set { keep, rules } => {
+                    version_retention::save_config(&version_retention::VersionRetentionConfig { keep, rules })?;
+                }
+                KeepVersionsAction::Off => version_retention::save_config(&Default::default())?,
+            }
+            println!("{}", serde_json::to_string_pretty(&version_retention::load_config())?);
+        }
+        Commands::Tag { action } => {
+            let mut config = project_tags::load_config();
+            match action.unwrap_or(TagAction::List { paths: Vec::new() }) {
+                TagAction::List { paths } => {
+                    let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+                    println!("{}", serde_json::to_string_pretty(&project_tags::tag_projects(&scan.projects, &config))?);
+                    return Ok(());
+                }
+                TagAction::Add { project, tags } => {
+                    let project = std::fs::canonicalize(&project).unwrap_or(project);
+                    config.projects.entry(project.to_string_lossy().to_string()).or_default().extend(tags);
+                }
+                TagAction::Remove { project, tags } => {
+                    let key = std::fs::canonicalize(&project).unwrap_or(project).to_string_lossy().to_string();
+                    if let Some(given) = config.projects.get_mut(&key) {
+                        given.retain(|t| !tags.is_empty() && !tags.contains(t));
+                        if given.is_empty() {
+                            config.projects.remove(&key);
+                        }
+                    }
+                }
+                TagAction::Rule { tag, under, remote } => {
+                    let under = under.map(|dir| std::fs::canonicalize(&dir).unwrap_or(dir));
+                    config.rules.push(project_tags::TagRule { tag, under, remote });
+                }
+                TagAction::Unrule { tag } => config.rules.retain(|r| r.tag != tag),
+                TagAction::Config => {
+                    println!("{}", serde_json::to_string_pretty(&config)?);
+                    return Ok(());
+                }
+            }
+            project_tags::save_config(&config)?;
+            println!("{}", serde_json::to_string_pretty(&config)?);
+        }
+        Commands::Activity { action } => {
+            if let Some(ActivityAction::Set { active_days, dead_days, purge_node_modules }) = action {
+                let purge_node_modules = purge_node_modules.into_iter()
+                    .map(|kind| match kind {
+                        ActivityKind::Active => ActivityClass::Active,
+                        ActivityKind::Dormant => ActivityClass::Dormant,
+                        ActivityKind::Dead => ActivityClass::Dead,
+                    })
+                    .collect();
+                project_activity::save_config(&project_activity::ActivityConfig { active_days, dead_days, purge_node_modules })?;
+            }
+            println!("{}", serde_json::to_string_pretty(&project_activity::load_config())?);
+        }
+        Commands::BackupExclude { paths, from_scan, undo, dry_run } => {
+            let scan: ScanOutput = match from_scan {
+                Some(file) => read_scan(&file)?,
+                None => hooked_scan(&paths, true, cli.cache_validation, &ctx)?,
+            };
+            let store = get_global_store_path().ok();
+            let targets = backup_exclude::targets(&scan, store.as_deref());
+            let report = backup_exclude::apply(&targets, &backup_exclude::platform_methods(), undo, dry_run)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if report.failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::Top { by, limit, growth_days } => {
+            let by = match by {
+                TopKind::Size => TopBy::Size,
+                TopKind::Growth => TopBy::Growth,
+                TopKind::Staleness => TopBy::Staleness,
+            };
+            let db = feature_store::FeatureStore::open_default()?;
+            let report = top::top(&db, by, limit, growth_days, chrono::Utc::now())?;
+            if report.packages == 0 {
+                eprintln!("No package snapshots recorded yet; run `scan` (without --lazy-sizes or --no-cache) first");
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Commands::Import { file, format, du_block_size } => {
+            let text = read_input(&file)?;
+            let format = match format {
+                Some(ImportKind::Ncdu) => ImportFormat::Ncdu,
+                Some(ImportKind::Du) => ImportFormat::Du { block_size: du_block_size },
+                Some(ImportKind::Wiztree) => ImportFormat::WizTree,
+                None => ImportFormat::detect(&text, du_block_size),
+            };
+            let scan = scan_import::import(&text, format)?;
+            eprintln!("Imported {} packages in {} projects", scan.packages.len(), scan.projects.len());
+            println!("{}", serde_json::to_string_pretty(&scan)?);
+        }
+        Commands::Quarantine { action: Some(QuarantineAction::Gc { warn_days }), .. } => {
+            let removed = safety::expire_quarantine()?;
+            for r in &removed {
+                eprintln!("Expired quarantine entry {} ({}), {} bytes freed", r.id, r.original_path, r.size_bytes);
+            }
+            let expiring = safety::expiring_within(warn_days);
+            for r in &expiring {
+                eprintln!("Quarantine entry {} ({}) expires soon", r.id, r.original_path);
+            }
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "status": "ok",
+                "expired": removed,
+                "bytes_freed": removed.iter().map(|r| r.size_bytes).sum::<u64>(),
+                "expiring_soon": expiring,
+            }))?);
+        }
+        Commands::Quarantine { action: Some(QuarantineAction::Prune { older_than, retention_days, auto, no_auto, dry_run }), .. } => {
+            let mut config = safety::load_config();
+            if retention_days.is_some() || auto || no_auto {
+                if let Some(days) = retention_days {
+                    config.retention_days = days;
+                }
+                if auto || no_auto {
+                    config.auto_prune = auto;
+                }
+                if dry_run {
+                    eprintln!("Dry run: the retention settings are not saved");
                 } else {
-                    safety::move_to_quarantine(&t)
-                };
-                match result {
-                    Ok(r) => recs.push(r),
-                    Err(e) => eprintln!("Failed to quarantine {:?}: {}", t, e),
+                    save_config(&config)?;
+                }
+            }
+            let days = older_than.unwrap_or(config.retention_days);
+            if days <= 0 {
+                eprintln!("A window of 0 days keeps entries forever; nothing to prune");
+            }
+            let pruned = safety::prune_quarantine(days, dry_run)?;
+            for r in &pruned {
+                let verb = if dry_run { "Would prune" } else { "Pruned" };
+                eprintln!("{} quarantine entry {} ({}), {} bytes", verb, r.id, r.original_path, r.size_bytes);
+            }
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "status": "ok",
+                "dry_run": dry_run,
+                "older_than_days": days,
+                "auto_prune": config.auto_prune,
+                "pruned": pruned,
+                "bytes_freed": if dry_run { 0 } else { pruned.iter().map(|r| r.size_bytes).sum::<u64>() },
+            }))?);
+        }
+        Commands::Quarantine { action: Some(QuarantineAction::List), .. } => {
+            println!("{}", serde_json::to_string_pretty(&safety::quarantine_entries())?);
+        }
+        Commands::Quarantine { action: Some(QuarantineAction::Show { id, limit }), .. } => {
+            let Some(entry) = safety::quarantine_entries().into_iter().find(|e| e.record.id == id) else {
+                eprintln!("No matching quarantine record found");
+                std::process::exit(1);
+            };
+            let (files, total_files) = safety::quarantine_contents(&entry.record, limit);
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "entry": entry,
+                "files": files,
+                "total_files": total_files,
+            }))?);
+        }
+        Commands::Quarantine { action: Some(QuarantineAction::Delete { id }), .. } => {
+            let Some(rec) = safety::find_quarantine_by_id(&id) else {
+                eprintln!("No matching quarantine record found");
+                std::process::exit(1);
+            };
+            safety::delete_quarantine_entry(&rec)?;
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "status": "ok",
+                "id": rec.id,
+                "bytes_freed": rec.size_bytes,
+            }))?);
+        }
+        Commands::Quarantine { action: None, targets, fast, roots, force, yes, reinstall_on_demand, compress } => {
+            if targets.is_empty() {
+                eprintln!("No quarantine targets provided");
+                std::process::exit(2);
+            }
+            if compress {
+                safety::set_compress();
+            }
+
+            let allowed = allowed_roots(roots)?;
+
+            let mut accepted = Vec::new();
+            for t in targets {
+                match safety::validate_target(&t, &allowed) {
+                    Ok(()) => accepted.push(t),
+                    Err(e) if force => {
+                        if yes || confirm(&format!("{}. Quarantine anyway?", e))? {
+                            accepted.push(t);
+                        } else {
+                            eprintln!("Skipped {:?}", t);
+                        }
+                    }
+                    Err(e) => eprintln!("Refusing to quarantine: {} (use --force to override)", e),
                 }
             }
-            println!("{}", serde_json::to_string_pretty(&recs)?);
+
+            apply_targets("quarantine", &accepted, None, fast, reinstall_on_demand, &ctx, None)?;
+        }
+        Commands::ShowRun { id: Some(id) } => {
+            println!("{}", serde_json::to_string_pretty(&run_manifest::load(&id)?)?);
+        }
+        Commands::ShowRun { id: None } => {
+            println!("{}", serde_json::to_string_pretty(&run_manifest::list())?);
         }
         Commands::Rollback { id, latest } => {
             let rec = if let Some(i) = id { 
@@ -134,6 +1825,8 @@ fn main() -> Result<()> {
                     eprintln!("{}", e);
                     std::process::exit(1);
                 }
+                reinstall::forget_record(&r);
+                hooks::run_hooks(HookEvent::OnRollback, &serde_json::to_value(&r)?)?;
                 println!("{}", serde_json::to_string_pretty(&serde_json::json!({
                     "status": "ok",
                     "id": r.id
@@ -143,35 +1836,125 @@ fn main() -> Result<()> {
                 std::process::exit(2);
             }
         }
-        Commands::Optimize { preserve_days, paths, enable_symlinking, enable_ml, lru_max_packages, lru_max_size_bytes } => {
-            let scan = scanner::scan(&paths)?;
+        Commands::Optimize { preserve_days, paths, enable_symlinking, enable_ml, lru_max_packages, lru_max_size_bytes, eviction_policy, include_patched, remote_activity, lazy_sizes, verify_with_pm, sort_by_risk, format } => {
+            let scan = hooked_scan_sized(&paths, lazy_sizes, cli.cache_validation, &ctx)?;
+            let defaults = config_file::get();
             let config = RulesConfig {
-                preserve_days,
-                enable_symlinking,
+                preserve_days: preserve_days.unwrap_or_else(default_preserve_days),
+                enable_symlinking: enable_symlinking || defaults.enable_symlinking.unwrap_or(false),
                 enable_ml_prediction: enable_ml,
-                lru_max_packages,
-                lru_max_size_bytes,
+                lru_max_packages: lru_max_packages.or(defaults.lru_max_packages).unwrap_or(1000),
+                lru_max_size_bytes: lru_max_size_bytes.or(defaults.lru_max_size_bytes).unwrap_or(10_000_000_000),
+                canonical_strategy: CanonicalStrategy::First,
+                dedup_mode: DedupMode::Symlink,
+                protect_patched: !include_patched,
             };
-            let mut engine = OptimizationEngine::new(config)?;
-            let report = engine.plan_optimized_cleanup(&scan)?;
-            println!("{}", serde_json::to_string_pretty(&report)?);
+            let mut engine = OptimizationEngine::new(config)?.with_context(ctx);
+            if let Some(kind) = eviction_policy {
+                engine = engine.with_eviction_policy(kind);
+            }
+            if remote_activity {
+                engine = engine.with_repo_activity(repo_activity::lookup_projects(&scan.projects));
+            }
+            if enable_ml {
+                if let Ok(db) = feature_store::FeatureStore::open_default() {
+                    engine = engine.with_behavior(scan.projects.iter()
+                        .filter_map(|p| Some((p.path.clone(), db.developer_behavior(&p.path).ok()?)))
+                        .collect());
+                    engine = engine.with_feature_store(db);
+                }
+            }
+            let mut report = engine.plan_optimized_cleanup(&scan)?;
+            if verify_with_pm {
+                pm_verify::cross_check(&mut report, &scan, pm_verify::list_installed);
+            }
+            scanner::size_plan_items(&mut report, &scan);
+            if let Some(lru) = &report.lru {
+                let recorded = feature_store::FeatureStore::open_default().and_then(|fs| fs.record_lru_stats(lru));
+                if let Err(e) = recorded {
+                    eprintln!("Warning: Failed to record LRU statistics: {}", e);
+                }
+            }
+            version_retention::apply_configured_retention(&mut report, &scan);
+            project_activity::apply_configured_retention(&mut report, &scan.projects);
+            wide_scan::flag_unrecognized(&mut report, &paths);
+            cloud_sync::flag_synced(&mut report, &scan.projects, &cloud_sync::sync_roots());
+            scanner::count_plan_inodes(&mut report, &scan);
+            risk::score_configured(&mut report, &scan);
+            if sort_by_risk {
+                risk::sort_by_risk(&mut report);
+            }
+            print_plan(&report, format)?;
         }
-        Commands::Symlink { paths } => {
-            let scan = scanner::scan(&paths)?;
+        Commands::Symlink { paths, dry_run, verify, canonical, mode, include_patched } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
             let config = RulesConfig {
                 preserve_days: 90,
                 enable_symlinking: true,
                 enable_ml_prediction: false,
                 lru_max_packages: 1000,
                 lru_max_size_bytes: 10_000_000_000,
+                canonical_strategy: canonical,
+                dedup_mode: mode,
+                protect_patched: !include_patched,
             };
-            let engine = OptimizationEngine::new(config)?;
-            let count = engine.execute_symlinking(&scan)?;
+            if dry_run {
+                let report = plan_symlinking(&scan, &config)?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            let engine = OptimizationEngine::new(config)?.with_context(ctx);
+            let outcome = engine.execute_symlinking_verified(&scan, verify.as_deref())?;
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "status": "ok",
+                "symlinked_count": outcome.symlinked_count,
+                "hardlinked_count": outcome.hardlinked_count,
+                "restored_count": outcome.restored_count,
+                "verifications": outcome.verifications,
+                "canonical_choices": outcome.canonical_choices,
+                "throughput": outcome.throughput,
+            }))?);
+        }
+        Commands::ShareTrees { paths, dry_run, roots } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let shared = tree_share::find_shared_trees(&scan, &open_file_snapshot());
+            if dry_run {
+                println!("{}", serde_json::to_string_pretty(&shared)?);
+                return Ok(());
+            }
+            // The scanned paths are where trees may be shared
+            share_trees(&shared, &allowed_roots(roots.into_iter().chain(paths).collect())?, &ctx)?;
+        }
+        Commands::Duplicates { paths, consolidate } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let report = cross_dedup::find_overlap(&scan, &get_global_store_path()?, &open_file_snapshot());
+            if !consolidate {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            let outcome = cross_dedup::consolidate(&report, &ctx)?;
+            for (path, error) in &outcome.failed {
+                eprintln!("Failed to link {:?}: {}", path, error);
+            }
             println!("{}", serde_json::to_string_pretty(&serde_json::json!({
                 "status": "ok",
-                "symlinked_count": count
+                "copies_linked": outcome.copies_linked,
+                "files_linked": outcome.files_linked,
+                "failed": outcome.failed.len(),
+                "total_reclaimable_bytes": report.total_reclaimable_bytes,
             }))?);
         }
+        Commands::Bench { projects, packages, depth, files, file_bytes, shared, iterations, dir } => {
+            let shape = bench::BenchShape { projects, packages, depth, files, file_bytes, shared };
+            let report = bench::run(&shape, iterations, dir.as_deref(), &ctx)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Commands::Watch { paths, min_interval, duration } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let db = feature_store::FeatureStore::open_default()?;
+            let summary = access_watch::watch(&scan, &db, Duration::from_secs(min_interval), duration.map(Duration::from_secs), &ctx)?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        }
         Commands::Stats => {
             let q_stats = get_quarantine_stats();
             let cache_path = ScanCache::default_cache_path();
@@ -184,16 +1967,30 @@ fn main() -> Result<()> {
             };
             
             // Feature store stats
-            let feature_stats = feature_store::FeatureStore::open_default()
-                .and_then(|fs| fs.get_stats())
-                .ok();
+            let store = feature_store::FeatureStore::open_default().ok();
+            let feature_stats = store.as_ref().and_then(|fs| fs.get_stats().ok());
+            let last_scan = store.as_ref()
+                .and_then(|fs| fs.recent_scans(1).ok())
+                .and_then(|scans| scans.into_iter().next());
+            let last_lru = store.as_ref().and_then(|fs| fs.last_lru_stats().ok().flatten());
+            let apply_accuracy = store.as_ref().and_then(|fs| fs.apply_accuracy(20).ok().flatten());
             
+            let compiler_caches = detect_compiler_caches();
+            let compiler_plan = plan_compiler_cache_trim(&compiler_caches, None);
+            let tool_overhead = overhead::measure(&overhead::load_config());
+            if let Some(warning) = &tool_overhead.warning {
+                eprintln!("Warning: {}", warning);
+            }
+
             println!("{}", serde_json::to_string_pretty(&serde_json::json!({
                 "quarantine": {
                     "total_entries": q_stats.total_entries,
                     "total_size_bytes": q_stats.total_size_bytes,
                     "oldest_entry_days": q_stats.oldest_entry_days,
                     "entries_over_retention": q_stats.entries_over_retention,
+                    "shared_bytes": q_stats.shared_bytes,
+                    "packed_entries": q_stats.packed_entries,
+                    "packed_bytes": q_stats.packed_bytes,
                 },
                 "scan_cache": cache_stats.map(|s| serde_json::json!({
                     "total_entries": s.total_entries,
@@ -205,13 +2002,30 @@ fn main() -> Result<()> {
                     "project_count": s.project_count,
                     "event_count": s.event_count,
                     "feature_count": s.feature_count,
+                    "stale_feature_count": s.stale_feature_count,
                 })),
+                "last_scan": last_scan,
+                "lru_cache": last_lru,
+                "apply_accuracy": apply_accuracy,
+                "overhead": tool_overhead,
+                "locations": {
+                    "state_dir": paths::state_dir(),
+                    "scan_cache": ScanCache::default_cache_path(),
+                    "feature_store": feature_store::FeatureStore::default_db_path(),
+                    "quarantine": paths::quarantine_dir(),
+                    "global_store": get_global_store_path().ok(),
+                },
+                "compiler_caches": {
+                    "total_size_bytes": compiler_caches.iter().map(|c| c.size_bytes).sum::<u64>(),
+                    "reclaimable_bytes": compiler_plan.total_estimated_bytes,
+                    "caches": compiler_caches,
+                },
             }))?);
         }
 
-        Commands::CleanupQuarantine { max_size_gb, retention_days } => {
+        Commands::CleanupQuarantine { max_size_gb, retention_days, compress } => {
             // Update config if parameters provided
-            if max_size_gb.is_some() || retention_days.is_some() {
+            if max_size_gb.is_some() || retention_days.is_some() || compress.is_some() {
                 let mut config = safety::load_config();
                 if let Some(size) = max_size_gb {
                     config.max_size_gb = size;
@@ -219,6 +2033,9 @@ fn main() -> Result<()> {
                 if let Some(days) = retention_days {
                     config.retention_days = days;
                 }
+                if let Some(compress) = compress {
+                    config.compress = compress;
+                }
                 save_config(&config)?;
             }
             
@@ -229,10 +2046,572 @@ fn main() -> Result<()> {
                 "bytes_freed": bytes_freed,
             }))?);
         }
+        Commands::Graph { paths, format, project, preserve_days } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let mut graph = DependencyGraph::from_scan(&scan, preserve_days);
+            if let Some(project) = project {
+                let root = std::fs::canonicalize(&project).unwrap_or(project);
+                graph = graph.reachable_from(&root.to_string_lossy());
+            }
+            match format {
+                GraphFormat::Dot => print!("{}", graph.to_dot()),
+                GraphFormat::Graphml => print!("{}", graph.to_graphml()),
+            }
+        }
+        Commands::Rdeps { package, paths } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let (name, version) = parse_package_spec(&package);
+            let dependents = DependencyGraph::from_scan(&scan, 90).dependents(name, version);
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "package": package,
+                "dependents": dependents,
+            }))?);
+        }
+        Commands::Verify { paths, background: true } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let queue = HashQueue::open_default()?;
+            let mut queued = 0;
+            for pkg in &scan.packages {
+                if queue.enqueue(JobKind::VerifyPackage, std::path::Path::new(&pkg.path), Some(&serde_json::to_string(pkg)?))? {
+                    queued += 1;
+                }
+            }
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "queued": queued }))?);
+        }
+        Commands::HashQueue { action } => {
+            let queue = HashQueue::open_default()?;
+            match action.unwrap_or(HashQueueAction::Status) {
+                HashQueueAction::Status => println!("{}", serde_json::to_string_pretty(&queue.status()?)?),
+                HashQueueAction::List { status } => println!("{}", serde_json::to_string_pretty(&queue.jobs(status.as_deref())?)?),
+                HashQueueAction::Run { rate_mib, max_seconds, watch } => {
+                    let options = hash_queue::RunOptions {
+                        bytes_per_sec: (rate_mib > 0).then(|| rate_mib * 1024 * 1024),
+                        max_duration: max_seconds.map(std::time::Duration::from_secs),
+                    };
+                    loop {
+                        let summary = queue.run(&options, &ctx)?;
+                        let Some(interval) = watch.filter(|_| !ctx.cancel.is_cancelled()) else {
+                            println!("{}", serde_json::to_string_pretty(&summary)?);
+                            break;
+                        };
+                        if summary.done + summary.failed > 0 {
+                            eprintln!("{}", serde_json::to_string(&summary)?);
+                        }
+                        std::thread::sleep(std::time::Duration::from_secs(interval.max(1)));
+                    }
+                }
+                HashQueueAction::Clear { keep_days } => {
+                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "cleared": queue.clear_finished(keep_days)? }))?);
+                }
+            }
+        }
+        Commands::Installed { project } => {
+            let project = match project {
+                Some(p) => p,
+                None => std::env::current_dir()?,
+            };
+            let event = install_events::record(&project)?;
+            println!("{}", serde_json::to_string_pretty(&event)?);
+        }
+        Commands::Verify { paths, background: false } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let results = verify_scan(&scan, &ctx)?;
+            let count = |f: fn(&IntegrityStatus) -> bool| results.iter().filter(|r| f(&r.status)).count();
+            let failed = count(|s| matches!(s, IntegrityStatus::Mismatch { .. } | IntegrityStatus::CorruptTarball));
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "checked": results.len(),
+                "verified": count(|s| *s == IntegrityStatus::Verified),
+                "failed": failed,
+                "unverifiable": count(|s| matches!(s, IntegrityStatus::Unverifiable { .. } | IntegrityStatus::NoLockfileHash)),
+                "results": results,
+            }))?);
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::EditorCaches { preserve_days, plan } => {
+            let entries = scan_editor_caches(&EditorLocations::detect(), preserve_days);
+            if plan {
+                println!("{}", serde_json::to_string_pretty(&plan_editor_cleanup(&entries))?);
+            } else {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                    "total_size_bytes": entries.iter().map(|e| e.size_bytes).sum::<u64>(),
+                    "stale_size_bytes": entries.iter().filter(|e| e.stale).map(|e| e.size_bytes).sum::<u64>(),
+                    "entries": entries,
+                }))?);
+            }
+        }
+        Commands::ModelCaches { retention_days, plan } => {
+            let entries = scan_model_caches(&ModelLocations::detect(), retention_days);
+            if plan {
+                println!("{}", serde_json::to_string_pretty(&plan_model_cleanup(&entries))?);
+            } else {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                    "total_size_bytes": entries.iter().map(|e| e.size_bytes).sum::<u64>(),
+                    "stale_size_bytes": entries.iter().filter(|e| e.stale).map(|e| e.size_bytes).sum::<u64>(),
+                    "models": entries,
+                }))?);
+            }
+        }
+        Commands::CompilerCaches { trim, budget, dry_run } => {
+            let caches = detect_compiler_caches();
+            if !trim {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                    "caches": caches,
+                    "plan": plan_compiler_cache_trim(&caches, budget),
+                }))?);
+                return Ok(());
+            }
+            let mut reports = Vec::new();
+            for cache in &caches {
+                let Some(limit) = budget.or(cache.configured_max_bytes) else {
+                    eprintln!("No budget for {}; pass --budget", cache.path);
+                    continue;
+                };
+                reports.push(trim_cache(cache.kind, std::path::Path::new(&cache.path), limit, dry_run)?);
+            }
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "status": "ok",
+                "trimmed": reports,
+                "bytes_freed": reports.iter().map(|r| r.bytes_freed).sum::<u64>(),
+            }))?);
+        }
+        Commands::CiClean { workspace_root, patterns, keep, pipeline_key, tool_caches, cache_budget, quarantine, dry_run } => {
+            let cache_budget_bytes = match cache_budget {
+                Some(s) => Some(parse_size(&s).ok_or_else(|| anyhow::anyhow!("Invalid cache budget `{}`", s))?),
+                None => None,
+            };
+            let config = CiCleanConfig {
+                workspace_root,
+                patterns,
+                keep_builds: keep,
+                pipeline_key,
+                tool_caches: if tool_caches.is_empty() { default_tool_caches() } else { tool_caches },
+                cache_budget_bytes,
+                quarantine,
+                dry_run,
+            };
+            let summary = ci_clean(&config, &ctx)?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        }
+        Commands::Impact { plan, paths, from_scan } => {
+            let plan = read_plan(&plan)?;
+            let scan: ScanOutput = match from_scan {
+                Some(file) => read_scan(&file)?,
+                None => hooked_scan(&paths, true, cli.cache_validation, &ctx)?,
+            };
+            let impact = impact::analyze_configured(&plan, &scan);
+            eprintln!("{}", impact.summary);
+            println!("{}", serde_json::to_string_pretty(&impact)?);
+        }
+        Commands::Compliance { policy, paths, from_scan, plan_out } => {
+            let policy = compliance::load_policy(&policy)?;
+            let scan: ScanOutput = match from_scan {
+                Some(file) => read_scan(&file)?,
+                None => hooked_scan(&paths, true, cli.cache_validation, &ctx)?,
+            };
+            let store = get_global_store_path().ok();
+            let report = compliance::evaluate(&policy, &scan, store.as_deref(), compliance::host_name(), MachineRole::current().0, chrono::Utc::now());
+            if let Some(file) = plan_out {
+                std::fs::write(&file, serde_json::to_string_pretty(&report.plan)?)?;
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.passed {
+                std::process::exit(1);
+            }
+        }
+        Commands::Approve { plan } => {
+            let plan = read_plan(&plan)?;
+            let (token, approval) = approval::approve(&plan)?;
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "token": token,
+                "approval": approval,
+                "items": plan.items.len(),
+                "total_estimated_bytes": approval::plan_size(&plan),
+            }))?);
+        }
+        Commands::Apply { plan, approval: token, fast, roots, reinstall_on_demand, yes, canary: canary_percent, canary_by, observe_hours, soft_disable: soft, soft_mode, grace_hours, max_risk } => {
+            let plan = read_plan(&plan)?;
+            let approved = approval::check_apply(&plan, token.as_deref())?;
+            if let Some(a) = &approved {
+                eprintln!("Applying plan approved by {} at {}", a.approver, a.issued_at);
+            }
+            let pending = canary::find(&plan);
+            if soft && pending.is_some() {
+                eprintln!("This plan has a canary running; apply it without --soft-disable to promote it");
+                std::process::exit(2);
+            }
+            let items = match (&pending, canary_percent) {
+                (Some(c), _) => {
+                    canary::check_promote(c, &safety::list_quarantine())?;
+                    let rest = c.remainder(&plan);
+                    eprintln!("Canary of {} items passed; applying the remaining {}", c.items.len(), rest.len());
+                    rest
+                }
+                (None, Some(percent)) => canary::sample(&plan.items, canary_by, percent, canary::random_seed()),
+                (None, None) => plan.items.clone(),
+            };
+
+            let items = within_risk(items, max_risk);
+            let accepted = accept_targets(&plan, &items, roots, yes)?;
+
+            if soft {
+                let (batch, failed) = soft_disable::disable(&accepted, soft_mode, grace_hours)?;
+                for (t, e) in failed {
+                    eprintln!("Failed to soft-disable {:?}: {}", t, e);
+                }
+                eprintln!(
+                    "Soft-disabled {} items (batch {}); `soft-disabled commit` quarantines them after {} if nothing complains",
+                    batch.items.len(), batch.id, batch.grace_until
+                );
+                println!("{}", serde_json::to_string_pretty(&batch)?);
+                return Ok(());
+            }
+            let records = apply_targets("apply", &accepted, Some(&items), fast, reinstall_on_demand, &ctx, approved.map(|a| format!("{} ({})", a.approver, a.approver_id)))?;
+            if let Some(c) = pending {
+                canary::finish(&c.plan_hash);
+            } else if records.is_empty() && canary_percent.is_some() {
+                eprintln!("Canary: nothing was quarantined, so no canary was started");
+            } else if let Some(percent) = canary_percent {
+                let started = canary::start(&plan, &items, &records, canary_by, percent, observe_hours)?;
+                eprintln!(
+                    "Canary: quarantined {} of {} items; apply the plan again after {} for the rest",
+                    started.items.len(), started.plan_items, started.observe_until
+                );
+            }
+        }
+        Commands::Purge { plan, preserve_days, paths, include_patched, approval: token, mut roots, fast, reinstall_on_demand, yes, max_risk } => {
+            let plan = match plan {
+                Some(file) => read_plan(&file)?,
+                None => {
+                    let scan = hooked_scan_sized(&paths, true, cli.cache_validation, &ctx)?;
+                    roots.extend(paths.iter().cloned());
+                    let report = basic_plan(&scan, &paths, preserve_days, include_patched, false)?;
+                    hooks::run_hooks(HookEvent::PostPlan, &serde_json::to_value(&report)?)?;
+                    report
+                }
+            };
+            if plan.items.is_empty() {
+                eprintln!("Nothing to purge");
+                return Ok(());
+            }
+            let approved = approval::check_apply(&plan, token.as_deref())?;
+            let shown = display::load();
+            let items = within_risk(plan.items.clone(), max_risk);
+            let accepted = accept_targets(&plan, &items, roots, yes)?;
+            let approved_by = approved.map(|a| format!("{} ({})", a.approver, a.approver_id));
+            let outcome = purge::purge(&items, &accepted, yes, &shown, confirm, |targets| {
+                apply_targets("purge", targets, Some(&items), fast, reinstall_on_demand, &ctx, approved_by)
+            })?;
+            eprintln!("{}", outcome.summary(&shown));
+            if matches!(outcome, purge::PurgeOutcome::Declined) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Canary { action } => {
+            if let Some(CanaryAction::Abort { plan_hash }) = action {
+                if !canary::finish(&plan_hash) {
+                    eprintln!("No canary for plan {}", plan_hash);
+                    std::process::exit(2);
+                }
+            }
+            println!("{}", serde_json::to_string_pretty(&canary::list(&safety::list_quarantine()))?);
+        }
+        Commands::SoftDisabled { action } => match action.unwrap_or(SoftAction::Show) {
+            SoftAction::Show => println!("{}", serde_json::to_string_pretty(&soft_disable::list()?)?),
+            SoftAction::Restore { target, force } => {
+                let restored = soft_disable::restore(&target, force)?;
+                println!("{}", serde_json::to_string_pretty(&restored)?);
+            }
+            SoftAction::Commit => {
+                let ready = soft_disable::ready()?;
+                if ready.is_empty() {
+                    eprintln!("No soft-disabled batch is ready to quarantine");
+                    return Ok(());
+                }
+                let mut targets = Vec::new();
+                for item in ready.iter().flat_map(|b| &b.items) {
+                    match soft_disable::reenable(item) {
+                        Ok(()) => targets.push(PathBuf::from(&item.original_path)),
+                        Err(e) => eprintln!("Failed to re-enable {:?}: {}", item.original_path, e),
+                    }
+                }
+                eprintln!("Quarantining {} items from {} soft-disabled batches", targets.len(), ready.len());
+                let committed: Vec<String> = match apply_targets("soft-commit", &targets, None, false, false, &ctx, None) {
+                    Ok(records) => records.into_iter().map(|r| r.original_path).collect(),
+                    Err(e) => {
+                        eprintln!("Failed to quarantine soft-disabled items: {}", e);
+                        Vec::new()
+                    }
+                };
+                let mut held = 0;
+                for batch in &ready {
+                    held += soft_disable::settle(batch, &committed)?.len();
+                }
+                if held > 0 {
+                    eprintln!("{} items stay disabled for the next `soft-disabled commit`", held);
+                }
+            }
+        },
+        Commands::Approval { action } => match action {
+            Some(ApprovalAction::Enroll) => {
+                println!("{}", serde_json::to_string_pretty(&approval::enroll()?)?);
+            }
+            Some(ApprovalAction::Approvers) => {
+                println!("{}", serde_json::to_string_pretty(&approval::approvers())?);
+            }
+            action => {
+                if let Some(ApprovalAction::Set { threshold, ttl_hours }) = action {
+                    approval::save_config(&approval::ApprovalConfig { threshold_bytes: threshold, token_ttl_hours: ttl_hours })?;
+                }
+                println!("{}", serde_json::to_string_pretty(&approval::load_config())?);
+            }
+        },
+        Commands::RepoActivity { paths, preserve_days } => {
+            let scan = hooked_scan(&paths, true, cli.cache_validation, &ctx)?;
+            let preserve_days = preserve_days.unwrap_or_else(default_preserve_days);
+            let found = repo_activity::lookup_projects(&scan.projects);
+            let now = chrono::Utc::now();
+            let projects: Vec<_> = scan.projects.iter().map(|p| {
+                let activity = found.get(&p.path);
+                serde_json::json!({
+                    "project": p.path,
+                    "remote": repo_activity::origin_url(std::path::Path::new(&p.path)),
+                    "local_last_commit": repo_activity::local_last_commit(std::path::Path::new(&p.path)),
+                    "activity": activity,
+                    "liveness": activity.map_or(repo_activity::Liveness::Unknown, |a| a.liveness(now, preserve_days)),
+                })
+            }).collect();
+            println!("{}", serde_json::to_string_pretty(&projects)?);
+        }
+        Commands::Archive { project, to, include, dry_run } => {
+            if dry_run {
+                println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                    "project": project,
+                    "dirs": archive::artifact_dirs(&project, &include),
+                }))?);
+            } else {
+                let dest = to.unwrap_or_else(|| archive::default_destination().to_string_lossy().to_string());
+                let db = feature_store::FeatureStore::open_default()?;
+                let record = archive::archive_project(&project, &dest, &include, &db, &ctx)?;
+                println!("{}", serde_json::to_string_pretty(&record)?);
+            }
+        }
+        Commands::Unarchive { project } => {
+            let db = feature_store::FeatureStore::open_default()?;
+            let record = archive::unarchive_project(&project, &db, &ctx)?;
+            println!("{}", serde_json::to_string_pretty(&record)?);
+        }
+        Commands::RestoreDeps { projects, dry_run } => {
+            let mut outcomes = Vec::new();
+            for project in &projects {
+                outcomes.push(reinstall::restore_deps(project, dry_run)?);
+            }
+            println!("{}", serde_json::to_string_pretty(&outcomes)?);
+        }
+        Commands::Exec { no_restore, command } => {
+            std::process::exit(exec_command(&command, !no_restore)?);
+        }
+        Commands::Archives { all } => {
+            let db = feature_store::FeatureStore::open_default()?;
+            println!("{}", serde_json::to_string_pretty(&db.list_archives(all)?)?);
+        }
+        Commands::Digest { action } => {
+            let config = digest::load_config();
+            let shown = display::load();
+            let build = || {
+                let db = feature_store::FeatureStore::open_default().ok();
+                digest::build(&config, db.as_ref(), approval::pending_plans(), &safety::list_quarantine(), chrono::Utc::now())
+            };
+            match action.unwrap_or(DigestAction::Show { html: false }) {
+                DigestAction::Show { html } => {
+                    let d = build();
+                    print!("{}", if html { digest::render_html(&d, &shown) } else { digest::render_text(&d, &shown) });
+                }
+                DigestAction::Send { if_due } => {
+                    let last_sent = digest::last_sent();
+                    if if_due && !digest::is_due(&config, last_sent, chrono::Utc::now()) {
+                        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                            "status": "not_due",
+                            "last_sent": last_sent,
+                        }))?);
+                        return Ok(());
+                    }
+                    let d = build();
+                    let delivered = digest::send(&d, &config, &shown)?;
+                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                        "status": "ok",
+                        "delivered": delivered,
+                        "digest": d,
+                    }))?);
+                }
+                DigestAction::Set { period_days, expiry_window_days, html, output, sendmail_to, webhook_url } => {
+                    digest::save_config(&digest::DigestConfig { period_days, expiry_window_days, html, output, sendmail_to, webhook_url })?;
+                    println!("{}", serde_json::to_string_pretty(&digest::load_config())?);
+                }
+                DigestAction::Config => println!("{}", serde_json::to_string_pretty(&config)?),
+            }
+        }
+        Commands::Display { action } => {
+            if let Some(DisplayAction::Set { size_units, dates, time_zone, locale }) = action {
+                display::save_config(&display::DisplayConfig { size_units, dates, time_zone, locale })?;
+            }
+            println!("{}", serde_json::to_string_pretty(&display::load())?);
+        }
+        Commands::Privacy { action } => {
+            if let Some(PrivacyAction::Set { hash_project_paths, drop_command_args, disable_event_logging }) = action {
+                privacy::save_config(&privacy::PrivacyConfig { hash_project_paths, drop_command_args, disable_event_logging })?;
+            }
+            println!("{}", serde_json::to_string_pretty(&privacy::load_config())?);
+        }
+        Commands::Db { action: DbAction::Redact } => {
+            safety::ensure_writable("redact the feature store")?;
+            let mut db = feature_store::FeatureStore::open_default()?;
+            println!("{}", serde_json::to_string_pretty(&db.redact(&privacy::load_config())?)?);
+        }
+        Commands::Overhead { action } => {
+            if let Some(OverheadAction::Set { max, warn_percent }) = action {
+                overhead::save_config(&overhead::OverheadConfig { max_bytes: max, warn_percent })?;
+            }
+            println!("{}", serde_json::to_string_pretty(&overhead::measure(&overhead::load_config()))?);
+        }
+        Commands::Hooks => {
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "config": hooks::config_path(),
+                "hooks": hooks::load_config()?.hooks,
+            }))?);
+        }
+        Commands::ScanRules => {
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "config": scan_rules::config_path(),
+                "rules": scan_rules::load_config(),
+            }))?);
+        }
+        Commands::Role { action } => {
+            match action.unwrap_or(RoleAction::Show) {
+                RoleAction::Show => {}
+                RoleAction::Set { role } => machine_role::save_role(Some(role))?,
+                RoleAction::Reset => machine_role::save_role(None)?,
+            }
+            let (role, source) = MachineRole::current();
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "role": role,
+                "source": source,
+                "detected": MachineRole::detect(),
+                "policy": role.policy(),
+            }))?);
+        }
+        Commands::Config { action } => match action.unwrap_or(ConfigAction::Show) {
+            ConfigAction::Show => println!("{}", serde_json::to_string_pretty(&config_lint::effective())?),
+            ConfigAction::Validate { effective } => {
+                let report = config_lint::validate();
+                for f in &report.findings {
+                    let at = f.key.as_ref().map(|k| format!(" ({})", k)).unwrap_or_default();
+                    eprintln!("{:?}: {}{}: {}", f.severity, f.file, at, f.message);
+                }
+                let mut value = serde_json::to_value(&report)?;
+                if effective {
+                    value["effective"] = serde_json::to_value(config_lint::effective())?;
+                }
+                println!("{}", serde_json::to_string_pretty(&value)?);
+                if !report.valid {
+                    std::process::exit(1);
+                }
+            }
+        },
+        Commands::Wsl { action } => {
+            let env = wsl::detect();
+            match action.unwrap_or(WslAction::Show) {
+                WslAction::Show => println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                    "environment": env,
+                    "other_side_roots": env.other_side_roots(),
+                }))?),
+                WslAction::Scan { paths } => {
+                    if env.side.is_none() {
+                        eprintln!("Neither on Windows nor inside WSL; use `scan` instead");
+                        std::process::exit(2);
+                    }
+                    let mut roots = if paths.is_empty() { vec![std::env::current_dir()?] } else { paths };
+                    roots.extend(env.other_side_roots());
+                    let scan = hooked_scan(&roots, true, cli.cache_validation, &ctx)?;
+                    println!("{}", serde_json::to_string_pretty(&wsl::report(&env, &roots, &scan))?);
+                }
+            }
+        }
+        Commands::Cache { action } => {
+            let cache_path = ScanCache::default_cache_path();
+            let mut cache = ScanCache::load_or_create(&cache_path)?;
+            match action {
+                CacheAction::Stats => {
+                    println!("{}", serde_json::to_string_pretty(&cache.stats())?);
+                }
+                CacheAction::Clear => {
+                    let removed = cache.stats();
+                    cache.clear();
+                    cache.save(&cache_path)?;
+                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                        "status": "ok",
+                        "removed": removed.total_entries + removed.negative_entries,
+                    }))?);
+                }
+                CacheAction::Prune => {
+                    let removed = cache.prune();
+                    cache.save(&cache_path)?;
+                    println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                        "status": "ok",
+                        "removed": removed,
+                        "remaining": cache.stats(),
+                    }))?);
+                }
+            }
+        }
+        Commands::Store { action: StoreAction::Move { new_path, paths } } => {
+            let report = relocate_store(&new_path, &paths, &ctx)?;
+            for link in &report.broken_links {
+                eprintln!("Link does not resolve after relocation: {}", link);
+            }
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.broken_links.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Store { action: StoreAction::Verify } => {
+            let index = StoreIndex::open_default()?;
+            let broken = index.broken()?;
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "checked": index.all()?.len(),
+                "broken": broken,
+            }))?);
+            if !broken.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Store { action: StoreAction::Hash } => {
+            let queue = HashQueue::open_default()?;
+            let entries = hash_queue::store_entries(&get_global_store_path()?);
+            let mut queued = 0;
+            for entry in &entries {
+                if queue.enqueue(JobKind::StoreEntry, entry, None)? {
+                    queued += 1;
+                }
+            }
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "entries": entries.len(), "queued": queued }))?);
+        }
+        Commands::Store { action: StoreAction::Gc { dry_run } } => {
+            let removed = StoreIndex::open_default()?.gc_store(&get_global_store_path()?, dry_run)?;
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "dry_run": dry_run,
+                "entries": removed.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+                "bytes_freed": removed.iter().map(|(_, s)| s).sum::<u64>(),
+            }))?);
+        }
         Commands::ClearCache => {
             let cache_path = ScanCache::default_cache_path();
-            if cache_path.exists() {
-                std::fs::remove_file(&cache_path)?;
+            let legacy_path = cache_path.with_extension("json");
+            if cache_path.exists() || legacy_path.exists() {
+                for path in [&cache_path, &legacy_path].into_iter().filter(|p| p.exists()) {
+                    std::fs::remove_file(path)?;
+                }
                 println!("{}", serde_json::to_string_pretty(&serde_json::json!({
                     "status": "ok",
                     "message": "Scan cache cleared"
@@ -1,6 +1,64 @@
 use chrono::Utc;
+use std::fmt;
+use crate::feature_store::FeatureStore;
 use crate::types::{PackageUsageMetrics, ProjectMetadata, DeveloperBehavior};
 
+/// Number of features in a vector
+pub const FEATURE_COUNT: usize = 10;
+
+/// Version of the feature vector layout, stored with every vector in the feature store.
+/// Bump it whenever a feature is added, removed, reordered or computed differently.
+pub const FEATURE_VERSION: u32 = 1;
+
+/// Feature names, in vector order
+pub const FEATURE_NAMES: [&str; FEATURE_COUNT] = [
+	"days_since_access",
+	"days_since_script",
+	"days_since_build",
+	"access_frequency",
+	"script_frequency",
+	"days_since_commit",
+	"project_type_score",
+	"dep_score",
+	"behavior_days_since_build",
+	"file_access_score",
+];
+
+/// Why a stored feature vector cannot be used with the current schema
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureDrift {
+	/// Written under another `FEATURE_VERSION`
+	Version { stored: u32 },
+	/// Wrong number of features
+	Length { stored: usize },
+	/// A feature is NaN or infinite
+	NotFinite { feature: &'static str },
+}
+
+impl fmt::Display for FeatureDrift {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Version { stored } => write!(f, "feature version {} (current: {})", stored, FEATURE_VERSION),
+			Self::Length { stored } => write!(f, "{} features (expected {})", stored, FEATURE_COUNT),
+			Self::NotFinite { feature } => write!(f, "feature {} is not a finite number", feature),
+		}
+	}
+}
+
+/// Check a vector stored as `version` against the current schema
+pub fn validate_features(version: u32, features: &[f64]) -> Result<(), FeatureDrift> {
+	if version != FEATURE_VERSION {
+		return Err(FeatureDrift::Version { stored: version });
+	}
+	if features.len() != FEATURE_COUNT {
+		return Err(FeatureDrift::Length { stored: features.len() });
+	}
+	match features.iter().position(|f| !f.is_finite()) {
+		Some(i) => Err(FeatureDrift::NotFinite { feature: FEATURE_NAMES[i] }),
+		None => Ok(()),
+	}
+}
+
 #[allow(dead_code)]
 pub trait MlRecommender {
 	fn is_safe_to_evict(&self, package_id: &str) -> Option<bool>;
@@ -28,8 +86,8 @@ impl PredictiveOptimizer {
 		Self { prediction_window_days }
 	}
 
-	/// Extract features from package metadata for ML prediction
-	fn extract_features(
+	/// Extract features from package metadata for ML prediction, in `FEATURE_NAMES` order
+	pub fn extract_features(
 		&self,
 		metrics: &PackageUsageMetrics,
 		project: &ProjectMetadata,
@@ -79,7 +137,7 @@ impl PredictiveOptimizer {
 		// Feature 10: File access frequency
 		let file_access_score = (behavior.file_access_frequency as f64 / 1000.0).min(1.0);
 		
-		vec![
+		let features = vec![
 			days_since_access,
 			days_since_script,
 			days_since_build,
@@ -90,7 +148,27 @@ impl PredictiveOptimizer {
 			dep_score,
 			behavior_days_since_build,
 			file_access_score,
-		]
+		];
+		debug_assert_eq!(features.len(), FEATURE_COUNT);
+		features
+	}
+
+	/// The stored feature vector of `package_key`, recomputed and stored again
+	/// when missing or written under another schema
+	pub fn stored_features(
+		&self,
+		store: &FeatureStore,
+		package_key: &str,
+		metrics: &PackageUsageMetrics,
+		project: &ProjectMetadata,
+		behavior: &DeveloperBehavior,
+	) -> crate::Result<Vec<f64>> {
+		if let Some(features) = store.get_features(package_key)? {
+			return Ok(features);
+		}
+		let features = self.extract_features(metrics, project, behavior);
+		store.store_features(package_key, &features)?;
+		Ok(features)
 	}
 
 	/// Predict whether package should be kept (binary classification)
@@ -101,8 +179,11 @@ impl PredictiveOptimizer {
 		project: &ProjectMetadata,
 		behavior: &DeveloperBehavior,
 	) -> bool {
-		let features = self.extract_features(metrics, project, behavior);
-		
+		self.predict_keep_features(&self.extract_features(metrics, project, behavior))
+	}
+
+	/// `predict_keep` from a vector already extracted (or looked up in the feature store)
+	pub fn predict_keep_features(&self, features: &[f64]) -> bool {
 		// Simple rule-based classifier (can be replaced with actual ML model)
 		// This implements a heuristic that mimics what a trained model would do
 		
@@ -139,16 +220,31 @@ impl PredictiveOptimizer {
 		
 		// Rule 6: Weighted score combining all features
 		// This is a simplified logistic regression-like decision
-		let score = self.compute_keep_score(&features);
+		let score = self.compute_keep_score(features);
 		score > 0.5
 	}
 
+	/// Probability (0.0 to 1.0) that the package will be needed again
+	pub fn keep_probability(
+		&self,
+		metrics: &PackageUsageMetrics,
+		project: &ProjectMetadata,
+		behavior: &DeveloperBehavior,
+	) -> f64 {
+		self.compute_keep_score(&self.extract_features(metrics, project, behavior))
+	}
+
+	/// `keep_probability` from a vector already extracted
+	pub fn keep_probability_features(&self, features: &[f64]) -> f64 {
+		self.compute_keep_score(features)
+	}
+
 	/// Compute a keep score (0.0 to 1.0) based on features
 	/// This mimics a logistic regression output
 	fn compute_keep_score(&self, features: &[f64]) -> f64 {
 		// Weighted combination of features (weights learned from training data in real ML)
 		// For now, use heuristic weights
-		let weights = vec![
+		let weights: [f64; FEATURE_COUNT] = [
 			-0.1,  // days_since_access (negative: more days = lower score)
 			-0.05, // days_since_script
 			-0.03, // days_since_build
@@ -186,3 +282,48 @@ impl MlRecommender for PredictiveOptimizer {
 		self.predict_keep(metrics, project, behavior)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tempfile::tempdir;
+
+	#[test]
+	fn test_validate_features_rejects_drift() {
+		let features = [1.0; FEATURE_COUNT];
+		assert_eq!(validate_features(FEATURE_VERSION, &features), Ok(()));
+		assert_eq!(validate_features(FEATURE_VERSION - 1, &features), Err(FeatureDrift::Version { stored: FEATURE_VERSION - 1 }));
+		assert_eq!(validate_features(FEATURE_VERSION + 1, &features), Err(FeatureDrift::Version { stored: FEATURE_VERSION + 1 }));
+		assert_eq!(validate_features(FEATURE_VERSION, &features[..4]), Err(FeatureDrift::Length { stored: 4 }));
+		let mut infinite = features;
+		infinite[3] = f64::INFINITY;
+		assert_eq!(validate_features(FEATURE_VERSION, &infinite), Err(FeatureDrift::NotFinite { feature: "access_frequency" }));
+	}
+
+	#[test]
+	fn test_stale_stored_features_are_recomputed() {
+		let dir = tempdir().unwrap();
+		let store = FeatureStore::open(&dir.path().join("features.db")).unwrap();
+		let predictor = PredictiveOptimizer::new(90);
+		let project = ProjectMetadata {
+			path: "/w/app".into(),
+			project_type: "node".into(),
+			last_commit_date: None,
+			dependency_count: 3,
+			last_modified: Utc::now(),
+		};
+		let metrics = PackageUsageMetrics { package_key: "lodash@4.17.21".into(), ..Default::default() };
+		let behavior = DeveloperBehavior::default();
+		let current = predictor.extract_features(&metrics, &project, &behavior);
+
+		// Written under another schema version, and under the current one with a feature missing
+		let stale = [-1.0; FEATURE_COUNT];
+		for (version, features) in [(FEATURE_VERSION + 1, &stale[..]), (FEATURE_VERSION, &stale[1..])] {
+			store.store_features_as("lodash@4.17.21", version, features);
+			assert_eq!(store.get_features("lodash@4.17.21").unwrap(), None);
+			let features = predictor.stored_features(&store, "lodash@4.17.21", &metrics, &project, &behavior).unwrap();
+			assert_eq!(features, current);
+			assert_eq!(store.get_features("lodash@4.17.21").unwrap(), Some(current.clone()));
+		}
+	}
+}
@@ -0,0 +1,280 @@
+//! ML Model Caches
+//!
+//! Reports the per-model contents of Hugging Face, Ollama, PyTorch Hub and
+//! Keras caches with their sizes and last use. Models are large and cheap to
+//! re-download compared to their disk cost, so they get their own retention
+//! window instead of the package `preserve_days`.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::types::{DryRunReport, PlanItem, PlanReason};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelSource {
+    HuggingFace,
+    Ollama,
+    TorchHub,
+    Keras,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelCacheEntry {
+    pub source: ModelSource,
+    /// Model identifier, e.g. `bert-base-uncased`, `llama3:8b`
+    pub model: String,
+    pub path: String,
+    pub size_bytes: u64,
+    /// Newest access or modification time of the model's files
+    pub last_used: DateTime<Utc>,
+    pub idle_days: i64,
+    pub stale: bool,
+    /// False when moving `path` alone would not free the model's data (Ollama
+    /// blobs are shared between models); `hint` says how to remove it instead
+    pub removable: bool,
+    pub hint: Option<String>,
+}
+
+/// Model cache roots on this machine
+#[derive(Debug, Clone, Default)]
+pub struct ModelLocations {
+    /// Hugging Face hub cache (`models--org--name` directories)
+    pub huggingface: Vec<PathBuf>,
+    /// Ollama model directories (holding `manifests/` and `blobs/`)
+    pub ollama: Vec<PathBuf>,
+    pub torch_hub: Vec<PathBuf>,
+    pub keras: Vec<PathBuf>,
+}
+
+impl ModelLocations {
+    /// Default locations, honouring `HF_HOME`, `HF_HUB_CACHE`, `OLLAMA_MODELS`
+    /// and `TORCH_HOME`, that exist
+    pub fn detect() -> Self {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        let cache = home.join(".cache");
+        let env = |k: &str| std::env::var_os(k).map(PathBuf::from);
+        let existing = |paths: Vec<Option<PathBuf>>| -> Vec<PathBuf> {
+            let mut seen = HashSet::new();
+            paths.into_iter().flatten().filter(|p| p.is_dir() && seen.insert(p.clone())).collect()
+        };
+        Self {
+            huggingface: existing(vec![
+                env("HF_HUB_CACHE"),
+                env("HF_HOME").map(|h| h.join("hub")),
+                Some(cache.join("huggingface").join("hub")),
+            ]),
+            ollama: existing(vec![env("OLLAMA_MODELS"), Some(home.join(".ollama").join("models"))]),
+            torch_hub: existing(vec![
+                env("TORCH_HOME").map(|t| t.join("hub")),
+                Some(cache.join("torch").join("hub")),
+            ]),
+            keras: existing(vec![Some(home.join(".keras").join("models"))]),
+        }
+    }
+
+    /// Whether `path` lies strictly inside one of the model cache roots
+    pub fn contains(&self, path: &Path) -> bool {
+        self.huggingface.iter().chain(&self.ollama).chain(&self.torch_hub).chain(&self.keras)
+            .any(|root| path.starts_with(root) && path != root)
+    }
+}
+
+/// Whether `path` is inside a detected model cache
+pub fn is_model_cache(path: &Path) -> bool {
+    ModelLocations::detect().contains(path)
+}
+
+/// Per-model entries; models unused for `retention_days` are stale
+pub fn scan_model_caches(locations: &ModelLocations, retention_days: i64) -> Vec<ModelCacheEntry> {
+    let mut out = Vec::new();
+    for hub in &locations.huggingface {
+        for dir in subdirs(hub) {
+            let name = file_name(&dir);
+            // models--org--name, datasets--org--name, spaces--org--name
+            let Some((_, repo)) = name.split_once("--") else { continue };
+            out.push(entry(ModelSource::HuggingFace, repo.replace("--", "/"), &dir));
+        }
+    }
+    for root in &locations.ollama {
+        out.extend(ollama_models(root));
+    }
+    for hub in &locations.torch_hub {
+        for path in children(&hub.join("checkpoints")) {
+            out.push(entry(ModelSource::TorchHub, file_name(&path), &path));
+        }
+        for dir in subdirs(hub).into_iter().filter(|d| file_name(d) != "checkpoints") {
+            out.push(entry(ModelSource::TorchHub, file_name(&dir), &dir));
+        }
+    }
+    for root in &locations.keras {
+        for path in children(root) {
+            out.push(entry(ModelSource::Keras, file_name(&path), &path));
+        }
+    }
+
+    let now = Utc::now();
+    for e in &mut out {
+        e.idle_days = (now - e.last_used).num_days().max(0);
+        e.stale = e.last_used < now - Duration::days(retention_days);
+    }
+    out
+}
+
+/// Plan quarantining stale, removable models
+pub fn plan_model_cleanup(entries: &[ModelCacheEntry]) -> DryRunReport {
+    let items: Vec<PlanItem> = entries.iter().filter(|e| e.stale && e.removable).map(|e| PlanItem {
+        target_path: e.path.clone(),
+        estimated_size_bytes: e.size_bytes,
+        reason: PlanReason::StaleModel { idle_days: e.idle_days },
+        blockers: Vec::new(),
+        risk: None,
+    }).collect();
+    let total = items.iter().map(|i| i.estimated_size_bytes).sum();
+    DryRunReport { items, total_estimated_bytes: total, lru: None, warnings: Vec::new(), confirm: Vec::new(), estimated_inodes: None, eviction: None }
+}
+
+fn entry(source: ModelSource, model: String, path: &Path) -> ModelCacheEntry {
+    let (size_bytes, last_used) = usage(std::iter::once(path.to_path_buf()));
+    ModelCacheEntry {
+        source,
+        model,
+        path: path.to_string_lossy().to_string(),
+        size_bytes,
+        last_used,
+        idle_days: 0,
+        stale: false,
+        removable: true,
+        hint: None,
+    }
+}
+
+/// Total size and newest access/modification time of the files below `paths`
+/// (directory times move whenever the tool touches its index, so only files
+/// count). Symlinks (Hugging Face snapshots point into `blobs/`) are not followed, as
+/// the blobs are counted directly.
+fn usage(paths: impl Iterator<Item = PathBuf>) -> (u64, DateTime<Utc>) {
+    let mut size = 0;
+    let mut newest = DateTime::<Utc>::MIN_UTC;
+    for path in paths {
+        for meta in WalkDir::new(&path).into_iter().filter_map(|e| e.ok()).filter_map(|e| e.metadata().ok()) {
+            if !meta.is_file() {
+                continue;
+            }
+            size += meta.len();
+            for t in [meta.accessed(), meta.modified()].into_iter().flatten() {
+                newest = newest.max(t.into());
+            }
+        }
+    }
+    (size, newest)
+}
+
+/// Ollama: one entry per manifest (`manifests/<registry>/<namespace>/<model>/<tag>`),
+/// sized by the blobs its layers reference
+fn ollama_models(root: &Path) -> Vec<ModelCacheEntry> {
+    let manifests = root.join("manifests");
+    WalkDir::new(&manifests).min_depth(4).max_depth(4)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            // Before reading it, which bumps its atime
+            let (_, manifest_used) = usage(std::iter::once(e.path().to_path_buf()));
+            let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(e.path()).ok()?).ok()?;
+            let blobs = json.get("layers").and_then(|l| l.as_array()).into_iter().flatten()
+                .chain(json.get("config"))
+                .filter_map(|layer| layer.get("digest")?.as_str())
+                .map(|digest| root.join("blobs").join(digest.replace(':', "-")));
+            let (size_bytes, blob_used) = usage(blobs);
+
+            let rel: Vec<String> = e.path().strip_prefix(&manifests).ok()?
+                .components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+            let model = match rel[1].as_str() {
+                "library" => format!("{}:{}", rel[2], rel[3]),
+                namespace => format!("{}/{}:{}", namespace, rel[2], rel[3]),
+            };
+            Some(ModelCacheEntry {
+                source: ModelSource::Ollama,
+                hint: Some(format!("ollama rm {}", model)),
+                model,
+                path: e.path().to_string_lossy().to_string(),
+                size_bytes,
+                last_used: blob_used.max(manifest_used),
+                idle_days: 0,
+                stale: false,
+                removable: false,
+            })
+        })
+        .collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+fn children(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|rd| rd.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default();
+    paths.sort();
+    paths
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    children(dir).into_iter().filter(|p| p.is_dir() && !p.is_symlink()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration as StdDuration, SystemTime};
+    use tempfile::tempdir;
+
+    fn age(path: &Path, days: u64) {
+        let when = SystemTime::now() - StdDuration::from_secs(days * 86_400);
+        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+            fs::File::options().write(true).open(entry.path()).unwrap()
+                .set_times(fs::FileTimes::new().set_accessed(when).set_modified(when)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_scan_model_caches() {
+        let temp = tempdir().unwrap();
+        let hub = temp.path().join("hf/hub");
+        let bert = hub.join("models--google--bert-base");
+        fs::create_dir_all(bert.join("blobs")).unwrap();
+        fs::write(bert.join("blobs/abc"), vec![0u8; 1000]).unwrap();
+        age(&bert, 200);
+        let fresh = hub.join("models--gpt2");
+        fs::create_dir_all(fresh.join("blobs")).unwrap();
+        fs::write(fresh.join("blobs/def"), vec![0u8; 10]).unwrap();
+
+        let ollama = temp.path().join("ollama");
+        let manifest = ollama.join("manifests/registry.ollama.ai/library/llama3/8b");
+        fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+        fs::create_dir_all(ollama.join("blobs")).unwrap();
+        fs::write(ollama.join("blobs/sha256-aa"), vec![0u8; 500]).unwrap();
+        fs::write(&manifest, r#"{"layers":[{"digest":"sha256:aa"}]}"#).unwrap();
+        age(&ollama, 200);
+
+        let locations = ModelLocations { huggingface: vec![hub], ollama: vec![ollama], ..Default::default() };
+        let entries = scan_model_caches(&locations, 60);
+        let find = |m: &str| entries.iter().find(|e| e.model == m).unwrap();
+        assert_eq!(find("google/bert-base").size_bytes, 1000);
+        assert!(find("google/bert-base").stale);
+        assert!(find("google/bert-base").idle_days >= 199);
+        assert!(!find("gpt2").stale);
+        let llama = find("llama3:8b");
+        assert_eq!((llama.size_bytes, llama.stale, llama.removable), (500, true, false));
+
+        let plan = plan_model_cleanup(&entries);
+        assert_eq!(plan.items.len(), 1);
+        assert_eq!(plan.total_estimated_bytes, 1000);
+    }
+}
@@ -0,0 +1,404 @@
+//! Native Directory Enumeration
+//!
+//! Sizing a package means visiting every file below it, and with walkdir each
+//! file costs a separate `stat`. On macOS `getattrlistbulk` returns names,
+//! types and lengths for a whole batch of entries per call; on Windows
+//! `FindFirstFileExW` with basic info and large fetch reads them in big
+//! `NtQueryDirectoryFile` batches. Both are used for `tree_totals` when
+//! available. Elsewhere, or when a native call fails part way, the tree is
+//! walked with walkdir instead, so results never depend on the platform.
+//!
+//! The walk itself sizes subdirectories in parallel on the rayon pool, so a
+//! single large tree (a CI cache, a quarantine entry) is not read one
+//! directory at a time.
+//!
+//! A file with several hard links counts once, by device and inode, the way
+//! `du` counts it. When the tree holds only some of its links (the rest are
+//! in the global store, a quarantine pool or another package), removing the
+//! tree frees nothing, so its bytes are reported as `linked_bytes` instead
+//! of `bytes`. Windows does not list link counts with directory entries, so
+//! there every link counts in full.
+//!
+//! Online-only files of a cloud sync client report their full length but
+//! take no space, so they count as files and not as bytes, and online-only
+//! directories are not entered, which would download their listings.
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::cloud_sync::is_placeholder;
+
+/// Whether this platform has online-only files at all
+const PLACEHOLDERS: bool = cfg!(any(target_os = "macos", windows));
+
+/// Byte and entry counts of a directory tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeTotals {
+    /// Lengths of regular files, the bytes removing the tree frees
+    pub bytes: u64,
+    /// Lengths of files also hard-linked from outside the tree
+    pub linked_bytes: u64,
+    /// Directories, including the root
+    pub dirs: u64,
+    pub files: u64,
+}
+
+/// Totals of the tree at `root`, not following symlinks and pruning
+/// directories named `skip` below the root
+pub fn tree_totals(root: &Path, skip: Option<&str>) -> TreeTotals {
+    native_totals(root, skip).unwrap_or_else(|| walk_totals(root, skip))
+}
+
+impl TreeTotals {
+    fn add(mut self, other: Self) -> Self {
+        self.bytes += other.bytes;
+        self.linked_bytes += other.linked_bytes;
+        self.dirs += other.dirs;
+        self.files += other.files;
+        self
+    }
+}
+
+/// Files with several hard links met in one tree, by device and inode:
+/// their length, link count and the links found in the tree
+#[derive(Default)]
+struct LinkTally(HashMap<(u64, u64), (u64, u64, u64)>);
+
+impl LinkTally {
+    fn add(&mut self, dev: u64, ino: u64, nlink: u64, len: u64) {
+        self.0.entry((dev, ino)).or_insert((len, nlink, 0)).2 += 1;
+    }
+
+    /// Count each file once: in `bytes` when the tree holds all its links,
+    /// in `linked_bytes` otherwise
+    fn settle(self, totals: &mut TreeTotals) {
+        for (len, nlink, found) in self.0.into_values() {
+            if found >= nlink {
+                totals.bytes += len;
+            } else {
+                totals.linked_bytes += len;
+            }
+        }
+    }
+}
+
+/// Add a regular file to `totals`, or to `links` when it has several links
+#[cfg(unix)]
+fn count_file(meta: &fs::Metadata, totals: &mut TreeTotals, links: &Mutex<LinkTally>) {
+    use std::os::unix::fs::MetadataExt;
+    if meta.nlink() <= 1 {
+        totals.bytes += meta.len();
+    } else if let Ok(mut links) = links.lock() {
+        links.add(meta.dev(), meta.ino(), meta.nlink(), meta.len());
+    }
+}
+
+#[cfg(not(unix))]
+fn count_file(meta: &fs::Metadata, totals: &mut TreeTotals, _links: &Mutex<LinkTally>) {
+    totals.bytes += meta.len();
+}
+
+/// Portable fallback
+fn walk_totals(root: &Path, skip: Option<&str>) -> TreeTotals {
+    let links = Mutex::default();
+    let mut totals = match fs::metadata(root) {
+        Ok(meta) if meta.is_dir() => walk_dir(root, skip, &links),
+        Ok(meta) if meta.is_file() => {
+            let mut totals = TreeTotals { files: 1, ..Default::default() };
+            if !is_placeholder(&meta) {
+                count_file(&meta, &mut totals, &links);
+            }
+            totals
+        }
+        _ => TreeTotals::default(),
+    };
+    if let Ok(links) = links.into_inner() {
+        links.settle(&mut totals);
+    }
+    totals
+}
+
+/// Totals of `dir`, its subdirectories walked in parallel. Unreadable
+/// entries are skipped.
+fn walk_dir(dir: &Path, skip: Option<&str>, links: &Mutex<LinkTally>) -> TreeTotals {
+    let mut totals = TreeTotals { dirs: 1, ..Default::default() };
+    let Ok(entries) = fs::read_dir(dir) else { return totals };
+    let mut subdirs = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        // Symlinks and special files are not followed or counted
+        let Ok(file_type) = entry.file_type() else { continue };
+        if file_type.is_dir() {
+            let pruned = skip.is_some_and(|s| entry.file_name() == s)
+                || PLACEHOLDERS && entry.metadata().is_ok_and(|m| is_placeholder(&m));
+            if !pruned {
+                subdirs.push(entry.path());
+            }
+        } else if file_type.is_file() {
+            totals.files += 1;
+            match entry.metadata() {
+                Ok(meta) if !is_placeholder(&meta) => count_file(&meta, &mut totals, links),
+                _ => {}
+            }
+        }
+    }
+    subdirs.par_iter()
+        .map(|sub| walk_dir(sub, skip, links))
+        .reduce(TreeTotals::default, TreeTotals::add)
+        .add(totals)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn native_totals(_root: &Path, _skip: Option<&str>) -> Option<TreeTotals> {
+    None
+}
+
+#[cfg(target_os = "macos")]
+fn native_totals(root: &Path, skip: Option<&str>) -> Option<TreeTotals> {
+    use std::ffi::{CStr, CString};
+    use std::mem::size_of;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    // `enum vtype` from <sys/vnode.h>
+    const VREG: u32 = 1;
+    const VDIR: u32 = 2;
+
+    let mut attrs = libc::attrlist {
+        bitmapcount: libc::ATTR_BIT_MAP_COUNT,
+        reserved: 0,
+        commonattr: libc::ATTR_CMN_RETURNED_ATTRS | libc::ATTR_CMN_NAME | libc::ATTR_CMN_DEVID
+            | libc::ATTR_CMN_OBJTYPE | libc::ATTR_CMN_FLAGS | libc::ATTR_CMN_FILEID,
+        volattr: 0,
+        dirattr: 0,
+        fileattr: libc::ATTR_FILE_LINKCOUNT | libc::ATTR_FILE_DATALENGTH,
+        forkattr: 0,
+    };
+    let mut buf = vec![0u8; 256 * 1024];
+    let mut totals = TreeTotals::default();
+    let mut links = LinkTally::default();
+    let mut stack: Vec<PathBuf> = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let c_dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let fd = unsafe { libc::open(c_dir.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC) };
+        if fd < 0 {
+            // Unreadable subdirectories are skipped like walkdir errors are
+            if dir == root {
+                return None;
+            }
+            continue;
+        }
+        totals.dirs += 1;
+        loop {
+            let count = unsafe {
+                libc::getattrlistbulk(fd, &mut attrs as *mut _ as *mut _, buf.as_mut_ptr() as *mut _, buf.len(), 0)
+            };
+            if count < 0 {
+                unsafe { libc::close(fd) };
+                return None;
+            }
+            if count == 0 {
+                break;
+            }
+            // Each entry: u32 length, returned attribute set, then the
+            // requested attributes in bit order within each group (common,
+            // then file); only returned ones are packed
+            let mut entry = 0usize;
+            for _ in 0..count {
+                let read_u32 = |at: usize| u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap());
+                let len = read_u32(entry) as usize;
+                let mut at = entry + 4;
+                let returned: libc::attribute_set_t =
+                    unsafe { std::ptr::read_unaligned(buf.as_ptr().add(at) as *const _) };
+                at += size_of::<libc::attribute_set_t>();
+
+                let mut name = None;
+                if returned.commonattr & libc::ATTR_CMN_NAME != 0 {
+                    let name_ref: libc::attrreference_t =
+                        unsafe { std::ptr::read_unaligned(buf.as_ptr().add(at) as *const _) };
+                    let start = (at as isize + name_ref.attr_dataoffset as isize) as usize;
+                    name = CStr::from_bytes_until_nul(&buf[start..start + name_ref.attr_length as usize]).ok();
+                    at += size_of::<libc::attrreference_t>();
+                }
+                let mut dev = 0;
+                if returned.commonattr & libc::ATTR_CMN_DEVID != 0 {
+                    dev = read_u32(at) as u64;
+                    at += size_of::<libc::dev_t>();
+                }
+                let mut kind = 0;
+                if returned.commonattr & libc::ATTR_CMN_OBJTYPE != 0 {
+                    kind = read_u32(at);
+                    at += size_of::<u32>();
+                }
+                let mut dataless = false;
+                if returned.commonattr & libc::ATTR_CMN_FLAGS != 0 {
+                    dataless = read_u32(at) & crate::cloud_sync::SF_DATALESS != 0;
+                    at += size_of::<u32>();
+                }
+                let mut file_id = None;
+                if returned.commonattr & libc::ATTR_CMN_FILEID != 0 {
+                    file_id = Some(u64::from_ne_bytes(buf[at..at + 8].try_into().unwrap()));
+                    at += size_of::<u64>();
+                }
+                match kind {
+                    VREG => {
+                        totals.files += 1;
+                        let mut link_count = 1;
+                        if returned.fileattr & libc::ATTR_FILE_LINKCOUNT != 0 {
+                            link_count = read_u32(at);
+                            at += size_of::<u32>();
+                        }
+                        if returned.fileattr & libc::ATTR_FILE_DATALENGTH != 0 && !dataless {
+                            let length: libc::off_t =
+                                unsafe { std::ptr::read_unaligned(buf.as_ptr().add(at) as *const _) };
+                            match file_id {
+                                Some(id) if link_count > 1 => links.add(dev, id, link_count as u64, length as u64),
+                                _ => totals.bytes += length as u64,
+                            }
+                        }
+                    }
+                    VDIR if !dataless => {
+                        if let Some(name) = name.map(|n| std::ffi::OsStr::from_bytes(n.to_bytes())) {
+                            if skip.is_none_or(|s| name != s) {
+                                stack.push(dir.join(name));
+                            }
+                        }
+                    }
+                    // Symlinks and special files are not followed or counted
+                    _ => {}
+                }
+                entry += len;
+            }
+        }
+        unsafe { libc::close(fd) };
+    }
+    links.settle(&mut totals);
+    Some(totals)
+}
+
+#[cfg(windows)]
+fn native_totals(root: &Path, skip: Option<&str>) -> Option<TreeTotals> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::PathBuf;
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW, FindNextFileW,
+        FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT, FIND_FIRST_EX_LARGE_FETCH, WIN32_FIND_DATAW,
+    };
+
+    if !root.is_dir() {
+        return None;
+    }
+    let mut totals = TreeTotals::default();
+    let mut stack: Vec<PathBuf> = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        totals.dirs += 1;
+        let pattern: Vec<u16> = dir.join("*").as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut data = WIN32_FIND_DATAW::default();
+        let handle = unsafe {
+            FindFirstFileExW(
+                pattern.as_ptr(),
+                FindExInfoBasic,
+                &mut data as *mut _ as *mut _,
+                FindExSearchNameMatch,
+                std::ptr::null(),
+                FIND_FIRST_EX_LARGE_FETCH,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            // Unreadable subdirectories are skipped like walkdir errors are
+            if dir == root {
+                return None;
+            }
+            continue;
+        }
+        loop {
+            let name_len = data.cFileName.iter().position(|c| *c == 0).unwrap_or(data.cFileName.len());
+            let name = OsString::from_wide(&data.cFileName[..name_len]);
+            let attributes = data.dwFileAttributes;
+            // Reparse points (symlinks, junctions) are not followed or counted
+            let counted = attributes & FILE_ATTRIBUTE_REPARSE_POINT == 0 && name != "." && name != "..";
+            let online_only = attributes & crate::cloud_sync::PLACEHOLDER_ATTRIBUTES != 0;
+            if counted && attributes & FILE_ATTRIBUTE_DIRECTORY != 0 {
+                if skip.is_none_or(|s| name != s) && !online_only {
+                    stack.push(dir.join(&name));
+                }
+            } else if counted {
+                totals.files += 1;
+                if !online_only {
+                    totals.bytes += ((data.nFileSizeHigh as u64) << 32) | data.nFileSizeLow as u64;
+                }
+            }
+            if unsafe { FindNextFileW(handle, &mut data) } == 0 {
+                break;
+            }
+        }
+        unsafe { FindClose(handle) };
+    }
+    Some(totals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_totals_match_walkdir() {
+        let temp = tempdir().unwrap();
+        let pkg = temp.path().join("lodash");
+        fs::create_dir_all(pkg.join("lib/deep")).unwrap();
+        fs::write(pkg.join("package.json"), vec![b'x'; 10]).unwrap();
+        fs::write(pkg.join("lib/a.js"), vec![b'x'; 100]).unwrap();
+        fs::write(pkg.join("lib/deep/b.js"), vec![b'x'; 1000]).unwrap();
+        // Nested node_modules are sized as packages of their own
+        fs::create_dir_all(pkg.join("node_modules/dep")).unwrap();
+        fs::write(pkg.join("node_modules/dep/index.js"), vec![b'x'; 5000]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(pkg.join("lib"), pkg.join("linked")).unwrap();
+
+        let expected = TreeTotals { bytes: 1110, dirs: 3, files: 3, ..Default::default() };
+        assert_eq!(tree_totals(&pkg, Some("node_modules")), expected);
+        assert_eq!(walk_totals(&pkg, Some("node_modules")), expected);
+        assert_eq!(tree_totals(&pkg, None), TreeTotals { bytes: 6110, dirs: 5, files: 4, ..Default::default() });
+
+        // A second link to a file adds a file but no bytes
+        #[cfg(unix)]
+        {
+            fs::hard_link(pkg.join("lib/deep/b.js"), pkg.join("lib/b-link.js")).unwrap();
+            let linked = TreeTotals { bytes: 1110, dirs: 3, files: 4, ..Default::default() };
+            assert_eq!(tree_totals(&pkg, Some("node_modules")), linked);
+            assert_eq!(walk_totals(&pkg, Some("node_modules")), linked);
+            assert_eq!(walk_totals(&pkg.join("lib/a.js"), None), TreeTotals { bytes: 100, dirs: 0, files: 1, ..Default::default() });
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_shared_between_trees() {
+        let temp = tempdir().unwrap();
+        let (store, pkg) = (temp.path().join("store/lodash"), temp.path().join("app/node_modules/lodash"));
+        for dir in [&store, &pkg] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(store.join("lodash.js"), vec![b'x'; 500]).unwrap();
+        fs::hard_link(store.join("lodash.js"), pkg.join("lodash.js")).unwrap();
+        fs::write(pkg.join("package.json"), vec![b'x'; 20]).unwrap();
+
+        // Each tree holds one of the two links, so neither frees the bytes
+        let shared = TreeTotals { bytes: 20, linked_bytes: 500, dirs: 1, files: 2 };
+        assert_eq!(tree_totals(&pkg, None), shared);
+        assert_eq!(walk_totals(&pkg, None), shared);
+        assert_eq!(walk_totals(&store, None), TreeTotals { linked_bytes: 500, dirs: 1, files: 1, ..Default::default() });
+
+        // A tree holding every link frees them, once
+        let both = walk_totals(temp.path(), None);
+        assert_eq!((both.bytes, both.linked_bytes, both.files), (520, 0, 3));
+    }
+}
@@ -1,18 +1,29 @@
-use anyhow::Result;
-use chrono::{Duration, Utc};
+use crate::error::Result;
+use serde::Serialize;
+use chrono::{DateTime, Duration, Utc};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::types::{DryRunReport, PlanItem, ScanOutput, PackageUsageMetrics, ProjectMetadata, DeveloperBehavior};
-use crate::symlink::SemanticDeduplication;
+use crate::types::{DedupBlocker, DryRunReport, PlanItem, PlanReason, ScanOutput, PackageUsageMetrics, ProjectMetadata, DeveloperBehavior};
+use crate::symlink::{detect_blockers, get_canonical_path, get_global_store_path, open_file_snapshot, project_dedup_mode, DedupMode, SemanticDeduplication};
 use crate::cache::PackageLruCache;
+use crate::eviction::{self, EvictionPolicy, PolicyKind};
+use crate::feature_store::FeatureStore;
 use crate::ml::{MlRecommender, PredictiveOptimizer};
-
-#[allow(dead_code)]
-pub enum EvictionPolicy {
-	MlThenArcThenLru,
-	LruOnly,
-}
+use crate::verify::{owning_project, verify_project, VerifyOutcome};
+use crate::safety::is_protected_path;
+use crate::progress::{count_files, OperationContext, Phase, ThroughputMeter, ThroughputSummary};
+use crate::canonical::{select_canonical, CanonicalChoice, CanonicalStrategy};
+use crate::types::{PackageManager, PackageRecord};
+use crate::cargo_caches;
+use crate::python_caches;
+use crate::go_caches;
+use crate::store_index::StoreIndex;
+use crate::integrity::IntegrityChecker;
+use crate::patches::PatchIndex;
+use crate::timings::{self, TimedPhase};
+use crate::hoisting::{redundant_copies, RedundantCopy};
+use crate::repo_activity::{local_last_commit, Liveness, RepoActivity};
 
 #[allow(dead_code)]
 pub struct RulesConfig {
@@ -25,10 +36,48 @@ pub struct RulesConfig {
 	pub lru_max_packages: usize,
 	#[allow(dead_code)]
 	pub lru_max_size_bytes: u64,
+	/// Which copy of a duplicated package is kept when symlinking
+	pub canonical_strategy: CanonicalStrategy,
+	/// Default dedup mode; projects can override it in their package.json
+	pub dedup_mode: DedupMode,
+	/// Never evict or deduplicate packages patched via patch-package or pnpm
+	/// `patchedDependencies`
+	pub protect_patched: bool,
+}
+
+/// Resolve the dedup mode for a package from its project's override, falling
+/// back to `default`. Lookups are memoized per project.
+fn dedup_mode_for(package_path: &Path, default: DedupMode, cache: &mut HashMap<PathBuf, DedupMode>) -> DedupMode {
+	match owning_project(package_path) {
+		Some(project) => *cache.entry(project.clone())
+			.or_insert_with(|| project_dedup_mode(&project).unwrap_or(default)),
+		None => default,
+	}
+}
+
+/// Cargo, Python and Go packages follow their own rules, see `cargo_caches`,
+/// `python_caches` and `go_caches`: Some with the item, if planned, for them and None for
+/// packages of other managers
+fn ecosystem_plan_item(pkg: &PackageRecord, locked: bool, cutoff: DateTime<Utc>) -> Option<Option<PlanItem>> {
+	let reason = match pkg.manager {
+		Some(PackageManager::Cargo) => cargo_caches::plan_reason(pkg, locked, cutoff),
+		Some(PackageManager::Python) => python_caches::plan_reason(pkg, locked, cutoff),
+		Some(PackageManager::Go) => go_caches::plan_reason(pkg, locked),
+		_ => return None,
+	};
+	Some(reason.map(|reason| PlanItem {
+		target_path: pkg.path.clone(),
+		estimated_size_bytes: pkg.size_bytes,
+		reason,
+		blockers: Vec::new(),
+		risk: None,
+	}))
 }
 
 pub fn plan_basic_cleanup(scan: &ScanOutput, cfg: &RulesConfig) -> Result<DryRunReport> {
+	let _timing = timings::span(TimedPhase::Plan);
 	let cutoff = Utc::now() - Duration::days(cfg.preserve_days);
+	let mut patches = PatchIndex::default();
 
 	let mut used: HashSet<(String, String)> = HashSet::new();
 	for proj in &scan.projects {
@@ -37,12 +86,19 @@ pub fn plan_basic_cleanup(scan: &ScanOutput, cfg: &RulesConfig) -> Result<DryRun
 		}
 	}
 
-	let mut seen_locations: HashMap<(String, String), Vec<PathBuf>> = HashMap::new();
+	let mut eligible: HashSet<&str> = HashSet::new();
 
 	let mut items: Vec<PlanItem> = Vec::new();
-	for pkg in &scan.packages {
+	for pkg in scan.packages.iter().filter(|p| !is_protected_path(Path::new(&p.path))) {
+		if cfg.protect_patched && patches.is_patched(pkg) {
+			continue;
+		}
 		let key = (pkg.name.clone(), pkg.version.clone());
-		seen_locations.entry(key.clone()).or_default().push(PathBuf::from(&pkg.path));
+		if let Some(item) = ecosystem_plan_item(pkg, used.contains(&key), cutoff) {
+			items.extend(item);
+			continue;
+		}
+		eligible.insert(&pkg.path);
 
 		let is_orphan = !used.contains(&key);
 		let is_old = pkg.mtime < cutoff;
@@ -51,21 +107,171 @@ pub fn plan_basic_cleanup(scan: &ScanOutput, cfg: &RulesConfig) -> Result<DryRun
 			items.push(PlanItem {
 				target_path: pkg.path.clone(),
 				estimated_size_bytes: pkg.size_bytes,
-				reason: if is_orphan { "orphaned".into() } else { "old".into() },
+				reason: if is_orphan {
+					PlanReason::Orphaned
+				} else {
+					PlanReason::Old { days: (Utc::now() - pkg.mtime).num_days() }
+				},
+				blockers: Vec::new(),
+				risk: None,
 			});
 		}
 	}
 
-	for (_key, paths) in seen_locations.into_iter() {
-		if paths.len() > 1 {
-			for p in paths.into_iter().skip(1) {
-				items.push(PlanItem { target_path: p.to_string_lossy().to_string(), estimated_size_bytes: 0, reason: "duplicate".into() });
+	// Only copies resolution can do without; version-conflict nesting stays
+	for (pkg, why) in redundant_copies(scan, |p| eligible.contains(p.path.as_str())) {
+		let canonical = match why {
+			RedundantCopy::Shadowed { by } => by,
+			RedundantCopy::Extraneous { other } => other,
+		};
+		items.push(PlanItem {
+			target_path: pkg.path.clone(),
+			estimated_size_bytes: 0,
+			reason: PlanReason::Duplicate { canonical },
+			blockers: Vec::new(),
+			risk: None,
+		});
+	}
+
+	let total = items.iter().map(|i| i.estimated_size_bytes).sum();
+	Ok(DryRunReport { items, total_estimated_bytes: total, lru: None, warnings: Vec::new(), confirm: Vec::new(), estimated_inodes: None, eviction: None })
+}
+
+/// Plan symlink deduplication without touching the filesystem.
+///
+/// Group unprotected packages by name@version, in scan order, and reorder each
+/// group so the copy chosen by `strategy` comes first
+fn duplicate_groups(scan: &ScanOutput, strategy: CanonicalStrategy) -> Vec<(Vec<&PackageRecord>, CanonicalChoice)> {
+	let mut groups: HashMap<(String, String), Vec<&PackageRecord>> = HashMap::new();
+	let mut order: Vec<(String, String)> = Vec::new();
+	for pkg in scan.packages.iter().filter(|p| !is_protected_path(Path::new(&p.path))) {
+		let key = (pkg.name.clone(), pkg.version.clone());
+		let entry = groups.entry(key.clone()).or_default();
+		if entry.is_empty() {
+			order.push(key);
+		}
+		entry.push(pkg);
+	}
+
+	let project_mtimes: HashMap<String, _> = scan.projects.iter().map(|p| (p.path.clone(), p.mtime)).collect();
+	order.into_iter().filter_map(|key| {
+		let mut pkgs = groups.remove(&key)?;
+		if pkgs.len() < 2 {
+			return None;
+		}
+		let (idx, detail) = select_canonical(&pkgs, strategy, &project_mtimes);
+		let canonical = pkgs.remove(idx);
+		pkgs.insert(0, canonical);
+		let choice = CanonicalChoice {
+			package: format!("{}@{}", key.0, key.1),
+			canonical: canonical.path.clone(),
+			strategy,
+			candidates: pkgs.len(),
+			detail,
+		};
+		Some((pkgs, choice))
+	}).collect()
+}
+
+/// Copies in a duplicate group that must not be merged, with the reason:
+/// locally patched (when `protect_patched`) or diverging from the lockfile
+/// integrity hash. If the canonical copy is excluded, the first copy that is
+/// not moves to the front in its place; if it is still excluded, so is every copy.
+fn split_excluded(
+	pkgs: &mut Vec<&PackageRecord>,
+	protect_patched: bool,
+	patches: &mut PatchIndex,
+	checker: &mut IntegrityChecker,
+) -> HashMap<String, DedupBlocker> {
+	let excluded: HashMap<String, DedupBlocker> = pkgs.iter()
+		.filter_map(|p| {
+			if protect_patched && patches.is_patched(p) {
+				Some((p.path.clone(), DedupBlocker::Patched))
+			} else if checker.diverges(p) {
+				Some((p.path.clone(), DedupBlocker::IntegrityMismatch))
+			} else {
+				None
+			}
+		})
+		.collect();
+	if let Some(idx) = pkgs.iter().position(|p| !excluded.contains_key(&p.path)).filter(|&i| i > 0) {
+		let canonical = pkgs.remove(idx);
+		pkgs.insert(0, canonical);
+	}
+	excluded
+}
+
+/// Mirrors `OptimizationEngine::execute_symlinking`: the canonical copy of each
+/// name@version (picked by `cfg.canonical_strategy`) stays in place and seeds the global store
+/// via hard links, and every other copy becomes a symlink. If the canonical copy
+/// cannot seed the store, the first unblocked duplicate does instead. Duplicates
+/// with blockers are reported but excluded from savings. Duplicates in projects
+/// using hardlink mode are reported as `hardlink_to_store`. Patched copies and
+/// copies that diverge from their lockfile integrity hash are never merged.
+pub fn plan_symlinking(scan: &ScanOutput, cfg: &RulesConfig) -> Result<DryRunReport> {
+	let _timing = timings::span(TimedPhase::Plan);
+	let store_path = get_global_store_path()?;
+	let open_files = open_file_snapshot();
+	let mut modes: HashMap<PathBuf, DedupMode> = HashMap::new();
+	let mut integrity = IntegrityChecker::default();
+	let mut patches = PatchIndex::default();
+
+	let mut items: Vec<PlanItem> = Vec::new();
+	for (mut pkgs, _) in duplicate_groups(scan, cfg.canonical_strategy) {
+		let excluded = split_excluded(&mut pkgs, cfg.protect_patched, &mut patches, &mut integrity);
+		let canonical = get_canonical_path(&store_path, &pkgs[0].name, &pkgs[0].version)?;
+		let mut store_seeded = canonical.exists()
+			|| detect_blockers(Path::new(&pkgs[0].path), &store_path, &open_files).is_empty();
+
+		for pkg in pkgs.iter().skip(1) {
+			let path = PathBuf::from(&pkg.path);
+			let mode = dedup_mode_for(&path, cfg.dedup_mode, &mut modes);
+			let mut blockers = detect_blockers(&path, &store_path, &open_files);
+			if mode == DedupMode::Hardlink {
+				// Native build output stays in place; only identical files get linked
+				blockers.retain(|b| *b != DedupBlocker::NativeBuild);
+			}
+			if let Some(blocker) = excluded.get(&pkg.path) {
+				blockers.push(blocker.clone());
+			}
+			if !blockers.is_empty() {
+				items.push(PlanItem {
+					target_path: pkg.path.clone(),
+					estimated_size_bytes: 0,
+					reason: PlanReason::SymlinkBlocked,
+					blockers,
+					risk: None,
+				});
+				continue;
+			}
+
+			if store_seeded {
+				items.push(PlanItem {
+					target_path: pkg.path.clone(),
+					estimated_size_bytes: pkg.size_bytes,
+					reason: match mode {
+						DedupMode::Symlink => PlanReason::SymlinkToStore,
+						DedupMode::Hardlink => PlanReason::HardlinkToStore,
+					},
+					blockers,
+					risk: None,
+				});
+			} else {
+				// Hard-linked into the store, so its bytes stay on disk
+				items.push(PlanItem {
+					target_path: pkg.path.clone(),
+					estimated_size_bytes: 0,
+					reason: PlanReason::MoveToStore,
+					blockers,
+					risk: None,
+				});
+				store_seeded = true;
 			}
 		}
 	}
 
 	let total = items.iter().map(|i| i.estimated_size_bytes).sum();
-	Ok(DryRunReport { items, total_estimated_bytes: total })
+	Ok(DryRunReport { items, total_estimated_bytes: total, lru: None, warnings: Vec::new(), confirm: Vec::new(), estimated_inodes: None, eviction: None })
 }
 
 /// Optimization engine with symlinking and ML/LRU strategies
@@ -73,8 +279,17 @@ pub fn plan_basic_cleanup(scan: &ScanOutput, cfg: &RulesConfig) -> Result<DryRun
 pub struct OptimizationEngine {
 	deduplication: Option<SemanticDeduplication>,
 	lru_cache: Option<PackageLruCache>,
+	/// Policy replacing the LRU keep check (`--eviction-policy`)
+	eviction: Option<Box<dyn EvictionPolicy>>,
 	ml_predictor: Option<PredictiveOptimizer>,
 	config: RulesConfig,
+	ctx: OperationContext,
+	/// Remote repository activity by project path (`--remote-activity`)
+	repo_activity: HashMap<String, RepoActivity>,
+	/// Recorded developer behavior by project path, for the predictor
+	behavior: HashMap<String, DeveloperBehavior>,
+	/// Store holding the vectors `ml compute-features` precomputed
+	feature_store: Option<FeatureStore>,
 }
 
 #[allow(dead_code)]
@@ -100,17 +315,56 @@ impl OptimizationEngine {
 		Ok(Self {
 			deduplication,
 			lru_cache,
+			eviction: None,
 			ml_predictor,
 			config,
+			ctx: OperationContext::default(),
+			repo_activity: HashMap::new(),
+			behavior: HashMap::new(),
+			feature_store: None,
 		})
 	}
 
+	/// Judge project liveness by remote repository activity as well
+	pub fn with_repo_activity(mut self, activity: HashMap<String, RepoActivity>) -> Self {
+		self.repo_activity = activity;
+		self
+	}
+
+	/// Feed the predictor the behavior recorded for each project
+	pub fn with_behavior(mut self, behavior: HashMap<String, DeveloperBehavior>) -> Self {
+		self.behavior = behavior;
+		self
+	}
+
+	/// Predict from the feature vectors precomputed in `store` where a package
+	/// has a current one, instead of extracting its features again
+	pub fn with_feature_store(mut self, store: FeatureStore) -> Self {
+		self.feature_store = Some(store);
+		self
+	}
+
+	/// Keep the packages `kind` holds after replaying package accesses through
+	/// it, with room for `lru_max_packages`, instead of asking the LRU cache
+	pub fn with_eviction_policy(mut self, kind: PolicyKind) -> Self {
+		self.eviction = Some(eviction::build(kind, self.config.lru_max_packages));
+		self
+	}
+
+	/// Report progress to, and honour cancellation from, the given context
+	pub fn with_context(mut self, ctx: OperationContext) -> Self {
+		self.ctx = ctx;
+		self
+	}
+
 	/// Plan cleanup with symlinking and ML/LRU optimization
 	pub fn plan_optimized_cleanup(
 		&mut self,
 		scan: &ScanOutput,
 	) -> Result<DryRunReport> {
-		let cutoff = Utc::now() - Duration::days(self.config.preserve_days);
+		let _timing = timings::span(TimedPhase::Plan);
+		let now = Utc::now();
+		let cutoff = now - Duration::days(self.config.preserve_days);
 
 		// Build usage metrics map from scan
 		let mut usage_map: HashMap<String, PackageUsageMetrics> = HashMap::new();
@@ -133,7 +387,8 @@ impl OptimizationEngine {
 			let metadata = ProjectMetadata {
 				path: proj.path.clone(),
 				project_type: detect_project_type(&proj.path),
-				last_commit_date: None, // Would be populated from git
+				last_commit_date: local_last_commit(Path::new(&proj.path))
+					.max(self.repo_activity.get(&proj.path).and_then(|a| a.last_push)),
 				dependency_count: proj.dependencies.len(),
 				last_modified: proj.mtime,
 			};
@@ -147,45 +402,92 @@ impl OptimizationEngine {
 			}
 		}
 
+		// Replay accesses oldest first, so the policy ends holding what it would keep today
+		if let Some(policy) = self.eviction.as_mut() {
+			let mut accesses: Vec<(&PackageRecord, String)> = scan.packages.iter()
+				.map(|p| (p, format!("{}@{}", p.name, p.version)))
+				.collect();
+			accesses.sort_by_key(|(p, _)| p.atime);
+			eviction::simulate(policy.as_mut(), accesses.iter().map(|(_, key)| key.as_str()));
+		}
+
 		let mut seen_locations: HashMap<(String, String), Vec<PathBuf>> = HashMap::new();
 		let mut items: Vec<PlanItem> = Vec::new();
+		let mut patches = PatchIndex::default();
 		let mut symlink_candidates: Vec<(PathBuf, String, String)> = Vec::new();
-
-		for pkg in &scan.packages {
+		let mut feature_lookup_failed = false;
+
+		let total_pkgs = scan.packages.len() as u64;
+		for (i, pkg) in scan.packages.iter().enumerate() {
+			self.ctx.check()?;
+			self.ctx.report(Phase::Plan, i as u64 + 1, Some(total_pkgs), Some(Path::new(&pkg.path)));
+			if is_protected_path(Path::new(&pkg.path)) {
+				continue;
+			}
+			if self.config.protect_patched && patches.is_patched(pkg) {
+				continue;
+			}
 			let key = (pkg.name.clone(), pkg.version.clone());
+			if let Some(item) = ecosystem_plan_item(pkg, used.contains(&key), cutoff) {
+				items.extend(item);
+				continue;
+			}
 			seen_locations.entry(key.clone()).or_default().push(PathBuf::from(&pkg.path));
 
 			let package_key = format!("{}@{}", pkg.name, pkg.version);
 			let is_orphan = !used.contains(&key);
-			let is_old = pkg.mtime < cutoff;
+			let mut is_old = pkg.mtime < cutoff;
+			let activity = owning_project(Path::new(&pkg.path))
+				.and_then(|p| self.repo_activity.get(&*p.to_string_lossy()));
+			let liveness = activity.map_or(Liveness::Unknown, |a| a.liveness(now, self.config.preserve_days));
+			// Developed elsewhere: age of the local copy says nothing
+			if liveness == Liveness::Active {
+				is_old = false;
+			}
+			let dormant = is_old && liveness == Liveness::Dormant;
 
 			// Record access in LRU cache
 			if let Some(ref mut cache) = self.lru_cache {
 				cache.record_access(&package_key, pkg.size_bytes);
 			}
 
-			// Check ML prediction
-			let should_keep_ml = if let Some(ref predictor) = self.ml_predictor {
-				if let (Some(metrics), Some(proj_path)) = (usage_map.get(&package_key), pkg.project_paths.first()) {
+			// Check ML prediction (keep decision plus eviction confidence)
+			let (should_keep_ml, ml_confidence) = if let Some(ref predictor) = self.ml_predictor {
+				let stored = match self.feature_store.as_ref().map(|db| db.get_features(&package_key)) {
+					Some(Ok(features)) => features,
+					Some(Err(e)) => {
+						// A failed lookup only costs the precomputation; say so once per plan
+						if !std::mem::replace(&mut feature_lookup_failed, true) {
+							eprintln!("Warning: Failed to read stored feature vectors, extracting them instead: {}", e);
+						}
+						None
+					}
+					None => None,
+				};
+				if let Some(features) = stored {
+					(predictor.predict_keep_features(&features), 1.0 - predictor.keep_probability_features(&features))
+				} else if let (Some(metrics), Some(proj_path)) = (usage_map.get(&package_key), pkg.project_paths.first()) {
 					if let Some(project_meta) = project_map.get(proj_path) {
-						let behavior = DeveloperBehavior {
-							npm_commands_executed: Vec::new(), // Would be populated from tracking
-							file_access_frequency: 0,
-							days_since_last_build: None,
-						};
-						predictor.should_keep(&package_key, metrics, project_meta, &behavior)
+						// Nothing recorded (or redacted away) reads as no signal
+						let behavior = self.behavior.get(proj_path).cloned().unwrap_or_default();
+						(
+							predictor.should_keep(&package_key, metrics, project_meta, &behavior),
+							1.0 - predictor.keep_probability(metrics, project_meta, &behavior),
+						)
 					} else {
-						true // Conservative: keep if no project metadata
+						(true, 0.0) // Conservative: keep if no project metadata
 					}
 				} else {
-					true
+					(true, 0.0)
 				}
 			} else {
-				true
+				(true, 0.0)
 			};
 
 			// Check LRU strategy
-			let should_keep_lru = if let Some(ref mut cache) = self.lru_cache {
+			let should_keep_lru = if let Some(policy) = self.eviction.as_ref() {
+				policy.contains(&package_key)
+			} else if let Some(ref mut cache) = self.lru_cache {
 				cache.should_keep_lru(&package_key, self.config.preserve_days)
 			} else {
 				true
@@ -199,19 +501,23 @@ impl OptimizationEngine {
 			};
 
 			// Determine if package should be removed
-			if is_orphan || (is_old && !should_keep_ml && !should_keep_lru) {
+			if is_orphan || dormant || (is_old && !should_keep_ml && !should_keep_lru) {
 				items.push(PlanItem {
 					target_path: pkg.path.clone(),
 					estimated_size_bytes: pkg.size_bytes,
 					reason: if is_orphan {
-						"orphaned".into()
+						PlanReason::Orphaned
+					} else if let (true, Some(a)) = (dormant, activity) {
+						PlanReason::DormantRepository { remote: a.remote.clone(), archived: a.archived }
 					} else if !should_keep_ml {
-						"ml_predicted_unused".into()
+						PlanReason::MlPredicted { confidence: ml_confidence }
 					} else if cache_size_limited {
-						"size_pressure".into()
+						PlanReason::SizePressure { budget: self.config.lru_max_size_bytes }
 					} else {
-						"old".into()
+						PlanReason::Old { days: (Utc::now() - pkg.mtime).num_days() }
 					},
+					blockers: Vec::new(),
+					risk: None,
 				});
 			}
 
@@ -224,49 +530,197 @@ impl OptimizationEngine {
 		}
 
 		// Process symlink candidates (in dry run, just mark them)
-		for (path, _name, _version) in symlink_candidates {
+		for (path, name, version) in symlink_candidates {
+			let canonical = seen_locations.get(&(name, version))
+				.and_then(|v| v.first())
+				.map(|p| p.to_string_lossy().to_string())
+				.unwrap_or_default();
 			items.push(PlanItem {
 				target_path: path.to_string_lossy().to_string(),
 				estimated_size_bytes: 0,
-				reason: "duplicate_symlink_candidate".into(),
+				reason: PlanReason::DuplicateSymlinkCandidate { canonical },
+				blockers: Vec::new(),
+				risk: None,
 			});
 		}
 
 		let total = items.iter().map(|i| i.estimated_size_bytes).sum();
-		Ok(DryRunReport { items, total_estimated_bytes: total })
+		let lru = self.lru_cache.as_ref().map(|c| c.stats());
+		let eviction = self.eviction.as_ref().map(|p| p.stats());
+		Ok(DryRunReport { items, total_estimated_bytes: total, lru, warnings: Vec::new(), confirm: Vec::new(), estimated_inodes: None, eviction })
 	}
 
 	/// Execute symlinking for duplicate packages
 	pub fn execute_symlinking(&self, scan: &ScanOutput) -> Result<usize> {
-		if let Some(ref dedup) = self.deduplication {
-			let mut seen: HashMap<(String, String), PathBuf> = HashMap::new();
-			let mut symlinked_count = 0;
+		Ok(self.execute_symlinking_verified(scan, None)?.symlinked_count)
+	}
 
-			for pkg in &scan.packages {
-				let key = (pkg.name.clone(), pkg.version.clone());
-				
-				// Keep first occurrence as canonical
-				let canonical = seen.entry(key.clone()).or_insert_with(|| PathBuf::from(&pkg.path));
-				
-				// Symlink duplicates
-				if canonical.to_string_lossy() != pkg.path {
-					let pkg_path = PathBuf::from(&pkg.path);
-					if let Err(e) = dedup.deduplicate_package(&pkg_path, &pkg.name, &pkg.version) {
-						eprintln!("Failed to symlink {:?}: {}", pkg_path, e);
-					} else {
-						symlinked_count += 1;
+	/// Execute symlinking and optionally smoke-check each affected project.
+	/// Projects whose verification command fails get their packages restored
+	/// from the store, undoing the deduplication for that project only.
+	/// Cancellation is honoured between packages and between projects. Patched
+	/// copies and copies whose content diverges from their lockfile integrity
+	/// hash are left alone.
+	pub fn execute_symlinking_verified(&self, scan: &ScanOutput, verify_cmd: Option<&str>) -> Result<SymlinkOutcome> {
+		let mut outcome = SymlinkOutcome::default();
+		let dedup = match self.deduplication {
+			Some(ref dedup) => dedup,
+			None => return Ok(outcome),
+		};
+
+		// project -> (package path, name, version, mode) deduplicated in this run
+		let mut by_project: HashMap<PathBuf, Vec<(PathBuf, String, String, DedupMode)>> = HashMap::new();
+		let mut modes: HashMap<PathBuf, DedupMode> = HashMap::new();
+		let index = match StoreIndex::open_default() {
+			Ok(index) => Some(index),
+			Err(e) => {
+				eprintln!("Warning: store reference index unavailable: {}", e);
+				None
+			}
+		};
+
+		let mut integrity = IntegrityChecker::default();
+		let mut patches = PatchIndex::default();
+		let groups = duplicate_groups(scan, self.config.canonical_strategy);
+		let total_pkgs: u64 = groups.iter().map(|(pkgs, _)| pkgs.len() as u64).sum();
+		let mut remaining_bytes: u64 = groups.iter()
+			.flat_map(|(pkgs, _)| pkgs.iter().skip(1))
+			.map(|p| p.size_bytes)
+			.sum();
+		let mut meter = ThroughputMeter::start();
+		let mut done: u64 = 0;
+		for (mut pkgs, mut choice) in groups {
+			let excluded = split_excluded(&mut pkgs, self.config.protect_patched, &mut patches, &mut integrity);
+			if excluded.contains_key(&pkgs[0].path) {
+				eprintln!("Skipping {}: every copy is patched or diverges from its lockfile integrity", choice.package);
+				done += pkgs.len() as u64;
+				remaining_bytes = remaining_bytes.saturating_sub(pkgs.iter().skip(1).map(|p| p.size_bytes).sum());
+				continue;
+			}
+			if pkgs[0].path != choice.canonical {
+				choice.canonical = pkgs[0].path.clone();
+				choice.detail = Some("preferred copy is patched or diverges from its lockfile integrity".into());
+			}
+			// Keep the chosen copy in place and let it seed the store; on failure
+			// the first duplicate seeds it through deduplicate_package
+			let canonical = pkgs[0];
+			if let Err(e) = dedup.seed_store(Path::new(&canonical.path), &canonical.name, &canonical.version) {
+				eprintln!("Failed to seed store from {:?}: {}", canonical.path, e);
+			}
+			outcome.canonical_choices.push(choice);
+			done += 1;
+
+			for pkg in pkgs.iter().skip(1) {
+				self.ctx.check()?;
+				done += 1;
+				let rate = meter.rate(total_pkgs - done + 1, Some(remaining_bytes));
+				self.ctx.report_rate(Phase::Symlink, done, Some(total_pkgs), Some(Path::new(&pkg.path)), rate);
+				remaining_bytes = remaining_bytes.saturating_sub(pkg.size_bytes);
+				match excluded.get(&pkg.path) {
+					Some(DedupBlocker::Patched) => {
+						eprintln!("Not deduplicating {}: package is patched locally", pkg.path);
+						continue;
 					}
+					Some(_) => {
+						eprintln!("Not deduplicating {}: content diverges from its lockfile integrity", pkg.path);
+						continue;
+					}
+					None => {}
+				}
+
+				let pkg_path = PathBuf::from(&pkg.path);
+				if crate::wsl::crosses_boundary(&pkg_path) {
+					eprintln!("Not deduplicating {}: links do not work across the WSL/Windows boundary", pkg.path);
+					continue;
+				}
+				let mode = dedup_mode_for(&pkg_path, self.config.dedup_mode, &mut modes);
+				let files = count_files(&pkg_path);
+				let result = match mode {
+					DedupMode::Symlink => dedup.deduplicate_package(&pkg_path, &pkg.name, &pkg.version),
+					DedupMode::Hardlink => dedup.hardlink_package(&pkg_path, &pkg.name, &pkg.version).map(|_| ()),
+				};
+				if let Err(e) = result {
+					eprintln!("Failed to deduplicate {:?}: {}", pkg_path, e);
+					continue;
+				}
+				meter.record(pkg.size_bytes, files);
+				match mode {
+					DedupMode::Symlink => {
+						outcome.symlinked_count += 1;
+						if let (Some(index), Ok(entry)) = (&index, dedup.entry_path(&pkg.name, &pkg.version)) {
+							if let Err(e) = index.record_link(&entry, &pkg_path) {
+								eprintln!("Warning: failed to index {:?}: {}", pkg_path, e);
+							}
+						}
+					}
+					DedupMode::Hardlink => outcome.hardlinked_count += 1,
+				}
+				if let Some(project) = owning_project(&pkg_path) {
+					by_project.entry(project).or_default().push((pkg_path, pkg.name.clone(), pkg.version.clone(), mode));
 				}
 			}
+		}
 
-			Ok(symlinked_count)
-		} else {
-			Ok(0)
+		outcome.throughput = meter.summary();
+
+		let Some(cmd) = verify_cmd else {
+			return Ok(outcome);
+		};
+
+		let total_projects = by_project.len() as u64;
+		for (i, (project, packages)) in by_project.into_iter().enumerate() {
+			self.ctx.check()?;
+			self.ctx.report(Phase::Verify, i as u64 + 1, Some(total_projects), Some(&project));
+			let names: Vec<String> = packages.iter().map(|(_, n, _, _)| n.clone()).collect();
+			let result = verify_project(&project, cmd, &names)?;
+			if !result.passed {
+				for (path, name, version, mode) in &packages {
+					let restored = match mode {
+						DedupMode::Symlink => dedup.restore_package(path, name, version),
+						DedupMode::Hardlink => dedup.unlink_package(path),
+					};
+					match restored {
+						Ok(()) => {
+							match mode {
+								DedupMode::Symlink => {
+									outcome.symlinked_count = outcome.symlinked_count.saturating_sub(1);
+									if let Some(index) = &index {
+										index.remove_link(path).ok();
+									}
+								}
+								DedupMode::Hardlink => {
+									outcome.hardlinked_count = outcome.hardlinked_count.saturating_sub(1);
+								}
+							}
+							outcome.restored_count += 1;
+						}
+						Err(e) => eprintln!("Failed to restore {:?}: {}", path, e),
+					}
+				}
+			}
+			outcome.verifications.push(result);
 		}
+
+		Ok(outcome)
 	}
 }
 
-fn detect_project_type(project_path: &str) -> String {
+/// Result of a (possibly verified) symlinking run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SymlinkOutcome {
+	pub symlinked_count: usize,
+	/// Packages kept as real directories with files hard-linked to the store
+	pub hardlinked_count: usize,
+	/// Packages restored because their project failed verification
+	pub restored_count: usize,
+	pub verifications: Vec<VerifyOutcome>,
+	/// Copy kept for each duplicated package
+	pub canonical_choices: Vec<CanonicalChoice>,
+	/// Bytes and files deduplicated, and how fast (verification excluded)
+	pub throughput: ThroughputSummary,
+}
+
+pub(crate) fn detect_project_type(project_path: &str) -> String {
 	use std::fs;
 	use std::path::Path;
 	
@@ -321,3 +775,81 @@ fn detect_project_type(project_path: &str) -> String {
 	
 	"node".into()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ml::FEATURE_VERSION;
+	use crate::types::ProjectRecord;
+	use tempfile::tempdir;
+
+	/// Months-old, never used features the predictor evicts
+	const STALE_FEATURES: [f64; 10] = [400.0, 400.0, 400.0, 0.0, 0.0, 400.0, 0.0, 0.0, 400.0, 0.0];
+
+	fn engine(store: FeatureStore) -> OptimizationEngine {
+		let config = RulesConfig {
+			preserve_days: 90,
+			enable_symlinking: false,
+			enable_ml_prediction: true,
+			lru_max_packages: 1,
+			lru_max_size_bytes: u64::MAX,
+			canonical_strategy: CanonicalStrategy::First,
+			dedup_mode: DedupMode::Symlink,
+			protect_patched: false,
+		};
+		OptimizationEngine::new(config).unwrap()
+			.with_feature_store(store)
+			.with_eviction_policy(PolicyKind::Lru)
+	}
+
+	/// `lodash` used by a project but old, and a newer `react` the one-slot
+	/// policy keeps instead; neither has project metadata to extract features from
+	fn scan(root: &Path) -> ScanOutput {
+		let old = Utc::now() - Duration::days(200);
+		let lodash = PackageRecord { atime: old, mtime: old, ..PackageRecord::for_test(root.join("node_modules/lodash"), "4.17.21", 100) };
+		let react = PackageRecord { mtime: old, ..PackageRecord::for_test(root.join("node_modules/react"), "18.2.0", 100) };
+		ScanOutput {
+			packages: vec![lodash, react],
+			projects: vec![ProjectRecord {
+				path: root.to_string_lossy().to_string(),
+				manager: None,
+				dependencies: vec![("lodash".into(), "4.17.21".into()), ("react".into(), "18.2.0".into())],
+				mtime: old,
+			}],
+			edges: Vec::new(),
+			stats: Default::default(),
+			deferred_sizes: Vec::new(),
+			marked_dirs: Vec::new(),
+		}
+	}
+
+	fn ml_predicted(report: &DryRunReport) -> Vec<&str> {
+		report.items.iter()
+			.filter(|i| matches!(i.reason, PlanReason::MlPredicted { .. }))
+			.map(|i| i.target_path.rsplit('/').next().unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn test_stored_features_drive_prediction() {
+		let dir = tempdir().unwrap();
+		let store = FeatureStore::open(&dir.path().join("features.db")).unwrap();
+		store.store_features("lodash@4.17.21", &STALE_FEATURES).unwrap();
+
+		let report = engine(store).plan_optimized_cleanup(&scan(dir.path())).unwrap();
+		assert_eq!(ml_predicted(&report), vec!["lodash"]);
+		let PlanReason::MlPredicted { confidence } = report.items[0].reason else { unreachable!() };
+		assert!(confidence > 0.5);
+	}
+
+	#[test]
+	fn test_stale_stored_features_are_ignored() {
+		let dir = tempdir().unwrap();
+		let store = FeatureStore::open(&dir.path().join("features.db")).unwrap();
+		store.store_features_as("lodash@4.17.21", FEATURE_VERSION + 1, &STALE_FEATURES);
+
+		// Falls back to extraction, which keeps packages without project metadata
+		let report = engine(store).plan_optimized_cleanup(&scan(dir.path())).unwrap();
+		assert!(ml_predicted(&report).is_empty());
+	}
+}
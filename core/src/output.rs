@@ -0,0 +1,265 @@
+//! Output Formats
+//!
+//! `scan`, `dry-run` and `optimize` print in one of four formats chosen with
+//! `--format`:
+//!
+//! - `json`: the whole result as one pretty-printed document (the default
+//!   when stdout is not a terminal, so scripts and the CLI wrapper see what
+//!   they always have)
+//! - `ndjson`: one record per line. Scans write the lines of `scan --stream`
+//!   (projects, packages, edges, marked directories, then a `summary`);
+//!   plans write one `item` line per plan item and end with a `summary`
+//!   holding the rest of the report
+//! - `csv`: packages or plan items as rows under a header line
+//! - `table`: aligned columns with sizes and dates as `display` configures
+//!   them (the default on a terminal)
+//!
+//! Hooks and saved plans always see JSON, whatever is printed.
+
+use chrono::Utc;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::display::DisplayConfig;
+use crate::types::{DryRunReport, ScanItem, ScanOutput, ScanSummary};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Ndjson,
+    Csv,
+    Table,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            "table" => Ok(Self::Table),
+            other => Err(format!("unknown output format `{}` (expected json, ndjson, csv or table)", other)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+            Self::Table => "table",
+        })
+    }
+}
+
+impl OutputFormat {
+    /// `chosen`, else a table on a terminal and JSON otherwise
+    pub fn resolve(chosen: Option<Self>) -> Self {
+        use std::io::IsTerminal;
+        chosen.unwrap_or(if io::stdout().is_terminal() { Self::Table } else { Self::Json })
+    }
+}
+
+fn json_line(out: &mut dyn Write, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    out.write_all(b"\n")
+}
+
+/// `field` quoted for CSV when it needs to be
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.starts_with(' ') || field.ends_with(' ') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_row(out: &mut dyn Write, fields: &[String]) -> io::Result<()> {
+    let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    writeln!(out, "{}", row.join(","))
+}
+
+/// Snake-case name a unit enum variant serializes to
+fn variant_name(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        Ok(other) => other.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+pub fn write_scan(out: &mut dyn Write, scan: &ScanOutput, format: OutputFormat, display: &DisplayConfig) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, scan)?;
+            out.write_all(b"\n")
+        }
+        OutputFormat::Ndjson => {
+            for project in &scan.projects {
+                json_line(out, &ScanItem::Project(project.clone()))?;
+            }
+            for package in &scan.packages {
+                json_line(out, &ScanItem::Package(package.clone()))?;
+            }
+            for (from, to) in &scan.edges {
+                json_line(out, &ScanItem::Edge { from: from.clone(), to: to.clone() })?;
+            }
+            for marked in &scan.marked_dirs {
+                json_line(out, &ScanItem::Marked(marked.clone()))?;
+            }
+            let summary = ScanSummary {
+                packages: scan.packages.len(),
+                package_bytes: scan.packages.iter().map(|p| p.size_bytes).sum(),
+                projects: scan.projects.len(),
+                edges: scan.edges.len(),
+                marked_dirs: scan.marked_dirs.len(),
+                stats: scan.stats.clone(),
+                deferred_sizes: scan.deferred_sizes.clone(),
+            };
+            let mut line = serde_json::to_value(&summary)?;
+            line["type"] = "summary".into();
+            json_line(out, &line)
+        }
+        OutputFormat::Csv => {
+            csv_row(out, &["name", "version", "path", "size_bytes", "file_count", "atime", "mtime", "manager", "projects"].map(String::from))?;
+            for p in &scan.packages {
+                csv_row(out, &[
+                    p.name.clone(),
+                    p.version.clone(),
+                    p.path.clone(),
+                    p.size_bytes.to_string(),
+                    p.file_count.to_string(),
+                    p.atime.to_rfc3339(),
+                    p.mtime.to_rfc3339(),
+                    p.manager.as_ref().map(variant_name).unwrap_or_default(),
+                    p.project_paths.join(";"),
+                ])?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let now = Utc::now();
+            let mut packages: Vec<_> = scan.packages.iter().collect();
+            packages.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.path.cmp(&b.path)));
+            let name_width = packages.iter().map(|p| p.name.chars().count()).max().unwrap_or(0).clamp(7, 40);
+            writeln!(out, "{:<name_width$}  {:<12}  {:>10}  {:<17}  PATH", "PACKAGE", "VERSION", "SIZE", "LAST USED")?;
+            for p in &packages {
+                writeln!(out, "{:<name_width$}  {:<12}  {:>10}  {:<17}  {}", p.name, p.version, display.size(p.size_bytes), display.date(p.atime, now), p.path)?;
+            }
+            let total: u64 = scan.packages.iter().map(|p| p.size_bytes).sum();
+            writeln!(out, "\n{} packages, {} in {} projects", scan.packages.len(), display.size(total), scan.projects.len())?;
+            if !scan.marked_dirs.is_empty() {
+                writeln!(out, "{} marked directories kept out of cleanup", scan.marked_dirs.len())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn write_plan(out: &mut dyn Write, report: &DryRunReport, format: OutputFormat, display: &DisplayConfig) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, report)?;
+            out.write_all(b"\n")
+        }
+        OutputFormat::Ndjson => {
+            let mut value = serde_json::to_value(report)?;
+            let items = value.as_object_mut().and_then(|o| o.remove("items"));
+            for mut item in items.and_then(|i| match i { serde_json::Value::Array(a) => Some(a), _ => None }).unwrap_or_default() {
+                item["type"] = "item".into();
+                json_line(out, &item)?;
+            }
+            value["type"] = "summary".into();
+            value["items"] = report.items.len().into();
+            json_line(out, &value)
+        }
+        OutputFormat::Csv => {
+            csv_row(out, &["target_path", "reason", "estimated_size_bytes", "risk", "blockers"].map(String::from))?;
+            for item in &report.items {
+                csv_row(out, &[
+                    item.target_path.clone(),
+                    item.reason.label().to_string(),
+                    item.estimated_size_bytes.to_string(),
+                    item.risk.as_ref().map(|r| r.score.to_string()).unwrap_or_default(),
+                    item.blockers.iter().map(variant_name).collect::<Vec<_>>().join(";"),
+                ])?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let reason_width = report.items.iter().map(|i| i.reason.label().len()).max().unwrap_or(0).max(6);
+            writeln!(out, "{:>10}  {:<reason_width$}  {:>4}  PATH", "SIZE", "REASON", "RISK")?;
+            for item in &report.items {
+                let risk = item.risk.as_ref().map(|r| r.score.to_string()).unwrap_or_else(|| "-".into());
+                writeln!(out, "{:>10}  {:<reason_width$}  {:>4}  {}", display.size(item.estimated_size_bytes), item.reason.label(), risk, item.target_path)?;
+            }
+            writeln!(out, "\n{} items, {} reclaimable", report.items.len(), display.size(report.total_estimated_bytes))?;
+            if !report.warnings.is_empty() {
+                writeln!(out, "\nWarnings:")?;
+                for w in &report.warnings {
+                    writeln!(out, "  {}: {}", w.target_path, w.message)?;
+                }
+            }
+            if !report.confirm.is_empty() {
+                writeln!(out, "\nApply asks before quarantining:")?;
+                for path in &report.confirm {
+                    writeln!(out, "  {}", path)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PlanItem, PlanReason, PlanWarning};
+
+    #[test]
+    fn test_plan_formats() {
+        let report = DryRunReport {
+            items: vec![PlanItem {
+                target_path: "/p/node_modules/a,b".into(),
+                estimated_size_bytes: 2048,
+                reason: PlanReason::Orphaned,
+                blockers: Vec::new(),
+                risk: None,
+            }],
+            total_estimated_bytes: 2048,
+            lru: None,
+            warnings: vec![PlanWarning { target_path: "/p/x".into(), message: "held back".into() }],
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+        let render = |format| {
+            let mut out = Vec::new();
+            write_plan(&mut out, &report, format, &DisplayConfig::default()).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let csv = render(OutputFormat::Csv);
+        assert_eq!(csv.lines().nth(1), Some("\"/p/node_modules/a,b\",orphaned,2048,,"));
+
+        let ndjson = render(OutputFormat::Ndjson);
+        let lines: Vec<serde_json::Value> = ndjson.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "item");
+        assert_eq!(lines[0]["reason"], "orphaned");
+        assert_eq!(lines[1]["type"], "summary");
+        assert_eq!(lines[1]["items"], 1);
+        assert_eq!(lines[1]["warnings"][0]["message"], "held back");
+
+        let table = render(OutputFormat::Table);
+        assert!(table.contains("2.0 KiB  orphaned"));
+        assert!(table.contains("1 items, 2.0 KiB reclaimable"));
+        assert_eq!("csv".parse::<OutputFormat>(), Ok(OutputFormat::Csv));
+    }
+}
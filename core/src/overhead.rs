@@ -0,0 +1,234 @@
+//! Tool Overhead
+//!
+//! Disk space held by PackagePurge itself: the quarantine, the global store,
+//! its databases and local archives. It is weighed against what the tool
+//! saved, meaning duplicate copies replaced by store links plus archived
+//! directories. An optional cap, set in `overhead.json` in the config
+//! directory, bounds the total. Quarantining past the cap first evicts the
+//! oldest quarantine entries and refuses if that is not enough.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::error::Error;
+use crate::feature_store::FeatureStore;
+use crate::scan_cache::ScanCache;
+use crate::store_index::StoreIndex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverheadConfig {
+    /// Upper bound on the tool's own footprint in bytes (0 = unlimited)
+    #[serde(default)]
+    pub max_bytes: u64,
+    /// Warn when the footprint exceeds this percentage of the space saved
+    #[serde(default = "default_warn_percent")]
+    pub warn_percent: f64,
+}
+
+fn default_warn_percent() -> f64 {
+    50.0
+}
+
+impl Default for OverheadConfig {
+    fn default() -> Self {
+        Self { max_bytes: 0, warn_percent: default_warn_percent() }
+    }
+}
+
+/// Footprint of the tool's state against the space it saved
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Overhead {
+    pub quarantine_bytes: u64,
+    pub store_bytes: u64,
+    /// Feature store, store index and scan cache
+    pub database_bytes: u64,
+    /// Archives kept in a local directory of the state dir
+    pub archive_bytes: u64,
+    pub total_bytes: u64,
+    /// Bytes of duplicate copies replaced by links into the store
+    pub dedup_saved_bytes: u64,
+    /// Bytes of project directories moved to archives
+    pub archived_saved_bytes: u64,
+    pub saved_bytes: u64,
+    /// `total_bytes` as a percentage of `saved_bytes` (None until something was saved)
+    pub percent_of_saved: Option<f64>,
+    pub max_bytes: u64,
+    pub warning: Option<String>,
+}
+
+fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("overhead.json")
+}
+
+pub fn load_config() -> OverheadConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &OverheadConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &OverheadConfig) -> Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save overhead config to {:?}", path))
+}
+
+/// Measure the current footprint and savings
+pub fn measure(config: &OverheadConfig) -> Overhead {
+    let mut seen = HashSet::new();
+    let quarantine_bytes = disk_usage(&crate::paths::quarantine_dir(), &mut seen);
+    let store = crate::symlink::get_global_store_path().ok();
+    let store_bytes = store.as_deref().map_or(0, |s| disk_usage(s, &mut seen));
+    let database_bytes = [FeatureStore::default_db_path(), StoreIndex::default_db_path(), ScanCache::default_cache_path()]
+        .iter()
+        .map(|p| disk_usage(p, &mut seen))
+        .sum();
+    let archive_bytes = disk_usage(&crate::archive::default_destination(), &mut seen);
+
+    let dedup_saved_bytes = StoreIndex::open_default().ok()
+        .and_then(|index| index.all().ok())
+        .map_or(0, |refs| dedup_savings(refs.iter().map(|r| r.entry.as_str())));
+    let archived_saved_bytes = FeatureStore::open_default().ok()
+        .and_then(|db| db.list_archives(false).ok())
+        .map_or(0, |archives| archives.iter().map(|a| a.size_bytes).sum());
+
+    summarize(
+        Overhead {
+            quarantine_bytes,
+            store_bytes,
+            database_bytes,
+            archive_bytes,
+            dedup_saved_bytes,
+            archived_saved_bytes,
+            ..Default::default()
+        },
+        config,
+    )
+}
+
+/// Fill in totals, the ratio and the warning
+fn summarize(mut o: Overhead, config: &OverheadConfig) -> Overhead {
+    o.total_bytes = o.quarantine_bytes + o.store_bytes + o.database_bytes + o.archive_bytes;
+    o.saved_bytes = o.dedup_saved_bytes + o.archived_saved_bytes;
+    o.percent_of_saved = (o.saved_bytes > 0).then(|| o.total_bytes as f64 * 100.0 / o.saved_bytes as f64);
+    o.max_bytes = config.max_bytes;
+    o.warning = if config.max_bytes > 0 && o.total_bytes > config.max_bytes {
+        Some(format!("tool overhead of {} bytes exceeds the cap of {} bytes", o.total_bytes, config.max_bytes))
+    } else {
+        match o.percent_of_saved {
+            Some(p) if p > config.warn_percent => Some(format!(
+                "tool overhead is {:.0}% of the space saved (warning above {:.0}%)", p, config.warn_percent
+            )),
+            _ => None,
+        }
+    };
+    o
+}
+
+/// Each store entry linked from n projects replaced n - 1 copies
+fn dedup_savings<'a>(entries: impl Iterator<Item = &'a str>) -> u64 {
+    let mut links: HashMap<&str, u64> = HashMap::new();
+    for entry in entries {
+        *links.entry(entry).or_default() += 1;
+    }
+    links.iter()
+        .filter(|(_, n)| **n > 1)
+        .map(|(entry, n)| (n - 1) * disk_usage(Path::new(entry), &mut HashSet::new()))
+        .sum()
+}
+
+/// Bytes under `path`, counting hardlinked files once across calls sharing `seen`
+fn disk_usage(path: &Path, seen: &mut HashSet<(u64, u64)>) -> u64 {
+    let mut total = 0;
+    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if meta.nlink() > 1 && !seen.insert((meta.dev(), meta.ino())) {
+                continue;
+            }
+        }
+        total += meta.len();
+    }
+    total
+}
+
+/// Make room for `incoming` more bytes under the configured cap, evicting the
+/// oldest quarantine entries if needed. Fails when the cap cannot be met.
+pub fn enforce_cap(incoming: u64) -> crate::Result<Option<(usize, u64)>> {
+    let config = load_config();
+    if config.max_bytes == 0 {
+        return Ok(None);
+    }
+    let current = measure(&config);
+    let needed = (current.total_bytes + incoming).saturating_sub(config.max_bytes);
+    if needed == 0 {
+        return Ok(None);
+    }
+    // Refuse up front rather than empty the quarantine for nothing
+    if needed > current.quarantine_bytes {
+        return Err(Error::Quarantine(anyhow::anyhow!(
+            "tool overhead cap of {} bytes would be exceeded by {} bytes even after emptying the quarantine",
+            config.max_bytes, needed - current.quarantine_bytes
+        )));
+    }
+    Ok(Some(crate::safety::evict_quarantine(needed)?))
+}
+
+/// Bytes `targets` would add to the quarantine
+pub fn incoming_bytes(targets: &[PathBuf]) -> u64 {
+    targets.iter().map(|t| disk_usage(t, &mut HashSet::new())).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_savings_and_warning() {
+        let temp = tempdir().unwrap();
+        let entry = temp.path().join("lodash@4.17.21");
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(entry.join("index.js"), vec![0u8; 100]).unwrap();
+        let entry = entry.to_string_lossy().to_string();
+
+        // Three links to one entry saved two copies; a single link saved nothing
+        let single = temp.path().join("single").to_string_lossy().to_string();
+        assert_eq!(dedup_savings([entry.as_str(), entry.as_str(), entry.as_str(), single.as_str()].into_iter()), 200);
+
+        let config = OverheadConfig { max_bytes: 0, warn_percent: 50.0 };
+        let o = summarize(Overhead { store_bytes: 150, dedup_saved_bytes: 200, ..Default::default() }, &config);
+        assert_eq!(o.percent_of_saved, Some(75.0));
+        assert!(o.warning.unwrap().contains("75%"));
+
+        let o = summarize(Overhead { store_bytes: 50, dedup_saved_bytes: 200, ..Default::default() }, &config);
+        assert!(o.warning.is_none());
+
+        let capped = OverheadConfig { max_bytes: 40, ..config };
+        assert!(summarize(Overhead { store_bytes: 50, ..Default::default() }, &capped).warning.unwrap().contains("cap"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlinks_counted_once() {
+        let temp = tempdir().unwrap();
+        fs::write(temp.path().join("a"), vec![0u8; 64]).unwrap();
+        fs::hard_link(temp.path().join("a"), temp.path().join("b")).unwrap();
+        assert_eq!(disk_usage(temp.path(), &mut HashSet::new()), 64);
+    }
+}
@@ -0,0 +1,121 @@
+//! Locally Patched Packages
+//!
+//! Finds packages whose installed content intentionally differs from the
+//! registry release: patch-package patches (`patches/<name>+<version>.patch`)
+//! and pnpm `patchedDependencies` (from package.json or pnpm-lock.yaml).
+//! Deduplicating such a package by name@version would replace the patched
+//! copy with, or spread it to, unpatched ones, and evicting it loses the patch
+//! until the next install.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::graph::parse_package_spec;
+use crate::types::PackageRecord;
+use crate::verify::owning_project;
+
+/// (name, version) a patch applies to; no version means every version
+pub type PatchTarget = (String, Option<String>);
+
+/// Packages the project at `project_dir` patches
+pub fn patched_dependencies(project_dir: &Path) -> HashSet<PatchTarget> {
+    let mut targets = HashSet::new();
+
+    if let Ok(entries) = fs::read_dir(project_dir.join("patches")) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let file = entry.file_name().to_string_lossy().to_string();
+            if let Some(target) = parse_patch_file_name(&file) {
+                targets.insert(target);
+            }
+        }
+    }
+
+    let manifest = fs::read_to_string(project_dir.join("package.json")).ok()
+        .and_then(|t| serde_json::from_str::<serde_json::Value>(&t).ok());
+    if let Some(patched) = manifest.as_ref()
+        .and_then(|m| m.get("pnpm"))
+        .and_then(|p| p.get("patchedDependencies"))
+        .and_then(|p| p.as_object())
+    {
+        targets.extend(patched.keys().map(|k| spec_target(k)));
+    }
+
+    let lock = fs::read_to_string(project_dir.join("pnpm-lock.yaml")).ok()
+        .and_then(|t| serde_yaml::from_str::<serde_yaml::Value>(&t).ok());
+    if let Some(patched) = lock.as_ref().and_then(|l| l.get("patchedDependencies")).and_then(|p| p.as_mapping()) {
+        targets.extend(patched.keys().filter_map(|k| k.as_str()).map(spec_target));
+    }
+    targets
+}
+
+/// `name@version` keys; ranges and bare names apply to every version
+fn spec_target(spec: &str) -> PatchTarget {
+    let (name, version) = parse_package_spec(spec);
+    let exact = version.filter(|v| v.starts_with(|c: char| c.is_ascii_digit()) && !v.contains([' ', '|', 'x', '*']));
+    (name.to_string(), exact.map(str::to_string))
+}
+
+/// patch-package file names: `name+1.0.0.patch`, `@scope+name+1.0.0.patch`,
+/// nested `parent++name+1.0.0.patch`, optionally with `.dev` or a
+/// `+001+description` sequence suffix
+fn parse_patch_file_name(file: &str) -> Option<PatchTarget> {
+    let stem = file.strip_suffix(".patch")?;
+    let stem = stem.strip_suffix(".dev").unwrap_or(stem);
+    let last = stem.rsplit("++").next()?;
+    let parts: Vec<&str> = last.split('+').collect();
+    let (name, version) = if last.starts_with('@') {
+        (format!("{}/{}", parts.first()?, parts.get(1)?), parts.get(2)?)
+    } else {
+        (parts.first()?.to_string(), parts.get(1)?)
+    };
+    Some((name, Some(version.to_string())))
+}
+
+/// Answers whether a scanned package is patched by its owning project,
+/// reading each project's patch configuration once
+#[derive(Default)]
+pub struct PatchIndex {
+    projects: HashMap<PathBuf, HashSet<PatchTarget>>,
+}
+
+impl PatchIndex {
+    pub fn is_patched(&mut self, pkg: &PackageRecord) -> bool {
+        let Some(project) = owning_project(Path::new(&pkg.path)) else {
+            return false;
+        };
+        let targets = self.projects.entry(project.clone())
+            .or_insert_with(|| patched_dependencies(&project));
+        targets.contains(&(pkg.name.clone(), Some(pkg.version.clone())))
+            || targets.contains(&(pkg.name.clone(), None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_patch_file_name() {
+        assert_eq!(parse_patch_file_name("lodash+4.17.21.patch"), Some(("lodash".into(), Some("4.17.21".into()))));
+        assert_eq!(parse_patch_file_name("@babel+core+7.2.0.dev.patch"), Some(("@babel/core".into(), Some("7.2.0".into()))));
+        assert_eq!(parse_patch_file_name("a++b+1.0.0+001+fix.patch"), Some(("b".into(), Some("1.0.0".into()))));
+        assert_eq!(parse_patch_file_name("README.md"), None);
+    }
+
+    #[test]
+    fn test_patched_dependencies_sources() {
+        let temp = tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("patches")).unwrap();
+        fs::write(temp.path().join("patches/left-pad+1.3.0.patch"), "").unwrap();
+        fs::write(temp.path().join("package.json"), r#"{"pnpm":{"patchedDependencies":{"react@18.2.0":"patches/react.patch","express":"patches/express.patch"}}}"#).unwrap();
+        fs::write(temp.path().join("pnpm-lock.yaml"), "patchedDependencies:\n  vue@^3.0.0:\n    hash: abc\n    path: patches/vue.patch\n").unwrap();
+
+        let targets = patched_dependencies(temp.path());
+        assert!(targets.contains(&("left-pad".into(), Some("1.3.0".into()))));
+        assert!(targets.contains(&("react".into(), Some("18.2.0".into()))));
+        assert!(targets.contains(&("express".into(), None)));
+        assert!(targets.contains(&("vue".into(), None)));
+    }
+}
@@ -0,0 +1,287 @@
+//! State Locations
+//!
+//! PackagePurge follows the platform's directory conventions: settings in the
+//! config directory, databases, quarantine and store in the state directory,
+//! and the scan cache in the cache directory.
+//!
+//! | platform | config                         | state                            | cache                        |
+//! |----------|--------------------------------|----------------------------------|------------------------------|
+//! | Linux    | `$XDG_CONFIG_HOME/packagepurge` | `$XDG_STATE_HOME/packagepurge`   | `$XDG_CACHE_HOME/packagepurge` |
+//! | macOS    | `~/Library/Application Support/packagepurge` | same as config      | `~/Library/Caches/packagepurge` |
+//! | Windows  | `%APPDATA%\packagepurge`       | `%LOCALAPPDATA%\packagepurge`    | `%LOCALAPPDATA%\packagepurge` |
+//!
+//! A legacy `~/.packagepurge` is migrated on first use (`--read-only` runs
+//! use it in place). The global store stays where it is (project symlinks
+//! point into it) and is recorded as relocated; `store move` can move it later.
+//!
+//! Each location can also be moved on its own, e.g. the scan cache onto a
+//! scratch disk or everything into a per-job temp directory on CI:
+//!
+//! | location      | flag               | environment                    | default                  |
+//! |---------------|--------------------|--------------------------------|--------------------------|
+//! | state         | `--state-dir`      | `PACKAGEPURGE_STATE_DIR`       | platform state dir       |
+//! | scan cache    | `--cache-dir`      | `PACKAGEPURGE_CACHE_DIR`       | platform cache dir       |
+//! | feature store | `--db`             | `PACKAGEPURGE_DB`              | `<state>/features.db`    |
+//! | quarantine    | `--quarantine-dir` | `PACKAGEPURGE_QUARANTINE_DIR`  | `<state>/quarantine`     |
+//! | global store  | `--store-dir`      | `PACKAGEPURGE_STORE_DIR`       | `<state>/global_store`   |
+//!
+//! Flags win over the environment. A state directory given either way holds
+//! config and cache too, as `~/.packagepurge` used to. A store moved with
+//! `store move` is recorded in the state directory and used when neither is given.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Locations given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct StateOverrides {
+    pub state_dir: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub db: Option<PathBuf>,
+    pub quarantine_dir: Option<PathBuf>,
+    pub store_dir: Option<PathBuf>,
+}
+
+static OVERRIDES: OnceLock<StateOverrides> = OnceLock::new();
+
+/// Use `overrides` for the rest of the process; only the first call takes effect
+pub fn set_overrides(overrides: StateOverrides) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+fn resolve(flag: impl Fn(&StateOverrides) -> Option<PathBuf>, env: &str) -> Option<PathBuf> {
+    OVERRIDES.get().and_then(flag)
+        .or_else(|| std::env::var_os(env).filter(|v| !v.is_empty()).map(PathBuf::from))
+}
+
+/// Config, state and cache base directories
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub config: PathBuf,
+    pub state: PathBuf,
+    pub cache: PathBuf,
+}
+
+impl Layout {
+    /// The platform's directories (XDG on Linux)
+    pub fn platform() -> Self {
+        let home = home();
+        let config = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+        let state = dirs::state_dir().or_else(dirs::data_local_dir)
+            .unwrap_or_else(|| home.join(".local").join("state"));
+        let cache = dirs::cache_dir().unwrap_or_else(|| home.join(".cache"));
+        Self {
+            config: config.join("packagepurge"),
+            state: state.join("packagepurge"),
+            cache: cache.join("packagepurge"),
+        }
+    }
+
+    /// Everything in one directory, like the legacy `~/.packagepurge`
+    pub fn single(dir: PathBuf) -> Self {
+        Self { config: dir.clone(), state: dir.clone(), cache: dir }
+    }
+}
+
+fn home() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The pre-XDG location of all state
+pub fn legacy_dir() -> PathBuf {
+    home().join(".packagepurge")
+}
+
+static PLATFORM: OnceLock<Layout> = OnceLock::new();
+
+fn base() -> Layout {
+    if let Some(dir) = resolve(|o| o.state_dir.clone(), "PACKAGEPURGE_STATE_DIR") {
+        return Layout::single(dir);
+    }
+    PLATFORM.get_or_init(|| {
+        let layout = Layout::platform();
+        match migrate_legacy(&legacy_dir(), &layout) {
+            Ok(_) => layout,
+            // Left for a run that may write
+            Err(e) if matches!(e.downcast_ref(), Some(crate::error::Error::ReadOnly(_))) => Layout::single(legacy_dir()),
+            Err(e) => {
+                eprintln!("Warning: Failed to migrate {:?}, still using it: {:#}", legacy_dir(), e);
+                Layout::single(legacy_dir())
+            }
+        }
+    }).clone()
+}
+
+/// Move a legacy single-directory layout into `layout`; returns whether
+/// anything was migrated. The quarantine moves first and is the only step that
+/// can fail without falling back to copying, so a failed migration leaves
+/// the legacy directory intact.
+pub fn migrate_legacy(legacy: &Path, layout: &Layout) -> Result<bool> {
+    if !legacy.is_dir() || *layout == Layout::single(legacy.to_path_buf()) {
+        return Ok(false);
+    }
+    if layout.state.exists() {
+        eprintln!("Warning: Ignoring legacy {:?}, {:?} is in use", legacy, layout.state);
+        return Ok(false);
+    }
+    crate::safety::ensure_writable("migrate the legacy state directory")?;
+    for dir in [&layout.config, &layout.state, &layout.cache] {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+
+    // Keep the store in place, recorded as relocated
+    let store = legacy.join("global_store");
+    let store_record = legacy.join("store.json");
+    if store.is_dir() && !store_record.exists() {
+        let json = serde_json::json!({ "path": store.to_string_lossy() });
+        fs::write(&store_record, serde_json::to_vec_pretty(&json)?)?;
+    }
+
+    let quarantine = legacy.join("quarantine");
+    if quarantine.is_dir() {
+        let moved = layout.state.join("quarantine");
+        if let Err(e) = fs::rename(&quarantine, &moved) {
+            let _ = fs::remove_dir(&layout.state);
+            return Err(e).with_context(|| format!("Failed to move {:?}", quarantine));
+        }
+        rewrite_quarantine_index(&moved.join("index.json"), &quarantine, &moved)?;
+        move_file(&moved.join("config.json"), &layout.config.join("quarantine.json"))?;
+    }
+    move_file(&legacy.join("role.json"), &layout.config.join("role.json"))?;
+    move_file(&legacy.join("config.toml"), &layout.config.join("config.toml"))?;
+    for name in ["scan_cache.db", "scan_cache.json"] {
+        move_file(&legacy.join(name), &layout.cache.join(name))?;
+    }
+    for entry in fs::read_dir(legacy)?.filter_map(|e| e.ok()) {
+        if entry.file_name() != "global_store" && entry.file_type().is_ok_and(|t| t.is_file()) {
+            move_file(&entry.path(), &layout.state.join(entry.file_name()))?;
+        }
+    }
+    // Still holds the store, if there is one
+    let _ = fs::remove_dir(legacy);
+    Ok(true)
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if !from.exists() {
+        return Ok(());
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).with_context(|| format!("Failed to copy {:?} to {:?}", from, to))?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Point quarantine records at the quarantine's new location
+fn rewrite_quarantine_index(index: &Path, from: &Path, to: &Path) -> Result<()> {
+    let Ok(text) = fs::read_to_string(index) else { return Ok(()) };
+    let mut records: Vec<serde_json::Value> = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse {:?}", index))?;
+    for rec in &mut records {
+        let Some(path) = rec.get("quarantine_path").and_then(|p| p.as_str()) else { continue };
+        if let Ok(rest) = Path::new(path).strip_prefix(from) {
+            rec["quarantine_path"] = to.join(rest).to_string_lossy().to_string().into();
+        }
+    }
+    fs::write(index, serde_json::to_vec_pretty(&records)?)?;
+    Ok(())
+}
+
+/// Settings (machine role, quarantine limits)
+pub fn config_dir() -> PathBuf {
+    base().config
+}
+
+/// Root of PackagePurge's own files
+pub fn state_dir() -> PathBuf {
+    base().state
+}
+
+/// Directory of the incremental scan cache
+pub fn cache_dir() -> PathBuf {
+    resolve(|o| o.cache_dir.clone(), "PACKAGEPURGE_CACHE_DIR").unwrap_or_else(|| base().cache)
+}
+
+/// Feature store database file
+pub fn feature_db() -> PathBuf {
+    resolve(|o| o.db.clone(), "PACKAGEPURGE_DB").unwrap_or_else(|| state_dir().join("features.db"))
+}
+
+pub fn quarantine_dir() -> PathBuf {
+    resolve(|o| o.quarantine_dir.clone(), "PACKAGEPURGE_QUARANTINE_DIR")
+        .unwrap_or_else(|| state_dir().join("quarantine"))
+}
+
+/// Global store given by flag or environment, if any
+pub fn store_dir_override() -> Option<PathBuf> {
+    resolve(|o| o.store_dir.clone(), "PACKAGEPURGE_STORE_DIR")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_env() {
+        // A variable no other test reads, so setting it cannot race
+        let var = "PACKAGEPURGE_TEST_RESOLVE_DIR";
+        assert_eq!(resolve(|_| None, var), None);
+        std::env::set_var(var, "");
+        assert_eq!(resolve(|_| None, var), None);
+        std::env::set_var(var, "/scratch/pp");
+        assert_eq!(resolve(|_| None, var), Some(PathBuf::from("/scratch/pp")));
+        std::env::remove_var(var);
+    }
+
+    #[test]
+    fn test_migrate_legacy() {
+        let temp = tempfile::tempdir().unwrap();
+        let legacy = temp.path().join(".packagepurge");
+        let q = legacy.join("quarantine");
+        fs::create_dir_all(q.join("1_left-pad")).unwrap();
+        fs::create_dir_all(legacy.join("global_store/left-pad/1.0.0")).unwrap();
+        let rec = serde_json::json!([{ "id": "1", "quarantine_path": q.join("1_left-pad") }]);
+        fs::write(q.join("index.json"), rec.to_string()).unwrap();
+        fs::write(q.join("config.json"), "{}").unwrap();
+        for name in ["features.db", "scan_cache.db", "role.json"] {
+            fs::write(legacy.join(name), name).unwrap();
+        }
+
+        let layout = Layout {
+            config: temp.path().join("config/packagepurge"),
+            state: temp.path().join("state/packagepurge"),
+            cache: temp.path().join("cache/packagepurge"),
+        };
+        assert!(migrate_legacy(&legacy, &layout).unwrap());
+        assert!(layout.state.join("features.db").exists());
+        assert!(layout.cache.join("scan_cache.db").exists());
+        assert!(layout.config.join("role.json").exists());
+        assert!(layout.config.join("quarantine.json").exists());
+        let index = fs::read_to_string(layout.state.join("quarantine/index.json")).unwrap();
+        assert!(index.contains(&*layout.state.join("quarantine/1_left-pad").to_string_lossy()));
+        // The store stays, recorded as relocated
+        let record = fs::read_to_string(layout.state.join("store.json")).unwrap();
+        assert!(record.contains(&*legacy.join("global_store").to_string_lossy()));
+        assert!(legacy.join("global_store").is_dir());
+        assert!(!legacy.join("quarantine").exists());
+
+        // Only once
+        assert!(!migrate_legacy(&legacy, &layout).unwrap());
+    }
+
+    #[test]
+    fn test_migrate_legacy_read_only() {
+        let temp = tempfile::tempdir().unwrap();
+        let legacy = temp.path().join(".packagepurge");
+        fs::create_dir_all(legacy.join("quarantine")).unwrap();
+        let layout = Layout::single(temp.path().join("state"));
+
+        let _read_only = crate::safety::read_only_for_test();
+        let err = migrate_legacy(&legacy, &layout).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(crate::error::Error::ReadOnly(_))));
+        assert!(legacy.join("quarantine").is_dir() && !layout.state.exists());
+    }
+}
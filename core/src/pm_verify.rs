@@ -0,0 +1,241 @@
+//! Package Manager Cross-Check
+//!
+//! The planner decides a package is orphaned from lockfiles it parses
+//! itself. As a second opinion, the project's own package manager can be
+//! asked which packages it considers installed (`npm ls --json --all`,
+//! `pnpm list --json --depth Infinity`, `yarn info --all --recursive --json`).
+//! An orphaned item the package manager still lists is taken out of the
+//! plan and reported as a warning instead, so a parser bug shows up as a
+//! disagreement rather than as a deletion. Projects whose package manager
+//! cannot be run keep their items, with a warning that they went unchecked.
+
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::reinstall::{owning_project, PackageManager};
+use crate::types::{DryRunReport, PlanReason, PlanWarning, ScanOutput};
+
+/// `name@version` pairs a package manager considers part of a project
+pub type Listing = HashSet<(String, String)>;
+
+/// Ask `pm` what it has installed in `project`; None when it cannot be run
+/// or its output is not understood
+pub fn list_installed(pm: PackageManager, project: &Path) -> Option<Listing> {
+    let args: &[&str] = match pm {
+        PackageManager::Npm => &["npm", "ls", "--json", "--all"],
+        PackageManager::Pnpm => &["pnpm", "list", "--json", "--depth", "Infinity"],
+        PackageManager::Yarn => &["yarn", "info", "--all", "--recursive", "--json"],
+        PackageManager::Bun => return None,
+    };
+    let output = Command::new(args[0])
+        .args(&args[1..])
+        .current_dir(project)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    // npm and pnpm exit non-zero on extraneous or missing packages but still
+    // print the tree, so the output decides
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match pm {
+        PackageManager::Npm => parse_npm_ls(&stdout),
+        PackageManager::Pnpm => parse_pnpm_list(&stdout),
+        PackageManager::Yarn => parse_yarn_info(&stdout),
+        PackageManager::Bun => None,
+    }
+}
+
+/// `npm ls --json --all`: a nested `dependencies` tree; extraneous entries
+/// are not counted, npm itself would prune them
+pub fn parse_npm_ls(text: &str) -> Option<Listing> {
+    fn walk(deps: &Value, out: &mut Listing) {
+        let Some(deps) = deps.as_object() else { return };
+        for (name, node) in deps {
+            if node.get("extraneous").and_then(Value::as_bool) == Some(true) {
+                continue;
+            }
+            if let Some(version) = node.get("version").and_then(Value::as_str) {
+                out.insert((name.clone(), version.to_string()));
+            }
+            if let Some(children) = node.get("dependencies") {
+                walk(children, out);
+            }
+        }
+    }
+    let root: Value = serde_json::from_str(text).ok()?;
+    let mut out = Listing::new();
+    walk(root.get("dependencies").unwrap_or(&Value::Null), &mut out);
+    Some(out)
+}
+
+/// `pnpm list --json`: one entry per workspace project, each with
+/// `dependencies`, `devDependencies` and `optionalDependencies` trees
+pub fn parse_pnpm_list(text: &str) -> Option<Listing> {
+    fn walk(deps: &Value, out: &mut Listing) {
+        let Some(deps) = deps.as_object() else { return };
+        for (name, node) in deps {
+            if let Some(version) = node.get("version").and_then(Value::as_str) {
+                out.insert((name.clone(), version.to_string()));
+            }
+            if let Some(children) = node.get("dependencies") {
+                walk(children, out);
+            }
+        }
+    }
+    let projects: Vec<Value> = serde_json::from_str(text).ok()?;
+    let mut out = Listing::new();
+    for project in &projects {
+        for field in ["dependencies", "devDependencies", "optionalDependencies"] {
+            walk(project.get(field).unwrap_or(&Value::Null), &mut out);
+        }
+    }
+    Some(out)
+}
+
+/// `yarn info --json` (Yarn 2+): one JSON object per line whose `value` is a
+/// locator such as `@scope/pkg@npm:1.2.3`
+pub fn parse_yarn_info(text: &str) -> Option<Listing> {
+    let mut out = Listing::new();
+    let mut parsed_any = false;
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let entry: Value = serde_json::from_str(line).ok()?;
+        parsed_any = true;
+        let Some(locator) = entry.get("value").and_then(Value::as_str) else { continue };
+        // The separating '@' is the first one after a scope's leading '@'
+        let Some(at) = locator[1..].find('@').map(|i| i + 1) else { continue };
+        let (name, reference) = (&locator[..at], &locator[at + 1..]);
+        let version = entry.pointer("/children/Version").and_then(Value::as_str)
+            .unwrap_or_else(|| reference.strip_prefix("npm:").unwrap_or(reference));
+        out.insert((name.to_string(), version.to_string()));
+    }
+    parsed_any.then_some(out)
+}
+
+/// Cross-check the orphaned items of `report` with `list`, which returns the
+/// listing of a project's package manager. Items the package manager still
+/// lists become warnings; projects it cannot list are warned about once.
+/// Returns the number of items taken out of the plan.
+pub fn cross_check(
+    report: &mut DryRunReport,
+    scan: &ScanOutput,
+    mut list: impl FnMut(PackageManager, &Path) -> Option<Listing>,
+) -> usize {
+    let packages: HashMap<&str, (&str, &str)> = scan.packages.iter()
+        .map(|p| (p.path.as_str(), (p.name.as_str(), p.version.as_str())))
+        .collect();
+    let mut listings: HashMap<PathBuf, Option<(PackageManager, Listing)>> = HashMap::new();
+    let mut downgraded = 0;
+
+    let mut kept = Vec::with_capacity(report.items.len());
+    for item in std::mem::take(&mut report.items) {
+        let checked = matches!(item.reason, PlanReason::Orphaned)
+            .then(|| Some((packages.get(item.target_path.as_str())?, owning_project(Path::new(&item.target_path))?)))
+            .flatten();
+        let Some((&(name, version), project)) = checked else {
+            kept.push(item);
+            continue;
+        };
+        let listing = listings.entry(project.clone()).or_insert_with(|| {
+            let (pm, _) = PackageManager::detect(&project);
+            let listing = list(pm, &project).map(|l| (pm, l));
+            if listing.is_none() {
+                report.warnings.push(PlanWarning {
+                    target_path: project.to_string_lossy().to_string(),
+                    message: format!("{} could not list installed packages; orphans here are unverified", pm),
+                });
+            }
+            listing
+        });
+        match listing {
+            Some((pm, listed)) if listed.contains(&(name.to_string(), version.to_string())) => {
+                downgraded += 1;
+                report.warnings.push(PlanWarning {
+                    target_path: item.target_path,
+                    message: format!("planned as orphaned, but {} still lists {}@{}", pm, name, version),
+                });
+            }
+            _ => kept.push(item),
+        }
+    }
+    report.items = kept;
+    report.total_estimated_bytes = report.items.iter().map(|i| i.estimated_size_bytes).sum();
+    downgraded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PackageRecord, PlanItem};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parsers() {
+        let npm = r#"{"name":"app","dependencies":{
+            "a":{"version":"1.0.0","dependencies":{"b":{"version":"2.0.0"}}},
+            "stray":{"version":"0.1.0","extraneous":true}}}"#;
+        let listed = parse_npm_ls(npm).unwrap();
+        assert!(listed.contains(&("b".into(), "2.0.0".into())));
+        assert!(!listed.iter().any(|(n, _)| n == "stray"));
+
+        let pnpm = r#"[{"name":"app","devDependencies":{"c":{"version":"3.1.0","dependencies":{"d":{"version":"4.0.0"}}}}}]"#;
+        assert!(parse_pnpm_list(pnpm).unwrap().contains(&("d".into(), "4.0.0".into())));
+
+        let yarn = "{\"value\":\"@scope/e@npm:5.0.0\",\"children\":{\"Version\":\"5.0.0\"}}\n{\"value\":\"f@npm:6.0.0\"}\n";
+        let listed = parse_yarn_info(yarn).unwrap();
+        assert!(listed.contains(&("@scope/e".into(), "5.0.0".into())));
+        assert!(listed.contains(&("f".into(), "6.0.0".into())));
+        assert!(parse_npm_ls("npm ERR!").is_none());
+    }
+
+    #[test]
+    fn test_listed_orphans_become_warnings() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("app");
+        fs::create_dir_all(project.join("node_modules")).unwrap();
+        fs::write(project.join("package.json"), "{}").unwrap();
+        let package = |name: &str| PackageRecord::for_test(project.join("node_modules").join(name), "1.0.0", 100);
+        let scan = ScanOutput {
+            packages: vec![package("used"), package("unused")],
+            projects: Vec::new(),
+            edges: Vec::new(),
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let mut report = DryRunReport {
+            items: scan.packages.iter().map(|p| PlanItem {
+                target_path: p.path.clone(),
+                estimated_size_bytes: p.size_bytes,
+                reason: PlanReason::Orphaned,
+                blockers: Vec::new(),
+                risk: None,
+            }).collect(),
+            total_estimated_bytes: 200,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+
+        let downgraded = cross_check(&mut report, &scan, |pm, _| {
+            assert_eq!(pm, PackageManager::Npm);
+            Some([("used".to_string(), "1.0.0".to_string())].into_iter().collect())
+        });
+        assert_eq!(downgraded, 1);
+        assert_eq!(report.items.len(), 1);
+        assert!(report.items[0].target_path.ends_with("unused"));
+        assert_eq!(report.total_estimated_bytes, 100);
+        assert!(report.warnings[0].target_path.ends_with("used"));
+
+        // Without a listing the items stay and the project is flagged once
+        let mut unchecked = report.clone();
+        unchecked.warnings.clear();
+        assert_eq!(cross_check(&mut unchecked, &scan, |_, _| None), 0);
+        assert_eq!(unchecked.items.len(), 1);
+        assert_eq!(unchecked.warnings.len(), 1);
+    }
+}
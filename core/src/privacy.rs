@@ -0,0 +1,99 @@
+//! Privacy Controls
+//!
+//! Limits what the feature store keeps about the person using it. Settings
+//! live in `privacy.json` in the config directory:
+//! - `hash_project_paths`: projects, behavior events, scan roots and package
+//!   snapshots are stored under a SHA-256 of their path instead of the path
+//!   itself. The hash is stable, so per-project history still accumulates.
+//! - `drop_command_args`: only the program of a recorded command is kept
+//!   (`npm`, not `npm run deploy --token ...`).
+//! - `disable_event_logging`: behavior events are not recorded at all.
+//!
+//! Settings apply to new records; `FeatureStore::redact` rewrites what was
+//! recorded before. Archive records keep real paths, since restoring an
+//! archive needs them.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::Error;
+
+/// Prefix marking a stored path as a hash
+const HASH_PREFIX: &str = "sha256:";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivacyConfig {
+    #[serde(default)]
+    pub hash_project_paths: bool,
+    #[serde(default)]
+    pub drop_command_args: bool,
+    #[serde(default)]
+    pub disable_event_logging: bool,
+}
+
+impl PrivacyConfig {
+    /// How `path` is stored: as is, or hashed. Already hashed values are
+    /// kept, so redacting twice changes nothing.
+    pub fn project_key(&self, path: &str) -> String {
+        if !self.hash_project_paths || is_hashed(path) {
+            return path.to_string();
+        }
+        format!("{}{}", HASH_PREFIX, hex::encode(Sha256::digest(path.as_bytes())))
+    }
+
+    /// How `command` is stored: whole, or only its program
+    pub fn command_text(&self, command: &str) -> String {
+        if self.drop_command_args {
+            command.split_whitespace().next().unwrap_or_default().to_string()
+        } else {
+            command.to_string()
+        }
+    }
+}
+
+/// Whether a stored path was hashed by `project_key`
+pub fn is_hashed(path: &str) -> bool {
+    path.strip_prefix(HASH_PREFIX).is_some_and(|h| h.len() == 64 && h.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("privacy.json")
+}
+
+pub fn load_config() -> PrivacyConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &PrivacyConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &PrivacyConfig) -> Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save privacy config to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction_is_idempotent() {
+        let config = PrivacyConfig { hash_project_paths: true, drop_command_args: true, ..Default::default() };
+        let key = config.project_key("/home/ada/client-x");
+        assert!(is_hashed(&key));
+        assert_eq!(config.project_key(&key), key);
+        assert_eq!(config.project_key("/home/ada/client-x"), key);
+        assert_eq!(config.command_text("npm run deploy --token abc"), "npm");
+        assert_eq!(PrivacyConfig::default().project_key("/p"), "/p");
+    }
+}
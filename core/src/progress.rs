@@ -0,0 +1,275 @@
+//! Progress Reporting and Cancellation
+//!
+//! Long-running operations (scan, plan, quarantine, symlink) accept an
+//! `OperationContext` carrying a `ProgressSink` for granular updates and a
+//! `CancellationToken` that is checked between units of work. Cancellation is
+//! cooperative: an operation only stops at item boundaries, so every mutation
+//! that was started is also finished.
+//!
+//! Operations that move data (quarantine, symlink) also measure their
+//! throughput with a `ThroughputMeter`, attach the current rate and an
+//! estimate of the remaining time to their progress updates, and include the
+//! final figures in their report.
+
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use walkdir::WalkDir;
+
+use crate::error::{Error, Result};
+
+/// Operation phase a progress update belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Walk,
+    Size,
+    Plan,
+    Quarantine,
+    /// Deleting without quarantine (`ci-clean`)
+    Remove,
+    Symlink,
+    Verify,
+}
+
+/// A single progress update
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent<'a> {
+    pub phase: Phase,
+    /// Units completed so far in this phase
+    pub done: u64,
+    /// Total units, when known up front
+    pub total: Option<u64>,
+    /// Item currently being processed
+    pub current: Option<&'a Path>,
+    /// Measured throughput and remaining-time estimate, for operations that move data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<Rate>,
+}
+
+/// Throughput so far and the time the remaining work should take at that pace
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Rate {
+    pub bytes_per_sec: f64,
+    pub files_per_sec: f64,
+    pub eta_secs: Option<f64>,
+}
+
+/// Final throughput figures of an operation, for its JSON report
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ThroughputSummary {
+    pub elapsed_secs: f64,
+    pub bytes: u64,
+    pub files: u64,
+    pub items: u64,
+    pub bytes_per_sec: f64,
+    pub files_per_sec: f64,
+}
+
+/// Accumulates bytes and files processed since the operation started
+#[derive(Debug, Clone)]
+pub struct ThroughputMeter {
+    started: Instant,
+    bytes: u64,
+    files: u64,
+    items: u64,
+}
+
+impl Default for ThroughputMeter {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+impl ThroughputMeter {
+    pub fn start() -> Self {
+        Self { started: Instant::now(), bytes: 0, files: 0, items: 0 }
+    }
+
+    /// Record one finished item of `bytes` bytes and `files` files
+    pub fn record(&mut self, bytes: u64, files: u64) {
+        self.bytes += bytes;
+        self.files += files;
+        self.items += 1;
+    }
+
+    /// Current rate. The estimate uses the remaining bytes when the caller
+    /// knows them, otherwise the average time per item over the items left.
+    pub fn rate(&self, remaining_items: u64, remaining_bytes: Option<u64>) -> Rate {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let per_sec = |n: u64| if elapsed > 0.0 { n as f64 / elapsed } else { 0.0 };
+        let bytes_per_sec = per_sec(self.bytes);
+        let eta_secs = match remaining_bytes {
+            Some(bytes) if bytes_per_sec > 0.0 => Some(bytes as f64 / bytes_per_sec),
+            _ if self.items > 0 => Some(elapsed / self.items as f64 * remaining_items as f64),
+            _ => None,
+        };
+        Rate { bytes_per_sec, files_per_sec: per_sec(self.files), eta_secs }
+    }
+
+    pub fn summary(&self) -> ThroughputSummary {
+        let rate = self.rate(0, None);
+        ThroughputSummary {
+            elapsed_secs: self.started.elapsed().as_secs_f64(),
+            bytes: self.bytes,
+            files: self.files,
+            items: self.items,
+            bytes_per_sec: rate.bytes_per_sec,
+            files_per_sec: rate.files_per_sec,
+        }
+    }
+}
+
+/// Number of regular files below `path` (without following symlinks), to
+/// count an item's files before it is moved
+pub fn count_files(path: &Path) -> u64 {
+    WalkDir::new(path).into_iter().filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()).count() as u64
+}
+
+/// Receiver of progress updates. Implementations must be cheap; they are
+/// called from worker threads in hot loops.
+pub trait ProgressSink: Send + Sync {
+    fn on_progress(&self, event: &ProgressEvent<'_>);
+}
+
+/// Sink that discards every update
+pub struct NoopProgress;
+
+impl ProgressSink for NoopProgress {
+    fn on_progress(&self, _event: &ProgressEvent<'_>) {}
+}
+
+/// Sink writing one JSON object per update to stderr
+pub struct JsonLinesProgress;
+
+impl ProgressSink for JsonLinesProgress {
+    fn on_progress(&self, event: &ProgressEvent<'_>) {
+        if let Ok(line) = serde_json::to_string(event) {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Shared flag used to request cooperative cancellation
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation; running operations stop at their next checkpoint
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Checkpoint: `Err(Error::Cancelled)` once cancellation was requested
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Progress sink plus cancellation token handed to long operations
+#[derive(Clone)]
+pub struct OperationContext {
+    pub progress: Arc<dyn ProgressSink>,
+    pub cancel: CancellationToken,
+}
+
+impl Default for OperationContext {
+    fn default() -> Self {
+        Self {
+            progress: Arc::new(NoopProgress),
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+impl OperationContext {
+    pub fn new(progress: Arc<dyn ProgressSink>, cancel: CancellationToken) -> Self {
+        Self { progress, cancel }
+    }
+
+    /// Emit a progress update
+    pub fn report(&self, phase: Phase, done: u64, total: Option<u64>, current: Option<&Path>) {
+        self.progress.on_progress(&ProgressEvent { phase, done, total, current, rate: None });
+    }
+
+    /// Emit a progress update carrying the operation's throughput
+    pub fn report_rate(&self, phase: Phase, done: u64, total: Option<u64>, current: Option<&Path>, rate: Rate) {
+        self.progress.on_progress(&ProgressEvent { phase, done, total, current, rate: Some(rate) });
+    }
+
+    /// Cancellation checkpoint
+    pub fn check(&self) -> Result<()> {
+        self.cancel.check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Recorder(Mutex<Vec<(Phase, u64)>>);
+
+    impl ProgressSink for Recorder {
+        fn on_progress(&self, event: &ProgressEvent<'_>) {
+            self.0.lock().unwrap().push((event.phase, event.done));
+        }
+    }
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(token.check().is_ok());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(matches!(token.check(), Err(Error::Cancelled)));
+    }
+
+    #[test]
+    fn test_context_reports_to_sink() {
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        let ctx = OperationContext::new(recorder.clone(), CancellationToken::new());
+        ctx.report(Phase::Walk, 1, None, None);
+        ctx.report(Phase::Plan, 2, Some(2), None);
+
+        let seen = recorder.0.lock().unwrap();
+        assert_eq!(*seen, vec![(Phase::Walk, 1), (Phase::Plan, 2)]);
+    }
+
+    #[test]
+    fn test_throughput_meter() {
+        let mut meter = ThroughputMeter::start();
+        assert!(meter.rate(3, None).eta_secs.is_none());
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        meter.record(1000, 4);
+        meter.record(1000, 6);
+        let rate = meter.rate(2, None);
+        assert!(rate.bytes_per_sec > 0.0 && rate.files_per_sec > 0.0);
+        // Two items done, two left: about as long again
+        let eta = rate.eta_secs.unwrap();
+        let elapsed = meter.summary().elapsed_secs;
+        assert!(eta > 0.5 * elapsed && eta < 1.5 * elapsed);
+        // 4000 bytes left at ~2000 bytes per elapsed span: about twice as long
+        assert!(meter.rate(2, Some(4000)).eta_secs.unwrap() > 1.5 * elapsed);
+
+        let summary = meter.summary();
+        assert_eq!((summary.bytes, summary.files, summary.items), (2000, 10, 2));
+    }
+}
@@ -0,0 +1,410 @@
+//! Project Activity Classes
+//!
+//! Labels each scanned project active, dormant or dead from the newest of
+//! several local signals:
+//! - the last commit or checkout in its git clone (the HEAD reflog)
+//! - the newest modification time among its own files, a few levels deep,
+//!   leaving out `node_modules` and dot directories
+//! - the last behavior event recorded for it in the feature store
+//! - when a JetBrains IDE last opened it (`recentProjects.xml`)
+//!
+//! A project whose last activity is within `active_days` is active. One
+//! idle for longer than `dead_days` is dead, unless a VS Code-style editor
+//! still lists it among recently opened folders; those lists carry no time,
+//! so such a project is at most dormant. Everything in between is dormant.
+//!
+//! Retention rules key off the label: projects whose class is listed in
+//! `purge_node_modules` have their whole `node_modules` planned for
+//! quarantine by `dry-run` and `optimize`, replacing the package items
+//! inside it, unless other projects link to that `node_modules` (see
+//! `tree_share`). Settings live in `project_activity.json` in the config
+//! directory; by default nothing is purged by class.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::feature_store::FeatureStore;
+use crate::native_walk::tree_totals;
+use crate::repo_activity::local_last_commit;
+use crate::symlink::is_symlink;
+use crate::tree_share::sharers_within;
+use crate::types::{ActivityClass, DryRunReport, PlanItem, PlanReason, PlanWarning, ProjectRecord};
+
+/// Directory levels below the project searched for modified files
+const MTIME_DEPTH: usize = 3;
+/// Entries looked at per project before giving up on finding newer ones
+const MTIME_ENTRY_LIMIT: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivityConfig {
+    #[serde(default = "default_active_days")]
+    pub active_days: i64,
+    #[serde(default = "default_dead_days")]
+    pub dead_days: i64,
+    /// Classes whose projects' `node_modules` plans purge as a whole
+    #[serde(default)]
+    pub purge_node_modules: Vec<ActivityClass>,
+}
+
+fn default_active_days() -> i64 {
+    30
+}
+
+fn default_dead_days() -> i64 {
+    365
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        Self { active_days: default_active_days(), dead_days: default_dead_days(), purge_node_modules: Vec::new() }
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("project_activity.json")
+}
+
+pub fn load_config() -> ActivityConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &ActivityConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &ActivityConfig) -> Result<()> {
+    if config.active_days <= 0 || config.dead_days <= config.active_days {
+        anyhow::bail!("dead_days ({}) must exceed active_days ({}), which must be positive", config.dead_days, config.active_days);
+    }
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save project activity settings to {:?}", path))
+}
+
+/// What was found about one project
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ActivitySignals {
+    pub last_commit: Option<DateTime<Utc>>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub last_event: Option<DateTime<Utc>>,
+    pub last_opened: Option<DateTime<Utc>>,
+    /// Listed by a VS Code-style editor as recently opened
+    pub in_editor_recents: bool,
+}
+
+impl ActivitySignals {
+    pub fn last_activity(&self) -> Option<DateTime<Utc>> {
+        [self.last_commit, self.last_modified, self.last_event, self.last_opened].into_iter().flatten().max()
+    }
+
+    pub fn classify(&self, config: &ActivityConfig, now: DateTime<Utc>) -> ActivityClass {
+        match self.last_activity() {
+            Some(t) if now - t <= Duration::days(config.active_days) => ActivityClass::Active,
+            Some(t) if now - t <= Duration::days(config.dead_days) => ActivityClass::Dormant,
+            _ if self.in_editor_recents => ActivityClass::Dormant,
+            _ => ActivityClass::Dead,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectActivity {
+    pub project: String,
+    pub activity: ActivityClass,
+    pub last_activity: Option<DateTime<Utc>>,
+    pub signals: ActivitySignals,
+}
+
+impl ProjectActivity {
+    fn idle_days(&self, now: DateTime<Utc>) -> Option<i64> {
+        self.last_activity.map(|t| (now - t).num_days().max(0))
+    }
+}
+
+/// Projects editors list as recently opened, with the time when known
+#[derive(Debug, Clone, Default)]
+pub struct EditorRecents {
+    opened: HashMap<PathBuf, Option<DateTime<Utc>>>,
+}
+
+impl EditorRecents {
+    /// Recent lists of the editors installed for the current user
+    pub fn detect() -> Self {
+        let vscode: Vec<PathBuf> = crate::editor_caches::EditorLocations::detect().vscode.into_iter()
+            .map(|i| i.user_data)
+            .collect();
+        let jetbrains: Vec<PathBuf> = dirs::config_dir()
+            .map(|c| c.join("JetBrains"))
+            .into_iter()
+            .filter(|p| p.is_dir())
+            .collect();
+        Self::load(&vscode, &jetbrains, dirs::home_dir().as_deref())
+    }
+
+    /// Read the recent lists under VS Code-style user data directories and
+    /// JetBrains config roots (one subdirectory per product version)
+    pub fn load(vscode_user_data: &[PathBuf], jetbrains_roots: &[PathBuf], home: Option<&Path>) -> Self {
+        let mut recents = Self::default();
+        for user_data in vscode_user_data {
+            for path in vscode_recents(user_data) {
+                recents.opened.entry(path).or_insert(None);
+            }
+        }
+        let product_dirs = jetbrains_roots.iter()
+            .flat_map(|root| fs::read_dir(root).into_iter().flatten().flatten())
+            .map(|e| e.path().join("options").join("recentProjects.xml"));
+        for file in product_dirs {
+            let Ok(xml) = fs::read_to_string(&file) else { continue };
+            for (path, opened) in jetbrains_recents(&xml, home) {
+                let entry = recents.opened.entry(path).or_insert(None);
+                *entry = (*entry).max(opened);
+            }
+        }
+        recents
+    }
+
+    /// Whether `project` is listed, and when it was last opened if known
+    pub fn get(&self, project: &Path) -> Option<Option<DateTime<Utc>>> {
+        self.opened.get(project).copied()
+    }
+}
+
+/// Folder and workspace paths of `history.recentlyOpenedPathsList` in
+/// `state.vscdb`, or of the older `storage.json`
+fn vscode_recents(user_data: &Path) -> Vec<PathBuf> {
+    let global = user_data.join("User").join("globalStorage");
+    let from_db = rusqlite::Connection::open_with_flags(global.join("state.vscdb"), rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|db| db.query_row(
+            "SELECT value FROM ItemTable WHERE key = 'history.recentlyOpenedPathsList'", [], |row| row.get::<_, String>(0),
+        ))
+        .ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok());
+    let from_json = || fs::read_to_string(global.join("storage.json")).ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+        .map(|v| v["openedPathsList"].clone());
+    let Some(list) = from_db.or_else(from_json) else { return Vec::new() };
+
+    let entries = list["entries"].as_array().into_iter().flatten()
+        .filter_map(|e| e["folderUri"].as_str().or_else(|| e["workspace"]["configPath"].as_str()));
+    let legacy = list["workspaces3"].as_array().into_iter().flatten()
+        .filter_map(|e| e.as_str().or_else(|| e["configURIPath"].as_str()));
+    entries.chain(legacy).filter_map(file_uri_path).collect()
+}
+
+/// Local path of a `file://` URI
+fn file_uri_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let bytes = rest.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%').then(|| rest.get(i + 1..i + 3)).flatten();
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // `/c:/Users/...` on Windows
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+/// Projects of a JetBrains `recentProjects.xml` with their newest open or
+/// activation time
+fn jetbrains_recents(xml: &str, home: Option<&Path>) -> Vec<(PathBuf, Option<DateTime<Utc>>)> {
+    let mut found = Vec::new();
+    for chunk in xml.split("<entry key=\"").skip(1) {
+        let Some((key, body)) = chunk.split_once('"') else { continue };
+        let key = match home {
+            Some(home) => key.replace("$USER_HOME$", &home.to_string_lossy()),
+            None => key.to_string(),
+        };
+        let opened = ["projectOpenTimestamp", "activationTimestamp"].iter()
+            .filter_map(|name| {
+                let after = body.split_once(&format!("name=\"{}\" value=\"", name))?.1;
+                let millis: i64 = after.split('"').next()?.parse().ok()?;
+                DateTime::from_timestamp_millis(millis)
+            })
+            .max();
+        found.push((PathBuf::from(key), opened));
+    }
+    found
+}
+
+/// Newest modification time of the project's own files
+fn newest_mtime(project: &Path) -> Option<DateTime<Utc>> {
+    walkdir::WalkDir::new(project)
+        .max_depth(MTIME_DEPTH)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !(e.file_name() == "node_modules" || e.file_name().to_string_lossy().starts_with('.')))
+        .filter_map(|e| e.ok())
+        .take(MTIME_ENTRY_LIMIT)
+        .filter_map(|e| e.metadata().ok()?.modified().ok())
+        .max()
+        .map(DateTime::<Utc>::from)
+}
+
+/// Classify every project
+pub fn classify_projects(
+    projects: &[ProjectRecord],
+    db: Option<&FeatureStore>,
+    recents: &EditorRecents,
+    config: &ActivityConfig,
+    now: DateTime<Utc>,
+) -> Vec<ProjectActivity> {
+    projects.iter().map(|project| {
+        let path = Path::new(&project.path);
+        let listed = recents.get(path);
+        let signals = ActivitySignals {
+            last_commit: local_last_commit(path),
+            last_modified: newest_mtime(path).or(Some(project.mtime)),
+            last_event: db.and_then(|db| db.last_event(&project.path).ok().flatten()),
+            last_opened: listed.flatten(),
+            in_editor_recents: listed.is_some(),
+        };
+        ProjectActivity {
+            project: project.path.clone(),
+            activity: signals.classify(config, now),
+            last_activity: signals.last_activity(),
+            signals,
+        }
+    })
+    .collect()
+}
+
+/// Add the `node_modules` of projects whose class `config` purges to
+/// `report`, dropping the package items inside them. Returns the number
+/// of trees added.
+pub fn apply_retention(report: &mut DryRunReport, activities: &[ProjectActivity], config: &ActivityConfig, now: DateTime<Utc>) -> usize {
+    let mut trees: Vec<(PathBuf, &ProjectActivity)> = activities.iter()
+        .filter(|a| config.purge_node_modules.contains(&a.activity))
+        .map(|a| (Path::new(&a.project).join("node_modules"), a))
+        .filter(|(nm, _)| nm.is_dir() && !is_symlink(nm))
+        .collect();
+    // Other projects link to these (see `tree_share`)
+    trees.retain(|(nm, _)| {
+        let sharers = sharers_within(nm);
+        if !sharers.is_empty() {
+            report.warnings.push(PlanWarning {
+                target_path: nm.to_string_lossy().to_string(),
+                message: format!("kept: {} link to this tree", sharers.join(", ")),
+            });
+        }
+        sharers.is_empty()
+    });
+    if trees.is_empty() {
+        return 0;
+    }
+    report.items.retain(|item| !trees.iter().any(|(nm, _)| Path::new(&item.target_path).starts_with(nm)));
+    for (node_modules, activity) in &trees {
+        report.items.push(PlanItem {
+            target_path: node_modules.to_string_lossy().to_string(),
+            estimated_size_bytes: tree_totals(node_modules, None).bytes,
+            reason: PlanReason::InactiveProject { activity: activity.activity, idle_days: activity.idle_days(now) },
+            blockers: Vec::new(),
+            risk: None,
+        });
+    }
+    report.total_estimated_bytes = report.items.iter().map(|i| i.estimated_size_bytes).sum();
+    trees.len()
+}
+
+/// Classify the scanned projects and apply the retention rules to `report`
+/// when any class is purged
+pub fn apply_configured_retention(report: &mut DryRunReport, projects: &[ProjectRecord]) -> usize {
+    let config = load_config();
+    if config.purge_node_modules.is_empty() {
+        return 0;
+    }
+    let now = Utc::now();
+    let db = FeatureStore::open_default().ok();
+    let activities = classify_projects(projects, db.as_ref(), &EditorRecents::detect(), &config, now);
+    apply_retention(report, &activities, &config, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn project(root: &Path, name: &str, idle_days: i64) -> ProjectRecord {
+        let dir = root.join(name);
+        fs::create_dir_all(dir.join("node_modules/lodash")).unwrap();
+        fs::write(dir.join("node_modules/lodash/index.js"), "module.exports = 1").unwrap();
+        fs::write(dir.join("index.js"), "").unwrap();
+        let when = std::time::SystemTime::from(Utc::now() - Duration::days(idle_days));
+        for path in [dir.join("index.js"), dir.clone()] {
+            fs::File::open(&path).unwrap().set_modified(when).unwrap();
+        }
+        ProjectRecord {
+            path: dir.to_string_lossy().to_string(),
+            manager: None,
+            dependencies: Vec::new(),
+            mtime: Utc::now() - Duration::days(idle_days),
+        }
+    }
+
+    #[test]
+    fn test_classes_from_signals_and_editor_recents() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let projects = vec![project(root, "app", 2), project(root, "old", 100), project(root, "gone", 800), project(root, "opened", 800)];
+
+        // A JetBrains IDE opened `opened` recently
+        let options = root.join("jetbrains/IntelliJIdea2024.1/options");
+        fs::create_dir_all(&options).unwrap();
+        let millis = (Utc::now() - Duration::days(5)).timestamp_millis();
+        fs::write(options.join("recentProjects.xml"), format!(
+            r#"<application><component name="RecentProjectsManager"><option name="additionalInfo"><map>
+            <entry key="$USER_HOME$/opened"><value><RecentProjectMetaInfo><option name="projectOpenTimestamp" value="{}" /></RecentProjectMetaInfo></value></entry>
+            </map></option></component></application>"#, millis)).unwrap();
+        let recents = EditorRecents::load(&[], &[root.join("jetbrains")], Some(root));
+
+        let config = ActivityConfig { purge_node_modules: vec![ActivityClass::Dead], ..Default::default() };
+        let now = Utc::now();
+        let activities = classify_projects(&projects, None, &recents, &config, now);
+        let classes: Vec<ActivityClass> = activities.iter().map(|a| a.activity).collect();
+        assert_eq!(classes, [ActivityClass::Active, ActivityClass::Dormant, ActivityClass::Dead, ActivityClass::Active]);
+
+        let lodash = root.join("gone/node_modules/lodash").to_string_lossy().to_string();
+        let mut report = DryRunReport {
+            items: vec![PlanItem { target_path: lodash, estimated_size_bytes: 18, reason: PlanReason::Orphaned, blockers: Vec::new(), risk: None }],
+            total_estimated_bytes: 18,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+        assert_eq!(apply_retention(&mut report, &activities, &config, now), 1);
+        assert_eq!(report.items.len(), 1);
+        assert!(report.items[0].target_path.ends_with("gone/node_modules"));
+        assert!(matches!(report.items[0].reason, PlanReason::InactiveProject { activity: ActivityClass::Dead, idle_days: Some(d) } if d >= 799));
+        assert_eq!(report.total_estimated_bytes, 18);
+
+        // Listed without a time by a VS Code-style editor: at most dormant
+        let signals = ActivitySignals { in_editor_recents: true, ..Default::default() };
+        assert_eq!(signals.classify(&config, now), ActivityClass::Dormant);
+        assert_eq!(file_uri_path("file:///home/ada/my%20app"), Some(PathBuf::from("/home/ada/my app")));
+    }
+}
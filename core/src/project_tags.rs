@@ -0,0 +1,257 @@
+//! Project Tags
+//!
+//! Projects carry tags given by hand (`tag add`) or by rules matching where
+//! a project lives or which repository it is a clone of. Both are kept in
+//! `tags.json` in the config directory:
+//!
+//! ```json
+//! {
+//!   "projects": { "/home/me/src/site": ["client-work"] },
+//!   "rules": [
+//!     { "tag": "client-work", "under": "/home/me/clients" },
+//!     { "tag": "experiments", "remote": "github.com/me-playground" }
+//!   ]
+//! }
+//! ```
+//!
+//! `remote` matches the `origin` remote as `host/owner/repo`, or just
+//! `owner/repo`, by whole path segments.
+//!
+//! `--tag` (or `PACKAGEPURGE_TAGS`, comma separated) narrows every scan to
+//! the projects carrying any of the tags, the packages installed in them or
+//! reachable from them, and the edges between those, so each command
+//! plans, reports and cleans only that part: `dry-run --tag experiments -d 7`
+//! and `dry-run --tag client-work -d 180` hold different work on the same
+//! machine to different policies. Shared caches belong to no project and
+//! fall outside every tag. Reports built from recorded history rather than
+//! a scan (`top`, `digest`, the quarantine) are not narrowed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::Error;
+use crate::repo_activity::{origin_url, split_url};
+use crate::types::{ProjectRecord, ScanOutput};
+
+static FILTER: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Tag projects matching one condition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagRule {
+    pub tag: String,
+    /// Projects at or below this directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub under: Option<PathBuf>,
+    /// Projects whose `origin` is in this org, group or repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+impl TagRule {
+    fn matches(&self, project: &Path, remote: Option<&(String, String)>) -> bool {
+        if let Some(dir) = &self.under {
+            return project.starts_with(dir);
+        }
+        let (Some(prefix), Some((host, path))) = (&self.remote, remote) else { return false };
+        let prefix = prefix.trim_matches('/').to_lowercase();
+        let within = |key: &str| key == prefix || key.starts_with(&format!("{}/", prefix));
+        within(&format!("{}/{}", host, path.to_lowercase())) || within(&path.to_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagsConfig {
+    /// Tags given by hand, by project path
+    #[serde(default)]
+    pub projects: BTreeMap<String, BTreeSet<String>>,
+    #[serde(default)]
+    pub rules: Vec<TagRule>,
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("tags.json")
+}
+
+pub fn load_config() -> TagsConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &TagsConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &TagsConfig) -> Result<()> {
+    for tag in config.projects.values().flatten().chain(config.rules.iter().map(|r| &r.tag)) {
+        check_tag(tag)?;
+    }
+    for rule in &config.rules {
+        anyhow::ensure!(
+            rule.under.is_some() != rule.remote.is_some(),
+            "Rule for tag {:?} needs exactly one of `under` and `remote`", rule.tag
+        );
+    }
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save tags to {:?}", path))
+}
+
+/// Tags are what `--tag` is given: letters, digits, `-`, `_` and `.`
+pub(crate) fn check_tag(tag: &str) -> Result<()> {
+    anyhow::ensure!(
+        !tag.is_empty() && tag.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.')),
+        "Invalid tag {:?}: use letters, digits, '-', '_' and '.'", tag
+    );
+    Ok(())
+}
+
+/// Narrow every scan of this process to projects with any of `tags`
+pub fn set_filter(tags: Vec<String>) {
+    let _ = FILTER.set(tags);
+}
+
+/// Tags scans are narrowed to: `set_filter`, else `PACKAGEPURGE_TAGS`
+pub fn filter() -> Vec<String> {
+    match FILTER.get() {
+        Some(tags) if !tags.is_empty() => tags.clone(),
+        _ => std::env::var("PACKAGEPURGE_TAGS")
+            .map(|v| v.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// A project and the tags it carries
+#[derive(Debug, Clone, Serialize)]
+pub struct TaggedProject {
+    pub project: String,
+    pub tags: BTreeSet<String>,
+}
+
+/// Tags of each project, given by hand or by a matching rule
+pub fn tag_projects(projects: &[ProjectRecord], config: &TagsConfig) -> Vec<TaggedProject> {
+    let by_remote = config.rules.iter().any(|r| r.remote.is_some());
+    projects.iter().map(|p| {
+        let path = Path::new(&p.path);
+        let remote = if by_remote { origin_url(path).and_then(|u| split_url(&u)) } else { None };
+        let mut tags = config.projects.get(&p.path).cloned().unwrap_or_default();
+        tags.extend(config.rules.iter().filter(|r| r.matches(path, remote.as_ref())).map(|r| r.tag.clone()));
+        TaggedProject { project: p.path.clone(), tags }
+    })
+    .collect()
+}
+
+/// The part of `scan` belonging to projects tagged with any of `tags`:
+/// those projects, the packages installed below them or reachable from
+/// them through the dependency edges, and the edges among what is kept
+pub fn filter_scan(scan: ScanOutput, tags: &[String], config: &TagsConfig) -> ScanOutput {
+    let kept: HashSet<String> = tag_projects(&scan.projects, config)
+        .into_iter()
+        .filter(|p| p.tags.iter().any(|t| tags.contains(t)))
+        .map(|p| p.project)
+        .collect();
+    let dirs: Vec<&Path> = kept.iter().map(Path::new).collect();
+    let in_kept = |path: &str| dirs.iter().any(|d| Path::new(path).starts_with(d));
+
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (from, to) in &scan.edges {
+        children.entry(from.as_str()).or_default().push(to.as_str());
+    }
+    let mut reached: HashSet<&str> = kept.iter().map(String::as_str).collect();
+    let mut queue: VecDeque<&str> = reached.iter().copied().collect();
+    while let Some(node) = queue.pop_front() {
+        for child in children.get(node).into_iter().flatten() {
+            if reached.insert(child) {
+                queue.push_back(child);
+            }
+        }
+    }
+    let keep = |path: &str| reached.contains(path) || in_kept(path);
+
+    let packages = scan.packages.iter().filter(|p| keep(&p.path)).cloned().collect();
+    let edges = scan.edges.iter().filter(|(from, to)| keep(from) && keep(to)).cloned().collect();
+    let deferred_sizes = scan.deferred_sizes.iter().filter(|p| keep(p)).cloned().collect();
+    let marked_dirs = scan.marked_dirs.iter().filter(|m| in_kept(&m.path)).cloned().collect();
+    let projects = scan.projects.iter().filter(|p| kept.contains(&p.path)).cloned().collect();
+    ScanOutput { packages, projects, edges, stats: scan.stats, deferred_sizes, marked_dirs }
+}
+
+/// `filter_scan` with the tags from `--tag` and the configured tags; the
+/// scan as it is when no tag was asked for
+pub fn apply_filter(scan: ScanOutput) -> ScanOutput {
+    let tags = filter();
+    if tags.is_empty() {
+        return scan;
+    }
+    filter_scan(scan, &tags, &load_config())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PackageRecord;
+    use chrono::Utc;
+
+    fn project(path: &str) -> ProjectRecord {
+        ProjectRecord { path: path.to_string(), manager: None, dependencies: Vec::new(), mtime: Utc::now() }
+    }
+
+    fn package(path: &str) -> PackageRecord {
+        PackageRecord::for_test(path, "1.0.0", 10)
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let under = TagRule { tag: "client".into(), under: Some("/work/clients".into()), remote: None };
+        assert!(under.matches(Path::new("/work/clients/acme/site"), None));
+        assert!(!under.matches(Path::new("/work/clients-old/site"), None));
+
+        let remote = TagRule { tag: "oss".into(), under: None, remote: Some("github.com/Acme".into()) };
+        let origin = split_url("git@github.com:acme/widgets.git");
+        assert!(remote.matches(Path::new("/src/widgets"), origin.as_ref()));
+        assert!(!remote.matches(Path::new("/src/widgets"), split_url("https://github.com/acme-labs/widgets").as_ref()));
+        let org_only = TagRule { remote: Some("acme".into()), ..remote };
+        assert!(org_only.matches(Path::new("/src/widgets"), origin.as_ref()));
+    }
+
+    #[test]
+    fn test_filter_scan_keeps_tagged_projects_and_their_packages() {
+        let scan = ScanOutput {
+            packages: vec![
+                package("/work/clients/site/node_modules/react"),
+                package("/store/shared"),
+                package("/play/toy/node_modules/left-pad"),
+                package("/home/.npm/lodash"),
+            ],
+            projects: vec![project("/work/clients/site"), project("/play/toy")],
+            edges: vec![
+                ("/work/clients/site".into(), "/store/shared".into()),
+                ("/play/toy".into(), "/play/toy/node_modules/left-pad".into()),
+            ],
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let mut config = TagsConfig {
+            rules: vec![TagRule { tag: "client-work".into(), under: Some("/work/clients".into()), remote: None }],
+            ..Default::default()
+        };
+        config.projects.insert("/play/toy".into(), ["experiments".to_string()].into());
+
+        let client = filter_scan(scan.clone(), &["client-work".to_string()], &config);
+        assert_eq!(client.projects.len(), 1);
+        let paths: Vec<&str> = client.packages.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, ["/work/clients/site/node_modules/react", "/store/shared"]);
+        assert_eq!(client.edges.len(), 1);
+
+        let both = filter_scan(scan, &["client-work".to_string(), "experiments".to_string()], &config);
+        assert_eq!((both.projects.len(), both.packages.len()), (2, 3));
+    }
+}
@@ -0,0 +1,122 @@
+//! Infrastructure Provider Caches
+//!
+//! Terraform downloads every provider version a configuration uses, into each
+//! working directory's `.terraform/providers` and the shared
+//! `~/.terraform.d/plugin-cache`, and the Serverless Framework keeps one
+//! release per version under `~/.serverless/releases`. These directories are
+//! enumerated as packages keyed by provider@version (`registry.terraform.io/
+//! hashicorp/aws@5.31.0`), and `.terraform.lock.hcl` files make their
+//! directories projects depending on the locked versions, so the regular
+//! orphan and age rules apply.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::types::PackageManager;
+
+/// Whether `path` is a provider cache directory the scanner should enumerate
+pub fn is_provider_cache_dir(path: &Path) -> bool {
+    let parent = path.parent().and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_string());
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string());
+    matches!(
+        (parent.as_deref(), name.as_deref()),
+        (Some(".terraform"), Some("providers"))
+            | (Some(".terraform.d"), Some("plugin-cache" | "plugins"))
+            | (Some(".serverless"), Some("releases"))
+    )
+}
+
+/// Whether `path` lies strictly inside a provider cache directory
+pub fn in_provider_cache(path: &Path) -> bool {
+    path.ancestors().skip(1).any(is_provider_cache_dir)
+}
+
+/// Provider versions in a cache directory: (version directory, name, version, manager)
+pub fn provider_packages(dir: &Path) -> Vec<(PathBuf, String, String, PackageManager)> {
+    if dir.parent().and_then(|p| p.file_name()).is_some_and(|n| n == ".serverless") {
+        return subdirs(dir).into_iter()
+            .filter_map(|release| {
+                let version = release.file_name()?.to_string_lossy().to_string();
+                Some((release, "serverless".to_string(), version, PackageManager::Serverless))
+            })
+            .collect();
+    }
+
+    // <host>/<namespace>/<type>/<version>/<os_arch>
+    let mut out = Vec::new();
+    for host in subdirs(dir) {
+        for namespace in subdirs(&host) {
+            for provider in subdirs(&namespace) {
+                for version in subdirs(&provider) {
+                    let name = [&host, &namespace, &provider]
+                        .iter()
+                        .map(|p| p.file_name().unwrap_or_default().to_string_lossy().to_string())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    let v = version.file_name().unwrap_or_default().to_string_lossy().to_string();
+                    out.push((version, name, v, PackageManager::Terraform));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Providers locked by a `.terraform.lock.hcl`, as (source address, version)
+pub fn parse_terraform_lock(path: &Path) -> Vec<(String, String)> {
+    let Ok(text) = fs::read_to_string(path) else { return Vec::new() };
+    let mut locked = Vec::new();
+    let mut current: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("provider ") {
+            current = rest.split('"').nth(1).map(str::to_string);
+        } else if let (Some(name), Some(rest)) = (&current, line.strip_prefix("version")) {
+            if let Some(version) = rest.trim_start().strip_prefix('=').map(|v| v.trim().trim_matches('"')) {
+                locked.push((name.clone(), version.to_string()));
+                current = None;
+            }
+        }
+    }
+    locked
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|rd| rd.filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| e.path())
+            .collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_provider_packages_and_lock() {
+        let temp = tempdir().unwrap();
+        let providers = temp.path().join("infra/.terraform/providers");
+        fs::create_dir_all(providers.join("registry.terraform.io/hashicorp/aws/5.31.0/linux_amd64")).unwrap();
+        fs::create_dir_all(providers.join("registry.terraform.io/hashicorp/aws/4.67.0/linux_amd64")).unwrap();
+        assert!(is_provider_cache_dir(&providers));
+        assert!(!is_provider_cache_dir(&temp.path().join("infra")));
+        assert!(in_provider_cache(&providers.join("registry.terraform.io")));
+        assert!(!in_provider_cache(&providers));
+
+        let mut found: Vec<String> = provider_packages(&providers).into_iter()
+            .map(|(_, n, v, _)| format!("{}@{}", n, v))
+            .collect();
+        found.sort();
+        assert_eq!(found, vec![
+            "registry.terraform.io/hashicorp/aws@4.67.0",
+            "registry.terraform.io/hashicorp/aws@5.31.0",
+        ]);
+
+        let lock = temp.path().join("infra/.terraform.lock.hcl");
+        fs::write(&lock, "provider \"registry.terraform.io/hashicorp/aws\" {\n  version     = \"5.31.0\"\n  constraints = \"~> 5.0\"\n  hashes = []\n}\n").unwrap();
+        assert_eq!(parse_terraform_lock(&lock), vec![("registry.terraform.io/hashicorp/aws".to_string(), "5.31.0".to_string())]);
+    }
+}
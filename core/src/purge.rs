@@ -0,0 +1,140 @@
+//! One-step Purge
+//!
+//! The tail of `purge`: once a plan's targets are accepted, ask before
+//! quarantining them (unless `--yes`) and summarize what was reclaimed.
+//! Prompting and quarantining are passed in, so the flow runs without a
+//! terminal or the real quarantine.
+
+use std::path::{Path, PathBuf};
+use crate::display::DisplayConfig;
+use crate::types::{PlanItem, QuarantineRecord};
+use crate::Result;
+
+/// How a purge ended
+#[derive(Debug)]
+pub enum PurgeOutcome {
+    /// No target was accepted; nothing was asked or quarantined
+    Nothing,
+    /// The confirmation was declined; nothing was quarantined
+    Declined,
+    /// `records` of the `accepted` targets were quarantined
+    Purged { accepted: usize, records: Vec<QuarantineRecord> },
+}
+
+impl PurgeOutcome {
+    /// The line printed when the purge ends
+    pub fn summary(&self, shown: &DisplayConfig) -> String {
+        match self {
+            Self::Nothing => "Nothing to purge".to_string(),
+            Self::Declined => "Nothing purged (pass --yes to purge without asking)".to_string(),
+            Self::Purged { accepted, records } => format!(
+                "Purged {} of {} items, reclaiming {}; `rollback` restores them until the quarantine expires",
+                records.len(), accepted, shown.size(records.iter().map(|r| r.size_bytes).sum()),
+            ),
+        }
+    }
+}
+
+/// Estimated bytes of the items among `items` whose target is `accepted`
+pub fn accepted_estimate(items: &[PlanItem], accepted: &[PathBuf]) -> u64 {
+    items.iter()
+        .filter(|i| accepted.iter().any(|t| t.as_path() == Path::new(&i.target_path)))
+        .map(|i| i.estimated_size_bytes)
+        .sum()
+}
+
+/// Quarantine `accepted` (targets of `items`) with `quarantine`, once
+/// `confirm` says yes to the question unless `yes`
+pub fn purge<E>(
+    items: &[PlanItem],
+    accepted: &[PathBuf],
+    yes: bool,
+    shown: &DisplayConfig,
+    confirm: impl FnOnce(&str) -> Result<bool, E>,
+    quarantine: impl FnOnce(&[PathBuf]) -> Result<Vec<QuarantineRecord>, E>,
+) -> Result<PurgeOutcome, E> {
+    if accepted.is_empty() {
+        return Ok(PurgeOutcome::Nothing);
+    }
+    let question = format!(
+        "Quarantine {} items (about {})?",
+        accepted.len(), shown.size(accepted_estimate(items, accepted)),
+    );
+    if !yes && !confirm(&question)? {
+        return Ok(PurgeOutcome::Declined);
+    }
+    let records = quarantine(accepted)?;
+    Ok(PurgeOutcome::Purged { accepted: accepted.len(), records })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PlanReason;
+    use std::cell::RefCell;
+
+    fn item(path: &str, bytes: u64) -> PlanItem {
+        PlanItem {
+            target_path: path.to_string(),
+            estimated_size_bytes: bytes,
+            reason: PlanReason::Orphaned,
+            blockers: Vec::new(),
+            risk: None,
+        }
+    }
+
+    fn plan() -> (Vec<PlanItem>, Vec<PathBuf>) {
+        let items = vec![item("/w/node_modules/a", 1024), item("/w/node_modules/b", 2048), item("/w/node_modules/c", 4096)];
+        // `c` was refused, so it is neither counted nor quarantined
+        let accepted = vec![PathBuf::from("/w/node_modules/a"), PathBuf::from("/w/node_modules/b")];
+        (items, accepted)
+    }
+
+    #[test]
+    fn test_declined_confirmation_quarantines_nothing() {
+        let (items, accepted) = plan();
+        let asked = RefCell::new(None);
+        let outcome = purge::<()>(&items, &accepted, false, &DisplayConfig::default(),
+            |q| { *asked.borrow_mut() = Some(q.to_string()); Ok(false) },
+            |_| panic!("declined purge quarantined"),
+        ).unwrap();
+
+        assert!(matches!(outcome, PurgeOutcome::Declined));
+        assert_eq!(asked.into_inner().unwrap(), "Quarantine 2 items (about 3.0 KiB)?");
+        assert_eq!(outcome.summary(&DisplayConfig::default()), "Nothing purged (pass --yes to purge without asking)");
+    }
+
+    #[test]
+    fn test_quarantine_outcome_and_summary() {
+        let (items, accepted) = plan();
+        let quarantined = RefCell::new(Vec::new());
+        // `b` fails to quarantine, so one of two is purged
+        let outcome = purge::<()>(&items, &accepted, true, &DisplayConfig::default(),
+            |_| panic!("--yes asked"),
+            |targets| {
+                quarantined.borrow_mut().extend_from_slice(targets);
+                Ok(vec![QuarantineRecord::for_test("q1", &targets[0], 1536)])
+            },
+        ).unwrap();
+
+        assert_eq!(quarantined.into_inner(), accepted);
+        let PurgeOutcome::Purged { accepted: count, records } = &outcome else { panic!("not purged: {:?}", outcome) };
+        assert_eq!((*count, records.len()), (2, 1));
+        assert_eq!(records[0].original_path, "/w/node_modules/a");
+        assert_eq!(
+            outcome.summary(&DisplayConfig::default()),
+            "Purged 1 of 2 items, reclaiming 1.5 KiB; `rollback` restores them until the quarantine expires",
+        );
+    }
+
+    #[test]
+    fn test_nothing_accepted_asks_nothing() {
+        let (items, _) = plan();
+        let outcome = purge::<()>(&items, &[], false, &DisplayConfig::default(),
+            |_| panic!("asked with nothing to purge"),
+            |_| panic!("quarantined with nothing to purge"),
+        ).unwrap();
+        assert!(matches!(outcome, PurgeOutcome::Nothing));
+        assert_eq!(outcome.summary(&DisplayConfig::default()), "Nothing to purge");
+    }
+}
@@ -0,0 +1,268 @@
+//! Python Environments and Caches
+//!
+//! Python projects leave regenerable trees next to their sources:
+//! virtualenvs (any directory holding a `pyvenv.cfg`, `.venv` usually),
+//! tox environments under `.tox/` and bytecode in `__pycache__/`. pip and
+//! Poetry keep a download cache each (`~/.cache/pip`, `~/.cache/pypoetry` or
+//! the platform's equivalent) with an HTTP cache, built wheels and, for
+//! Poetry, downloaded archives and its own virtualenvs.
+//!
+//! Environments, bytecode and HTTP caches are enumerated as packages named by
+//! their path with no version, and planned only once untouched for the
+//! preservation window. Cached wheels and sdists are enumerated by
+//! distribution name and version, read from the archive file name, and
+//! planned when no scanned `poetry.lock`, `Pipfile.lock` or
+//! `requirements.txt` pins them or they are old. Names are compared in
+//! their normalized form (PEP 503), so `Typing_Extensions` and
+//! `typing-extensions` are one distribution.
+
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::types::{PackageManager, PackageRecord, PlanReason};
+
+/// Files and subdirectories written when an environment is created or changed
+const ENV_STATE: [&str; 5] = ["pyvenv.cfg", "bin", "Scripts", "lib", "Lib"];
+
+/// Cache subdirectories enumerated as one unit each
+const HTTP_CACHES: [&str; 3] = ["http", "http-v2", "cache"];
+
+/// Lock and requirements files whose pins make up the used set
+pub const LOCK_FILES: [&str; 3] = ["poetry.lock", "Pipfile.lock", "requirements.txt"];
+
+/// Normalized distribution name: lowercase, runs of `-`, `_` and `.` as one `-`
+pub fn normalize_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '-' | '_' | '.') {
+            if !out.ends_with('-') {
+                out.push('-');
+            }
+        } else {
+            out.push(c.to_ascii_lowercase());
+        }
+    }
+    out
+}
+
+/// Whether `path` is a virtualenv, a `.tox` directory or a `__pycache__`
+pub fn is_python_env(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == "__pycache__" || n == ".tox") || path.join("pyvenv.cfg").is_file()
+}
+
+/// Whether `path` is the pip or Poetry cache (on Windows, the `Cache`
+/// directory inside `pip`/`pypoetry`)
+pub fn is_python_cache(path: &Path) -> bool {
+    let named = |tool: &str| {
+        path.file_name().is_some_and(|n| n == tool) || path.parent().and_then(|p| p.file_name()).is_some_and(|n| n == tool)
+    };
+    (named("pip") && ["http", "http-v2", "wheels"].iter().any(|d| path.join(d).is_dir()))
+        || (named("pypoetry") && ["artifacts", "cache", "virtualenvs"].iter().any(|d| path.join(d).is_dir()))
+}
+
+/// Whether the scanner should enumerate `path` as a Python environment or cache
+pub fn is_python_dir(path: &Path) -> bool {
+    is_python_env(path) || is_python_cache(path)
+}
+
+/// Whether `path` is, or lies inside, a Python environment or cache, so it
+/// can be recreated
+pub fn in_python_dir(path: &Path) -> bool {
+    is_python_env(path) || path.ancestors().skip(1).any(is_python_dir)
+}
+
+/// What kind of regenerable tree `path` is, for plan reasons
+pub fn artifact_kind(path: &Path) -> &'static str {
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some("__pycache__") => "__pycache__",
+        Some(".tox") => "tox",
+        _ if path.join("pyvenv.cfg").is_file() => "virtualenv",
+        _ => "http_cache",
+    }
+}
+
+/// Distribution name and version from a wheel or sdist file name
+/// (`typing_extensions-4.9.0-py3-none-any.whl`, `PyYAML-6.0.1.tar.gz`)
+pub fn split_distribution(file_name: &str) -> Option<(String, String)> {
+    let (name, version) = if let Some(stem) = file_name.strip_suffix(".whl") {
+        let mut parts = stem.split('-');
+        (parts.next()?, parts.next()?)
+    } else {
+        let stem = file_name.strip_suffix(".tar.gz").or_else(|| file_name.strip_suffix(".zip"))?;
+        stem.rsplit_once('-')?
+    };
+    let versioned = version.starts_with(|c: char| c.is_ascii_digit());
+    (versioned && !name.is_empty()).then(|| (normalize_name(name), version.to_string()))
+}
+
+/// Environments of a project tree or units of a pip/Poetry cache:
+/// (directory, name, version, manager)
+pub fn python_packages(dir: &Path) -> Vec<(PathBuf, String, String, PackageManager)> {
+    let unit = |path: PathBuf| {
+        let name = path.to_string_lossy().to_string();
+        (path, name, String::new(), PackageManager::Python)
+    };
+    if is_python_env(dir) {
+        return vec![unit(dir.to_path_buf())];
+    }
+
+    let mut packages: Vec<_> = HTTP_CACHES.iter()
+        .map(|d| dir.join(d))
+        .filter(|d| d.is_dir())
+        .map(unit)
+        .collect();
+    packages.extend(subdirs(&dir.join("virtualenvs")).into_iter().filter(|v| v.join("pyvenv.cfg").is_file()).map(unit));
+    for archives in ["wheels", "artifacts"].iter().map(|d| dir.join(d)).filter(|d| d.is_dir()) {
+        // <cache>/wheels/ab/cd/ef/<hash>/<dist>.whl: the hash directory is the package
+        let mut found: Vec<(PathBuf, String, String)> = walkdir::WalkDir::new(&archives)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                let (name, version) = split_distribution(&e.file_name().to_string_lossy())?;
+                Some((e.path().parent()?.to_path_buf(), name, version))
+            })
+            .collect();
+        found.sort();
+        found.dedup_by(|a, b| a.0 == b.0);
+        packages.extend(found.into_iter().map(|(path, name, version)| (path, name, version, PackageManager::Python)));
+    }
+    packages
+}
+
+/// Last time `path` or the environment state below it was written to
+pub fn last_modified(path: &Path) -> Option<DateTime<Utc>> {
+    std::iter::once(path.to_path_buf())
+        .chain(ENV_STATE.iter().map(|d| path.join(d)))
+        .filter_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .max()
+        .map(DateTime::<Utc>::from)
+}
+
+/// Distributions pinned by a `poetry.lock`, `Pipfile.lock` or
+/// `requirements.txt`, as (normalized name, version); unpinned requirements
+/// are left out
+pub fn parse_python_lock(path: &Path) -> Vec<(String, String)> {
+    let Ok(text) = fs::read_to_string(path) else { return Vec::new() };
+    match path.file_name().and_then(|n| n.to_str()) {
+        Some("poetry.lock") => text.split("[[package]]").skip(1)
+            .filter_map(|block| {
+                let field = |key: &str| {
+                    block.lines()
+                        .filter_map(|l| l.trim().strip_prefix(key))
+                        .find_map(|rest| rest.trim_start().strip_prefix('='))
+                        .map(|v| v.trim().trim_matches('"').to_string())
+                };
+                Some((normalize_name(&field("name")?), field("version")?))
+            })
+            .collect(),
+        Some("Pipfile.lock") => {
+            let json: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+            ["default", "develop"].iter()
+                .filter_map(|section| json.get(section).and_then(|s| s.as_object()))
+                .flat_map(|deps| deps.iter())
+                .filter_map(|(name, spec)| {
+                    let version = spec.get("version")?.as_str()?.strip_prefix("==")?;
+                    Some((normalize_name(name), version.to_string()))
+                })
+                .collect()
+        }
+        _ => text.lines()
+            .filter_map(|line| {
+                let requirement = line.split(['#', ';']).next()?.trim();
+                let (name, version) = requirement.split_once("==")?;
+                let name = name.split('[').next()?.trim();
+                let version = version.split_whitespace().next()?;
+                (!name.is_empty() && !name.starts_with('-')).then(|| (normalize_name(name), version.to_string()))
+            })
+            .collect(),
+    }
+}
+
+/// Why `pkg` (a Python package) is planned, if it is: environments and
+/// HTTP caches only once untouched since `cutoff`, cached distributions when
+/// no project pins them (`locked` false) or they are older than `cutoff`
+pub fn plan_reason(pkg: &PackageRecord, locked: bool, cutoff: DateTime<Utc>) -> Option<PlanReason> {
+    let days = (Utc::now() - pkg.mtime).num_days();
+    let stale = pkg.mtime < cutoff;
+    if pkg.version.is_empty() {
+        let kind = artifact_kind(Path::new(&pkg.path)).to_string();
+        stale.then_some(PlanReason::StalePythonArtifact { kind, days })
+    } else {
+        (!locked || stale).then_some(PlanReason::CachedDistribution { locked, days })
+    }
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|rd| rd.filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| e.path())
+            .collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_python_packages_and_plan() {
+        assert_eq!(split_distribution("typing_extensions-4.9.0-py3-none-any.whl"), Some(("typing-extensions".into(), "4.9.0".into())));
+        assert_eq!(split_distribution("PyYAML-6.0.1.tar.gz"), Some(("pyyaml".into(), "6.0.1".into())));
+        assert_eq!(split_distribution("selfcheck.json"), None);
+
+        let temp = tempdir().unwrap();
+        let app = temp.path().join("app");
+        fs::create_dir_all(app.join(".venv/bin")).unwrap();
+        fs::write(app.join(".venv/pyvenv.cfg"), "home = /usr/bin\nversion = 3.11.4\n").unwrap();
+        fs::create_dir_all(app.join(".tox/py311")).unwrap();
+        fs::create_dir_all(app.join("src/__pycache__")).unwrap();
+        for dir in [".venv", ".tox", "src/__pycache__"] {
+            assert!(is_python_dir(&app.join(dir)), "{}", dir);
+        }
+        assert_eq!(artifact_kind(&app.join(".venv")), "virtualenv");
+        assert!(!is_python_dir(&app.join("src")));
+
+        let pip = temp.path().join(".cache/pip");
+        let wheel_dir = pip.join("wheels/3f/a1/9c/0d2e");
+        fs::create_dir_all(&wheel_dir).unwrap();
+        fs::write(wheel_dir.join("Typing_Extensions-4.9.0-py3-none-any.whl"), "whl").unwrap();
+        fs::create_dir_all(pip.join("http-v2/a/b")).unwrap();
+        assert!(is_python_cache(&pip));
+        assert!(in_python_dir(&wheel_dir) && !in_python_dir(&app));
+        let mut units: Vec<(String, String)> = python_packages(&pip).into_iter().map(|(_, n, v, _)| (n, v)).collect();
+        units.sort();
+        assert_eq!(units, [
+            (pip.join("http-v2").to_string_lossy().to_string(), String::new()),
+            ("typing-extensions".to_string(), "4.9.0".to_string()),
+        ]);
+
+        fs::write(app.join("requirements.txt"), "# pinned\nrequests[socks]==2.31.0 ; python_version >= '3.8'\nflask>=2\n-r dev.txt\n").unwrap();
+        fs::write(app.join("Pipfile.lock"), r#"{"default": {"Typing_Extensions": {"version": "==4.9.0"}}, "develop": {}}"#).unwrap();
+        fs::write(app.join("poetry.lock"), "[[package]]\nname = \"PyYAML\"\nversion = \"6.0.1\"\n").unwrap();
+        assert_eq!(parse_python_lock(&app.join("requirements.txt")), [("requests".to_string(), "2.31.0".to_string())]);
+        assert_eq!(parse_python_lock(&app.join("Pipfile.lock")), [("typing-extensions".to_string(), "4.9.0".to_string())]);
+        assert_eq!(parse_python_lock(&app.join("poetry.lock")), [("pyyaml".to_string(), "6.0.1".to_string())]);
+
+        let package = |path: PathBuf, version: &str, days: i64| PackageRecord {
+            mtime: Utc::now() - Duration::days(days),
+            manager: Some(PackageManager::Python),
+            ..PackageRecord::for_test(path, version, 0)
+        };
+        let cutoff = Utc::now() - Duration::days(30);
+        assert_eq!(plan_reason(&package(app.join(".venv"), "", 2), false, cutoff), None);
+        assert_eq!(
+            plan_reason(&package(app.join(".venv"), "", 45), false, cutoff),
+            Some(PlanReason::StalePythonArtifact { kind: "virtualenv".into(), days: 45 })
+        );
+        assert_eq!(plan_reason(&package(wheel_dir.clone(), "4.9.0", 2), true, cutoff), None);
+        assert_eq!(
+            plan_reason(&package(wheel_dir, "4.9.0", 2), false, cutoff),
+            Some(PlanReason::CachedDistribution { locked: false, days: 2 })
+        );
+    }
+}
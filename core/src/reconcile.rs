@@ -0,0 +1,92 @@
+//! Apply Reconciliation
+//!
+//! A plan's `total_estimated_bytes` comes from scan-time sizes, which can be
+//! stale or miss parts of a tree. After a plan is applied, every quarantined
+//! item is compared against its estimate: the bytes it held when it was
+//! moved, less whatever sits at its path again afterwards, are what applying
+//! it actually reclaimed. Runs are recorded in the feature store so `stats`
+//! can show how far estimates are off over time.
+//!
+//! Only items whose estimate is their size take part; duplicates and store
+//! entries are estimated at 0 by design (see `PlanReason::counts_size`).
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::native_walk::tree_totals;
+use crate::types::{ApplyReconciliation, ItemSavings, PlanItem, QuarantineRecord};
+
+/// Items listed in `largest_misses`
+const MISSES_SHOWN: usize = 5;
+
+/// Compare the applied `items` with the quarantine `records` made for them.
+/// Items without a record were not applied and are left out.
+pub fn reconcile(items: &[PlanItem], records: &[QuarantineRecord]) -> ApplyReconciliation {
+    let moved: HashMap<&str, u64> = records.iter().map(|r| (r.original_path.as_str(), r.size_bytes)).collect();
+    let mut outcomes: Vec<ItemSavings> = items.iter()
+        .filter(|item| item.reason.counts_size())
+        .filter_map(|item| {
+            let moved_bytes = *moved.get(item.target_path.as_str())?;
+            let path = Path::new(&item.target_path);
+            let remaining = if path.exists() { tree_totals(path, None).bytes } else { 0 };
+            Some(ItemSavings {
+                target_path: item.target_path.clone(),
+                estimated_bytes: item.estimated_size_bytes,
+                actual_bytes: moved_bytes.saturating_sub(remaining),
+            })
+        })
+        .collect();
+
+    let estimated_bytes = outcomes.iter().map(|o| o.estimated_bytes).sum();
+    let actual_bytes = outcomes.iter().map(|o| o.actual_bytes).sum();
+    let items = outcomes.len();
+    outcomes.sort_by_key(|o| std::cmp::Reverse(o.estimated_bytes.abs_diff(o.actual_bytes)));
+    outcomes.retain(|o| o.estimated_bytes != o.actual_bytes);
+    outcomes.truncate(MISSES_SHOWN);
+    ApplyReconciliation {
+        items,
+        estimated_bytes,
+        actual_bytes,
+        accuracy: (estimated_bytes > 0).then(|| actual_bytes as f64 / estimated_bytes as f64),
+        largest_misses: outcomes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PlanReason;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_reconcile_against_moved_and_remaining_bytes() {
+        let temp = tempdir().unwrap();
+        let path = |name: &str| temp.path().join(name).to_string_lossy().to_string();
+        let item = |name: &str, estimate: u64, reason: PlanReason| PlanItem {
+            target_path: path(name),
+            estimated_size_bytes: estimate,
+            reason,
+            blockers: Vec::new(),
+            risk: None,
+        };
+        let record = |name: &str, size: u64| QuarantineRecord::for_test(name, path(name), size);
+        // Something recreated `b` after it was moved
+        fs::create_dir_all(temp.path().join("b")).unwrap();
+        fs::write(temp.path().join("b/index.js"), vec![b'x'; 100]).unwrap();
+
+        let items = vec![
+            item("a", 1000, PlanReason::Orphaned),
+            item("b", 500, PlanReason::Old { days: 100 }),
+            item("dup", 0, PlanReason::Duplicate { canonical: String::new() }),
+            item("failed", 700, PlanReason::Orphaned),
+        ];
+        let records = vec![record("a", 1000), record("b", 800), record("dup", 300)];
+        let result = reconcile(&items, &records);
+        assert_eq!(result.items, 2);
+        assert_eq!(result.estimated_bytes, 1500);
+        assert_eq!(result.actual_bytes, 1700);
+        assert_eq!(result.largest_misses.len(), 1);
+        assert_eq!(result.largest_misses[0].actual_bytes, 700);
+    }
+}
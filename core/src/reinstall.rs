@@ -0,0 +1,259 @@
+//! Reinstall on Demand
+//!
+//! When a project's `node_modules` is purged with `--reinstall-on-demand`, a
+//! marker file is left in the project recording which package manager and
+//! install command bring it back. `restore-deps` later runs that command
+//! (`npm ci`, `pnpm install --frozen-lockfile`, ...) and removes the marker,
+//! so an inactive project that becomes active again is one command away from
+//! working. Rolling the quarantine back also clears the marker.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::error::Error;
+use crate::safety::ensure_writable;
+use crate::types::QuarantineRecord;
+
+/// Marker file left in the project root
+pub const MARKER: &str = ".packagepurge-reinstall.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+    Bun,
+}
+
+impl PackageManager {
+    /// Package manager and lockfile of `project`, judged by its lockfile;
+    /// npm when there is none
+    pub fn detect(project: &Path) -> (Self, Option<&'static str>) {
+        const LOCKFILES: &[(&str, PackageManager)] = &[
+            ("pnpm-lock.yaml", PackageManager::Pnpm),
+            ("yarn.lock", PackageManager::Yarn),
+            ("bun.lock", PackageManager::Bun),
+            ("bun.lockb", PackageManager::Bun),
+            ("package-lock.json", PackageManager::Npm),
+            ("npm-shrinkwrap.json", PackageManager::Npm),
+        ];
+        LOCKFILES.iter()
+            .find(|(file, _)| project.join(file).is_file())
+            .map_or((Self::Npm, None), |(file, pm)| (*pm, Some(*file)))
+    }
+
+    /// Command that reinstalls exactly what the lockfile pins
+    pub fn install_command(self, project: &Path, has_lockfile: bool) -> Vec<String> {
+        let args: &[&str] = match (self, has_lockfile) {
+            (Self::Npm, true) => &["npm", "ci"],
+            (Self::Npm, false) => &["npm", "install"],
+            (Self::Pnpm, _) => &["pnpm", "install", "--frozen-lockfile"],
+            // Yarn 2+ (configured through .yarnrc.yml) renamed the flag
+            (Self::Yarn, _) if project.join(".yarnrc.yml").is_file() => &["yarn", "install", "--immutable"],
+            (Self::Yarn, _) => &["yarn", "install", "--frozen-lockfile"],
+            (Self::Bun, _) => &["bun", "install", "--frozen-lockfile"],
+        };
+        args.iter().map(|a| a.to_string()).collect()
+    }
+}
+
+impl fmt::Display for PackageManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Npm => "npm",
+            Self::Pnpm => "pnpm",
+            Self::Yarn => "yarn",
+            Self::Bun => "bun",
+        })
+    }
+}
+
+/// Contents of the marker file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReinstallMarker {
+    pub purged_at: DateTime<Utc>,
+    pub package_manager: PackageManager,
+    pub lockfile: Option<String>,
+    pub command: Vec<String>,
+    /// Quarantine records of the purged paths
+    #[serde(default)]
+    pub quarantine_ids: Vec<String>,
+}
+
+/// What `restore_deps` did or would do
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreOutcome {
+    pub project: PathBuf,
+    pub command: Vec<String>,
+    /// Whether a marker was found; without one the command is detected
+    pub had_marker: bool,
+    pub ran: bool,
+}
+
+/// Project whose `node_modules` contains `path`, if it has a package.json
+pub fn owning_project(path: &Path) -> Option<PathBuf> {
+    let mut project = PathBuf::new();
+    for component in path.components() {
+        if component == Component::Normal("node_modules".as_ref()) {
+            return project.join("package.json").is_file().then_some(project);
+        }
+        project.push(component);
+    }
+    None
+}
+
+pub fn read_marker(project: &Path) -> Option<ReinstallMarker> {
+    let text = fs::read_to_string(project.join(MARKER)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Leave a marker in every project that lost (part of) its `node_modules`
+/// to `records`; returns the projects marked
+pub fn mark_purged(records: &[QuarantineRecord]) -> crate::Result<Vec<PathBuf>> {
+    mark_purged_impl(records).map_err(Error::lift(Error::Install))
+}
+
+fn mark_purged_impl(records: &[QuarantineRecord]) -> Result<Vec<PathBuf>> {
+    let mut by_project: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    for rec in records {
+        if let Some(project) = owning_project(Path::new(&rec.original_path)) {
+            by_project.entry(project).or_default().push(rec.id.clone());
+        }
+    }
+    for (project, ids) in &by_project {
+        let mut marker = read_marker(project).unwrap_or_else(|| {
+            let (pm, lockfile) = PackageManager::detect(project);
+            ReinstallMarker {
+                purged_at: Utc::now(),
+                package_manager: pm,
+                lockfile: lockfile.map(String::from),
+                command: pm.install_command(project, lockfile.is_some()),
+                quarantine_ids: Vec::new(),
+            }
+        });
+        marker.purged_at = Utc::now();
+        marker.quarantine_ids.extend(ids.iter().cloned());
+        write_marker(project, &marker)?;
+    }
+    Ok(by_project.into_keys().collect())
+}
+
+fn write_marker(project: &Path, marker: &ReinstallMarker) -> Result<()> {
+    let path = project.join(MARKER);
+    fs::write(&path, serde_json::to_string_pretty(marker)?).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Drop `rec` from its project's marker after a rollback put it back; the
+/// marker goes away once none of its records are still quarantined
+pub fn forget_record(rec: &QuarantineRecord) {
+    let Some(project) = owning_project(Path::new(&rec.original_path)) else { return };
+    let Some(mut marker) = read_marker(&project) else { return };
+    marker.quarantine_ids.retain(|id| *id != rec.id);
+    let _ = if marker.quarantine_ids.is_empty() {
+        fs::remove_file(project.join(MARKER)).map_err(anyhow::Error::from)
+    } else {
+        write_marker(&project, &marker)
+    };
+}
+
+/// Reinstall `project`'s dependencies with the command from its marker (or
+/// the detected one) and remove the marker on success. The installer's
+/// output goes to stderr.
+pub fn restore_deps(project: &Path, dry_run: bool) -> crate::Result<RestoreOutcome> {
+    restore_deps_impl(project, dry_run).map_err(Error::lift(Error::Install))
+}
+
+fn restore_deps_impl(project: &Path, dry_run: bool) -> Result<RestoreOutcome> {
+    anyhow::ensure!(project.join("package.json").is_file(), "{:?} has no package.json", project);
+    let marker = read_marker(project);
+    let command = match &marker {
+        Some(m) if !m.command.is_empty() => m.command.clone(),
+        _ => {
+            let (pm, lockfile) = PackageManager::detect(project);
+            pm.install_command(project, lockfile.is_some())
+        }
+    };
+    let mut outcome = RestoreOutcome { project: project.to_path_buf(), command, had_marker: marker.is_some(), ran: false };
+    if dry_run {
+        return Ok(outcome);
+    }
+
+    ensure_writable("reinstall dependencies")?;
+    let status = Command::new(&outcome.command[0])
+        .args(&outcome.command[1..])
+        .current_dir(project)
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(std::io::stderr()))
+        .status()
+        .with_context(|| format!("Failed to run `{}`", outcome.command.join(" ")))?;
+    anyhow::ensure!(status.success(), "`{}` exited with {}", outcome.command.join(" "), status);
+    if outcome.had_marker {
+        fs::remove_file(project.join(MARKER))?;
+    }
+    outcome.ran = true;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn record(id: &str, path: &Path) -> QuarantineRecord {
+        QuarantineRecord::for_test(id, path, 0)
+    }
+
+    #[test]
+    fn test_marker_lifecycle() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("app");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("package.json"), "{}").unwrap();
+        fs::write(project.join("pnpm-lock.yaml"), "lockfileVersion: '9.0'\n").unwrap();
+
+        assert_eq!(owning_project(&project.join("node_modules/lodash")), Some(project.clone()));
+        assert_eq!(owning_project(&temp.path().join("node_modules")), None);
+
+        let a = record("a", &project.join("node_modules"));
+        let b = record("b", &project.join("node_modules/.cache"));
+        assert_eq!(mark_purged(&[a.clone(), b.clone()]).unwrap(), vec![project.clone()]);
+        let marker = read_marker(&project).unwrap();
+        assert_eq!(marker.package_manager, PackageManager::Pnpm);
+        assert_eq!(marker.command, ["pnpm", "install", "--frozen-lockfile"]);
+
+        let planned = restore_deps(&project, true).unwrap();
+        assert!(planned.had_marker && !planned.ran);
+
+        forget_record(&a);
+        assert_eq!(read_marker(&project).unwrap().quarantine_ids, ["b"]);
+        forget_record(&b);
+        assert!(read_marker(&project).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_runs_marker_command() {
+        let temp = tempdir().unwrap();
+        fs::write(temp.path().join("package.json"), "{}").unwrap();
+        let marker = ReinstallMarker {
+            purged_at: Utc::now(),
+            package_manager: PackageManager::Npm,
+            lockfile: None,
+            command: vec!["mkdir".into(), "node_modules".into()],
+            quarantine_ids: vec!["a".into()],
+        };
+        write_marker(temp.path(), &marker).unwrap();
+
+        let outcome = restore_deps(temp.path(), false).unwrap();
+        assert!(outcome.ran);
+        assert!(temp.path().join("node_modules").is_dir());
+        assert!(read_marker(temp.path()).is_none());
+    }
+}
@@ -0,0 +1,212 @@
+//! Global Store Relocation
+//!
+//! Moves the global store to another location (typically another disk),
+//! rewrites every project symlink pointing into the old store and verifies the
+//! rewritten links before the old store is removed. Links come from the store
+//! reference index or from walking the given roots.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::error::Error;
+use crate::progress::{OperationContext, Phase};
+use crate::safety::protected_dirs;
+use crate::store_index::StoreIndex;
+use crate::symlink::{create_symlink_impl, get_global_store_path, write_store_location};
+
+/// Outcome of a store relocation
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StoreMoveReport {
+    pub old_path: String,
+    pub new_path: String,
+    pub links_rewritten: usize,
+    /// Links that do not resolve into the new store after rewriting
+    pub broken_links: Vec<String>,
+    /// Whether the old store was removed (only when every link verified)
+    pub old_store_removed: bool,
+}
+
+/// Find symlinks below `roots` whose target lies inside `store`
+pub fn find_store_links(roots: &[PathBuf], store: &Path, ctx: &OperationContext) -> crate::Result<Vec<PathBuf>> {
+    find_store_links_impl(roots, store, ctx).map_err(Error::lift(Error::Store))
+}
+
+fn find_store_links_impl(roots: &[PathBuf], store: &Path, ctx: &OperationContext) -> Result<Vec<PathBuf>> {
+    let protected = protected_dirs();
+    let mut links = Vec::new();
+    let mut visited: u64 = 0;
+    for root in roots {
+        let walker = WalkDir::new(root)
+            .into_iter()
+            .filter_entry(|e| !protected.iter().any(|p| e.path().starts_with(p)));
+        for entry in walker.filter_map(|e| e.ok()) {
+            ctx.check()?;
+            visited += 1;
+            if visited.is_multiple_of(256) {
+                ctx.report(Phase::Walk, visited, None, Some(entry.path()));
+            }
+            if !entry.path_is_symlink() {
+                continue;
+            }
+            if let Ok(target) = fs::read_link(entry.path()) {
+                if target.starts_with(store) {
+                    links.push(entry.into_path());
+                }
+            }
+        }
+    }
+    Ok(links)
+}
+
+/// Move the global store to `new_path`, retarget the links pointing into it
+/// and verify them. Links are taken from the store reference index, or found
+/// by walking `roots` when any are given (the index is only as complete as the
+/// symlinking runs and scans that fed it). The old store is only deleted when
+/// every link resolves into the new store; otherwise it is left in place.
+pub fn relocate_store(new_path: &Path, roots: &[PathBuf], ctx: &OperationContext) -> crate::Result<StoreMoveReport> {
+    relocate_store_impl(new_path, roots, ctx).map_err(Error::lift(Error::Store))
+}
+
+fn relocate_store_impl(new_path: &Path, roots: &[PathBuf], ctx: &OperationContext) -> Result<StoreMoveReport> {
+    crate::safety::ensure_writable("relocate the global store")?;
+    let old = get_global_store_path()?;
+    let new = std::path::absolute(new_path)?;
+    validate_destination(&old, &new)?;
+
+    // Discover links before touching anything, so a cancelled run changes nothing
+    let index = StoreIndex::open_default()?;
+    let links = if roots.is_empty() {
+        index.prune_dangling()?;
+        index.all()?.into_iter()
+            .filter(|r| Path::new(&r.entry).starts_with(&old))
+            .map(|r| PathBuf::from(r.link))
+            .collect()
+    } else {
+        find_store_links_impl(roots, &old, ctx)?
+    };
+
+    let report = move_store(&old, &new, &links, ctx, write_store_location)?;
+    index.rewrite_store_prefix(&old, &new)?;
+    Ok(report)
+}
+
+fn validate_destination(old: &Path, new: &Path) -> Result<()> {
+    if new.starts_with(old) || old.starts_with(new) {
+        anyhow::bail!("New store location {:?} overlaps the current store {:?}", new, old);
+    }
+    if new.exists() && fs::read_dir(new)?.next().is_some() {
+        anyhow::bail!("New store location {:?} is not empty", new);
+    }
+    Ok(())
+}
+
+/// Copy the store from `old` to `new` and retarget `links`; `persist` records
+/// the new location once the contents are in place
+fn move_store(
+    old: &Path,
+    new: &Path,
+    links: &[PathBuf],
+    ctx: &OperationContext,
+    persist: impl FnOnce(&Path) -> Result<()>,
+) -> Result<StoreMoveReport> {
+    let (old, new) = (old.to_path_buf(), new.to_path_buf());
+    validate_destination(&old, &new)?;
+
+    fs::create_dir_all(&new).with_context(|| format!("Failed to create {:?}", new))?;
+    if old.exists() {
+        let opts = fs_extra::dir::CopyOptions::new().content_only(true);
+        fs_extra::dir::copy(&old, &new, &opts)
+            .map_err(|e| anyhow::anyhow!("Failed to copy store {:?} to {:?}: {}", old, new, e))?;
+    }
+    persist(&new)?;
+
+    let mut report = StoreMoveReport {
+        old_path: old.to_string_lossy().to_string(),
+        new_path: new.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    let total = links.len() as u64;
+    for (i, link) in links.iter().enumerate() {
+        // Not cancellable: the store has already moved, every link must follow
+        ctx.report(Phase::Symlink, i as u64 + 1, Some(total), Some(link));
+        match retarget_link(link, &old, &new) {
+            Ok(()) => report.links_rewritten += 1,
+            Err(e) => eprintln!("Failed to rewrite {:?}: {:#}", link, e),
+        }
+    }
+
+    report.broken_links = links.iter()
+        .filter(|l| !link_resolves_into(l, &new))
+        .map(|l| l.to_string_lossy().to_string())
+        .collect();
+
+    if report.broken_links.is_empty() && old.exists() {
+        fs::remove_dir_all(&old).with_context(|| format!("Failed to remove old store {:?}", old))?;
+        report.old_store_removed = true;
+    }
+    Ok(report)
+}
+
+/// Point `link` at the same entry below `new` that it pointed at below `old`
+fn retarget_link(link: &Path, old: &Path, new: &Path) -> Result<()> {
+    let target = fs::read_link(link)?;
+    let rel = target.strip_prefix(old).context("Link does not point into the old store")?;
+    let temp = link.with_extension("packagepurge.relink");
+    create_symlink_impl(&temp, &new.join(rel))?;
+    #[cfg(windows)]
+    crate::symlink::remove_symlink(link)?;
+    fs::rename(&temp, link).with_context(|| format!("Failed to replace symlink {:?}", link))?;
+    Ok(())
+}
+
+fn link_resolves_into(link: &Path, store: &Path) -> bool {
+    fs::read_link(link).map(|t| t.starts_with(store) && t.is_dir()).unwrap_or(false)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_move_store_rewrites_links() {
+        let temp = tempdir().unwrap();
+        let old = temp.path().join("old_store");
+        let new = temp.path().join("disk2/store");
+        let entry = old.join("a/1.0.0/abcd");
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(entry.join("package.json"), "{}").unwrap();
+
+        let project = temp.path().join("app/node_modules");
+        fs::create_dir_all(&project).unwrap();
+        std::os::unix::fs::symlink(&entry, project.join("a")).unwrap();
+
+        let ctx = OperationContext::default();
+        let links = find_store_links_impl(&[temp.path().join("app")], &old, &ctx).unwrap();
+        let mut persisted = None;
+        let report = move_store(&old, &new, &links, &ctx, |p| {
+            persisted = Some(p.to_path_buf());
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(report.links_rewritten, 1);
+        assert!(report.broken_links.is_empty());
+        assert!(report.old_store_removed);
+        assert!(!old.exists());
+        assert_eq!(persisted, Some(new.clone()));
+        assert_eq!(fs::read_link(project.join("a")).unwrap(), new.join("a/1.0.0/abcd"));
+        assert!(project.join("a/package.json").exists());
+    }
+
+    #[test]
+    fn test_move_store_rejects_nested_target() {
+        let temp = tempdir().unwrap();
+        let old = temp.path().join("store");
+        fs::create_dir_all(&old).unwrap();
+        assert!(validate_destination(&old, &old.join("inner")).is_err());
+        assert!(validate_destination(&old, &temp.path().join("elsewhere")).is_ok());
+    }
+}
@@ -0,0 +1,332 @@
+//! Repository Activity
+//!
+//! Local modification times say little about a project cloned long ago that
+//! is still developed elsewhere. With `--remote-activity`, `optimize` asks the
+//! forge behind each project's `origin` remote (GitHub, GitHub Enterprise or
+//! GitLab) for the last push, the open pull/merge requests and whether the
+//! repository is archived:
+//!
+//! - a recent push or an open pull request marks the project active, so its
+//!   packages are not evicted for age
+//! - an archived repository, or one without a push within the preservation
+//!   window, marks it dormant once its local files are old too
+//!
+//! Tokens are read from `GITHUB_TOKEN` (or `GH_TOKEN`) and `GITLAB_TOKEN`.
+//! Answers are cached for a day in `repo_activity.json` in the cache
+//! directory. A failed lookup leaves the project to the local signals.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::types::ProjectRecord;
+
+/// How long a fetched answer is reused
+const CACHE_TTL_HOURS: i64 = 24;
+const TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Forge {
+    GitHub,
+    GitLab,
+}
+
+/// Forge repository behind a git remote URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub forge: Forge,
+    pub host: String,
+    /// `owner/repo`, or a GitLab `group/subgroup/repo` path
+    pub path: String,
+}
+
+impl Remote {
+    /// Parse `https://host/owner/repo(.git)`, `ssh://git@host/owner/repo` or
+    /// `git@host:owner/repo`; hosts are recognised by name
+    pub fn parse(url: &str) -> Option<Self> {
+        let (host, path) = split_url(url)?;
+        let forge = if host.contains("github") {
+            Forge::GitHub
+        } else if host.contains("gitlab") {
+            Forge::GitLab
+        } else {
+            return None;
+        };
+        Some(Self { forge, host, path })
+    }
+
+    /// Stable key for the cache and reports
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.host, self.path)
+    }
+}
+
+/// Lowercased host and `owner/repo` path of any git remote URL, in the
+/// forms `Remote::parse` takes
+pub fn split_url(url: &str) -> Option<(String, String)> {
+    let url = url.trim();
+    let rest = if let Some((_, rest)) = url.split_once("://") {
+        rest.to_string()
+    } else {
+        // scp-like syntax
+        let (host, path) = url.split_once(':')?;
+        format!("{}/{}", host, path)
+    };
+    let rest = rest.rsplit_once('@').map_or(rest.as_str(), |(_, r)| r);
+    let (host, path) = rest.split_once('/')?;
+    let host = host.split(':').next()?.to_lowercase();
+    let path = path.trim_matches('/').trim_end_matches(".git").to_string();
+    if path.split('/').filter(|s| !s.is_empty()).count() < 2 {
+        return None;
+    }
+    Some((host, path))
+}
+
+/// What the forge reports about a repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoActivity {
+    pub remote: String,
+    pub last_push: Option<DateTime<Utc>>,
+    /// Open pull/merge requests (at most 100 are counted)
+    pub open_pull_requests: Option<u64>,
+    pub archived: bool,
+    pub fetched_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    Active,
+    Dormant,
+    Unknown,
+}
+
+impl RepoActivity {
+    /// Verdict for a preservation window of `preserve_days`
+    pub fn liveness(&self, now: DateTime<Utc>, preserve_days: i64) -> Liveness {
+        if self.archived {
+            return Liveness::Dormant;
+        }
+        if self.open_pull_requests.is_some_and(|n| n > 0) {
+            return Liveness::Active;
+        }
+        match self.last_push {
+            Some(t) if now - t < Duration::days(preserve_days) => Liveness::Active,
+            Some(_) => Liveness::Dormant,
+            None => Liveness::Unknown,
+        }
+    }
+}
+
+/// The `.git` directory of the repository containing `project`, following
+/// `gitdir:` files of worktrees and submodules
+fn git_dir(project: &Path) -> Option<PathBuf> {
+    for dir in project.ancestors() {
+        let dot_git = dir.join(".git");
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+        if let Ok(text) = fs::read_to_string(&dot_git) {
+            let target = text.trim().strip_prefix("gitdir:")?.trim();
+            return Some(dir.join(target));
+        }
+    }
+    None
+}
+
+/// URL of the `origin` remote of the repository containing `project`
+pub fn origin_url(project: &Path) -> Option<String> {
+    let git = git_dir(project)?;
+    // Worktrees keep their config in the main repository
+    let config = fs::read_to_string(git.join("config"))
+        .or_else(|_| fs::read_to_string(git.join("commondir")).and_then(|c| fs::read_to_string(git.join(c.trim()).join("config"))))
+        .ok()?;
+    let mut in_origin = false;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_origin = line == "[remote \"origin\"]";
+        } else if in_origin {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "url" {
+                    return Some(value.trim().to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Time of the last commit or checkout in the local clone, from the HEAD reflog
+pub fn local_last_commit(project: &Path) -> Option<DateTime<Utc>> {
+    let log = fs::read_to_string(git_dir(project)?.join("logs").join("HEAD")).ok()?;
+    // `<old> <new> Name <email> <unix-time> <tz>\t<message>`
+    let entry = log.lines().last()?.split('\t').next()?;
+    let secs: i64 = entry.rsplit(' ').nth(1)?.parse().ok()?;
+    DateTime::from_timestamp(secs, 0)
+}
+
+/// Whether the work tree of the repository containing `project` has
+/// uncommitted changes; `None` outside a repository or without `git`
+pub fn has_uncommitted_changes(project: &Path) -> Option<bool> {
+    git_dir(project)?;
+    let output = std::process::Command::new("git")
+        .arg("-C").arg(project)
+        .args(["status", "--porcelain", "--untracked-files=normal"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    Some(!output.stdout.is_empty())
+}
+
+fn get_json(url: &str, auth: Option<(&str, String)>) -> Result<serde_json::Value> {
+    let mut req = ureq::get(url)
+        .timeout(std::time::Duration::from_secs(TIMEOUT_SECS))
+        .set("User-Agent", "packagepurge")
+        .set("Accept", "application/json");
+    if let Some((header, value)) = &auth {
+        req = req.set(header, value);
+    }
+    let body = req.call().map_err(|e| anyhow::anyhow!("GET {} failed: {}", url, e))?.into_string()?;
+    serde_json::from_str(&body).with_context(|| format!("Unexpected response from {}", url))
+}
+
+fn env_token(names: &[&str]) -> Option<String> {
+    names.iter().find_map(|n| std::env::var(n).ok().filter(|v| !v.is_empty()))
+}
+
+fn parse_time(value: &serde_json::Value) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.as_str()?).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// Ask the forge about `remote`
+pub fn fetch(remote: &Remote) -> Result<RepoActivity> {
+    let (repo, pulls, last_push) = match remote.forge {
+        Forge::GitHub => {
+            let api = if remote.host == "github.com" {
+                "https://api.github.com".to_string()
+            } else {
+                format!("https://{}/api/v3", remote.host)
+            };
+            let auth = || env_token(&["GITHUB_TOKEN", "GH_TOKEN"]).map(|t| ("Authorization", format!("Bearer {}", t)));
+            let repo = get_json(&format!("{}/repos/{}", api, remote.path), auth())?;
+            let pulls = get_json(&format!("{}/repos/{}/pulls?state=open&per_page=100", api, remote.path), auth());
+            let last_push = parse_time(&repo["pushed_at"]);
+            (repo, pulls, last_push)
+        }
+        Forge::GitLab => {
+            let api = format!("https://{}/api/v4/projects/{}", remote.host, remote.path.replace('/', "%2F"));
+            let auth = || env_token(&["GITLAB_TOKEN"]).map(|t| ("PRIVATE-TOKEN", t));
+            let repo = get_json(&api, auth())?;
+            let pulls = get_json(&format!("{}/merge_requests?state=opened&per_page=100", api), auth());
+            let last_push = parse_time(&repo["last_activity_at"]);
+            (repo, pulls, last_push)
+        }
+    };
+    Ok(RepoActivity {
+        remote: remote.key(),
+        last_push,
+        open_pull_requests: pulls.ok().and_then(|p| p.as_array().map(|a| a.len() as u64)),
+        archived: repo["archived"].as_bool().unwrap_or(false),
+        fetched_at: Utc::now(),
+    })
+}
+
+fn cache_path() -> PathBuf {
+    crate::paths::cache_dir().join("repo_activity.json")
+}
+
+/// Activity of every scanned project with a recognised `origin` remote, keyed
+/// by project path. Lookups that fail are reported and skipped.
+pub fn lookup_projects(projects: &[ProjectRecord]) -> HashMap<String, RepoActivity> {
+    let path = cache_path();
+    let mut cache: HashMap<String, RepoActivity> = fs::read_to_string(&path).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    let now = Utc::now();
+    let mut changed = false;
+    let mut found = HashMap::new();
+    for project in projects {
+        let Some(remote) = origin_url(Path::new(&project.path)).as_deref().and_then(Remote::parse) else {
+            continue;
+        };
+        let key = remote.key();
+        let fresh = cache.get(&key).filter(|a| now - a.fetched_at < Duration::hours(CACHE_TTL_HOURS));
+        let activity = match fresh {
+            Some(a) => a.clone(),
+            None => match fetch(&remote) {
+                Ok(a) => {
+                    cache.insert(key, a.clone());
+                    changed = true;
+                    a
+                }
+                Err(e) => {
+                    eprintln!("Warning: No remote activity for {}: {:#}", project.path, e);
+                    continue;
+                }
+            },
+        };
+        found.insert(project.path.clone(), activity);
+    }
+    if changed {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).ok();
+        }
+        if let Err(e) = serde_json::to_vec_pretty(&cache).map_err(anyhow::Error::from).and_then(|b| Ok(fs::write(&path, b)?)) {
+            eprintln!("Warning: Failed to save {:?}: {}", path, e);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote() {
+        let gh = Remote::parse("git@github.com:acme/web.git").unwrap();
+        assert_eq!((gh.forge, gh.host.as_str(), gh.path.as_str()), (Forge::GitHub, "github.com", "acme/web"));
+        assert_eq!(Remote::parse("https://github.com/acme/web").unwrap(), gh);
+        let gl = Remote::parse("ssh://git@gitlab.example.com:2222/group/sub/app.git").unwrap();
+        assert_eq!((gl.forge, gl.path.as_str()), (Forge::GitLab, "group/sub/app"));
+        assert!(Remote::parse("https://bitbucket.org/acme/web.git").is_none());
+        assert!(Remote::parse("/srv/git/web.git").is_none());
+    }
+
+    #[test]
+    fn test_liveness() {
+        let now = Utc::now();
+        let activity = |days: i64, prs: u64, archived: bool| RepoActivity {
+            remote: "github.com/acme/web".into(),
+            last_push: Some(now - Duration::days(days)),
+            open_pull_requests: Some(prs),
+            archived,
+            fetched_at: now,
+        };
+        assert_eq!(activity(3, 0, false).liveness(now, 90), Liveness::Active);
+        assert_eq!(activity(400, 0, false).liveness(now, 90), Liveness::Dormant);
+        assert_eq!(activity(400, 2, false).liveness(now, 90), Liveness::Active);
+        assert_eq!(activity(3, 2, true).liveness(now, 90), Liveness::Dormant);
+    }
+
+    #[test]
+    fn test_local_git_metadata() {
+        let temp = tempfile::tempdir().unwrap();
+        let git = temp.path().join(".git");
+        fs::create_dir_all(git.join("logs")).unwrap();
+        fs::write(git.join("config"), "[core]\n\tbare = false\n[remote \"origin\"]\n\turl = git@github.com:acme/web.git\n").unwrap();
+        fs::write(
+            git.join("logs/HEAD"),
+            "0000 1111 Dev <dev@example.com> 1700000000 +0000\tclone\n1111 2222 Dev <dev@example.com> 1710000000 +0100\tcommit: fix\n",
+        ).unwrap();
+        let project = temp.path().join("packages/app");
+        fs::create_dir_all(&project).unwrap();
+
+        assert_eq!(origin_url(&project).as_deref(), Some("git@github.com:acme/web.git"));
+        assert_eq!(local_last_commit(&project).unwrap().timestamp(), 1_710_000_000);
+    }
+}
@@ -0,0 +1,221 @@
+//! Plan Item Risk Scores
+//!
+//! Every signal about whether removing a plan item hurts lives somewhere
+//! else: the ML confidence in its reason, the projects reaching it in the
+//! impact analysis, their activity class, open files in the dedup blockers.
+//! `score_plan` folds them into one number per item, 0 (safe) to 100, and
+//! keeps the factors that contributed:
+//!
+//! - `in_use`: 30 while a process holds files open inside the item
+//! - `activity`: 25 when the liveliest project hit is active, 10 when dormant
+//! - `reachability`: 12 for one project reaching the item, 4 more per further
+//!   project, up to 20
+//! - `ml_confidence`: up to 20, the less sure the model was it is unused
+//! - `reinstall_cost`: up to 10 by size, 5 more when a project hit has no
+//!   lockfile to reinstall the same versions from
+//! - `git_dirty`: 10 when a project hit has uncommitted changes
+//!
+//! The sum is capped at 100. `apply --max-risk` and `purge --max-risk` leave
+//! out items scoring above a threshold; `--sort-by-risk` orders a plan by it.
+
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::feature_store::FeatureStore;
+use crate::impact::broken_by_item;
+use crate::project_activity::{classify_projects, load_config, EditorRecents};
+use crate::reinstall::PackageManager;
+use crate::repo_activity::has_uncommitted_changes;
+use crate::symlink::{is_in_use, open_file_snapshot};
+use crate::types::{ActivityClass, DryRunReport, PlanItem, PlanReason, ProjectRecord, RiskFactor, RiskScore, RiskSignal, ScanOutput};
+
+const IN_USE_POINTS: u8 = 30;
+const GIT_DIRTY_POINTS: u8 = 10;
+const UNLOCKED_POINTS: u8 = 5;
+
+/// What is known about a project an item breaks
+#[derive(Debug, Clone, Default)]
+pub struct ProjectState {
+    pub activity: Option<ActivityClass>,
+    pub dirty: bool,
+    pub locked: bool,
+}
+
+fn factor(signal: RiskSignal, points: u8, detail: String) -> RiskFactor {
+    RiskFactor { signal, points, detail }
+}
+
+/// Score one item, given the projects it breaks and whether it is in use
+pub fn score_item(item: &PlanItem, projects: &[(&str, &ProjectState)], in_use: bool) -> RiskScore {
+    let mut factors = Vec::new();
+    if in_use {
+        factors.push(factor(RiskSignal::InUse, IN_USE_POINTS, "a running process holds files open inside it".to_string()));
+    }
+
+    let liveliest = projects.iter()
+        .filter_map(|(project, state)| Some((*project, state.activity?)))
+        .min_by_key(|(_, class)| match class {
+            ActivityClass::Active => 0,
+            ActivityClass::Dormant => 1,
+            ActivityClass::Dead => 2,
+        });
+    match liveliest {
+        Some((project, ActivityClass::Active)) => factors.push(factor(RiskSignal::Activity, 25, format!("{} is active", project))),
+        Some((project, ActivityClass::Dormant)) => factors.push(factor(RiskSignal::Activity, 10, format!("{} is dormant", project))),
+        _ => {}
+    }
+
+    if !projects.is_empty() {
+        let points = (12 + 4 * (projects.len() - 1)).min(20) as u8;
+        let detail = match projects {
+            [(project, _)] => format!("{} depends on it", project),
+            _ => format!("{} projects depend on it", projects.len()),
+        };
+        factors.push(factor(RiskSignal::Reachability, points, detail));
+    }
+
+    if let PlanReason::MlPredicted { confidence } = item.reason {
+        let points = ((1.0 - confidence.clamp(0.0, 1.0)) * 20.0).round() as u8;
+        if points > 0 {
+            factors.push(factor(RiskSignal::MlConfidence, points, format!("predicted unused with {:.0}% confidence", confidence * 100.0)));
+        }
+    }
+
+    // 2 points at 10 MB, 7 at 100 MB, 10 from about 300 MB
+    let mb = item.estimated_size_bytes as f64 / 1_000_000.0;
+    let mut reinstall = ((mb / 10.0 + 1.0).log2() * 2.0).round().min(10.0) as u8;
+    let mut detail = format!("{:.0} MB to download again", mb);
+    if let Some((project, _)) = projects.iter().find(|(_, state)| !state.locked) {
+        reinstall += UNLOCKED_POINTS;
+        detail.push_str(&format!("; {} has no lockfile", project));
+    }
+    if reinstall > 0 {
+        factors.push(factor(RiskSignal::ReinstallCost, reinstall, detail));
+    }
+
+    if let Some((project, _)) = projects.iter().find(|(_, state)| state.dirty) {
+        factors.push(factor(RiskSignal::GitDirty, GIT_DIRTY_POINTS, format!("{} has uncommitted changes", project)));
+    }
+
+    let score = factors.iter().map(|f| f.points as u32).sum::<u32>().min(100) as u8;
+    RiskScore { score, factors }
+}
+
+/// Score every item of `plan`, given the state of the scanned projects and
+/// the files open at the time
+pub fn score_plan(plan: &mut DryRunReport, scan: &ScanOutput, states: &HashMap<String, ProjectState>, open_files: &HashSet<PathBuf>) {
+    let broken: HashMap<String, Vec<String>> = broken_by_item(plan, scan).into_iter()
+        .map(|i| (i.target_path, i.projects))
+        .collect();
+    let unknown = ProjectState::default();
+    for item in &mut plan.items {
+        let projects: Vec<(&str, &ProjectState)> = broken.get(&item.target_path).into_iter().flatten()
+            .map(|p| (p.as_str(), states.get(p).unwrap_or(&unknown)))
+            .collect();
+        let in_use = is_in_use(Path::new(&item.target_path), open_files);
+        item.risk = Some(score_item(item, &projects, in_use));
+    }
+}
+
+/// `score_plan` with the projects hit classified the way `projects` does,
+/// their work trees checked with `git` and the open files read from the system
+pub fn score_configured(plan: &mut DryRunReport, scan: &ScanOutput) {
+    let broken: HashSet<String> = broken_by_item(plan, scan).into_iter().flat_map(|i| i.projects).collect();
+    let hit: Vec<ProjectRecord> = scan.projects.iter().filter(|p| broken.contains(&p.path)).cloned().collect();
+    let db = FeatureStore::open_default().ok();
+    let mut states: HashMap<String, ProjectState> = hit.iter().map(|p| {
+        let path = Path::new(&p.path);
+        (p.path.clone(), ProjectState {
+            activity: None,
+            dirty: has_uncommitted_changes(path).unwrap_or(false),
+            locked: PackageManager::detect(path).1.is_some(),
+        })
+    })
+    .collect();
+    for activity in classify_projects(&hit, db.as_ref(), &EditorRecents::detect(), &load_config(), Utc::now()) {
+        if let Some(state) = states.get_mut(&activity.project) {
+            state.activity = Some(activity.activity);
+        }
+    }
+    score_plan(plan, scan, &states, &open_file_snapshot());
+}
+
+/// Riskiest items first
+pub fn sort_by_risk(plan: &mut DryRunReport) {
+    plan.items.sort_by_key(|i| std::cmp::Reverse(i.risk.as_ref().map_or(0, |r| r.score)));
+}
+
+/// Split `items` into those scoring at most `max` and the rest. Items
+/// without a score (plans written before scoring, or edited by hand) are
+/// held too, since their risk is unknown.
+pub fn within(items: Vec<PlanItem>, max: u8) -> (Vec<PlanItem>, Vec<PlanItem>) {
+    items.into_iter().partition(|i| i.risk.as_ref().is_some_and(|r| r.score <= max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PackageRecord, ProjectRecord};
+
+    fn item(path: &str, size: u64, reason: PlanReason) -> PlanItem {
+        PlanItem { target_path: path.to_string(), estimated_size_bytes: size, reason, blockers: Vec::new(), risk: None }
+    }
+
+    #[test]
+    fn test_score_plan() {
+        let package = |path: &str| PackageRecord::for_test(path, "1.0.0", 10);
+        let scan = ScanOutput {
+            packages: vec![package("/w/app/node_modules/lodash"), package("/cache/tool")],
+            projects: vec![ProjectRecord { path: "/w/app".to_string(), manager: None, dependencies: Vec::new(), mtime: Utc::now() }],
+            edges: Vec::new(),
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let mut plan = DryRunReport {
+            items: vec![
+                item("/cache/tool", 0, PlanReason::Orphaned),
+                item("/w/app/node_modules/lodash", 100_000_000, PlanReason::MlPredicted { confidence: 0.75 }),
+            ],
+            total_estimated_bytes: 100_000_000,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+        let states = HashMap::from([(
+            "/w/app".to_string(),
+            ProjectState { activity: Some(ActivityClass::Active), dirty: true, locked: false },
+        )]);
+        let open = HashSet::from([PathBuf::from("/w/app/node_modules/lodash/index.js")]);
+        score_plan(&mut plan, &scan, &states, &open);
+
+        let unused = plan.items[0].risk.as_ref().unwrap();
+        assert_eq!((unused.score, unused.factors.len()), (0, 0));
+        let risky = plan.items[1].risk.as_ref().unwrap();
+        // in use 30, active 25, one project 12, 75% confidence 5, 100 MB
+        // without lockfile 7 + 5, dirty 10
+        assert_eq!(risky.score, 94);
+        assert_eq!(risky.factors.len(), 6);
+
+        sort_by_risk(&mut plan);
+        assert_eq!(plan.items[0].target_path, "/w/app/node_modules/lodash");
+        let (kept, held) = within(plan.items.clone(), 50);
+        assert_eq!((kept.len(), held.len()), (1, 1));
+        assert_eq!(held[0].target_path, "/w/app/node_modules/lodash");
+    }
+
+    #[test]
+    fn test_within_holds_unscored_items() {
+        let mut scored = item("/w/a/node_modules/left-pad", 10, PlanReason::Orphaned);
+        scored.risk = Some(RiskScore { score: 10, factors: Vec::new() });
+        let unscored = item("/w/a/node_modules/lodash", 10, PlanReason::Orphaned);
+
+        let (kept, held) = within(vec![scored, unscored], 30);
+        assert_eq!(kept[0].target_path, "/w/a/node_modules/left-pad");
+        assert_eq!(held[0].target_path, "/w/a/node_modules/lodash");
+        assert_eq!((kept.len(), held.len()), (1, 1));
+    }
+}
@@ -0,0 +1,280 @@
+//! Run Manifests
+//!
+//! Every apply (`apply`, `quarantine`, `share-trees`) writes a manifest of
+//! what it changed to `runs/<id>.json` in the state directory: for each path
+//! the action taken, its state before and after (kind, size, content hash,
+//! link target), the quarantine entry it went to and any error. The run id is also in the command's output, the `post-apply` hook
+//! payload and the feature store's apply history, so any of those lead to
+//! the manifest and on to the quarantine entries. `show-run` lists runs and
+//! prints one.
+//!
+//! Hashes are the quarantine checksums of what was moved; runs with
+//! `--fast` record them once the hash queue has filled them in.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::types::QuarantineRecord;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathKind {
+    Missing,
+    Dir,
+    File,
+    Symlink,
+}
+
+/// What was at a path at one moment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathState {
+    pub kind: PathKind,
+    #[serde(default)]
+    pub size_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+}
+
+impl PathState {
+    /// Kind and link target of `path`, without walking it
+    pub fn probe(path: &Path) -> Self {
+        let (kind, link_target) = match fs::symlink_metadata(path) {
+            Err(_) => (PathKind::Missing, None),
+            Ok(meta) if meta.file_type().is_symlink() => {
+                (PathKind::Symlink, fs::read_link(path).ok().map(|t| t.to_string_lossy().to_string()))
+            }
+            Ok(meta) if meta.is_dir() => (PathKind::Dir, None),
+            Ok(_) => (PathKind::File, None),
+        };
+        PathState { kind, size_bytes: 0, sha256: None, link_target }
+    }
+
+    /// `before` with the size and checksum of what quarantine moved
+    fn moved(mut self, rec: &QuarantineRecord) -> Self {
+        self.size_bytes = rec.size_bytes;
+        self.sha256 = known_checksum(&rec.sha256);
+        self
+    }
+}
+
+/// Quarantine checksums still to be computed or that failed are not hashes
+fn known_checksum(sha256: &str) -> Option<String> {
+    (sha256.len() == 64).then(|| sha256.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunAction {
+    /// Moved into quarantine
+    Quarantine,
+    /// Moved into quarantine and replaced by a link to an identical tree
+    ShareTree,
+}
+
+/// One path a run changed, or failed to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub action: RunAction,
+    pub before: PathState,
+    pub after: PathState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quarantine_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quarantine_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub id: String,
+    /// Subcommand that made the changes
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approved_by: Option<String>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A run as `show-run` lists it
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub id: String,
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    pub changed: usize,
+    pub failed: usize,
+    pub bytes_moved: u64,
+}
+
+impl RunManifest {
+    pub fn summary(&self) -> RunSummary {
+        let changed: Vec<&ManifestEntry> = self.entries.iter().filter(|e| e.error.is_none()).collect();
+        RunSummary {
+            id: self.id.clone(),
+            command: self.command.clone(),
+            started_at: self.started_at,
+            changed: changed.len(),
+            failed: self.entries.len() - changed.len(),
+            bytes_moved: changed.iter().map(|e| e.before.size_bytes).sum(),
+        }
+    }
+}
+
+pub fn runs_dir() -> PathBuf {
+    crate::paths::state_dir().join("runs")
+}
+
+/// Collects the entries of a run as it goes
+pub struct RunRecorder {
+    manifest: RunManifest,
+}
+
+impl RunRecorder {
+    pub fn start(command: &str, approved_by: Option<String>) -> Self {
+        let now = Utc::now();
+        let mut suffix = [0u8; 2];
+        // Only tells apart runs started in the same second
+        let _ = getrandom::getrandom(&mut suffix);
+        RunRecorder {
+            manifest: RunManifest {
+                id: format!("{}-{}", now.format("%Y%m%d-%H%M%S"), hex::encode(suffix)),
+                command: command.to_string(),
+                started_at: now,
+                finished_at: now,
+                approved_by,
+                entries: Vec::new(),
+            },
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.manifest.id
+    }
+
+    /// Record `path`, which was `before` until `action` gave `result`
+    pub fn record(&mut self, path: &Path, action: RunAction, before: PathState, result: &crate::Result<QuarantineRecord>) {
+        let (before, quarantine_id, quarantine_path, error) = match result {
+            Ok(rec) => (before.moved(rec), Some(rec.id.clone()), Some(rec.quarantine_path.clone()), None),
+            Err(e) => (before, None, None, Some(e.to_string())),
+        };
+        self.manifest.entries.push(ManifestEntry {
+            path: path.to_string_lossy().to_string(),
+            action,
+            before,
+            after: PathState::probe(path),
+            quarantine_id,
+            quarantine_path,
+            error,
+        });
+    }
+
+    /// Write the manifest to `runs/<id>.json`
+    pub fn finish(self) -> crate::Result<RunManifest> {
+        self.finish_in(&runs_dir()).map_err(Error::lift(Error::Quarantine))
+    }
+
+    fn finish_in(mut self, dir: &Path) -> Result<RunManifest> {
+        self.manifest.finished_at = Utc::now();
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        let path = dir.join(format!("{}.json", self.manifest.id));
+        fs::write(&path, serde_json::to_string_pretty(&self.manifest)?)
+            .with_context(|| format!("Failed to write run manifest {:?}", path))?;
+        Ok(self.manifest)
+    }
+}
+
+/// The manifest of run `id` (`latest` for the newest), with checksums the
+/// hash queue has filled in since
+pub fn load(id: &str) -> crate::Result<RunManifest> {
+    load_from(&runs_dir(), id).map_err(Error::lift(Error::Quarantine))
+}
+
+fn load_from(dir: &Path, id: &str) -> Result<RunManifest> {
+    let id = match id {
+        "latest" => list_from(dir).into_iter().next().map(|m| m.id).context("No runs recorded yet")?,
+        _ => id.to_string(),
+    };
+    anyhow::ensure!(!id.contains(['/', '\\']) && !id.starts_with('.'), "Invalid run id {:?}", id);
+    let path = dir.join(format!("{}.json", id));
+    let text = fs::read_to_string(&path).with_context(|| format!("No run {:?}", id))?;
+    let mut manifest: RunManifest = serde_json::from_str(&text).with_context(|| format!("Unreadable run manifest {:?}", path))?;
+    for entry in manifest.entries.iter_mut().filter(|e| e.before.sha256.is_none()) {
+        let rec = entry.quarantine_id.as_deref().and_then(crate::safety::find_quarantine_by_id);
+        entry.before.sha256 = rec.and_then(|r| known_checksum(&r.sha256));
+    }
+    Ok(manifest)
+}
+
+/// Recorded runs, newest first
+pub fn list() -> Vec<RunSummary> {
+    list_from(&runs_dir()).iter().map(RunManifest::summary).collect()
+}
+
+fn list_from(dir: &Path) -> Vec<RunManifest> {
+    let mut runs: Vec<RunManifest> = fs::read_dir(dir).into_iter().flatten()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+        .filter_map(|e| serde_json::from_str(&fs::read_to_string(e.path()).ok()?).ok())
+        .collect();
+    runs.sort_by(|a, b| b.started_at.cmp(&a.started_at).then_with(|| b.id.cmp(&a.id)));
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_manifest_records_before_and_after() {
+        let temp = tempdir().unwrap();
+        let moved = temp.path().join("app/node_modules");
+        let linked = temp.path().join("site/node_modules");
+        let failed = temp.path().join("locked");
+        for dir in [&moved, &linked, &failed] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let before: Vec<PathState> = [&moved, &linked, &failed].iter().map(|p| PathState::probe(p)).collect();
+        assert!(before.iter().all(|s| s.kind == PathKind::Dir));
+
+        let record = |path: &Path, sha256: &str| QuarantineRecord {
+            quarantine_path: "/q/42_node_modules".into(),
+            sha256: sha256.into(),
+            ..QuarantineRecord::for_test("42", path, 1000)
+        };
+        let mut run = RunRecorder::start("apply", Some("ops@example.com".into()));
+        fs::remove_dir(&moved).unwrap();
+        run.record(&moved, RunAction::Quarantine, before[0].clone(), &Ok(record(&moved, &"a".repeat(64))));
+        fs::remove_dir(&linked).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&moved, &linked).unwrap();
+        run.record(&linked, RunAction::ShareTree, before[1].clone(), &Ok(record(&linked, "deferred")));
+        run.record(&failed, RunAction::Quarantine, before[2].clone(), &Err(Error::ReadOnly("quarantine packages")));
+        let id = run.id().to_string();
+        run.finish_in(&temp.path().join("runs")).unwrap();
+
+        let manifest = load_from(&temp.path().join("runs"), "latest").unwrap();
+        assert_eq!((manifest.id.as_str(), manifest.approved_by.as_deref()), (id.as_str(), Some("ops@example.com")));
+        let [quarantined, shared, refused] = &manifest.entries[..] else { panic!("three entries") };
+        assert_eq!((quarantined.before.size_bytes, quarantined.after.kind), (1000, PathKind::Missing));
+        assert_eq!(quarantined.before.sha256.as_deref(), Some("a".repeat(64).as_str()));
+        assert_eq!(shared.before.sha256, None);
+        #[cfg(unix)]
+        assert_eq!(shared.after.link_target.as_deref(), Some(moved.to_string_lossy().as_ref()));
+        assert_eq!((refused.after.kind, refused.quarantine_id.as_deref()), (PathKind::Dir, None));
+        assert!(refused.error.as_deref().is_some_and(|e| e.contains("read-only")));
+
+        let summary = manifest.summary();
+        assert_eq!((summary.changed, summary.failed, summary.bytes_moved), (2, 1, 2000));
+        assert!(load_from(&temp.path().join("runs"), "../secrets").is_err());
+    }
+}
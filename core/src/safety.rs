@@ -5,13 +5,31 @@
 //! - Lazy SHA256 (computed only when needed)
 //! - Size quotas and automatic cleanup
 //! - Rollback capability
+//! - Optional packing of entries into zstd-compressed tarballs, unpacked
+//!   again on rollback
+//! - Read-only mode, in which every mutation of packages, caches, the
+//!   quarantine or the global store fails with `Error::ReadOnly`
 
 use anyhow::{Context, Result};
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::{fs, path::{Path, PathBuf}};
+use std::{fs, io, path::{Path, PathBuf}};
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::error::Error;
+use crate::fs_snapshot::Snapshot;
+use crate::hash_queue::{hash_dir, DirProgress, JobKind, RateLimiter};
+use crate::progress::{count_files, OperationContext, Phase, ThroughputMeter, ThroughputSummary};
+use crate::scanner::is_cache_dir;
+use crate::editor_caches::is_editor_cache;
+use crate::model_caches::is_model_cache;
+use crate::machine_role::MachineRole;
+use crate::provider_caches::in_provider_cache;
+use crate::cargo_caches::in_cargo_dir;
+use crate::python_caches::in_python_dir;
+use crate::go_caches::in_mod_cache;
+use crate::timings::{self, TimedPhase};
 use crate::types::QuarantineRecord;
 
 /// Quarantine manager configuration
@@ -23,6 +41,22 @@ pub struct QuarantineConfig {
     pub retention_days: i64,
     /// Maximum number of entries to keep (0 = unlimited)
     pub max_entries: usize,
+    /// Hardlink identical files across quarantine entries
+    #[serde(default = "default_true")]
+    pub dedupe: bool,
+    /// Roots under which quarantine targets must live (empty = current directory)
+    #[serde(default)]
+    pub allowed_roots: Vec<PathBuf>,
+    /// Delete expired entries before each quarantine run
+    #[serde(default)]
+    pub auto_prune: bool,
+    /// Pack new entries into a `.tar.zst` instead of keeping the tree
+    #[serde(default)]
+    pub compress: bool,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Default for QuarantineConfig {
@@ -31,6 +65,10 @@ impl Default for QuarantineConfig {
             max_size_gb: 10,       // 10GB default
             retention_days: 30,    // 30 days default
             max_entries: 200,      // 200 entries default
+            dedupe: true,
+            allowed_roots: Vec::new(),
+            auto_prune: false,
+            compress: false,
         }
     }
 }
@@ -42,23 +80,116 @@ pub struct QuarantineStats {
     pub total_size_bytes: u64,
     pub oldest_entry_days: i64,
     pub entries_over_retention: usize,
+    /// Bytes not actually occupying extra disk thanks to hardlink sharing
+    pub shared_bytes: u64,
+    /// Entries packed into compressed tarballs, and their size on disk
+    pub packed_entries: usize,
+    pub packed_bytes: u64,
 }
 
 fn quarantine_dir() -> PathBuf {
-    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-    home.join(".packagepurge").join("quarantine")
+    crate::paths::quarantine_dir()
+}
+
+/// Content-addressed pool of file objects shared between quarantine entries
+fn objects_dir() -> PathBuf {
+    quarantine_dir().join(".objects")
+}
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Refuse every mutation for the rest of the process (`--read-only`)
+pub fn set_read_only() {
+    READ_ONLY.store(true, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_READ_ONLY: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Read-only mode for the calling test's thread until the guard drops, so
+/// tests running alongside are unaffected
+#[cfg(test)]
+pub(crate) fn read_only_for_test() -> impl Drop {
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            TEST_READ_ONLY.with(|r| r.set(false));
+        }
+    }
+    TEST_READ_ONLY.with(|r| r.set(true));
+    Guard
+}
+
+/// Whether mutations are refused, by `set_read_only` or `PACKAGEPURGE_READ_ONLY`
+pub fn is_read_only() -> bool {
+    #[cfg(test)]
+    if TEST_READ_ONLY.with(|r| r.get()) {
+        return true;
+    }
+    READ_ONLY.load(Ordering::SeqCst)
+        || std::env::var("PACKAGEPURGE_READ_ONLY").is_ok_and(|v| !v.is_empty() && v != "0" && v != "false")
+}
+
+/// Checkpoint before mutating anything outside PackagePurge's own bookkeeping
+/// (scan cache, feature store, settings). `action` completes "refusing to ...".
+pub fn ensure_writable(action: &'static str) -> crate::Result<()> {
+    if is_read_only() {
+        Err(Error::ReadOnly(action))
+    } else {
+        Ok(())
+    }
+}
+
+static COMPRESS: AtomicBool = AtomicBool::new(false);
+
+/// Pack entries quarantined for the rest of the process (`quarantine --compress`)
+pub fn set_compress() {
+    COMPRESS.store(true, Ordering::SeqCst);
 }
 
-fn index_path() -> PathBuf {
-    quarantine_dir().join("index.json")
+/// Whether new entries are packed, by `set_compress`,
+/// `PACKAGEPURGE_QUARANTINE_COMPRESS` or the saved `compress` setting
+fn compress_enabled(config: &QuarantineConfig) -> bool {
+    COMPRESS.load(Ordering::SeqCst)
+        || std::env::var("PACKAGEPURGE_QUARANTINE_COMPRESS").is_ok_and(|v| !v.is_empty() && v != "0" && v != "false")
+        || config.compress
+}
+
+/// Directories owned by the tool itself. Nothing inside them may ever be
+/// scanned as a package, planned for cleanup, deduplicated or quarantined.
+pub fn protected_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![quarantine_dir()];
+    if let Ok(store) = crate::symlink::get_global_store_path() {
+        dirs.push(store);
+    }
+    dirs
+}
+
+/// Check whether a path lies inside (or is) one of the tool's own directories
+pub fn is_protected_path(path: &Path) -> bool {
+    let abs = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    protected_dirs().iter().any(|d| abs.starts_with(d))
+}
+
+fn ensure_not_protected(target: &Path) -> Result<()> {
+    if is_protected_path(target) {
+        anyhow::bail!("{:?} is inside PackagePurge's own store or quarantine directory", target);
+    }
+    Ok(())
 }
 
 fn config_path() -> PathBuf {
-    quarantine_dir().join("config.json")
+    crate::paths::config_dir().join("quarantine.json")
 }
 
 fn read_index() -> Vec<QuarantineRecord> {
-    let p = index_path();
+    read_index_in(&quarantine_dir())
+}
+
+fn read_index_in(dir: &Path) -> Vec<QuarantineRecord> {
+    let p = dir.join("index.json");
     if let Ok(text) = fs::read_to_string(&p) {
         if let Ok(list) = serde_json::from_str::<Vec<QuarantineRecord>>(&text) { 
             return list; 
@@ -68,28 +199,105 @@ fn read_index() -> Vec<QuarantineRecord> {
 }
 
 fn write_index(list: &[QuarantineRecord]) -> Result<()> {
+    write_index_in(&quarantine_dir(), list)
+}
+
+fn write_index_in(dir: &Path, list: &[QuarantineRecord]) -> Result<()> {
     let data = serde_json::to_string_pretty(list)?;
-    fs::write(index_path(), data).context("Failed to write quarantine index")?;
+    fs::write(dir.join("index.json"), data).context("Failed to write quarantine index")?;
     Ok(())
 }
 
-/// Load quarantine configuration
+/// Expiry timestamp for an entry created at `created_at` under `config`
+pub fn expiry_for(created_at: DateTime<Utc>, config: &QuarantineConfig) -> Option<DateTime<Utc>> {
+    if config.retention_days > 0 {
+        Some(created_at + Duration::days(config.retention_days))
+    } else {
+        None
+    }
+}
+
+/// Effective expiry of a record. Records written before `expires_at` existed
+/// fall back to the configured retention window.
+pub fn effective_expiry(rec: &QuarantineRecord, config: &QuarantineConfig) -> Option<DateTime<Utc>> {
+    rec.expires_at.or_else(|| expiry_for(rec.created_at, config))
+}
+
+fn is_expired(rec: &QuarantineRecord, config: &QuarantineConfig, now: DateTime<Utc>) -> bool {
+    effective_expiry(rec, config).map(|t| t <= now).unwrap_or(false)
+}
+
+/// Load quarantine configuration (the machine role's defaults when none was saved)
 pub fn load_config() -> QuarantineConfig {
+    load_config_for(MachineRole::current().0)
+}
+
+/// Quarantine configuration in effect under `role`
+pub fn load_config_for(role: MachineRole) -> QuarantineConfig {
     let p = config_path();
     if let Ok(text) = fs::read_to_string(&p) {
         if let Ok(config) = serde_json::from_str::<QuarantineConfig>(&text) {
             return config;
         }
     }
-    QuarantineConfig::default()
+    // Nothing saved: config.toml's values over the machine role's defaults
+    let mut config = role.policy().quarantine_config();
+    let defaults = &crate::config_file::get().quarantine;
+    if let Some(days) = defaults.retention_days {
+        config.retention_days = days;
+    }
+    if let Some(size) = defaults.max_size_gb {
+        config.max_size_gb = size;
+    }
+    config
 }
 
 /// Save quarantine configuration
-pub fn save_config(config: &QuarantineConfig) -> Result<()> {
-    let qdir = quarantine_dir();
-    fs::create_dir_all(&qdir).ok();
+pub fn save_config(config: &QuarantineConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Quarantine))
+}
+
+fn save_config_impl(config: &QuarantineConfig) -> Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
     let data = serde_json::to_string_pretty(config)?;
-    fs::write(config_path(), data).context("Failed to save quarantine config")?;
+    fs::write(&path, data).context("Failed to save quarantine config")?;
+    Ok(())
+}
+
+/// Check that a quarantine target is something this tool should ever move:
+/// it must exist, sit under one of `roots` and look like a package or cache
+/// directory. Returns a human-readable refusal otherwise.
+pub fn validate_target(target: &Path, roots: &[PathBuf]) -> crate::Result<()> {
+    validate_target_impl(target, roots).map_err(Error::lift(Error::Quarantine))
+}
+
+fn validate_target_impl(target: &Path, roots: &[PathBuf]) -> Result<()> {
+    let canonical = fs::canonicalize(target)
+        .with_context(|| format!("Quarantine target {:?} does not exist", target))?;
+    ensure_not_protected(&canonical)?;
+
+    let under_root = roots.iter()
+        .filter_map(|r| fs::canonicalize(r).ok())
+        .any(|r| canonical.starts_with(&r) && canonical != r);
+    if !under_root {
+        anyhow::bail!("{:?} is outside the allowed roots {:?}", target, roots);
+    }
+
+    let sharers = crate::tree_share::sharers_within(&canonical);
+    if !sharers.is_empty() {
+        anyhow::bail!("{:?} holds the node_modules tree that {} link to; roll those links back first", target, sharers.join(", "));
+    }
+
+    let in_node_modules = canonical.components().any(|c| c.as_os_str() == "node_modules");
+    let regenerable = is_cache_dir(&canonical) || is_editor_cache(&canonical) || in_provider_cache(&canonical)
+        || is_model_cache(&canonical) || in_cargo_dir(&canonical) || in_python_dir(&canonical)
+        || in_mod_cache(&canonical) || crate::cache_markers::is_cleanable(&canonical);
+    if !in_node_modules && !regenerable {
+        anyhow::bail!("{:?} does not look like a package or cache directory", target);
+    }
     Ok(())
 }
 
@@ -99,32 +307,198 @@ pub fn sha256_dir_lazy(path: PathBuf) -> impl FnOnce() -> Result<(String, u64)>
     move || sha256_dir(&path)
 }
 
-/// Compute SHA256 of directory contents
+/// Compute SHA256 of directory contents, the way the background hash queue
+/// does, so a copy elsewhere (the quarantine entry, a filesystem snapshot)
+/// hashes the same
 fn sha256_dir(path: &Path) -> Result<(String, u64)> {
-    let mut hasher = Sha256::new();
-    let mut total: u64 = 0;
-    
-    for entry in walkdir::WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        let p = entry.path();
-        hasher.update(p.to_string_lossy().as_bytes());
-        if entry.file_type().is_file() {
-            let data = fs::read(p)?;
-            total += data.len() as u64;
-            hasher.update(&data);
+    let mut progress = DirProgress::default();
+    let hash = hash_dir(path, &mut progress, &mut RateLimiter::unlimited(), &mut |_| Ok(()))?;
+    Ok((hash, progress.bytes))
+}
+
+/// Number of hard links pointing at a file's inode (1 when not shared)
+#[cfg(unix)]
+fn link_count(meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink()
+}
+
+/// Hardlink every file under `dir` against the content-addressed pool.
+/// Files whose content and permission bits are already pooled are replaced
+/// by a link to the pooled object; new objects are added to the pool. Files
+/// already hard-linked elsewhere (say from pnpm's content store) are left
+/// alone: their objects would never fall to the one link `gc_pool` collects.
+/// Returns bytes shared with previously quarantined entries.
+#[cfg(unix)]
+fn dedupe_into_pool(dir: &Path, pool: &Path) -> Result<u64> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::create_dir_all(pool).context("Failed to create quarantine object pool")?;
+    let mut shared: u64 = 0;
+
+    for entry in walkdir::WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let meta = entry.metadata()?;
+        if link_count(&meta) > 1 {
+            continue;
+        }
+        let path = entry.path();
+        let mut hasher = Sha256::new();
+        let len = io::copy(&mut io::BufReader::new(fs::File::open(path)?), &mut hasher)?;
+        if len == 0 {
+            continue;
+        }
+        // Linked files share their mode, so it is part of the key
+        let mode = meta.permissions().mode() & 0o7777;
+        let object = pool.join(format!("{}-{:o}", hex::encode(hasher.finalize()), mode));
+
+        if object.exists() {
+            let tmp = path.with_extension("packagepurge.link");
+            if fs::hard_link(&object, &tmp).is_ok() {
+                fs::rename(&tmp, path)?;
+                shared += len;
+            }
+        } else {
+            // Pooling is best-effort; a failed link just leaves the file unshared
+            fs::hard_link(path, &object).ok();
         }
     }
-    Ok((hex::encode(hasher.finalize()), total))
+    Ok(shared)
+}
+
+/// The pool is collected by link count, which only Unix reports, so other
+/// platforms leave quarantined files unshared
+#[cfg(not(unix))]
+fn dedupe_into_pool(_dir: &Path, _pool: &Path) -> Result<u64> {
+    Ok(0)
+}
+
+/// Give every file under `dir` linked into `pool` its own inode again so
+/// restored packages can be modified without touching pooled objects or other
+/// quarantine entries. Links to files outside the pool are kept.
+fn break_hardlinks(dir: &Path, pool: &Path) -> Result<()> {
+    #[cfg(unix)]
+    let pooled: std::collections::HashSet<(u64, u64)> = {
+        use std::os::unix::fs::MetadataExt;
+        fs::read_dir(pool).into_iter().flatten().filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok())
+            .map(|m| (m.dev(), m.ino()))
+            .collect()
+    };
+    #[cfg(not(unix))]
+    let _ = pool;
+
+    for entry in walkdir::WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let in_pool = entry.metadata()
+                .is_ok_and(|m| link_count(&m) > 1 && pooled.contains(&(m.dev(), m.ino())));
+            if !in_pool {
+                continue;
+            }
+        }
+        let path = entry.path();
+        let tmp = path.with_extension("packagepurge.unlink");
+        fs::copy(path, &tmp)
+            .with_context(|| format!("Failed to copy {:?} while breaking hard links", path))?;
+        fs::rename(&tmp, path)?;
+    }
+    Ok(())
+}
+
+/// zstd level packed entries are written at
+const PACK_LEVEL: i32 = 3;
+
+/// `<dir>.tar.zst`
+fn packed_path(dir: &Path) -> PathBuf {
+    let mut name = dir.as_os_str().to_owned();
+    name.push(".tar.zst");
+    PathBuf::from(name)
+}
+
+/// Pack the tree at `dir` into `<dir>.tar.zst` and delete the tree.
+/// Symlinks are stored as links. Returns the archive and its size; on
+/// failure the tree is left as it was.
+fn pack_tree(dir: &Path) -> Result<(PathBuf, u64)> {
+    let archive = packed_path(dir);
+    let packed = (|| -> Result<u64> {
+        let encoder = zstd::Encoder::new(fs::File::create(&archive)?, PACK_LEVEL)?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+        builder.append_dir_all(".", dir)?;
+        let file = builder.into_inner()?.finish()?;
+        file.sync_all()?;
+        Ok(file.metadata()?.len())
+    })();
+    let bytes = match packed {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            fs::remove_file(&archive).ok();
+            return Err(e.context(format!("Failed to pack {:?}", dir)));
+        }
+    };
+    fs::remove_dir_all(dir).with_context(|| format!("Packed {:?} but could not delete the tree", dir))?;
+    Ok((archive, bytes))
+}
+
+/// Unpack an entry written by `pack_tree` to `dest`, which must not exist.
+/// The tree is extracted next to `dest` first and renamed into place.
+fn unpack_tree(archive: &Path, dest: &Path) -> Result<()> {
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let staging = dest.with_file_name(format!(".{}.packagepurge-restore", name));
+    if staging.exists() {
+        fs::remove_dir_all(&staging).ok();
+    }
+    fs::create_dir_all(&staging)?;
+    let unpacked = (|| -> Result<()> {
+        let mut tar = tar::Archive::new(zstd::Decoder::new(fs::File::open(archive)?)?);
+        tar.set_preserve_permissions(true);
+        tar.unpack(&staging)?;
+        fs::rename(&staging, dest)?;
+        Ok(())
+    })();
+    if unpacked.is_err() {
+        fs::remove_dir_all(&staging).ok();
+    }
+    unpacked.with_context(|| format!("Failed to unpack {:?} to {:?}", archive, dest))
+}
+
+/// Delete what an entry holds, a tree or a packed tarball
+fn remove_entry_files(qpath: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(qpath)?.is_dir() {
+        fs::remove_dir_all(qpath)
+    } else {
+        fs::remove_file(qpath)
+    }
+}
+
+/// Drop pooled objects no longer referenced by any quarantine entry
+fn gc_pool(pool: &Path) {
+    #[cfg(unix)]
+    {
+        if let Ok(entries) = fs::read_dir(pool) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if entry.metadata().map(|m| link_count(&m) <= 1).unwrap_or(false) {
+                    fs::remove_file(entry.path()).ok();
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = pool;
+    }
 }
 
 /// Quick size estimate without full hash (faster for quota checks)
 fn quick_size(path: &Path) -> u64 {
-    walkdir::WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter_map(|e| e.metadata().ok())
-        .map(|m| m.len())
-        .sum()
+    crate::native_walk::tree_totals(path, None).bytes
 }
 
 /// Get quarantine statistics
@@ -139,25 +513,29 @@ pub fn get_quarantine_stats() -> QuarantineStats {
         .max()
         .unwrap_or(0);
     
-    let over_retention = if config.retention_days > 0 {
-        list.iter()
-            .filter(|r| (now - r.created_at).num_days() > config.retention_days)
-            .count()
-    } else {
-        0
-    };
+    let over_retention = list.iter()
+        .filter(|r| is_expired(r, &config, now))
+        .count();
     
     QuarantineStats {
         total_entries: list.len(),
         total_size_bytes: total_size,
         oldest_entry_days: oldest_days,
         entries_over_retention: over_retention,
+        shared_bytes: list.iter().map(|r| r.shared_bytes).sum(),
+        packed_entries: list.iter().filter(|r| r.packed_bytes.is_some()).count(),
+        packed_bytes: list.iter().filter_map(|r| r.packed_bytes).sum(),
     }
 }
 
 /// Cleanup old entries based on configuration
 /// Returns number of entries cleaned and bytes freed
-pub fn cleanup_quarantine() -> Result<(usize, u64)> {
+pub fn cleanup_quarantine() -> crate::Result<(usize, u64)> {
+    cleanup_quarantine_impl().map_err(Error::lift(Error::Quarantine))
+}
+
+fn cleanup_quarantine_impl() -> Result<(usize, u64)> {
+    ensure_writable("prune the quarantine")?;
     let config = load_config();
     let mut list = read_index();
     let now = Utc::now();
@@ -171,11 +549,9 @@ pub fn cleanup_quarantine() -> Result<(usize, u64)> {
     let mut to_remove = Vec::new();
     
     // Check retention period
-    if config.retention_days > 0 {
-        for rec in &list {
-            if (now - rec.created_at).num_days() > config.retention_days {
-                to_remove.push(rec.id.clone());
-            }
+    for rec in &list {
+        if is_expired(rec, &config, now) {
+            to_remove.push(rec.id.clone());
         }
     }
     
@@ -189,10 +565,11 @@ pub fn cleanup_quarantine() -> Result<(usize, u64)> {
         }
     }
     
-    // Check size quota
+    // Check size quota (packed entries count at their size on disk)
     if config.max_size_gb > 0 {
         let max_bytes = config.max_size_gb * 1024 * 1024 * 1024;
-        let total: u64 = list.iter().map(|r| r.size_bytes).sum();
+        let stored = |r: &QuarantineRecord| r.packed_bytes.unwrap_or(r.size_bytes);
+        let total: u64 = list.iter().map(stored).sum();
         
         if total > max_bytes {
             let mut current_size = total;
@@ -202,7 +579,7 @@ pub fn cleanup_quarantine() -> Result<(usize, u64)> {
                 }
                 if !to_remove.contains(&rec.id) {
                     to_remove.push(rec.id.clone());
-                    current_size -= rec.size_bytes;
+                    current_size -= stored(rec);
                 }
             }
         }
@@ -213,7 +590,7 @@ pub fn cleanup_quarantine() -> Result<(usize, u64)> {
         if let Some(rec) = list.iter().find(|r| &r.id == id) {
             let qpath = PathBuf::from(&rec.quarantine_path);
             if qpath.exists() {
-                if let Ok(()) = fs::remove_dir_all(&qpath) {
+                if let Ok(()) = remove_entry_files(&qpath) {
                     bytes_freed += rec.size_bytes;
                     cleaned_count += 1;
                 }
@@ -224,19 +601,176 @@ pub fn cleanup_quarantine() -> Result<(usize, u64)> {
     // Remove from index
     list.retain(|r| !to_remove.contains(&r.id));
     write_index(&list)?;
+
+    if cleaned_count > 0 {
+        gc_pool(&objects_dir());
+    }
     
     Ok((cleaned_count, bytes_freed))
 }
 
+/// Permanently delete the oldest entries until at least `bytes` were freed
+/// (or the quarantine is empty). Returns entries removed and bytes freed.
+pub fn evict_quarantine(bytes: u64) -> crate::Result<(usize, u64)> {
+    evict_quarantine_impl(bytes).map_err(Error::lift(Error::Quarantine))
+}
+
+fn evict_quarantine_impl(bytes: u64) -> Result<(usize, u64)> {
+    ensure_writable("prune the quarantine")?;
+    let mut list = read_index();
+    list.sort_by_key(|r| r.created_at);
+
+    let mut freed: u64 = 0;
+    let mut removed = 0;
+    while freed < bytes && removed < list.len() {
+        let rec = &list[removed];
+        let qpath = PathBuf::from(&rec.quarantine_path);
+        if qpath.exists() {
+            remove_entry_files(&qpath).with_context(|| format!("Failed to delete {:?}", qpath))?;
+        }
+        freed += rec.size_bytes;
+        removed += 1;
+    }
+
+    if removed > 0 {
+        write_index(&list[removed..])?;
+        gc_pool(&objects_dir());
+    }
+    Ok((removed, freed))
+}
+
+/// Permanently delete entries whose expiry has passed.
+/// Returns the expired records so callers can notify about what was removed.
+pub fn expire_quarantine() -> crate::Result<Vec<QuarantineRecord>> {
+    ensure_writable("prune the quarantine")?;
+    expire_in(&quarantine_dir(), &load_config(), None, false, Utc::now()).map_err(Error::lift(Error::Quarantine))
+}
+
+/// `expire_quarantine` with a retention window of `days` for every entry,
+/// whatever expiry it was given when quarantined, so a shortened window
+/// applies to what is already held (0 deletes nothing). With `dry_run` the
+/// entries are only returned.
+pub fn prune_quarantine(days: i64, dry_run: bool) -> crate::Result<Vec<QuarantineRecord>> {
+    if !dry_run {
+        ensure_writable("prune the quarantine")?;
+    }
+    expire_in(&quarantine_dir(), &load_config(), Some(days), dry_run, Utc::now()).map_err(Error::lift(Error::Quarantine))
+}
+
+/// Delete the expired entries of the quarantine in `dir`: those past their
+/// expiry under `config`, or with `window`, those at least that many days
+/// old. Entries that could not be deleted stay listed.
+fn expire_in(
+    dir: &Path,
+    config: &QuarantineConfig,
+    window: Option<i64>,
+    dry_run: bool,
+    now: DateTime<Utc>,
+) -> Result<Vec<QuarantineRecord>> {
+    let (expired, kept): (Vec<_>, Vec<_>) = read_index_in(dir)
+        .into_iter()
+        .partition(|r| match window {
+            Some(days) => days > 0 && r.created_at + Duration::days(days) <= now,
+            None => is_expired(r, config, now),
+        });
+    if dry_run {
+        return Ok(expired);
+    }
+
+    let mut removed = Vec::new();
+    let mut failed = Vec::new();
+    for rec in expired {
+        let qpath = PathBuf::from(&rec.quarantine_path);
+        if !qpath.exists() || remove_entry_files(&qpath).is_ok() {
+            removed.push(rec);
+        } else {
+            failed.push(rec);
+        }
+    }
+
+    let mut list = kept;
+    list.extend(failed);
+    write_index_in(dir, &list)?;
+
+    if !removed.is_empty() {
+        gc_pool(&dir.join(".objects"));
+    }
+    Ok(removed)
+}
+
+/// Entries that will expire within the given number of days
+pub fn expiring_within(days: i64) -> Vec<QuarantineRecord> {
+    let config = load_config();
+    let horizon = Utc::now() + Duration::days(days);
+    read_index()
+        .into_iter()
+        .filter(|r| effective_expiry(r, &config).map(|t| t <= horizon).unwrap_or(false))
+        .collect()
+}
+
+/// Add or take away write access throughout `root`: the owner's when
+/// opening it up, everyone's when sealing it
+fn set_tree_writable(root: &Path, writable: bool) -> Result<()> {
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry?;
+        if entry.path_is_symlink() {
+            continue;
+        }
+        let mut perms = entry.metadata()?.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = perms.mode();
+            perms.set_mode(if writable { mode | 0o200 } else { mode & !0o222 });
+        }
+        #[cfg(not(unix))]
+        perms.set_readonly(!writable);
+        fs::set_permissions(entry.path(), perms)
+            .with_context(|| format!("Failed to change permissions of {:?}", entry.path()))?;
+    }
+    Ok(())
+}
+
+/// Open up a read-only tree (Go module cache entries) so it can be moved,
+/// deduplicated and deleted; returns whether it was read-only
+fn unseal(target: &Path) -> Result<bool> {
+    let read_only = fs::metadata(target).is_ok_and(|m| m.is_dir() && m.permissions().readonly());
+    if read_only {
+        set_tree_writable(target, true)?;
+    }
+    Ok(read_only)
+}
+
+/// Seal a tree `unseal` opened up again after a failed move
+fn reseal(target: &Path, read_only: bool) {
+    if read_only && target.exists() {
+        let _ = set_tree_writable(target, false);
+    }
+}
+
 /// Move target to quarantine with lazy SHA256
 /// SHA256 is only computed after move succeeds (optimizes for common case)
-pub fn move_to_quarantine(target: &Path) -> Result<QuarantineRecord> {
+pub fn move_to_quarantine(target: &Path) -> crate::Result<QuarantineRecord> {
+    move_to_quarantine_impl(target, None).map_err(Error::lift(Error::Quarantine))
+}
+
+/// `move_to_quarantine`, hashing `stable` (the target as a filesystem
+/// snapshot holds it) before the move instead of the moved copy
+pub fn move_to_quarantine_hashed(target: &Path, stable: &Path) -> crate::Result<QuarantineRecord> {
+    move_to_quarantine_impl(target, Some(stable)).map_err(Error::lift(Error::Quarantine))
+}
+
+fn move_to_quarantine_impl(target: &Path, stable: Option<&Path>) -> Result<QuarantineRecord> {
+    ensure_writable("quarantine packages")?;
+    ensure_not_protected(target)?;
+    let read_only = unseal(target)?;
+
     // Run cleanup first if needed
     let stats = get_quarantine_stats();
     let config = load_config();
     
     if config.max_entries > 0 && stats.total_entries >= config.max_entries {
-        cleanup_quarantine()?;
+        cleanup_quarantine_impl()?;
     }
     
     let qdir = quarantine_dir();
@@ -247,46 +781,52 @@ pub fn move_to_quarantine(target: &Path) -> Result<QuarantineRecord> {
     
     // Get size first (faster than full hash)
     let size = quick_size(target);
+    let stable_checksum = stable.map(|s| sha256_dir(s).map(|(hash, _)| hash));
     
     // Perform the move
-    if let Err(e) = fs::rename(target, &qpath) {
-        // Handle cross-device link errors - try copy-and-delete as fallback
-        let copy_opts = fs_extra::dir::CopyOptions::new().content_only(true);
-        
-        // Create target directory first for content_only copy
-        fs::create_dir_all(&qpath)
-            .with_context(|| format!("Failed to create quarantine directory {:?}", qpath))?;
-        
-        if let Err(copy_err) = fs_extra::dir::copy(target, &qpath, &copy_opts) {
-            fs::remove_dir_all(&qpath).ok();
-            return Err(anyhow::anyhow!(
-                "Failed to move {:?} to quarantine (rename: {}, copy: {})", 
-                target, e, copy_err
-            ));
-        }
-        
-        if let Err(rm_err) = fs::remove_dir_all(target) {
-            fs::remove_dir_all(&qpath).ok();
-            return Err(anyhow::anyhow!(
-                "Failed to remove original {:?} after copy: {}", 
-                target, rm_err
-            ));
-        }
-    }
+    let moving = timings::span(TimedPhase::Move);
+    move_tree(target, &qpath, read_only)?;
+    drop(moving);
     
     // Compute SHA256 AFTER move (lazy - only if move succeeds)
-    let checksum = match sha256_dir(&qpath) {
-        Ok((hash, _)) => hash,
+    let checksum = match stable_checksum.unwrap_or_else(|| sha256_dir(&qpath).map(|(hash, _)| hash)) {
+        Ok(hash) => hash,
         Err(_) => "unknown".to_string(), // Don't fail on hash error
     };
+
+    // Pack the tree when asked to; if that fails the entry stays a tree
+    let packed = if compress_enabled(&config) {
+        match pack_tree(&qpath) {
+            Ok(packed) => Some(packed),
+            Err(e) => {
+                eprintln!("Warning: keeping {:?} unpacked: {:#}", qpath, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Share identical files with earlier entries (e.g. the same package@version
+    // quarantined from several projects)
+    let shared_bytes = if config.dedupe && packed.is_none() {
+        dedupe_into_pool(&qpath, &objects_dir()).unwrap_or(0)
+    } else {
+        0
+    };
     
+    let now = Utc::now();
     let rec = QuarantineRecord {
         id,
         original_path: target.to_string_lossy().to_string(),
-        quarantine_path: qpath.to_string_lossy().to_string(),
+        quarantine_path: packed.as_ref().map_or(&qpath, |(archive, _)| archive).to_string_lossy().to_string(),
         sha256: checksum,
         size_bytes: size,
-        created_at: Utc::now(),
+        created_at: now,
+        shared_bytes,
+        expires_at: expiry_for(now, &config),
+        read_only,
+        packed_bytes: packed.map(|(_, bytes)| bytes),
     };
     
     let mut list = read_index();
@@ -296,8 +836,39 @@ pub fn move_to_quarantine(target: &Path) -> Result<QuarantineRecord> {
     Ok(rec)
 }
 
+/// Move `target` to `qpath`, copying and deleting when a rename cannot cross
+/// devices. On failure the quarantine copy is dropped and the target sealed
+/// again if it was read-only.
+fn move_tree(target: &Path, qpath: &Path, read_only: bool) -> Result<()> {
+    let Err(rename_err) = fs::rename(target, qpath) else { return Ok(()) };
+    let copied = (|| -> Result<()> {
+        // Create target directory first for content_only copy
+        fs::create_dir_all(qpath)
+            .with_context(|| format!("Failed to create quarantine directory {:?}", qpath))?;
+        let copy_opts = fs_extra::dir::CopyOptions::new().content_only(true);
+        fs_extra::dir::copy(target, qpath, &copy_opts).map_err(|copy_err| anyhow::anyhow!(
+            "Failed to move {:?} to quarantine (rename: {}, copy: {})", target, rename_err, copy_err
+        ))?;
+        fs::remove_dir_all(target)
+            .with_context(|| format!("Failed to remove original {:?} after copy", target))
+    })();
+    if copied.is_err() {
+        fs::remove_dir_all(qpath).ok();
+        reseal(target, read_only);
+    }
+    copied
+}
+
 /// Move to quarantine with explicit skip of SHA256 (fastest option)
-pub fn move_to_quarantine_fast(target: &Path) -> Result<QuarantineRecord> {
+pub fn move_to_quarantine_fast(target: &Path) -> crate::Result<QuarantineRecord> {
+    move_to_quarantine_fast_impl(target).map_err(Error::lift(Error::Quarantine))
+}
+
+fn move_to_quarantine_fast_impl(target: &Path) -> Result<QuarantineRecord> {
+    ensure_writable("quarantine packages")?;
+    ensure_not_protected(target)?;
+    let read_only = unseal(target)?;
+
     let qdir = quarantine_dir();
 
     fs::create_dir_all(&qdir).ok();
@@ -307,36 +878,109 @@ pub fn move_to_quarantine_fast(target: &Path) -> Result<QuarantineRecord> {
     
     let size = quick_size(target);
     
-    if let Err(e) = fs::rename(target, &qpath) {
-        let copy_opts = fs_extra::dir::CopyOptions::new().content_only(true);
-        fs::create_dir_all(&qpath)?;
-        
-        if let Err(copy_err) = fs_extra::dir::copy(target, &qpath, &copy_opts) {
-            fs::remove_dir_all(&qpath).ok();
-            return Err(anyhow::anyhow!(
-                "Failed to quarantine {:?}: rename={}, copy={}", 
-                target, e, copy_err
-            ));
+    let moving = timings::span(TimedPhase::Move);
+    move_tree(target, &qpath, read_only)?;
+    drop(moving);
+
+    // A packed tree is gone before the hash queue would get to it, so it is
+    // hashed here; packing reads every file anyway
+    let config = load_config();
+    let packed = if compress_enabled(&config) {
+        let checksum = sha256_dir(&qpath).map_or_else(|_| "unknown".to_string(), |(hash, _)| hash);
+        match pack_tree(&qpath) {
+            Ok(packed) => Some((checksum, packed)),
+            Err(e) => {
+                eprintln!("Warning: keeping {:?} unpacked: {:#}", qpath, e);
+                None
+            }
         }
-        fs::remove_dir_all(target)?;
-    }
+    } else {
+        None
+    };
     
+    let now = Utc::now();
     let rec = QuarantineRecord {
         id,
         original_path: target.to_string_lossy().to_string(),
-        quarantine_path: qpath.to_string_lossy().to_string(),
-        sha256: "deferred".to_string(), // Not computed
+        quarantine_path: packed.as_ref().map_or(&qpath, |(_, (archive, _))| archive).to_string_lossy().to_string(),
+        // Filled in by the hash queue unless packed
+        sha256: packed.as_ref().map_or_else(|| "deferred".to_string(), |(checksum, _)| checksum.clone()),
         size_bytes: size,
-        created_at: Utc::now(),
+        created_at: now,
+        shared_bytes: 0,
+        expires_at: expiry_for(now, &config),
+        read_only,
+        packed_bytes: packed.as_ref().map(|(_, (_, bytes))| *bytes),
     };
     
     let mut list = read_index();
     list.push(rec.clone());
     write_index(&list)?;
+    if packed.is_none() {
+        crate::hash_queue::enqueue_default(JobKind::QuarantineChecksum, &qpath, Some(&rec.id));
+    }
     
     Ok(rec)
 }
 
+/// Fill in the checksum of quarantine record `id` (computed by the hash
+/// queue). Returns whether the record still exists.
+pub fn record_checksum(id: &str, sha256: &str) -> crate::Result<bool> {
+    record_checksum_impl(id, sha256).map_err(Error::lift(Error::Quarantine))
+}
+
+fn record_checksum_impl(id: &str, sha256: &str) -> Result<bool> {
+    let mut list = read_index();
+    let Some(rec) = list.iter_mut().find(|r| r.id == id) else {
+        return Ok(false);
+    };
+    rec.sha256 = sha256.to_string();
+    write_index(&list)?;
+    Ok(true)
+}
+
+/// Outcome of `quarantine_targets`
+pub struct QuarantineBatch {
+    /// Each target with its record or failure
+    pub results: Vec<(PathBuf, crate::Result<QuarantineRecord>)>,
+    /// Bytes and files moved into quarantine, and how fast
+    pub throughput: ThroughputSummary,
+}
+
+/// Quarantine several targets, checking for cancellation between items so a
+/// cancelled batch never leaves a target half-moved. Per-target failures are
+/// returned alongside the target instead of aborting the batch. Progress
+/// updates carry the measured throughput and an estimate of the time left.
+/// Targets a snapshot in `snapshots` holds are hashed there (unless `fast`).
+pub fn quarantine_targets(
+    targets: &[PathBuf],
+    fast: bool,
+    snapshots: &[Snapshot],
+    ctx: &OperationContext,
+) -> crate::Result<QuarantineBatch> {
+    ensure_writable("quarantine packages")?;
+    let total = targets.len() as u64;
+    let mut results = Vec::with_capacity(targets.len());
+    let mut meter = ThroughputMeter::start();
+    for (i, t) in targets.iter().enumerate() {
+        ctx.check()?;
+        ctx.report_rate(Phase::Quarantine, i as u64, Some(total), Some(t), meter.rate(total - i as u64, None));
+        let files = count_files(t);
+        let stable = snapshots.iter().find_map(|s| s.view_of(t)).filter(|v| v.exists());
+        let result = match stable {
+            _ if fast => move_to_quarantine_fast(t),
+            Some(stable) => move_to_quarantine_hashed(t, &stable),
+            None => move_to_quarantine(t),
+        };
+        if let Ok(rec) = &result {
+            meter.record(rec.size_bytes, files);
+        }
+        results.push((t.clone(), result));
+    }
+    ctx.report_rate(Phase::Quarantine, total, Some(total), None, meter.rate(0, None));
+    Ok(QuarantineBatch { results, throughput: meter.summary() })
+}
+
 #[allow(dead_code)]
 pub fn list_quarantine() -> Vec<QuarantineRecord> { 
     read_index() 
@@ -352,22 +996,145 @@ pub fn find_quarantine_by_id(id: &str) -> Option<QuarantineRecord> {
     read_index().into_iter().find(|r| r.id == id)
 }
 
-pub fn rollback_record(rec: &QuarantineRecord) -> Result<()> {
+/// A quarantine entry with the expiry it is actually subject to
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineEntry {
+    #[serde(flatten)]
+    pub record: QuarantineRecord,
+    /// `expires_at`, or the end of the retention window for records without one
+    pub expires: Option<DateTime<Utc>>,
+    /// Whole days until `expires`; negative once it has passed
+    pub expires_in_days: Option<i64>,
+}
+
+/// Quarantine entries, newest first
+pub fn quarantine_entries() -> Vec<QuarantineEntry> {
+    let config = load_config();
+    let now = Utc::now();
+    let mut list = read_index();
+    list.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+    list.into_iter()
+        .map(|record| {
+            let expires = effective_expiry(&record, &config);
+            QuarantineEntry { expires_in_days: expires.map(|t| (t - now).num_days()), expires, record }
+        })
+        .collect()
+}
+
+/// A file inside a quarantined tree
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineFile {
+    /// Relative to the original path
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+/// Files of an entry in path order, at most `limit` of them, and how many
+/// there are in all
+pub fn quarantine_contents(rec: &QuarantineRecord, limit: usize) -> (Vec<QuarantineFile>, usize) {
+    let root = Path::new(&rec.quarantine_path);
+    if rec.packed_bytes.is_some() {
+        return packed_contents(root, limit).unwrap_or_default();
+    }
+    let mut files = Vec::new();
+    let mut total = 0;
+    for entry in walkdir::WalkDir::new(root).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        total += 1;
+        if files.len() < limit {
+            files.push(QuarantineFile {
+                path: entry.path().strip_prefix(root).unwrap_or(entry.path()).to_string_lossy().to_string(),
+                size_bytes: entry.metadata().map(|m| m.len()).unwrap_or(0),
+            });
+        }
+    }
+    (files, total)
+}
+
+/// `quarantine_contents` of a packed entry, read from the tarball's headers
+fn packed_contents(archive: &Path, limit: usize) -> Result<(Vec<QuarantineFile>, usize)> {
+    let mut tar = tar::Archive::new(zstd::Decoder::new(fs::File::open(archive)?)?);
+    let mut files = Vec::new();
+    for entry in tar.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_dir() {
+            continue;
+        }
+        let path = entry.path()?;
+        files.push(QuarantineFile {
+            path: path.strip_prefix(".").unwrap_or(&path).to_string_lossy().to_string(),
+            size_bytes: entry.header().size()?,
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let total = files.len();
+    files.truncate(limit);
+    Ok((files, total))
+}
+
+/// Permanently delete one entry ahead of its expiry
+pub fn delete_quarantine_entry(rec: &QuarantineRecord) -> crate::Result<()> {
+    delete_quarantine_entry_impl(rec).map_err(Error::lift(Error::Quarantine))
+}
+
+fn delete_quarantine_entry_impl(rec: &QuarantineRecord) -> Result<()> {
+    ensure_writable("delete from quarantine")?;
+    let qpath = PathBuf::from(&rec.quarantine_path);
+    if qpath.exists() {
+        remove_entry_files(&qpath).with_context(|| format!("Failed to delete {:?}", qpath))?;
+    }
+    let mut list = read_index();
+    list.retain(|r| r.id != rec.id);
+    write_index(&list)?;
+
+    gc_pool(&objects_dir());
+    Ok(())
+}
+
+pub fn rollback_record(rec: &QuarantineRecord) -> crate::Result<()> {
+    rollback_record_impl(rec).map_err(Error::lift(Error::Quarantine))
+}
+
+fn rollback_record_impl(rec: &QuarantineRecord) -> Result<()> {
+    ensure_writable("restore from quarantine")?;
     let orig = PathBuf::from(&rec.original_path);
     let q = PathBuf::from(&rec.quarantine_path);
     
     if let Some(parent) = orig.parent() { 
         fs::create_dir_all(parent).ok(); 
     }
+
+    // Detach from the shared object pool before handing files back
+    if rec.packed_bytes.is_none() {
+        break_hardlinks(&q, &objects_dir())?;
+    }
+
+    // A link left standing in for the moved tree (see `tree_share`) gives way
+    if fs::symlink_metadata(&orig).is_ok_and(|m| m.file_type().is_symlink()) {
+        crate::symlink::remove_symlink(&orig)?;
+    }
     
-    fs::rename(&q, &orig).with_context(|| {
-        format!("Failed to rollback from quarantine: {:?} -> {:?}", q, orig)
-    })?;
+    if rec.packed_bytes.is_some() {
+        unpack_tree(&q, &orig).context("Failed to rollback from quarantine")?;
+        fs::remove_file(&q).ok();
+    } else {
+        fs::rename(&q, &orig).with_context(|| {
+            format!("Failed to rollback from quarantine: {:?} -> {:?}", q, orig)
+        })?;
+    }
+    if rec.read_only {
+        set_tree_writable(&orig, false)
+            .with_context(|| format!("Restored {:?} but could not make it read-only again", orig))?;
+    }
     
     // Remove from index
     let mut list = read_index();
     list.retain(|r| r.id != rec.id);
     write_index(&list)?;
+
+    gc_pool(&objects_dir());
     
     Ok(())
 }
@@ -385,6 +1152,89 @@ mod tests {
         assert_eq!(config.max_entries, 200);
     }
 
+    #[test]
+    fn test_effective_expiry() {
+        let config = QuarantineConfig::default();
+        let created = Utc::now() - Duration::days(40);
+        let mut rec = QuarantineRecord { created_at: created, ..QuarantineRecord::for_test("1", "/a", 0) };
+
+        // Legacy record: expiry derived from retention window
+        assert_eq!(effective_expiry(&rec, &config), Some(created + Duration::days(30)));
+        assert!(is_expired(&rec, &config, Utc::now()));
+
+        // Explicit expiry wins over the configured window
+        rec.expires_at = Some(Utc::now() + Duration::days(1));
+        assert!(!is_expired(&rec, &config, Utc::now()));
+
+        let forever = QuarantineConfig { retention_days: 0, ..QuarantineConfig::default() };
+        assert_eq!(expiry_for(created, &forever), None);
+    }
+
+    #[test]
+    fn test_expire_and_prune_window() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path();
+        let now = Utc::now();
+        let entry = |id: &str, age_days: i64, expires_in_days: i64| {
+            let qpath = dir.join(format!("{}_pkg", id));
+            fs::create_dir_all(&qpath).unwrap();
+            QuarantineRecord {
+                quarantine_path: qpath.to_string_lossy().to_string(),
+                created_at: now - Duration::days(age_days),
+                expires_at: Some(now + Duration::days(expires_in_days)),
+                ..QuarantineRecord::for_test(id, format!("/app/node_modules/{}", id), 10)
+            }
+        };
+        // Two entries quarantined for 30 days, 20 and 2 days ago, and one past its expiry
+        let list = [entry("old", 20, 10), entry("new", 2, 28), entry("due", 40, -10)];
+        write_index_in(dir, &list).unwrap();
+        let config = QuarantineConfig::default();
+        let ids = |recs: &[QuarantineRecord]| recs.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+
+        // A dry run lists without deleting
+        assert_eq!(ids(&expire_in(dir, &config, Some(7), true, now).unwrap()), ["old", "due"]);
+        assert_eq!(read_index_in(dir).len(), 3);
+        assert!(dir.join("old_pkg").is_dir());
+
+        // Expiry keeps what each entry was promised
+        assert_eq!(ids(&expire_in(dir, &config, None, false, now).unwrap()), ["due"]);
+        assert!(!dir.join("due_pkg").exists());
+
+        // A shortened window applies to what is already held; 0 keeps everything
+        assert!(expire_in(dir, &config, Some(0), false, now).unwrap().is_empty());
+        assert_eq!(ids(&expire_in(dir, &config, Some(7), false, now).unwrap()), ["old"]);
+        assert_eq!(ids(&read_index_in(dir)), ["new"]);
+        assert!(dir.join("new_pkg").is_dir() && !dir.join("old_pkg").exists());
+    }
+
+    #[test]
+    fn test_validate_target() {
+        let temp = tempdir().unwrap();
+        let root = temp.path().join("work");
+        let pkg = root.join("app").join("node_modules").join("left-pad");
+        let src = root.join("app").join("src");
+        fs::create_dir_all(&pkg).unwrap();
+        fs::create_dir_all(&src).unwrap();
+        let roots = vec![root.clone()];
+
+        assert!(validate_target(&pkg, &roots).is_ok());
+        assert!(validate_target(&src, &roots).is_err());
+        assert!(validate_target(&root, &roots).is_err());
+        assert!(validate_target(&pkg, &[temp.path().join("elsewhere")]).is_err());
+        assert!(validate_target(&root.join("missing"), &roots).is_err());
+    }
+
+    #[test]
+    fn test_protected_paths() {
+        let q = quarantine_dir().join("123_left-pad");
+        assert!(is_protected_path(&q));
+        assert!(move_to_quarantine_fast(&q).is_err());
+
+        let store = crate::symlink::get_global_store_path().unwrap().join("react");
+        assert!(is_protected_path(&store));
+        assert!(!is_protected_path(Path::new("/work/app/node_modules/react")));
+    }
+
     #[test]
     fn test_quick_size() {
         let temp = tempdir().unwrap();
@@ -395,6 +1245,61 @@ mod tests {
         assert_eq!(size, 11); // "hello world".len()
     }
 
+    #[test]
+    fn test_pack_and_unpack_tree() {
+        let temp = tempdir().unwrap();
+        let entry = temp.path().join("1_lodash.debounce");
+        fs::create_dir_all(entry.join("lib")).unwrap();
+        fs::write(entry.join("package.json"), "{}").unwrap();
+        fs::write(entry.join("lib/index.js"), "module.exports = 1".repeat(100)).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("lib/index.js", entry.join("main.js")).unwrap();
+
+        let (archive, bytes) = pack_tree(&entry).unwrap();
+        assert_eq!(archive, temp.path().join("1_lodash.debounce.tar.zst"));
+        assert!(!entry.exists());
+        assert!(bytes > 0 && bytes < 1800);
+
+        let rec = QuarantineRecord {
+            quarantine_path: archive.to_string_lossy().to_string(),
+            packed_bytes: Some(bytes),
+            ..QuarantineRecord::for_test("1", "/a/node_modules/lodash.debounce", 1802)
+        };
+        let (files, total) = quarantine_contents(&rec, 1);
+        assert_eq!(total, if cfg!(unix) { 3 } else { 2 });
+        assert_eq!(files[0].path, Path::new("lib").join("index.js").to_string_lossy());
+        assert_eq!(files[0].size_bytes, 1800);
+
+        let restored = temp.path().join("app/node_modules/lodash.debounce");
+        fs::create_dir_all(restored.parent().unwrap()).unwrap();
+        unpack_tree(&archive, &restored).unwrap();
+        assert_eq!(fs::read_to_string(restored.join("lib/index.js")).unwrap().len(), 1800);
+        #[cfg(unix)]
+        assert_eq!(fs::read_link(restored.join("main.js")).unwrap(), Path::new("lib/index.js"));
+        // Nothing is left half-extracted beside it
+        assert_eq!(fs::read_dir(restored.parent().unwrap()).unwrap().count(), 1);
+        assert!(unpack_tree(&archive, &restored).is_err());
+    }
+
+    #[test]
+    fn test_quarantine_contents() {
+        let temp = tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("lib")).unwrap();
+        fs::write(temp.path().join("package.json"), "{}").unwrap();
+        fs::write(temp.path().join("lib/index.js"), "module.exports = 1").unwrap();
+        fs::write(temp.path().join("README.md"), "# pad").unwrap();
+        let rec = QuarantineRecord {
+            quarantine_path: temp.path().to_string_lossy().to_string(),
+            ..QuarantineRecord::for_test("1", "/a/node_modules/left-pad", 0)
+        };
+
+        let (files, total) = quarantine_contents(&rec, 2);
+        assert_eq!(total, 3);
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["README.md", Path::new("lib").join("index.js").to_str().unwrap()]);
+        assert_eq!(files[0].size_bytes, 5);
+    }
+
     #[test]
     fn test_sha256_dir() {
         let temp = tempdir().unwrap();
@@ -404,6 +1309,95 @@ mod tests {
         let (hash, size) = sha256_dir(temp.path()).unwrap();
         assert!(!hash.is_empty());
         assert_eq!(size, 18); // 9 + 9
+
+        // A copy elsewhere hashes the same
+        let copy = tempdir().unwrap();
+        fs::write(copy.path().join("b.txt"), "content b").unwrap();
+        fs::write(copy.path().join("a.txt"), "content a").unwrap();
+        assert_eq!(sha256_dir(copy.path()).unwrap().0, hash);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dedupe_into_pool() {
+        let temp = tempdir().unwrap();
+        let pool = temp.path().join(".objects");
+        let a = temp.path().join("a");
+        let b = temp.path().join("b");
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("index.js"), "module.exports = 1").unwrap();
+        fs::write(b.join("index.js"), "module.exports = 1").unwrap();
+
+        assert_eq!(dedupe_into_pool(&a, &pool).unwrap(), 0);
+        assert_eq!(dedupe_into_pool(&b, &pool).unwrap(), 18);
+        let meta = fs::metadata(b.join("index.js")).unwrap();
+        assert_eq!(link_count(&meta), 3);
+
+        // Rolling back one entry must not affect the other
+        break_hardlinks(&b, &pool).unwrap();
+        fs::write(b.join("index.js"), "changed").unwrap();
+        assert_eq!(fs::read_to_string(a.join("index.js")).unwrap(), "module.exports = 1");
+
+        fs::remove_dir_all(&a).unwrap();
+        gc_pool(&pool);
+        assert_eq!(fs::read_dir(&pool).unwrap().count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dedupe_into_pool_skips_files_linked_elsewhere() {
+        let temp = tempdir().unwrap();
+        let pool = temp.path().join(".objects");
+        let store = temp.path().join("store");
+        let (a, b) = (temp.path().join("a"), temp.path().join("b"));
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join("index.js"), "module.exports = 1").unwrap();
+        for dir in [&a, &b] {
+            fs::create_dir_all(dir).unwrap();
+            fs::hard_link(store.join("index.js"), dir.join("index.js")).unwrap();
+        }
+
+        assert_eq!(dedupe_into_pool(&a, &pool).unwrap(), 0);
+        assert_eq!(dedupe_into_pool(&b, &pool).unwrap(), 0);
+        assert_eq!(fs::read_dir(&pool).unwrap().count(), 0);
+
+        // Rolling back keeps the link to the store
+        break_hardlinks(&a, &pool).unwrap();
+        assert_eq!(link_count(&fs::metadata(store.join("index.js")).unwrap()), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dedupe_into_pool_keeps_modes_apart() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = tempdir().unwrap();
+        let pool = temp.path().join(".objects");
+        let (a, b) = (temp.path().join("a"), temp.path().join("b"));
+        for (dir, mode) in [(&a, 0o644), (&b, 0o755)] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("cli.js"), "#!/usr/bin/env node").unwrap();
+            fs::set_permissions(dir.join("cli.js"), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        dedupe_into_pool(&a, &pool).unwrap();
+        assert_eq!(dedupe_into_pool(&b, &pool).unwrap(), 0);
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!((mode(&a.join("cli.js")), mode(&b.join("cli.js"))), (0o644, 0o755));
+        assert_eq!(fs::read_dir(&pool).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_read_only_refuses_quarantine_and_prune() {
+        let temp = tempdir().unwrap();
+        let target = temp.path().join("node_modules/left-pad");
+        fs::create_dir_all(&target).unwrap();
+
+        let _read_only = read_only_for_test();
+        assert!(matches!(move_to_quarantine(&target), Err(Error::ReadOnly(_))));
+        assert!(matches!(move_to_quarantine_fast(&target), Err(Error::ReadOnly(_))));
+        assert!(matches!(prune_quarantine(1, false), Err(Error::ReadOnly(_))));
+        assert!(target.is_dir());
     }
 
     #[test]
@@ -420,4 +1414,26 @@ mod tests {
         assert!(!hash.is_empty());
         assert_eq!(size, 4);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unseal_read_only_tree() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = tempdir().unwrap();
+        let module = temp.path().join("errors@v0.9.1");
+        fs::create_dir_all(module.join("internal")).unwrap();
+        fs::write(module.join("internal/errors.go"), "package errors").unwrap();
+        set_tree_writable(&module, false).unwrap();
+        let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!((mode(&module), mode(&module.join("internal/errors.go"))), (0o555, 0o444));
+
+        // Opened up, the tree can be deleted; sealed again, it is as Go left it
+        assert!(unseal(&module).unwrap());
+        assert!(!unseal(&module).unwrap());
+        assert_eq!(mode(&module.join("internal")), 0o755);
+        reseal(&module, true);
+        assert_eq!(mode(&module.join("internal")), 0o555);
+        set_tree_writable(&module, true).unwrap();
+        fs::remove_dir_all(&module).unwrap();
+    }
 }
@@ -4,18 +4,82 @@
 //! - File modification times (mtime) to detect changes
 //! - Package fingerprints for quick change detection
 //! - Directory sizes to avoid redundant walks
+//! - Directories confirmed to contain no packages (negative entries), so large
+//!   unrelated trees are skipped instead of walked again
+//!
+//! Entries expire after a per-entry TTL. Fingerprints only look at a
+//! directory's own mtime and children, so the TTL bounds how long a change
+//! deep inside a negatively cached tree can go unnoticed.
+//!
+//! The default (`fast`) validation only fingerprints a directory's own mtime,
+//! children and package.json, so an in-place edit of a nested file goes
+//! unnoticed. `thorough` validation additionally compares the newest mtime,
+//! file count and byte count of the whole subtree, at the cost of walking it.
+//!
+//! Where the platform keeps a change journal (see `change_feed`), fast
+//! validation reads it from the position saved by the previous scan and
+//! trusts every entry below a root that no reported change touches, without
+//! fingerprinting it. Entries below a changed directory are fingerprinted as
+//! usual, and without a complete journal the fingerprints decide alone.
+//!
+//! Projects also remember the dependencies parsed from their lockfile, keyed
+//! by a hash of the lockfile and of package.json's dependency fields. Edits
+//! to scripts, version or other unrelated fields leave both hashes unchanged,
+//! so the lockfile (the most expensive per-project step) is not parsed again.
+//!
+//! The cache lives in its own SQLite database (`scan_cache.db` in the cache directory).
+//! Scans load only the rows below their roots and `save` writes only the rows
+//! changed since loading. A `scan_cache.json` from earlier versions is imported
+//! on first open.
 //!
 //! Expected improvement: 5-10x faster scans on subsequent runs.
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::SystemTime;
+use walkdir::WalkDir;
+
+use crate::change_feed::{Change, FeedCursor};
+use crate::native_walk::TreeTotals;
+
+/// How cached entries are checked against the filesystem
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheValidation {
+    /// Directory mtime, child count and package.json mtime
+    #[default]
+    Fast,
+    /// Also the newest mtime, file count and size of the whole subtree
+    Thorough,
+}
 
+impl FromStr for CacheValidation {
+    type Err = String;
 
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Self::Fast),
+            "thorough" => Ok(Self::Thorough),
+            other => Err(format!("unknown cache validation `{}` (expected fast or thorough)", other)),
+        }
+    }
+}
+
+impl fmt::Display for CacheValidation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Fast => "fast",
+            Self::Thorough => "thorough",
+        })
+    }
+}
 
 /// Cached metadata for a single path
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,8 +90,40 @@ pub struct CachedEntry {
     pub fingerprint: String,
     /// Cached directory size (avoids walking)
     pub size_bytes: u64,
+    /// Regular files and directories counted with the size (None = entries
+    /// from before file counts)
+    #[serde(default)]
+    pub file_count: Option<u64>,
+    #[serde(default)]
+    pub dir_count: Option<u64>,
+    /// Bytes hard-linked from outside the directory, kept apart from the size
+    #[serde(default)]
+    pub linked_bytes: u64,
     /// When this cache entry was created
     pub cached_at: DateTime<Utc>,
+    /// When the entry stops being trusted (None = entries from before TTLs)
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Subtree fingerprint, recorded by thorough validation runs
+    #[serde(default)]
+    pub deep_fingerprint: Option<String>,
+}
+
+/// Dependencies parsed from a project's lockfile, with the hashes they were parsed under
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockfileEntry {
+    /// SHA-256 of the lockfile
+    pub lock_hash: String,
+    /// SHA-256 of package.json's dependency-related fields
+    pub deps_hash: String,
+    pub dependencies: Vec<(String, String)>,
+    pub cached_at: DateTime<Utc>,
+}
+
+impl CachedEntry {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
 }
 
 /// Scan cache for incremental scanning
@@ -35,68 +131,343 @@ pub struct CachedEntry {
 pub struct ScanCache {
     /// Path -> cached metadata
     entries: HashMap<String, CachedEntry>,
+    /// Directories whose subtree held no packages or projects
+    #[serde(default)]
+    empty_dirs: HashMap<String, CachedEntry>,
+    /// Project path -> parsed lockfile
+    #[serde(default)]
+    lockfiles: HashMap<String, LockfileEntry>,
     /// When the cache was last saved
     pub last_saved: Option<DateTime<Utc>>,
     /// Cache version for migration
     pub version: u32,
+    /// Validation used by this run
+    #[serde(skip)]
+    validation: CacheValidation,
+    /// Rows added, changed or removed since loading, written by `save`
+    #[serde(skip)]
+    pending: HashSet<(EntryKind, String)>,
+    /// Change journal position per scan root, written by `save`
+    #[serde(skip)]
+    feed_cursors: HashMap<String, FeedCursor>,
+    /// Roots whose changes since the last saved scan are known
+    #[serde(skip)]
+    feed_roots: Vec<PathBuf>,
+    /// Paths reported changed below `feed_roots`, and whether their whole
+    /// subtree is affected
+    #[serde(skip)]
+    feed_changes: BTreeMap<String, bool>,
+}
+
+/// Which map a database row belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum EntryKind {
+    Size,
+    Empty,
+    Lockfile,
+}
+
+impl EntryKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Size => "size",
+            Self::Empty => "empty",
+            Self::Lockfile => "lockfile",
+        }
+    }
 }
 
 impl ScanCache {
-    const CURRENT_VERSION: u32 = 1;
+    const CURRENT_VERSION: u32 = 2;
+    /// Lifetime of a cached directory size
+    pub const SIZE_TTL_DAYS: i64 = 30;
+    /// Lifetime of a negative entry; shorter, since a skipped tree is not looked at all
+    pub const NEGATIVE_TTL_DAYS: i64 = 7;
 
     /// Create a new empty cache
     pub fn new() -> Self {
         Self {
             entries: HashMap::new(),
+            empty_dirs: HashMap::new(),
+            lockfiles: HashMap::new(),
             last_saved: None,
             version: Self::CURRENT_VERSION,
+            validation: CacheValidation::Fast,
+            pending: HashSet::new(),
+            feed_cursors: HashMap::new(),
+            feed_roots: Vec::new(),
+            feed_changes: BTreeMap::new(),
         }
     }
 
-    /// Load cache from disk, or create new if not exists
+    /// Check entries with `validation` from now on
+    pub fn set_validation(&mut self, validation: CacheValidation) {
+        self.validation = validation;
+    }
+
+    /// Load the whole cache from its database, or create an empty one
     pub fn load_or_create(cache_path: &Path) -> Result<Self> {
+        let conn = Self::open_db(cache_path)?;
+        let mut cache = Self::new();
+        cache.load_rows(&conn, None)?;
+        Ok(cache)
+    }
 
-        if cache_path.exists() {
-            let content = fs::read_to_string(cache_path)
-                .with_context(|| format!("Failed to read scan cache from {:?}", cache_path))?;
-            let cache: ScanCache = serde_json::from_str(&content)
-                .with_context(|| "Failed to parse scan cache")?;
-            
-            // Check version compatibility
-            if cache.version != Self::CURRENT_VERSION {
-                eprintln!("Scan cache version mismatch, creating new cache");
-                return Ok(Self::new());
+    /// Load only the entries at or below `roots`; a scan never looks up
+    /// anything else, so large caches are not read in full
+    pub fn load_for_roots(cache_path: &Path, roots: &[PathBuf]) -> Result<Self> {
+        let conn = Self::open_db(cache_path)?;
+        let mut cache = Self::new();
+        for root in roots {
+            cache.load_rows(&conn, Some(root))?;
+            let key = root.to_string_lossy().to_string();
+            let cursor = conn.query_row(
+                "SELECT value FROM scan_meta WHERE key = ?1", params![format!("feed:{}", key)], |row| row.get::<_, String>(0),
+            ).ok().and_then(|value| serde_json::from_str(&value).ok());
+            if let Some(cursor) = cursor {
+                cache.feed_cursors.insert(key, cursor);
             }
-            
-            Ok(cache)
-        } else {
-            Ok(Self::new())
         }
+        Ok(cache)
     }
 
-    /// Persist cache to disk
+    /// Write entries changed since loading (row-level upserts and deletes)
     pub fn save(&mut self, cache_path: &Path) -> Result<()> {
-        // Ensure parent directory exists
+        let mut conn = Self::open_db(cache_path)?;
+        self.write_pending(&mut conn)
+            .with_context(|| format!("Failed to write scan cache to {:?}", cache_path))
+    }
+
+    /// Get the default cache path
+    pub fn default_cache_path() -> PathBuf {
+        crate::paths::cache_dir().join("scan_cache.db")
+    }
+
+    /// Open the database, creating the schema and importing a JSON cache
+    /// left by earlier versions next to it
+    fn open_db(cache_path: &Path) -> Result<Connection> {
         if let Some(parent) = cache_path.parent() {
             fs::create_dir_all(parent)
                 .with_context(|| format!("Failed to create cache directory {:?}", parent))?;
         }
+        let mut conn = Connection::open(cache_path)
+            .with_context(|| format!("Failed to open scan cache at {:?}", cache_path))?;
+        conn.busy_timeout(crate::scan_lease::DB_BUSY_TIMEOUT)?;
 
-        self.last_saved = Some(Utc::now());
-        
-        let content = serde_json::to_string_pretty(self)
-            .with_context(|| "Failed to serialize scan cache")?;
-        
-        fs::write(cache_path, content)
-            .with_context(|| format!("Failed to write scan cache to {:?}", cache_path))?;
-        
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != 0 && version != Self::CURRENT_VERSION {
+            eprintln!("Scan cache version mismatch, creating new cache");
+            conn.execute_batch("DROP TABLE IF EXISTS scan_entries; DROP TABLE IF EXISTS scan_lockfiles; DROP TABLE IF EXISTS scan_meta;")?;
+        }
+        conn.execute_batch(&format!(r#"
+            CREATE TABLE IF NOT EXISTS scan_entries (
+                path TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                mtime TEXT NOT NULL,
+                fingerprint TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                cached_at TEXT NOT NULL,
+                expires_at TEXT,
+                deep_fingerprint TEXT,
+                file_count INTEGER,
+                dir_count INTEGER,
+                linked_bytes INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (path, kind)
+            );
+            CREATE TABLE IF NOT EXISTS scan_lockfiles (
+                path TEXT PRIMARY KEY,
+                lock_hash TEXT NOT NULL,
+                deps_hash TEXT NOT NULL,
+                dependencies TEXT NOT NULL,
+                cached_at TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS scan_meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+            PRAGMA user_version = {};
+        "#, Self::CURRENT_VERSION)).with_context(|| "Failed to initialize scan cache schema")?;
+        let has_counts = conn
+            .prepare("SELECT 1 FROM pragma_table_info('scan_entries') WHERE name = 'file_count'")?
+            .exists([])?;
+        if !has_counts {
+            conn.execute_batch("ALTER TABLE scan_entries ADD COLUMN file_count INTEGER; ALTER TABLE scan_entries ADD COLUMN dir_count INTEGER;")
+                .with_context(|| "Failed to migrate scan cache schema")?;
+        }
+        let has_linked = conn
+            .prepare("SELECT 1 FROM pragma_table_info('scan_entries') WHERE name = 'linked_bytes'")?
+            .exists([])?;
+        if !has_linked {
+            conn.execute_batch("ALTER TABLE scan_entries ADD COLUMN linked_bytes INTEGER NOT NULL DEFAULT 0;")
+                .with_context(|| "Failed to migrate scan cache schema")?;
+        }
+
+        let legacy = cache_path.with_extension("json");
+        if legacy.is_file() {
+            Self::import_json(&mut conn, &legacy)?;
+        }
+        Ok(conn)
+    }
+
+    /// One-time migration of a `scan_cache.json`; the file is removed once imported
+    fn import_json(conn: &mut Connection, legacy: &Path) -> Result<()> {
+        let imported = fs::read_to_string(legacy).ok()
+            .and_then(|content| serde_json::from_str::<ScanCache>(&content).ok())
+            .filter(|c| c.version == Self::CURRENT_VERSION);
+        if let Some(mut old) = imported {
+            let mut fresh = Self::new();
+            fresh.entries = std::mem::take(&mut old.entries);
+            fresh.empty_dirs = std::mem::take(&mut old.empty_dirs);
+            fresh.lockfiles = std::mem::take(&mut old.lockfiles);
+            fresh.pending = fresh.entries.keys().map(|p| (EntryKind::Size, p.clone()))
+                .chain(fresh.empty_dirs.keys().map(|p| (EntryKind::Empty, p.clone())))
+                .chain(fresh.lockfiles.keys().map(|p| (EntryKind::Lockfile, p.clone())))
+                .collect();
+            fresh.write_pending(conn)?;
+        }
+        fs::remove_file(legacy)
+            .with_context(|| format!("Failed to remove migrated scan cache {:?}", legacy))?;
         Ok(())
     }
 
-    /// Get the default cache path
-    pub fn default_cache_path() -> PathBuf {
-        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-        home.join(".packagepurge").join("scan_cache.json")
+    fn write_pending(&mut self, conn: &mut Connection) -> Result<()> {
+        let now = Utc::now();
+        let tx = conn.transaction()?;
+        {
+            let mut upsert = tx.prepare(
+                "INSERT OR REPLACE INTO scan_entries
+                 (path, kind, mtime, fingerprint, size_bytes, cached_at, expires_at, deep_fingerprint, file_count, dir_count, linked_bytes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            let mut delete = tx.prepare("DELETE FROM scan_entries WHERE path = ?1 AND kind = ?2")?;
+            let mut upsert_lock = tx.prepare(
+                "INSERT OR REPLACE INTO scan_lockfiles (path, lock_hash, deps_hash, dependencies, cached_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            let mut delete_lock = tx.prepare("DELETE FROM scan_lockfiles WHERE path = ?1")?;
+            for (kind, path) in self.pending.drain() {
+                let map = match kind {
+                    EntryKind::Size => &self.entries,
+                    EntryKind::Empty => &self.empty_dirs,
+                    EntryKind::Lockfile => {
+                        match self.lockfiles.get(&path) {
+                            Some(l) => upsert_lock.execute(params![
+                                path,
+                                l.lock_hash,
+                                l.deps_hash,
+                                serde_json::to_string(&l.dependencies)?,
+                                l.cached_at.to_rfc3339(),
+                            ])?,
+                            None => delete_lock.execute(params![path])?,
+                        };
+                        continue;
+                    }
+                };
+                match map.get(&path) {
+                    Some(e) => upsert.execute(params![
+                        path,
+                        kind.as_str(),
+                        e.mtime.to_rfc3339(),
+                        e.fingerprint,
+                        e.size_bytes as i64,
+                        e.cached_at.to_rfc3339(),
+                        e.expires_at.map(|t| t.to_rfc3339()),
+                        e.deep_fingerprint,
+                        e.file_count.map(|n| n as i64),
+                        e.dir_count.map(|n| n as i64),
+                        e.linked_bytes as i64,
+                    ])?,
+                    None => delete.execute(params![path, kind.as_str()])?,
+                };
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO scan_meta (key, value) VALUES ('last_saved', ?1)",
+                params![now.to_rfc3339()],
+            )?;
+            for (root, cursor) in &self.feed_cursors {
+                tx.execute(
+                    "INSERT OR REPLACE INTO scan_meta (key, value) VALUES (?1, ?2)",
+                    params![format!("feed:{}", root), serde_json::to_string(cursor)?],
+                )?;
+            }
+        }
+        tx.commit()?;
+        self.last_saved = Some(now);
+        Ok(())
+    }
+
+    fn load_rows(&mut self, conn: &Connection, root: Option<&Path>) -> Result<()> {
+        let mut filter = String::new();
+        let mut args: Vec<String> = Vec::new();
+        if let Some(root) = root {
+            let root = root.to_string_lossy().to_string();
+            let escaped = root.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            filter.push_str(" WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'");
+            args.push(root);
+            args.push(format!("{}{}%", escaped, std::path::MAIN_SEPARATOR));
+        }
+        let parse_ts = |s: String| DateTime::parse_from_rfc3339(&s).ok().map(|t| t.with_timezone(&Utc));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT path, kind, mtime, fingerprint, size_bytes, cached_at, expires_at, deep_fingerprint, file_count, dir_count, linked_bytes
+             FROM scan_entries{}",
+            filter,
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(&args), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, Option<String>>(7)?,
+                (row.get::<_, Option<i64>>(8)?, row.get::<_, Option<i64>>(9)?, row.get::<_, i64>(10)?),
+            ))
+        })?.collect::<std::result::Result<Vec<_>, _>>()?;
+
+        for (path, kind, mtime, fingerprint, size_bytes, cached_at, expires_at, deep_fingerprint, (files, dirs, linked)) in rows {
+            let (Some(mtime), Some(cached_at)) = (parse_ts(mtime), parse_ts(cached_at)) else { continue };
+            let entry = CachedEntry {
+                mtime,
+                fingerprint,
+                size_bytes: size_bytes as u64,
+                file_count: files.map(|n| n as u64),
+                dir_count: dirs.map(|n| n as u64),
+                linked_bytes: linked as u64,
+                cached_at,
+                expires_at: expires_at.and_then(parse_ts),
+                deep_fingerprint,
+            };
+            match kind.as_str() {
+                "empty" => self.empty_dirs.insert(path, entry),
+                _ => self.entries.insert(path, entry),
+            };
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT path, lock_hash, deps_hash, dependencies, cached_at FROM scan_lockfiles{}",
+            filter,
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(&args), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?.collect::<std::result::Result<Vec<_>, _>>()?;
+        for (path, lock_hash, deps_hash, dependencies, cached_at) in rows {
+            let (Ok(dependencies), Some(cached_at)) = (serde_json::from_str(&dependencies), parse_ts(cached_at)) else { continue };
+            self.lockfiles.insert(path, LockfileEntry { lock_hash, deps_hash, dependencies, cached_at });
+        }
+
+        self.last_saved = conn.query_row(
+            "SELECT value FROM scan_meta WHERE key = 'last_saved'", [], |row| row.get::<_, String>(0),
+        ).ok().and_then(parse_ts);
+        Ok(())
     }
 
     /// Generate fingerprint for a path based on mtime and file count
@@ -138,12 +509,100 @@ impl ScanCache {
         Ok((fingerprint, mtime, size))
     }
 
+    /// Newest mtime, file count and total size of a subtree (nested
+    /// `node_modules` excluded, as in scanner sizing), hashed
+    fn deep_fingerprint(path: &Path) -> String {
+        let (mut newest, mut files, mut bytes) = (SystemTime::UNIX_EPOCH, 0u64, 0u64);
+        let walker = WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || e.file_name() != "node_modules");
+        for meta in walker.filter_map(|e| e.ok()).filter_map(|e| e.metadata().ok()) {
+            if let Ok(mtime) = meta.modified() {
+                newest = newest.max(mtime);
+            }
+            if meta.is_file() {
+                files += 1;
+                bytes += meta.len();
+            }
+        }
+
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{:?}:files={}:bytes={}", newest, files, bytes).as_bytes());
+        hex::encode(&hasher.finalize()[..8])
+    }
+
+    /// Read each root's change journal from the position the last saved scan
+    /// reached. The new positions are saved with the cache, so a scan that is
+    /// not saved is read again next time. Only fast validation uses the feed.
+    pub fn apply_change_feed(&mut self, roots: &[PathBuf]) {
+        if self.validation != CacheValidation::Fast {
+            return;
+        }
+        for root in roots {
+            let key = root.to_string_lossy().to_string();
+            let Some(read) = crate::change_feed::read(root, self.feed_cursors.get(&key)) else { continue };
+            self.feed_cursors.insert(key, read.cursor);
+            if let Some(changes) = read.changed {
+                self.trust_changes(root, changes);
+            }
+        }
+    }
+
+    /// Judge entries below `root` by `changes`, everything that changed
+    /// there since the last saved scan
+    pub fn trust_changes(&mut self, root: &Path, changes: Vec<Change>) {
+        self.feed_roots.push(root.to_path_buf());
+        for change in changes {
+            *self.feed_changes.entry(change.path.to_string_lossy().to_string()).or_default() |= change.subtree;
+        }
+    }
+
+    /// Whether the change feed says `path` changed: Some(true) when a change
+    /// touched it or its subtree, Some(false) when none did, None when the
+    /// feed does not cover it or a directory above it changed (so it may have
+    /// been moved or removed)
+    fn feed_verdict(&self, path: &Path) -> Option<bool> {
+        if self.validation != CacheValidation::Fast || !self.feed_roots.iter().any(|root| path.starts_with(root)) {
+            return None;
+        }
+        let key = path.to_string_lossy().to_string();
+        let nested = format!("{}{}", key, std::path::MAIN_SEPARATOR);
+        let touched = self.feed_changes.contains_key(&key)
+            || self.feed_changes.range(nested.clone()..).next().is_some_and(|(p, _)| p.starts_with(&nested));
+        if touched {
+            return Some(true);
+        }
+        for ancestor in path.ancestors().skip(1) {
+            if let Some(subtree) = self.feed_changes.get(ancestor.to_string_lossy().as_ref()) {
+                return if *subtree { Some(true) } else { None };
+            }
+        }
+        Some(false)
+    }
+
     /// Check if a path is stale (needs re-scanning)
     pub fn is_stale(&self, path: &Path) -> bool {
         let path_str = path.to_string_lossy().to_string();
-        
-        match self.entries.get(&path_str) {
+        let entry = self.entries.get(&path_str);
+        if let Some(changed) = self.feed_verdict(path) {
+            return changed || entry.is_none_or(|e| e.expired(Utc::now()));
+        }
+        if Self::entry_stale(entry, path) {
+            return true;
+        }
+        match (self.validation, entry) {
+            (CacheValidation::Thorough, Some(cached)) => {
+                cached.deep_fingerprint.as_deref() != Some(Self::deep_fingerprint(path).as_str())
+            }
+            _ => false,
+        }
+    }
+
+    fn entry_stale(entry: Option<&CachedEntry>, path: &Path) -> bool {
+        match entry {
             None => true, // Not in cache
+            Some(cached) if cached.expired(Utc::now()) => true,
             Some(cached) => {
                 // Check if file still exists
                 if !path.exists() {
@@ -172,19 +631,153 @@ impl ScanCache {
 
     /// Update cache entry for a path with pre-computed size
     pub fn update(&mut self, path: &Path, size_bytes: u64) -> Result<()> {
+        self.update_entry(path, size_bytes, None)
+    }
+
+    /// Update cache entry for a path with its size and file counts
+    pub fn update_totals(&mut self, path: &Path, totals: &TreeTotals) -> Result<()> {
+        self.update_entry(path, totals.bytes, Some(totals))
+    }
+
+    fn update_entry(&mut self, path: &Path, size_bytes: u64, totals: Option<&TreeTotals>) -> Result<()> {
+        let mut entry = Self::new_entry(path, size_bytes, chrono::Duration::days(Self::SIZE_TTL_DAYS))?;
+        entry.file_count = totals.map(|t| t.files);
+        entry.dir_count = totals.map(|t| t.dirs);
+        entry.linked_bytes = totals.map_or(0, |t| t.linked_bytes);
+        if self.validation == CacheValidation::Thorough {
+            entry.deep_fingerprint = Some(Self::deep_fingerprint(path));
+        }
         let path_str = path.to_string_lossy().to_string();
+        self.pending.insert((EntryKind::Size, path_str.clone()));
+        self.entries.insert(path_str, entry);
+        Ok(())
+    }
+
+    fn new_entry(path: &Path, size_bytes: u64, ttl: chrono::Duration) -> Result<CachedEntry> {
         let (fingerprint, mtime, _) = Self::generate_fingerprint(path)?;
-        
-        self.entries.insert(path_str, CachedEntry {
+        let now = Utc::now();
+        Ok(CachedEntry {
             mtime: mtime.into(),
             fingerprint,
             size_bytes,
-            cached_at: Utc::now(),
-        });
-        
+            file_count: None,
+            dir_count: None,
+            linked_bytes: 0,
+            cached_at: now,
+            expires_at: Some(now + ttl),
+            deep_fingerprint: None,
+        })
+    }
+
+    /// Remember that `path`'s subtree holds no packages or projects
+    pub fn mark_empty(&mut self, path: &Path) -> Result<()> {
+        let entry = Self::new_entry(path, 0, chrono::Duration::days(Self::NEGATIVE_TTL_DAYS))?;
+        let path_str = path.to_string_lossy().to_string();
+        // A negative entry covers everything below it
+        let nested = format!("{}{}", path_str, std::path::MAIN_SEPARATOR);
+        retain_tracked(&mut self.empty_dirs, EntryKind::Empty, &mut self.pending, |p, _| !p.starts_with(&nested));
+        self.pending.insert((EntryKind::Empty, path_str.clone()));
+        self.empty_dirs.insert(path_str, entry);
         Ok(())
     }
 
+    /// Whether `path` was confirmed empty of packages and is unchanged since.
+    /// Thorough validation never skips a subtree.
+    pub fn is_known_empty(&self, path: &Path) -> bool {
+        if self.validation == CacheValidation::Thorough {
+            return false;
+        }
+        let path_str = path.to_string_lossy().to_string();
+        self.empty_dirs.get(&path_str).is_some_and(|e| match self.feed_verdict(path) {
+            Some(changed) => !changed && !e.expired(Utc::now()),
+            None => !Self::entry_stale(Some(e), path),
+        })
+    }
+
+    /// Drop a negative entry, e.g. once packages appeared below it
+    pub fn forget_empty(&mut self, path: &Path) {
+        let path_str = path.to_string_lossy().to_string();
+        if self.empty_dirs.remove(&path_str).is_some() {
+            self.pending.insert((EntryKind::Empty, path_str));
+        }
+    }
+
+    /// Lockfile dependencies recorded for `project`, if parsed under the same hashes
+    pub fn lockfile_deps(&self, project: &Path, lock_hash: &str, deps_hash: &str) -> Option<&[(String, String)]> {
+        self.lockfiles.get(project.to_string_lossy().as_ref())
+            .filter(|l| l.lock_hash == lock_hash && l.deps_hash == deps_hash)
+            .map(|l| l.dependencies.as_slice())
+    }
+
+    /// Record the dependencies parsed from `project`'s lockfile
+    pub fn update_lockfile(&mut self, project: &Path, entry: LockfileEntry) {
+        let path_str = project.to_string_lossy().to_string();
+        self.pending.insert((EntryKind::Lockfile, path_str.clone()));
+        self.lockfiles.insert(path_str, entry);
+    }
+
+    /// Projects with remembered lockfile dependencies, and when they were parsed
+    pub fn lockfile_projects(&self) -> impl Iterator<Item = (&Path, DateTime<Utc>)> {
+        self.lockfiles.iter().map(|(p, l)| (Path::new(p.as_str()), l.cached_at))
+    }
+
+    /// Drop every loaded entry at or below `path`, and negative entries
+    /// above it; returns how many were dropped
+    pub fn forget_below(&mut self, path: &Path) -> usize {
+        let before = self.entries.len() + self.empty_dirs.len() + self.lockfiles.len();
+        let below = |p: &String| Path::new(p.as_str()).starts_with(path);
+        retain_tracked(&mut self.entries, EntryKind::Size, &mut self.pending, |p, _| !below(p));
+        retain_tracked(&mut self.empty_dirs, EntryKind::Empty, &mut self.pending, |p, _| {
+            !below(p) && !path.starts_with(p.as_str())
+        });
+        let pending = &mut self.pending;
+        self.lockfiles.retain(|p, _| {
+            let kept = !below(p);
+            if !kept {
+                pending.insert((EntryKind::Lockfile, p.clone()));
+            }
+            kept
+        });
+        before - self.entries.len() - self.empty_dirs.len() - self.lockfiles.len()
+    }
+
+    /// `forget_below` straight in the database at `cache_path`, without
+    /// loading it; returns how many rows were deleted
+    pub fn invalidate(cache_path: &Path, path: &Path) -> Result<usize> {
+        let conn = Self::open_db(cache_path)?;
+        let key = path.to_string_lossy().to_string();
+        let escaped = key.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let nested = format!("{}{}%", escaped, std::path::MAIN_SEPARATOR);
+        let mut removed = conn.execute("DELETE FROM scan_entries WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'", params![key, nested])?;
+        removed += conn.execute("DELETE FROM scan_lockfiles WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'", params![key, nested])?;
+        for ancestor in path.ancestors().skip(1) {
+            removed += conn.execute(
+                "DELETE FROM scan_entries WHERE path = ?1 AND kind = 'empty'", params![ancestor.to_string_lossy()],
+            )?;
+        }
+        Ok(removed)
+    }
+
+    /// SHA-256 of a lockfile's contents
+    pub fn lockfile_hash(content: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        hex::encode(Sha256::digest(content))
+    }
+
+    /// SHA-256 of the package.json fields that affect dependency resolution;
+    /// scripts, version and other metadata do not change it
+    pub fn dependency_hash(package_json: &serde_json::Value) -> String {
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        for key in DEPENDENCY_FIELDS {
+            if let Some(value) = package_json.get(key) {
+                hasher.update(key.as_bytes());
+                hasher.update(canonical_json(value).as_bytes());
+            }
+        }
+        hex::encode(hasher.finalize())
+    }
+
     /// Get cached size for a path (None if stale or not cached)
     pub fn get_cached_size(&self, path: &Path) -> Option<u64> {
         if self.is_stale(path) {
@@ -195,6 +788,16 @@ impl ScanCache {
         self.entries.get(&path_str).map(|e| e.size_bytes)
     }
 
+    /// Cached size and file counts for a path (None if stale, not cached
+    /// or cached without counts)
+    pub fn get_cached_totals(&self, path: &Path) -> Option<TreeTotals> {
+        if self.is_stale(path) {
+            return None;
+        }
+        let entry = self.entries.get(path.to_string_lossy().as_ref())?;
+        Some(TreeTotals { bytes: entry.size_bytes, linked_bytes: entry.linked_bytes, files: entry.file_count?, dirs: entry.dir_count? })
+    }
+
     /// Get cached package record if still valid
     pub fn get_cached_package(&self, path: &Path) -> Option<&CachedEntry> {
         let path_str = path.to_string_lossy().to_string();
@@ -208,33 +811,109 @@ impl ScanCache {
 
     /// Remove entries for paths that no longer exist
     pub fn prune_missing(&mut self) {
-        self.entries.retain(|path_str, _| {
-            Path::new(path_str).exists()
+        retain_tracked(&mut self.entries, EntryKind::Size, &mut self.pending, |p, _| Path::new(p).exists());
+        retain_tracked(&mut self.empty_dirs, EntryKind::Empty, &mut self.pending, |p, _| Path::new(p).exists());
+        let pending = &mut self.pending;
+        self.lockfiles.retain(|p, _| {
+            let kept = Path::new(p).exists();
+            if !kept {
+                pending.insert((EntryKind::Lockfile, p.clone()));
+            }
+            kept
         });
     }
 
+    /// Remove expired entries and entries for paths that no longer exist;
+    /// returns how many were removed
+    pub fn prune(&mut self) -> usize {
+        let before = self.entries.len() + self.empty_dirs.len() + self.lockfiles.len();
+        let now = Utc::now();
+        retain_tracked(&mut self.entries, EntryKind::Size, &mut self.pending, |_, e| !e.expired(now));
+        retain_tracked(&mut self.empty_dirs, EntryKind::Empty, &mut self.pending, |_, e| !e.expired(now));
+        self.prune_missing();
+        before - self.entries.len() - self.empty_dirs.len() - self.lockfiles.len()
+    }
+
     /// Get cache statistics
     pub fn stats(&self) -> CacheStats {
+        let now = Utc::now();
         CacheStats {
             total_entries: self.entries.len(),
             total_cached_size: self.entries.values().map(|e| e.size_bytes).sum(),
             last_saved: self.last_saved,
+            negative_entries: self.empty_dirs.len(),
+            lockfile_entries: self.lockfiles.len(),
+            expired_entries: self.entries.values().chain(self.empty_dirs.values()).filter(|e| e.expired(now)).count(),
         }
     }
 
-    /// Clear the cache
+    /// Clear the loaded entries (all of them after `load_or_create`)
     pub fn clear(&mut self) {
-        self.entries.clear();
+        retain_tracked(&mut self.entries, EntryKind::Size, &mut self.pending, |_, _| false);
+        retain_tracked(&mut self.empty_dirs, EntryKind::Empty, &mut self.pending, |_, _| false);
+        self.pending.extend(self.lockfiles.drain().map(|(p, _)| (EntryKind::Lockfile, p)));
         self.last_saved = None;
     }
 }
 
+/// package.json fields hashed into a project's dependency fingerprint
+const DEPENDENCY_FIELDS: [&str; 8] = [
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "optionalDependencies",
+    "bundledDependencies",
+    "overrides",
+    "resolutions",
+    "workspaces",
+];
+
+/// JSON text with object keys sorted, so key order does not affect hashes
+fn canonical_json(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys.iter()
+                .map(|k| format!("{}:{}", serde_json::Value::String((*k).clone()), canonical_json(&map[*k])))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        serde_json::Value::Array(items) => {
+            format!("[{}]", items.iter().map(canonical_json).collect::<Vec<_>>().join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// `HashMap::retain` that queues the removed keys for deletion on save
+fn retain_tracked(
+    map: &mut HashMap<String, CachedEntry>,
+    kind: EntryKind,
+    pending: &mut HashSet<(EntryKind, String)>,
+    mut keep: impl FnMut(&String, &CachedEntry) -> bool,
+) {
+    map.retain(|path, entry| {
+        let kept = keep(path, entry);
+        if !kept {
+            pending.insert((kind, path.clone()));
+        }
+        kept
+    });
+}
+
 /// Cache statistics for reporting
 #[derive(Debug, Clone, Serialize)]
 pub struct CacheStats {
     pub total_entries: usize,
     pub total_cached_size: u64,
     pub last_saved: Option<DateTime<Utc>>,
+    /// Directories remembered as holding no packages
+    pub negative_entries: usize,
+    /// Projects with remembered lockfile dependencies
+    pub lockfile_entries: usize,
+    /// Entries past their TTL, removed by `prune`
+    pub expired_entries: usize,
 }
 
 /// Wrapper for scanning with cache support
@@ -334,7 +1013,7 @@ mod tests {
     #[test]
     fn test_cache_save_load() {
         let temp = tempdir().unwrap();
-        let cache_path = temp.path().join("test_cache.json");
+        let cache_path = temp.path().join("test_cache.db");
         
         let mut cache = ScanCache::new();
         cache.update(temp.path(), 1234).unwrap();
@@ -344,6 +1023,72 @@ mod tests {
         assert_eq!(loaded.entries.len(), 1);
     }
 
+    #[test]
+    fn test_cache_rows_and_partial_load() {
+        let temp = tempdir().unwrap();
+        let db = temp.path().join("cache.db");
+        let (a, b) = (temp.path().join("a"), temp.path().join("a_b"));
+        fs::create_dir_all(a.join("pkg")).unwrap();
+        fs::create_dir_all(&b).unwrap();
+
+        let mut cache = ScanCache::new();
+        cache.update(&a.join("pkg"), 1).unwrap();
+        cache.update(&b, 2).unwrap();
+        cache.mark_empty(&a).unwrap();
+        cache.save(&db).unwrap();
+        assert!(cache.pending.is_empty());
+
+        // `_` in the root must not match `a_b` as a LIKE wildcard
+        let partial = ScanCache::load_for_roots(&db, std::slice::from_ref(&a)).unwrap();
+        assert_eq!(partial.entries.len(), 1);
+        assert_eq!(partial.empty_dirs.len(), 1);
+        assert!(partial.last_saved.is_some());
+
+        // Removals are written as row deletes without touching other rows
+        let mut partial = partial;
+        partial.forget_empty(&a);
+        partial.save(&db).unwrap();
+        let full = ScanCache::load_or_create(&db).unwrap();
+        assert_eq!((full.entries.len(), full.empty_dirs.len()), (2, 0));
+    }
+
+    #[test]
+    fn test_dependency_hash_ignores_unrelated_fields() {
+        let a = serde_json::json!({"name": "x", "dependencies": {"a": "1", "b": "2"}});
+        let b = serde_json::json!({"name": "x", "version": "2.0.0", "scripts": {"t": "jest"}, "dependencies": {"b": "2", "a": "1"}});
+        let c = serde_json::json!({"name": "x", "dependencies": {"a": "1"}, "overrides": {"b": "3"}});
+        assert_eq!(ScanCache::dependency_hash(&a), ScanCache::dependency_hash(&b));
+        assert_ne!(ScanCache::dependency_hash(&a), ScanCache::dependency_hash(&c));
+
+        let temp = tempdir().unwrap();
+        let db = temp.path().join("cache.db");
+        let mut cache = ScanCache::new();
+        cache.update_lockfile(temp.path(), LockfileEntry {
+            lock_hash: "l".into(),
+            deps_hash: "d".into(),
+            dependencies: vec![("a".into(), "1.0.0".into())],
+            cached_at: Utc::now(),
+        });
+        cache.save(&db).unwrap();
+        let loaded = ScanCache::load_or_create(&db).unwrap();
+        assert_eq!(loaded.lockfile_deps(temp.path(), "l", "d").map(|d| d.len()), Some(1));
+        assert!(loaded.lockfile_deps(temp.path(), "l", "other").is_none());
+    }
+
+    #[test]
+    fn test_legacy_json_import() {
+        let temp = tempdir().unwrap();
+        let mut old = ScanCache::new();
+        old.update(temp.path(), 7).unwrap();
+        fs::write(temp.path().join("scan_cache.json"), serde_json::to_string(&old).unwrap()).unwrap();
+
+        let db = temp.path().join("scan_cache.db");
+        let cache = ScanCache::load_or_create(&db).unwrap();
+        assert_eq!(cache.entries.len(), 1);
+        assert!(!temp.path().join("scan_cache.json").exists());
+        assert_eq!(ScanCache::load_or_create(&db).unwrap().entries.len(), 1);
+    }
+
     #[test]
     fn test_cache_staleness() {
         let cache = ScanCache::new();
@@ -359,7 +1104,7 @@ mod tests {
     #[test]
     fn test_cached_scanner() {
         let temp = tempdir().unwrap();
-        let cache_path = temp.path().join("scanner_cache.json");
+        let cache_path = temp.path().join("scanner_cache.db");
         
         let mut scanner = CachedScanner::with_cache_path(cache_path).unwrap();
         
@@ -374,4 +1119,105 @@ mod tests {
         assert_eq!(scanner.hits, 1);
         assert_eq!(scanner.misses, 1);
     }
+
+    #[test]
+    fn test_entry_ttl_and_prune() {
+        let temp = tempdir().unwrap();
+        let mut cache = ScanCache::new();
+        cache.update(temp.path(), 10).unwrap();
+        assert_eq!(cache.get_cached_size(temp.path()), Some(10));
+
+        let key = temp.path().to_string_lossy().to_string();
+        cache.entries.get_mut(&key).unwrap().expires_at = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert_eq!(cache.get_cached_size(temp.path()), None);
+        assert_eq!(cache.stats().expired_entries, 1);
+        assert_eq!(cache.prune(), 1);
+        assert_eq!(cache.stats().total_entries, 0);
+    }
+
+    #[test]
+    fn test_thorough_validation_sees_nested_edits() {
+        let temp = tempdir().unwrap();
+        let pkg = temp.path().join("pkg");
+        fs::create_dir_all(pkg.join("lib")).unwrap();
+        fs::write(pkg.join("lib/index.js"), "a").unwrap();
+
+        let mut cache = ScanCache::new();
+        cache.set_validation(CacheValidation::Thorough);
+        cache.update(&pkg, 1).unwrap();
+        assert_eq!(cache.get_cached_size(&pkg), Some(1));
+
+        // Same directory listing, so only the deep fingerprint notices
+        fs::write(pkg.join("lib/index.js"), "abc").unwrap();
+        cache.set_validation(CacheValidation::Fast);
+        assert_eq!(cache.get_cached_size(&pkg), Some(1));
+        cache.set_validation(CacheValidation::Thorough);
+        assert_eq!(cache.get_cached_size(&pkg), None);
+
+        assert_eq!("thorough".parse::<CacheValidation>(), Ok(CacheValidation::Thorough));
+        assert!("deep".parse::<CacheValidation>().is_err());
+    }
+
+    #[test]
+    fn test_negative_entries() {
+        let temp = tempdir().unwrap();
+        let big = temp.path().join("big");
+        fs::create_dir_all(big.join("inner")).unwrap();
+
+        let mut cache = ScanCache::new();
+        assert!(!cache.is_known_empty(&big));
+        cache.mark_empty(&big.join("inner")).unwrap();
+        cache.mark_empty(&big).unwrap();
+        assert!(cache.is_known_empty(&big));
+        // The outer entry replaces the nested one
+        assert_eq!(cache.stats().negative_entries, 1);
+
+        // A new child changes the fingerprint
+        fs::create_dir(big.join("node_modules")).unwrap();
+        assert!(!cache.is_known_empty(&big));
+    }
+
+    #[test]
+    fn test_change_feed_replaces_fingerprints() {
+        let temp = tempdir().unwrap();
+        let db = temp.path().join("cache.db");
+        let root = temp.path().join("root");
+        let (a, b, c) = (root.join("a"), root.join("b"), root.join("c"));
+        for dir in [&a, &b, &c] {
+            fs::create_dir_all(dir.join("lib")).unwrap();
+        }
+        let mut cache = ScanCache::new();
+        for dir in [&a, &b, &c] {
+            cache.update(dir, 1).unwrap();
+        }
+        cache.feed_cursors.insert(root.to_string_lossy().to_string(), FeedCursor { journal: "usn:1".into(), position: 42 });
+        cache.save(&db).unwrap();
+
+        // The cursor comes back for the same root
+        let mut cache = ScanCache::load_for_roots(&db, std::slice::from_ref(&root)).unwrap();
+        assert_eq!(cache.feed_cursors.values().next().unwrap().position, 42);
+
+        // `a` changed in a way its fingerprint sees, but the feed did not report it
+        fs::create_dir(a.join("new")).unwrap();
+        fs::write(b.join("lib/index.js"), "x").unwrap();
+        cache.trust_changes(&root, vec![Change { path: b.join("lib/index.js"), subtree: false }]);
+        assert!(!cache.is_stale(&a));
+        // A change below an entry makes it stale
+        assert!(cache.is_stale(&b));
+        assert!(!cache.is_stale(&c));
+
+        // A change to the parent falls back to the fingerprint, which sees `c` gone
+        fs::remove_dir_all(&c).unwrap();
+        cache.trust_changes(&root, vec![Change { path: root.clone(), subtree: false }]);
+        assert!(cache.is_stale(&c));
+        cache.trust_changes(&root, vec![Change { path: root.clone(), subtree: true }]);
+        assert!(cache.is_stale(&a));
+
+        // Thorough validation ignores the feed
+        let mut thorough = ScanCache::new();
+        thorough.update(&b, 1).unwrap();
+        thorough.set_validation(CacheValidation::Thorough);
+        thorough.trust_changes(&root, Vec::new());
+        assert!(thorough.feed_verdict(&b).is_none());
+    }
 }
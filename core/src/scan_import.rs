@@ -0,0 +1,338 @@
+//! External Scan Import
+//!
+//! Builds a `ScanOutput` from size data another tool collected, for machines
+//! PackagePurge cannot run on: an ncdu JSON export (`ncdu -o`), `du` output
+//! (`du`, `du -k`, `du -b`, `du -h`, optionally with `--time`) or a WizTree
+//! CSV export. Recognized paths become packages:
+//! - directories directly below a `node_modules` (or its `@scope`), sized
+//!   without their own nested `node_modules`
+//! - Yarn cache archives, whose file names carry name and version
+//! - npm and pnpm caches, one record per cache directory
+//!
+//! Only paths and sizes are known, not package.json contents: versions are
+//! `unknown`, and the project owning a `node_modules` is taken to depend on
+//! everything installed in it, so plans from imported data rest on age (when
+//! the export has modification times) and on caches, which nothing depends on.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::error::Error;
+use crate::scanner::is_cache_dir;
+use crate::types::{PackageManager, PackageRecord, ProjectRecord, ScanOutput};
+
+const UNKNOWN_VERSION: &str = "unknown";
+
+/// Export formats `import` understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Ncdu,
+    /// `du` output; bare sizes are in blocks of `block_size` bytes
+    Du { block_size: u64 },
+    WizTree,
+}
+
+impl ImportFormat {
+    /// Guess the format from the content: JSON is ncdu, a `File Name` header is WizTree
+    pub fn detect(text: &str, du_block_size: u64) -> Self {
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with('[') {
+            Self::Ncdu
+        } else if text.lines().take(2).any(|l| l.starts_with("\"File Name\"") || l.starts_with("File Name,")) {
+            Self::WizTree
+        } else {
+            Self::Du { block_size: du_block_size }
+        }
+    }
+}
+
+/// A directory (or file) of the export, with its cumulative size
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    path: String,
+    size: u64,
+    mtime: Option<DateTime<Utc>>,
+}
+
+/// Parse `text` as `format` and map it to a scan
+pub fn import(text: &str, format: ImportFormat) -> crate::Result<ScanOutput> {
+    import_impl(text, format).map_err(Error::lift(Error::Scan))
+}
+
+fn import_impl(text: &str, format: ImportFormat) -> Result<ScanOutput> {
+    let text = text.trim_start_matches('\u{feff}');
+    let entries = match format {
+        ImportFormat::Ncdu => parse_ncdu(text)?,
+        ImportFormat::Du { block_size } => parse_du(text, block_size)?,
+        ImportFormat::WizTree => parse_wiztree(text)?,
+    };
+    Ok(to_scan(&entries))
+}
+
+fn parse_ncdu(text: &str) -> Result<Vec<Entry>> {
+    fn walk(node: &Value, parent: Option<&str>, out: &mut Vec<Entry>) -> Result<u64> {
+        let (info, children) = match node {
+            Value::Array(items) => (items.first().context("Empty ncdu directory")?, &items[1..]),
+            info => (info, &[][..]),
+        };
+        let name = info.get("name").and_then(Value::as_str).context("ncdu entry without a name")?;
+        let path = match parent {
+            Some(parent) => format!("{}/{}", parent.trim_end_matches('/'), name),
+            None => name.to_string(),
+        };
+        let own = info.get("asize").or_else(|| info.get("dsize")).and_then(Value::as_u64).unwrap_or(0);
+        let index = out.len();
+        out.push(Entry {
+            path: path.clone(),
+            size: own,
+            mtime: info.get("mtime").and_then(Value::as_i64).and_then(|t| Utc.timestamp_opt(t, 0).single()),
+        });
+        let mut total = own;
+        for child in children {
+            total += walk(child, Some(&path), out)?;
+        }
+        out[index].size = total;
+        Ok(total)
+    }
+    let export: Vec<Value> = serde_json::from_str(text).context("Not an ncdu JSON export")?;
+    let root = export.get(3).context("ncdu export has no directory tree")?;
+    let mut out = Vec::new();
+    walk(root, None, &mut out)?;
+    Ok(out)
+}
+
+/// `du -h` sizes are binary; bare numbers count blocks
+fn du_size(field: &str, block_size: u64) -> Option<u64> {
+    let (number, unit) = field.split_at(field.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(field.len()));
+    let exponent = match unit.to_ascii_uppercase().as_str() {
+        "" => return number.parse::<u64>().ok().map(|n| n * block_size),
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return None,
+    };
+    Some((number.parse::<f64>().ok()? * 1024f64.powi(exponent)) as u64)
+}
+
+fn parse_du(text: &str, block_size: u64) -> Result<Vec<Entry>> {
+    let mut out = Vec::new();
+    for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(size), Some(second)) = (fields.next(), fields.next()) else {
+            anyhow::bail!("du line {} is not `SIZE<TAB>PATH`: {:?}", n + 1, line);
+        };
+        let size = du_size(size.trim(), block_size)
+            .with_context(|| format!("du line {} has an unreadable size: {:?}", n + 1, line))?;
+        // `du --time` puts the modification time between size and path
+        let (mtime, path) = match fields.next() {
+            Some(path) => (NaiveDateTime::parse_from_str(second, "%Y-%m-%d %H:%M").ok().map(|t| t.and_utc()), path),
+            None => (None, second),
+        };
+        out.push(Entry { path: path.to_string(), size, mtime });
+    }
+    Ok(out)
+}
+
+/// Split one CSV record, honoring quotes and doubled quotes inside them
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+fn parse_wiztree(text: &str) -> Result<Vec<Entry>> {
+    let mut lines = text.lines().skip_while(|l| !(l.starts_with("\"File Name\"") || l.starts_with("File Name,")));
+    let header = csv_fields(lines.next().context("WizTree export has no `File Name` header")?);
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let name_col = column("File Name").context("WizTree export has no File Name column")?;
+    let size_col = column("Size").context("WizTree export has no Size column")?;
+    let modified_col = column("Modified");
+    let mut out = Vec::new();
+    for line in lines.filter(|l| !l.trim().is_empty()) {
+        let fields = csv_fields(line);
+        let (Some(path), Some(size)) = (fields.get(name_col), fields.get(size_col)) else { continue };
+        let Ok(size) = size.trim().parse() else { continue };
+        let mtime = modified_col.and_then(|c| fields.get(c))
+            .and_then(|m| NaiveDateTime::parse_from_str(m.trim(), "%Y/%m/%d %H:%M:%S").ok())
+            .map(|t| t.and_utc());
+        out.push(Entry { path: path.clone(), size, mtime });
+    }
+    Ok(out)
+}
+
+/// Path components, for `/` and `\` separated paths alike
+fn components(path: &str) -> Vec<&str> {
+    path.split(['/', '\\']).filter(|c| !c.is_empty()).collect()
+}
+
+/// `name@version` from a Yarn cache archive such as
+/// `@babel-core-npm-7.23.0-1a2b3c-4d5e6f.zip` (scope and name are joined by `-`)
+fn yarn_cache_package(file: &str) -> Option<(String, String)> {
+    let stem = file.strip_suffix(".zip")?;
+    let (name, rest) = stem.split_once("-npm-")?;
+    let version = rest.split('-').next().filter(|v| !v.is_empty())?;
+    let name = match name.strip_prefix('@') {
+        Some(scoped) => format!("@{}", scoped.replacen('-', "/", 1)),
+        None => name.to_string(),
+    };
+    Some((name, version.to_string()))
+}
+
+fn cache_manager(normalized: &str) -> PackageManager {
+    if normalized.contains("yarn") {
+        PackageManager::Yarn
+    } else if normalized.contains("pnpm") {
+        PackageManager::Pnpm
+    } else {
+        PackageManager::Npm
+    }
+}
+
+fn to_scan(entries: &[Entry]) -> ScanOutput {
+    let trimmed = |p: &str| p.trim_end_matches(['/', '\\']).to_string();
+    let by_path: HashMap<String, &Entry> = entries.iter().map(|e| (trimmed(&e.path), e)).collect();
+    let now = Utc::now();
+    let record = |path: &str, name: String, version: String, size: u64, mtime: Option<DateTime<Utc>>, manager| PackageRecord {
+        name,
+        version,
+        path: path.to_string(),
+        size_bytes: size,
+        linked_bytes: 0,
+        file_count: 0,
+        inode_count: 0,
+        atime: mtime.unwrap_or(now),
+        mtime: mtime.unwrap_or(now),
+        manager,
+        project_paths: Vec::new(),
+    };
+
+    let mut packages = Vec::new();
+    let mut projects: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    let mut caches: Vec<String> = Vec::new();
+    let mut paths: Vec<&String> = by_path.keys().collect();
+    paths.sort();
+    for path in paths {
+        let entry = by_path[path];
+        let parts = components(path);
+        let Some(&last) = parts.last() else { continue };
+        let sep = if path.contains('\\') && !path.contains('/') { '\\' } else { '/' };
+
+        let normalized = path.replace('\\', "/").to_lowercase();
+        if caches.iter().any(|c| normalized.starts_with(&format!("{}/", c))) {
+            if normalized.contains("yarn/cache") {
+                if let Some((name, version)) = yarn_cache_package(last) {
+                    packages.push(record(path, name, version, entry.size, entry.mtime, Some(PackageManager::Yarn)));
+                }
+            }
+            continue;
+        }
+        if is_cache_dir(Path::new(&normalized)) {
+            caches.push(normalized.clone());
+            if !normalized.contains("yarn/cache") {
+                packages.push(record(path, last.to_string(), UNKNOWN_VERSION.into(), entry.size, entry.mtime,
+                    Some(cache_manager(&normalized))));
+            }
+            continue;
+        }
+
+        let n = parts.len();
+        let name = if n >= 2 && parts[n - 2] == "node_modules" && !last.starts_with('.') && !last.starts_with('@') {
+            last.to_string()
+        } else if n >= 3 && parts[n - 3] == "node_modules" && parts[n - 2].starts_with('@') {
+            format!("{}/{}", parts[n - 2], last)
+        } else {
+            continue;
+        };
+        let nested = by_path.get(&format!("{}{}node_modules", path, sep)).map_or(0, |e| e.size);
+        let mut package = record(path, name.clone(), UNKNOWN_VERSION.into(), entry.size.saturating_sub(nested), entry.mtime, None);
+        let first_modules = parts.iter().position(|p| *p == "node_modules").unwrap_or(n);
+        let project = path.split(&format!("{}node_modules", sep)).next().unwrap_or_default().to_string();
+        if first_modules > 0 && !project.is_empty() {
+            package.project_paths.push(project.clone());
+            projects.entry(project).or_default().push((name, UNKNOWN_VERSION.into()));
+        }
+        packages.push(package);
+    }
+
+    let projects = projects.into_iter()
+        .map(|(path, mut dependencies)| {
+            dependencies.sort();
+            dependencies.dedup();
+            let mtime = by_path.get(&path).and_then(|e| e.mtime).unwrap_or(now);
+            ProjectRecord { path, manager: None, dependencies, mtime }
+        })
+        .collect();
+    ScanOutput {
+        packages,
+        projects,
+        edges: Vec::new(),
+        stats: Default::default(),
+        deferred_sizes: Vec::new(),
+        marked_dirs: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(scan: &ScanOutput) -> Vec<(&str, u64)> {
+        let mut sizes: Vec<(&str, u64)> = scan.packages.iter().map(|p| (p.name.as_str(), p.size_bytes)).collect();
+        sizes.sort();
+        sizes
+    }
+
+    #[test]
+    fn test_du_and_ncdu_map_packages() {
+        let du = "40\t/srv/app/node_modules/a/node_modules/b\n40\t/srv/app/node_modules/a/node_modules\n100\t/srv/app/node_modules/a\n\
+                  8\t/srv/app/node_modules/@types/node\n200\t/srv/app/node_modules\n\
+                  12\t/home/ci/.npm\n300\t/srv/app\n";
+        let scan = import(du, ImportFormat::detect(du, 1024)).unwrap();
+        assert_eq!(sizes(&scan), vec![(".npm", 12 * 1024), ("@types/node", 8 * 1024), ("a", 60 * 1024), ("b", 40 * 1024)]);
+        assert_eq!(scan.projects.len(), 1);
+        assert_eq!(scan.projects[0].path, "/srv/app");
+        assert_eq!(scan.projects[0].dependencies.len(), 3);
+        assert_eq!(du_size("1.5K", 1024), Some(1536));
+
+        let ncdu = r#"[1, 2, {"progname": "ncdu"}, [{"name": "/srv/app"},
+            [{"name": "node_modules"},
+                [{"name": "left-pad", "mtime": 1600000000}, {"name": "index.js", "asize": 300}, {"name": "package.json", "asize": 50}]]]]"#;
+        let scan = import(ncdu, ImportFormat::detect(ncdu, 1024)).unwrap();
+        assert_eq!(sizes(&scan), vec![("left-pad", 350)]);
+        assert_eq!(scan.packages[0].mtime.timestamp(), 1_600_000_000);
+    }
+
+    #[test]
+    fn test_wiztree_csv_and_yarn_cache() {
+        let csv = "\u{feff}Generated by WizTree 4.15\n\
+            \"File Name\",\"Size\",\"Allocated\",\"Modified\",\"Attributes\",\"Files\",\"Folders\"\n\
+            \"C:\\work\\web\\node_modules\\react\\\",5000,8192,\"2023/01/15 10:22:33\",16,10,2\n\
+            \"C:\\Users\\ada\\AppData\\Local\\Yarn\\Cache\\\",900,4096,\"2023/01/15 10:22:33\",16,1,0\n\
+            \"C:\\Users\\ada\\AppData\\Local\\Yarn\\Cache\\@babel-core-npm-7.23.0-1a2b3c-4d5e6f.zip\",900,4096,\"2023/01/15 10:22:33\",32,0,0\n";
+        let scan = import(csv, ImportFormat::detect(csv, 1024)).unwrap();
+        let mut found: Vec<(&str, &str)> = scan.packages.iter().map(|p| (p.name.as_str(), p.version.as_str())).collect();
+        found.sort();
+        assert_eq!(found, vec![("@babel/core", "7.23.0"), ("react", "unknown")]);
+        let react = scan.packages.iter().find(|p| p.name == "react").unwrap();
+        assert_eq!(react.size_bytes, 5000);
+        assert_eq!(react.project_paths, vec!["C:\\work\\web".to_string()]);
+        assert_eq!(react.mtime.format("%Y-%m-%d").to_string(), "2023-01-15");
+        assert_eq!(csv_fields(r#""a,""b""",2"#), vec!["a,\"b\"", "2"]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Shared Scan Coordination
+//!
+//! Several invocations can scan at once, say a scheduled scan and one run by
+//! hand. Each cached scan holds a lease, a small file in `leases/` under the
+//! cache directory naming its process and roots, from loading the scan cache
+//! until saving it. A scan whose roots overlap those of an earlier lease
+//! waits for that lease to be released and then loads the cache its holder
+//! just saved, so the two never size the same tree twice or overwrite each
+//! other's fresh entries with stale ones. Scans of disjoint roots run side by
+//! side, their row-level writes serialized by SQLite: every database
+//! connection waits up to `DB_BUSY_TIMEOUT` for another writer instead of
+//! failing.
+//!
+//! Leases of processes that are gone, or older than `STALE_AFTER_MINUTES`,
+//! are ignored and removed. A scan says which process it waits for, and
+//! gives up after `WAIT_TIMEOUT`.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration as StdDuration;
+
+use crate::error::Error;
+use crate::progress::OperationContext;
+
+/// How long a lease counts without its process being checkable
+pub const STALE_AFTER_MINUTES: i64 = 60;
+const POLL: StdDuration = StdDuration::from_millis(100);
+/// How long a scan waits for overlapping leases before failing
+pub const WAIT_TIMEOUT: StdDuration = StdDuration::from_secs(30 * 60);
+/// How long a database statement waits for another invocation's write
+/// transaction before failing as busy
+pub(crate) const DB_BUSY_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LeaseFile {
+    pid: u32,
+    roots: Vec<PathBuf>,
+    started_at: DateTime<Utc>,
+}
+
+impl LeaseFile {
+    fn overlaps(&self, roots: &[PathBuf]) -> bool {
+        self.roots.iter().any(|a| roots.iter().any(|b| a.starts_with(b) || b.starts_with(a)))
+    }
+
+    /// Held before `other`; ties go to the lower pid so two leases never wait on each other
+    fn precedes(&self, other: &LeaseFile) -> bool {
+        (self.started_at, self.pid) < (other.started_at, other.pid)
+    }
+
+    fn is_stale(&self) -> bool {
+        Utc::now() - self.started_at > Duration::minutes(STALE_AFTER_MINUTES) || !process_alive(self.pid)
+    }
+}
+
+/// Whether `pid` still runs: it exists, even if it cannot be signalled
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else { return false };
+    if pid <= 0 {
+        return false;
+    }
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+/// Whether `pid` still runs: it has no exit code yet, or cannot be opened
+/// for any reason but not existing
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_INVALID_PARAMETER, STILL_ACTIVE};
+    use windows_sys::Win32::System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return GetLastError() != ERROR_INVALID_PARAMETER;
+        }
+        let mut code = 0u32;
+        let ok = GetExitCodeProcess(handle, &mut code);
+        CloseHandle(handle);
+        ok == 0 || code == STILL_ACTIVE as u32
+    }
+}
+
+/// Assumed to run where it cannot be checked; the lease still goes stale
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// A held lease, released on drop
+#[derive(Debug)]
+pub struct ScanLease {
+    path: PathBuf,
+    /// Milliseconds spent waiting for overlapping scans
+    pub waited_ms: u64,
+}
+
+impl ScanLease {
+    pub fn default_dir() -> PathBuf {
+        crate::paths::cache_dir().join("leases")
+    }
+
+    /// Take a lease on `roots` in `dir`, waiting until no earlier lease
+    /// overlaps them. Stops with `Error::Cancelled` when `ctx` is cancelled,
+    /// and fails once it waited `WAIT_TIMEOUT`.
+    pub fn acquire(dir: &Path, roots: &[PathBuf], ctx: &OperationContext) -> crate::Result<Self> {
+        Self::acquire_as(dir, std::process::id(), roots, WAIT_TIMEOUT, ctx).map_err(Error::lift(Error::Scan))
+    }
+
+    fn acquire_as(dir: &Path, pid: u32, roots: &[PathBuf], timeout: StdDuration, ctx: &OperationContext) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create lease directory {:?}", dir))?;
+        let own = LeaseFile { pid, roots: roots.to_vec(), started_at: Utc::now() };
+        let path = dir.join(format!("scan-{}.json", pid));
+        fs::write(&path, serde_json::to_string(&own)?)
+            .with_context(|| format!("Failed to write scan lease {:?}", path))?;
+        let mut lease = Self { path, waited_ms: 0 };
+
+        let started = std::time::Instant::now();
+        let mut announced = None;
+        loop {
+            let blocking = blocking_leases(dir, &lease.path, &own);
+            let Some(&holder) = blocking.first() else { break };
+            if announced != Some(holder) {
+                eprintln!("Waiting for scan lease held by pid {} (overlapping roots)", holder);
+                announced = Some(holder);
+            }
+            if started.elapsed() >= timeout {
+                anyhow::bail!(
+                    "Gave up after {}s waiting for the scan lease held by pid {}; remove its file in {:?} if that scan is gone",
+                    timeout.as_secs(), holder, dir
+                );
+            }
+            ctx.check()?;
+            std::thread::sleep(POLL);
+        }
+        lease.waited_ms = started.elapsed().as_millis() as u64;
+        Ok(lease)
+    }
+}
+
+impl Drop for ScanLease {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Pids of the live leases in `dir` that overlap `own` and precede it;
+/// stale ones are removed
+fn blocking_leases(dir: &Path, own_path: &Path, own: &LeaseFile) -> Vec<u32> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut blocking = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path == own_path {
+            continue;
+        }
+        // Unreadable files may be half written; the next poll looks again
+        let Some(other) = fs::read_to_string(&path).ok()
+            .and_then(|text| serde_json::from_str::<LeaseFile>(&text).ok()) else { continue };
+        if other.is_stale() {
+            let _ = fs::remove_file(&path);
+        } else if other.overlaps(&own.roots) && other.precedes(own) {
+            blocking.push(other.pid);
+        }
+    }
+    blocking
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_overlapping_scan_waits_for_earlier_lease() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path().join("leases");
+        let ctx = OperationContext::default();
+        let me = std::process::id();
+        let earlier = ScanLease::acquire_as(&dir, me, &[PathBuf::from("/src")], WAIT_TIMEOUT, &ctx).unwrap();
+
+        // Disjoint roots proceed at once
+        let disjoint = ScanLease::acquire_as(&dir, me + 1, &[PathBuf::from("/other")], WAIT_TIMEOUT, &ctx).unwrap();
+        drop(disjoint);
+
+        let (tx, rx) = mpsc::channel();
+        let waiting_dir = dir.clone();
+        let waiter = std::thread::spawn(move || {
+            let lease = ScanLease::acquire_as(&waiting_dir, me + 2, &[PathBuf::from("/src/app")], WAIT_TIMEOUT, &OperationContext::default()).unwrap();
+            tx.send(()).unwrap();
+            lease.waited_ms
+        });
+        assert!(rx.recv_timeout(StdDuration::from_millis(300)).is_err());
+        drop(earlier);
+        rx.recv_timeout(StdDuration::from_secs(5)).unwrap();
+        assert!(waiter.join().unwrap() >= 300);
+    }
+
+    #[test]
+    fn test_wait_times_out_and_dead_holders_are_skipped() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path().join("leases");
+        let ctx = OperationContext::default();
+        let me = std::process::id();
+        let _held = ScanLease::acquire_as(&dir, me, &[PathBuf::from("/src")], WAIT_TIMEOUT, &ctx).unwrap();
+
+        let err = ScanLease::acquire_as(&dir, me + 1, &[PathBuf::from("/src")], StdDuration::from_millis(200), &ctx).unwrap_err();
+        assert!(err.to_string().contains(&format!("held by pid {}", me)), "{}", err);
+
+        // A lease left by a crashed scan does not hold anyone up
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn().unwrap();
+        let gone = child.id();
+        child.wait().unwrap();
+        assert!(!process_alive(gone));
+        assert!(process_alive(me));
+        let crashed = LeaseFile { pid: gone, roots: vec![PathBuf::from("/app")], started_at: Utc::now() - Duration::minutes(1) };
+        fs::write(dir.join(format!("scan-{}.json", gone)), serde_json::to_string(&crashed).unwrap()).unwrap();
+        let lease = ScanLease::acquire_as(&dir, me + 2, &[PathBuf::from("/app")], StdDuration::from_millis(200), &ctx).unwrap();
+        assert!(lease.waited_ms < 200);
+        assert!(!dir.join(format!("scan-{}.json", gone)).exists());
+    }
+}
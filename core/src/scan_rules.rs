@@ -0,0 +1,233 @@
+//! Scan Pruning Rules
+//!
+//! Most of a home directory is not development data: photo and music
+//! libraries, VCS object stores, trash folders. The walker consults these
+//! rules before descending and never enters a directory a `skip` glob
+//! matches, unless a `keep` glob matches it too. Globs are matched against
+//! the full path; a leading `~/` stands for the home directory. The rules
+//! live in `scan_rules.json` in the config directory and default to
+//! `default_skip` when the file is absent.
+//!
+//! Scans rooted at the home directory or the filesystem root also skip the
+//! `wide_skip` globs: mail stores, photo libraries, browser profiles and
+//! system trees, which hold no packages and take most of such a walk.
+//!
+//! Directories marked as caches by a `CACHEDIR.TAG`, and vendored
+//! dependency trees (Go, Composer, Bundler), are never walked into.
+//! `tagged_caches` and `vendor_dirs` decide whether they are skipped,
+//! reported, or recorded as packages the cleanup rules may remove.
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::types::Marker;
+
+/// What a scan does with a marked directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerPolicy {
+    /// Leave it out of the scan altogether
+    Skip,
+    /// List it in `marked_dirs`, never planned for cleanup
+    #[default]
+    Report,
+    /// Record it as a package, so the cleanup rules may plan it
+    Clean,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanRulesConfig {
+    /// Directories never descended into
+    #[serde(default = "default_skip")]
+    pub skip: Vec<String>,
+    /// Exceptions to `skip`
+    #[serde(default)]
+    pub keep: Vec<String>,
+    /// Also never descended into when a scan root is `~` or `/`
+    #[serde(default = "default_wide_skip")]
+    pub wide_skip: Vec<String>,
+    /// Directories holding a `CACHEDIR.TAG`
+    #[serde(default)]
+    pub tagged_caches: MarkerPolicy,
+    /// Vendored dependency directories
+    #[serde(default)]
+    pub vendor_dirs: MarkerPolicy,
+}
+
+fn default_skip() -> Vec<String> {
+    [
+        "**/.git", "**/.hg", "**/.svn",
+        "**/*.photoslibrary", "**/*.musiclibrary", "**/*.tvlibrary", "**/*.aplibrary", "**/*.lrdata",
+        "**/.Trash", "**/.Trashes", "**/$RECYCLE.BIN", "**/System Volume Information",
+        "~/Pictures", "~/Photos", "~/Music", "~/Movies", "~/Videos",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_wide_skip() -> Vec<String> {
+    [
+        // Mail and messages
+        "~/Library/Mail", "~/Library/Messages", "~/.thunderbird", "~/.local/share/evolution", "~/Mail",
+        "~/AppData/Roaming/Thunderbird", "~/AppData/Local/Microsoft/Outlook",
+        // Photos and other media
+        "~/Library/Photos", "~/Library/Containers/com.apple.Photos*", "~/.local/share/shotwell",
+        // Browser profiles
+        "~/Library/Safari", "~/Library/Application Support/Google/Chrome", "~/Library/Application Support/Firefox",
+        "~/Library/Application Support/Microsoft Edge", "~/Library/Application Support/BraveSoftware",
+        "~/.mozilla", "~/.config/google-chrome", "~/.config/chromium", "~/.config/microsoft-edge", "~/.config/BraveSoftware",
+        "~/AppData/Local/Google/Chrome/User Data", "~/AppData/Roaming/Mozilla", "~/AppData/Local/Microsoft/Edge/User Data",
+        "~/AppData/Local/BraveSoftware",
+        // System trees
+        "/proc", "/sys", "/dev", "/run", "/boot", "/lost+found", "/System", "/Volumes", "/private/var/vm",
+        "/mnt", "/media", "/snap", "C:/Windows", "C:/$WinREAgent",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+impl Default for ScanRulesConfig {
+    fn default() -> Self {
+        Self {
+            skip: default_skip(),
+            keep: Vec::new(),
+            wide_skip: default_wide_skip(),
+            tagged_caches: MarkerPolicy::default(),
+            vendor_dirs: MarkerPolicy::default(),
+        }
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("scan_rules.json")
+}
+
+pub fn load_config() -> ScanRulesConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &ScanRulesConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &ScanRulesConfig) -> Result<()> {
+    ScanRules::compile(config)?;
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save scan rules to {:?}", path))
+}
+
+/// Compiled skip and keep globs
+#[derive(Debug, Clone)]
+pub struct ScanRules {
+    skip: GlobSet,
+    keep: GlobSet,
+    wide_skip: GlobSet,
+    /// Whether `wide_skip` applies (a scan root is `~` or `/`)
+    wide: bool,
+    tagged_caches: MarkerPolicy,
+    vendor_dirs: MarkerPolicy,
+}
+
+impl ScanRules {
+    pub fn compile(config: &ScanRulesConfig) -> crate::Result<Self> {
+        let home = dirs::home_dir();
+        let build = |globs: &[String]| -> Result<GlobSet> {
+            let mut set = GlobSetBuilder::new();
+            for glob in globs {
+                let expanded = match (glob.strip_prefix("~/"), &home) {
+                    (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+                    _ => glob.clone(),
+                };
+                set.add(Glob::new(&expanded).with_context(|| format!("Invalid scan rule {:?}", glob))?);
+            }
+            Ok(set.build()?)
+        };
+        let compiled = (|| Ok(Self {
+            skip: build(&config.skip)?,
+            keep: build(&config.keep)?,
+            wide_skip: build(&config.wide_skip)?,
+            wide: false,
+            tagged_caches: config.tagged_caches,
+            vendor_dirs: config.vendor_dirs,
+        }))();
+        compiled.map_err(Error::lift(Error::Config))
+    }
+
+    /// Rules that prune nothing
+    pub fn none() -> Self {
+        Self {
+            skip: GlobSet::empty(),
+            keep: GlobSet::empty(),
+            wide_skip: GlobSet::empty(),
+            wide: false,
+            tagged_caches: MarkerPolicy::default(),
+            vendor_dirs: MarkerPolicy::default(),
+        }
+    }
+
+    /// Apply `wide_skip` too, for scans of `~` or `/`
+    pub fn wide(mut self, wide: bool) -> Self {
+        self.wide = wide;
+        self
+    }
+
+    /// Whether the walker should stay out of directory `path`
+    pub fn prunes(&self, path: &Path) -> bool {
+        (self.skip.is_match(path) || (self.wide && self.wide_skip.is_match(path))) && !self.keep.is_match(path)
+    }
+
+    /// The policy for directories marked by `marker`
+    pub fn marker_policy(&self, marker: Marker) -> MarkerPolicy {
+        match marker {
+            Marker::CachedirTag => self.tagged_caches,
+            Marker::GoVendor | Marker::ComposerVendor | Marker::BundlerVendor => self.vendor_dirs,
+        }
+    }
+}
+
+/// The configured rules with config.toml's `exclude` added to `skip`, or
+/// the defaults if the config holds a bad glob
+pub fn load() -> ScanRules {
+    let mut config = load_config();
+    config.skip.extend(crate::config_file::get().exclude.iter().cloned());
+    ScanRules::compile(&config).unwrap_or_else(|e| {
+        eprintln!("Warning: ignoring scan rules: {}", e);
+        ScanRules::compile(&ScanRulesConfig::default()).unwrap_or_else(|_| ScanRules::none())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_and_keep() {
+        let rules = ScanRules::compile(&ScanRulesConfig {
+            skip: vec!["**/.git".into(), "**/media".into()],
+            keep: vec!["**/work/media".into()],
+            wide_skip: vec!["**/.mozilla".into()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(rules.prunes(Path::new("/src/app/.git")));
+        assert!(rules.prunes(Path::new("/home/u/media")));
+        assert!(!rules.prunes(Path::new("/home/u/work/media")));
+        assert!(!rules.prunes(Path::new("/src/app/.github")));
+        assert!(!rules.prunes(Path::new("/home/u/.mozilla")));
+        assert!(rules.wide(true).prunes(Path::new("/home/u/.mozilla")));
+        let bad = ScanRulesConfig { skip: vec!["a/[".into()], ..Default::default() };
+        assert!(ScanRules::compile(&bad).is_err());
+    }
+}
@@ -2,31 +2,118 @@
 //!
 //! Scans filesystem to discover node_modules, package caches, and project roots.
 //! Uses incremental caching for improved performance on subsequent runs.
+//! Every scan counts the work it does (`ScanStats`) so regressions and the
+//! cache's benefit can be tracked; cached scans also log these figures to the
+//! feature store.
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
-use std::{fs, path::{Path, PathBuf}, time::SystemTime};
+use std::time::{Instant, SystemTime};
+use std::{fs, path::{Path, PathBuf}};
 use walkdir::WalkDir;
 
-use crate::types::{PackageRecord, ProjectRecord, ScanOutput, PackageManager};
+use crate::types::{DryRunReport, FileTiming, MarkedDir, Marker, PackageRecord, ProjectRecord, ScanItem, PackageSnapshot, ScanOutput, ScanStats, ScanSummary, PackageManager};
 use crate::lockfiles::{parse_npm_package_lock, parse_yarn_lock, parse_pnpm_lock};
-use crate::scan_cache::ScanCache;
+use crate::cache_markers;
+use crate::cargo_caches::{self, cargo_packages, is_cargo_dir, parse_cargo_lock};
+use crate::python_caches::{self, is_python_dir, parse_python_lock, python_packages};
+use crate::go_caches::{go_packages, is_mod_cache, parse_go_sum};
+use crate::error::Error;
+use crate::ignore_file::IgnoreRules;
+use crate::install_events;
+use crate::feature_store::FeatureStore;
+use crate::progress::{OperationContext, Phase};
+use crate::provider_caches::{is_provider_cache_dir, parse_terraform_lock, provider_packages};
+use crate::safety::protected_dirs;
+use crate::scan_lease::ScanLease;
+use crate::scan_rules::{self, MarkerPolicy, ScanRules};
+use crate::scan_cache::{CacheValidation, LockfileEntry, ScanCache};
+use crate::store_index::StoreIndex;
+use crate::symlink::get_global_store_path;
+use crate::timings::{self, TimedPhase};
+use crate::native_walk::TreeTotals;
 
 fn to_utc(st: SystemTime) -> DateTime<Utc> { st.into() }
 
-/// Compute directory size by walking all files
-fn dir_size(path: &Path) -> u64 {
-    let mut total: u64 = 0;
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            if let Ok(meta) = entry.metadata() {
-                total += meta.len();
-            }
+/// Work counters shared by the scan's worker threads
+#[derive(Default)]
+struct ScanCounters {
+    dirs: AtomicU64,
+    files: AtomicU64,
+    bytes_read: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    negative_hits: AtomicU64,
+    lockfiles_reused: AtomicU64,
+    sizes_deferred: AtomicU64,
+    dirs_pruned: AtomicU64,
+}
+
+impl ScanCounters {
+    fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<String> {
+        let text = fs::read_to_string(path)?;
+        Self::add(&self.bytes_read, text.len() as u64);
+        Ok(text)
+    }
+
+    fn stats(&self, started_at: DateTime<Utc>, started: Instant, cpu_before: Option<u64>) -> ScanStats {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        let (hits, misses) = (get(&self.cache_hits), get(&self.cache_misses));
+        ScanStats {
+            started_at: Some(started_at),
+            dirs_walked: get(&self.dirs),
+            files_stated: get(&self.files),
+            bytes_read: get(&self.bytes_read),
+            wall_ms: started.elapsed().as_millis() as u64,
+            cpu_ms: cpu_before.zip(process_cpu_ms()).map(|(before, after)| after.saturating_sub(before)),
+            cache_hits: hits,
+            cache_misses: misses,
+            cache_hit_rate: if hits + misses > 0 { hits as f64 / (hits + misses) as f64 } else { 0.0 },
+            dirs_skipped: get(&self.negative_hits),
+            lockfiles_reused: get(&self.lockfiles_reused),
+            sizes_deferred: get(&self.sizes_deferred),
+            dirs_pruned: get(&self.dirs_pruned),
+            parse_ms: 0,
+            slowest_files: Vec::new(),
         }
     }
-    total
+}
+
+/// CPU time used by this process so far, all threads included.
+/// Only available on Linux (via /proc); None elsewhere.
+fn process_cpu_ms() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        // Fields after the parenthesised command name; utime and stime are
+        // the 12th and 13th, in USER_HZ (fixed at 100) ticks
+        let stat = fs::read_to_string("/proc/self/stat").ok()?;
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+        Some((utime + stime) * 10)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Compute directory size and file counts with the native enumerator where
+/// there is one. Nested `node_modules` are excluded since their packages are
+/// recorded (and sized) separately.
+fn dir_totals(path: &Path, counters: &ScanCounters) -> TreeTotals {
+    let totals = crate::native_walk::tree_totals(path, Some("node_modules"));
+    ScanCounters::add(&counters.dirs, totals.dirs);
+    ScanCounters::add(&counters.files, totals.files);
+    totals
 }
 
 fn detect_manager_from_lock(dir: &Path) -> Option<PackageManager> {
@@ -36,16 +123,192 @@ fn detect_manager_from_lock(dir: &Path) -> Option<PackageManager> {
     None
 }
 
-fn is_cache_dir(path: &Path) -> bool {
+pub(crate) fn is_cache_dir(path: &Path) -> bool {
     let p = path.to_string_lossy().to_lowercase();
     p.ends_with(".npm") || p.contains("yarn/cache") || p.contains("pnpm/store")
 }
 
+/// Real (non-symlink) subdirectories of `dir`
+fn child_dirs(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|rd| rd.filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| e.path())
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Enumerate every package below a `node_modules` directory exactly once.
+///
+/// `@scope` directories are treated as containers of scoped packages rather
+/// than packages themselves. Nested `node_modules` inside packages are
+/// followed explicitly at any depth, pnpm's
+/// virtual store (`.pnpm/<id>/node_modules`) is descended into, and other
+/// dot-directories (`.bin`, `.cache`) are ignored. Symlinked entries, such as
+/// pnpm's top-level links into `.pnpm`, are not followed, so every package is
+/// reached through its real location only.
+fn node_modules_packages(node_modules: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    collect_node_modules(node_modules, &mut out);
+    out
+}
+
+fn collect_node_modules(node_modules: &Path, out: &mut Vec<PathBuf>) {
+    for child in child_dirs(node_modules) {
+        let name = child.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if name == ".pnpm" {
+            for entry in child_dirs(&child) {
+                collect_node_modules(&entry.join("node_modules"), out);
+            }
+        } else if name.starts_with('.') {
+            continue;
+        } else if name.starts_with('@') {
+            // Scope directory: its children are the packages
+            for scoped in child_dirs(&child) {
+                push_package(scoped, out);
+            }
+        } else {
+            push_package(child, out);
+        }
+    }
+}
+
+/// Record a package directory (if it has a manifest) and descend into its
+/// own nested `node_modules`
+fn push_package(dir: PathBuf, out: &mut Vec<PathBuf>) {
+    if dir.join("package.json").is_file() {
+        collect_node_modules(&dir.join("node_modules"), out);
+        out.push(dir);
+    }
+}
+
+/// Enumerate package directories in a package-manager cache directory
+fn cache_dir_packages(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir).min_depth(1).max_depth(3)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_dir() && e.path().join("package.json").exists())
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Normalize scan roots: make them absolute, drop duplicates and collapse
+/// roots nested inside another root, so `~/dev` and `~/dev/project-x` are
+/// walked once. Roots are compared by their canonical paths but keep the
+/// user's spelling, so a symlinked root is reported as typed, and never carry
+/// Windows' `\\?\` verbatim prefix.
+fn normalize_roots(paths: &[PathBuf]) -> Vec<PathBuf> {
+    // (canonical path, spelling)
+    let mut roots: Vec<(PathBuf, PathBuf)> = paths.iter()
+        .map(|p| {
+            let spelled = std::path::absolute(p)
+                .map(|a| dunce::simplified(&a).to_path_buf())
+                .unwrap_or_else(|_| p.clone());
+            (dunce::canonicalize(p).unwrap_or_else(|_| spelled.clone()), spelled)
+        })
+        .collect();
+    // Sorting puts every ancestor before its descendants
+    roots.sort();
+    roots.dedup_by(|a, b| a.0 == b.0);
+
+    let mut collapsed: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for root in roots {
+        if !collapsed.iter().any(|(kept, _)| root.0.starts_with(kept)) {
+            collapsed.push(root);
+        }
+    }
+    collapsed.into_iter().map(|(_, spelled)| spelled).collect()
+}
+
+/// Manifests and lockfiles listed by parse time in the scan statistics
+const SLOWEST_FILES: usize = 10;
+
+/// Directories with at least this many entries below them and nothing found
+/// are remembered as empty in the scan cache
+const NEGATIVE_CACHE_MIN_ENTRIES: u64 = 1000;
+
+/// A directory still being walked: entries seen below it so far and whether
+/// anything was found there
+struct OpenDir {
+    path: PathBuf,
+    depth: usize,
+    entries: u64,
+    found: bool,
+}
+
 /// Single-pass directory walker that collects both package directories and projects
 struct SinglePassCollector {
     package_dirs: Vec<PathBuf>,
     projects: Vec<ProjectRecord>,
-    edges: Vec<(String, String)>,
+    /// Direct dependency names declared by each project's package.json
+    project_deps: Vec<(PathBuf, Vec<String>)>,
+    /// Terraform provider and Serverless release caches
+    provider_dirs: Vec<PathBuf>,
+    /// Cargo `target/` directories and registries
+    cargo_dirs: Vec<PathBuf>,
+    /// Virtualenvs, `.tox`, `__pycache__` and the pip/Poetry caches
+    python_dirs: Vec<PathBuf>,
+    /// Go module caches
+    go_dirs: Vec<PathBuf>,
+    /// Tagged caches and vendored dependencies the scan rules did not skip
+    marked_dirs: Vec<(PathBuf, Marker)>,
+    /// Large subtrees that held no packages or projects
+    empty_dirs: Vec<PathBuf>,
+    /// Lockfiles parsed this run, to be remembered by the cache
+    lockfiles: Vec<(PathBuf, LockfileEntry)>,
+    /// Read and parse time of every manifest and lockfile
+    timings: Vec<FileTiming>,
+    /// Skip globs applied while walking
+    rules: ScanRules,
+    counters: ScanCounters,
+}
+
+/// A manifest found by the walk, parsed once the walk is done
+enum Manifest {
+    PackageJson(PathBuf),
+    TerraformLock(PathBuf),
+    CargoLock(PathBuf),
+    /// `poetry.lock`, `Pipfile.lock` or `requirements.txt`
+    PythonLock(PathBuf),
+    GoSum(PathBuf),
+}
+
+/// What the walk of one root found, in walk order
+#[derive(Default)]
+struct RootWalk {
+    package_dirs: Vec<PathBuf>,
+    provider_dirs: Vec<PathBuf>,
+    cargo_dirs: Vec<PathBuf>,
+    python_dirs: Vec<PathBuf>,
+    go_dirs: Vec<PathBuf>,
+    marked_dirs: Vec<(PathBuf, Marker)>,
+    empty_dirs: Vec<PathBuf>,
+    manifests: Vec<Manifest>,
+}
+
+impl RootWalk {
+    /// Finish a walked directory; large ones with nothing below them become
+    /// negative cache candidates, replacing candidates nested inside them
+    fn close_dir(&mut self, dir: OpenDir) {
+        if dir.depth == 0 || dir.found || dir.entries < NEGATIVE_CACHE_MIN_ENTRIES {
+            return;
+        }
+        self.empty_dirs.retain(|d| !d.starts_with(&dir.path));
+        self.empty_dirs.push(dir.path);
+    }
+}
+
+/// A project parsed from a package.json, with its lockfile when it was parsed anew
+struct ParsedProject {
+    project: ProjectRecord,
+    direct: Vec<String>,
+    lockfile: Option<(PathBuf, LockfileEntry)>,
+}
+
+enum Parsed {
+    Project(ParsedProject),
+    /// Terraform or Cargo lock file
+    Locked(ProjectRecord),
 }
 
 impl SinglePassCollector {
@@ -53,157 +316,628 @@ impl SinglePassCollector {
         Self {
             package_dirs: Vec::new(),
             projects: Vec::new(),
-            edges: Vec::new(),
+            project_deps: Vec::new(),
+            provider_dirs: Vec::new(),
+            cargo_dirs: Vec::new(),
+            python_dirs: Vec::new(),
+            go_dirs: Vec::new(),
+            marked_dirs: Vec::new(),
+            empty_dirs: Vec::new(),
+            lockfiles: Vec::new(),
+            timings: Vec::new(),
+            counters: ScanCounters::default(),
+            rules: ScanRules::none(),
         }
     }
 
-    /// Collect all data in a single directory walk
-    fn collect(&mut self, roots: &[PathBuf]) -> Result<()> {
-        for root in roots {
-            for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
-                let path = entry.path();
-                
-                if entry.file_type().is_dir() {
-                    let name = entry.file_name().to_string_lossy();
-                    if name == "node_modules" || is_cache_dir(path) {
-                        self.package_dirs.push(entry.into_path());
-                    }
-                } else if entry.file_type().is_file() && entry.file_name() == "package.json" {
-                    // Skip node_modules package.json files
-                    let path_str = path.to_string_lossy();
-                    if path_str.contains("node_modules") {
-                        continue;
-                    }
-                    
-                    if let Some(project) = self.parse_project(path) {
-                        self.projects.push(project);
-                    }
-                }
+    /// Collect all data in a single directory walk. Directories `rules`
+    /// prune are not entered. Subtrees `cache`
+    /// remembers as holding nothing are skipped; new ones are recorded in
+    /// `empty_dirs`. Lockfiles `cache` parsed under the same hashes are not
+    /// parsed again; newly parsed ones are recorded in `lockfiles`.
+    ///
+    /// Roots are walked in parallel, then every manifest and lockfile found
+    /// is parsed in parallel; results are merged in walk order, so the
+    /// outcome matches a serial scan.
+    fn collect(&mut self, roots: &[PathBuf], ctx: &OperationContext, cache: Option<&ScanCache>) -> Result<()> {
+        let protected = protected_dirs();
+        let visited = AtomicU64::new(0);
+        let (counters, rules) = (&self.counters, &self.rules);
+        let walks = timings::time(TimedPhase::Walk, || roots.par_iter()
+            .map(|root| walk_root(root, &protected, rules, &visited, counters, ctx, cache))
+            .collect::<Result<Vec<_>>>())?;
+
+        let mut manifests = Vec::new();
+        for walk in walks {
+            self.package_dirs.extend(walk.package_dirs);
+            self.provider_dirs.extend(walk.provider_dirs);
+            self.cargo_dirs.extend(walk.cargo_dirs);
+            self.python_dirs.extend(walk.python_dirs);
+            self.go_dirs.extend(walk.go_dirs);
+            self.marked_dirs.extend(walk.marked_dirs);
+            self.empty_dirs.extend(walk.empty_dirs);
+            manifests.extend(walk.manifests);
+        }
+
+        let _timing = timings::span(TimedPhase::Lockfiles);
+        let parsed: Vec<(Option<Parsed>, Vec<FileTiming>)> = manifests.par_iter()
+            .filter(|_| !ctx.cancel.is_cancelled())
+            .map(|manifest| {
+                let mut timings = Vec::new();
+                let parsed = match manifest {
+                    Manifest::PackageJson(path) => parse_project(path, counters, cache, &mut timings).map(Parsed::Project),
+                    Manifest::TerraformLock(path) => parse_locked_project(path, PackageManager::Terraform, counters, &mut timings).map(Parsed::Locked),
+                    Manifest::CargoLock(path) => parse_locked_project(path, PackageManager::Cargo, counters, &mut timings).map(Parsed::Locked),
+                    Manifest::PythonLock(path) => parse_locked_project(path, PackageManager::Python, counters, &mut timings).map(Parsed::Locked),
+                    Manifest::GoSum(path) => parse_locked_project(path, PackageManager::Go, counters, &mut timings).map(Parsed::Locked),
+                };
+                (parsed, timings)
+            })
+            .collect();
+        ctx.check()?;
+
+        for (parsed, timings) in parsed {
+            self.timings.extend(timings);
+            match parsed {
+                Some(Parsed::Project(p)) => self.add_project(p),
+                Some(Parsed::Locked(project)) => self.add_locked_project(project),
+                None => {}
             }
         }
         Ok(())
     }
 
-    fn parse_project(&self, package_json: &Path) -> Option<ProjectRecord> {
-        let dir = package_json.parent()?;
-        let manager = detect_manager_from_lock(dir);
-        let mtime = fs::metadata(package_json).and_then(|m| m.modified()).ok()
-            .map(to_utc).unwrap_or_else(|| Utc::now());
-        
-        let mut deps: Vec<(String, String)> = Vec::new();
-        if let Ok(content) = fs::read_to_string(package_json) {
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
-                for key in ["dependencies", "devDependencies", "peerDependencies"] {
-                    if let Some(obj) = json.get(key).and_then(|v| v.as_object()) {
-                        for (name, ver) in obj {
-                            if let Some(ver_str) = ver.as_str() {
-                                deps.push((name.clone(), ver_str.to_string()));
-                            }
+    fn add_project(&mut self, parsed: ParsedProject) {
+        let ParsedProject { project, direct, lockfile } = parsed;
+        self.lockfiles.extend(lockfile);
+        self.project_deps.push((PathBuf::from(&project.path), direct));
+        // A lock file walked earlier may already have created the project
+        match self.projects.iter_mut().find(|p| p.path == project.path) {
+            Some(existing) => {
+                let locked = std::mem::take(&mut existing.dependencies);
+                *existing = project;
+                existing.dependencies.extend(locked);
+            }
+            None => self.projects.push(project),
+        }
+    }
+
+    /// Record the providers or crates a Terraform or Cargo lock file pins as
+    /// dependencies of its directory, merging into the project already found there
+    fn add_locked_project(&mut self, project: ProjectRecord) {
+        match self.projects.iter_mut().find(|p| p.path == project.path) {
+            Some(existing) => existing.dependencies.extend(project.dependencies),
+            None => self.projects.push(project),
+        }
+    }
+
+    /// Total parse time and the `n` slowest files
+    fn parse_timings(&self, n: usize) -> (u64, Vec<FileTiming>) {
+        let total_micros: u64 = self.timings.iter().map(|t| t.micros).sum();
+        let mut slowest = self.timings.clone();
+        slowest.sort_by_key(|t| std::cmp::Reverse(t.micros));
+        slowest.truncate(n);
+        (total_micros / 1000, slowest)
+    }
+}
+
+/// Walk one root, noting package dirs, provider caches, empty subtrees and
+/// manifests without parsing anything. What the scan rules or the root's
+/// ignore files (see `ignore_file`) match is never entered.
+fn walk_root(
+    root: &Path,
+    protected: &[PathBuf],
+    rules: &ScanRules,
+    visited: &AtomicU64,
+    counters: &ScanCounters,
+    ctx: &OperationContext,
+    cache: Option<&ScanCache>,
+) -> Result<RootWalk> {
+    let mut out = RootWalk::default();
+    let ignored = IgnoreRules::for_root(root);
+    let mut walker = WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| {
+            if protected.iter().any(|p| e.path().starts_with(p)) {
+                return false;
+            }
+            if e.depth() > 0 && !e.file_type().is_dir() && ignored.is_ignored(e.path(), false) {
+                return false;
+            }
+            // Scan roots are walked even when a rule matches them
+            let pruned = e.depth() > 0 && e.file_type().is_dir()
+                && (rules.prunes(e.path()) || ignored.is_ignored(e.path(), true));
+            if pruned {
+                ScanCounters::add(&counters.dirs_pruned, 1);
+            }
+            !pruned
+        });
+    let mut open: Vec<OpenDir> = Vec::new();
+    while let Some(entry) = walker.next() {
+        let Ok(entry) = entry else { continue };
+        ctx.check()?;
+        let path = entry.path();
+        let seen = visited.fetch_add(1, Ordering::Relaxed) + 1;
+        if seen.is_multiple_of(256) {
+            ctx.report(Phase::Walk, seen, None, Some(path));
+        }
+        while open.last().is_some_and(|d| d.depth >= entry.depth()) {
+            let done = open.pop().unwrap();
+            out.close_dir(done);
+        }
+        for dir in &mut open {
+            dir.entries += 1;
+        }
+        let mut found = false;
+
+        if entry.file_type().is_dir() {
+            ScanCounters::add(&counters.dirs, 1);
+            let name = entry.file_name().to_string_lossy();
+            if name == "node_modules" || is_cache_dir(path) {
+                // Package enumeration covers the whole subtree, so nested
+                // node_modules are never collected (and walked) twice
+                out.package_dirs.push(entry.into_path());
+                walker.skip_current_dir();
+                found = true;
+            } else if is_provider_cache_dir(path) {
+                out.provider_dirs.push(entry.into_path());
+                walker.skip_current_dir();
+                found = true;
+            } else if is_cargo_dir(path) {
+                // Checked before markers: Cargo tags `target/` with a CACHEDIR.TAG
+                out.cargo_dirs.push(entry.into_path());
+                walker.skip_current_dir();
+                found = true;
+            } else if is_python_dir(path) {
+                out.python_dirs.push(entry.into_path());
+                walker.skip_current_dir();
+                found = true;
+            } else if is_mod_cache(path) {
+                out.go_dirs.push(entry.into_path());
+                walker.skip_current_dir();
+                found = true;
+            } else if let Some(marker) = cache_markers::marker(path) {
+                // Marked trees are caches as a whole; nothing below them is walked
+                walker.skip_current_dir();
+                if rules.marker_policy(marker) != MarkerPolicy::Skip {
+                    out.marked_dirs.push((entry.into_path(), marker));
+                    found = true;
+                }
+            } else if entry.depth() > 0 && cache.is_some_and(|c| c.is_known_empty(path)) {
+                ScanCounters::add(&counters.negative_hits, 1);
+                walker.skip_current_dir();
+            } else {
+                open.push(OpenDir { depth: entry.depth(), path: entry.into_path(), entries: 0, found: false });
+            }
+        } else if entry.file_type().is_file() && entry.file_name() == ".terraform.lock.hcl" {
+            out.manifests.push(Manifest::TerraformLock(entry.into_path()));
+            found = true;
+        } else if entry.file_type().is_file() && entry.file_name() == "Cargo.lock" {
+            out.manifests.push(Manifest::CargoLock(entry.into_path()));
+            found = true;
+        } else if entry.file_type().is_file() && python_caches::LOCK_FILES.iter().any(|f| entry.file_name() == *f) {
+            out.manifests.push(Manifest::PythonLock(entry.into_path()));
+            found = true;
+        } else if entry.file_type().is_file() && entry.file_name() == "go.sum" {
+            out.manifests.push(Manifest::GoSum(entry.into_path()));
+            found = true;
+        } else if entry.file_type().is_file() && entry.file_name() == "package.json" {
+            // Skip node_modules package.json files
+            if path.to_string_lossy().contains("node_modules") {
+                continue;
+            }
+            out.manifests.push(Manifest::PackageJson(entry.into_path()));
+            found = true;
+        }
+        if found {
+            for dir in &mut open {
+                dir.found = true;
+            }
+        }
+    }
+    while let Some(done) = open.pop() {
+        out.close_dir(done);
+    }
+    Ok(out)
+}
+
+/// Count a file a parser read in full towards the scan's bytes read
+fn count_read(counters: &ScanCounters, path: &Path) -> u64 {
+    let len = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    ScanCounters::add(&counters.bytes_read, len);
+    len
+}
+
+fn timing(path: &Path, started: Instant, bytes: u64) -> FileTiming {
+    FileTiming { path: path.to_string_lossy().to_string(), micros: started.elapsed().as_micros() as u64, bytes }
+}
+
+fn parse_locked_project(lock: &Path, manager: PackageManager, counters: &ScanCounters, timings: &mut Vec<FileTiming>) -> Option<ProjectRecord> {
+    let dir = lock.parent()?;
+    let started = Instant::now();
+    let locked = match manager {
+        PackageManager::Cargo => parse_cargo_lock(lock),
+        PackageManager::Python => parse_python_lock(lock),
+        PackageManager::Go => parse_go_sum(lock),
+        _ => parse_terraform_lock(lock),
+    };
+    let bytes = count_read(counters, lock);
+    timings.push(timing(lock, started, bytes));
+    let mtime = fs::metadata(lock).and_then(|m| m.modified()).ok()
+        .map(to_utc).unwrap_or_else(Utc::now);
+    ScanCounters::add(&counters.files, 1);
+    Some(ProjectRecord {
+        path: dir.to_string_lossy().to_string(),
+        manager: Some(manager),
+        dependencies: locked,
+        mtime,
+    })
+}
+
+fn parse_project(
+    package_json: &Path,
+    counters: &ScanCounters,
+    cache: Option<&ScanCache>,
+    timings: &mut Vec<FileTiming>,
+) -> Option<ParsedProject> {
+    let dir = package_json.parent()?;
+    let manager = detect_manager_from_lock(dir);
+    let mtime = fs::metadata(package_json).and_then(|m| m.modified()).ok()
+        .map(to_utc).unwrap_or_else(Utc::now);
+    ScanCounters::add(&counters.files, 1);
+
+    let started = Instant::now();
+    let mut deps: Vec<(String, String)> = Vec::new();
+    let mut deps_hash = None;
+    let mut manifest_bytes = 0;
+    if let Ok(content) = counters.read(package_json) {
+        manifest_bytes = content.len() as u64;
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&content) {
+            deps_hash = Some(ScanCache::dependency_hash(&json));
+            for key in ["dependencies", "devDependencies", "peerDependencies"] {
+                if let Some(obj) = json.get(key).and_then(|v| v.as_object()) {
+                    for (name, ver) in obj {
+                        if let Some(ver_str) = ver.as_str() {
+                            deps.push((name.clone(), ver_str.to_string()));
                         }
                     }
                 }
             }
         }
-        
-        let lock_deps = match manager {
-            Some(PackageManager::Npm) => parse_npm_package_lock(&dir.join("package-lock.json")),
-            Some(PackageManager::Yarn) => parse_yarn_lock(&dir.join("yarn.lock")),
-            Some(PackageManager::Pnpm) => parse_pnpm_lock(&dir.join("pnpm-lock.yaml")),
-            None => Vec::new(),
-        };
-        
-        let mut all_deps = deps;
-        all_deps.extend(lock_deps);
+    }
+    timings.push(timing(package_json, started, manifest_bytes));
+
+    let lockfile = match manager {
+        Some(PackageManager::Npm) => dir.join("package-lock.json"),
+        Some(PackageManager::Yarn) => dir.join("yarn.lock"),
+        Some(PackageManager::Pnpm) => dir.join("pnpm-lock.yaml"),
+        _ => PathBuf::new(),
+    };
+    let started = Instant::now();
+    // Hashing is a single read; parsing (YAML for pnpm) is the expensive part
+    let lock_hash = manager.as_ref().and_then(|_| fs::read(&lockfile).ok()).map(|bytes| {
+        ScanCounters::add(&counters.bytes_read, bytes.len() as u64);
+        ScanCache::lockfile_hash(&bytes)
+    });
+    let remembered = cache.zip(lock_hash.as_deref()).zip(deps_hash.as_deref())
+        .and_then(|((c, lock), deps)| c.lockfile_deps(dir, lock, deps));
+    let lock_deps = match (remembered, &manager) {
+        (Some(remembered), _) => {
+            ScanCounters::add(&counters.lockfiles_reused, 1);
+            remembered.to_vec()
+        }
+        (None, Some(PackageManager::Npm)) => parse_npm_package_lock(&lockfile),
+        (None, Some(PackageManager::Yarn)) => parse_yarn_lock(&lockfile),
+        (None, Some(PackageManager::Pnpm)) => parse_pnpm_lock(&lockfile),
+        (None, _) => Vec::new(),
+    };
+    let mut parsed_lockfile = None;
+    if remembered.is_none() && manager.is_some() {
+        let bytes = count_read(counters, &lockfile);
+        timings.push(timing(&lockfile, started, bytes));
+        if let (Some(lock_hash), Some(deps_hash)) = (lock_hash, deps_hash) {
+            parsed_lockfile = Some((dir.to_path_buf(), LockfileEntry {
+                lock_hash,
+                deps_hash,
+                dependencies: lock_deps.clone(),
+                cached_at: Utc::now(),
+            }));
+        }
+    }
 
-        Some(ProjectRecord {
+    let direct: Vec<String> = deps.iter().map(|(n, _)| n.clone()).collect();
+    let mut all_deps = deps;
+    all_deps.extend(lock_deps);
+
+    Some(ParsedProject {
+        project: ProjectRecord {
             path: dir.to_string_lossy().to_string(),
             manager,
             dependencies: all_deps,
             mtime,
-        })
-    }
+        },
+        direct,
+        lockfile: parsed_lockfile,
+    })
 }
 
 /// Main scan function - uses incremental caching for improved performance
-pub fn scan(paths: &[PathBuf]) -> Result<ScanOutput> {
+pub fn scan(paths: &[PathBuf]) -> crate::Result<ScanOutput> {
     scan_with_cache(paths, true)
 }
 
 /// Scan with optional caching
-pub fn scan_with_cache(paths: &[PathBuf], use_cache: bool) -> Result<ScanOutput> {
+pub fn scan_with_cache(paths: &[PathBuf], use_cache: bool) -> crate::Result<ScanOutput> {
+    scan_with_context(paths, use_cache, &OperationContext::default())
+}
+
+/// Scan reporting progress to `ctx` and stopping with `Error::Cancelled`
+/// once its token is cancelled. The scan cache is only saved for complete scans.
+pub fn scan_with_context(paths: &[PathBuf], use_cache: bool, ctx: &OperationContext) -> crate::Result<ScanOutput> {
+    scan_validated(paths, use_cache, CacheValidation::Fast, ctx)
+}
+
+/// `scan_with_context`, checking cached entries with the given validation
+pub fn scan_validated(
+    paths: &[PathBuf],
+    use_cache: bool,
+    validation: CacheValidation,
+    ctx: &OperationContext,
+) -> crate::Result<ScanOutput> {
+    scan_impl(paths, use_cache, validation, false, ctx).map_err(Error::lift(Error::Scan))
+}
+
+/// Fast scan that sizes no package up front: sizes come from the scan cache
+/// where it has them, the rest are 0 and listed in `deferred_sizes` for
+/// `size_plan_items` to fill in once a plan has picked its candidates
+pub fn scan_lazy(paths: &[PathBuf], validation: CacheValidation, ctx: &OperationContext) -> crate::Result<ScanOutput> {
+    scan_impl(paths, true, validation, true, ctx).map_err(Error::lift(Error::Scan))
+}
+
+/// Scan without holding every record: projects, packages and dependency
+/// edges are handed to `visit` as they are produced, packages in batches of
+/// `STREAM_BATCH`, so memory grows with the number of package paths rather
+/// than with the records themselves. An error from `visit` stops the scan;
+/// the scan cache is only saved when it completes. No package snapshots are
+/// recorded for `top`, as they would hold every package until the end.
+pub fn scan_each(
+    paths: &[PathBuf],
+    use_cache: bool,
+    validation: CacheValidation,
+    lazy: bool,
+    ctx: &OperationContext,
+    mut visit: impl FnMut(ScanItem) -> Result<()>,
+) -> crate::Result<ScanSummary> {
+    scan_stream(paths, use_cache, validation, lazy, false, ctx, &mut visit).map_err(Error::lift(Error::Scan))
+}
+
+/// Packages sized together and then handed to the visitor
+const STREAM_BATCH: usize = 4096;
+
+fn scan_impl(
+    paths: &[PathBuf],
+    use_cache: bool,
+    validation: CacheValidation,
+    lazy: bool,
+    ctx: &OperationContext,
+) -> Result<ScanOutput> {
+    let (mut packages, mut projects, mut edges, mut marked_dirs) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    // Every record is held here anyway, so the scan can snapshot the packages
+    let summary = scan_stream(paths, use_cache, validation, lazy, true, ctx, &mut |item| {
+        match item {
+            ScanItem::Project(project) => projects.push(project),
+            ScanItem::Package(package) => packages.push(package),
+            ScanItem::Edge { from, to } => edges.push((from, to)),
+            ScanItem::Marked(dir) => marked_dirs.push(dir),
+        }
+        Ok(())
+    })?;
+    Ok(ScanOutput {
+        packages,
+        projects,
+        edges,
+        stats: summary.stats,
+        deferred_sizes: summary.deferred_sizes,
+        marked_dirs,
+    })
+}
+
+fn scan_stream(
+    paths: &[PathBuf],
+    use_cache: bool,
+    validation: CacheValidation,
+    lazy: bool,
+    snapshot_packages: bool,
+    ctx: &OperationContext,
+    visit: &mut dyn FnMut(ScanItem) -> Result<()>,
+) -> Result<ScanSummary> {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let cpu_before = process_cpu_ms();
     let roots: Vec<PathBuf> = if paths.is_empty() { 
         vec![std::env::current_dir()?] 
     } else { 
-        paths.to_vec() 
+        normalize_roots(paths)
     };
 
+    // Wait out overlapping scans so this one loads the cache they save
+    let _lease = if use_cache { Some(ScanLease::acquire(&ScanLease::default_dir(), &roots, ctx)?) } else { None };
+
     // Initialize cache with Mutex for thread-safe updates
     let cache_path = ScanCache::default_cache_path();
-    let cache = if use_cache {
-        ScanCache::load_or_create(&cache_path).unwrap_or_else(|_| ScanCache::new())
+    let mut cache = if use_cache {
+        ScanCache::load_for_roots(&cache_path, &roots).unwrap_or_else(|_| ScanCache::new())
     } else {
         ScanCache::new()
     };
+    cache.set_validation(validation);
+    if use_cache {
+        cache.apply_change_feed(&roots);
+        for project in install_events::installed_since_scan(&cache) {
+            cache.forget_below(&project);
+        }
+    }
     let cache = Mutex::new(cache);
 
     // Single-pass collection
     let mut collector = SinglePassCollector::new();
-    collector.collect(&roots)?;
-
-    // Process packages in parallel with thread-safe cache access
-    let packages: Vec<PackageRecord> = collector.package_dirs.par_iter().flat_map(|dir| {
-        WalkDir::new(dir).min_depth(1).max_depth(3)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_dir())
-            .filter_map(|pkg_dir| {
-                let pkg_path = pkg_dir.path().to_path_buf();
-                let package_json = pkg_path.join("package.json");
-                if !package_json.exists() { return None; }
-                
-                let meta = fs::metadata(&pkg_path).ok()?;
-                let atime = meta.accessed().ok().map(to_utc).unwrap_or_else(|| Utc::now());
-                let mtime = meta.modified().ok().map(to_utc).unwrap_or_else(|| Utc::now());
-                
-                // Use cached size if available, otherwise compute
-                let size = if use_cache {
-                    let cached_size = cache.lock().ok()
-                        .and_then(|c| c.get_cached_size(&pkg_path));
-                    
-                    if let Some(size) = cached_size {
-                        size
-                    } else {
-                        let computed = dir_size(&pkg_path);
-                        if let Ok(mut c) = cache.lock() {
-                            let _ = c.update(&pkg_path, computed);
-                        }
-                        computed
-                    }
-                } else {
-                    dir_size(&pkg_path)
-                };
-                
-                let (name, version) = if let Ok(text) = fs::read_to_string(&package_json) {
-                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                        let n = json.get("name").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
-                        let v = json.get("version").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
-                        (n, v)
-                    } else { ("unknown".into(), "unknown".into()) }
-                } else { ("unknown".into(), "unknown".into()) };
-                
-                Some(PackageRecord {
-                    name,
-                    version,
-                    path: pkg_path.to_string_lossy().to_string(),
-                    size_bytes: size,
-                    atime,
-                    mtime,
-                    manager: None,
-                    project_paths: Vec::new(),
-                })
+    let home = dirs::home_dir().and_then(|h| dunce::canonicalize(h).ok());
+    let wide = roots.iter()
+        .any(|r| crate::wide_scan::is_wide_root(&dunce::canonicalize(r).unwrap_or_else(|_| r.clone()), home.as_deref()));
+    if wide {
+        eprintln!("{}", crate::wide_scan::notice(&roots));
+    }
+    collector.rules = scan_rules::load().wide(wide);
+    {
+        let cached = cache.lock().ok();
+        collector.collect(&roots, ctx, cached.as_deref().filter(|_| use_cache))?;
+    }
+    if use_cache {
+        if let Ok(mut c) = cache.lock() {
+            for dir in &collector.empty_dirs {
+                let _ = c.mark_empty(dir);
+            }
+            for (project, entry) in collector.lockfiles.drain(..) {
+                c.update_lockfile(&project, entry);
+            }
+        }
+    }
+    let counters = &collector.counters;
+
+    // Enumerate package directories, each exactly once even when reached
+    // through more than one package dir
+    let mut seen_dirs: HashSet<PathBuf> = HashSet::new();
+    let pkg_paths: Vec<PathBuf> = collector.package_dirs.par_iter()
+        .flat_map(|dir| {
+            if dir.file_name().is_some_and(|n| n == "node_modules") {
+                node_modules_packages(dir)
+            } else {
+                cache_dir_packages(dir)
+            }
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .filter(|p| seen_dirs.insert(p.clone()))
+        .collect();
+    ctx.check()?;
+
+    let mut summary = ScanSummary::default();
+    for project in std::mem::take(&mut collector.projects) {
+        summary.projects += 1;
+        visit(ScanItem::Project(project))?;
+    }
+    // Edges resolve against every enumerated package directory
+    for (project, names) in &collector.project_deps {
+        for (from, to) in resolve_edges(project, names, &seen_dirs) {
+            summary.edges += 1;
+            visit(ScanItem::Edge { from, to })?;
+        }
+    }
+
+    // Process packages in parallel with thread-safe cache access, one batch
+    // at a time so only a batch of records is held
+    let total_pkgs = pkg_paths.len() as u64;
+    let sized = AtomicU64::new(0);
+    // Kept for `top` when asked for, the scan is recorded and sizes every package
+    let keep_snapshot = snapshot_packages && use_cache && !lazy;
+    let mut snapshot: Vec<PackageSnapshot> = Vec::new();
+    for batch in pkg_paths.chunks(STREAM_BATCH) {
+        let records: Vec<(PackageRecord, Vec<String>)> = timings::time(TimedPhase::Size, || batch.par_iter()
+            .filter(|_| !ctx.cancel.is_cancelled())
+            .filter_map(|pkg_path| {
+                let record = package_record(pkg_path, use_cache, lazy, &cache, counters);
+                let done = sized.fetch_add(1, Ordering::Relaxed) + 1;
+                if done.is_multiple_of(64) || done == total_pkgs {
+                    ctx.report(Phase::Size, done, Some(total_pkgs), Some(pkg_path));
+                }
+                record
             })
-            .collect::<Vec<_>>()
-    }).collect();
+            .collect());
+        ctx.check()?;
+
+        let mut edges = Vec::new();
+        for (record, names) in &records {
+            edges.extend(resolve_edges(Path::new(&record.path), names, &seen_dirs));
+        }
+        let packages: Vec<PackageRecord> = records.into_iter().map(|(r, _)| r).collect();
+        if keep_snapshot {
+            snapshot.extend(packages.iter().map(PackageSnapshot::from));
+        }
+        emit_packages(packages, lazy, &cache, &mut summary, visit)?;
+        for (from, to) in edges {
+            summary.edges += 1;
+            visit(ScanItem::Edge { from, to })?;
+        }
+    }
+
+    let providers: Vec<_> = collector.provider_dirs.iter().flat_map(|d| provider_packages(d))
+        .chain(collector.cargo_dirs.iter().flat_map(|d| cargo_packages(d)))
+        .chain(collector.python_dirs.iter().flat_map(|d| python_packages(d)))
+        .chain(collector.go_dirs.iter().flat_map(|d| go_packages(d)))
+        .collect();
+    let sizing = timings::span(TimedPhase::Size);
+    let providers = providers.into_par_iter().map(|(path, name, version, manager)| {
+        let meta = fs::metadata(&path).ok();
+        // A profile's or environment's own mtime misses writes below it
+        let built = match manager {
+            PackageManager::Cargo => cargo_caches::last_modified(&path),
+            PackageManager::Python => python_caches::last_modified(&path),
+            _ => None,
+        };
+        ScanCounters::add(&counters.files, 1);
+        let totals = package_totals(&path, use_cache, lazy, &cache, counters);
+        PackageRecord {
+            name,
+            version,
+            path: path.to_string_lossy().to_string(),
+            size_bytes: totals.bytes,
+            linked_bytes: totals.linked_bytes,
+            file_count: totals.files,
+            inode_count: totals.files + totals.dirs,
+            atime: meta.as_ref().and_then(|m| m.accessed().ok()).map(to_utc).unwrap_or_else(Utc::now),
+            mtime: built.or_else(|| meta.as_ref().and_then(|m| m.modified().ok()).map(to_utc)).unwrap_or_else(Utc::now),
+            manager: Some(manager),
+            project_paths: Vec::new(),
+        }
+    }).collect::<Vec<_>>();
+    ctx.check()?;
+    if keep_snapshot {
+        snapshot.extend(providers.iter().map(PackageSnapshot::from));
+    }
+    emit_packages(providers, lazy, &cache, &mut summary, visit)?;
+
+    // Marked trees the rules let cleanup remove become packages; the rest
+    // are only reported
+    let marked = collector.marked_dirs.par_iter().map(|(path, marker)| {
+        let totals = package_totals(path, use_cache, lazy, &cache, counters);
+        (path, *marker, totals)
+    }).collect::<Vec<_>>();
+    drop(sizing);
+    ctx.check()?;
+    let mut cleanable = Vec::new();
+    for (path, marker, totals) in marked {
+        if collector.rules.marker_policy(marker) == MarkerPolicy::Clean {
+            cleanable.push(marked_package(path, totals));
+        } else {
+            summary.marked_dirs += 1;
+            visit(ScanItem::Marked(MarkedDir {
+                path: path.to_string_lossy().to_string(),
+                marker,
+                size_bytes: totals.bytes,
+                file_count: totals.files,
+            }))?;
+        }
+    }
+    if keep_snapshot {
+        snapshot.extend(cleanable.iter().map(PackageSnapshot::from));
+    }
+    emit_packages(cleanable, lazy, &cache, &mut summary, visit)?;
+
+    // Reconcile the store reference index with the links under the scanned roots
+    if let Ok(store) = get_global_store_path() {
+        let links = store_links(&collector.package_dirs, &pkg_paths, &store);
+        sync_store_index(&roots, &links);
+    }
 
     // Save cache
     if use_cache {
@@ -218,15 +952,302 @@ pub fn scan_with_cache(paths: &[PathBuf], use_cache: bool) -> Result<ScanOutput>
         }
     }
 
-    Ok(ScanOutput { 
-        packages, 
-        projects: collector.projects, 
-        edges: collector.edges 
-    })
+    let mut stats = counters.stats(started_at, started, cpu_before);
+    (stats.parse_ms, stats.slowest_files) = collector.parse_timings(SLOWEST_FILES);
+    if use_cache {
+        record_scan_stats(&roots, &summary, &stats, keep_snapshot.then_some(snapshot.as_slice()));
+    }
+    summary.stats = stats;
+    Ok(summary)
+}
+
+/// A marked tree as a package, named after it and the directory holding it
+/// (`vendor` alone says little)
+fn marked_package(path: &Path, totals: TreeTotals) -> PackageRecord {
+    let meta = fs::metadata(path).ok();
+    let name = [path.parent().and_then(Path::file_name), path.file_name()]
+        .iter()
+        .flatten()
+        .map(|n| n.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    PackageRecord {
+        name,
+        version: String::new(),
+        path: path.to_string_lossy().to_string(),
+        size_bytes: totals.bytes,
+        linked_bytes: totals.linked_bytes,
+        file_count: totals.files,
+        inode_count: totals.files + totals.dirs,
+        atime: meta.as_ref().and_then(|m| m.accessed().ok()).map(to_utc).unwrap_or_else(Utc::now),
+        mtime: meta.as_ref().and_then(|m| m.modified().ok()).map(to_utc).unwrap_or_else(Utc::now),
+        manager: None,
+        project_paths: Vec::new(),
+    }
+}
+
+/// Hand a batch of packages to the visitor, noting those a lazy scan left
+/// unsized
+fn emit_packages(
+    packages: Vec<PackageRecord>,
+    lazy: bool,
+    cache: &Mutex<ScanCache>,
+    summary: &mut ScanSummary,
+    visit: &mut dyn FnMut(ScanItem) -> Result<()>,
+) -> Result<()> {
+    if lazy {
+        let cache = cache.lock().ok();
+        summary.deferred_sizes.extend(packages.iter()
+            .filter(|p| p.size_bytes == 0 && cache.as_ref().is_none_or(|c| c.get_cached_size(Path::new(&p.path)).is_none()))
+            .map(|p| p.path.clone()));
+    }
+    for package in packages {
+        summary.packages += 1;
+        summary.package_bytes += package.size_bytes;
+        visit(ScanItem::Package(package))?;
+    }
+    Ok(())
+}
+
+/// Best-effort log of the scan's figures to the feature store
+/// and, for scans that sized every package, of the packages found
+fn record_scan_stats(roots: &[PathBuf], summary: &ScanSummary, stats: &ScanStats, snapshot: Option<&[PackageSnapshot]>) {
+    let result = FeatureStore::open_default().and_then(|store| {
+        let scan_id = store.record_scan(roots, summary.packages, summary.package_bytes, stats)?;
+        snapshot.map_or(Ok(()), |packages| store.record_snapshot(scan_id, packages))
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: Failed to record scan statistics: {}", e);
+    }
+}
+
+/// Symlinks into the global store found in the collected `node_modules`
+/// directories and the nested ones of every package, as (link, store entry)
+fn store_links(package_dirs: &[PathBuf], pkg_paths: &[PathBuf], store: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let node_modules = package_dirs.iter()
+        .filter(|d| d.file_name().is_some_and(|n| n == "node_modules"))
+        .cloned()
+        .chain(pkg_paths.iter().map(|p| p.join("node_modules")).filter(|d| d.is_dir()));
+
+    let mut links = Vec::new();
+    for dir in node_modules {
+        let entries = fs::read_dir(&dir).into_iter().flatten().filter_map(|e| e.ok());
+        for entry in entries {
+            let path = entry.path();
+            let candidates = if entry.file_name().to_string_lossy().starts_with('@') && path.is_dir() {
+                fs::read_dir(&path).into_iter().flatten().filter_map(|e| e.ok()).map(|e| e.path()).collect()
+            } else {
+                vec![path]
+            };
+            for link in candidates {
+                if let Ok(target) = fs::read_link(&link) {
+                    if target.starts_with(store) {
+                        links.push((link, target));
+                    }
+                }
+            }
+        }
+    }
+    links
+}
+
+/// Best-effort update of the store reference index; the database is only
+/// created once there is something to record
+fn sync_store_index(roots: &[PathBuf], links: &[(PathBuf, PathBuf)]) {
+    if links.is_empty() && !StoreIndex::default_db_path().exists() {
+        return;
+    }
+    let result = StoreIndex::open_default().and_then(|mut index| {
+        for root in roots {
+            let under_root: Vec<_> = links.iter().filter(|(l, _)| l.starts_with(root)).cloned().collect();
+            index.sync_root(root, &under_root)?;
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: Failed to update store reference index: {}", e);
+    }
+}
+
+/// Resolve dependency names the way Node does: look in `<dir>/node_modules`
+/// for each ancestor of `from`, nearest first. Symlinked entries (pnpm) are
+/// followed to their real location. Yields `(from, package path)` edges for
+/// dependencies that resolve to a scanned package.
+fn resolve_edges(from: &Path, names: &[String], known: &HashSet<PathBuf>) -> Vec<(String, String)> {
+    let parent = from.to_string_lossy().to_string();
+    let mut edges = Vec::new();
+    for name in names {
+        for dir in from.ancestors().filter(|d| d.file_name().is_none_or(|n| n != "node_modules")) {
+            let candidate = dir.join("node_modules").join(name);
+            let resolved = if known.contains(&candidate) {
+                Some(candidate)
+            } else if candidate.is_symlink() {
+                fs::canonicalize(&candidate).ok()
+                    .filter(|real| known.contains(real))
+            } else {
+                None
+            };
+            if let Some(target) = resolved {
+                edges.push((parent.clone(), target.to_string_lossy().to_string()));
+                break;
+            }
+        }
+    }
+    edges
+}
+
+/// Size and file counts of a package directory, from the scan cache when it
+/// is still valid. Entries cached before file counts were kept count as misses.
+fn cached_dir_totals(pkg_path: &Path, use_cache: bool, cache: &Mutex<ScanCache>, counters: &ScanCounters) -> TreeTotals {
+    if !use_cache {
+        return dir_totals(pkg_path, counters);
+    }
+    let cached = cache.lock().ok()
+        .and_then(|c| c.get_cached_totals(pkg_path));
+    if let Some(totals) = cached {
+        ScanCounters::add(&counters.cache_hits, 1);
+        return totals;
+    }
+    ScanCounters::add(&counters.cache_misses, 1);
+    let computed = dir_totals(pkg_path, counters);
+    if let Ok(mut c) = cache.lock() {
+        let _ = c.update_totals(pkg_path, &computed);
+    }
+    computed
+}
+
+/// Size and file counts of a package for the scan: lazy scans take only what
+/// the cache knows and leave the rest at 0
+fn package_totals(pkg_path: &Path, use_cache: bool, lazy: bool, cache: &Mutex<ScanCache>, counters: &ScanCounters) -> TreeTotals {
+    if !lazy {
+        return cached_dir_totals(pkg_path, use_cache, cache, counters);
+    }
+    // Sizes cached before file counts were kept come without counts
+    let cached = cache.lock().ok().and_then(|c| {
+        let size = c.get_cached_size(pkg_path)?;
+        Some(c.get_cached_totals(pkg_path).unwrap_or(TreeTotals { bytes: size, ..Default::default() }))
+    });
+    match cached {
+        Some(totals) => {
+            ScanCounters::add(&counters.cache_hits, 1);
+            totals
+        }
+        None => {
+            ScanCounters::add(&counters.sizes_deferred, 1);
+            TreeTotals::default()
+        }
+    }
+}
+
+/// Fill in the sizes a lazy `scan` deferred for the items of `report` that
+/// count a package's size, updating the scan cache, and recompute the total.
+/// Returns the number of items sized.
+pub fn size_plan_items(report: &mut DryRunReport, scan: &ScanOutput) -> usize {
+    if scan.deferred_sizes.is_empty() {
+        return 0;
+    }
+    let _timing = timings::span(TimedPhase::Size);
+    let cache_path = ScanCache::default_cache_path();
+    let cache = Mutex::new(ScanCache::load_or_create(&cache_path).unwrap_or_else(|_| ScanCache::new()));
+    let sized = size_items(report, &scan.deferred_sizes, &cache);
+    if sized > 0 {
+        if let Ok(mut c) = cache.lock() {
+            if let Err(e) = c.save(&cache_path) {
+                eprintln!("Warning: Failed to save scan cache: {}", e);
+            }
+        }
+    }
+    sized
+}
+
+/// Set the inodes `report` would free: the items that count toward its
+/// size, taking each package's count from the scan and counting other
+/// targets (whole `node_modules`, caches) on disk. Returns the total.
+pub fn count_plan_inodes(report: &mut DryRunReport, scan: &ScanOutput) -> u64 {
+    let scanned: HashMap<&str, &PackageRecord> = scan.packages.iter().map(|p| (p.path.as_str(), p)).collect();
+    let total = report.items.par_iter()
+        .filter(|item| item.reason.counts_size())
+        .map(|item| {
+            let path = Path::new(&item.target_path);
+            match scanned.get(item.target_path.as_str()) {
+                Some(p) if p.inode_count > 0 => p.inode_count,
+                Some(_) => {
+                    let t = crate::native_walk::tree_totals(path, Some("node_modules"));
+                    t.files + t.dirs
+                }
+                None => {
+                    let t = crate::native_walk::tree_totals(path, None);
+                    t.files + t.dirs
+                }
+            }
+        })
+        .sum();
+    report.estimated_inodes = Some(total);
+    total
+}
+
+fn size_items(report: &mut DryRunReport, deferred: &[String], cache: &Mutex<ScanCache>) -> usize {
+    let deferred: HashSet<&str> = deferred.iter().map(String::as_str).collect();
+    let counters = ScanCounters::default();
+    let sized: usize = report.items.par_iter_mut()
+        .filter(|item| item.reason.counts_size() && deferred.contains(item.target_path.as_str()))
+        .map(|item| {
+            item.estimated_size_bytes = cached_dir_totals(Path::new(&item.target_path), true, cache, &counters).bytes;
+            1
+        })
+        .sum();
+    report.total_estimated_bytes = report.items.iter().map(|i| i.estimated_size_bytes).sum();
+    sized
+}
+
+/// Build the record for one package directory, using the cached size when
+/// available. Also returns the dependency names declared in its manifest.
+fn package_record(
+    pkg_path: &Path,
+    use_cache: bool,
+    lazy: bool,
+    cache: &Mutex<ScanCache>,
+    counters: &ScanCounters,
+) -> Option<(PackageRecord, Vec<String>)> {
+    let package_json = pkg_path.join("package.json");
+    let meta = fs::metadata(pkg_path).ok()?;
+    ScanCounters::add(&counters.files, 1);
+    let atime = meta.accessed().ok().map(to_utc).unwrap_or_else(Utc::now);
+    let mtime = meta.modified().ok().map(to_utc).unwrap_or_else(Utc::now);
+
+    let totals = package_totals(pkg_path, use_cache, lazy, cache, counters);
+
+    let mut deps: Vec<String> = Vec::new();
+    let (name, version) = if let Ok(text) = counters.read(&package_json) {
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
+            let n = json.get("name").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+            let v = json.get("version").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+            for key in ["dependencies", "optionalDependencies", "peerDependencies"] {
+                if let Some(obj) = json.get(key).and_then(|v| v.as_object()) {
+                    deps.extend(obj.keys().cloned());
+                }
+            }
+            (n, v)
+        } else { ("unknown".into(), "unknown".into()) }
+    } else { ("unknown".into(), "unknown".into()) };
+
+    Some((PackageRecord {
+        name,
+        version,
+        path: pkg_path.to_string_lossy().to_string(),
+        size_bytes: totals.bytes,
+        linked_bytes: totals.linked_bytes,
+        file_count: totals.files,
+        inode_count: totals.files + totals.dirs,
+        atime,
+        mtime,
+        manager: None,
+        project_paths: Vec::new(),
+    }, deps))
 }
 
 /// Scan without using cache (for testing or forced refresh)
-pub fn scan_no_cache(paths: &[PathBuf]) -> Result<ScanOutput> {
+pub fn scan_no_cache(paths: &[PathBuf]) -> crate::Result<ScanOutput> {
     scan_with_cache(paths, false)
 }
 
@@ -265,12 +1286,126 @@ mod tests {
         fs::write(project_dir.join("package.json"), r#"{"name": "test", "version": "1.0.0"}"#).unwrap();
         
         let mut collector = SinglePassCollector::new();
-        collector.collect(&[temp.path().to_path_buf()]).unwrap();
+        collector.collect(&[temp.path().to_path_buf()], &OperationContext::default(), None).unwrap();
         
         assert_eq!(collector.projects.len(), 1);
         assert_eq!(collector.projects[0].path, project_dir.to_string_lossy());
     }
 
+    #[test]
+    fn test_rules_prune_walk() {
+        let temp = tempdir().unwrap();
+        for dir in ["app", "app/.git/objects", "media/vendored"] {
+            fs::create_dir_all(temp.path().join(dir)).unwrap();
+            fs::write(temp.path().join(dir).join("package.json"), "{}").unwrap();
+        }
+
+        let mut collector = SinglePassCollector::new();
+        collector.rules = ScanRules::compile(&scan_rules::ScanRulesConfig {
+            skip: vec!["**/.git".into(), "**/media".into()],
+            keep: Vec::new(),
+            wide_skip: Vec::new(),
+            ..Default::default()
+        }).unwrap();
+        collector.collect(&[temp.path().to_path_buf()], &OperationContext::default(), None).unwrap();
+        assert_eq!(collector.projects.len(), 1);
+        assert!(collector.projects[0].path.ends_with("app"));
+        assert_eq!(collector.counters.dirs_pruned.load(Ordering::Relaxed), 2);
+
+        // A root is walked even when a rule matches it
+        let mut rooted = SinglePassCollector::new();
+        rooted.rules = collector.rules.clone();
+        rooted.collect(&[temp.path().join("media")], &OperationContext::default(), None).unwrap();
+        assert_eq!(rooted.projects.len(), 1);
+    }
+
+    #[test]
+    fn test_parallel_roots_merge_in_walk_order() {
+        let temp = tempdir().unwrap();
+        let mut roots = Vec::new();
+        for name in ["a", "b", "c"] {
+            let root = temp.path().join(name);
+            fs::create_dir_all(root.join("app")).unwrap();
+            fs::write(root.join("app/package.json"), r#"{"dependencies": {"x": "^1.0.0"}}"#).unwrap();
+            fs::write(root.join("app/package-lock.json"),
+                r#"{"lockfileVersion": 3, "packages": {"node_modules/x": {"version": "1.0.1"}}}"#).unwrap();
+            roots.push(root);
+        }
+
+        let mut collector = SinglePassCollector::new();
+        collector.collect(&roots, &OperationContext::default(), None).unwrap();
+        let paths: Vec<_> = collector.projects.iter().map(|p| p.path.clone()).collect();
+        let expected: Vec<_> = roots.iter().map(|r| r.join("app").to_string_lossy().to_string()).collect();
+        assert_eq!(paths, expected);
+        assert_eq!(collector.lockfiles.len(), 3);
+
+        // One timing per package.json and per lockfile
+        assert_eq!(collector.timings.len(), 6);
+        let (_, slowest) = collector.parse_timings(2);
+        assert_eq!(slowest.len(), 2);
+        assert!(slowest[0].micros >= slowest[1].micros);
+    }
+
+    #[test]
+    fn test_empty_subtrees_negatively_cached() {
+        let temp = tempdir().unwrap();
+        let project_dir = temp.path().join("my-project");
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(project_dir.join("package.json"), r#"{"name": "test"}"#).unwrap();
+        let data = temp.path().join("datasets/images");
+        fs::create_dir_all(&data).unwrap();
+        for i in 0..NEGATIVE_CACHE_MIN_ENTRIES {
+            fs::write(data.join(format!("{}.png", i)), "").unwrap();
+        }
+
+        let mut collector = SinglePassCollector::new();
+        collector.collect(&[temp.path().to_path_buf()], &OperationContext::default(), None).unwrap();
+        // The outermost empty directory is recorded, not the project's parent
+        assert_eq!(collector.empty_dirs, vec![temp.path().join("datasets")]);
+
+        let mut cache = ScanCache::new();
+        cache.mark_empty(&collector.empty_dirs[0]).unwrap();
+        let mut rescan = SinglePassCollector::new();
+        rescan.collect(&[temp.path().to_path_buf()], &OperationContext::default(), Some(&cache)).unwrap();
+        assert_eq!(rescan.counters.negative_hits.load(Ordering::Relaxed), 1);
+        assert!(rescan.counters.dirs.load(Ordering::Relaxed) < 10);
+        assert_eq!(rescan.projects.len(), 1);
+    }
+
+    #[test]
+    fn test_unchanged_lockfile_not_reparsed() {
+        let temp = tempdir().unwrap();
+        let project_dir = temp.path().join("app");
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(project_dir.join("package.json"), r#"{"name": "app", "dependencies": {"a": "^1.0.0"}}"#).unwrap();
+        fs::write(project_dir.join("package-lock.json"),
+            r#"{"lockfileVersion": 3, "packages": {"node_modules/a": {"version": "1.0.1"}}}"#).unwrap();
+
+        let mut first = SinglePassCollector::new();
+        first.collect(&[temp.path().to_path_buf()], &OperationContext::default(), None).unwrap();
+        assert_eq!(first.lockfiles.len(), 1);
+        let mut cache = ScanCache::new();
+        for (project, entry) in first.lockfiles.drain(..) {
+            cache.update_lockfile(&project, entry);
+        }
+
+        // A script edit leaves the dependency hash alone
+        fs::write(project_dir.join("package.json"),
+            r#"{"name": "app", "scripts": {"build": "tsc"}, "dependencies": {"a": "^1.0.0"}}"#).unwrap();
+        let mut rescan = SinglePassCollector::new();
+        rescan.collect(&[temp.path().to_path_buf()], &OperationContext::default(), Some(&cache)).unwrap();
+        assert_eq!(rescan.counters.lockfiles_reused.load(Ordering::Relaxed), 1);
+        assert!(rescan.lockfiles.is_empty());
+        assert_eq!(rescan.projects[0].dependencies, first.projects[0].dependencies);
+
+        // A dependency edit forces a parse
+        fs::write(project_dir.join("package.json"), r#"{"name": "app", "dependencies": {"a": "^1.0.1"}}"#).unwrap();
+        let mut changed = SinglePassCollector::new();
+        changed.collect(&[temp.path().to_path_buf()], &OperationContext::default(), Some(&cache)).unwrap();
+        assert_eq!(changed.counters.lockfiles_reused.load(Ordering::Relaxed), 0);
+        assert_eq!(changed.lockfiles.len(), 1);
+    }
+
     #[test]
     fn test_scan_with_cache() {
         let temp = tempdir().unwrap();
@@ -285,5 +1420,424 @@ mod tests {
         // First scan
         let result1 = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
         assert!(!result1.packages.is_empty() || !result1.projects.is_empty());
+
+        // Walk: temp, project, node_modules; sizing: test-pkg
+        let stats = &result1.stats;
+        assert!(stats.dirs_walked >= 4);
+        assert!(stats.files_stated >= 2);
+        assert!(stats.bytes_read >= 40);
+        assert_eq!((stats.cache_hits, stats.cache_misses), (0, 0));
+    }
+
+    #[test]
+    fn test_lazy_sizes_filled_for_candidates() {
+        let temp = tempdir().unwrap();
+        let pkg = temp.path().join("node_modules/left-pad");
+        fs::create_dir_all(&pkg).unwrap();
+        fs::write(pkg.join("index.js"), "x".repeat(500)).unwrap();
+        let dup = temp.path().join("node_modules/dup");
+        fs::create_dir_all(&dup).unwrap();
+        fs::write(dup.join("index.js"), "y".repeat(300)).unwrap();
+
+        let cache = Mutex::new(ScanCache::new());
+        let counters = ScanCounters::default();
+        assert_eq!(package_totals(&pkg, true, true, &cache, &counters).bytes, 0);
+        assert_eq!(counters.sizes_deferred.load(Ordering::Relaxed), 1);
+
+        let item = |path: &Path, reason| crate::types::PlanItem {
+            target_path: path.to_string_lossy().to_string(),
+            estimated_size_bytes: 0,
+            reason,
+            blockers: Vec::new(),
+            risk: None,
+        };
+        let mut report = DryRunReport {
+            items: vec![
+                item(&pkg, crate::types::PlanReason::Orphaned),
+                item(&dup, crate::types::PlanReason::Duplicate { canonical: String::new() }),
+            ],
+            total_estimated_bytes: 0,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+        let deferred = vec![pkg.to_string_lossy().to_string(), dup.to_string_lossy().to_string()];
+        assert_eq!(size_items(&mut report, &deferred, &cache), 1);
+        assert!(report.items[0].estimated_size_bytes >= 500);
+        assert_eq!(report.items[1].estimated_size_bytes, 0);
+        assert_eq!(report.total_estimated_bytes, report.items[0].estimated_size_bytes);
+
+        // Sized once, the cache answers later lazy scans
+        let again = ScanCounters::default();
+        assert_eq!(package_totals(&pkg, true, true, &cache, &again).bytes, report.items[0].estimated_size_bytes);
+        assert_eq!(again.sizes_deferred.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_normalize_roots_collapses_nested() {
+        let temp = tempdir().unwrap();
+        let dev = temp.path().join("dev");
+        let project = dev.join("project-x");
+        let other = temp.path().join("other");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&other).unwrap();
+
+        let roots = normalize_roots(&[project.clone(), dev.clone(), dev.join("."), other.clone()]);
+        assert_eq!(roots, vec![dev, other]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_normalize_roots_keeps_symlinked_spelling() {
+        let temp = tempdir().unwrap();
+        let data = temp.path().join("data");
+        fs::create_dir_all(data.join("project-x")).unwrap();
+        let dev = temp.path().join("dev");
+        std::os::unix::fs::symlink(&data, &dev).unwrap();
+
+        // The link is walked as typed, and its target's subdirectory collapses into it
+        assert_eq!(normalize_roots(&[data.join("project-x"), dev.clone()]), vec![dev.clone()]);
+        assert_eq!(normalize_roots(&[dev.clone(), data.clone()]).len(), 1);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_normalize_roots_drops_verbatim_prefix() {
+        let temp = tempdir().unwrap();
+        let roots = normalize_roots(&[temp.path().to_path_buf()]);
+        assert!(!roots[0].to_string_lossy().starts_with(r"\\?\"), "{:?}", roots);
+    }
+
+    #[test]
+    fn test_overlapping_roots_scanned_once() {
+        let temp = tempdir().unwrap();
+        let project_dir = temp.path().join("project-x");
+        let pkg_dir = project_dir.join("node_modules").join("left-pad");
+        fs::create_dir_all(&pkg_dir).unwrap();
+        fs::write(project_dir.join("package.json"), r#"{"name": "root"}"#).unwrap();
+        fs::write(pkg_dir.join("package.json"), r#"{"name": "left-pad", "version": "1.3.0"}"#).unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf(), project_dir.clone()], false).unwrap();
+        assert_eq!(out.packages.iter().filter(|p| p.name == "left-pad").count(), 1);
+        assert_eq!(out.projects.len(), 1);
+    }
+
+    #[test]
+    fn test_nested_node_modules_enumerated_once() {
+        let temp = tempdir().unwrap();
+        let nm = temp.path().join("node_modules");
+        let write_pkg = |dir: &Path, name: &str| {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("package.json"), format!(r#"{{"name": "{}", "version": "1.0.0"}}"#, name)).unwrap();
+        };
+        write_pkg(&nm.join("a"), "a");
+        write_pkg(&nm.join("a/node_modules/b"), "b");
+        write_pkg(&nm.join("a/node_modules/b/node_modules/c"), "c");
+        write_pkg(&nm.join(".pnpm/d@1.0.0/node_modules/d"), "d");
+        fs::create_dir_all(nm.join(".bin")).unwrap();
+
+        let mut found = node_modules_packages(&nm);
+        found.sort();
+        let mut expected = vec![
+            nm.join("a"),
+            nm.join("a/node_modules/b"),
+            nm.join("a/node_modules/b/node_modules/c"),
+            nm.join(".pnpm/d@1.0.0/node_modules/d"),
+        ];
+        expected.sort();
+        assert_eq!(found, expected);
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        assert_eq!(out.packages.len(), 4);
+        let a = out.packages.iter().find(|p| p.name == "a").unwrap();
+        assert!(a.size_bytes < 100, "nested packages must not count towards their parent");
+    }
+
+    #[test]
+    fn test_scoped_packages_enumerated() {
+        let temp = tempdir().unwrap();
+        let nm = temp.path().join("node_modules");
+        for dir in [
+            "@babel/core",
+            "@babel/core/node_modules/@babel/types",
+            "@babel/core/node_modules/@babel/types/node_modules/to-fast-properties",
+            ".pnpm/@types+node@20.0.0/node_modules/@types/node",
+        ] {
+            fs::create_dir_all(nm.join(dir)).unwrap();
+            fs::write(nm.join(dir).join("package.json"), "{}").unwrap();
+        }
+        // Stray directory in a scope without a manifest is not a package
+        fs::create_dir_all(nm.join("@babel/.cache")).unwrap();
+
+        let found = node_modules_packages(&nm);
+        assert_eq!(found.len(), 4);
+        assert!(found.contains(&nm.join("@babel/core")));
+        assert!(found.contains(&nm.join("@babel/core/node_modules/@babel/types/node_modules/to-fast-properties")));
+        assert!(found.contains(&nm.join(".pnpm/@types+node@20.0.0/node_modules/@types/node")));
+        assert!(!found.contains(&nm.join("@babel")));
+    }
+
+    #[test]
+    fn test_scan_resolves_edges() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("app");
+        let nm = project.join("node_modules");
+        fs::create_dir_all(nm.join("a/node_modules/b")).unwrap();
+        fs::create_dir_all(nm.join("c")).unwrap();
+        fs::write(project.join("package.json"), r#"{"dependencies": {"a": "^1.0.0"}}"#).unwrap();
+        fs::write(nm.join("a/package.json"), r#"{"name": "a", "dependencies": {"b": "1", "c": "1"}}"#).unwrap();
+        fs::write(nm.join("a/node_modules/b/package.json"), r#"{"name": "b"}"#).unwrap();
+        fs::write(nm.join("c/package.json"), r#"{"name": "c"}"#).unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        let root = fs::canonicalize(&project).unwrap();
+        let s = |p: PathBuf| p.to_string_lossy().to_string();
+        let mut edges = out.edges.clone();
+        edges.sort();
+        assert_eq!(edges, vec![
+            (s(root.clone()), s(root.join("node_modules/a"))),
+            (s(root.join("node_modules/a")), s(root.join("node_modules/a/node_modules/b"))),
+            (s(root.join("node_modules/a")), s(root.join("node_modules/c"))),
+        ]);
+    }
+
+    #[test]
+    fn test_file_counts_and_plan_inodes() {
+        let temp = tempdir().unwrap();
+        let nm = temp.path().join("app/node_modules");
+        fs::create_dir_all(nm.join("a/lib")).unwrap();
+        fs::create_dir_all(nm.join("a/node_modules/b")).unwrap();
+        fs::write(temp.path().join("app/package.json"), r#"{"dependencies": {"a": "1"}}"#).unwrap();
+        fs::write(nm.join("a/package.json"), r#"{"name": "a"}"#).unwrap();
+        fs::write(nm.join("a/lib/index.js"), "").unwrap();
+        fs::write(nm.join("a/node_modules/b/package.json"), r#"{"name": "b"}"#).unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        let a = out.packages.iter().find(|p| p.name == "a").unwrap();
+        // package.json and lib/index.js; a, lib (its nested node_modules is b's)
+        assert_eq!((a.file_count, a.inode_count), (2, 4));
+
+        let item = |path: &Path| crate::types::PlanItem {
+            target_path: path.to_string_lossy().to_string(),
+            estimated_size_bytes: 0,
+            reason: crate::types::PlanReason::Orphaned,
+            blockers: Vec::new(),
+            risk: None,
+        };
+        let mut report = DryRunReport {
+            items: vec![item(Path::new(&a.path)), item(&fs::canonicalize(&nm).unwrap().join("a/node_modules"))],
+            total_estimated_bytes: 0,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+        // node_modules, b and its package.json counted on disk
+        assert_eq!(count_plan_inodes(&mut report, &out), 7);
+        assert_eq!(report.estimated_inodes, Some(7));
+    }
+
+    #[test]
+    fn test_marked_dirs_reported_not_walked() {
+        let temp = tempdir().unwrap();
+        let tagged = temp.path().join("tool-cache");
+        fs::create_dir_all(tagged.join("tmp/pkg")).unwrap();
+        fs::write(tagged.join("CACHEDIR.TAG"), "Signature: 8a477f597d28d172789f06886806bc55\n").unwrap();
+        fs::write(tagged.join("tmp/pkg/package.json"), r#"{"name": "pkg"}"#).unwrap();
+        let vendor = temp.path().join("svc/vendor");
+        fs::create_dir_all(&vendor).unwrap();
+        fs::write(vendor.join("modules.txt"), "# github.com/pkg/errors v0.9.1\n").unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        assert!(out.projects.is_empty());
+        assert!(out.packages.is_empty());
+        let mut markers: Vec<_> = out.marked_dirs.iter()
+            .map(|d| (Path::new(&d.path).file_name().unwrap().to_string_lossy().to_string(), d.marker))
+            .collect();
+        markers.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(markers, vec![("tool-cache".to_string(), Marker::CachedirTag), ("vendor".to_string(), Marker::GoVendor)]);
+        let cache = out.marked_dirs.iter().find(|d| d.marker == Marker::CachedirTag).unwrap();
+        assert_eq!(cache.file_count, 2);
+    }
+
+    #[test]
+    fn test_scan_each_streams_items() {
+        let temp = tempdir().unwrap();
+        let nm = temp.path().join("app/node_modules");
+        fs::create_dir_all(nm.join("a")).unwrap();
+        fs::create_dir_all(nm.join("b")).unwrap();
+        fs::write(temp.path().join("app/package.json"), r#"{"dependencies": {"a": "1"}}"#).unwrap();
+        fs::write(nm.join("a/package.json"), r#"{"name": "a", "dependencies": {"b": "1"}}"#).unwrap();
+        fs::write(nm.join("b/package.json"), r#"{"name": "b"}"#).unwrap();
+        let roots = [temp.path().to_path_buf()];
+        let ctx = OperationContext::default();
+
+        let mut kinds = Vec::new();
+        let summary = scan_each(&roots, false, CacheValidation::Fast, false, &ctx, |item| {
+            kinds.push(match item {
+                ScanItem::Project(_) => "project",
+                ScanItem::Package(_) => "package",
+                ScanItem::Edge { .. } => "edge",
+                ScanItem::Marked(_) => "marked",
+            });
+            Ok(())
+        }).unwrap();
+        assert_eq!(kinds, ["project", "edge", "package", "package", "edge"]);
+        assert_eq!((summary.projects, summary.packages, summary.edges), (1, 2, 2));
+
+        // A failing visitor stops the scan
+        let mut seen = 0;
+        let result = scan_each(&roots, false, CacheValidation::Fast, false, &ctx, |_| {
+            seen += 1;
+            anyhow::bail!("consumer gone")
+        });
+        assert!(matches!(result, Err(Error::Scan(_))));
+        assert_eq!(seen, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_store_links_found() {
+        let temp = tempdir().unwrap();
+        let store = temp.path().join("store");
+        let entry = store.join("a/1.0.0/abcd");
+        fs::create_dir_all(&entry).unwrap();
+        let nm = temp.path().join("app/node_modules");
+        fs::create_dir_all(nm.join("@scope")).unwrap();
+        std::os::unix::fs::symlink(&entry, nm.join("a")).unwrap();
+        std::os::unix::fs::symlink(&entry, nm.join("@scope/b")).unwrap();
+        std::os::unix::fs::symlink(temp.path(), nm.join("elsewhere")).unwrap();
+
+        let mut links = store_links(std::slice::from_ref(&nm), &[], &store);
+        links.sort();
+        assert_eq!(links, vec![
+            (nm.join("@scope/b"), entry.clone()),
+            (nm.join("a"), entry.clone()),
+        ]);
+    }
+
+    #[test]
+    fn test_scan_cancelled() {
+        let temp = tempdir().unwrap();
+        fs::write(temp.path().join("package.json"), r#"{"name": "root"}"#).unwrap();
+
+        let ctx = OperationContext::default();
+        ctx.cancel.cancel();
+        let result = scan_with_context(&[temp.path().to_path_buf()], false, &ctx);
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+
+    #[test]
+    fn test_scan_terraform_providers() {
+        let temp = tempdir().unwrap();
+        let infra = temp.path().join("infra");
+        let providers = infra.join(".terraform/providers/registry.terraform.io/hashicorp/aws");
+        for version in ["5.31.0", "4.67.0"] {
+            fs::create_dir_all(providers.join(version).join("linux_amd64")).unwrap();
+            fs::write(providers.join(version).join("linux_amd64/terraform-provider-aws"), "bin").unwrap();
+        }
+        fs::write(infra.join(".terraform.lock.hcl"), "provider \"registry.terraform.io/hashicorp/aws\" {\n  version = \"5.31.0\"\n}\n").unwrap();
+        fs::write(infra.join("package.json"), r#"{"dependencies": {"cdktf": "1"}}"#).unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        let mut versions: Vec<(&str, u64)> = out.packages.iter()
+            .filter(|p| matches!(p.manager, Some(PackageManager::Terraform)))
+            .map(|p| (p.version.as_str(), p.size_bytes))
+            .collect();
+        versions.sort();
+        assert_eq!(versions, vec![("4.67.0", 3), ("5.31.0", 3)]);
+
+        assert_eq!(out.projects.len(), 1);
+        let deps = &out.projects[0].dependencies;
+        assert!(deps.contains(&("registry.terraform.io/hashicorp/aws".to_string(), "5.31.0".to_string())));
+        assert!(deps.iter().any(|(n, _)| n == "cdktf"));
+    }
+
+    #[test]
+    fn test_scan_cargo_target_and_registry() {
+        let temp = tempdir().unwrap();
+        let app = temp.path().join("app");
+        fs::create_dir_all(app.join("target/debug/deps")).unwrap();
+        fs::write(app.join("target/debug/deps/libapp.rlib"), "rlib").unwrap();
+        fs::write(app.join("target/CACHEDIR.TAG"), "Signature: 8a477f597d28d172789f06886806bc55\n").unwrap();
+        fs::write(app.join("Cargo.toml"), "[package]\nname = \"app\"\n").unwrap();
+        fs::write(app.join("Cargo.lock"), "[[package]]\nname = \"serde\"\nversion = \"1.0.197\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\"\n").unwrap();
+        let registry = temp.path().join(".cargo/registry");
+        fs::create_dir_all(registry.join("cache")).unwrap();
+        fs::create_dir_all(registry.join("src/index.crates.io-6f17d22bba15001f/serde-1.0.197")).unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        let mut cargo: Vec<(String, &str, u64)> = out.packages.iter()
+            .filter(|p| matches!(p.manager, Some(PackageManager::Cargo)))
+            .map(|p| (p.name.clone(), p.version.as_str(), p.size_bytes))
+            .collect();
+        cargo.sort();
+        assert_eq!(cargo, vec![
+            (app.join("target/debug").to_string_lossy().to_string(), "", 4),
+            ("serde".to_string(), "1.0.197", 0),
+        ]);
+        // The tagged target/ is enumerated by profile, not quarantined whole
+        assert!(out.marked_dirs.is_empty());
+
+        assert_eq!(out.projects.len(), 1);
+        assert!(matches!(out.projects[0].manager, Some(PackageManager::Cargo)));
+        assert_eq!(out.projects[0].dependencies, vec![("serde".to_string(), "1.0.197".to_string())]);
+    }
+
+    #[test]
+    fn test_scan_python_envs_and_caches() {
+        let temp = tempdir().unwrap();
+        let app = temp.path().join("app");
+        fs::create_dir_all(app.join(".venv/lib/python3.11/site-packages/requests")).unwrap();
+        fs::write(app.join(".venv/pyvenv.cfg"), "version = 3.11.4\n").unwrap();
+        fs::create_dir_all(app.join("pkg/__pycache__")).unwrap();
+        fs::write(app.join("pkg/__pycache__/mod.cpython-311.pyc"), "pyc").unwrap();
+        fs::write(app.join("requirements.txt"), "requests==2.31.0\n").unwrap();
+        let wheels = temp.path().join("home/.cache/pip/wheels/3f/a1");
+        fs::create_dir_all(&wheels).unwrap();
+        fs::write(wheels.join("requests-2.31.0-py3-none-any.whl"), "whl").unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        let mut python: Vec<(String, &str)> = out.packages.iter()
+            .filter(|p| matches!(p.manager, Some(PackageManager::Python)))
+            .map(|p| (p.name.clone(), p.version.as_str()))
+            .collect();
+        python.sort();
+        assert_eq!(python, vec![
+            (app.join(".venv").to_string_lossy().to_string(), ""),
+            (app.join("pkg/__pycache__").to_string_lossy().to_string(), ""),
+            ("requests".to_string(), "2.31.0"),
+        ]);
+        assert_eq!(out.projects.len(), 1);
+        assert_eq!(out.projects[0].dependencies, vec![("requests".to_string(), "2.31.0".to_string())]);
+    }
+
+    #[test]
+    fn test_scan_go_module_cache() {
+        let temp = tempdir().unwrap();
+        let cache = temp.path().join("go/pkg/mod");
+        fs::create_dir_all(cache.join("cache/download/github.com/pkg/errors/@v")).unwrap();
+        for module in ["github.com/pkg/errors@v0.9.1", "github.com/pkg/errors@v0.8.0"] {
+            fs::create_dir_all(cache.join(module)).unwrap();
+            fs::write(cache.join(module).join("errors.go"), "package errors").unwrap();
+        }
+        let svc = temp.path().join("svc");
+        fs::create_dir_all(&svc).unwrap();
+        fs::write(svc.join("go.sum"), "github.com/pkg/errors v0.9.1 h1:abc=\ngithub.com/pkg/errors v0.9.1/go.mod h1:def=\n").unwrap();
+
+        let out = scan_with_cache(&[temp.path().to_path_buf()], false).unwrap();
+        let mut go: Vec<(&str, &str, u64)> = out.packages.iter()
+            .filter(|p| matches!(p.manager, Some(PackageManager::Go)))
+            .map(|p| (p.name.as_str(), p.version.as_str(), p.size_bytes))
+            .collect();
+        go.sort();
+        assert_eq!(go, vec![("github.com/pkg/errors", "v0.8.0", 14), ("github.com/pkg/errors", "v0.9.1", 14)]);
+        assert_eq!(out.projects.len(), 1);
+        assert!(matches!(out.projects[0].manager, Some(PackageManager::Go)));
+
+        assert_eq!(out.projects[0].dependencies, vec![("github.com/pkg/errors".to_string(), "v0.9.1".to_string())]);
     }
 }
@@ -0,0 +1,487 @@
+//! Grace-Period Soft Disable
+//!
+//! Quarantine is reversible, but a rollback still moves whole trees back.
+//! `apply --soft-disable` goes one step softer: each target stays where it is
+//! and is only disabled for a grace period (`--grace-hours`, 48 by default),
+//! either renamed with a `.packagepurge-disabled` suffix or, with
+//! `--soft-mode permissions`, made unreadable. Anything that still needs it
+//! breaks right away, and restoring it is a rename or a chmod.
+//!
+//! Targets are disabled in batches, one per apply. A batch collects
+//! complaints while it waits:
+//!
+//! - recreated: the original path exists again, so an install or build put it back
+//! - permissions_changed: someone gave a disabled target its access back
+//! - restored: `soft-disabled restore` re-enabled an item
+//!
+//! `soft-disabled commit` quarantines the items of every batch whose grace
+//! period passed without a complaint; batches with complaints wait for their
+//! items to be restored. An item whose original path was recreated is
+//! restored with `--force`, which quarantines the recreated copy first.
+//! Items that fail to commit stay disabled in their batch for the next
+//! commit. Batches live in the state directory, one file each.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::safety::{ensure_writable, is_protected_path};
+
+/// Suffix disabled targets are renamed with
+pub const DISABLED_SUFFIX: &str = ".packagepurge-disabled";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SoftMode {
+    /// Rename the target with `DISABLED_SUFFIX`
+    #[default]
+    Rename,
+    /// Take all access away from the target directory (Unix only)
+    Permissions,
+}
+
+impl FromStr for SoftMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rename" => Ok(Self::Rename),
+            "permissions" | "chmod" => Ok(Self::Permissions),
+            other => Err(format!("unknown soft-disable mode `{}` (expected rename or permissions)", other)),
+        }
+    }
+}
+
+impl fmt::Display for SoftMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rename => "rename",
+            Self::Permissions => "permissions",
+        })
+    }
+}
+
+/// A disabled target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoftItem {
+    pub original_path: String,
+    /// Where the target is while disabled (the original path in permissions mode)
+    pub disabled_path: String,
+    /// Permission bits to give back (permissions mode)
+    #[serde(default)]
+    pub mode_bits: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplaintKind {
+    Recreated,
+    PermissionsChanged,
+    Restored,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Complaint {
+    pub path: String,
+    pub kind: ComplaintKind,
+    pub at: DateTime<Utc>,
+}
+
+/// Targets disabled together, awaiting the end of their grace period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoftBatch {
+    pub id: String,
+    pub mode: SoftMode,
+    pub created_at: DateTime<Utc>,
+    pub grace_until: DateTime<Utc>,
+    pub items: Vec<SoftItem>,
+    #[serde(default)]
+    pub complaints: Vec<Complaint>,
+}
+
+/// Where a batch stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchState {
+    /// Grace period still running, no complaints
+    Grace,
+    /// A complaint blocks the batch from being quarantined
+    Complained,
+    /// Grace period over without complaints: `commit` quarantines it
+    Ready,
+}
+
+impl SoftBatch {
+    pub fn state(&self, now: DateTime<Utc>) -> BatchState {
+        if !self.complaints.is_empty() {
+            BatchState::Complained
+        } else if now < self.grace_until {
+            BatchState::Grace
+        } else {
+            BatchState::Ready
+        }
+    }
+
+    /// Record complaints for items recreated or given their access back
+    /// since the last look; returns whether any were new
+    pub fn observe(&mut self, now: DateTime<Utc>) -> bool {
+        let mut found = Vec::new();
+        for item in &self.items {
+            let kind = match self.mode {
+                SoftMode::Rename => Path::new(&item.original_path).exists().then_some(ComplaintKind::Recreated),
+                SoftMode::Permissions => (current_mode(Path::new(&item.disabled_path)).is_some_and(|m| m & 0o777 != 0))
+                    .then_some(ComplaintKind::PermissionsChanged),
+            };
+            let Some(kind) = kind else { continue };
+            if !self.complaints.iter().any(|c| c.path == item.original_path && c.kind == kind) {
+                found.push(Complaint { path: item.original_path.clone(), kind, at: now });
+            }
+        }
+        let new = !found.is_empty();
+        self.complaints.extend(found);
+        new
+    }
+}
+
+/// A batch with its state, as `soft-disabled` lists them
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchStatus {
+    #[serde(flatten)]
+    pub batch: SoftBatch,
+    pub state: BatchState,
+}
+
+fn batch_dir() -> PathBuf {
+    crate::paths::state_dir().join("soft-disabled")
+}
+
+#[cfg(unix)]
+fn current_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::symlink_metadata(path).ok().map(|m| m.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn current_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to change permissions of {:?}", path))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    anyhow::bail!("Permissions mode is only supported on Unix; use rename")
+}
+
+fn disable_one(target: &Path, mode: SoftMode) -> Result<SoftItem> {
+    anyhow::ensure!(!is_protected_path(target), "{:?} is inside PackagePurge's own store or quarantine directory", target);
+    let original_path = target.to_string_lossy().to_string();
+    match mode {
+        SoftMode::Rename => {
+            let disabled = PathBuf::from(format!("{}{}", original_path, DISABLED_SUFFIX));
+            anyhow::ensure!(!disabled.exists(), "{:?} already exists", disabled);
+            fs::rename(target, &disabled).with_context(|| format!("Failed to disable {:?}", target))?;
+            Ok(SoftItem { original_path, disabled_path: disabled.to_string_lossy().to_string(), mode_bits: None })
+        }
+        SoftMode::Permissions => {
+            let bits = current_mode(target).with_context(|| format!("Failed to read permissions of {:?}", target))?;
+            set_mode(target, bits & !0o777)?;
+            Ok(SoftItem { original_path: original_path.clone(), disabled_path: original_path, mode_bits: Some(bits) })
+        }
+    }
+}
+
+/// Moves a recreated original path out of the way of a restore
+type SetAside<'a> = &'a dyn Fn(&Path) -> Result<()>;
+
+/// Put a disabled item back as it was
+pub fn reenable(item: &SoftItem) -> crate::Result<()> {
+    reenable_impl(item, None).map_err(Error::lift(Error::Quarantine))
+}
+
+fn reenable_impl(item: &SoftItem, set_aside: Option<SetAside>) -> Result<()> {
+    match item.mode_bits {
+        Some(bits) => set_mode(Path::new(&item.disabled_path), bits),
+        None => {
+            let original = Path::new(&item.original_path);
+            if original.exists() {
+                let Some(set_aside) = set_aside else {
+                    anyhow::bail!("{:?} was recreated; restore with --force to quarantine it first", original);
+                };
+                set_aside(original)?;
+            }
+            fs::rename(&item.disabled_path, original)
+                .with_context(|| format!("Failed to restore {:?}", original))
+        }
+    }
+}
+
+/// Disable `targets` for `grace_hours`, recording them as one batch. Targets
+/// that cannot be disabled are returned with their error instead.
+pub fn disable(targets: &[PathBuf], mode: SoftMode, grace_hours: i64) -> crate::Result<(SoftBatch, Vec<(PathBuf, String)>)> {
+    ensure_writable("soft-disable packages")?;
+    disable_in(&batch_dir(), targets, mode, grace_hours, Utc::now()).map_err(Error::lift(Error::Quarantine))
+}
+
+fn disable_in(
+    dir: &Path,
+    targets: &[PathBuf],
+    mode: SoftMode,
+    grace_hours: i64,
+    now: DateTime<Utc>,
+) -> Result<(SoftBatch, Vec<(PathBuf, String)>)> {
+    let mut items = Vec::new();
+    let mut failed = Vec::new();
+    for target in targets {
+        match disable_one(target, mode) {
+            Ok(item) => items.push(item),
+            Err(e) => failed.push((target.clone(), format!("{:#}", e))),
+        }
+    }
+    let batch = SoftBatch {
+        id: now.timestamp_nanos_opt().unwrap_or_default().to_string(),
+        mode,
+        created_at: now,
+        grace_until: now + Duration::hours(grace_hours),
+        items,
+        complaints: Vec::new(),
+    };
+    if !batch.items.is_empty() {
+        save_in(dir, &batch)?;
+    }
+    Ok((batch, failed))
+}
+
+fn save_in(dir: &Path, batch: &SoftBatch) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", batch.id));
+    fs::write(&path, serde_json::to_string_pretty(batch)?)
+        .with_context(|| format!("Failed to write soft-disable batch {:?}", path))
+}
+
+fn load_in(dir: &Path) -> Vec<SoftBatch> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut batches: Vec<SoftBatch> = entries.filter_map(|e| e.ok())
+        .filter_map(|e| serde_json::from_str(&fs::read_to_string(e.path()).ok()?).ok())
+        .collect();
+    batches.sort_by_key(|b| b.created_at);
+    batches
+}
+
+/// Every batch with its state, oldest first, after recording new complaints
+pub fn list() -> crate::Result<Vec<BatchStatus>> {
+    list_in(&batch_dir(), Utc::now()).map_err(Error::lift(Error::Quarantine))
+}
+
+fn list_in(dir: &Path, now: DateTime<Utc>) -> Result<Vec<BatchStatus>> {
+    let mut out = Vec::new();
+    for mut batch in load_in(dir) {
+        if batch.observe(now) {
+            save_in(dir, &batch)?;
+        }
+        out.push(BatchStatus { state: batch.state(now), batch });
+    }
+    Ok(out)
+}
+
+/// Re-enable the items of batch `target`, or the item disabled from path
+/// `target`, recording a complaint against their batch; returns the items
+/// restored. With `force`, an original path that was recreated is
+/// quarantined to make way.
+pub fn restore(target: &str, force: bool) -> crate::Result<Vec<SoftItem>> {
+    ensure_writable("restore soft-disabled packages")?;
+    let quarantine = |path: &Path| -> Result<()> {
+        crate::safety::move_to_quarantine(path)?;
+        Ok(())
+    };
+    restore_in(&batch_dir(), target, force.then_some(&quarantine as SetAside), Utc::now())
+        .map_err(Error::lift(Error::Quarantine))
+}
+
+fn restore_in(dir: &Path, target: &str, set_aside: Option<SetAside>, now: DateTime<Utc>) -> Result<Vec<SoftItem>> {
+    let mut restored = Vec::new();
+    for mut batch in load_in(dir) {
+        let whole = batch.id == target;
+        let (matching, kept): (Vec<SoftItem>, Vec<SoftItem>) = batch.items.drain(..)
+            .partition(|i| whole || i.original_path == target || i.disabled_path == target);
+        batch.items = kept;
+        for item in matching {
+            match reenable_impl(&item, set_aside) {
+                Ok(()) => {
+                    batch.complaints.push(Complaint { path: item.original_path.clone(), kind: ComplaintKind::Restored, at: now });
+                    restored.push(item);
+                }
+                Err(e) => {
+                    batch.items.push(item);
+                    save_in(dir, &batch)?;
+                    return Err(e);
+                }
+            }
+        }
+        if batch.items.is_empty() {
+            let _ = fs::remove_file(dir.join(format!("{}.json", batch.id)));
+        } else {
+            save_in(dir, &batch)?;
+        }
+    }
+    anyhow::ensure!(!restored.is_empty(), "No soft-disabled batch or item matches {:?}", target);
+    Ok(restored)
+}
+
+/// Batches ready to be quarantined, after recording new complaints
+pub fn ready() -> crate::Result<Vec<SoftBatch>> {
+    Ok(list()?.into_iter().filter(|s| s.state == BatchState::Ready).map(|s| s.batch).collect())
+}
+
+/// Record how committing `batch` went: items whose original path is in
+/// `committed` were quarantined and leave the batch, the rest stay disabled
+/// in it (disabled again if they were re-enabled for the commit). The batch
+/// is forgotten once no items are left; returns the items it still holds.
+pub fn settle(batch: &SoftBatch, committed: &[String]) -> crate::Result<Vec<SoftItem>> {
+    settle_in(&batch_dir(), batch, committed).map_err(Error::lift(Error::Quarantine))
+}
+
+fn settle_in(dir: &Path, batch: &SoftBatch, committed: &[String]) -> Result<Vec<SoftItem>> {
+    let mut left = Vec::new();
+    for item in batch.items.iter().filter(|i| !committed.contains(&i.original_path)) {
+        let reenabled = match batch.mode {
+            SoftMode::Rename => !Path::new(&item.disabled_path).exists() && Path::new(&item.original_path).exists(),
+            SoftMode::Permissions => current_mode(Path::new(&item.disabled_path)).is_some_and(|m| m & 0o777 != 0),
+        };
+        if !reenabled {
+            left.push(item.clone());
+            continue;
+        }
+        match disable_one(Path::new(&item.original_path), batch.mode) {
+            Ok(again) => left.push(again),
+            Err(e) => eprintln!("Warning: Failed to disable {:?} again, leaving it enabled: {:#}", item.original_path, e),
+        }
+    }
+    if left.is_empty() {
+        let _ = fs::remove_file(dir.join(format!("{}.json", batch.id)));
+    } else {
+        save_in(dir, &SoftBatch { items: left.clone(), ..batch.clone() })?;
+    }
+    Ok(left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rename_batch_waits_for_quiet_grace_period() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path().join("batches");
+        let (a, b) = (temp.path().join("app/node_modules/a"), temp.path().join("app/node_modules/b"));
+        for t in [&a, &b] {
+            fs::create_dir_all(t).unwrap();
+            fs::write(t.join("index.js"), "module.exports = 1").unwrap();
+        }
+        let now = Utc::now();
+
+        let (batch, failed) = disable_in(&dir, &[a.clone(), b.clone(), temp.path().join("missing")], SoftMode::Rename, 48, now).unwrap();
+        assert_eq!((batch.items.len(), failed.len()), (2, 1));
+        assert!(!a.exists() && Path::new(&format!("{}{}", a.display(), DISABLED_SUFFIX)).exists());
+
+        let statuses = list_in(&dir, now).unwrap();
+        assert_eq!(statuses[0].state, BatchState::Grace);
+        assert_eq!(list_in(&dir, now + Duration::hours(49)).unwrap()[0].state, BatchState::Ready);
+
+        // An install put b back: the batch is held until it is sorted out
+        fs::create_dir_all(&b).unwrap();
+        let statuses = list_in(&dir, now + Duration::hours(49)).unwrap();
+        assert_eq!(statuses[0].state, BatchState::Complained);
+        assert_eq!(statuses[0].batch.complaints[0].kind, ComplaintKind::Recreated);
+        assert!(restore_in(&dir, &b.to_string_lossy(), None, now).is_err());
+
+        let restored = restore_in(&dir, &a.to_string_lossy(), None, now).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(fs::read_to_string(a.join("index.js")).unwrap(), "module.exports = 1");
+        assert_eq!(load_in(&dir)[0].complaints.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_mode() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path().join("batches");
+        let target = temp.path().join("node_modules/a");
+        fs::create_dir_all(&target).unwrap();
+        let before = current_mode(&target).unwrap();
+
+        let (batch, _) = disable_in(&dir, std::slice::from_ref(&target), SoftMode::Permissions, 1, Utc::now()).unwrap();
+        assert_eq!(current_mode(&target).unwrap() & 0o777, 0);
+        assert_eq!(list_in(&dir, Utc::now()).unwrap()[0].state, BatchState::Grace);
+
+        set_mode(&target, 0o700).unwrap();
+        let statuses = list_in(&dir, Utc::now()).unwrap();
+        assert_eq!(statuses[0].batch.complaints[0].kind, ComplaintKind::PermissionsChanged);
+
+        restore_in(&dir, &batch.id, None, Utc::now()).unwrap();
+        assert_eq!(current_mode(&target).unwrap(), before);
+        assert!(load_in(&dir).is_empty());
+    }
+
+    #[test]
+    fn test_force_restore_drains_complained_batch() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path().join("batches");
+        let aside = temp.path().join("aside");
+        let target = temp.path().join("node_modules/a");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("index.js"), "old").unwrap();
+        let now = Utc::now();
+        disable_in(&dir, std::slice::from_ref(&target), SoftMode::Rename, 1, now).unwrap();
+
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("index.js"), "new").unwrap();
+        assert_eq!(list_in(&dir, now).unwrap()[0].state, BatchState::Complained);
+
+        let move_aside = |p: &Path| -> Result<()> { Ok(fs::rename(p, &aside)?) };
+        restore_in(&dir, &target.to_string_lossy(), Some(&move_aside), now).unwrap();
+        assert_eq!(fs::read_to_string(target.join("index.js")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(aside.join("index.js")).unwrap(), "new");
+        assert!(load_in(&dir).is_empty());
+    }
+
+    #[test]
+    fn test_settle_keeps_uncommitted_items_disabled() {
+        let temp = tempdir().unwrap();
+        let dir = temp.path().join("batches");
+        let (a, b, c) = (temp.path().join("a"), temp.path().join("b"), temp.path().join("c"));
+        for t in [&a, &b, &c] {
+            fs::create_dir_all(t).unwrap();
+        }
+        let (batch, _) = disable_in(&dir, &[a.clone(), b.clone(), c.clone()], SoftMode::Rename, 0, Utc::now()).unwrap();
+
+        // Commit re-enabled a and b, quarantined a, failed on b; c never came back
+        reenable_impl(&batch.items[0], None).unwrap();
+        reenable_impl(&batch.items[1], None).unwrap();
+        fs::remove_dir(&a).unwrap();
+        let left = settle_in(&dir, &batch, &[a.to_string_lossy().to_string()]).unwrap();
+
+        assert_eq!(left.len(), 2);
+        assert!(!b.exists() && Path::new(&left[0].disabled_path).exists());
+        assert!(!c.exists() && Path::new(&left[1].disabled_path).exists());
+        assert_eq!(load_in(&dir)[0].items.len(), 2);
+
+        for item in &left {
+            fs::remove_dir(&item.disabled_path).unwrap();
+        }
+        let committed: Vec<String> = left.iter().map(|i| i.original_path.clone()).collect();
+        assert!(settle_in(&dir, &load_in(&dir)[0], &committed).unwrap().is_empty());
+        assert!(load_in(&dir).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! Store Reference Index - SQLite-backed reverse index of store symlinks
+//!
+//! Maps every global-store entry to the project symlinks that point at it.
+//! Symlinking records links as it creates them and scans reconcile the links
+//! found under their roots, so store gc, link verification and relocation can
+//! work from the recorded references instead of re-walking the machine.
+
+use anyhow::Context;
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{db_err, Error, Result};
+
+/// A recorded symlink and the store entry it points at
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StoreReference {
+    pub entry: String,
+    pub link: String,
+}
+
+/// SQLite-backed store reference index
+pub struct StoreIndex {
+    conn: Connection,
+}
+
+impl StoreIndex {
+    /// Default path for the reference index database
+    pub fn default_db_path() -> PathBuf {
+        crate::paths::state_dir().join("store_index.db")
+    }
+
+    /// Open or create an index at the given path
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if let Some(parent) = db_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))
+                .map_err(Error::Db)?;
+        }
+
+        let conn = Connection::open(db_path)
+            .with_context(|| format!("Failed to open database at {:?}", db_path))
+            .map_err(Error::Db)?;
+        conn.busy_timeout(crate::scan_lease::DB_BUSY_TIMEOUT)?;
+
+        conn.execute_batch(r#"
+            CREATE TABLE IF NOT EXISTS store_refs (
+                link TEXT PRIMARY KEY,
+                entry TEXT NOT NULL,
+                recorded_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE INDEX IF NOT EXISTS idx_store_refs_entry ON store_refs(entry);
+        "#).map_err(db_err("Failed to initialize store index schema"))?;
+
+        Ok(Self { conn })
+    }
+
+    /// Open the default index
+    pub fn open_default() -> Result<Self> {
+        Self::open(&Self::default_db_path())
+    }
+
+    /// Record that `link` points at store `entry` (replacing any previous target)
+    pub fn record_link(&self, entry: &Path, link: &Path) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO store_refs (link, entry) VALUES (?1, ?2)",
+            params![link.to_string_lossy(), entry.to_string_lossy()],
+        ).map_err(db_err("Failed to record store reference"))?;
+        Ok(())
+    }
+
+    /// Forget a link (e.g. after the package was restored to a real directory)
+    pub fn remove_link(&self, link: &Path) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM store_refs WHERE link = ?1",
+            params![link.to_string_lossy()],
+        ).map_err(db_err("Failed to remove store reference"))?;
+        Ok(())
+    }
+
+    /// Links referencing a store entry
+    pub fn links_for(&self, entry: &Path) -> Result<Vec<PathBuf>> {
+        let mut stmt = self.conn.prepare("SELECT link FROM store_refs WHERE entry = ?1 ORDER BY link")?;
+        let rows = stmt.query_map(params![entry.to_string_lossy()], |row| row.get::<_, String>(0))?;
+        Ok(rows.filter_map(|r| r.ok()).map(PathBuf::from).collect())
+    }
+
+    /// Every recorded reference
+    pub fn all(&self) -> Result<Vec<StoreReference>> {
+        let mut stmt = self.conn.prepare("SELECT entry, link FROM store_refs ORDER BY entry, link")?;
+        let rows = stmt.query_map([], |row| Ok(StoreReference { entry: row.get(0)?, link: row.get(1)? }))?;
+        Ok(rows.filter_map(|r| r.ok()).collect())
+    }
+
+    /// Replace the references recorded below `root` with the links a scan of
+    /// that root just found
+    pub fn sync_root(&mut self, root: &Path, found: &[(PathBuf, PathBuf)]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let prefix = root.to_string_lossy().to_string();
+            let mut stale = tx.prepare("SELECT link FROM store_refs WHERE link LIKE ?1 ESCAPE '\\'")?;
+            let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+            let under_root: Vec<String> = stale.query_map(params![pattern], |row| row.get(0))?
+                .filter_map(|r| r.ok())
+                .filter(|l: &String| Path::new(l).starts_with(root))
+                .collect();
+            for link in under_root {
+                tx.execute("DELETE FROM store_refs WHERE link = ?1", params![link])?;
+            }
+            for (link, entry) in found {
+                tx.execute(
+                    "INSERT OR REPLACE INTO store_refs (link, entry) VALUES (?1, ?2)",
+                    params![link.to_string_lossy(), entry.to_string_lossy()],
+                )?;
+            }
+        }
+        tx.commit().map_err(db_err("Failed to sync store references"))?;
+        Ok(())
+    }
+
+    /// Drop references whose link no longer points at the recorded entry.
+    /// Returns the number of references removed.
+    pub fn prune_dangling(&self) -> Result<usize> {
+        let mut removed = 0;
+        for r in self.all()? {
+            let live = fs::read_link(&r.link).map(|t| t == Path::new(&r.entry)).unwrap_or(false);
+            if !live {
+                self.remove_link(Path::new(&r.link))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// References whose link is missing, points elsewhere, or whose store
+    /// entry no longer exists
+    pub fn broken(&self) -> Result<Vec<StoreReference>> {
+        Ok(self.all()?.into_iter().filter(|r| {
+            let points_at_entry = fs::read_link(&r.link).map(|t| t == Path::new(&r.entry)).unwrap_or(false);
+            !(points_at_entry && Path::new(&r.entry).is_dir())
+        }).collect())
+    }
+
+    /// Store entries (`<store>/<name>/<version>/<hash>`) without any recorded
+    /// reference, after dropping dangling references. Deletes them unless
+    /// `dry_run`. Returns the entries with their sizes.
+    pub fn gc_store(&self, store: &Path, dry_run: bool) -> Result<Vec<(PathBuf, u64)>> {
+        if !dry_run {
+            crate::safety::ensure_writable("delete store entries")?;
+        }
+        self.prune_dangling()?;
+        let referenced: std::collections::HashSet<PathBuf> = self.all()?.into_iter()
+            .map(|r| PathBuf::from(r.entry))
+            .collect();
+
+        let mut unreferenced = Vec::new();
+        let entries = walkdir::WalkDir::new(store).min_depth(3).max_depth(3)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_dir());
+        for entry in entries {
+            if referenced.contains(entry.path()) {
+                continue;
+            }
+            let size: u64 = walkdir::WalkDir::new(entry.path()).into_iter()
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum();
+            if !dry_run {
+                fs::remove_dir_all(entry.path())
+                    .with_context(|| format!("Failed to remove store entry {:?}", entry.path()))
+                    .map_err(Error::Store)?;
+            }
+            unreferenced.push((entry.into_path(), size));
+        }
+        Ok(unreferenced)
+    }
+
+    /// Rewrite entry paths after the store moved from `old` to `new`
+    pub fn rewrite_store_prefix(&self, old: &Path, new: &Path) -> Result<usize> {
+        let mut rewritten = 0;
+        for r in self.all()? {
+            if let Ok(rel) = Path::new(&r.entry).strip_prefix(old) {
+                self.record_link(&new.join(rel), Path::new(&r.link))?;
+                rewritten += 1;
+            }
+        }
+        Ok(rewritten)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_record_and_query() {
+        let temp = tempdir().unwrap();
+        let index = StoreIndex::open(&temp.path().join("idx.db")).unwrap();
+        let entry = Path::new("/store/a/1.0.0/abcd");
+        index.record_link(entry, Path::new("/p1/node_modules/a")).unwrap();
+        index.record_link(entry, Path::new("/p2/node_modules/a")).unwrap();
+
+        assert_eq!(index.links_for(entry).unwrap().len(), 2);
+        index.remove_link(Path::new("/p1/node_modules/a")).unwrap();
+        assert_eq!(index.links_for(entry).unwrap(), vec![PathBuf::from("/p2/node_modules/a")]);
+
+        index.rewrite_store_prefix(Path::new("/store"), Path::new("/disk2/store")).unwrap();
+        assert_eq!(index.all().unwrap()[0].entry, "/disk2/store/a/1.0.0/abcd");
+    }
+
+    #[test]
+    fn test_sync_root_replaces_only_that_root() {
+        let temp = tempdir().unwrap();
+        let mut index = StoreIndex::open(&temp.path().join("idx.db")).unwrap();
+        let entry = Path::new("/store/a/1.0.0/abcd");
+        index.record_link(entry, Path::new("/work/old/node_modules/a")).unwrap();
+        index.record_link(entry, Path::new("/work_other/node_modules/a")).unwrap();
+
+        let found = vec![(PathBuf::from("/work/new/node_modules/a"), entry.to_path_buf())];
+        index.sync_root(Path::new("/work"), &found).unwrap();
+
+        let links: Vec<String> = index.all().unwrap().into_iter().map(|r| r.link).collect();
+        assert_eq!(links, vec!["/work/new/node_modules/a", "/work_other/node_modules/a"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_gc_store_keeps_referenced_entries() {
+        let temp = tempdir().unwrap();
+        let index = StoreIndex::open(&temp.path().join("idx.db")).unwrap();
+        let store = temp.path().join("store");
+        let used = store.join("a/1.0.0/aaaa");
+        let unused = store.join("b/2.0.0/bbbb");
+        for dir in [&used, &unused] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("index.js"), "x").unwrap();
+        }
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&used, &link).unwrap();
+        index.record_link(&used, &link).unwrap();
+
+        let dry = index.gc_store(&store, true).unwrap();
+        assert_eq!(dry, vec![(unused.clone(), 1)]);
+        assert!(unused.exists());
+
+        index.gc_store(&store, false).unwrap();
+        assert!(!unused.exists());
+        assert!(used.exists());
+        assert!(index.broken().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_prune_dangling() {
+        let temp = tempdir().unwrap();
+        let index = StoreIndex::open(&temp.path().join("idx.db")).unwrap();
+        let entry = temp.path().join("store/a");
+        fs::create_dir_all(&entry).unwrap();
+        let live = temp.path().join("live");
+        std::os::unix::fs::symlink(&entry, &live).unwrap();
+        index.record_link(&entry, &live).unwrap();
+        index.record_link(&entry, &temp.path().join("gone")).unwrap();
+
+        assert_eq!(index.prune_dangling().unwrap(), 1);
+        assert_eq!(index.links_for(&entry).unwrap(), vec![live]);
+    }
+}
@@ -1,7 +1,13 @@
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::error::Error;
+use crate::safety::ensure_writable;
+use crate::types::DedupBlocker;
+
 #[cfg(windows)]
 use std::os::windows::fs as win_fs;
 
@@ -9,14 +15,47 @@ use std::os::windows::fs as win_fs;
 use std::os::unix::fs as unix_fs;
 
 /// Global store path (platform-specific)
-pub fn get_global_store_path() -> Result<PathBuf> {
-    let home = dirs::home_dir().context("Could not determine home directory")?;
-    Ok(home.join(".packagepurge").join("global_store"))
+pub fn get_global_store_path() -> crate::Result<PathBuf> {
+    get_global_store_path_impl().map_err(Error::lift(Error::Store))
+}
+
+fn get_global_store_path_impl() -> Result<PathBuf> {
+    if let Some(path) = crate::paths::store_dir_override().or_else(read_store_location) {
+        return Ok(path);
+    }
+    Ok(crate::paths::state_dir().join("global_store"))
+}
+
+/// File recording a relocated store (absent while the store is in its default place)
+fn store_location_file() -> PathBuf {
+    crate::paths::state_dir().join("store.json")
+}
+
+fn read_store_location() -> Option<PathBuf> {
+    let text = fs::read_to_string(store_location_file()).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&text).ok()?;
+    json.get("path").and_then(|v| v.as_str()).map(PathBuf::from)
+}
+
+/// Persist the global store location used by all later runs
+pub(crate) fn write_store_location(path: &Path) -> Result<()> {
+    ensure_writable("relocate the global store")?;
+    let file = store_location_file();
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::json!({ "path": path.to_string_lossy() });
+    fs::write(&file, serde_json::to_vec_pretty(&json)?)
+        .with_context(|| format!("Failed to write store location {:?}", file))
 }
 
 /// Initialize global store directory
-pub fn ensure_global_store() -> Result<PathBuf> {
-    let store_path = get_global_store_path()?;
+pub fn ensure_global_store() -> crate::Result<PathBuf> {
+    ensure_global_store_impl().map_err(Error::lift(Error::Store))
+}
+
+fn ensure_global_store_impl() -> Result<PathBuf> {
+    let store_path = get_global_store_path_impl()?;
     fs::create_dir_all(&store_path)
         .with_context(|| format!("Failed to create global store at {:?}", store_path))?;
     Ok(store_path)
@@ -24,7 +63,7 @@ pub fn ensure_global_store() -> Result<PathBuf> {
 
 /// Generate content-addressable path for a package
 /// Format: global_store/{name}/{version}/{hash}
-pub fn get_canonical_path(store_path: &Path, name: &str, version: &str) -> Result<PathBuf> {
+pub fn get_canonical_path(store_path: &Path, name: &str, version: &str) -> crate::Result<PathBuf> {
     // Use a simple hash of name@version for content addressing
     use sha2::{Digest, Sha256};
     let mut hasher = Sha256::new();
@@ -67,7 +106,12 @@ pub fn is_symlink(path: &Path) -> bool {
 }
 
 /// Create hard links for all files in source directory to target directory
-pub fn hard_link_directory(src: &Path, dst: &Path) -> Result<()> {
+pub fn hard_link_directory(src: &Path, dst: &Path) -> crate::Result<()> {
+    hard_link_directory_impl(src, dst).map_err(Error::lift(Error::Store))
+}
+
+fn hard_link_directory_impl(src: &Path, dst: &Path) -> Result<()> {
+    ensure_writable("write to the global store")?;
     if dst.exists() {
         fs::remove_dir_all(dst)
             .with_context(|| format!("Failed to remove existing directory {:?}", dst))?;
@@ -119,7 +163,12 @@ fn copy_directory_with_hard_links(src: &Path, dst: &Path) -> Result<()> {
 }
 
 /// Create a symlink (or junction on Windows) from target to source
-pub fn create_symlink(target: &Path, source: &Path) -> Result<()> {
+pub fn create_symlink(target: &Path, source: &Path) -> crate::Result<()> {
+    create_symlink_impl(target, source).map_err(Error::lift(Error::Store))
+}
+
+pub(crate) fn create_symlink_impl(target: &Path, source: &Path) -> Result<()> {
+    ensure_writable("create symlinks")?;
     // Remove existing target if it exists
     if target.exists() {
         if target.is_dir() {
@@ -159,6 +208,171 @@ pub fn create_symlink(target: &Path, source: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Detect compiled native addons (node-gyp output or prebuilt binaries)
+pub fn has_native_build(package_path: &Path) -> bool {
+    if package_path.join("binding.gyp").exists() {
+        return true;
+    }
+    for sub in ["build", "prebuilds"] {
+        let dir = package_path.join(sub);
+        if !dir.is_dir() {
+            continue;
+        }
+        let found = walkdir::WalkDir::new(&dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .any(|e| e.path().extension().map(|x| x == "node").unwrap_or(false));
+        if found {
+            return true;
+        }
+    }
+    false
+}
+
+/// Check whether two paths reside on different devices.
+/// The store may not exist yet, so its closest existing ancestor is used.
+pub fn is_cross_device(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let existing = |p: &Path| p.ancestors().find_map(|anc| fs::metadata(anc).ok());
+        match (existing(a), existing(b)) {
+            (Some(ma), Some(mb)) => ma.dev() != mb.dev(),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        // Volume comparison by path prefix (drive letter)
+        let root = |p: &Path| p.components().next().map(|c| c.as_os_str().to_os_string());
+        root(a) != root(b)
+    }
+}
+
+/// Snapshot of paths currently held open by running processes.
+/// Only available on Linux (via /proc); empty elsewhere.
+pub fn open_file_snapshot() -> HashSet<PathBuf> {
+    let mut open = HashSet::new();
+
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(procs) = fs::read_dir("/proc") {
+            for proc_entry in procs.filter_map(|e| e.ok()) {
+                let pid_dir = proc_entry.path();
+                if let Ok(cwd) = fs::read_link(pid_dir.join("cwd")) {
+                    open.insert(cwd);
+                }
+                if let Ok(fds) = fs::read_dir(pid_dir.join("fd")) {
+                    for fd in fds.filter_map(|e| e.ok()) {
+                        if let Ok(target) = fs::read_link(fd.path()) {
+                            open.insert(target);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    open
+}
+
+/// Check whether any snapshotted open path lies inside the package
+pub fn is_in_use(package_path: &Path, open_files: &HashSet<PathBuf>) -> bool {
+    open_files.iter().any(|p| p.starts_with(package_path))
+}
+
+/// Collect all blockers that apply to deduplicating a package into the store
+pub fn detect_blockers(package_path: &Path, store_path: &Path, open_files: &HashSet<PathBuf>) -> Vec<DedupBlocker> {
+    let mut blockers = Vec::new();
+    if has_native_build(package_path) {
+        blockers.push(DedupBlocker::NativeBuild);
+    }
+    if is_cross_device(package_path, store_path) {
+        blockers.push(DedupBlocker::CrossDevice);
+    }
+    if crate::wsl::crosses_boundary(package_path) || crate::wsl::crosses_boundary(store_path) {
+        blockers.push(DedupBlocker::WslBoundary);
+    }
+    if is_in_use(package_path, open_files) {
+        blockers.push(DedupBlocker::InUse);
+    }
+    blockers
+}
+
+/// How a duplicate package is tied to its store entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupMode {
+    /// Replace the package directory with a symlink to the store
+    #[default]
+    Symlink,
+    /// Keep a real directory whose files are hard links into the store
+    /// (pnpm-style), for toolchains that resolve symlinks
+    Hardlink,
+}
+
+impl std::str::FromStr for DedupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "symlink" => Ok(Self::Symlink),
+            "hardlink" => Ok(Self::Hardlink),
+            other => Err(format!("unknown dedup mode `{}` (expected symlink or hardlink)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for DedupMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Symlink => "symlink",
+            Self::Hardlink => "hardlink",
+        })
+    }
+}
+
+/// Per-project override read from `"packagepurge": { "dedupMode": "hardlink" }`
+/// in the project's package.json
+pub fn project_dedup_mode(project_dir: &Path) -> Option<DedupMode> {
+    let text = fs::read_to_string(project_dir.join("package.json")).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&text).ok()?;
+    json.get("packagepurge")?.get("dedupMode")?.as_str()?.parse().ok()
+}
+
+/// Whether two paths are the same file (inode, or file index on Windows)
+fn same_file(a: &Path, b: &Path) -> bool {
+    same_file::is_same_file(a, b).unwrap_or(false)
+}
+
+/// Whether two files hold the same bytes, read in chunks
+fn same_content(a: &Path, b: &Path) -> std::io::Result<bool> {
+    use std::io::{BufRead, BufReader};
+    let (mut a, mut b) = (BufReader::new(fs::File::open(a)?), BufReader::new(fs::File::open(b)?));
+    loop {
+        let (chunk_a, chunk_b) = (a.fill_buf()?, b.fill_buf()?);
+        if chunk_a.is_empty() || chunk_b.is_empty() {
+            return Ok(chunk_a.is_empty() && chunk_b.is_empty());
+        }
+        let n = chunk_a.len().min(chunk_b.len());
+        if chunk_a[..n] != chunk_b[..n] {
+            return Ok(false);
+        }
+        a.consume(n);
+        b.consume(n);
+    }
+}
+
+/// Files of a package, excluding nested `node_modules` (separate packages)
+fn package_files(package_path: &Path) -> impl Iterator<Item = walkdir::DirEntry> {
+    walkdir::WalkDir::new(package_path)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || e.file_name() != "node_modules")
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+}
+
 /// Deduplicate packages by creating symlinks to global store
 #[allow(dead_code)]
 pub struct SemanticDeduplication {
@@ -166,18 +380,47 @@ pub struct SemanticDeduplication {
 }
 
 impl SemanticDeduplication {
-    pub fn new() -> Result<Self> {
+    pub fn new() -> crate::Result<Self> {
         let store_path = ensure_global_store()?;
         Ok(Self { store_path })
     }
 
+    /// Store entry a deduplicated name@version links to
+    pub fn entry_path(&self, name: &str, version: &str) -> crate::Result<PathBuf> {
+        get_canonical_path(&self.store_path, name, version)
+    }
+
+    /// Seed the store entry for name@version from `package_path` via hard links,
+    /// leaving the package itself in place. Returns false if the store already
+    /// holds the entry.
+    pub fn seed_store(&self, package_path: &Path, name: &str, version: &str) -> crate::Result<bool> {
+        self.seed_store_impl(package_path, name, version).map_err(Error::lift(Error::Store))
+    }
+
+    fn seed_store_impl(&self, package_path: &Path, name: &str, version: &str) -> Result<bool> {
+        let canonical_path = get_canonical_path(&self.store_path, name, version)?;
+        if canonical_path.exists() {
+            return Ok(false);
+        }
+        if let Err(e) = hard_link_directory_impl(package_path, &canonical_path) {
+            fs::remove_dir_all(&canonical_path).ok();
+            return Err(e.context(format!("Failed to seed store from {:?}", package_path)));
+        }
+        Ok(true)
+    }
+
     /// Process a package: hard link to global store, then symlink from original location
-    pub fn deduplicate_package(&self, package_path: &Path, name: &str, version: &str) -> Result<()> {
+    pub fn deduplicate_package(&self, package_path: &Path, name: &str, version: &str) -> crate::Result<()> {
+        self.deduplicate_package_impl(package_path, name, version).map_err(Error::lift(Error::Store))
+    }
+
+    fn deduplicate_package_impl(&self, package_path: &Path, name: &str, version: &str) -> Result<()> {
+        ensure_writable("deduplicate packages")?;
         let canonical_path = get_canonical_path(&self.store_path, name, version)?;
         
         // If canonical doesn't exist, create it by hard linking from package_path
         if !canonical_path.exists() {
-            hard_link_directory(package_path, &canonical_path)
+            hard_link_directory_impl(package_path, &canonical_path)
                 .with_context(|| format!("Failed to create canonical package at {:?}", canonical_path))?;
         }
         
@@ -187,7 +430,7 @@ impl SemanticDeduplication {
             let temp_path = package_path.with_extension(".packagepurge.tmp");
             
             // Create symlink at temp location first
-            create_symlink(&temp_path, &canonical_path)?;
+            create_symlink_impl(&temp_path, &canonical_path)?;
             
             // Remove original and rename temp
             if package_path.is_dir() {
@@ -204,6 +447,116 @@ impl SemanticDeduplication {
         
         Ok(())
     }
+
+    /// Hardlink-farm variant of `deduplicate_package`: the package stays a real
+    /// directory, but every file whose content matches the store entry is
+    /// replaced by a hard link to it. Returns the number of files linked.
+    pub fn hardlink_package(&self, package_path: &Path, name: &str, version: &str) -> crate::Result<usize> {
+        self.hardlink_package_impl(package_path, name, version).map_err(Error::lift(Error::Store))
+    }
+
+    fn hardlink_package_impl(&self, package_path: &Path, name: &str, version: &str) -> Result<usize> {
+        ensure_writable("deduplicate packages")?;
+        if is_symlink(package_path) {
+            anyhow::bail!("{:?} is a symlink, not a package directory", package_path);
+        }
+        let canonical_path = get_canonical_path(&self.store_path, name, version)?;
+        if !canonical_path.exists() {
+            hard_link_directory_impl(package_path, &canonical_path)
+                .with_context(|| format!("Failed to create canonical package at {:?}", canonical_path))?;
+            return Ok(0);
+        }
+
+        let mut linked = 0;
+        for entry in package_files(package_path) {
+            let path = entry.path();
+            let rel = path.strip_prefix(package_path)?;
+            let store_file = canonical_path.join(rel);
+            let (Ok(meta), Ok(store_meta)) = (entry.metadata(), fs::metadata(&store_file)) else {
+                continue;
+            };
+            if meta.len() != store_meta.len() || same_file(path, &store_file) {
+                continue;
+            }
+            // Never link a file whose content differs from the store's copy
+            if !same_content(path, &store_file)? {
+                continue;
+            }
+            let temp = path.with_extension("packagepurge.link");
+            fs::hard_link(&store_file, &temp)
+                .with_context(|| format!("Failed to hard link {:?} to {:?}", temp, store_file))?;
+            fs::rename(&temp, path)
+                .with_context(|| format!("Failed to replace {:?} with a hard link", path))?;
+            linked += 1;
+        }
+        Ok(linked)
+    }
+
+    /// Undo `hardlink_package`: give every file of the package its own inode again
+    pub fn unlink_package(&self, package_path: &Path) -> crate::Result<()> {
+        unlink_package_impl(package_path).map_err(Error::lift(Error::Store))
+    }
+
+    /// Undo `deduplicate_package`: replace the store symlink with a private copy
+    /// of the canonical package contents
+    pub fn restore_package(&self, package_path: &Path, name: &str, version: &str) -> crate::Result<()> {
+        self.restore_package_impl(package_path, name, version).map_err(Error::lift(Error::Store))
+    }
+
+    fn restore_package_impl(&self, package_path: &Path, name: &str, version: &str) -> Result<()> {
+        ensure_writable("restore deduplicated packages")?;
+        let canonical_path = get_canonical_path(&self.store_path, name, version)?;
+        if !is_symlink(package_path) {
+            return Ok(());
+        }
+
+        let temp_path = package_path.with_extension(".packagepurge.restore");
+        if temp_path.exists() {
+            fs::remove_dir_all(&temp_path).ok();
+        }
+        fs::create_dir_all(&temp_path)
+            .with_context(|| format!("Failed to create restore directory {:?}", temp_path))?;
+        let copy_opts = fs_extra::dir::CopyOptions::new().content_only(true);
+        if let Err(e) = fs_extra::dir::copy(&canonical_path, &temp_path, &copy_opts) {
+            fs::remove_dir_all(&temp_path).ok();
+            return Err(anyhow::anyhow!("Failed to copy {:?} out of the store: {}", canonical_path, e));
+        }
+
+        remove_symlink(package_path)?;
+        fs::rename(&temp_path, package_path)
+            .with_context(|| format!("Failed to move restored copy into {:?}", package_path))?;
+        Ok(())
+    }
+}
+
+fn unlink_package_impl(package_path: &Path) -> Result<()> {
+    ensure_writable("restore deduplicated packages")?;
+    for entry in package_files(package_path) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if entry.metadata().map(|m| m.nlink() <= 1).unwrap_or(true) {
+                continue;
+            }
+        }
+        let path = entry.path();
+        let temp = path.with_extension("packagepurge.unlink");
+        fs::copy(path, &temp).with_context(|| format!("Failed to copy {:?}", path))?;
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Remove a symlink (or junction) without following it
+pub(crate) fn remove_symlink(path: &Path) -> Result<()> {
+    ensure_writable("remove symlinks")?;
+    #[cfg(windows)]
+    {
+        if fs::remove_dir(path).is_ok() {
+            return Ok(());
+        }
+    }
+    fs::remove_file(path).with_context(|| format!("Failed to remove symlink {:?}", path))
 }
 
 #[cfg(test)]
@@ -212,6 +565,27 @@ mod tests {
     use std::fs;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_detect_native_build() {
+        let temp = tempdir().unwrap();
+        assert!(!has_native_build(temp.path()));
+
+        let release = temp.path().join("build").join("Release");
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join("addon.node"), b"\0").unwrap();
+        assert!(has_native_build(temp.path()));
+    }
+
+    #[test]
+    fn test_in_use_detection() {
+        let temp = tempdir().unwrap();
+        let mut open = HashSet::new();
+        assert!(!is_in_use(temp.path(), &open));
+
+        open.insert(temp.path().join("index.js"));
+        assert!(is_in_use(temp.path(), &open));
+    }
+
     #[test]
     fn test_get_canonical_path() {
         let store = PathBuf::from("/tmp/store");
@@ -219,5 +593,46 @@ mod tests {
         assert!(path.to_string_lossy().contains("react"));
         assert!(path.to_string_lossy().contains("18.2.0"));
     }
-}
 
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlink_package_and_undo() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp = tempdir().unwrap();
+        let dedup = SemanticDeduplication { store_path: temp.path().join("store") };
+        let (first, second) = (temp.path().join("p1/a"), temp.path().join("p2/a"));
+        for dir in [&first, &second] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("index.js"), "module.exports = 1;").unwrap();
+            fs::write(dir.join("README.md"), "readme").unwrap();
+        }
+        fs::write(second.join("README.md"), "patched").unwrap();
+
+        assert_eq!(dedup.hardlink_package(&first, "a", "1.0.0").unwrap(), 0);
+        assert_eq!(dedup.hardlink_package(&second, "a", "1.0.0").unwrap(), 1);
+        let ino = |p: &Path| fs::metadata(p).unwrap().ino();
+        assert!(second.is_dir() && !is_symlink(&second));
+        assert_eq!(ino(&first.join("index.js")), ino(&second.join("index.js")));
+        assert_ne!(ino(&first.join("README.md")), ino(&second.join("README.md")));
+        // Already linked files are left alone on the next run
+        assert_eq!(dedup.hardlink_package(&second, "a", "1.0.0").unwrap(), 0);
+
+        dedup.unlink_package(&second).unwrap();
+        assert_ne!(ino(&first.join("index.js")), ino(&second.join("index.js")));
+        assert_eq!(fs::read_to_string(second.join("index.js")).unwrap(), "module.exports = 1;");
+    }
+
+    #[test]
+    fn test_read_only_refuses_dedup() {
+        let temp = tempdir().unwrap();
+        let dedup = SemanticDeduplication { store_path: temp.path().join("store") };
+        let package = temp.path().join("app/node_modules/a");
+        fs::create_dir_all(&package).unwrap();
+
+        let _read_only = crate::safety::read_only_for_test();
+        assert!(matches!(dedup.hardlink_package(&package, "a", "1.0.0"), Err(Error::ReadOnly(_))));
+        assert!(matches!(dedup.deduplicate_package(&package, "a", "1.0.0"), Err(Error::ReadOnly(_))));
+        assert!(package.is_dir() && !temp.path().join("store").exists());
+    }
+}
@@ -0,0 +1,149 @@
+//! Phase Timings
+//!
+//! `--timings` (or `PACKAGEPURGE_TIMINGS=1`) breaks a command's wall time
+//! down by phase and prints the breakdown to stderr when it finishes:
+//!
+//! - `walk`: directory walk of the scan roots
+//! - `lockfiles`: reading and parsing manifests and lockfiles
+//! - `size`: sizing packages, during the scan or for a lazy plan
+//! - `plan`: building cleanup and symlink plans
+//! - `hash`: content hashes of trees (quarantine checksums, store and
+//!   duplicate checks)
+//! - `move`: moving targets into quarantine
+//!
+//! Phases are timed from the thread driving them, so parallel work counts
+//! once. `hash` time spent inside a phase (a quarantine checksum taken
+//! after the move) is not counted in that phase as well; whatever no phase
+//! covers is reported as `other`.
+
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimedPhase {
+    Walk,
+    Lockfiles,
+    Size,
+    Plan,
+    Hash,
+    Move,
+}
+
+impl TimedPhase {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Walk => "walk",
+            Self::Lockfiles => "lockfiles",
+            Self::Size => "size",
+            Self::Plan => "plan",
+            Self::Hash => "hash",
+            Self::Move => "move",
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TOTALS: Mutex<BTreeMap<TimedPhase, (Duration, u64)>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// Phases this thread is inside, so a phase entered again (recursion,
+    /// a helper timing itself) is counted once
+    static ACTIVE: RefCell<Vec<TimedPhase>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Time phases for the rest of the process (`--timings`)
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Stop timing phases (`PACKAGEPURGE_TIMINGS` still applies)
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// Whether phases are timed, by `enable` or `PACKAGEPURGE_TIMINGS`
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+        || std::env::var("PACKAGEPURGE_TIMINGS").is_ok_and(|v| !v.is_empty() && v != "0" && v != "false")
+}
+
+/// Time spent in one phase so far
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    pub phase: TimedPhase,
+    pub ms: f64,
+    /// Times the phase was entered
+    pub count: u64,
+}
+
+/// Adds the time until it is dropped to its phase
+#[must_use]
+pub struct Span {
+    phase: TimedPhase,
+    started: Option<Instant>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(started) = self.started else { return };
+        ACTIVE.with(|active| active.borrow_mut().retain(|p| *p != self.phase));
+        if let Ok(mut totals) = TOTALS.lock() {
+            let entry = totals.entry(self.phase).or_default();
+            entry.0 += started.elapsed();
+            entry.1 += 1;
+        }
+    }
+}
+
+/// Start timing `phase`; a no-op unless timings are enabled
+pub fn span(phase: TimedPhase) -> Span {
+    let outermost = is_enabled() && ACTIVE.with(|active| {
+        let mut active = active.borrow_mut();
+        let outermost = !active.contains(&phase);
+        if outermost {
+            active.push(phase);
+        }
+        outermost
+    });
+    Span { phase, started: outermost.then(Instant::now) }
+}
+
+/// Run `f` as `phase`
+pub fn time<T>(phase: TimedPhase, f: impl FnOnce() -> T) -> T {
+    let _span = span(phase);
+    f()
+}
+
+/// Time spent per phase so far, in phase order
+pub fn report() -> Vec<PhaseTiming> {
+    TOTALS.lock().map(|totals| totals.iter()
+        .map(|(phase, (spent, count))| PhaseTiming { phase: *phase, ms: spent.as_secs_f64() * 1000.0, count: *count })
+        .collect())
+        .unwrap_or_default()
+}
+
+/// Forget the time recorded so far
+pub fn reset() {
+    if let Ok(mut totals) = TOTALS.lock() {
+        totals.clear();
+    }
+}
+
+/// The breakdown printed after a command that took `total`
+pub fn render(total: Duration) -> String {
+    let total_ms = total.as_secs_f64() * 1000.0;
+    let share = |ms: f64| if total_ms > 0.0 { ms / total_ms * 100.0 } else { 0.0 };
+    let mut out = format!("Timings: {:.1} ms total\n", total_ms);
+    let phases = report();
+    for p in &phases {
+        out.push_str(&format!("  {:<10} {:>10.1} ms {:>5.1}%  ({}x)\n", p.phase.name(), p.ms, share(p.ms), p.count));
+    }
+    let other = (total_ms - phases.iter().map(|p| p.ms).sum::<f64>()).max(0.0);
+    out.push_str(&format!("  {:<10} {:>10.1} ms {:>5.1}%\n", "other", other, share(other)));
+    out
+}
@@ -0,0 +1,144 @@
+//! Largest, Fastest-Growing and Stalest Packages
+//!
+//! Answers "what is taking the space?" from the package snapshots recorded
+//! by earlier full (not lazy or streamed) scans, without scanning or
+//! planning. Each package is taken as its newest snapshot; growth is its
+//! size there minus its size in its oldest snapshot within the growth
+//! window, so it needs two scans of the package inside the window. Packages
+//! deleted since their last scan are left out, except when paths are stored
+//! hashed and cannot be checked.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::feature_store::FeatureStore;
+
+/// What `top` ranks by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopBy {
+    Size,
+    Growth,
+    /// Longest since last access
+    Staleness,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopEntry {
+    pub name: String,
+    pub version: String,
+    pub path: String,
+    pub size_bytes: u64,
+    /// Bytes gained within the window (None with a single snapshot there)
+    pub growth_bytes: Option<i64>,
+    pub last_access: DateTime<Utc>,
+    pub stale_days: i64,
+    pub scanned_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopReport {
+    pub by: TopBy,
+    pub growth_window_days: i64,
+    /// Packages with a snapshot, before limiting
+    pub packages: usize,
+    /// Time of the newest scan the entries come from
+    pub latest_scan: Option<DateTime<Utc>>,
+    pub entries: Vec<TopEntry>,
+}
+
+/// The `limit` packages ranking highest by `by`
+pub fn top(db: &FeatureStore, by: TopBy, limit: usize, growth_window_days: i64, now: DateTime<Utc>) -> crate::Result<TopReport> {
+    let latest = db.latest_snapshots()?;
+    let baselines = db.baseline_sizes(now - Duration::days(growth_window_days))?;
+    let newest_scan = latest.iter().map(|(_, _, at)| *at).max();
+    let packages = latest.len();
+
+    let mut entries: Vec<TopEntry> = latest.into_iter()
+        .map(|(snapshot, scan_id, scanned_at)| {
+            // A baseline from the same scan as the snapshot is no comparison
+            let growth_bytes = baselines.get(&snapshot.path)
+                .filter(|(_, baseline_scan)| *baseline_scan != scan_id)
+                .map(|(size, _)| snapshot.size_bytes as i64 - *size as i64);
+            TopEntry {
+                stale_days: (now - snapshot.atime).num_days().max(0),
+                name: snapshot.name,
+                version: snapshot.version,
+                path: snapshot.path,
+                size_bytes: snapshot.size_bytes,
+                growth_bytes,
+                last_access: snapshot.atime,
+                scanned_at,
+            }
+        })
+        .collect();
+    match by {
+        TopBy::Size => entries.sort_by_key(|e| std::cmp::Reverse(e.size_bytes)),
+        TopBy::Growth => {
+            entries.retain(|e| e.growth_bytes.is_some_and(|g| g != 0));
+            entries.sort_by_key(|e| std::cmp::Reverse(e.growth_bytes));
+        }
+        TopBy::Staleness => entries.sort_by_key(|e| (e.last_access, std::cmp::Reverse(e.size_bytes))),
+    }
+
+    let mut kept = Vec::new();
+    for entry in entries {
+        if kept.len() == limit {
+            break;
+        }
+        if crate::privacy::is_hashed(&entry.path) || Path::new(&entry.path).exists() {
+            kept.push(entry);
+        }
+    }
+    Ok(TopReport { by, growth_window_days, packages, latest_scan: newest_scan, entries: kept })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{PackageSnapshot, ScanStats};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rank_by_size_growth_and_staleness() {
+        let temp = tempdir().unwrap();
+        let db = FeatureStore::open(&temp.path().join("features.db")).unwrap();
+        let now = Utc::now();
+        let package = |name: &str, size: u64, idle_days: i64| {
+            let path = temp.path().join("node_modules").join(name);
+            fs::create_dir_all(&path).unwrap();
+            PackageSnapshot {
+                path: path.to_string_lossy().to_string(),
+                name: name.into(),
+                version: "1.0.0".into(),
+                size_bytes: size,
+                atime: now - Duration::days(idle_days),
+            }
+        };
+        let scan = |days_ago: i64, packages: &[PackageSnapshot]| {
+            let stats = ScanStats { started_at: Some(now - Duration::days(days_ago)), ..Default::default() };
+            let id = db.record_scan(&[PathBuf::from("/work")], packages.len(), 0, &stats).unwrap();
+            db.record_snapshot(id, packages).unwrap();
+        };
+        scan(60, &[package("big", 900, 1)]);
+        scan(10, &[package("big", 1000, 1), package("grows", 100, 2), package("gone", 50, 3)]);
+        scan(0, &[package("big", 1000, 1), package("grows", 600, 40), package("new", 10, 0)]);
+        fs::remove_dir_all(temp.path().join("node_modules/gone")).unwrap();
+
+        let names = |report: TopReport| report.entries.into_iter().map(|e| e.name).collect::<Vec<_>>();
+        let by_size = top(&db, TopBy::Size, 2, 30, now).unwrap();
+        assert_eq!(by_size.packages, 4);
+        assert_eq!(names(by_size), ["big", "grows"]);
+
+        // `big` last changed outside the window; `new` has one snapshot
+        let by_growth = top(&db, TopBy::Growth, 10, 30, now).unwrap();
+        assert_eq!(by_growth.entries[0].growth_bytes, Some(500));
+        assert_eq!(names(by_growth), ["grows"]);
+        assert_eq!(names(top(&db, TopBy::Growth, 10, 90, now).unwrap()), ["grows", "big"]);
+
+        assert_eq!(names(top(&db, TopBy::Staleness, 10, 30, now).unwrap()), ["grows", "big", "new"]);
+    }
+}
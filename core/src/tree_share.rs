@@ -0,0 +1,387 @@
+//! Shared node_modules Trees
+//!
+//! Projects generated from one template often install exactly the same
+//! tree. Rather than deduplicating such trees package by package, one
+//! project's `node_modules` can stand in for all of them: the others are
+//! moved to quarantine and replaced by a symlink (a junction on Windows) to
+//! it.
+//!
+//! Candidates are projects whose lockfiles are byte-identical, or whose
+//! trees are the same size when there is no lockfile. Every candidate tree
+//! is then fingerprinted, covering paths, file contents and link targets,
+//! and only trees identical to the canonical one are shared. A duplicate is
+//! reported with blockers instead when:
+//! - its content differs from the canonical tree despite an identical
+//!   lockfile (postinstall output, local patches): `IntegrityMismatch`
+//! - it links to packages inside its own project (workspaces, `file:`
+//!   dependencies), which would resolve to the canonical project's: `LocalLink`
+//! - a running process holds files open inside it: `InUse`
+//!
+//! `node_modules/.cache` is tool state, not install output, and is left out
+//! of the comparison. Once shared, an install in any of the projects writes
+//! to the one tree all of them use.
+//!
+//! Each share is recorded in `shared_trees.json` in the state directory. A
+//! canonical tree that other projects still link to is never planned by the
+//! project retention rules and refused as a quarantine target, whatever
+//! becomes of its own project; rolling back the links releases it.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::native_walk::tree_totals;
+use crate::reinstall::PackageManager;
+use crate::symlink::{is_in_use, is_symlink};
+use crate::timings::{self, TimedPhase};
+use crate::types::{DedupBlocker, QuarantineRecord, ScanOutput};
+
+/// How the projects of a group were found to install the same tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeMatch {
+    /// Identical lockfiles
+    Lockfile,
+    /// No lockfile; identical tree content
+    Content,
+}
+
+/// A `node_modules` tree that could replace the trees of other projects
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedTree {
+    /// The tree that is kept
+    pub canonical: String,
+    pub matched_by: TreeMatch,
+    pub tree_bytes: u64,
+    pub duplicates: Vec<TreeDuplicate>,
+    /// Bytes freed by sharing every unblocked duplicate
+    pub estimated_savings: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeDuplicate {
+    pub node_modules: String,
+    pub blockers: Vec<DedupBlocker>,
+}
+
+/// Content identity of a tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint {
+    digest: String,
+    bytes: u64,
+    /// Links resolve into the project outside `node_modules`
+    local_links: bool,
+}
+
+fn fingerprint(node_modules: &Path) -> Result<Fingerprint> {
+    let _timing = timings::span(TimedPhase::Hash);
+    let project = node_modules.parent().unwrap_or(node_modules);
+    let mut hasher = Sha256::new();
+    let mut bytes = 0;
+    let mut local_links = false;
+    let walker = walkdir::WalkDir::new(node_modules)
+        .follow_links(false)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| !(e.depth() == 1 && e.file_name() == ".cache"));
+    for entry in walker {
+        let entry = entry.with_context(|| format!("Failed to walk {:?}", node_modules))?;
+        if entry.depth() == 0 {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(node_modules)?;
+        hasher.update(rel.to_string_lossy().as_bytes());
+        let kind = entry.file_type();
+        if kind.is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            hasher.update(b"\0l");
+            hasher.update(target.to_string_lossy().as_bytes());
+            let resolved = entry.path().parent().unwrap_or(node_modules).join(&target);
+            let resolved = normalize(&resolved);
+            local_links |= resolved.starts_with(project) && !resolved.starts_with(node_modules);
+        } else if kind.is_file() {
+            hasher.update(b"\0f");
+            let mut file = fs::File::open(entry.path())
+                .with_context(|| format!("Failed to read {:?}", entry.path()))?;
+            bytes += std::io::copy(&mut file, &mut hasher)?;
+        } else {
+            hasher.update(b"\0d");
+        }
+        hasher.update(b"\n");
+    }
+    Ok(Fingerprint { digest: hex::encode(hasher.finalize()), bytes, local_links })
+}
+
+/// Resolve `.` and `..` lexically; link targets need not exist
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            std::path::Component::ParentDir => { out.pop(); }
+            std::path::Component::CurDir => {}
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Grouping key before fingerprinting
+fn candidate_key(project: &Path, node_modules: &Path) -> Option<(TreeMatch, String)> {
+    match PackageManager::detect(project) {
+        (pm, Some(lockfile)) => {
+            let bytes = fs::read(project.join(lockfile)).ok()?;
+            Some((TreeMatch::Lockfile, format!("{}:{}", pm, hex::encode(Sha256::digest(&bytes)))))
+        }
+        (_, None) => Some((TreeMatch::Content, tree_totals(node_modules, None).bytes.to_string())),
+    }
+}
+
+/// Find the scanned projects whose `node_modules` trees could be shared.
+/// Trees that are already links are not candidates.
+pub fn find_shared_trees(scan: &ScanOutput, open_files: &HashSet<PathBuf>) -> Vec<SharedTree> {
+    let mut groups: HashMap<(TreeMatch, String), Vec<PathBuf>> = HashMap::new();
+    for project in &scan.projects {
+        let project = Path::new(&project.path);
+        let node_modules = project.join("node_modules");
+        if !node_modules.is_dir() || is_symlink(&node_modules) {
+            continue;
+        }
+        if let Some(key) = candidate_key(project, &node_modules) {
+            groups.entry(key).or_default().push(node_modules);
+        }
+    }
+
+    let mut shared: Vec<SharedTree> = groups.into_iter()
+        .filter(|(_, trees)| trees.len() > 1)
+        .filter_map(|((matched_by, _), mut trees)| {
+            trees.sort();
+            trees.dedup();
+            let prints: Vec<(PathBuf, Fingerprint)> = trees.into_iter()
+                .filter_map(|t| fingerprint(&t).ok().map(|f| (t, f)))
+                .collect();
+            share_group(matched_by, prints, open_files)
+        })
+        .collect();
+    shared.sort_by_key(|s| std::cmp::Reverse(s.estimated_savings));
+    shared
+}
+
+/// Pick the canonical tree of a group, the first one with the most common
+/// content, and check every other tree against it
+fn share_group(matched_by: TreeMatch, prints: Vec<(PathBuf, Fingerprint)>, open_files: &HashSet<PathBuf>) -> Option<SharedTree> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (_, print) in &prints {
+        *counts.entry(print.digest.as_str()).or_default() += 1;
+    }
+    let (canonical, canonical_print) = prints.iter()
+        .max_by_key(|(path, print)| (counts[print.digest.as_str()], std::cmp::Reverse(path.clone())))?;
+
+    let duplicates: Vec<TreeDuplicate> = prints.iter()
+        .filter(|(path, _)| path != canonical)
+        .filter_map(|(path, print)| {
+            let mut blockers = Vec::new();
+            if print.digest != canonical_print.digest {
+                // Same-sized trees without lockfiles are not related at all
+                if matched_by == TreeMatch::Content {
+                    return None;
+                }
+                blockers.push(DedupBlocker::IntegrityMismatch);
+            }
+            if print.local_links || canonical_print.local_links {
+                blockers.push(DedupBlocker::LocalLink);
+            }
+            if is_in_use(path, open_files) {
+                blockers.push(DedupBlocker::InUse);
+            }
+            Some(TreeDuplicate { node_modules: path.to_string_lossy().to_string(), blockers })
+        })
+        .collect();
+    if duplicates.is_empty() {
+        return None;
+    }
+    let unblocked = duplicates.iter().filter(|d| d.blockers.is_empty()).count() as u64;
+    Some(SharedTree {
+        canonical: canonical.to_string_lossy().to_string(),
+        matched_by,
+        tree_bytes: canonical_print.bytes,
+        duplicates,
+        estimated_savings: canonical_print.bytes * unblocked,
+    })
+}
+
+/// Move `duplicate` to quarantine and link it to `canonical`. Both must
+/// pass `validate_target` under `roots`. The duplicate is restored if the
+/// link cannot be created; rolling back the returned record later replaces
+/// the link with the original tree.
+pub fn share_tree(canonical: &Path, duplicate: &Path, roots: &[PathBuf]) -> crate::Result<QuarantineRecord> {
+    crate::safety::validate_target(canonical, roots)?;
+    crate::safety::validate_target(duplicate, roots)?;
+    let record = crate::safety::move_to_quarantine(duplicate)?;
+    if let Err(e) = crate::symlink::create_symlink(duplicate, canonical) {
+        if let Err(restore) = crate::safety::rollback_record(&record) {
+            eprintln!("Warning: Failed to restore {:?}: {}", duplicate, restore);
+        }
+        return Err(e);
+    }
+    if let Err(e) = record_share(&registry_path(), canonical, duplicate) {
+        eprintln!("Warning: Failed to record that {:?} links to {:?}: {}", duplicate, canonical, e);
+    }
+    Ok(record)
+}
+
+/// A canonical tree and the trees that were replaced by links to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ShareGroup {
+    canonical: String,
+    sharers: Vec<String>,
+}
+
+fn registry_path() -> PathBuf {
+    crate::paths::state_dir().join("shared_trees.json")
+}
+
+fn read_registry(path: &Path) -> Vec<ShareGroup> {
+    fs::read_to_string(path).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+fn record_share(path: &Path, canonical: &Path, duplicate: &Path) -> Result<()> {
+    let mut groups = read_registry(path);
+    let canonical = canonical.to_string_lossy().to_string();
+    let duplicate = duplicate.to_string_lossy().to_string();
+    match groups.iter_mut().find(|g| g.canonical == canonical) {
+        Some(group) if !group.sharers.contains(&duplicate) => group.sharers.push(duplicate),
+        Some(_) => {}
+        None => groups.push(ShareGroup { canonical, sharers: vec![duplicate] }),
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&groups)?).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Canonical trees (resolved) with the projects' trees still linking to them
+fn live_groups(path: &Path) -> Vec<(PathBuf, Vec<String>)> {
+    read_registry(path).into_iter()
+        .filter_map(|group| {
+            let canonical = fs::canonicalize(&group.canonical).ok()?;
+            let sharers: Vec<String> = group.sharers.into_iter()
+                .filter(|s| is_symlink(Path::new(s)) && fs::canonicalize(s).is_ok_and(|t| t == canonical))
+                .collect();
+            (!sharers.is_empty()).then_some((canonical, sharers))
+        })
+        .collect()
+}
+
+/// Trees linking to a canonical tree at or inside `target`; empty when
+/// removing `target` would break no shared project
+pub fn sharers_within(target: &Path) -> Vec<String> {
+    sharers_within_in(&registry_path(), target)
+}
+
+fn sharers_within_in(registry: &Path, target: &Path) -> Vec<String> {
+    let Ok(target) = fs::canonicalize(target) else { return Vec::new() };
+    live_groups(registry).into_iter()
+        .filter(|(canonical, _)| canonical.starts_with(&target))
+        .flat_map(|(_, sharers)| sharers)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProjectRecord;
+    use chrono::Utc;
+    use tempfile::tempdir;
+
+    fn project(root: &Path, name: &str, lodash: &str) -> ProjectRecord {
+        let dir = root.join(name);
+        fs::create_dir_all(dir.join("node_modules/lodash")).unwrap();
+        fs::write(dir.join("package.json"), r#"{"dependencies": {"lodash": "^4.0.0"}}"#).unwrap();
+        fs::write(dir.join("package-lock.json"), r#"{"lockfileVersion": 3}"#).unwrap();
+        fs::write(dir.join("node_modules/lodash/index.js"), lodash).unwrap();
+        ProjectRecord {
+            path: dir.to_string_lossy().to_string(),
+            manager: None,
+            dependencies: Vec::new(),
+            mtime: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_identical_trees_are_shared_and_differences_block() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let mut projects = vec![
+            project(root, "a", "module.exports = 1"),
+            project(root, "b", "module.exports = 1"),
+            project(root, "c", "module.exports = 2"),
+            project(root, "d", "module.exports = 1"),
+        ];
+        // `.cache` is ignored; a workspace link into the project blocks
+        fs::create_dir_all(root.join("b/node_modules/.cache")).unwrap();
+        fs::write(root.join("b/node_modules/.cache/state"), "b").unwrap();
+        fs::create_dir_all(root.join("d/packages/ui")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("../packages/ui", root.join("d/node_modules/ui")).unwrap();
+        #[cfg(not(unix))]
+        projects.pop();
+        projects.sort_by(|x, y| x.path.cmp(&y.path));
+
+        let scan = ScanOutput {
+            packages: Vec::new(),
+            projects,
+            edges: Vec::new(),
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let shared = find_shared_trees(&scan, &HashSet::new());
+        assert_eq!(shared.len(), 1);
+        let group = &shared[0];
+        assert_eq!(group.matched_by, TreeMatch::Lockfile);
+        assert!(group.canonical.ends_with("a/node_modules"));
+        let blockers: HashMap<&str, &Vec<DedupBlocker>> = group.duplicates.iter()
+            .map(|d| (d.node_modules.as_str(), &d.blockers))
+            .collect();
+        let of = |name: &str| blockers[root.join(name).join("node_modules").to_string_lossy().as_ref()];
+        assert!(of("b").is_empty());
+        assert_eq!(of("c"), &vec![DedupBlocker::IntegrityMismatch]);
+        #[cfg(unix)]
+        assert!(of("d").contains(&DedupBlocker::LocalLink));
+        assert_eq!(group.estimated_savings, group.tree_bytes);
+
+        let open: HashSet<PathBuf> = [root.join("b/node_modules/lodash/index.js")].into_iter().collect();
+        let busy = find_shared_trees(&scan, &open);
+        assert_eq!(busy[0].estimated_savings, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_canonical_tree_is_protected_while_linked() {
+        let temp = tempdir().unwrap();
+        let root = temp.path();
+        let registry = root.join("shared_trees.json");
+        project(root, "a", "module.exports = 1");
+        let canonical = root.join("a/node_modules");
+        let sharer = root.join("b/node_modules");
+        fs::create_dir_all(sharer.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(&canonical, &sharer).unwrap();
+        record_share(&registry, &canonical, &sharer).unwrap();
+        record_share(&registry, &canonical, &sharer).unwrap();
+
+        let sharers = vec![sharer.to_string_lossy().to_string()];
+        assert_eq!(sharers_within_in(&registry, &canonical), sharers);
+        assert_eq!(sharers_within_in(&registry, &root.join("a")), sharers);
+        assert!(sharers_within_in(&registry, &canonical.join("lodash")).is_empty());
+
+        // Once the link is rolled back the tree is free again
+        fs::remove_file(&sharer).unwrap();
+        fs::create_dir(&sharer).unwrap();
+        assert!(sharers_within_in(&registry, &canonical).is_empty());
+    }
+}
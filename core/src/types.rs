@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::eviction::PolicyKind;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum PackageManager { Npm, Yarn, Pnpm }
+pub enum PackageManager { Npm, Yarn, Pnpm, Terraform, Serverless, Cargo, Python, Go }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageRecord {
@@ -10,12 +12,46 @@ pub struct PackageRecord {
     pub version: String,
     pub path: String,
     pub size_bytes: u64,
+    /// Bytes of files also hard-linked from outside the package (the global
+    /// store, another package): removing it does not free them, so they are
+    /// not in `size_bytes`
+    #[serde(default)]
+    pub linked_bytes: u64,
+    /// Regular files below the package, nested `node_modules` excluded
+    /// (0 when the scan did not count them)
+    #[serde(default)]
+    pub file_count: u64,
+    /// Files and directories below the package: the inodes removing it frees
+    #[serde(default)]
+    pub inode_count: u64,
     pub atime: DateTime<Utc>,
     pub mtime: DateTime<Utc>,
     pub manager: Option<PackageManager>,
     pub project_paths: Vec<String>,
 }
 
+#[cfg(test)]
+impl PackageRecord {
+    /// A package at `path`, named after its last component and used just
+    /// now, for tests; other fields are set with struct update syntax
+    pub(crate) fn for_test(path: impl AsRef<std::path::Path>, version: &str, size_bytes: u64) -> Self {
+        let path = path.as_ref();
+        Self {
+            name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            version: version.to_string(),
+            path: path.to_string_lossy().to_string(),
+            size_bytes,
+            linked_bytes: 0,
+            file_count: 0,
+            inode_count: 0,
+            atime: Utc::now(),
+            mtime: Utc::now(),
+            manager: None,
+            project_paths: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectRecord {
     pub path: String,
@@ -28,20 +64,487 @@ pub struct ProjectRecord {
 pub struct ScanOutput {
     pub packages: Vec<PackageRecord>,
     pub projects: Vec<ProjectRecord>,
-    pub edges: Vec<(String, String)>, // parent -> dependency
+    /// Resolved dependency edges: (project or package path, package path)
+    pub edges: Vec<(String, String)>,
+    /// Work done and time taken by the scan that produced this output
+    #[serde(default)]
+    pub stats: ScanStats,
+    /// Packages a lazy scan did not size (`size_bytes` is 0 until planning sizes them)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deferred_sizes: Vec<String>,
+    /// Tagged caches and vendored dependencies the scan rules report but
+    /// keep out of cleanup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub marked_dirs: Vec<MarkedDir>,
+}
+
+/// What marks a directory outside the known package locations as a cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Marker {
+    /// A `CACHEDIR.TAG` with the specification's signature
+    CachedirTag,
+    /// Go `vendor/` with its `modules.txt`
+    GoVendor,
+    /// Composer `vendor/` with its `autoload.php`
+    ComposerVendor,
+    /// Bundler `vendor/bundle` next to a `Gemfile`
+    BundlerVendor,
+}
+
+/// A marked directory found by a scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkedDir {
+    pub path: String,
+    pub marker: Marker,
+    pub size_bytes: u64,
+    #[serde(default)]
+    pub file_count: u64,
+}
+
+/// One result of a streaming scan, in the order produced: projects, edges
+/// from projects, then each batch of packages followed by its edges
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScanItem {
+    Project(ProjectRecord),
+    Package(PackageRecord),
+    /// Resolved dependency edge: (project or package path, package path)
+    Edge { from: String, to: String },
+    Marked(MarkedDir),
+}
+
+/// Totals of a streaming scan whose records went to a visitor
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanSummary {
+    pub packages: usize,
+    /// Bytes held by the packages found (unsized packages count as 0)
+    #[serde(default)]
+    pub package_bytes: u64,
+    pub projects: usize,
+    pub edges: usize,
+    /// Marked directories reported (those recorded as packages count as packages)
+    #[serde(default)]
+    pub marked_dirs: usize,
+    pub stats: ScanStats,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deferred_sizes: Vec<String>,
 }
 
+/// Per-scan I/O and timing figures, for tracking scan performance over time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanStats {
+    pub started_at: Option<DateTime<Utc>>,
+    pub dirs_walked: u64,
+    /// Files whose metadata was read (sizing, timestamps)
+    pub files_stated: u64,
+    /// Manifest and lockfile bytes read and parsed; the scan hashes no file content
+    pub bytes_read: u64,
+    pub wall_ms: u64,
+    /// Process CPU time (all threads); None where it cannot be measured
+    pub cpu_ms: Option<u64>,
+    /// Package sizes served from, and computed despite, the incremental scan cache
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub cache_hit_rate: f64,
+    /// Directories skipped because the cache remembers them as holding no packages
+    #[serde(default)]
+    pub dirs_skipped: u64,
+    /// Projects whose lockfile and dependency fields were unchanged, so the
+    /// lockfile was not parsed again
+    #[serde(default)]
+    pub lockfiles_reused: u64,
+    /// Time spent reading and parsing manifests and lockfiles, summed over threads
+    #[serde(default)]
+    pub parse_ms: u64,
+    /// Packages whose size a lazy scan left to planning
+    #[serde(default)]
+    pub sizes_deferred: u64,
+    /// Directories not entered because a scan rule skips them
+    #[serde(default)]
+    pub dirs_pruned: u64,
+    /// The manifests and lockfiles that took longest to parse
+    #[serde(default)]
+    pub slowest_files: Vec<FileTiming>,
+}
+
+/// Read and parse time of one manifest or lockfile during a scan
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTiming {
+    pub path: String,
+    pub micros: u64,
+    pub bytes: u64,
+}
+
+/// Conditions that make replacing a package with a store symlink unsafe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupBlocker {
+    /// Package ships compiled native addons tied to its original location
+    NativeBuild,
+    /// Package and global store live on different filesystems (hard links impossible)
+    CrossDevice,
+    /// A running process holds files open inside the package
+    InUse,
+    /// Package content diverges from its lockfile integrity hash
+    IntegrityMismatch,
+    /// Package is patched locally (patch-package or pnpm patchedDependencies)
+    Patched,
+    /// Tree links to packages inside its own project (workspaces, `file:` dependencies)
+    LocalLink,
+    /// Package or store lies across the WSL/Windows boundary, where links do not work
+    WslBoundary,
+}
+
+/// A signal that went into a plan item's risk score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskSignal {
+    /// The ML model was unsure the package is unused
+    MlConfidence,
+    /// Projects reach the item through their dependencies
+    Reachability,
+    /// The most recently worked on of those projects
+    Activity,
+    /// One of them has uncommitted changes
+    GitDirty,
+    /// A running process holds files open inside the item
+    InUse,
+    /// Size to download again, and whether a lockfile pins what comes back
+    ReinstallCost,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RiskFactor {
+    pub signal: RiskSignal,
+    /// Points this signal adds to the score
+    pub points: u8,
+    pub detail: String,
+}
+
+/// How risky removing a plan item is, 0 (safe) to 100, with the signals
+/// that contributed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RiskScore {
+    pub score: u8,
+    pub factors: Vec<RiskFactor>,
+}
+
+/// How recently a project was worked on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityClass {
+    Active,
+    Dormant,
+    Dead,
+}
+
+/// Why a path appears in a plan, with the data that led to the decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlanReason {
+    /// No scanned project depends on this name@version
+    Orphaned,
+    /// Not modified for `days` days
+    Old { days: i64 },
+    /// Redundant copy of the package at `canonical`
+    Duplicate { canonical: String },
+    /// Predictor expects the package to stay unused
+    MlPredicted { confidence: f64 },
+    /// LRU cache exceeded its byte budget
+    SizePressure { budget: u64 },
+    /// Content can be regenerated by the owning tool (build output, download cache)
+    Regenerable { kind: String },
+    /// ML model not used within the model retention window
+    StaleModel { idle_days: i64 },
+    /// Old package of a project whose remote repository is archived or has
+    /// not been pushed to within the preservation window
+    DormantRepository { remote: String, archived: bool },
+    /// `node_modules` of a project whose activity class the retention rules
+    /// purge; `idle_days` since its last recorded activity
+    InactiveProject { activity: ActivityClass, idle_days: Option<i64> },
+    /// Cached or stored version older than the newest `keep` of its package;
+    /// `newest` is the newest version kept
+    OlderVersion { keep: usize, newest: String },
+    /// Cargo build output of `profile` (`target/debug`, ...) not rebuilt for `days` days
+    StaleBuildOutput { profile: String, days: i64 },
+    /// Crate extracted into the Cargo registry that no scanned `Cargo.lock`
+    /// uses (`locked` false), or one not touched for `days` days
+    RegistryCrate { locked: bool, days: i64 },
+    /// Virtualenv, `.tox`, `__pycache__` or pip/Poetry HTTP cache (`kind`)
+    /// not written to for `days` days
+    StalePythonArtifact { kind: String, days: i64 },
+    /// Wheel or sdist in the pip or Poetry cache that no scanned lock or
+    /// requirements file pins (`locked` false), or one not touched for `days` days
+    CachedDistribution { locked: bool, days: i64 },
+    /// Module version in the Go module cache that no scanned `go.sum` lists,
+    /// extracted `days` days ago
+    UnusedGoModule { days: i64 },
+    /// Duplicate that symlinking would replace
+    DuplicateSymlinkCandidate { canonical: String },
+    /// Symlink dry-run: duplicate becomes a symlink into the store
+    SymlinkToStore,
+    /// Symlink dry-run: duplicate seeds the store via hard links
+    MoveToStore,
+    /// Symlink dry-run (hardlink mode): duplicate's files become hard links into the store
+    HardlinkToStore,
+    /// Symlink dry-run: duplicate cannot be deduplicated (see blockers)
+    SymlinkBlocked,
+}
+
+impl PlanReason {
+    /// Whether the item's estimate is the package's size; duplicates and
+    /// store-seeding or blocked entries are estimated at 0 by design
+    pub fn counts_size(&self) -> bool {
+        !matches!(
+            self,
+            PlanReason::Duplicate { .. }
+                | PlanReason::DuplicateSymlinkCandidate { .. }
+                | PlanReason::MoveToStore
+                | PlanReason::SymlinkBlocked
+        )
+    }
+
+    /// Legacy flat label, as emitted before reasons carried data
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlanReason::Orphaned => "orphaned",
+            PlanReason::Old { .. } => "old",
+            PlanReason::Duplicate { .. } => "duplicate",
+            PlanReason::MlPredicted { .. } => "ml_predicted_unused",
+            PlanReason::SizePressure { .. } => "size_pressure",
+            PlanReason::Regenerable { .. } => "regenerable",
+            PlanReason::StaleModel { .. } => "stale_model",
+            PlanReason::DormantRepository { .. } => "dormant_repository",
+            PlanReason::InactiveProject { .. } => "inactive_project",
+            PlanReason::OlderVersion { .. } => "older_version",
+            PlanReason::StaleBuildOutput { .. } => "stale_build_output",
+            PlanReason::RegistryCrate { .. } => "registry_crate",
+            PlanReason::StalePythonArtifact { .. } => "stale_python_artifact",
+            PlanReason::CachedDistribution { .. } => "cached_distribution",
+            PlanReason::UnusedGoModule { .. } => "unused_go_module",
+            PlanReason::DuplicateSymlinkCandidate { .. } => "duplicate_symlink_candidate",
+            PlanReason::SymlinkToStore => "symlink_to_store",
+            PlanReason::MoveToStore => "move_to_store",
+            PlanReason::HardlinkToStore => "hardlink_to_store",
+            PlanReason::SymlinkBlocked => "symlink_blocked",
+        }
+    }
+
+    /// Rebuild a reason from a legacy label (structured data is lost)
+    pub fn from_label(label: &str) -> Option<Self> {
+        Some(match label {
+            "orphaned" => PlanReason::Orphaned,
+            "old" => PlanReason::Old { days: 0 },
+            "duplicate" => PlanReason::Duplicate { canonical: String::new() },
+            "ml_predicted_unused" => PlanReason::MlPredicted { confidence: 0.0 },
+            "size_pressure" => PlanReason::SizePressure { budget: 0 },
+            "regenerable" => PlanReason::Regenerable { kind: String::new() },
+            "stale_model" => PlanReason::StaleModel { idle_days: 0 },
+            "dormant_repository" => PlanReason::DormantRepository { remote: String::new(), archived: false },
+            "inactive_project" => PlanReason::InactiveProject { activity: ActivityClass::Dead, idle_days: None },
+            "older_version" => PlanReason::OlderVersion { keep: 0, newest: String::new() },
+            "stale_build_output" => PlanReason::StaleBuildOutput { profile: String::new(), days: 0 },
+            "registry_crate" => PlanReason::RegistryCrate { locked: false, days: 0 },
+            "stale_python_artifact" => PlanReason::StalePythonArtifact { kind: String::new(), days: 0 },
+            "cached_distribution" => PlanReason::CachedDistribution { locked: false, days: 0 },
+            "unused_go_module" => PlanReason::UnusedGoModule { days: 0 },
+            "duplicate_symlink_candidate" => PlanReason::DuplicateSymlinkCandidate { canonical: String::new() },
+            "symlink_to_store" => PlanReason::SymlinkToStore,
+            "move_to_store" => PlanReason::MoveToStore,
+            "hardlink_to_store" => PlanReason::HardlinkToStore,
+            "symlink_blocked" => PlanReason::SymlinkBlocked,
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for PlanReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "PlanItemRepr", try_from = "PlanItemRepr")]
 pub struct PlanItem {
     pub target_path: String,
     pub estimated_size_bytes: u64,
-    pub reason: String,
+    pub reason: PlanReason,
+    pub blockers: Vec<DedupBlocker>,
+    /// Set by `risk::score_plan`
+    pub risk: Option<RiskScore>,
+}
+
+/// Wire format of `PlanItem`: `reason` stays the legacy string label for
+/// existing consumers, `reason_detail` carries the structured reason
+#[derive(Serialize, Deserialize)]
+struct PlanItemRepr {
+    target_path: String,
+    estimated_size_bytes: u64,
+    reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason_detail: Option<PlanReason>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    blockers: Vec<DedupBlocker>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    risk: Option<RiskScore>,
+}
+
+impl From<PlanItem> for PlanItemRepr {
+    fn from(item: PlanItem) -> Self {
+        Self {
+            target_path: item.target_path,
+            estimated_size_bytes: item.estimated_size_bytes,
+            reason: item.reason.label().to_string(),
+            reason_detail: Some(item.reason),
+            blockers: item.blockers,
+            risk: item.risk,
+        }
+    }
+}
+
+impl TryFrom<PlanItemRepr> for PlanItem {
+    type Error = String;
+
+    fn try_from(repr: PlanItemRepr) -> Result<Self, Self::Error> {
+        let reason = match repr.reason_detail {
+            Some(r) => r,
+            None => PlanReason::from_label(&repr.reason)
+                .ok_or_else(|| format!("unknown plan reason `{}`", repr.reason))?,
+        };
+        Ok(Self {
+            target_path: repr.target_path,
+            estimated_size_bytes: repr.estimated_size_bytes,
+            reason,
+            blockers: repr.blockers,
+            risk: repr.risk,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DryRunReport {
     pub items: Vec<PlanItem>,
     pub total_estimated_bytes: u64,
+    /// LRU cache telemetry of the planning run (optimize only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lru: Option<LruStats>,
+    /// Items held back from the plan, and other findings needing a look
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<PlanWarning>,
+    /// Targets `apply` asks about before quarantining (see `wide_scan`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub confirm: Vec<String>,
+    /// Files and directories the items counting toward the total would free
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_inodes: Option<u64>,
+    /// Statistics of the eviction policy packages were replayed through
+    /// (optimize `--eviction-policy` only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eviction: Option<PolicyStats>,
+}
+
+/// A path the plan has a reservation about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanWarning {
+    pub target_path: String,
+    pub message: String,
+}
+
+/// Estimated against reclaimed bytes of one applied plan item
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemSavings {
+    pub target_path: String,
+    pub estimated_bytes: u64,
+    pub actual_bytes: u64,
+}
+
+/// Bytes a plan said it would free against what applying it freed, over
+/// the items whose estimate is their size
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyReconciliation {
+    pub items: usize,
+    pub estimated_bytes: u64,
+    pub actual_bytes: u64,
+    /// `actual_bytes / estimated_bytes` (None when nothing was estimated)
+    pub accuracy: Option<f64>,
+    /// Items whose estimate was furthest off, worst first
+    pub largest_misses: Vec<ItemSavings>,
+}
+
+/// Estimate accuracy over recent apply runs, for `stats`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApplyAccuracy {
+    pub runs: usize,
+    pub estimated_bytes: u64,
+    pub actual_bytes: u64,
+    pub accuracy: Option<f64>,
+    /// Accuracy of the most recent run
+    pub last_accuracy: Option<f64>,
+}
+
+/// Hit, miss and eviction counters of the package LRU cache against its limits,
+/// for tuning `lru_max_packages` and `lru_max_size_bytes`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LruStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries evicted because the cache held `max_packages` entries
+    pub evictions: u64,
+    /// Entries evicted to get back under `budget_bytes`
+    pub size_evictions: u64,
+    pub entries: usize,
+    pub max_packages: usize,
+    pub current_bytes: u64,
+    pub budget_bytes: u64,
+    pub hit_rate: f64,
+}
+
+/// Statistics every eviction policy reports, so policies can be compared
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PolicyStats {
+    pub policy: PolicyKind,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    /// Keys the policy holds at most (None: unbounded)
+    pub capacity: Option<usize>,
+    pub hit_rate: f64,
+    /// Occupancy of each internal list or segment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<SegmentStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentStats {
+    pub name: String,
+    pub entries: usize,
+    pub capacity: Option<usize>,
+}
+
+/// A package as one scan found it, kept to rank packages and measure their
+/// growth without scanning again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageSnapshot {
+    pub path: String,
+    pub name: String,
+    pub version: String,
+    pub size_bytes: u64,
+    pub atime: DateTime<Utc>,
+}
+
+impl From<&PackageRecord> for PackageSnapshot {
+    fn from(package: &PackageRecord) -> Self {
+        Self {
+            path: package.path.clone(),
+            name: package.name.clone(),
+            version: package.version.clone(),
+            size_bytes: package.size_bytes,
+            atime: package.atime,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +555,56 @@ pub struct QuarantineRecord {
     pub sha256: String,
     pub size_bytes: u64,
     pub created_at: DateTime<Utc>,
+    /// Bytes hardlinked against identical files already held in quarantine
+    #[serde(default)]
+    pub shared_bytes: u64,
+    /// When the entry becomes eligible for automatic deletion (None = keep forever)
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The tree was read-only (as Go leaves module cache entries) and was
+    /// opened up to move it; rollback makes it read-only again
+    #[serde(default)]
+    pub read_only: bool,
+    /// Size of the `.tar.zst` the tree was packed into (None = kept as a directory)
+    #[serde(default)]
+    pub packed_bytes: Option<u64>,
+}
+
+#[cfg(test)]
+impl QuarantineRecord {
+    /// A record of `original_path` quarantined just now, for tests; other
+    /// fields are set with struct update syntax
+    pub(crate) fn for_test(id: &str, original_path: impl AsRef<std::path::Path>, size_bytes: u64) -> Self {
+        Self {
+            id: id.into(),
+            original_path: original_path.as_ref().to_string_lossy().to_string(),
+            quarantine_path: String::new(),
+            sha256: String::new(),
+            size_bytes,
+            created_at: Utc::now(),
+            shared_bytes: 0,
+            expires_at: None,
+            read_only: false,
+            packed_bytes: None,
+        }
+    }
+}
+
+/// A project's build artifacts packed into cold storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveRecord {
+    pub id: i64,
+    pub project_path: String,
+    /// Local file or `s3://bucket/key` of the tar.gz
+    pub location: String,
+    /// Directories packed, relative to the project
+    pub dirs: Vec<String>,
+    /// Bytes the directories occupied before archiving
+    pub size_bytes: u64,
+    pub archive_bytes: u64,
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+    pub restored_at: Option<DateTime<Utc>>,
 }
 
 /// Usage metrics for a package
@@ -90,9 +643,41 @@ pub struct ProjectMetadata {
 }
 
 /// Developer behavior metrics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DeveloperBehavior {
     pub npm_commands_executed: Vec<(String, DateTime<Utc>)>, // (command, timestamp)
     pub file_access_frequency: u64,
     pub days_since_last_build: Option<i64>,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_item_wire_format() {
+        let item = PlanItem {
+            target_path: "/p/node_modules/a".into(),
+            estimated_size_bytes: 10,
+            reason: PlanReason::Old { days: 120 },
+            blockers: Vec::new(),
+            risk: None,
+        };
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["reason"], "old");
+        assert_eq!(json["reason_detail"]["type"], "old");
+        assert_eq!(json["reason_detail"]["days"], 120);
+
+        let back: PlanItem = serde_json::from_value(json).unwrap();
+        assert_eq!(back.reason, PlanReason::Old { days: 120 });
+    }
+
+    #[test]
+    fn test_plan_item_legacy_reason() {
+        let legacy = r#"{"target_path":"/x","estimated_size_bytes":0,"reason":"orphaned"}"#;
+        let item: PlanItem = serde_json::from_str(legacy).unwrap();
+        assert_eq!(item.reason, PlanReason::Orphaned);
+
+        let bad = r#"{"target_path":"/x","estimated_size_bytes":0,"reason":"mystery"}"#;
+        assert!(serde_json::from_str::<PlanItem>(bad).is_err());
+    }
+}
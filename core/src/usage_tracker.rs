@@ -20,8 +20,7 @@ pub struct UsageTracker {
 impl UsageTracker {
     /// Default path for usage metrics cache
     pub fn default_cache_path() -> PathBuf {
-        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-        home.join(".packagepurge").join("usage_metrics.json")
+        crate::paths::state_dir().join("usage_metrics.json")
     }
 
     pub fn new(cache_path: PathBuf, max_packages: usize, max_size_bytes: u64) -> Result<Self> {
@@ -145,12 +144,12 @@ fn extract_tools_from_script(script: &str) -> Vec<String> {
     
     // Split by common command separators: &&, ||, ;, |
     let parts: Vec<&str> = script
-        .split(|c| c == '&' || c == '|' || c == ';')
+        .split(['&', '|', ';'])
         .filter(|s| !s.is_empty())
         .collect();
     
     for part in parts {
-        let words: Vec<&str> = part.trim().split_whitespace().collect();
+        let words: Vec<&str> = part.split_whitespace().collect();
         if words.is_empty() {
             continue;
         }
@@ -0,0 +1,129 @@
+//! Post-Deduplication Verification
+//!
+//! Runs a user-supplied smoke check inside a project after its packages were
+//! replaced with store symlinks. Commands run through the platform shell with
+//! the project directory as working directory. A `{name}` placeholder expands
+//! the command once per deduplicated package, e.g.
+//! `node -e "require('{name}')"`; without it the command runs once per project.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::Error;
+
+/// Result of verifying a single project
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyOutcome {
+    pub project_path: String,
+    pub passed: bool,
+    /// Expanded command that failed (if any)
+    pub failed_command: Option<String>,
+    /// Captured stderr of the failing command
+    pub stderr: Option<String>,
+}
+
+/// Locate the project owning a package: the parent of the first `node_modules`
+/// component in the package path
+pub fn owning_project(package_path: &Path) -> Option<PathBuf> {
+    let mut project = PathBuf::new();
+    for comp in package_path.components() {
+        if comp.as_os_str() == "node_modules" {
+            return Some(project);
+        }
+        project.push(comp);
+    }
+    None
+}
+
+pub(crate) fn shell_command(cmd: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut c = Command::new("cmd");
+        c.args(["/C", cmd]);
+        c
+    }
+
+    #[cfg(not(windows))]
+    {
+        let mut c = Command::new("sh");
+        c.args(["-c", cmd]);
+        c
+    }
+}
+
+/// Expand the verification command for the given packages
+pub fn expand_commands(template: &str, package_names: &[String]) -> Vec<String> {
+    if template.contains("{name}") {
+        package_names.iter().map(|n| template.replace("{name}", n)).collect()
+    } else {
+        vec![template.to_string()]
+    }
+}
+
+/// Run the verification command(s) in the project directory.
+/// Stops at the first failing command.
+pub fn verify_project(project_dir: &Path, template: &str, package_names: &[String]) -> crate::Result<VerifyOutcome> {
+    verify_project_impl(project_dir, template, package_names).map_err(Error::lift(Error::Store))
+}
+
+fn verify_project_impl(project_dir: &Path, template: &str, package_names: &[String]) -> Result<VerifyOutcome> {
+    for cmd in expand_commands(template, package_names) {
+        let output = shell_command(&cmd)
+            .current_dir(project_dir)
+            .output()
+            .with_context(|| format!("Failed to spawn verification command `{}`", cmd))?;
+
+        if !output.status.success() {
+            return Ok(VerifyOutcome {
+                project_path: project_dir.to_string_lossy().to_string(),
+                passed: false,
+                failed_command: Some(cmd),
+                stderr: Some(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+            });
+        }
+    }
+
+    Ok(VerifyOutcome {
+        project_path: project_dir.to_string_lossy().to_string(),
+        passed: true,
+        failed_command: None,
+        stderr: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_owning_project() {
+        let pkg = Path::new("/work/app/node_modules/a/node_modules/b");
+        assert_eq!(owning_project(pkg), Some(PathBuf::from("/work/app")));
+        assert_eq!(owning_project(Path::new("/work/app/src")), None);
+    }
+
+    #[test]
+    fn test_expand_commands() {
+        let names = vec!["react".to_string(), "lodash".to_string()];
+        assert_eq!(expand_commands("npm ls", &names), vec!["npm ls"]);
+        assert_eq!(
+            expand_commands("node -e \"require('{name}')\"", &names),
+            vec!["node -e \"require('react')\"", "node -e \"require('lodash')\""]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_project_failure() {
+        let temp = tempdir().unwrap();
+        let ok = verify_project(temp.path(), "true", &[]).unwrap();
+        assert!(ok.passed);
+
+        let bad = verify_project(temp.path(), "exit 3", &[]).unwrap();
+        assert!(!bad.passed);
+        assert_eq!(bad.failed_command.as_deref(), Some("exit 3"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Version Retention
+//!
+//! Registry caches and the global store bloat by accumulating versions:
+//! every upgrade leaves the previous release behind, however recently it
+//! was used. This rule keeps only the newest N versions of each package
+//! found outside `node_modules` (package manager caches, provider caches,
+//! the store) and plans every copy of the older versions for quarantine,
+//! whatever their age.
+//!
+//! Settings live in `version_retention.json` in the config directory:
+//!
+//! ```json
+//! { "keep": 3, "rules": [{ "pattern": "@types/*", "keep": 1 }, { "pattern": "typescript", "keep": 5 }] }
+//! ```
+//!
+//! The first rule whose glob matches a package name applies, then `keep`;
+//! a package neither covers is left alone, and by default nothing is.
+//! Versions a scanned project depends on are kept even when older, since
+//! store entries are linked into its `node_modules`.
+
+use anyhow::{Context, Result};
+use globset::Glob;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::types::{DryRunReport, PackageRecord, PlanItem, PlanReason, ScanOutput};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRule {
+    /// Glob over package names, e.g. `@types/*`
+    pub pattern: String,
+    pub keep: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRetentionConfig {
+    /// Versions kept of packages no rule matches (`None`: no limit)
+    #[serde(default)]
+    pub keep: Option<usize>,
+    #[serde(default)]
+    pub rules: Vec<VersionRule>,
+}
+
+impl VersionRetentionConfig {
+    /// Versions kept of `name`, if it is limited
+    pub fn keep_for(&self, name: &str) -> Option<usize> {
+        self.rules.iter()
+            .find(|r| Glob::new(&r.pattern).is_ok_and(|g| g.compile_matcher().is_match(name)))
+            .map(|r| r.keep)
+            .or(self.keep)
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.keep.is_some() || !self.rules.is_empty()
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    crate::paths::config_dir().join("version_retention.json")
+}
+
+pub fn load_config() -> VersionRetentionConfig {
+    fs::read_to_string(config_path()).ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(config: &VersionRetentionConfig) -> crate::Result<()> {
+    save_config_impl(config).map_err(Error::lift(Error::Config))
+}
+
+fn save_config_impl(config: &VersionRetentionConfig) -> Result<()> {
+    for rule in &config.rules {
+        Glob::new(&rule.pattern).with_context(|| format!("Invalid package pattern {:?}", rule.pattern))?;
+        anyhow::ensure!(rule.keep > 0, "Rule {:?} must keep at least one version", rule.pattern);
+    }
+    anyhow::ensure!(config.keep != Some(0), "keep must be at least one version");
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(config)?)
+        .with_context(|| format!("Failed to save version retention settings to {:?}", path))
+}
+
+/// Order versions semver-style: numeric components compared as numbers,
+/// a pre-release before its release; anything unparsable sorts by text
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(v: &str) -> (Vec<Option<u64>>, Option<&str>) {
+        let v = v.trim_start_matches('v').split('+').next().unwrap_or(v);
+        let (core, pre) = match v.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (v, None),
+        };
+        (core.split('.').map(|n| n.parse().ok()).collect(), pre)
+    }
+    let ((a_core, a_pre), (b_core, b_pre)) = (split(a), split(b));
+    a_core.cmp(&b_core)
+        .then_with(|| match (a_pre, b_pre) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(x), Some(y)) => x.cmp(y),
+        })
+        .then_with(|| a.cmp(b))
+}
+
+fn in_node_modules(path: &Path) -> bool {
+    path.components().any(|c| c.as_os_str() == "node_modules")
+}
+
+/// Plan the copies of every version beyond the newest `keep` of each
+/// package outside `node_modules`, replacing any other item for them.
+/// Returns the number of items added.
+pub fn apply_retention(report: &mut DryRunReport, scan: &ScanOutput, config: &VersionRetentionConfig) -> usize {
+    if !config.is_enabled() {
+        return 0;
+    }
+    let used: HashSet<(&str, &str)> = scan.projects.iter()
+        .flat_map(|p| p.dependencies.iter().map(|(n, v)| (n.as_str(), v.as_str())))
+        .collect();
+
+    let mut by_name: BTreeMap<&str, BTreeMap<&str, Vec<&PackageRecord>>> = BTreeMap::new();
+    for pkg in scan.packages.iter().filter(|p| !in_node_modules(Path::new(&p.path))) {
+        by_name.entry(pkg.name.as_str()).or_default().entry(pkg.version.as_str()).or_default().push(pkg);
+    }
+
+    let mut evicted: Vec<PlanItem> = Vec::new();
+    for (name, versions) in by_name {
+        let Some(keep) = config.keep_for(name) else { continue };
+        let mut ordered: Vec<&str> = versions.keys().copied().collect();
+        ordered.sort_by(|a, b| compare_versions(b, a));
+        let Some(newest) = ordered.first().map(|v| v.to_string()) else { continue };
+        for version in ordered.into_iter().skip(keep).filter(|v| !used.contains(&(name, *v))) {
+            for pkg in &versions[version] {
+                evicted.push(PlanItem {
+                    target_path: pkg.path.clone(),
+                    estimated_size_bytes: pkg.size_bytes,
+                    reason: PlanReason::OlderVersion { keep, newest: newest.clone() },
+                    blockers: Vec::new(),
+                    risk: None,
+                });
+            }
+        }
+    }
+
+    let targets: HashSet<&str> = evicted.iter().map(|i| i.target_path.as_str()).collect();
+    report.items.retain(|item| !targets.contains(item.target_path.as_str()));
+    let added = evicted.len();
+    report.items.extend(evicted);
+    report.total_estimated_bytes = report.items.iter().map(|i| i.estimated_size_bytes).sum();
+    added
+}
+
+/// `apply_retention` with the saved settings
+pub fn apply_configured_retention(report: &mut DryRunReport, scan: &ScanOutput) -> usize {
+    apply_retention(report, scan, &load_config())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProjectRecord;
+    use chrono::Utc;
+
+    fn package(path: &str, name: &str, version: &str) -> PackageRecord {
+        PackageRecord { name: name.to_string(), ..PackageRecord::for_test(path, version, 10) }
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        let mut versions = vec!["1.10.0", "1.9.2", "2.0.0-rc.1", "2.0.0", "1.9.10"];
+        versions.sort_by(|a, b| compare_versions(b, a));
+        assert_eq!(versions, ["2.0.0", "2.0.0-rc.1", "1.10.0", "1.9.10", "1.9.2"]);
+    }
+
+    #[test]
+    fn test_keeps_newest_versions_per_pattern() {
+        let scan = ScanOutput {
+            packages: vec![
+                package("/c/lodash/4.17.21", "lodash", "4.17.21"),
+                package("/c/lodash/4.17.20", "lodash", "4.17.20"),
+                package("/store/lodash@4.17.20", "lodash", "4.17.20"),
+                package("/c/lodash/4.9.0", "lodash", "4.9.0"),
+                package("/c/lodash/3.10.1", "lodash", "3.10.1"),
+                package("/c/@types/node/20.1.0", "@types/node", "20.1.0"),
+                package("/c/@types/node/18.0.0", "@types/node", "18.0.0"),
+                package("/app/node_modules/lodash", "lodash", "1.0.0"),
+            ],
+            projects: vec![ProjectRecord {
+                path: "/app".into(),
+                manager: None,
+                dependencies: vec![("lodash".into(), "3.10.1".into())],
+                mtime: Utc::now(),
+            }],
+            edges: Vec::new(),
+            stats: Default::default(),
+            deferred_sizes: Vec::new(),
+            marked_dirs: Vec::new(),
+        };
+        let config = VersionRetentionConfig {
+            keep: Some(1),
+            rules: vec![VersionRule { pattern: "@types/*".into(), keep: 2 }],
+        };
+        let mut report = DryRunReport {
+            items: vec![PlanItem {
+                target_path: "/c/lodash/4.9.0".into(),
+                estimated_size_bytes: 10,
+                reason: PlanReason::Old { days: 400 },
+                blockers: Vec::new(),
+                risk: None,
+            }],
+            total_estimated_bytes: 10,
+            lru: None,
+            warnings: Vec::new(),
+            confirm: Vec::new(),
+            estimated_inodes: None,
+            eviction: None,
+        };
+
+        // Both copies of 4.17.20 and 4.9.0 go; 3.10.1 is in use, @types/node keeps two
+        assert_eq!(apply_retention(&mut report, &scan, &config), 3);
+        let targets: Vec<&str> = report.items.iter().map(|i| i.target_path.as_str()).collect();
+        assert_eq!(targets, ["/c/lodash/4.17.20", "/store/lodash@4.17.20", "/c/lodash/4.9.0"]);
+        assert!(matches!(&report.items[2].reason, PlanReason::OlderVersion { keep: 1, newest } if newest == "4.17.21"));
+        assert_eq!(report.total_estimated_bytes, 30);
+
+        let mut untouched = report.clone();
+        assert_eq!(apply_retention(&mut untouched, &scan, &VersionRetentionConfig::default()), 0);
+    }
+}
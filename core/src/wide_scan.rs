@@ -0,0 +1,98 @@
+//! Whole-Home and Whole-Disk Scans
+//!
+//! `~` and `/` are the roots people actually pass, and they hold far more
+//! than projects. A scan rooted at either skips the `wide_skip` scan rules,
+//! warns up front how long it is likely to take (the last scan over the
+//! same roots, when there is one), and the plans made from it list every
+//! item outside recognized package and cache directories in `confirm`, so
+//! `apply` asks before quarantining those.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::cache_markers::marker;
+use crate::feature_store::FeatureStore;
+use crate::provider_caches::in_provider_cache;
+use crate::scanner::is_cache_dir;
+use crate::types::DryRunReport;
+
+/// Whether `root` is the home directory or a filesystem root
+pub fn is_wide_root(root: &Path, home: Option<&Path>) -> bool {
+    root.parent().is_none() || home.is_some_and(|h| h == root)
+}
+
+/// The roots a scan of `paths` walks that are `~` or `/` (the working
+/// directory when `paths` is empty)
+pub fn wide_roots(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let home = dirs::home_dir().and_then(|h| h.canonicalize().ok());
+    let roots = if paths.is_empty() { std::env::current_dir().into_iter().collect() } else { paths.to_vec() };
+    roots.into_iter()
+        .map(|p| p.canonicalize().unwrap_or(p))
+        .filter(|p| is_wide_root(p, home.as_deref()))
+        .collect()
+}
+
+/// Warning printed before walking wide `roots`
+pub fn duration_notice(roots: &[PathBuf], last_scan_ms: Option<u64>) -> String {
+    let roots: Vec<String> = roots.iter().map(|r| r.display().to_string()).collect();
+    let expected = match last_scan_ms {
+        Some(ms) => format!("the last scan of it took {}s", ms.div_ceil(1000)),
+        None => "a first scan of it can take several minutes".to_string(),
+    };
+    format!(
+        "Scanning all of {}: mail, photos, browser profiles and system trees are skipped, and {} (Ctrl-C cancels)",
+        roots.join(", "), expected,
+    )
+}
+
+/// `duration_notice` with the time of the last scan over `roots`
+pub fn notice(roots: &[PathBuf]) -> String {
+    let last = FeatureStore::open_default().ok().and_then(|db| db.last_scan_ms(roots).ok().flatten());
+    duration_notice(roots, last)
+}
+
+/// Whether `path` lies in a `node_modules`, a package manager cache, a
+/// provider cache or a marked cache, the directories a package scan finds
+pub fn is_recognized(path: &Path) -> bool {
+    path.components().any(|c| c == Component::Normal("node_modules".as_ref()))
+        || path.ancestors().any(is_cache_dir)
+        || in_provider_cache(path)
+        || path.ancestors().any(|a| marker(a).is_some())
+}
+
+/// List the items of a plan made from a scan of `paths` that `apply`
+/// must confirm: none unless a root is `~` or `/`, then every item outside
+/// recognized directories. Returns how many were listed.
+pub fn flag_unrecognized(report: &mut DryRunReport, paths: &[PathBuf]) -> usize {
+    if wide_roots(paths).is_empty() {
+        return 0;
+    }
+    report.confirm = report.items.iter()
+        .filter(|item| !is_recognized(Path::new(&item.target_path)))
+        .map(|item| item.target_path.clone())
+        .collect();
+    report.confirm.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wide_roots_and_recognized_targets() {
+        let home = Path::new("/home/ada");
+        assert!(is_wide_root(Path::new("/"), Some(home)));
+        assert!(is_wide_root(home, Some(home)));
+        assert!(!is_wide_root(Path::new("/home/ada/dev"), Some(home)));
+        assert!(!is_wide_root(Path::new("/home"), None));
+
+        assert!(is_recognized(Path::new("/home/ada/dev/app/node_modules/lodash")));
+        assert!(is_recognized(Path::new("/home/ada/.npm/_cacache/content-v2")));
+        assert!(is_recognized(Path::new("/home/ada/.local/share/pnpm/store/v3")));
+        assert!(!is_recognized(Path::new("/home/ada/Documents/taxes")));
+        assert!(!is_recognized(Path::new("/home/ada/.config/Code/CachedData")));
+        assert!(!is_recognized(Path::new("/home/ada/dev/app/node_modules_backup")));
+
+        let notice = duration_notice(&[PathBuf::from("/home/ada")], Some(61_500));
+        assert!(notice.contains("/home/ada") && notice.contains("62s"));
+    }
+}
@@ -0,0 +1,352 @@
+//! WSL and Windows Across the Boundary
+//!
+//! Developers on Windows often keep projects on both sides: some on the
+//! Windows drives, some inside a WSL distribution. Each side sees the other
+//! through a bridge:
+//!
+//! - inside WSL, the Windows drives are drvfs mounts (`/mnt/c`, served over 9p
+//!   on WSL 2), listed in `/proc/mounts`
+//! - on Windows, each distribution's filesystem is the `\\wsl.localhost\<distro>`
+//!   share (`\\wsl$\<distro>` on older builds); `wsl.exe --list` names them
+//!
+//! `wsl scan` scans the local roots and the other side's home directories in
+//! one go and reports packages per side, each with its path as seen from the
+//! other side. Links do not survive the bridge: a symlink created over 9p or
+//! the `\\wsl$` share is either refused or dangles on the other side, so
+//! deduplication never links packages there (`DedupBlocker::WslBoundary`).
+//! Quarantining works as usual; moves across the bridge copy.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::types::ScanOutput;
+
+/// A side of the boundary
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Side {
+    Windows,
+    Wsl { distro: String },
+}
+
+/// A Windows drive mounted inside WSL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DrvfsMount {
+    pub mount_point: PathBuf,
+    /// Drive as Windows names it, e.g. `C:`
+    pub drive: String,
+}
+
+/// Where this process runs and what it can see of the other side
+#[derive(Debug, Clone, Serialize)]
+pub struct WslEnvironment {
+    /// None when neither on Windows nor inside WSL
+    pub side: Option<Side>,
+    /// Windows drives (inside WSL)
+    pub windows_mounts: Vec<DrvfsMount>,
+    /// Installed distributions (on Windows)
+    pub distributions: Vec<String>,
+}
+
+impl WslEnvironment {
+    /// Home directories of the other side, where its projects usually live
+    pub fn other_side_roots(&self) -> Vec<PathBuf> {
+        match &self.side {
+            Some(Side::Wsl { .. }) => self.windows_mounts.iter()
+                .map(|m| m.mount_point.join("Users"))
+                .filter(|p| p.is_dir())
+                .collect(),
+            Some(Side::Windows) => self.distributions.iter()
+                .filter_map(|d| share_root(d))
+                .map(|p| p.join("home"))
+                .filter(|p| p.is_dir())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Which side `path` lies on, as this process sees it
+    pub fn side_of(&self, path: &Path) -> Option<Side> {
+        match self.side.as_ref()? {
+            Side::Wsl { distro } => Some(if mount_of(&self.windows_mounts, path).is_some() {
+                Side::Windows
+            } else {
+                Side::Wsl { distro: distro.clone() }
+            }),
+            Side::Windows => Some(match windows_to_linux(&path.to_string_lossy()) {
+                Some((Some(distro), _)) => Side::Wsl { distro },
+                _ => Side::Windows,
+            }),
+        }
+    }
+
+    /// `path` as the other side names it
+    pub fn translate(&self, path: &Path) -> Option<String> {
+        match self.side.as_ref()? {
+            Side::Wsl { distro } => Some(linux_to_windows(&path.to_string_lossy(), &self.windows_mounts, distro)),
+            Side::Windows => windows_to_linux(&path.to_string_lossy()).map(|(_, linux)| linux),
+        }
+    }
+}
+
+/// Detect the environment: inside WSL from `WSL_DISTRO_NAME` or the kernel
+/// release, on Windows from `wsl.exe --list`
+pub fn detect() -> WslEnvironment {
+    if cfg!(windows) {
+        let distributions = std::process::Command::new("wsl.exe")
+            .args(["--list", "--quiet"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| decode_wsl_list(&o.stdout))
+            .unwrap_or_default();
+        return WslEnvironment { side: Some(Side::Windows), windows_mounts: Vec::new(), distributions };
+    }
+    let distro = std::env::var("WSL_DISTRO_NAME").ok().filter(|d| !d.is_empty()).or_else(|| {
+        std::fs::read_to_string("/proc/sys/kernel/osrelease").ok()
+            .filter(|r| r.to_lowercase().contains("microsoft"))
+            .map(|_| "WSL".to_string())
+    });
+    match distro {
+        Some(distro) => WslEnvironment {
+            side: Some(Side::Wsl { distro }),
+            windows_mounts: drvfs_mounts().to_vec(),
+            distributions: Vec::new(),
+        },
+        None => WslEnvironment { side: None, windows_mounts: Vec::new(), distributions: Vec::new() },
+    }
+}
+
+/// Windows drives mounted in this WSL instance; read once
+fn drvfs_mounts() -> &'static [DrvfsMount] {
+    static MOUNTS: OnceLock<Vec<DrvfsMount>> = OnceLock::new();
+    MOUNTS.get_or_init(|| {
+        std::fs::read_to_string("/proc/mounts").map(|m| parse_drvfs_mounts(&m)).unwrap_or_default()
+    })
+}
+
+/// Whether `path` lies across the boundary: on a drvfs mount inside WSL, or on
+/// a distribution's share on Windows
+pub fn crosses_boundary(path: &Path) -> bool {
+    if cfg!(windows) {
+        windows_to_linux(&path.to_string_lossy()).is_some_and(|(distro, _)| distro.is_some())
+    } else {
+        mount_of(drvfs_mounts(), path).is_some()
+    }
+}
+
+fn mount_of<'a>(mounts: &'a [DrvfsMount], path: &Path) -> Option<&'a DrvfsMount> {
+    mounts.iter().filter(|m| path.starts_with(&m.mount_point)).max_by_key(|m| m.mount_point.as_os_str().len())
+}
+
+/// The share a distribution's filesystem is reachable under from Windows
+fn share_root(distro: &str) -> Option<PathBuf> {
+    [format!(r"\\wsl.localhost\{}", distro), format!(r"\\wsl$\{}", distro)]
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_dir())
+}
+
+/// `/proc/mounts` fields escape spaces and backslashes as octal (`\040`, `\134`)
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4)
+            .filter(|d| bytes[i] == b'\\' && d.iter().all(|b| (b'0'..=b'7').contains(b)))
+            .map(|d| d.iter().fold(0u32, |acc, b| acc * 8 + (b - b'0') as u32));
+        match octal {
+            Some(v) if v <= 0xff => {
+                out.push(v as u8);
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Windows drives among the entries of `/proc/mounts`: `drvfs` mounts (WSL 1)
+/// and `9p` mounts of the `drvfs` share (WSL 2)
+pub fn parse_drvfs_mounts(proc_mounts: &str) -> Vec<DrvfsMount> {
+    proc_mounts.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (source, target, fstype, options) = (fields.next()?, fields.next()?, fields.next()?, fields.next().unwrap_or(""));
+            let drvfs = fstype == "drvfs" || (fstype == "9p" && options.split([',', ';']).any(|o| o == "aname=drvfs"));
+            if !drvfs {
+                return None;
+            }
+            let source = unescape_mount_field(source);
+            let mount_point = PathBuf::from(unescape_mount_field(target));
+            let drive = match source.as_bytes() {
+                [letter, b':', ..] if letter.is_ascii_alphabetic() => format!("{}:", (*letter as char).to_ascii_uppercase()),
+                _ => format!("{}:", mount_point.file_name()?.to_str()?.to_ascii_uppercase()),
+            };
+            Some(DrvfsMount { mount_point, drive })
+        })
+        .collect()
+}
+
+/// A path inside WSL as Windows names it: drvfs paths become drive paths,
+/// everything else goes through the distribution's share
+pub fn linux_to_windows(path: &str, mounts: &[DrvfsMount], distro: &str) -> String {
+    if let Some(mount) = mount_of(mounts, Path::new(path)) {
+        let rest = Path::new(path).strip_prefix(&mount.mount_point).unwrap_or(Path::new(""));
+        let rest = rest.to_string_lossy().replace('/', "\\");
+        return format!("{}\\{}", mount.drive, rest);
+    }
+    format!(r"\\wsl.localhost\{}{}", distro, path.replace('/', "\\"))
+}
+
+/// A Windows path as WSL names it, with the distribution when it points into
+/// one: `C:\Users\a` is `/mnt/c/Users/a`, `\\wsl$\Ubuntu\home\a` is
+/// `/home/a` in Ubuntu. None for other UNC paths.
+pub fn windows_to_linux(path: &str) -> Option<(Option<String>, String)> {
+    let path = path.strip_prefix(r"\\?\UNC\").map(|p| format!(r"\\{}", p))
+        .or_else(|| path.strip_prefix(r"\\?\").map(str::to_string))
+        .unwrap_or_else(|| path.to_string());
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        let mut parts = unc.splitn(3, '\\');
+        let host = parts.next()?;
+        if !host.eq_ignore_ascii_case("wsl$") && !host.eq_ignore_ascii_case("wsl.localhost") {
+            return None;
+        }
+        let distro = parts.next().filter(|d| !d.is_empty())?;
+        let rest = parts.next().unwrap_or("");
+        return Some((Some(distro.to_string()), format!("/{}", rest.replace('\\', "/"))));
+    }
+    match path.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => {
+            let rest = path[2..].trim_start_matches(['\\', '/']).replace('\\', "/");
+            let drive = (*letter as char).to_ascii_lowercase();
+            Some((None, if rest.is_empty() { format!("/mnt/{}", drive) } else { format!("/mnt/{}/{}", drive, rest) }))
+        }
+        _ => None,
+    }
+}
+
+/// Distribution names from `wsl.exe --list --quiet`, which writes UTF-16LE
+pub fn decode_wsl_list(output: &[u8]) -> Vec<String> {
+    let units: Vec<u16> = output.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    String::from_utf16_lossy(&units)
+        .lines()
+        .map(|l| l.trim_matches(|c: char| c.is_whitespace() || c == '\u{feff}' || c == '\0').to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// A package of the unified report
+#[derive(Debug, Clone, Serialize)]
+pub struct WslPackage {
+    pub side: Side,
+    pub name: String,
+    pub version: String,
+    pub path: String,
+    /// The path as the other side names it
+    pub translated_path: Option<String>,
+    pub size_bytes: u64,
+}
+
+/// Totals of one side
+#[derive(Debug, Clone, Serialize)]
+pub struct SideSummary {
+    pub side: Side,
+    pub packages: usize,
+    pub projects: usize,
+    pub total_bytes: u64,
+    /// Whether deduplication may link packages here
+    pub links_supported: bool,
+}
+
+/// Packages of both sides in one report
+#[derive(Debug, Clone, Serialize)]
+pub struct WslReport {
+    pub environment: WslEnvironment,
+    pub roots: Vec<String>,
+    pub sides: Vec<SideSummary>,
+    pub total_bytes: u64,
+    pub packages: Vec<WslPackage>,
+}
+
+fn side_index(sides: &mut Vec<SideSummary>, side: &Side, here: &Side) -> usize {
+    if let Some(i) = sides.iter().position(|s| s.side == *side) {
+        return i;
+    }
+    sides.push(SideSummary { side: side.clone(), packages: 0, projects: 0, total_bytes: 0, links_supported: side == here });
+    sides.len() - 1
+}
+
+/// Group `scan` (of `roots`) by side; None when `env` is neither Windows nor WSL
+pub fn report(env: &WslEnvironment, roots: &[PathBuf], scan: &ScanOutput) -> Option<WslReport> {
+    let here = env.side.clone()?;
+    let mut sides: Vec<SideSummary> = Vec::new();
+    let mut packages = Vec::new();
+    for pkg in &scan.packages {
+        let path = Path::new(&pkg.path);
+        let side = env.side_of(path).unwrap_or_else(|| here.clone());
+        let i = side_index(&mut sides, &side, &here);
+        sides[i].packages += 1;
+        sides[i].total_bytes += pkg.size_bytes;
+        packages.push(WslPackage {
+            side,
+            name: pkg.name.clone(),
+            version: pkg.version.clone(),
+            path: pkg.path.clone(),
+            translated_path: env.translate(path),
+            size_bytes: pkg.size_bytes,
+        });
+    }
+    for project in &scan.projects {
+        let side = env.side_of(Path::new(&project.path)).unwrap_or_else(|| here.clone());
+        let i = side_index(&mut sides, &side, &here);
+        sides[i].projects += 1;
+    }
+    Some(WslReport {
+        environment: env.clone(),
+        roots: roots.iter().map(|r| r.to_string_lossy().to_string()).collect(),
+        total_bytes: sides.iter().map(|s| s.total_bytes).sum(),
+        sides,
+        packages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_translation() {
+        let mounts = parse_drvfs_mounts(concat!(
+            "/dev/sdc / ext4 rw,relatime 0 0\n",
+            "C:\\134 /mnt/c 9p rw,noatime,aname=drvfs;path=C:\\;uid=1000 0 0\n",
+            "drivers /usr/lib/wsl/drivers 9p ro,aname=drivers;fmask=222 0 0\n",
+            "D: /mnt/data\\040disk drvfs rw,noatime 0 0\n",
+        ));
+        assert_eq!(mounts, [
+            DrvfsMount { mount_point: PathBuf::from("/mnt/c"), drive: "C:".into() },
+            DrvfsMount { mount_point: PathBuf::from("/mnt/data disk"), drive: "D:".into() },
+        ]);
+
+        assert_eq!(linux_to_windows("/mnt/c/Users/a/app", &mounts, "Ubuntu"), r"C:\Users\a\app");
+        assert_eq!(linux_to_windows("/mnt/data disk/x", &mounts, "Ubuntu"), r"D:\x");
+        assert_eq!(linux_to_windows("/home/a/app", &mounts, "Ubuntu"), r"\\wsl.localhost\Ubuntu\home\a\app");
+
+        assert_eq!(windows_to_linux(r"C:\Users\a\app"), Some((None, "/mnt/c/Users/a/app".into())));
+        assert_eq!(windows_to_linux(r"\\wsl$\Ubuntu\home\a"), Some((Some("Ubuntu".into()), "/home/a".into())));
+        assert_eq!(windows_to_linux(r"\\?\UNC\wsl.localhost\Debian\srv"), Some((Some("Debian".into()), "/srv".into())));
+        assert_eq!(windows_to_linux(r"\\fileserver\share\x"), None);
+
+        let listed: Vec<u8> = "\u{feff}Ubuntu\r\ndocker-desktop\r\n".encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        assert_eq!(decode_wsl_list(&listed), ["Ubuntu", "docker-desktop"]);
+
+        let env = WslEnvironment { side: Some(Side::Wsl { distro: "Ubuntu".into() }), windows_mounts: mounts, distributions: Vec::new() };
+        assert_eq!(env.side_of(Path::new("/mnt/c/Users/a")), Some(Side::Windows));
+        assert_eq!(env.side_of(Path::new("/home/a")), Some(Side::Wsl { distro: "Ubuntu".into() }));
+    }
+}
@@ -1,10 +1,18 @@
 #!/usr/bin/env node
 
 import { Command } from 'commander';
+import * as fs from 'fs';
+import * as os from 'os';
+import * as path from 'path';
+import * as readline from 'readline';
 import chalk from 'chalk';
 import { logger } from '../utils/logger';
-import { runCore, runCoreStreaming, StreamProgress } from '../utils/core-utils';
-import { output, OutputFormat } from '../utils/formatter';
+import { runCore, runCoreInherit, runCoreStreaming, StreamProgress } from '../utils/core-utils';
+import { output, OutputFormat, formatBytes, formatDate } from '../utils/formatter';
+import { setDisplayOptions, displayEnv, DisplayOptions } from '../utils/display';
+import { applyTerminalOptions, ColorMode, spinnerFrames, sym } from '../utils/terminal';
+import { browseQuarantine } from '../utils/quarantine-browser';
+import { guideCloudSync } from '../utils/cloud-sync-guide';
 import { loadConfig, detectWorkspace, mergeWithCliOptions, generateExampleConfig, PackagePurgeConfig } from '../utils/config';
 
 // Load configuration early
@@ -12,18 +20,21 @@ const { config: loadedConfig, source: configSource } = loadConfig();
 
 // Enhanced spinner with progress tracking
 class Spinner {
-	private frames = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+	private frames = spinnerFrames();
 	private current = 0;
 	private interval: NodeJS.Timeout | null = null;
 	private text: string;
 	private progressCount = 0;
 	private progressType = '';
+	// Redrawing a line only works on a terminal; CI logs get the final line alone
+	private animated = process.stderr.isTTY === true;
 
 	constructor(text: string) {
 		this.text = text;
 	}
 
 	start(): void {
+		if (!this.animated) return;
 		process.stderr.write('\x1B[?25l'); // Hide cursor
 		this.interval = setInterval(() => {
 			const progressStr = this.progressCount > 0
@@ -48,12 +59,12 @@ class Spinner {
 
 	succeed(text?: string): void {
 		this.stop();
-		console.error(`\r${chalk.green('✓')} ${text || this.text}`);
+		console.error(`${chalk.green(sym('ok'))} ${text || this.text}`);
 	}
 
 	fail(text?: string): void {
 		this.stop();
-		console.error(`\r${chalk.red('✗')} ${text || this.text}`);
+		console.error(`${chalk.red(sym('fail'))} ${text || this.text}`);
 	}
 
 	private stop(): void {
@@ -61,19 +72,29 @@ class Spinner {
 			clearInterval(this.interval);
 			this.interval = null;
 		}
+		if (!this.animated) return;
 		process.stderr.write('\x1B[?25h'); // Show cursor
 		process.stderr.write('\r\x1B[K'); // Clear line
 	}
 }
 
-/**
- * Format file size for display
- */
-function formatSize(bytes: number): string {
-	if (bytes < 1024) return `${bytes} B`;
-	if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
-	if (bytes < 1024 * 1024 * 1024) return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
-	return `${(bytes / (1024 * 1024 * 1024)).toFixed(2)} GB`;
+// Scans of ~ or / walk far more than projects; say so before the wait
+function warnWideScan(paths: string[], quiet: boolean): void {
+	if (quiet) return;
+	const roots = paths.length ? paths.map((p) => path.resolve(p)) : [process.cwd()];
+	const wide = roots.filter((r) => r === os.homedir() || path.dirname(r) === r);
+	if (wide.length) {
+		logger.warn(`Scanning all of ${wide.join(', ')}: mail, photos, browser profiles and system trees are skipped, but this can take several minutes`);
+	}
+}
+
+// Yes/no question on the terminal; anything but y/yes is no
+function ask(question: string): Promise<boolean> {
+	const rl = readline.createInterface({ input: process.stdin, output: process.stderr });
+	return new Promise((resolve) => rl.question(`${question} [y/N] `, (answer) => {
+		rl.close();
+		resolve(['y', 'yes'].includes(answer.trim().toLowerCase()));
+	}));
 }
 
 const program = new Command();
@@ -83,27 +104,88 @@ program
 	.version('2.0.0')
 	.option('-q, --quiet', 'Minimal output', false)
 	.option('-v, --verbose', 'Verbose logging', false)
-	.option('-f, --format <format>', 'Output format: table|json|yaml', 'table');
+	.option('-f, --format <format>', 'Output format: table|json|yaml (scan, analyze and optimize also ndjson|csv)', 'table')
+	.option('--role <role>', 'Machine role for default policies: laptop|workstation|ci-runner|build-server')
+	.option('--config <file>', 'TOML file with default flags for the core (default: config.toml in the config directory)')
+	.option('--state-dir <dir>', 'Directory for all PackagePurge state (default: platform state/config/cache dirs)')
+	.option('--cache-dir <dir>', 'Directory for the scan cache (default: platform cache dir)')
+	.option('--db <file>', 'Feature store database file')
+	.option('--read-only', 'Refuse every quarantine, symlink, store or cache mutation', false)
+	.option('--snapshot', 'Scan and hash in read-only filesystem snapshots (Btrfs, ZFS, LVM, VSS)', false)
+	.option('--timings', 'Print the time core spent per phase (walk, lockfiles, size, plan, hash, move) to stderr', false)
+	.option('--tag <tags...>', 'Only projects carrying any of these tags, and what they install (see `purge tag`)')
+	.option('--size-units <units>', 'Sizes in binary (KiB, MiB) or si (kB, MB) units')
+	.option('--dates <style>', 'Dates as absolute or relative ("3 days ago")')
+	.option('--time-zone <zone>', 'Time zone of absolute dates: local, UTC or an IANA name')
+	.option('--locale <locale>', 'Locale of numbers, dates and report strings (en, de, es)')
+	.option('--color <when>', 'Color output: auto, always or never (auto honors NO_COLOR)')
+	.option('--ascii', 'ASCII-only symbols: no emoji, box drawing or animated spinner', false)
+	.option('--high-contrast', 'No gray or dimmed text', false);
 
 program.hook('preAction', (_, actionCommand) => {
 	const opts = actionCommand.optsWithGlobals();
 	if (opts.verbose) logger.setLevel(0);
+	const color: ColorMode = opts.color || loadedConfig.color || 'auto';
+	if (!['auto', 'always', 'never'].includes(color)) {
+		console.error(chalk.red(`Invalid --color value "${color}"; expected auto, always or never`));
+		process.exit(2);
+	}
+	applyTerminalOptions({
+		color,
+		ascii: opts.ascii || loadedConfig.ascii || false,
+		highContrast: opts.highContrast || loadedConfig.highContrast || false,
+	});
+	// Inherited by every core invocation
+	if (opts.role) process.env.PACKAGEPURGE_ROLE = opts.role;
+	if (opts.config) process.env.PACKAGEPURGE_CONFIG = opts.config;
+	if (opts.stateDir) process.env.PACKAGEPURGE_STATE_DIR = opts.stateDir;
+	if (opts.cacheDir) process.env.PACKAGEPURGE_CACHE_DIR = opts.cacheDir;
+	if (opts.db) process.env.PACKAGEPURGE_DB = opts.db;
+	if (opts.readOnly || loadedConfig.readOnly) process.env.PACKAGEPURGE_READ_ONLY = '1';
+	if (opts.snapshot) process.env.PACKAGEPURGE_SNAPSHOT = '1';
+	if (opts.timings) process.env.PACKAGEPURGE_TIMINGS = '1';
+	if (opts.tag?.length) process.env.PACKAGEPURGE_TAGS = opts.tag.join(',');
+	const display: Partial<DisplayOptions> = {
+		...loadedConfig.display,
+		...(opts.sizeUnits && { sizeUnits: opts.sizeUnits }),
+		...(opts.dates && { dates: opts.dates }),
+		...(opts.timeZone && { timeZone: opts.timeZone }),
+		...(opts.locale && { locale: opts.locale }),
+	};
+	setDisplayOptions(display);
+	Object.assign(process.env, displayEnv(display));
 });
 
+/** `--format` for core commands that render ndjson and csv themselves */
+function coreFormatArgs(format: OutputFormat): string[] {
+	return format === 'ndjson' || format === 'csv' ? ['--format', format] : [];
+}
+
 // Scan command with streaming support
 program
 	.command('scan')
 	.description('Scan filesystem and output results')
 	.option('-p, --paths <paths...>', 'Paths to scan', [])
 	.option('--no-cache', 'Disable incremental caching')
+	.option('--cache-validation <mode>', 'Cache validation: fast or thorough', 'fast')
+	.option('--lazy-sizes', 'Record packages without sizing them (sizes from the cache where known)', false)
+	.option('--stream', 'Print one JSON record per line as the scan produces them (for very large scans)', false)
 	.action(async (opts, cmd) => {
 		const g = cmd.parent?.opts?.() || {};
 		const format = (g.format || 'table') as OutputFormat;
-		const spinner = !g.quiet && format === 'table' ? new Spinner('Scanning for packages...') : null;
 
-		spinner?.start();
+		const args = ['scan', '--cache-validation', opts.cacheValidation, ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		if (opts.lazySizes && opts.cache !== false) args.push('--lazy-sizes');
+		args.push(...coreFormatArgs(format));
+
+		// Records go straight to stdout as JSON lines; nothing is held here
+		if (opts.stream) {
+			process.exit(await runCoreInherit([...args, '--stream']));
+		}
 
-		const args = ['scan', ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		const spinner = !g.quiet && format === 'table' ? new Spinner('Scanning for packages...') : null;
+		warnWideScan(opts.paths || [], !spinner);
+		spinner?.start();
 
 		// Use streaming for progress updates
 		const res = await runCoreStreaming(args, (progress: StreamProgress) => {
@@ -127,16 +209,26 @@ program
 	.command('analyze')
 	.description('Dry-run cleanup plan (no changes)')
 	.option('-p, --paths <paths...>', 'Paths to analyze', [])
-	.option('-d, --preserve-days <days>', 'Preserve days for recency', '90')
+	.option('-d, --preserve-days <days>', 'Preserve days for recency (default: machine role policy)')
+	.option('--lazy-sizes', 'Quick plan: size only the cleanup candidates', false)
+	.option('--verify-with-pm', 'Cross-check orphans with the project\'s package manager (npm ls, pnpm list, yarn info)', false)
+	.option('--from-scan <file>', 'Plan from a scan saved by `purge scan -f json` or `purge import` instead of scanning')
+	.option('--sort-by-risk', 'List the riskiest items first', false)
 	.action(async (opts, cmd) => {
 		const g = cmd.parent?.opts?.() || {};
 		const format = (g.format || 'table') as OutputFormat;
 		const spinner = !g.quiet && format === 'table' ? new Spinner('Analyzing packages...') : null;
 
+		if (!opts.fromScan) warnWideScan(opts.paths || [], !spinner);
 		spinner?.start();
 
-		const preserve = String(opts.preserveDays ?? 90);
-		const args = ['dry-run', '--preserve-days', preserve, ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		const preserve = opts.preserveDays ? ['--preserve-days', String(opts.preserveDays)] : [];
+		const args = ['dry-run', ...preserve, ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		if (opts.lazySizes) args.push('--lazy-sizes');
+		if (opts.verifyWithPm) args.push('--verify-with-pm');
+		if (opts.fromScan) args.push('--from-scan', opts.fromScan);
+		if (opts.sortByRisk) args.push('--sort-by-risk');
+		args.push(...coreFormatArgs(format));
 
 		const res = await runCoreStreaming(args, (progress: StreamProgress) => {
 			if (spinner && progress.type === 'plan_item') {
@@ -154,29 +246,53 @@ program
 		output(res.stdout, format, 'analyze');
 	});
 
+// Import command - size data collected elsewhere (ncdu, du, WizTree)
+program
+	.command('import')
+	.description('Convert an ncdu JSON export, du output or WizTree CSV into a scan for `analyze --from-scan`')
+	.argument('<file>', 'Export file, or - for stdin')
+	.option('--format <format>', 'ncdu, du or wiztree (default: detected)')
+	.option('--du-block-size <bytes>', 'Bytes per unit of unsuffixed du sizes (1 for du -b)', '1024')
+	.action(async (file: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['import', file, '--du-block-size', String(opts.duBlockSize)];
+		if (opts.format) args.push('--format', opts.format);
+		const res = file === '-' ? { code: await runCoreInherit(args), stdout: '', stderr: '' } : await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Import failed');
+			process.exit(res.code);
+		}
+		process.stdout.write(res.stdout);
+	});
+
 // Clean command (quarantine)
 program
 	.command('clean')
-	.description('Quarantine targets (Move-and-Delete transaction). Defaults to dry-run via analyze.')
+	.description('Quarantine targets (Move-and-Delete transaction), or plan like analyze and purge the plan after confirming')
 	.option('-t, --targets <targets...>', 'Paths to quarantine (from analyze)')
-	.option('--fast', 'Skip SHA256 verification for faster cleanup', false)
+	.option('-p, --paths <paths...>', 'Without --targets: paths to plan from', [])
+	.option('-d, --preserve-days <days>', 'Without --targets: preserve days for recency (default: machine role policy)')
+	.option('--plan <file>', 'Without --targets: purge this saved plan instead of planning')
+	.option('--approval <token>', 'Token printed by `purge approve`, for large plans')
+	.option('-y, --yes', 'Purge the plan without asking', false)
+	.option('--max-risk <score>', 'Without --targets: leave out items whose risk score (0-100) is above this')
+	.option('--fast', 'Leave the SHA256 to the background hash queue (`purge hash-queue run`)', false)
+	.option('--compress', 'Pack quarantined packages into zstd-compressed tarballs (rollback unpacks them)', false)
+	.option('--reinstall-on-demand', 'Mark projects whose node_modules is purged for `purge restore-deps`', loadedConfig.reinstallOnDemand || false)
 	.action(async (opts, cmd) => {
 		const g = cmd.parent?.opts?.() || {};
 		const format = (g.format || 'table') as OutputFormat;
+		if (opts.compress) process.env.PACKAGEPURGE_QUARANTINE_COMPRESS = '1';
 
 		if (!opts.targets || !opts.targets.length) {
-			if (!g.quiet) {
-				console.log(chalk.yellow('⚠ No targets provided.'));
-				console.log(chalk.gray('  Run `purge analyze` first to produce a cleanup plan.'));
-				console.log(chalk.gray('  Then use: purge clean --targets <path1> <path2> ...'));
-			}
-			process.exit(2);
+			await purgePlan(opts, !!g.quiet, format);
+			return;
 		}
 
 		const spinner = !g.quiet && format === 'table' ? new Spinner(`Quarantining ${opts.targets.length} packages...`) : null;
 		spinner?.start();
 
-		const res = await runCore(['quarantine', ...opts.targets]);
+		const res = await runCore(['quarantine', ...opts.targets, ...(opts.reinstallOnDemand ? ['--reinstall-on-demand'] : [])]);
 
 		if (res.code !== 0) {
 			spinner?.fail('Quarantine failed');
@@ -188,6 +304,62 @@ program
 		output(res.stdout, format, 'quarantine');
 	});
 
+interface PurgeOptions {
+	paths?: string[];
+	preserveDays?: string;
+	plan?: string;
+	approval?: string;
+	yes?: boolean;
+	fast?: boolean;
+	reinstallOnDemand?: boolean;
+	maxRisk?: string;
+}
+
+/** `clean` without targets: plan (or load a plan), confirm here, then let the core purge it */
+async function purgePlan(opts: PurgeOptions, quiet: boolean, format: OutputFormat): Promise<void> {
+	let planFile = opts.plan;
+	if (!planFile) {
+		warnWideScan(opts.paths || [], quiet);
+		const preserve = opts.preserveDays ? ['--preserve-days', String(opts.preserveDays)] : [];
+		const planned = await runCore(['dry-run', '--lazy-sizes', ...preserve, ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])]);
+		if (planned.code !== 0) {
+			if (!quiet) logger.error(planned.stderr || 'Planning failed');
+			process.exit(planned.code);
+		}
+		planFile = path.join(fs.mkdtempSync(path.join(os.tmpdir(), 'purge-')), 'plan.json');
+		fs.writeFileSync(planFile, planned.stdout);
+	}
+	let plan: { items?: unknown[]; total_estimated_bytes?: number } = {};
+	try {
+		plan = JSON.parse(fs.readFileSync(planFile, 'utf8'));
+	} catch {
+		// The core reports unreadable plans
+	}
+	if (Array.isArray(plan.items) && plan.items.length === 0) {
+		if (!quiet) console.log(chalk.green(`${sym('ok')} Nothing to purge`));
+		return;
+	}
+	if (!opts.yes) {
+		const size = formatBytes(plan.total_estimated_bytes || 0);
+		if (!process.stdin.isTTY || !(await ask(`Quarantine ${plan.items?.length ?? 'all'} items (about ${size})?`))) {
+			if (!quiet) console.error(chalk.yellow('Nothing purged (pass --yes to purge without asking)'));
+			process.exit(1);
+		}
+	}
+	const args = ['purge', '--plan', planFile, '--yes', ...(opts.paths || []).flatMap((p: string) => ['--roots', p])];
+	if (opts.approval) args.push('--approval', opts.approval);
+	if (opts.fast) args.push('--fast');
+	if (opts.reinstallOnDemand) args.push('--reinstall-on-demand');
+	if (opts.maxRisk) args.push('--max-risk', String(opts.maxRisk));
+	const res = await runCore(args);
+	if (res.code !== 0) {
+		if (!quiet) logger.error(res.stderr || 'Purge failed');
+		process.exit(res.code);
+	}
+	if (!quiet && res.stderr.includes('Purged')) console.error(chalk.gray(res.stderr.trim()));
+	output(res.stdout, format, 'quarantine');
+}
+
 // Rollback command
 program
 	.command('rollback')
@@ -200,7 +372,7 @@ program
 
 		if (!opts.id && !opts.latest) {
 			if (!g.quiet) {
-				console.log(chalk.yellow('⚠ No rollback target specified.'));
+				console.log(chalk.yellow(`${sym('warn')} No rollback target specified.`));
 				console.log(chalk.gray('  Use: purge rollback --latest'));
 				console.log(chalk.gray('  Or:  purge rollback --id <quarantine-id>'));
 			}
@@ -226,16 +398,113 @@ program
 		output(res.stdout, format, 'rollback');
 	});
 
+// Show-run command - what an apply changed, path by path
+program
+	.command('show-run')
+	.description('Show the before/after manifest of an apply (paths, sizes, hashes, actions), or list recorded runs')
+	.argument('[id]', 'Run id from an apply\'s output, or "latest"; omit to list runs')
+	.action(async (id: string | undefined, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const res = await runCore(['show-run', ...(id ? [id] : [])]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Run manifest not found');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+// ML jobs run ahead of planning
+program
+	.command('ml')
+	.description('Run ML predictor jobs; compute-features precomputes feature vectors from the latest scan')
+	.argument('<job>', 'compute-features')
+	.action(async (job: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const res = await runCore(['ml', job]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || `ml ${job} failed`);
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+// Quarantine command - browse entries interactively, or list/show/delete/gc/prune them
+program
+	.command('quarantine')
+	.description('Browse quarantined entries: contents, expiry, restore or delete (interactive on a terminal)')
+	.argument('[action]', 'list, show, delete, gc or prune; omit to browse')
+	.argument('[id]', 'Entry id, for show and delete')
+	.option('--older-than <days>', 'prune: delete entries quarantined more than this many days ago (default: retention window)')
+	.option('--retention-days <days>', 'prune: save this retention window (0 = keep forever) and prune by it')
+	.option('--auto', 'prune: delete expired entries before each quarantine run from now on')
+	.option('--no-auto', 'prune: stop deleting expired entries before quarantine runs')
+	.option('--dry-run', 'prune: list what would be deleted', false)
+	.action(async (action: string | undefined, id: string | undefined, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+		if (!action) {
+			if (process.stdin.isTTY && process.stdout.isTTY) {
+				await browseQuarantine();
+				return;
+			}
+			action = 'list';
+		}
+		if ((action === 'show' || action === 'delete') && !id) {
+			if (!g.quiet) logger.error(`purge quarantine ${action} needs an entry id (see \`purge quarantine list\`)`);
+			process.exit(2);
+		}
+
+		const args = ['quarantine', action, ...(id ? [id] : [])];
+		if (action === 'prune') {
+			if (opts.olderThan) args.push('--older-than', String(opts.olderThan));
+			if (opts.retentionDays) args.push('--retention-days', String(opts.retentionDays));
+			if (opts.auto === true) args.push('--auto');
+			if (opts.auto === false) args.push('--no-auto');
+			if (opts.dryRun) args.push('--dry-run');
+		}
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Quarantine command failed');
+			process.exit(res.code);
+		}
+		output(res.stdout, format, 'quarantine-list');
+	});
+
+program
+	.command('cleanup-quarantine')
+	.description('Delete quarantine entries past the retention window, entry limit or size quota, saving any limits given first')
+	.option('--max-size-gb <n>', 'Save this quarantine size quota')
+	.option('--retention-days <days>', 'Save this retention window (0 = keep forever)')
+	.option('--compress <bool>', 'Save whether new entries are packed into zstd-compressed tarballs (true or false)')
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['cleanup-quarantine'];
+		if (opts.maxSizeGb) args.push('--max-size-gb', String(opts.maxSizeGb));
+		if (opts.retentionDays) args.push('--retention-days', String(opts.retentionDays));
+		if (opts.compress) args.push('--compress', String(opts.compress));
+
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Quarantine cleanup failed');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
 // Optimize command
 program
 	.command('optimize')
 	.description('Optimize with ML/LRU prediction and symlinking (dry run)')
 	.option('-p, --paths <paths...>', 'Paths to optimize', [])
-	.option('-d, --preserve-days <days>', 'Days to preserve packages', '90')
+	.option('-d, --preserve-days <days>', 'Days to preserve packages (default: machine role policy)')
 	.option('--enable-symlinking', 'Enable cross-project symlinking', false)
 	.option('--enable-ml', 'Enable ML-based predictions', false)
+	.option('--remote-activity', 'Judge project liveness by GitHub/GitLab activity (GITHUB_TOKEN/GITLAB_TOKEN)', false)
 	.option('--lru-max-packages <count>', 'Maximum packages in LRU cache', '1000')
 	.option('--lru-max-size-bytes <bytes>', 'Maximum size of LRU cache in bytes', '10000000000')
+	.option('--eviction-policy <policy>', 'Keep what this policy would cache: lru, slru, lfu, arc or w-tiny-lfu')
+	.option('--verify-with-pm', 'Cross-check orphans with the project\'s package manager (npm ls, pnpm list, yarn info)', false)
+	.option('--sort-by-risk', 'List the riskiest items first', false)
 	.action(async (opts, cmd) => {
 		const g = cmd.parent?.opts?.() || {};
 		const format = (g.format || 'table') as OutputFormat;
@@ -246,22 +515,27 @@ program
 		const featureStr = features.length ? ` (${features.join(', ')})` : '';
 
 		const spinner = !g.quiet && format === 'table' ? new Spinner(`Optimizing packages${featureStr}...`) : null;
+		warnWideScan(opts.paths || [], !spinner);
 		spinner?.start();
 
-		const preserve = String(opts.preserveDays ?? 90);
 		const lruPackages = String(opts.lruMaxPackages ?? 1000);
 		const lruSize = String(opts.lruMaxSizeBytes ?? 10000000000);
 
 		const args = [
 			'optimize',
-			'--preserve-days', preserve,
 			'--lru-max-packages', lruPackages,
 			'--lru-max-size-bytes', lruSize,
 		];
 
+		if (opts.preserveDays) args.push('--preserve-days', String(opts.preserveDays));
 		if (opts.enableSymlinking) args.push('--enable-symlinking');
+		if (opts.evictionPolicy) args.push('--eviction-policy', opts.evictionPolicy);
+		if (opts.verifyWithPm) args.push('--verify-with-pm');
 		if (opts.enableMl) args.push('--enable-ml');
+		if (opts.remoteActivity) args.push('--remote-activity');
+		if (opts.sortByRisk) args.push('--sort-by-risk');
 		if (opts.paths?.length) args.push('--paths', ...opts.paths);
+		args.push(...coreFormatArgs(format));
 
 		const res = await runCoreStreaming(args, (progress: StreamProgress) => {
 			if (spinner) {
@@ -284,19 +558,21 @@ program
 	.command('symlink')
 	.description('Execute symlinking for duplicate packages across projects')
 	.option('-p, --paths <paths...>', 'Paths to process', [])
+	.option('--canonical <strategy>', 'Copy to keep: first, recent-project, fastest-disk, verified-integrity', loadedConfig.canonicalStrategy || 'first')
+	.option('--mode <mode>', 'symlink, or hardlink to keep real directories with files linked to the store', loadedConfig.dedupMode || 'symlink')
 	.action(async (opts, cmd) => {
 		const g = cmd.parent?.opts?.() || {};
 		const format = (g.format || 'table') as OutputFormat;
 
 		// Check for Windows symlink capability
 		if (process.platform === 'win32') {
-			console.log(chalk.yellow('⚠ Note: Symlinking on Windows requires Administrator privileges or Developer Mode.'));
+			console.log(chalk.yellow(`${sym('warn')} Note: Symlinking on Windows requires Administrator privileges or Developer Mode.`));
 		}
 
 		const spinner = !g.quiet && format === 'table' ? new Spinner('Creating symlinks...') : null;
 		spinner?.start();
 
-		const args = ['symlink'];
+		const args = ['symlink', '--canonical', opts.canonical, '--mode', opts.mode];
 		if (opts.paths?.length) args.push('--paths', ...opts.paths);
 
 		const res = await runCore(args);
@@ -306,7 +582,7 @@ program
 			if (!g.quiet) {
 				logger.error(res.stderr || 'Symlink failed');
 				if (process.platform === 'win32' && res.stderr?.includes('symlink')) {
-					console.log(chalk.yellow('\n💡 Tip: Enable Developer Mode in Windows Settings > For Developers'));
+					console.log(chalk.yellow(`\n${sym('tip')} Enable Developer Mode in Windows Settings > For Developers`));
 					console.log(chalk.gray('   Or run this command as Administrator'));
 				}
 			}
@@ -317,6 +593,356 @@ program
 		output(res.stdout, format, 'symlink');
 	});
 
+// Share-trees command
+program
+	.command('share-trees')
+	.description('Share one node_modules tree between projects that install identical ones')
+	.option('-p, --paths <paths...>', 'Paths to process', [])
+	.option('--dry-run', 'List shareable trees and their blockers without changing anything', false)
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+
+		const spinner = !g.quiet && format === 'table' ? new Spinner('Comparing node_modules trees...') : null;
+		spinner?.start();
+
+		const args = ['share-trees'];
+		if (opts.dryRun) args.push('--dry-run');
+		if (opts.paths?.length) args.push('--paths', ...opts.paths);
+
+		const res = await runCore(args);
+
+		if (res.code !== 0) {
+			spinner?.fail('Sharing trees failed');
+			if (!g.quiet) logger.error(res.stderr || 'Share-trees failed');
+			process.exit(res.code);
+		}
+
+		spinner?.succeed(opts.dryRun ? 'Comparison complete' : 'Trees shared');
+		output(res.stdout, format, 'share-trees');
+	});
+
+// Duplicates command - identical packages kept by more than one tool
+program
+	.command('duplicates')
+	.description('Packages stored with identical content by more than one tool (npm, Yarn, pnpm, Cargo registries, ...)')
+	.option('-p, --paths <paths...>', 'Paths to scan', [])
+	.option('--consolidate', 'Hard link the unblocked copies to one global store entry', false)
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+
+		const spinner = !g.quiet && format === 'table' ? new Spinner('Comparing package caches...') : null;
+		spinner?.start();
+
+		const args = ['duplicates'];
+		if (opts.consolidate) args.push('--consolidate');
+		if (opts.paths?.length) args.push('--paths', ...opts.paths);
+
+		const res = await runCore(args);
+
+		if (res.code !== 0) {
+			spinner?.fail('Comparing caches failed');
+			if (!g.quiet) logger.error(res.stderr || 'Duplicates failed');
+			process.exit(res.code);
+		}
+
+		spinner?.succeed(opts.consolidate ? 'Copies linked' : 'Comparison complete');
+		output(res.stdout, format, 'duplicates');
+	});
+
+// Bench command - scan and plan throughput on a generated tree
+program
+	.command('bench')
+	.description('Scan and plan a generated tree several times and report throughput')
+	.option('--projects <count>', 'Projects in the tree', '20')
+	.option('--packages <count>', 'Top-level packages per project', '50')
+	.option('--depth <levels>', 'Nesting depth of each package\'s dependencies', '2')
+	.option('--files <count>', 'Files per package', '4')
+	.option('--file-bytes <bytes>', 'Size of each file', '2048')
+	.option('--shared <fraction>', 'Fraction of packages installed at the same version in every project', '0.5')
+	.option('--iterations <count>', 'Scans and plans of the tree', '3')
+	.option('--dir <dir>', 'Generate the tree here and keep it (default: a temporary directory)')
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+
+		const spinner = !g.quiet && format === 'table' ? new Spinner('Generating and scanning...') : null;
+		spinner?.start();
+
+		const args = ['bench',
+			'--projects', String(opts.projects),
+			'--packages', String(opts.packages),
+			'--depth', String(opts.depth),
+			'--files', String(opts.files),
+			'--file-bytes', String(opts.fileBytes),
+			'--shared', String(opts.shared),
+			'--iterations', String(opts.iterations),
+		];
+		if (opts.dir) args.push('--dir', opts.dir);
+
+		const res = await runCore(args);
+
+		if (res.code !== 0) {
+			spinner?.fail('Benchmark failed');
+			if (!g.quiet) logger.error(res.stderr || 'Bench failed');
+			process.exit(res.code);
+		}
+
+		spinner?.succeed('Benchmark complete');
+		output(res.stdout, format, 'bench');
+	});
+
+// Top command - answers from recorded scan snapshots, no scanning
+program
+	.command('top')
+	.description('Largest, fastest-growing or stalest packages as of the last scans')
+	.option('--by <metric>', 'size, growth or staleness', 'size')
+	.option('-n, --limit <count>', 'Packages listed', '20')
+	.option('--growth-days <days>', 'Days back growth is measured over', '30')
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+
+		const res = await runCore(['top', '--by', opts.by, '-n', String(opts.limit), '--growth-days', String(opts.growthDays)]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Top failed');
+			process.exit(res.code);
+		}
+		output(res.stdout, format, 'top');
+	});
+
+// Projects command - activity class of each project
+program
+	.command('projects')
+	.description('Classify projects as active, dormant or dead')
+	.option('-p, --paths <paths...>', 'Paths to scan', [])
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+
+		const res = await runCore(['projects', ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Classification failed');
+			process.exit(res.code);
+		}
+		output(res.stdout, format, 'projects');
+	});
+
+// Activity command - thresholds and purge-by-class retention rules
+program
+	.command('activity')
+	.description('Show or set activity thresholds and which classes have node_modules purged whole')
+	.option('--active-days <days>', 'Days within which a project is active')
+	.option('--dead-days <days>', 'Days after which a project is dead')
+	.option('--purge-node-modules <classes...>', 'Classes (active, dormant, dead) whose node_modules are purged whole; "none" clears')
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['activity'];
+		if (opts.activeDays || opts.deadDays || opts.purgeNodeModules) {
+			args.push('set');
+			if (opts.activeDays) args.push('--active-days', String(opts.activeDays));
+			if (opts.deadDays) args.push('--dead-days', String(opts.deadDays));
+			for (const c of opts.purgeNodeModules || []) {
+				if (c !== 'none') args.push('--purge-node-modules', c);
+			}
+		}
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Activity settings failed');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+// Keep-versions command - limit how many versions of a package caches and the store hold
+program
+	.command('keep-versions')
+	.description('Show or set how many versions of each package caches and the store keep')
+	.option('--keep <n>', 'Versions kept of packages no rule matches')
+	.option('--rule <rules...>', 'PATTERN=N: keep N versions of packages matching the glob, e.g. "@types/*=1"')
+	.option('--off', 'Stop limiting versions', false)
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['keep-versions'];
+		if (opts.off) {
+			args.push('off');
+		} else if (opts.keep || opts.rule) {
+			args.push('set');
+			if (opts.keep) args.push('--keep', String(opts.keep));
+			for (const rule of opts.rule || []) args.push('--rule', rule);
+		}
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Version retention settings failed');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+// Cloud sync command - keep node_modules out of OneDrive, Dropbox and iCloud Drive
+program
+	.command('cloud-sync')
+	.description('Find node_modules inside OneDrive, Dropbox or iCloud Drive folders and exclude or relocate them (guided on a terminal)')
+	.argument('[action]', 'list, exclude or relocate; omit for the guided flow')
+	.argument('[node_modules]', 'Tree to exclude or relocate')
+	.option('-p, --paths <paths...>', 'Paths to scan for projects', [])
+	.option('--to <dir>', 'With relocate: directory outside every synced folder to move the tree below')
+	.action(async (action: string | undefined, nodeModules: string | undefined, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		if (!action) {
+			if (process.stdin.isTTY && process.stdout.isTTY) {
+				await guideCloudSync(opts.paths);
+				return;
+			}
+			action = 'list';
+		}
+		if ((action === 'exclude' || action === 'relocate') && !nodeModules) {
+			if (!g.quiet) logger.error(`purge cloud-sync ${action} needs a node_modules path (see \`purge cloud-sync list\`)`);
+			process.exit(2);
+		}
+		if (action === 'relocate' && !opts.to) {
+			if (!g.quiet) logger.error('purge cloud-sync relocate needs --to <dir>');
+			process.exit(2);
+		}
+
+		const args = ['cloud-sync', action];
+		if (action === 'list') {
+			for (const p of opts.paths) args.push('-p', p);
+		} else {
+			args.push(nodeModules as string);
+			if (action === 'relocate') args.push('--to', opts.to);
+		}
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Cloud sync command failed');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+// Bundle command - distribute vetted settings as one signed file
+program
+	.command('bundle')
+	.description('Export this machine\'s shareable settings as a signed bundle, or import one')
+	.argument('<action>', 'export or import')
+	.argument('<file>', 'Bundle file to write (export) or read (import)')
+	.option('--name <name>', 'With export: name shown when the bundle is imported')
+	.option('--description <text>', 'With export: what the bundle is for')
+	.option('--key <file>', 'Signing key (default: bundle.key in the config directory)')
+	.option('--dry-run', 'With import: report what would change without writing', false)
+	.action(async (action: string, file: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		if (action === 'export' && !opts.name) {
+			if (!g.quiet) logger.error('purge bundle export needs --name');
+			process.exit(2);
+		}
+		const args = ['bundle', action, file];
+		if (opts.name) args.push('--name', opts.name);
+		if (opts.description) args.push('--description', opts.description);
+		if (opts.key) args.push('--key', opts.key);
+		if (opts.dryRun) args.push('--dry-run');
+
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Bundle command failed');
+			process.exit(res.code);
+		}
+		if (res.stderr.trim() && !g.quiet) console.error(chalk.gray(res.stderr.trim()));
+		console.log(res.stdout.trim());
+	});
+
+// Tag command - group projects so commands and policies can target each group
+program
+	.command('tag')
+	.description('Tag projects by hand or by rule (directory, git remote org); --tag narrows any command to them')
+	.argument('[action]', 'list, add, remove, rule, unrule or config', 'list')
+	.argument('[args...]', 'add/remove: <project> <tags...>; rule/unrule: <tag>')
+	.option('-p, --paths <paths...>', 'With list: paths to scan', [])
+	.option('--under <dir>', 'With rule: tag the projects below this directory')
+	.option('--remote <org>', 'With rule: tag the projects cloned from this org, group or repository')
+	.action(async (action: string, rest: string[], opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['tag', action, ...rest];
+		if (action === 'list' && opts.paths?.length) args.push('--paths', ...opts.paths);
+		if (opts.under) args.push('--under', opts.under);
+		if (opts.remote) args.push('--remote', opts.remote);
+
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Tag command failed');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+// Backup-exclude command - keep regenerable trees out of backups
+program
+	.command('backup-exclude')
+	.description('Mark node_modules, package caches and the global store so backups skip them')
+	.option('-p, --paths <paths...>', 'Paths to scan', [])
+	.option('--from-scan <file>', 'Take the trees from a scan saved by `purge scan -f json` or `purge import`')
+	.option('--undo', 'Remove the exclusions this command added', false)
+	.option('--dry-run', 'List what would change without writing', false)
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+		const args = ['backup-exclude', ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		if (opts.fromScan) args.push('--from-scan', opts.fromScan);
+		if (opts.undo) args.push('--undo');
+		if (opts.dryRun) args.push('--dry-run');
+
+		const res = await runCore(args);
+		// Exit code 1 still carries the report of what was marked
+		if (res.code !== 0 && !res.stdout.trim()) {
+			if (!g.quiet) logger.error(res.stderr || 'Backup exclusion failed');
+			process.exit(res.code);
+		}
+		output(res.stdout, format, 'backup-exclude');
+		if (res.code !== 0) process.exit(res.code);
+	});
+
+// Hash-queue command - deferred, rate-limited hashing
+program
+	.command('hash-queue')
+	.description('Show or work through the queue of deferred hashing left by --fast, `verify --background` and `installed`')
+	.argument('[action]', 'status, list, run or clear', 'status')
+	.option('--status <status>', 'With list: only queued, running, done or failed jobs')
+	.option('--rate-mib <n>', 'With run: read at most this many MiB per second (0 for unlimited)')
+	.option('--max-seconds <n>', 'With run: stop taking new jobs after this many seconds')
+	.option('--watch <seconds>', 'With run: keep running, checking for new jobs at this interval')
+	.option('--keep-days <n>', 'With clear: keep finished jobs newer than this many days')
+	.action(async (action: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['hash-queue', action];
+		if (opts.status) args.push('--status', opts.status);
+		if (opts.rateMib) args.push('--rate-mib', String(opts.rateMib));
+		if (opts.maxSeconds) args.push('--max-seconds', String(opts.maxSeconds));
+		if (opts.watch) args.push('--watch', String(opts.watch));
+		if (opts.keepDays) args.push('--keep-days', String(opts.keepDays));
+
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Hash queue failed');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+program
+	.command('installed')
+	.description('Record a package-manager install so scans refresh the project; add `purge installed` to a postinstall script')
+	.argument('[project]', 'Project installed into (default: the working directory)')
+	.action(async (project: string | undefined, _opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const res = await runCore(['installed', ...(project ? [project] : [])]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Recording the install failed');
+			process.exit(res.code);
+		}
+		if (!g.quiet) console.log(res.stdout.trim());
+	});
+
 // Stats command - uses Rust core stats
 program
 	.command('stats')
@@ -334,7 +960,7 @@ program
 				process.exit(res.code);
 			}
 		} else {
-			console.log(chalk.bold('\n📊 PackagePurge Statistics\n'));
+			console.log(chalk.bold(`\n${sym('total')}PackagePurge Statistics\n`));
 
 			// Show config source
 			if (configSource) {
@@ -361,6 +987,9 @@ program
 					console.log(`  Entries: ${stats.quarantine?.total_entries || 0}`);
 					console.log(`  Size: ${formatBytes(stats.quarantine?.total_size_bytes || 0)}`);
 					console.log(`  Oldest: ${stats.quarantine?.oldest_entry_days || 0} days`);
+					if (stats.quarantine?.packed_entries) {
+						console.log(`  Packed: ${stats.quarantine.packed_entries} entries in ${formatBytes(stats.quarantine.packed_bytes || 0)}`);
+					}
 					console.log();
 
 					if (stats.scan_cache) {
@@ -368,32 +997,483 @@ program
 						console.log(`  Entries: ${stats.scan_cache.total_entries}`);
 						console.log(`  Cached size: ${formatBytes(stats.scan_cache.total_cached_size)}`);
 					}
+
+					if (stats.compiler_caches?.caches?.length) {
+						console.log();
+						console.log(chalk.bold('Compiler Caches:'));
+						for (const c of stats.compiler_caches.caches) {
+							console.log(`  ${c.kind}: ${formatBytes(c.size_bytes)} (${c.path})`);
+						}
+						console.log(`  Reclaimable: ${formatBytes(stats.compiler_caches.reclaimable_bytes)}`);
+					}
+
+					if (stats.lru_cache) {
+						const lru = stats.lru_cache;
+						console.log();
+						console.log(chalk.bold('LRU Cache (last optimize):'));
+						console.log(`  Hit rate: ${(lru.hit_rate * 100).toFixed(1)}% (${lru.hits} hits, ${lru.misses} misses)`);
+						console.log(`  Evictions: ${lru.evictions} by count, ${lru.size_evictions} by size`);
+						console.log(`  Entries: ${lru.entries} / ${lru.max_packages}`);
+						console.log(`  Size: ${formatBytes(lru.current_bytes)} / ${formatBytes(lru.budget_bytes)}`);
+					}
+
+					if (stats.apply_accuracy) {
+						const a = stats.apply_accuracy;
+						const pct = (r: number | null) => (r == null ? 'n/a' : `${(r * 100).toFixed(0)}%`);
+						console.log();
+						console.log(chalk.bold(`Estimate Accuracy (last ${a.runs} applies):`));
+						console.log(`  Estimated: ${formatBytes(a.estimated_bytes)}, reclaimed: ${formatBytes(a.actual_bytes)} (${pct(a.accuracy)})`);
+						console.log(`  Last apply: ${pct(a.last_accuracy)}`);
+					}
+
+					if (stats.overhead) {
+						const o = stats.overhead;
+						console.log();
+						console.log(chalk.bold('Tool Overhead:'));
+						console.log(`  Quarantine: ${formatBytes(o.quarantine_bytes)}, store: ${formatBytes(o.store_bytes)}, databases: ${formatBytes(o.database_bytes)}, archives: ${formatBytes(o.archive_bytes)}`);
+						console.log(`  Total: ${formatBytes(o.total_bytes)}${o.max_bytes ? ` / cap ${formatBytes(o.max_bytes)}` : ''}`);
+						console.log(`  Saved: ${formatBytes(o.saved_bytes)}${o.percent_of_saved != null ? ` (overhead ${o.percent_of_saved.toFixed(0)}% of it)` : ''}`);
+						if (o.warning) console.log(chalk.yellow(`  ${sym('warn')} ${o.warning}`));
+					}
+
+					if (stats.locations) {
+						const loc = stats.locations;
+						console.log();
+						console.log(chalk.dim('Locations:'));
+						console.log(chalk.dim(`  State: ${loc.state_dir}`));
+						console.log(chalk.dim(`  Quarantine: ${loc.quarantine}`));
+						console.log(chalk.dim(`  Cache: ${loc.scan_cache}`));
+						console.log(chalk.dim(`  Features: ${loc.feature_store}`));
+						if (loc.global_store) console.log(chalk.dim(`  Store: ${loc.global_store}`));
+					}
 				} catch {
 					console.log(res.stdout);
 				}
 			}
+		}
+	});
 
-			console.log();
-			console.log(chalk.dim('Locations:'));
-			console.log(chalk.dim('  Quarantine: ~/.packagepurge/quarantine'));
-			console.log(chalk.dim('  Cache: ~/.packagepurge/scan_cache.json'));
-			console.log(chalk.dim('  Features: ~/.packagepurge/features.db'));
+// CI clean command - old build workspaces and oversized tool caches on runners
+program
+	.command('ci-clean')
+	.description('Clean a CI runner: keep the newest builds per pipeline, clear oversized tool caches')
+	.requiredOption('--workspace-root <dir>', 'Directory holding build workspaces')
+	.requiredOption('--pattern <globs...>', 'Globs relative to the root matching build directories')
+	.option('--keep <count>', 'Most recent builds kept per pipeline', '3')
+	.option('--pipeline-key <key>', 'Group builds by parent directory or by name (Jenkins job@N)', 'parent')
+	.option('--tool-cache <dirs...>', 'Tool caches to check (default: common package and build caches)')
+	.option('--cache-budget <size>', 'Clear tool caches larger than this, e.g. 5G')
+	.option('--quarantine', 'Quarantine removals instead of deleting them', false)
+	.option('--dry-run', 'Report what would be removed', false)
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = [
+			'ci-clean',
+			'--workspace-root', opts.workspaceRoot,
+			'--keep', String(opts.keep),
+			'--pipeline-key', opts.pipelineKey,
+		];
+		for (const pattern of opts.pattern) args.push('--pattern', pattern);
+		for (const dir of opts.toolCache ?? []) args.push('--tool-cache', dir);
+		if (opts.cacheBudget) args.push('--cache-budget', opts.cacheBudget);
+		if (opts.quarantine) args.push('--quarantine');
+		if (opts.dryRun) args.push('--dry-run');
+
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'CI clean failed');
+			process.exit(res.code);
+		}
+		// Machine-readable by design; runners parse it
+		console.log(res.stdout);
+	});
+
+// Approve/apply - two-person workflow for plans above the approval threshold
+program
+	.command('impact')
+	.description('List the projects whose installs a saved plan would break, by activity class')
+	.argument('<plan>', 'Plan JSON file')
+	.option('-p, --paths <paths...>', 'Paths the plan was made from', [])
+	.option('--from-scan <file>', 'Take projects and dependencies from a saved scan instead of scanning')
+	.action(async (plan: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+		const args = ['impact', plan, ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		if (opts.fromScan) args.push('--from-scan', opts.fromScan);
+
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Impact analysis failed');
+			process.exit(res.code);
+		}
+		output(res.stdout, format, 'impact');
+	});
+
+// Compliance command - check this machine against an org policy file
+program
+	.command('compliance')
+	.description('Check this machine against a policy file: cache size, stale node_modules, backup exclusions')
+	.argument('<policy>', 'Policy JSON file')
+	.option('-p, --paths <paths...>', 'Paths to check', [])
+	.option('--from-scan <file>', 'Take packages from a saved scan instead of scanning')
+	.option('--plan-out <file>', 'Also write the remediation plan here, for `purge approve` or `purge apply`')
+	.action(async (policy: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+		const args = ['compliance', policy, ...(opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		if (opts.fromScan) args.push('--from-scan', opts.fromScan);
+		if (opts.planOut) args.push('--plan-out', opts.planOut);
+
+		const res = await runCore(args);
+		// Exit code 1 carries the report of the failed checks
+		if (res.code !== 0 && !res.stdout.trim()) {
+			if (!g.quiet) logger.error(res.stderr || 'Compliance check failed');
+			process.exit(res.code);
+		}
+		output(res.stdout, format, 'compliance');
+		if (res.code !== 0) process.exit(res.code);
+	});
+
+program
+	.command('approve')
+	.description('Approve a saved plan (analyze -f json output) for someone else to apply')
+	.argument('<plan>', 'Plan JSON file')
+	.action(async (plan: string, _opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const res = await runCore(['approve', plan]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Approve failed');
+			process.exit(res.code);
+		}
+		if (g.format === 'json') {
+			console.log(res.stdout);
+			return;
+		}
+		const info = JSON.parse(res.stdout);
+		console.log(chalk.bold(`\nApproved ${info.items} items (${formatBytes(info.total_estimated_bytes)}) as ${info.approval.approver}`));
+		console.log(chalk.gray(`  Valid until ${formatDate(info.approval.expires_at)}. Hand this token to the person applying the plan:`));
+		console.log(info.token);
+	});
+
+program
+	.command('apply')
+	.description('Quarantine every item of a saved plan; large plans need an approval token')
+	.argument('<plan>', 'Plan JSON file')
+	.option('--approval <token>', 'Token printed by `purge approve`')
+	.option('--fast', 'Leave the SHA256 to the background hash queue (`purge hash-queue run`)', false)
+	.option('--reinstall-on-demand', 'Mark projects whose node_modules is purged for `purge restore-deps`', loadedConfig.reinstallOnDemand || false)
+	.option('-y, --yes', 'Include items outside package and cache directories without asking', false)
+	.option('--canary <percent>', 'Quarantine only a random share of the plan; apply it again for the rest once the canary passed')
+	.option('--canary-by <basis>', 'count or bytes', 'count')
+	.option('--observe-hours <n>', 'Hours to watch the canary for reinstalls and rollbacks', '24')
+	.option('--soft-disable', 'Disable targets in place for a grace period; `purge soft-disabled commit` quarantines them after', false)
+	.option('--soft-mode <mode>', 'rename or permissions', 'rename')
+	.option('--grace-hours <n>', 'Hours a soft-disabled batch must go without complaints', '48')
+	.option('--max-risk <score>', 'Leave out items whose risk score (0-100) is above this, or that have no score')
+	.action(async (plan: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const format = (g.format || 'table') as OutputFormat;
+		const args = ['apply', plan, ...(opts.approval ? ['--approval', opts.approval] : []), ...(opts.fast ? ['--fast'] : [])];
+		if (opts.reinstallOnDemand) args.push('--reinstall-on-demand');
+		if (opts.canary) args.push('--canary', String(opts.canary), '--canary-by', opts.canaryBy, '--observe-hours', String(opts.observeHours));
+		if (opts.softDisable) args.push('--soft-disable', '--soft-mode', opts.softMode, '--grace-hours', String(opts.graceHours));
+		if (opts.maxRisk) args.push('--max-risk', String(opts.maxRisk));
+		// The core cannot prompt through a pipe, so plans from scans of ~ or / are confirmed here
+		let confirm: string[] = [];
+		try {
+			confirm = plan === '-' ? [] : JSON.parse(fs.readFileSync(plan, 'utf8')).confirm || [];
+		} catch {
+			// The core reports unreadable plans
+		}
+		if (opts.yes) {
+			args.push('--yes');
+		} else if (confirm.length && process.stdin.isTTY) {
+			for (const target of confirm) console.error(chalk.yellow(`  ${sym('warn')} ${target}`));
+			if (await ask(`Quarantine these ${confirm.length} items outside package and cache directories too?`)) args.push('--yes');
+		}
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Apply failed');
+			process.exit(res.code);
+		}
+		if (!g.quiet && /Canary|Soft-disabled|Leaving out/.test(res.stderr)) console.error(chalk.gray(res.stderr.trim()));
+		if (opts.softDisable) {
+			console.log(res.stdout.trim());
+			return;
+		}
+		output(res.stdout, format, 'quarantine');
+	});
+
+program
+	.command('canary')
+	.description('List canary purges (apply --canary) with their verdicts, or abort one')
+	.argument('[action]', 'abort; omit to list')
+	.argument('[planHash]', 'Plan hash of the canary to abort')
+	.action(async (action: string | undefined, planHash: string | undefined, _opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		if (action === 'abort' && !planHash) {
+			if (!g.quiet) logger.error('purge canary abort needs a plan hash (see `purge canary`)');
+			process.exit(2);
+		}
+		const res = await runCore(action === 'abort' ? ['canary', 'abort', planHash as string] : ['canary']);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Canary failed');
+			process.exit(res.code);
+		}
+		console.log(res.stdout.trim());
+	});
+
+program
+	.command('soft-disabled')
+	.description('List soft-disabled batches (apply --soft-disable), restore one instantly, or quarantine the ones past their grace period')
+	.argument('[action]', 'restore or commit; omit to list')
+	.argument('[target]', 'Batch id or original path to restore')
+	.option('--force', 'Quarantine an original path that was recreated, then restore over it', false)
+	.action(async (action: string | undefined, target: string | undefined, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		if (action === 'restore' && !target) {
+			if (!g.quiet) logger.error('purge soft-disabled restore needs a batch id or path (see `purge soft-disabled`)');
+			process.exit(2);
+		}
+		const args = action === 'restore' ? ['soft-disabled', 'restore', target as string, ...(opts.force ? ['--force'] : [])] : action === 'commit' ? ['soft-disabled', 'commit'] : ['soft-disabled'];
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Soft-disable failed');
+			process.exit(res.code);
+		}
+		if (!g.quiet && res.stderr.trim()) console.error(chalk.gray(res.stderr.trim()));
+		console.log(res.stdout.trim());
+	});
+
+// Digest command - periodic summary, delivered to a file, sendmail or a webhook
+program
+	.command('digest')
+	.description('Show the activity digest for the current period, or send it to the destinations set in digest.json')
+	.option('--send', 'Deliver the digest instead of printing it', false)
+	.option('--if-due', 'With --send, only send once the configured period has passed', false)
+	.option('--html', 'Print HTML instead of text', false)
+	.action(async (opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = opts.send
+			? ['digest', 'send', ...(opts.ifDue ? ['--if-due'] : [])]
+			: ['digest', 'show', ...(opts.html ? ['--html'] : [])];
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Digest failed');
+			process.exit(res.code);
+		}
+		if (!opts.send || g.format === 'json') {
+			process.stdout.write(res.stdout);
+			return;
+		}
+		const info = JSON.parse(res.stdout);
+		if (info.status === 'not_due') {
+			console.log(chalk.gray(`Digest not due yet (last sent ${info.last_sent})`));
+			return;
+		}
+		for (const destination of info.delivered) {
+			console.log(`${chalk.green('Sent')} ${destination}`);
+		}
+	});
+
+// Exec command - run a command after restoring its project's quarantined packages
+program
+	.command('exec')
+	.description('Run a command in the current project, restoring quarantined packages first and recording usage')
+	.argument('<command...>', 'Command and its arguments (put them after --)')
+	.option('--no-restore', 'Do not restore quarantined packages before running')
+	.action(async (command: string[], opts) => {
+		const args = ['exec', ...(opts.restore === false ? ['--no-restore'] : []), '--', ...command];
+		process.exit(await runCoreInherit(args));
+	});
+
+// Watch command - record package uses as they happen
+program
+	.command('watch')
+	.description('Record package uses in the feature store as they happen, by watching node_modules trees until Ctrl-C')
+	.option('-p, --paths <paths...>', 'Paths whose projects are watched', [])
+	.option('--min-interval <seconds>', 'Seconds before another use of the same package is recorded', '60')
+	.option('--duration <seconds>', 'Stop after this many seconds')
+	.action(async (opts) => {
+		const args = ['watch', '--min-interval', String(opts.minInterval)];
+		if (opts.duration) args.push('--duration', String(opts.duration));
+		if (opts.paths?.length) args.push('--paths', ...opts.paths);
+		// Ctrl-C reaches core too, which stops and prints its summary
+		process.on('SIGINT', () => {});
+		process.exit(await runCoreInherit(args));
+	});
+
+// Restore-deps command - reinstall projects purged with --reinstall-on-demand
+program
+	.command('restore-deps')
+	.description('Reinstall dependencies of purged projects from their lockfile (npm ci, pnpm install --frozen-lockfile, ...)')
+	.argument('<projects...>', 'Project directories')
+	.option('--dry-run', 'Print the install commands without running them', false)
+	.action(async (projects: string[], opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const res = await runCore(['restore-deps', ...projects, ...(opts.dryRun ? ['--dry-run'] : [])]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Restore failed');
+			process.exit(res.code);
+		}
+		if (g.format === 'json') {
+			console.log(res.stdout);
+			return;
+		}
+		for (const outcome of JSON.parse(res.stdout)) {
+			const verb = outcome.ran ? 'Reinstalled' : 'Would run';
+			console.log(`${chalk.green(verb)} ${outcome.project}: ${chalk.gray(outcome.command.join(' '))}`);
 		}
 	});
 
+// Archive commands - move a dormant project's artifacts to cold storage
+program
+	.command('archive')
+	.description("Pack a project's node_modules and build artifacts into cold storage and delete them")
+	.argument('<project>', 'Project directory')
+	.option('--to <dest>', 'Directory or s3://bucket/prefix for the archive')
+	.option('--include <dirs...>', 'Extra directories to pack, relative to the project')
+	.option('--dry-run', 'Only list the directories that would be archived', false)
+	.action(async (project: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['archive', project, ...(opts.to ? ['--to', opts.to] : []), ...(opts.dryRun ? ['--dry-run'] : [])];
+		for (const dir of opts.include || []) args.push('--include', dir);
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Archive failed');
+			process.exit(res.code);
+		}
+		if (g.format === 'json') {
+			console.log(res.stdout);
+			return;
+		}
+		const info = JSON.parse(res.stdout);
+		if (opts.dryRun) {
+			console.log(chalk.bold(`\nWould archive: ${info.dirs.join(', ') || 'nothing'}`));
+			return;
+		}
+		console.log(chalk.bold(`\nArchived ${info.dirs.join(', ')} (${formatBytes(info.size_bytes)} -> ${formatBytes(info.archive_bytes)})`));
+		console.log(chalk.gray(`  ${info.location}`));
+	});
+
+program
+	.command('unarchive')
+	.description("Restore a project's most recent archive")
+	.argument('<project>', 'Project directory')
+	.action(async (project: string, _opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const res = await runCore(['unarchive', project]);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Unarchive failed');
+			process.exit(res.code);
+		}
+		if (g.format === 'json') {
+			console.log(res.stdout);
+			return;
+		}
+		const info = JSON.parse(res.stdout);
+		console.log(chalk.bold(`\nRestored ${info.dirs.join(', ')} from ${info.location}`));
+	});
+
+// Role command - machine role and the default policy it selects
+program
+	.command('role')
+	.description('Show the machine role, or set/reset it')
+	.argument('[action]', 'show, set or reset', 'show')
+	.argument('[role]', 'laptop, workstation, ci-runner or build-server (for set)')
+	.action(async (action: string, role: string | undefined, _opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['role', action, ...(role ? [role] : [])];
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'Role command failed');
+			process.exit(res.code);
+		}
+		if (g.format === 'json') {
+			console.log(res.stdout);
+			return;
+		}
+		const info = JSON.parse(res.stdout);
+		const p = info.policy;
+		console.log(chalk.bold(`\nMachine role: ${chalk.cyan(info.role)}`), chalk.dim(`(${info.source}, detected ${info.detected})`));
+		console.log(`  Preserve days: ${p.preserve_days}`);
+		console.log(`  Automation: ${p.automation}`);
+		console.log(`  Schedule: ${p.schedule}`);
+		console.log(`  Quarantine retention: ${p.quarantine_retention_days} days, max ${p.quarantine_max_size_gb} GB`);
+	});
+
+// WSL command - projects on both sides of the WSL/Windows boundary
+program
+	.command('wsl')
+	.description('Show the WSL/Windows boundary as seen from here, or scan both sides into one report')
+	.argument('[action]', 'show or scan', 'show')
+	.option('-p, --paths <paths...>', 'With scan: local paths to scan besides the other side\'s home directories', [])
+	.action(async (action: string, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		const args = ['wsl', action, ...(action === 'scan' && opts.paths?.length ? ['--paths', ...opts.paths] : [])];
+		const res = await runCore(args);
+		if (res.code !== 0) {
+			if (!g.quiet) logger.error(res.stderr || 'WSL command failed');
+			process.exit(res.code);
+		}
+		if (g.format === 'json' || action !== 'scan') {
+			console.log(res.stdout.trim());
+			return;
+		}
+		const report = JSON.parse(res.stdout);
+		for (const side of report.sides) {
+			const name = side.side.kind === 'windows' ? 'Windows' : `WSL (${side.side.distro})`;
+			const links = side.links_supported ? '' : chalk.dim(' — no link deduplication across the boundary');
+			console.log(`${chalk.bold(name)}: ${side.packages} packages in ${side.projects} projects, ${formatBytes(side.total_bytes)}${links}`);
+		}
+		console.log(chalk.gray(`Total: ${formatBytes(report.total_bytes)}`));
+	});
+
 // Config command - show current configuration
 program
 	.command('config')
-	.description('Show current configuration')
+	.description('Show current configuration; `validate` checks the settings files, `effective` shows the settings in effect for a machine role')
+	.argument('[action]', 'validate or effective; omit to show the CLI configuration')
 	.option('--json', 'Output as JSON')
-	.action(async (opts) => {
+	.action(async (action: string | undefined, opts, cmd) => {
+		const g = cmd.parent?.opts?.() || {};
+		if (action === 'validate' || action === 'effective') {
+			const res = await runCore(action === 'validate' ? ['config', 'validate'] : ['config', 'show']);
+			if (action === 'effective' || opts.json) {
+				if (res.code !== 0 && !res.stdout) {
+					if (!g.quiet) logger.error(res.stderr || 'Config failed');
+					process.exit(res.code);
+				}
+				console.log(res.stdout.trim());
+				process.exit(res.code);
+			}
+			let report: { config_dir: string; files: string[]; findings: { file: string; severity: string; key?: string; message: string }[]; valid: boolean };
+			try {
+				report = JSON.parse(res.stdout);
+			} catch {
+				if (!g.quiet) logger.error(res.stderr || 'Config validation failed');
+				process.exit(res.code || 1);
+			}
+			console.log(chalk.bold(`\n${sym('settings')}Settings in ${report.config_dir}\n`));
+			if (!report.files.length) console.log(chalk.dim('  No settings files saved; everything uses its defaults'));
+			for (const f of report.findings) {
+				const mark = f.severity === 'error' ? chalk.red(sym('fail')) : chalk.yellow(sym('warn'));
+				console.log(`${mark} ${chalk.cyan(f.file)}${f.key ? chalk.dim(` ${f.key}`) : ''}: ${f.message}`);
+			}
+			if (report.valid) console.log(chalk.green(sym('ok')), `${report.files.length} settings files checked${report.findings.length ? ', warnings only' : ''}`);
+			process.exit(report.valid ? 0 : 1);
+		}
+		if (action) {
+			logger.error(`Unknown config action: ${action}`);
+			process.exit(2);
+		}
 		if (opts.json) {
 			console.log(JSON.stringify(loadedConfig, null, 2));
 		} else {
-			console.log(chalk.bold('\n⚙️  PackagePurge Configuration\n'));
+			console.log(chalk.bold(`\n${sym('settings')}PackagePurge Configuration\n`));
 
 			if (configSource) {
-				console.log(chalk.green('✓'), `Loaded from: ${chalk.cyan(configSource)}`);
+				console.log(chalk.green(sym('ok')), `Loaded from: ${chalk.cyan(configSource)}`);
 			} else {
 				console.log(chalk.yellow('!'), 'No config file found, using defaults');
 				console.log(chalk.dim('  Run `purge init` to create a config file'));
@@ -432,7 +1512,7 @@ program
 		const configPath = '.packagepurgerc.yaml';
 
 		if (!opts.force && require('fs').existsSync(configPath)) {
-			console.log(chalk.yellow('⚠'), `Config file already exists: ${configPath}`);
+			console.log(chalk.yellow(sym('warn')), `Config file already exists: ${configPath}`);
 			console.log(chalk.dim('  Use --force to overwrite'));
 			process.exit(1);
 		}
@@ -440,19 +1520,11 @@ program
 		const content = generateExampleConfig();
 		require('fs').writeFileSync(configPath, content);
 
-		console.log(chalk.green('✓'), `Created ${chalk.cyan(configPath)}`);
+		console.log(chalk.green(sym('ok')), `Created ${chalk.cyan(configPath)}`);
 		console.log();
 		console.log(chalk.dim('Edit this file to customize PackagePurge behavior.'));
 		console.log(chalk.dim('Run `purge config` to see current settings.'));
 	});
 
-// Helper function for formatting bytes
-function formatBytes(bytes: number): string {
-	if (bytes < 1024) return `${bytes} B`;
-	if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
-	if (bytes < 1024 * 1024 * 1024) return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
-	return `${(bytes / (1024 * 1024 * 1024)).toFixed(2)} GB`;
-}
-
 program.parse(process.argv);
 
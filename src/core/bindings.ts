@@ -17,6 +17,8 @@ export interface OptimizeOptions {
 
 export interface SymlinkOptions {
 	paths?: string[];
+	canonicalStrategy?: 'first' | 'recent-project' | 'fastest-disk' | 'verified-integrity';
+	dedupMode?: 'symlink' | 'hardlink';
 }
 
 /**
@@ -58,6 +60,12 @@ export async function optimize(options: OptimizeOptions = {}): Promise<OptimizeR
 export async function executeSymlinking(options: SymlinkOptions = {}): Promise<SymlinkResult> {
 	const args = ['symlink'];
 
+	if (options.canonicalStrategy) {
+		args.push('--canonical', options.canonicalStrategy);
+	}
+	if (options.dedupMode) {
+		args.push('--mode', options.dedupMode);
+	}
 	if (options.paths && options.paths.length > 0) {
 		args.push('--paths', ...options.paths);
 	}
@@ -84,9 +84,29 @@ export interface OptimizeResult {
   total_estimated_bytes: number;
 }
 
+export interface CanonicalChoice {
+  package: string;
+  canonical: string;
+  strategy: string;
+  candidates: number;
+  detail: string | null;
+}
+
 export interface SymlinkResult {
   status: string;
   symlinked_count: number;
+  hardlinked_count?: number;
+  canonical_choices?: CanonicalChoice[];
+  throughput?: ThroughputSummary;
+}
+
+export interface ThroughputSummary {
+  elapsed_secs: number;
+  bytes: number;
+  files: number;
+  items: number;
+  bytes_per_sec: number;
+  files_per_sec: number;
 }
 
 export interface DependencyGraph {
@@ -0,0 +1,94 @@
+/**
+ * Guided cleanup of node_modules inside OneDrive, Dropbox and iCloud Drive
+ * folders: for each synced tree, exclude it in place, relocate it out of the
+ * sync root, or leave it.
+ */
+
+import * as os from 'os';
+import * as path from 'path';
+import chalk from 'chalk';
+import inquirer from 'inquirer';
+import { runCore } from './core-utils';
+import { formatBytes } from './display';
+import { truncatePath } from './formatter';
+import { logger } from './logger';
+import { sym } from './terminal';
+
+export interface SyncedTree {
+    path: string;
+    project: string;
+    provider: 'onedrive' | 'dropbox' | 'icloud';
+    sync_root: string;
+    size_bytes: number;
+    excluded: { method: 'ignored' | 'no_sync' | 'relocated'; to?: string } | null;
+}
+
+const PROVIDERS: Record<SyncedTree['provider'], string> = {
+    onedrive: 'OneDrive',
+    dropbox: 'Dropbox',
+    icloud: 'iCloud Drive',
+};
+
+/** Where relocated trees go unless the user picks another directory */
+const DEFAULT_DESTINATION = path.join(os.homedir(), '.packagepurge-local');
+
+async function core(args: string[]): Promise<string | null> {
+    const res = await runCore(args);
+    if (res.code !== 0) {
+        logger.error(res.stderr.trim() || `purge ${args.join(' ')} failed`);
+        return null;
+    }
+    return res.stdout;
+}
+
+/**
+ * Walk through each synced node_modules below `paths`
+ */
+export async function guideCloudSync(paths: string[]): Promise<void> {
+    const listed = await core(['cloud-sync', 'list', ...paths.flatMap(p => ['-p', p])]);
+    if (listed === null) process.exit(1);
+    const { trees }: { trees: SyncedTree[] } = JSON.parse(listed);
+    const synced = trees.filter(t => !t.excluded);
+    if (!synced.length) {
+        console.log(chalk.green(`${sym('ok')} No node_modules is being synced`));
+        return;
+    }
+
+    const total = synced.reduce((sum, t) => sum + t.size_bytes, 0);
+    console.log(chalk.yellow(`${sym('warn')} ${synced.length} node_modules (${formatBytes(total)}) are uploaded by a sync client on every install`));
+    for (const tree of synced) {
+        const provider = PROVIDERS[tree.provider];
+        console.log();
+        console.log(`${chalk.bold(truncatePath(tree.path, 70))} ${chalk.gray(`${formatBytes(tree.size_bytes)}, ${provider}`)}`);
+        const choices = [
+            { name: 'Leave it synced', value: 'skip' },
+            { name: `Relocate it out of ${provider} and link it back`, value: 'relocate' },
+        ];
+        if (tree.provider !== 'onedrive') {
+            const how = tree.provider === 'dropbox' ? 'mark it ignored' : 'rename it to node_modules.nosync';
+            choices.splice(1, 0, { name: `Exclude it in place (${how})`, value: 'exclude' });
+        }
+        const { action } = await inquirer.prompt<{ action: 'skip' | 'exclude' | 'relocate' }>([{
+            type: 'list',
+            name: 'action',
+            message: 'What should happen to it?',
+            choices,
+        }]);
+        if (action === 'exclude') {
+            if (await core(['cloud-sync', 'exclude', tree.path]) !== null) {
+                console.log(chalk.green(`${sym('ok')} ${provider} no longer syncs ${tree.path}`));
+            }
+        } else if (action === 'relocate') {
+            const { to } = await inquirer.prompt<{ to: string }>([{
+                type: 'input',
+                name: 'to',
+                message: 'Move it below which directory (outside every synced folder)?',
+                default: DEFAULT_DESTINATION,
+            }]);
+            const moved = await core(['cloud-sync', 'relocate', tree.path, '--to', to]);
+            if (moved !== null) {
+                console.log(chalk.green(`${sym('ok')} Moved to ${JSON.parse(moved).to}`));
+            }
+        }
+    }
+}
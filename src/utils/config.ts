@@ -33,6 +33,14 @@ export interface PackagePurgeConfig {
     lruMaxPackages?: number;
     /** Maximum size of LRU cache in bytes */
     lruMaxSizeBytes?: number;
+    /** Which duplicate copy symlinking keeps (default: first) */
+    canonicalStrategy?: 'first' | 'recent-project' | 'fastest-disk' | 'verified-integrity';
+    /** Replace duplicates with store symlinks or keep directories and hardlink files (default: symlink) */
+    dedupMode?: 'symlink' | 'hardlink';
+    /** Refuse every mutating operation (exploratory runs) */
+    readOnly?: boolean;
+    /** Leave a marker when purging node_modules so `restore-deps` can reinstall it */
+    reinstallOnDemand?: boolean;
     /** Quarantine settings */
     quarantine?: {
         /** Maximum quarantine size in GB */
@@ -40,6 +48,23 @@ export interface PackagePurgeConfig {
         /** Days to retain quarantine entries */
         retentionDays?: number;
     };
+    /** How human-readable output shows sizes, dates and report strings */
+    display?: {
+        /** 1024-based KiB/MiB or 1000-based kB/MB (default: binary) */
+        sizeUnits?: 'binary' | 'si';
+        /** Calendar dates or distances like "3 days ago" (default: absolute) */
+        dates?: 'absolute' | 'relative';
+        /** Time zone of absolute dates, "local" or an IANA name such as "Europe/Berlin" (default: local) */
+        timeZone?: string;
+        /** Locale of numbers, dates and report strings, e.g. "de" or "es-MX" (default: en) */
+        locale?: string;
+    };
+    /** Color output: auto honors NO_COLOR and non-terminal output (default: auto) */
+    color?: 'auto' | 'always' | 'never';
+    /** ASCII-only symbols, for limited terminals and screen readers */
+    ascii?: boolean;
+    /** Avoid gray and dimmed text */
+    highContrast?: boolean;
     /** Output format preference */
     format?: 'table' | 'json' | 'yaml';
     /** Quiet mode */
@@ -323,6 +348,18 @@ quarantine:
   maxSizeGb: 10
   retentionDays: 30
 
+# Human-readable output
+display:
+  sizeUnits: binary   # binary (KiB, MiB) or si (kB, MB)
+  dates: absolute     # absolute or relative ("3 days ago")
+  timeZone: local     # local or an IANA zone such as Europe/Berlin
+  locale: en          # en, de, es; other locales format numbers but keep English text
+
+# Terminal output
+color: auto           # auto (honors NO_COLOR), always or never
+ascii: false          # ASCII-only symbols, no emoji or animated spinner
+highContrast: false   # no gray or dimmed text
+
 # Output format: table, json, yaml
 format: table
 
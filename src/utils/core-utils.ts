@@ -100,6 +100,16 @@ export interface CoreResult {
 	code: number;
 }
 
+/**
+ * Pass the phase breakdown core prints with --timings on to our stderr, since
+ * captured stderr is otherwise only shown on failure
+ */
+function echoTimings(stderr: string): void {
+	if (!process.env.PACKAGEPURGE_TIMINGS) return;
+	const start = stderr.lastIndexOf('Timings: ');
+	if (start >= 0) process.stderr.write(stderr.slice(start));
+}
+
 /**
  * Progress callback for streaming operations
  */
@@ -192,6 +202,7 @@ export function runCoreStreaming(
 
 		child.on('error', reject);
 		child.on('close', (code) => {
+			echoTimings(err);
 			resolve({ stdout: out, stderr: err, code: code ?? 1 });
 		});
 	});
@@ -209,7 +220,23 @@ export function runCore(args: string[]): Promise<CoreResult> {
 		child.stdout.on('data', (d) => out += d.toString());
 		child.stderr.on('data', (d) => err += d.toString());
 		child.on('error', reject);
-		child.on('close', (code) => resolve({ stdout: out, stderr: err, code: code ?? 1 }));
+		child.on('close', (code) => {
+			echoTimings(err);
+			resolve({ stdout: out, stderr: err, code: code ?? 1 });
+		});
+	});
+}
+
+/**
+ * Run core with its stdout and stderr passed straight through, so output the
+ * binary streams is never buffered here; resolves to the exit code
+ */
+export function runCoreInherit(args: string[]): Promise<number> {
+	return new Promise((resolve, reject) => {
+		const bin = coreBinary();
+		const child = spawn(bin, args, { stdio: ['ignore', 'inherit', 'inherit'], env: process.env });
+		child.on('error', reject);
+		child.on('close', (code) => resolve(code ?? 1));
 	});
 }
 
@@ -246,6 +273,7 @@ export function runCoreWithTimeout(
 		child.on('close', (code) => {
 			clearTimeout(timeout);
 			if (!killed) {
+				echoTimings(err);
 				resolve({ stdout: out, stderr: err, code: code ?? 1 });
 			}
 		});
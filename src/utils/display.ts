@@ -0,0 +1,180 @@
+/**
+ * Display settings for human-readable output
+ * Size units, date style, time zone and the language of report strings.
+ * The same settings are passed to the core through PACKAGEPURGE_* variables
+ * so reports it renders (the digest) match the tables printed here.
+ */
+
+export interface DisplayOptions {
+    sizeUnits: 'binary' | 'si';
+    dates: 'absolute' | 'relative';
+    /** "local" or an IANA time zone name */
+    timeZone: string;
+    locale: string;
+}
+
+let current: DisplayOptions = {
+    sizeUnits: 'binary',
+    dates: 'absolute',
+    timeZone: 'local',
+    locale: 'en',
+};
+
+const MESSAGES = {
+    en: {
+        packagesFound: 'Packages Found',
+        package: 'Package',
+        version: 'Version',
+        size: 'Size',
+        files: 'Files',
+        path: 'Path',
+        morePackages: '... and {n} more packages',
+        total: 'Total: {n} packages, {size}, {files} files',
+        projectsFound: 'Projects Found: {n}',
+        moreProjects: '... and {n} more projects',
+        markedDirs: 'Tagged caches and vendored dependencies (kept): {n}',
+        cleanupPlan: 'Cleanup Plan',
+        nothingToClean: 'No packages identified for cleanup!',
+        category: '{reason} ({n} packages, {size})',
+        more: '... and {n} more',
+        summary: 'Summary',
+        totalPackages: 'Total packages:',
+        estimatedSavings: 'Estimated savings:',
+        inodesFreed: 'Files and directories freed:',
+        risk: 'risk {score}',
+        quarantineResults: 'Quarantine Results',
+        nothingQuarantined: 'No items were quarantined.',
+        quarantined: 'Quarantined:',
+        expires: 'Expires:',
+        quarantineTotal: '{n} items quarantined, {size} recoverable space',
+        today: 'today',
+    },
+    de: {
+        packagesFound: 'Gefundene Pakete',
+        package: 'Paket',
+        version: 'Version',
+        size: 'Größe',
+        files: 'Dateien',
+        path: 'Pfad',
+        morePackages: '... und {n} weitere Pakete',
+        total: 'Gesamt: {n} Pakete, {size}, {files} Dateien',
+        projectsFound: 'Gefundene Projekte: {n}',
+        moreProjects: '... und {n} weitere Projekte',
+        markedDirs: 'Markierte Caches und Vendor-Abhängigkeiten (behalten): {n}',
+        cleanupPlan: 'Bereinigungsplan',
+        nothingToClean: 'Keine Pakete zur Bereinigung gefunden!',
+        category: '{reason} ({n} Pakete, {size})',
+        more: '... und {n} weitere',
+        summary: 'Zusammenfassung',
+        totalPackages: 'Pakete gesamt:',
+        estimatedSavings: 'Geschätzte Ersparnis:',
+        inodesFreed: 'Freigegebene Dateien und Verzeichnisse:',
+        risk: 'Risiko {score}',
+        quarantineResults: 'Quarantäne-Ergebnis',
+        nothingQuarantined: 'Nichts wurde in Quarantäne verschoben.',
+        quarantined: 'In Quarantäne:',
+        expires: 'Läuft ab:',
+        quarantineTotal: '{n} Einträge in Quarantäne, {size} wiederherstellbar',
+        today: 'heute',
+    },
+    es: {
+        packagesFound: 'Paquetes encontrados',
+        package: 'Paquete',
+        version: 'Versión',
+        size: 'Tamaño',
+        files: 'Archivos',
+        path: 'Ruta',
+        morePackages: '... y {n} paquetes más',
+        total: 'Total: {n} paquetes, {size}, {files} archivos',
+        projectsFound: 'Proyectos encontrados: {n}',
+        moreProjects: '... y {n} proyectos más',
+        markedDirs: 'Cachés marcadas y dependencias vendorizadas (conservadas): {n}',
+        cleanupPlan: 'Plan de limpieza',
+        nothingToClean: '¡No hay paquetes para limpiar!',
+        category: '{reason} ({n} paquetes, {size})',
+        more: '... y {n} más',
+        summary: 'Resumen',
+        totalPackages: 'Paquetes en total:',
+        estimatedSavings: 'Ahorro estimado:',
+        inodesFreed: 'Archivos y directorios liberados:',
+        risk: 'riesgo {score}',
+        quarantineResults: 'Resultado de la cuarentena',
+        nothingQuarantined: 'No se puso nada en cuarentena.',
+        quarantined: 'En cuarentena:',
+        expires: 'Caduca:',
+        quarantineTotal: '{n} elementos en cuarentena, {size} recuperables',
+        today: 'hoy',
+    },
+};
+
+export type MessageKey = keyof typeof MESSAGES.en;
+
+/**
+ * Replace the settings in effect, keeping those not given
+ */
+export function setDisplayOptions(options: Partial<DisplayOptions>): void {
+    const defined = Object.fromEntries(Object.entries(options).filter(([, v]) => v !== undefined && v !== ''));
+    current = { ...current, ...defined };
+}
+
+export function getDisplayOptions(): DisplayOptions {
+    return current;
+}
+
+/**
+ * Report string in the configured language (English when untranslated)
+ */
+export function t(key: MessageKey, vars: Record<string, string | number> = {}): string {
+    const language = current.locale.split(/[-_]/)[0] as keyof typeof MESSAGES;
+    const template = (MESSAGES[language] ?? MESSAGES.en)[key];
+    return template.replace(/\{(\w+)\}/g, (match, name) => (name in vars ? String(vars[name]) : match));
+}
+
+/**
+ * Format bytes into a human-readable string
+ */
+export function formatBytes(bytes: number): string {
+    const base = current.sizeUnits === 'si' ? 1000 : 1024;
+    const units = current.sizeUnits === 'si' ? ['B', 'kB', 'MB', 'GB', 'TB'] : ['B', 'KiB', 'MiB', 'GiB', 'TiB'];
+    let value = bytes;
+    let unit = 0;
+    while (Math.abs(value) >= base && unit < units.length - 1) {
+        value /= base;
+        unit++;
+    }
+    const digits = unit === 0 ? 0 : 1;
+    const number = new Intl.NumberFormat(current.locale, { minimumFractionDigits: digits, maximumFractionDigits: digits }).format(value);
+    return `${number} ${units[unit]}`;
+}
+
+/**
+ * Format a timestamp as an absolute date or the distance from now
+ */
+export function formatDate(value: string | number | Date, now: Date = new Date()): string {
+    const date = new Date(value);
+    if (isNaN(date.getTime())) return String(value);
+    if (current.dates === 'relative') {
+        const days = Math.trunc((date.getTime() - now.getTime()) / 86_400_000);
+        if (days === 0) return t('today');
+        return new Intl.RelativeTimeFormat(current.locale, { numeric: 'always' }).format(days, 'day');
+    }
+    return date.toLocaleString(current.locale, {
+        dateStyle: 'medium',
+        timeStyle: 'short',
+        timeZone: current.timeZone === 'local' ? undefined : current.timeZone,
+    });
+}
+
+/**
+ * Variables that carry the given settings to the core; settings left out
+ * fall back to the core's own display.json
+ */
+export function displayEnv(options: Partial<DisplayOptions>): Record<string, string> {
+    const env: Record<string, string> = {};
+    if (options.sizeUnits) env.PACKAGEPURGE_SIZE_UNITS = options.sizeUnits;
+    if (options.dates) env.PACKAGEPURGE_DATES = options.dates;
+    // The core knows local time and UTC only
+    if (options.timeZone) env.PACKAGEPURGE_TIME_ZONE = options.timeZone.toUpperCase() === 'UTC' ? 'utc' : 'local';
+    if (options.locale) env.PACKAGEPURGE_LOCALE = options.locale;
+    return env;
+}
@@ -4,19 +4,13 @@
  */
 import chalk from 'chalk';
 import YAML from 'yaml';
+import type { ThroughputSummary } from '../types';
+import { formatBytes, formatDate, getDisplayOptions, t } from './display';
+import { rule, sym } from './terminal';
 
-export type OutputFormat = 'table' | 'json' | 'yaml';
+export { formatBytes, formatDate } from './display';
 
-/**
- * Format bytes into a human-readable string
- */
-export function formatBytes(bytes: number): string {
-    if (bytes === 0) return '0 B';
-    const k = 1024;
-    const sizes = ['B', 'KB', 'MB', 'GB', 'TB'];
-    const i = Math.floor(Math.log(bytes) / Math.log(k));
-    return parseFloat((bytes / Math.pow(k, i)).toFixed(2)) + ' ' + sizes[i];
-}
+export type OutputFormat = 'table' | 'json' | 'yaml' | 'ndjson' | 'csv';
 
 /**
  * Truncate a path to fit within maxLen characters
@@ -45,6 +39,20 @@ function getReasonColor(reason: string): chalk.Chalk {
             return chalk.magenta;
         case 'size_pressure':
             return chalk.red;
+        case 'inactive_project':
+            return chalk.gray;
+        case 'older_version':
+            return chalk.cyan;
+        case 'stale_build_output':
+            return chalk.gray;
+        case 'registry_crate':
+            return chalk.yellow;
+        case 'stale_python_artifact':
+            return chalk.gray;
+        case 'cached_distribution':
+            return chalk.yellow;
+        case 'unused_go_module':
+            return chalk.yellow;
         default:
             return chalk.white;
     }
@@ -67,6 +75,20 @@ function formatReason(reason: string): string {
             return 'ML: Unused';
         case 'size_pressure':
             return 'Size Pressure';
+        case 'inactive_project':
+            return 'Inactive Project';
+        case 'older_version':
+            return 'Older Version';
+        case 'stale_build_output':
+            return 'Stale Build Output';
+        case 'registry_crate':
+            return 'Registry Crate';
+        case 'stale_python_artifact':
+            return 'Stale Python Env';
+        case 'cached_distribution':
+            return 'Cached Wheel';
+        case 'unused_go_module':
+            return 'Unused Go Module';
         default:
             return reason;
     }
@@ -99,15 +121,25 @@ function extractPackageInfo(targetPath: string): { name: string; version: string
     return { name: last, version: '-' };
 }
 
+export interface RiskFactor {
+    signal: string;
+    points: number;
+    detail: string;
+}
+
 export interface PlanItem {
     target_path: string;
     estimated_size_bytes: number;
     reason: string;
+    /** 0 (safe) to 100, with the signals that contributed */
+    risk?: { score: number; factors: RiskFactor[] };
 }
 
 export interface DryRunReport {
     items: PlanItem[];
     total_estimated_bytes: number;
+    confirm?: string[];
+    estimated_inodes?: number;
 }
 
 export interface ScanOutput {
@@ -116,23 +148,37 @@ export interface ScanOutput {
         version: string;
         path: string;
         size_bytes: number;
+        file_count?: number;
+        inode_count?: number;
     }>;
     projects: Array<{
         path: string;
         manager?: string;
     }>;
+    marked_dirs?: Array<{
+        path: string;
+        marker: 'cachedir_tag' | 'go_vendor' | 'composer_vendor' | 'bundler_vendor';
+        size_bytes: number;
+        file_count?: number;
+    }>;
+    stats?: {
+        dirs_walked: number;
+        files_stated: number;
+        wall_ms: number;
+        cache_hit_rate: number;
+    };
 }
 
 /**
  * Format scan output as a human-readable table
  */
 export function formatScanAsTable(data: ScanOutput): void {
-    console.log(chalk.bold.cyan('\n📦 Packages Found\n'));
+    console.log(chalk.bold.cyan(`\n${sym('packages')}${t('packagesFound')}\n`));
 
     // Simple table without external dependency
-    const header = `${'Package'.padEnd(30)} ${'Version'.padEnd(12)} ${'Size'.padEnd(10)} Path`;
+    const header = `${t('package').padEnd(30)} ${t('version').padEnd(12)} ${t('size').padEnd(10)} ${t('files').padEnd(8)} ${t('path')}`;
     console.log(chalk.bold(header));
-    console.log('─'.repeat(100));
+    console.log(rule(100));
 
     const sortedPackages = [...(data.packages || [])].sort((a, b) => b.size_bytes - a.size_bytes);
     const displayLimit = 50;
@@ -142,36 +188,57 @@ export function formatScanAsTable(data: ScanOutput): void {
         const name = (pkg.name || 'unknown').slice(0, 28).padEnd(30);
         const version = (pkg.version || '-').slice(0, 10).padEnd(12);
         const size = formatBytes(pkg.size_bytes).padEnd(10);
+        const files = (pkg.file_count ? pkg.file_count.toLocaleString(getDisplayOptions().locale) : '-').padEnd(8);
         const path = truncatePath(pkg.path, 45);
-        console.log(`${chalk.green(name)} ${chalk.cyan(version)} ${chalk.yellow(size)} ${chalk.gray(path)}`);
+        console.log(`${chalk.green(name)} ${chalk.cyan(version)} ${chalk.yellow(size)} ${files} ${chalk.gray(path)}`);
     }
 
     if (sortedPackages.length > displayLimit) {
-        console.log(chalk.gray(`\n... and ${sortedPackages.length - displayLimit} more packages`));
+        console.log(chalk.gray(`\n${t('morePackages', { n: sortedPackages.length - displayLimit })}`));
     }
 
     const totalSize = data.packages?.reduce((sum, p) => sum + p.size_bytes, 0) || 0;
-    console.log(chalk.bold(`\n📊 Total: ${data.packages?.length || 0} packages, ${formatBytes(totalSize)}`));
+    const totalFiles = data.packages?.reduce((sum, p) => sum + (p.file_count || 0), 0) || 0;
+    const files = totalFiles.toLocaleString(getDisplayOptions().locale);
+    console.log(chalk.bold(`\n${sym('total')}${t('total', { n: data.packages?.length || 0, size: formatBytes(totalSize), files })}`));
 
     if (data.projects?.length) {
-        console.log(chalk.bold.cyan(`\n📁 Projects Found: ${data.projects.length}`));
+        console.log(chalk.bold.cyan(`\n${sym('projects')}${t('projectsFound', { n: data.projects.length })}`));
         for (const proj of data.projects.slice(0, 10)) {
-            console.log(`   ${chalk.gray('•')} ${proj.path}`);
+            console.log(`   ${chalk.gray(sym('bullet'))} ${proj.path}`);
         }
         if (data.projects.length > 10) {
-            console.log(chalk.gray(`   ... and ${data.projects.length - 10} more projects`));
+            console.log(chalk.gray(`   ${t('moreProjects', { n: data.projects.length - 10 })}`));
+        }
+    }
+
+    if (data.marked_dirs?.length) {
+        console.log(chalk.bold.cyan(`\n${t('markedDirs', { n: data.marked_dirs.length })}`));
+        for (const dir of data.marked_dirs.slice(0, 10)) {
+            console.log(`   ${chalk.gray(sym('bullet'))} ${chalk.yellow(formatBytes(dir.size_bytes).padEnd(10))} ${dir.marker.padEnd(16)} ${chalk.gray(truncatePath(dir.path, 60))}`);
         }
     }
+
+    if (data.stats) {
+        const s = data.stats;
+        console.log(chalk.gray(`\n${s.dirs_walked} dirs walked, ${s.files_stated} files stat'ed in ${s.wall_ms} ms (cache hit rate ${(s.cache_hit_rate * 100).toFixed(0)}%)`));
+    }
 }
 
 /**
  * Format cleanup plan as a human-readable table
  */
+function riskColor(score: number): chalk.Chalk {
+    if (score >= 60) return chalk.red;
+    if (score >= 30) return chalk.yellow;
+    return chalk.green;
+}
+
 export function formatPlanAsTable(data: DryRunReport): void {
-    console.log(chalk.bold.cyan('\n🧹 Cleanup Plan\n'));
+    console.log(chalk.bold.cyan(`\n${sym('plan')}${t('cleanupPlan')}\n`));
 
     if (!data.items?.length) {
-        console.log(chalk.green('✓ No packages identified for cleanup!'));
+        console.log(chalk.green(`${sym('ok')} ${t('nothingToClean')}`));
         return;
     }
 
@@ -189,8 +256,8 @@ export function formatPlanAsTable(data: DryRunReport): void {
         const reasonText = formatReason(reason);
         const categorySize = items.reduce((sum, i) => sum + i.estimated_size_bytes, 0);
 
-        console.log(colorFn.bold(`\n${reasonText} (${items.length} packages, ${formatBytes(categorySize)})`));
-        console.log('─'.repeat(80));
+        console.log(colorFn.bold(`\n${t('category', { reason: reasonText, n: items.length, size: formatBytes(categorySize) })}`));
+        console.log(rule(80));
 
         const sorted = [...items].sort((a, b) => b.estimated_size_bytes - a.estimated_size_bytes);
         const toShow = sorted.slice(0, 10);
@@ -198,51 +265,73 @@ export function formatPlanAsTable(data: DryRunReport): void {
         for (const item of toShow) {
             const { name } = extractPackageInfo(item.target_path);
             const size = formatBytes(item.estimated_size_bytes).padEnd(10);
-            console.log(`  ${colorFn('•')} ${name.padEnd(35)} ${chalk.yellow(size)} ${chalk.gray(truncatePath(item.target_path, 30))}`);
+            const risk = item.risk ? ` ${riskColor(item.risk.score)(t('risk', { score: item.risk.score }))}` : '';
+            console.log(`  ${colorFn(sym('bullet'))} ${name.padEnd(35)} ${chalk.yellow(size)} ${chalk.gray(truncatePath(item.target_path, 30))}${risk}`);
         }
 
         if (items.length > 10) {
-            console.log(chalk.gray(`  ... and ${items.length - 10} more`));
+            console.log(chalk.gray(`  ${t('more', { n: items.length - 10 })}`));
         }
     }
 
     // Summary
-    console.log(chalk.bold.green(`\n📊 Summary`));
-    console.log(`   ${chalk.bold('Total packages:')} ${data.items.length}`);
-    console.log(`   ${chalk.bold('Estimated savings:')} ${chalk.yellow.bold(formatBytes(data.total_estimated_bytes))}`);
+    console.log(chalk.bold.green(`\n${sym('total')}${t('summary')}`));
+    console.log(`   ${chalk.bold(t('totalPackages'))} ${data.items.length}`);
+    console.log(`   ${chalk.bold(t('estimatedSavings'))} ${chalk.yellow.bold(formatBytes(data.total_estimated_bytes))}`);
+    if (data.estimated_inodes != null) {
+        console.log(`   ${chalk.bold(t('inodesFreed'))} ${chalk.yellow.bold(data.estimated_inodes.toLocaleString(getDisplayOptions().locale))}`);
+    }
+    if (data.confirm?.length) {
+        console.log(chalk.yellow(`\n${sym('warn')} ${data.confirm.length} items lie outside package and cache directories; \`purge apply\` asks before quarantining them`));
+    }
 }
 
 /**
  * Format quarantine result
  */
-export function formatQuarantineResult(data: any[]): void {
-    console.log(chalk.bold.cyan('\n🗄️ Quarantine Results\n'));
+export function formatQuarantineResult(result: any): void {
+    console.log(chalk.bold.cyan(`\n${sym('quarantine')}${t('quarantineResults')}\n`));
 
+    const data: any[] = Array.isArray(result) ? result : (result?.records ?? []);
     if (!data?.length) {
-        console.log(chalk.yellow('No items were quarantined.'));
+        console.log(chalk.yellow(t('nothingQuarantined')));
         return;
     }
 
     for (const rec of data) {
-        console.log(chalk.green('✓') + ` Quarantined: ${chalk.cyan(rec.original_path || rec.id)}`);
+        console.log(chalk.green(sym('ok')) + ` ${t('quarantined')} ${chalk.cyan(rec.original_path || rec.id)}`);
         console.log(`  ${chalk.gray('ID:')} ${rec.id}`);
-        console.log(`  ${chalk.gray('Size:')} ${formatBytes(rec.size_bytes || 0)}`);
+        console.log(`  ${chalk.gray(t('size') + ':')} ${formatBytes(rec.size_bytes || 0)}`);
+        if (rec.expires_at) {
+            console.log(`  ${chalk.gray(t('expires'))} ${formatDate(rec.expires_at)}`);
+        }
     }
 
     const totalSize = data.reduce((sum, r) => sum + (r.size_bytes || 0), 0);
-    console.log(chalk.bold.green(`\n✓ ${data.length} items quarantined, ${formatBytes(totalSize)} recoverable space`));
+    console.log(chalk.bold.green(`\n${sym('ok')} ${t('quarantineTotal', { n: data.length, size: formatBytes(totalSize) })}`));
+    printThroughput(result?.throughput);
+}
+
+/**
+ * Print elapsed time and throughput of an apply run
+ */
+function printThroughput(t: ThroughputSummary | undefined): void {
+    if (!t) {
+        return;
+    }
+    console.log(chalk.gray(`  ${t.elapsed_secs.toFixed(1)}s, ${formatBytes(Math.round(t.bytes_per_sec))}/s, ${t.files_per_sec.toFixed(0)} files/s`));
 }
 
 /**
  * Format rollback result
  */
 export function formatRollbackResult(data: any): void {
-    console.log(chalk.bold.cyan('\n↩️ Rollback Result\n'));
+    console.log(chalk.bold.cyan(`\n${sym('rollback')}Rollback Result\n`));
 
     if (data.status === 'ok') {
-        console.log(chalk.green('✓') + ` Successfully rolled back: ${chalk.cyan(data.id)}`);
+        console.log(chalk.green(sym('ok')) + ` Successfully rolled back: ${chalk.cyan(data.id)}`);
     } else {
-        console.log(chalk.red('✗') + ` Rollback failed`);
+        console.log(chalk.red(sym('fail')) + ` Rollback failed`);
     }
 }
 
@@ -250,16 +339,297 @@ export function formatRollbackResult(data: any): void {
  * Format symlink result
  */
 export function formatSymlinkResult(data: any): void {
-    console.log(chalk.bold.cyan('\n🔗 Symlink Results\n'));
+    console.log(chalk.bold.cyan(`\n${sym('symlink')}Symlink Results\n`));
 
     if (data.status === 'ok') {
-        console.log(chalk.green('✓') + ` Successfully symlinked ${chalk.bold(data.symlinked_count)} packages`);
+        console.log(chalk.green(sym('ok')) + ` Successfully symlinked ${chalk.bold(data.symlinked_count)} packages`);
+        printThroughput(data.throughput);
     } else {
-        console.log(chalk.yellow('ℹ') + ` Symlink operation completed`);
+        console.log(chalk.yellow(sym('info')) + ` Symlink operation completed`);
         console.log(JSON.stringify(data, null, 2));
     }
 }
 
+export interface TopReport {
+    by: 'size' | 'growth' | 'staleness';
+    growth_window_days: number;
+    packages: number;
+    latest_scan: string | null;
+    entries: Array<{
+        name: string;
+        version: string;
+        path: string;
+        size_bytes: number;
+        growth_bytes: number | null;
+        last_access: string;
+        stale_days: number;
+    }>;
+}
+
+/**
+ * Format the top packages as a ranked table
+ */
+export function formatTopAsTable(data: TopReport): void {
+    if (!data.entries?.length) {
+        console.log(chalk.yellow(data.packages ? 'No packages changed size within the growth window.' : 'No scans recorded yet; run `purge scan` first.'));
+        return;
+    }
+    const heading = data.by === 'staleness' ? 'Last used' : `Growth (${data.growth_window_days}d)`;
+    console.log(chalk.bold(`${'#'.padEnd(4)}${t('package').padEnd(30)} ${t('size').padEnd(12)} ${heading.padEnd(18)} ${t('path')}`));
+    console.log(rule(100));
+    data.entries.forEach((e, i) => {
+        const growth = e.growth_bytes == null ? '-' : `${e.growth_bytes < 0 ? '-' : '+'}${formatBytes(Math.abs(e.growth_bytes))}`;
+        const detail = data.by === 'staleness' ? formatDate(e.last_access) : growth;
+        const name = `${e.name}@${e.version}`.slice(0, 28).padEnd(30);
+        console.log(`${String(i + 1).padEnd(4)}${chalk.green(name)} ${chalk.yellow(formatBytes(e.size_bytes).padEnd(12))} ${detail.padEnd(18)} ${chalk.gray(truncatePath(e.path, 40))}`);
+    });
+    if (data.latest_scan) {
+        console.log(chalk.gray(`\nFrom ${data.packages} packages as of the scan ${formatDate(data.latest_scan)}`));
+    }
+}
+
+export interface ProjectActivity {
+    project: string;
+    activity: 'active' | 'dormant' | 'dead';
+    last_activity: string | null;
+    signals: {
+        last_commit: string | null;
+        last_modified: string | null;
+        last_event: string | null;
+        last_opened: string | null;
+        in_editor_recents: boolean;
+    };
+}
+
+/**
+ * Format project activity classes as a table
+ */
+export function formatProjectsAsTable(data: ProjectActivity[]): void {
+    if (!data.length) {
+        console.log(chalk.yellow('No projects found.'));
+        return;
+    }
+    const colors = { active: chalk.green, dormant: chalk.yellow, dead: chalk.red };
+    console.log(chalk.bold(`${'Activity'.padEnd(10)} ${'Last activity'.padEnd(24)} ${t('path')}`));
+    console.log(rule(100));
+    for (const p of data) {
+        const last = p.last_activity ? formatDate(p.last_activity) : '-';
+        const recent = p.signals.in_editor_recents ? chalk.gray(' (recently opened)') : '';
+        console.log(`${colors[p.activity](p.activity.padEnd(10))} ${last.padEnd(24)} ${truncatePath(p.project, 60)}${recent}`);
+    }
+}
+
+export interface BackupExclusionReport {
+    dry_run: boolean;
+    undo: boolean;
+    changed: number;
+    failed: number;
+    targets: Array<{
+        path: string;
+        kind: 'node_modules' | 'cache' | 'store';
+        changed: string[];
+        unchanged: string[];
+        errors?: string[];
+    }>;
+}
+
+/**
+ * Format the result of marking trees for backup exclusion
+ */
+export function formatBackupExclusionAsTable(data: BackupExclusionReport): void {
+    const verb = data.undo ? (data.dry_run ? 'Would unmark' : 'Unmarked') : (data.dry_run ? 'Would mark' : 'Marked');
+    for (const target of data.targets) {
+        if (target.errors?.length) {
+            console.log(`  ${chalk.red(sym('fail'))} ${truncatePath(target.path, 70)} ${chalk.red(target.errors.join('; '))}`);
+        } else if (target.changed.length) {
+            console.log(`  ${chalk.green(sym('ok'))} ${truncatePath(target.path, 70)} ${chalk.gray(target.changed.join(', '))}`);
+        }
+    }
+    const unchanged = data.targets.length - data.changed - data.failed;
+    console.log(chalk.bold(`\n${verb} ${data.changed} of ${data.targets.length} trees${unchanged > 0 ? `, ${unchanged} already ${data.undo ? 'unmarked' : 'excluded'}` : ''}${data.failed ? chalk.red(`, ${data.failed} failed`) : ''}`));
+}
+
+export interface PlanImpact {
+    summary: string;
+    counts: { active: number; dormant: number; dead: number };
+    projects: Array<{
+        project: string;
+        activity: 'active' | 'dormant' | 'dead' | null;
+        items: string[];
+    }>;
+    items: Array<{ target_path: string; projects: string[] }>;
+}
+
+/**
+ * Format the projects a plan would break, grouped by activity class
+ */
+export function formatImpactAsTable(data: PlanImpact): void {
+    const colors = { active: chalk.green, dormant: chalk.yellow, dead: chalk.red };
+    for (const activity of ['active', 'dormant', 'dead'] as const) {
+        const hit = data.projects.filter(p => p.activity === activity);
+        if (!hit.length) continue;
+        console.log(colors[activity].bold(`\n${activity} (${hit.length})`));
+        for (const p of hit) {
+            console.log(`   ${chalk.gray(sym('bullet'))} ${truncatePath(p.project, 60)} ${chalk.gray(`${p.items.length} item${p.items.length === 1 ? '' : 's'}`)}`);
+        }
+    }
+    const color = data.counts.active ? chalk.red : data.projects.length ? chalk.yellow : chalk.green;
+    console.log(color.bold(`\n${data.summary}`));
+}
+
+export interface ComplianceReport {
+    policy: string | null;
+    host: string;
+    role: string;
+    generated_at: string;
+    passed: boolean;
+    checks: Array<{
+        check: 'max_cache_size' | 'stale_node_modules' | 'backup_exclusions';
+        passed: boolean;
+        limit: string;
+        actual: string;
+        offenders: string[];
+        remediation: string | null;
+    }>;
+    plan: DryRunReport;
+}
+
+/**
+ * Format a policy compliance report, one line per check
+ */
+export function formatComplianceAsTable(data: ComplianceReport): void {
+    console.log(chalk.bold(`${data.host} (${data.role})${data.policy ? ` against ${data.policy}` : ''}`));
+    for (const c of data.checks) {
+        const mark = c.passed ? chalk.green(sym('ok')) : chalk.red(sym('fail'));
+        console.log(`  ${mark} ${c.check.padEnd(20)} ${c.actual} ${chalk.gray(`(limit ${c.limit})`)}`);
+        for (const path of c.offenders.slice(0, 5)) {
+            console.log(`     ${chalk.gray(sym('bullet'))} ${truncatePath(path, 60)}`);
+        }
+        if (c.offenders.length > 5) console.log(chalk.gray(`     ... and ${c.offenders.length - 5} more`));
+        if (c.remediation) console.log(chalk.yellow(`     ${c.remediation}`));
+    }
+    console.log(data.passed ? chalk.green.bold('\nCompliant') : chalk.red.bold('\nNot compliant'));
+}
+
+export interface StoreRef {
+    tool: string;
+    root: string;
+}
+
+export interface OverlapReport {
+    duplicates: Array<{
+        name: string;
+        version: string;
+        digest: string;
+        size_bytes: number;
+        copies: Array<{ path: string; store: StoreRef; linked: boolean; blockers: string[] }>;
+        reclaimable_bytes: number;
+    }>;
+    overlap: Array<{ stores: [StoreRef, StoreRef]; packages: number; bytes: number }>;
+    total_reclaimable_bytes: number;
+}
+
+/**
+ * Format the identical content package caches share, by pair of caches and
+ * by package
+ */
+export function formatOverlapAsTable(data: OverlapReport): void {
+    if (!data.duplicates?.length) {
+        console.log(chalk.green('No package is stored twice.'));
+        return;
+    }
+    for (const o of data.overlap) {
+        const [a, b] = o.stores;
+        console.log(`  ${chalk.bold(`${a.tool} & ${b.tool}`.padEnd(24))} ${String(o.packages).padStart(6)} packages  ${chalk.yellow(formatBytes(o.bytes).padStart(10))}`);
+        console.log(chalk.gray(`     ${truncatePath(a.root, 40)}, ${truncatePath(b.root, 40)}`));
+    }
+    console.log();
+    console.log(chalk.bold(`${t('package').padEnd(30)} ${t('size').padEnd(12)} Stores`));
+    console.log(rule(80));
+    for (const d of data.duplicates.slice(0, 20)) {
+        const name = `${d.name}@${d.version}`.slice(0, 28).padEnd(30);
+        const stores = d.copies.map(c => c.blockers.length ? chalk.red(c.store.tool) : c.linked ? chalk.gray(c.store.tool) : c.store.tool).join(', ');
+        console.log(`${chalk.green(name)} ${chalk.yellow(formatBytes(d.size_bytes).padEnd(12))} ${stores}`);
+    }
+    if (data.duplicates.length > 20) console.log(chalk.gray(`... and ${data.duplicates.length - 20} more`));
+    console.log(chalk.bold(`
+${formatBytes(data.total_reclaimable_bytes)} reclaimable with --consolidate`));
+}
+
+export interface PhaseTiming {
+    phase: string;
+    ms: number;
+    count: number;
+}
+
+export interface BenchReport {
+    version: string;
+    shape: { projects: number; packages: number; depth: number; files: number; file_bytes: number; shared: number };
+    root: string;
+    generated: { packages: number; files: number; bytes: number; generate_ms: number };
+    runs: Array<{ scan_ms: number; plan_ms: number; packages: number; plan_items: number; phases: PhaseTiming[] }>;
+    scan_median_ms: number;
+    plan_median_ms: number;
+    scan_packages_per_sec: number;
+    scan_files_per_sec: number;
+    plan_packages_per_sec: number;
+}
+
+/**
+ * Format benchmark runs with their phase breakdown and the median throughput
+ */
+export function formatBenchAsTable(data: BenchReport): void {
+    const g = data.generated;
+    console.log(chalk.bold(`PackagePurge ${data.version}`) + chalk.gray(` - ${g.packages} packages, ${g.files} files, ${formatBytes(g.bytes)} generated in ${g.generate_ms.toFixed(0)} ms`));
+    console.log();
+    console.log(chalk.bold(`${'Run'.padEnd(5)} ${'Scan'.padStart(10)} ${'Plan'.padStart(10)}  Phases`));
+    console.log(rule(80));
+    data.runs.forEach((r, i) => {
+        const phases = r.phases.map(p => `${p.phase} ${p.ms.toFixed(1)}`).join(', ');
+        console.log(`${String(i + 1).padEnd(5)} ${`${r.scan_ms.toFixed(1)} ms`.padStart(10)} ${`${r.plan_ms.toFixed(1)} ms`.padStart(10)}  ${chalk.gray(phases)}`);
+    });
+    console.log(chalk.bold(`
+Scan ${data.scan_median_ms.toFixed(1)} ms median: ${Math.round(data.scan_packages_per_sec)} packages/s, ${Math.round(data.scan_files_per_sec)} files/s`));
+    console.log(chalk.bold(`Plan ${data.plan_median_ms.toFixed(1)} ms median: ${Math.round(data.plan_packages_per_sec)} packages/s`));
+}
+
+export interface QuarantineEntry {
+    id: string;
+    original_path: string;
+    quarantine_path: string;
+    sha256: string;
+    size_bytes: number;
+    created_at: string;
+    shared_bytes: number;
+    packed_bytes: number | null;
+    expires_at: string | null;
+    expires: string | null;
+    expires_in_days: number | null;
+}
+
+/**
+ * Days left until a quarantine entry expires, colored as it gets close
+ */
+export function expiryLabel(entry: QuarantineEntry): string {
+    const days = entry.expires_in_days;
+    if (days === null) return chalk.gray('kept');
+    if (days < 0) return chalk.red('expired');
+    if (days === 0) return chalk.red('expires today');
+    const text = `expires in ${days}d`;
+    return days <= 3 ? chalk.yellow(text) : chalk.gray(text);
+}
+
+/**
+ * Format quarantine entries, newest first, with their expiry countdowns
+ */
+export function formatQuarantineEntriesAsTable(entries: QuarantineEntry[]): void {
+    for (const e of entries) {
+        console.log(`  ${chalk.gray(e.id)} ${truncatePath(e.original_path, 50).padEnd(50)} ${formatBytes(e.size_bytes).padStart(10)}  ${expiryLabel(e)}`);
+    }
+    const total = entries.reduce((sum, e) => sum + e.size_bytes, 0);
+    console.log(chalk.bold(`\n${entries.length} entries, ${formatBytes(total)}`));
+}
+
 /**
  * Format data as JSON
  */
@@ -286,9 +656,15 @@ export function formatAsYAML(data: any): string {
 export function output(
     data: string | object,
     format: OutputFormat,
-    type: 'scan' | 'analyze' | 'optimize' | 'quarantine' | 'rollback' | 'symlink' | 'stats' = 'analyze'
+    type: 'scan' | 'analyze' | 'optimize' | 'quarantine' | 'rollback' | 'symlink' | 'stats' | 'share-trees' | 'top' | 'projects' | 'backup-exclude' | 'impact' | 'compliance' | 'quarantine-list' | 'duplicates' | 'bench' = 'analyze'
 ): void {
 
+    // Rendered by the core (`--format ndjson|csv`)
+    if ((format === 'ndjson' || format === 'csv') && typeof data === 'string') {
+        process.stdout.write(data);
+        return;
+    }
+
     // Parse JSON string if needed
     let parsed: any;
     if (typeof data === 'string') {
@@ -325,6 +701,33 @@ export function output(
                 case 'symlink':
                     formatSymlinkResult(parsed);
                     break;
+                case 'top':
+                    formatTopAsTable(parsed as TopReport);
+                    break;
+                case 'projects':
+                    formatProjectsAsTable(parsed as ProjectActivity[]);
+                    break;
+                case 'backup-exclude':
+                    formatBackupExclusionAsTable(parsed as BackupExclusionReport);
+                    break;
+                case 'impact':
+                    formatImpactAsTable(parsed as PlanImpact);
+                    break;
+                case 'compliance':
+                    formatComplianceAsTable(parsed as ComplianceReport);
+                    break;
+                case 'duplicates':
+                    if (parsed.duplicates) formatOverlapAsTable(parsed as OverlapReport);
+                    else console.log(formatAsJSON(parsed));
+                    break;
+                case 'bench':
+                    if (parsed.runs) formatBenchAsTable(parsed as BenchReport);
+                    else console.log(formatAsJSON(parsed));
+                    break;
+                case 'quarantine-list':
+                    if (Array.isArray(parsed)) formatQuarantineEntriesAsTable(parsed as QuarantineEntry[]);
+                    else console.log(formatAsJSON(parsed));
+                    break;
                 default:
                     console.log(formatAsJSON(parsed));
             }
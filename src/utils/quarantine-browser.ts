@@ -0,0 +1,111 @@
+/**
+ * Interactive quarantine browser: pick an entry, look at what it holds and
+ * when it expires, then restore it or delete it for good.
+ */
+
+import chalk from 'chalk';
+import inquirer from 'inquirer';
+import { runCore } from './core-utils';
+import { formatBytes, formatDate } from './display';
+import { expiryLabel, QuarantineEntry, truncatePath } from './formatter';
+import { logger } from './logger';
+import { sym } from './terminal';
+
+interface QuarantineDetail {
+    entry: QuarantineEntry;
+    files: Array<{ path: string; size_bytes: number }>;
+    total_files: number;
+}
+
+/** How many files of an entry are listed */
+const FILES_SHOWN = 40;
+
+async function core(args: string[]): Promise<string | null> {
+    const res = await runCore(args);
+    if (res.code !== 0) {
+        logger.error(res.stderr.trim() || `purge ${args.join(' ')} failed`);
+        return null;
+    }
+    return res.stdout;
+}
+
+function printDetail(detail: QuarantineDetail): void {
+    const { entry } = detail;
+    console.log();
+    console.log(chalk.bold(entry.original_path));
+    console.log(`  ${chalk.gray('Quarantined')}  ${formatDate(entry.created_at)}`);
+    console.log(`  ${chalk.gray('Size')}         ${formatBytes(entry.size_bytes)}${entry.shared_bytes ? chalk.gray(` (${formatBytes(entry.shared_bytes)} shared)`) : ''}${entry.packed_bytes ? chalk.gray(` (packed to ${formatBytes(entry.packed_bytes)})`) : ''}`);
+    console.log(`  ${chalk.gray('Expiry')}       ${entry.expires ? `${formatDate(entry.expires)}, ` : ''}${expiryLabel(entry)}`);
+    console.log(`  ${chalk.gray('Checksum')}     ${entry.sha256}`);
+    console.log(`  ${chalk.gray('Files')}        ${detail.total_files}`);
+    for (const file of detail.files) {
+        console.log(`     ${chalk.gray(sym('bullet'))} ${truncatePath(file.path, 60)} ${chalk.gray(formatBytes(file.size_bytes))}`);
+    }
+    if (detail.total_files > detail.files.length) {
+        console.log(chalk.gray(`     ... and ${detail.total_files - detail.files.length} more`));
+    }
+    console.log();
+}
+
+/**
+ * Browse the quarantine until the user quits
+ */
+export async function browseQuarantine(): Promise<void> {
+    for (;;) {
+        const listed = await core(['quarantine', 'list']);
+        if (listed === null) process.exit(1);
+        const entries: QuarantineEntry[] = JSON.parse(listed);
+        if (!entries.length) {
+            console.log(chalk.green(`${sym('ok')} Quarantine is empty`));
+            return;
+        }
+
+        const total = entries.reduce((sum, e) => sum + e.size_bytes, 0);
+        const { id } = await inquirer.prompt<{ id: string | null }>([{
+            type: 'list',
+            name: 'id',
+            message: `${entries.length} quarantined entries, ${formatBytes(total)}`,
+            pageSize: 15,
+            choices: [
+                ...entries.map(e => ({
+                    name: `${truncatePath(e.original_path, 50).padEnd(50)} ${formatBytes(e.size_bytes).padStart(10)}  ${expiryLabel(e)}`,
+                    value: e.id,
+                })),
+                new inquirer.Separator(),
+                { name: 'Quit', value: null },
+            ],
+        }]);
+        if (!id) return;
+
+        const shown = await core(['quarantine', 'show', id, '--limit', String(FILES_SHOWN)]);
+        if (shown === null) continue;
+        const detail: QuarantineDetail = JSON.parse(shown);
+        printDetail(detail);
+
+        const { action } = await inquirer.prompt<{ action: 'restore' | 'delete' | 'back' }>([{
+            type: 'list',
+            name: 'action',
+            message: 'What should happen to this entry?',
+            choices: [
+                { name: 'Back to the list', value: 'back' },
+                { name: `Restore to ${truncatePath(detail.entry.original_path, 50)}`, value: 'restore' },
+                { name: 'Delete permanently', value: 'delete' },
+            ],
+        }]);
+        if (action === 'restore') {
+            if (await core(['rollback', '--id', id]) !== null) {
+                console.log(chalk.green(`${sym('ok')} Restored ${detail.entry.original_path}`));
+            }
+        } else if (action === 'delete') {
+            const { sure } = await inquirer.prompt<{ sure: boolean }>([{
+                type: 'confirm',
+                name: 'sure',
+                message: `Delete ${formatBytes(detail.entry.size_bytes)} permanently? This cannot be undone.`,
+                default: false,
+            }]);
+            if (sure && await core(['quarantine', 'delete', id]) !== null) {
+                console.log(chalk.green(`${sym('ok')} Deleted ${detail.entry.original_path}`));
+            }
+        }
+    }
+}
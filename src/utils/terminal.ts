@@ -0,0 +1,81 @@
+/**
+ * Terminal capabilities for human-readable output
+ * Whether to color, and whether to stay within ASCII and high-contrast
+ * styling for CI logs, screen readers and limited terminals.
+ */
+import chalk from 'chalk';
+
+export type ColorMode = 'auto' | 'always' | 'never';
+
+export interface TerminalOptions {
+    color: ColorMode;
+    /** Plain ASCII symbols: no emoji, box drawing or braille spinner */
+    ascii: boolean;
+    /** No gray or dimmed text */
+    highContrast: boolean;
+}
+
+let ascii = false;
+
+const SYMBOLS = {
+    ok: ['✓', '[ok]'],
+    fail: ['✗', '[x]'],
+    warn: ['⚠', '[!]'],
+    info: ['ℹ', '[i]'],
+    tip: ['💡 Tip:', 'Tip:'],
+    bullet: ['•', '*'],
+    rule: ['─', '-'],
+    packages: ['📦 ', ''],
+    total: ['📊 ', ''],
+    projects: ['📁 ', ''],
+    plan: ['🧹 ', ''],
+    quarantine: ['🗄️ ', ''],
+    rollback: ['↩️ ', ''],
+    symlink: ['🔗 ', ''],
+    settings: ['⚙️  ', ''],
+} as const;
+
+export type SymbolName = keyof typeof SYMBOLS;
+
+/**
+ * Apply the options to chalk for the rest of the process.
+ * In auto mode NO_COLOR (any non-empty value) turns color off, as does
+ * stdout not being a terminal; FORCE_COLOR is honored by chalk itself.
+ */
+export function applyTerminalOptions(options: Partial<TerminalOptions>, env: NodeJS.ProcessEnv = process.env): void {
+    const color = options.color ?? 'auto';
+    if (color === 'never' || (color === 'auto' && env.NO_COLOR)) {
+        chalk.level = 0;
+    } else if (color === 'always' && chalk.level === 0) {
+        chalk.level = 1;
+    }
+    ascii = options.ascii ?? false;
+    if (options.highContrast) {
+        // Dim and gray text is unreadable on many terminals; show it plainly
+        for (const style of ['gray', 'grey', 'dim', 'blackBright']) {
+            Object.defineProperty(chalk, style, { get: () => chalk.reset, configurable: true });
+        }
+    }
+}
+
+export function isAscii(): boolean {
+    return ascii;
+}
+
+/**
+ * A status symbol or heading icon; empty or a plain word in ASCII mode
+ */
+export function sym(name: SymbolName): string {
+    return SYMBOLS[name][ascii ? 1 : 0];
+}
+
+/**
+ * A horizontal rule of the given width
+ */
+export function rule(width: number): string {
+    return sym('rule').repeat(width);
+}
+
+export function spinnerFrames(): string[] {
+    return ascii ? ['|', '/', '-', '\\'] : ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+}